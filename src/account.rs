@@ -0,0 +1,243 @@
+use std::{
+    ffi::CStr,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+};
+
+use crate::capture::{Image, Rect};
+
+/// The width and height of shared pictures, which are scaled down to it if they're larger.
+const AVATAR_SIZE: u32 = 256;
+
+/// `User` is what's known about the user running the portal, for the Account portal.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct User {
+    /// The login name.
+    pub login: String,
+
+    /// The real name, or the login name if there's none.
+    pub name: String,
+
+    /// The picture set for the user, if there's one.
+    pub picture: Option<PathBuf>,
+}
+
+impl User {
+    /// Look up the user running the portal in the password database, which is `/etc/passwd`
+    /// on most systems, with the picture AccountsService or `~/.face` has for them.
+    pub fn current() -> Self {
+        let Some((login, gecos)) = passwd() else {
+            log::warn!("failed to look up the current user");
+            return Self::default();
+        };
+
+        let name = match real_name(&gecos, &login) {
+            name if name.is_empty() => login.clone(),
+            name => name,
+        };
+
+        let picture = picture(&login);
+
+        Self {
+            login,
+            name,
+            picture,
+        }
+    }
+}
+
+/// Make an avatar of the picture at `path`: the square in its middle, scaled down to
+/// `AVATAR_SIZE`, written to `$XDG_CACHE_HOME/xdg-desktop-portal-rs/avatars`.
+///
+/// Only PNG pictures can be processed, so others are shared as they are, and so are pictures
+/// that fail to be.
+pub fn avatar(path: &Path) -> PathBuf {
+    let processed = std::fs::read(path).and_then(|bytes| {
+        let Some(picture) = Image::decode(&bytes) else {
+            return Ok(path.to_path_buf());
+        };
+
+        let dir = dirs::cache_dir()
+            .ok_or_else(|| std::io::Error::other("no cache directory"))?
+            .join("xdg-desktop-portal-rs")
+            .join("avatars");
+
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+
+        bytes.hash(&mut hasher);
+
+        let avatar = dir.join(format!("{:016x}.png", hasher.finish()));
+
+        if !avatar.is_file() {
+            std::fs::create_dir_all(&dir)?;
+            square(&picture).save(&avatar)?;
+        }
+
+        Ok(avatar)
+    });
+
+    processed.unwrap_or_else(|e| {
+        log::warn!("failed to make an avatar of {:?}: {}", path, e);
+        path.to_path_buf()
+    })
+}
+
+/// Crop the square in the middle of `picture`, scaling it down to `AVATAR_SIZE`.
+fn square(picture: &Image) -> Image {
+    let side = picture.width.min(picture.height);
+
+    let square = picture.crop(&Rect {
+        x: (picture.width - side) / 2,
+        y: (picture.height - side) / 2,
+        width: side,
+        height: side,
+    });
+
+    if side <= AVATAR_SIZE {
+        return square;
+    }
+
+    // Each pixel is the average of the pixels it covers, so nothing is skipped over; the
+    // square is larger than the avatar, so each covers at least one.
+    let span = |i: u32| (i * side / AVATAR_SIZE)..((i + 1) * side / AVATAR_SIZE);
+
+    let mut pixels = Vec::with_capacity((AVATAR_SIZE * AVATAR_SIZE * 4) as usize);
+
+    for y in 0..AVATAR_SIZE {
+        for x in 0..AVATAR_SIZE {
+            let mut sum = [0u32; 4];
+            let mut count = 0;
+
+            for sy in span(y) {
+                for sx in span(x) {
+                    let pixel = square.pixel(sx, sy).unwrap_or_default();
+
+                    for (sum, channel) in sum.iter_mut().zip(pixel) {
+                        *sum += u32::from(channel);
+                    }
+
+                    count += 1;
+                }
+            }
+
+            pixels.extend(sum.map(|sum| (sum / count.max(1)) as u8));
+        }
+    }
+
+    Image {
+        width: AVATAR_SIZE,
+        height: AVATAR_SIZE,
+        pixels,
+    }
+}
+
+/// Get the login name and GECOS field of the current user.
+fn passwd() -> Option<(String, String)> {
+    // SAFETY: getuid has no preconditions and can't fail.
+    let uid = unsafe { libc::getuid() };
+
+    // SAFETY: passwd is plain data, which getpwuid_r fills in.
+    let mut entry: libc::passwd = unsafe { std::mem::zeroed() };
+
+    let mut buffer = vec![0 as libc::c_char; 4096];
+
+    let mut result = std::ptr::null_mut();
+
+    // SAFETY: the pointers are valid for the call, and `buffer` for its whole length.
+    let failed = unsafe {
+        libc::getpwuid_r(
+            uid,
+            &mut entry,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut result,
+        )
+    };
+
+    if failed != 0 || result.is_null() {
+        return None;
+    }
+
+    let string = |pointer: *const libc::c_char| match pointer.is_null() {
+        true => String::new(),
+
+        // SAFETY: non-null fields of the entry point to NUL-terminated strings in `buffer`.
+        false => unsafe { CStr::from_ptr(pointer) }
+            .to_string_lossy()
+            .into_owned(),
+    };
+
+    Some((string(entry.pw_name), string(entry.pw_gecos)))
+}
+
+/// Get the real name of a GECOS field, which is the part before the first comma, with `&`
+/// standing for the capitalized `login`.
+fn real_name(gecos: &str, login: &str) -> String {
+    let name = gecos.split(',').next().unwrap_or_default();
+
+    let mut capitalized = login.chars();
+
+    let capitalized: String = capitalized
+        .next()
+        .map(|first| first.to_uppercase().chain(capitalized).collect())
+        .unwrap_or_default();
+
+    name.replace('&', &capitalized).trim().to_owned()
+}
+
+/// Find the picture of `login`, the one AccountsService keeps or else `~/.face`.
+fn picture(login: &str) -> Option<PathBuf> {
+    let accounts_service = PathBuf::from("/var/lib/AccountsService/icons").join(login);
+
+    let face = dirs::home_dir().map(|home| home.join(".face"));
+
+    [Some(accounts_service), face]
+        .into_iter()
+        .flatten()
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{real_name, square, AVATAR_SIZE};
+    use crate::capture::Image;
+
+    #[test]
+    fn real_names() {
+        assert_eq!(real_name("Ada Lovelace,,,", "ada"), "Ada Lovelace");
+        assert_eq!(real_name("& Smith,Room 1", "jane"), "Jane Smith");
+        assert_eq!(real_name("", "jane"), "");
+    }
+
+    #[test]
+    fn avatars() {
+        // A wide picture, red on the left half and blue on the right.
+        let (width, height) = (1024, 512);
+
+        let pixels = (0..width * height)
+            .flat_map(|i| match i % width < width / 2 {
+                true => [255, 0, 0, 255],
+                false => [0, 0, 255, 255],
+            })
+            .collect();
+
+        let avatar = square(&Image {
+            width,
+            height,
+            pixels,
+        });
+
+        assert_eq!((avatar.width, avatar.height), (AVATAR_SIZE, AVATAR_SIZE));
+        assert_eq!(avatar.pixel(0, 0), Some([255, 0, 0, 255]));
+        assert_eq!(avatar.pixel(AVATAR_SIZE - 1, 0), Some([0, 0, 255, 255]));
+
+        // Small pictures are only cropped.
+        let small = square(&Image {
+            width: 3,
+            height: 2,
+            pixels: vec![0; 24],
+        });
+
+        assert_eq!((small.width, small.height), (2, 2));
+    }
+}
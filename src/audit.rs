@@ -0,0 +1,128 @@
+use std::{io::Write, os::unix::net::UnixDatagram, path::PathBuf, sync::Mutex};
+
+use serde::Serialize;
+
+use crate::config::{AuditConfig, AuditTarget};
+
+/// The socket journald receives native protocol messages on.
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// `Audit` appends a record of every portal decision to the configured target.
+///
+/// It's opt-in and does nothing unless `[audit] target` is set.
+pub struct Audit {
+    target: Option<AuditTarget>,
+    path: PathBuf,
+
+    /// Keeps records of concurrent requests from interleaving in the log file.
+    lock: Mutex<()>,
+}
+
+/// `Outcome` is how a request ended.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    Chosen,
+    Cancelled,
+
+    /// The request failed, was closed, timed out or was rejected by the policy.
+    Failed,
+}
+
+/// `Record` is a single audit log entry.
+#[derive(Debug, Serialize)]
+struct Record<'a> {
+    timestamp: String,
+    app_id: &'a str,
+    method: &'a str,
+    outcome: Outcome,
+    uris: &'a [String],
+}
+
+impl Audit {
+    pub fn new(config: &AuditConfig) -> Self {
+        let path = config.path.clone().unwrap_or_else(|| {
+            dirs::state_dir()
+                .unwrap_or_else(std::env::temp_dir)
+                .join("xdg-desktop-portal-rs")
+                .join("audit.log")
+        });
+
+        Self {
+            target: config.target,
+            path,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Record the outcome of a `method` call made by `app_id`.
+    pub fn record(&self, app_id: &str, method: &str, outcome: Outcome, uris: &[String]) {
+        let Some(target) = self.target else {
+            return;
+        };
+
+        let record = Record {
+            timestamp: humantime::format_rfc3339_millis(std::time::SystemTime::now()).to_string(),
+            app_id,
+            method,
+            outcome,
+            uris,
+        };
+
+        let written = match target {
+            AuditTarget::File => self.append(&record),
+            AuditTarget::Journald => journal(&record),
+        };
+
+        if let Err(e) = written {
+            log::error!("failed to write audit record {:?}: {}", record, e);
+        }
+    }
+
+    /// Append a record to the log file as a line of JSON.
+    fn append(&self, record: &Record<'_>) -> std::io::Result<()> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(line.as_bytes())
+    }
+}
+
+/// Send a record to journald with its fields as structured journal fields.
+fn journal(record: &Record<'_>) -> std::io::Result<()> {
+    let message = format!(
+        "{} {} {}",
+        record.app_id,
+        record.method,
+        serde_json::to_string(&record.outcome)?.trim_matches('"')
+    );
+
+    let fields = [
+        ("MESSAGE", message.as_str()),
+        ("PRIORITY", "6"),
+        ("SYSLOG_IDENTIFIER", "xdg-desktop-portal-rs"),
+        ("PORTAL_APP_ID", record.app_id),
+        ("PORTAL_METHOD", record.method),
+        ("PORTAL_URIS", &record.uris.join(" ")),
+    ];
+
+    // URIs are escaped and app ids can't contain newlines, so the simple `KEY=value` form is safe.
+    let payload: String = fields
+        .iter()
+        .map(|(key, value)| format!("{}={}\n", key, value.replace('\n', " ")))
+        .collect();
+
+    UnixDatagram::unbound()?.send_to(payload.as_bytes(), JOURNAL_SOCKET)?;
+
+    Ok(())
+}
@@ -0,0 +1,160 @@
+use std::path::PathBuf;
+
+/// Characters that need an argument of an `Exec` line quoted.
+const RESERVED: &[char] = &[
+    ' ', '\t', '\n', '"', '\'', '\\', '>', '<', '~', '|', '&', ';', '$', '*', '?', '#', '(', ')',
+    '`',
+];
+
+/// Start `app_id` with `commandline` when the user logs in, by writing a desktop file to
+/// `$XDG_CONFIG_HOME/autostart`.
+///
+/// Sandboxed apps are started through Flatpak, and `dbus_activatable` ones by D-Bus where
+/// the session supports it.
+pub fn enable(
+    app_id: &str,
+    commandline: &[String],
+    sandboxed: bool,
+    dbus_activatable: bool,
+) -> std::io::Result<()> {
+    let path = path(app_id)?;
+
+    if commandline.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "the command line is empty",
+        ));
+    }
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    std::fs::write(
+        path,
+        entry(app_id, commandline, sandboxed, dbus_activatable),
+    )
+}
+
+/// Stop starting `app_id` when the user logs in, removing its autostart file if there's one.
+pub fn disable(app_id: &str) -> std::io::Result<()> {
+    match std::fs::remove_file(path(app_id)?) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        removed => removed,
+    }
+}
+
+/// Get the path of the autostart file of `app_id`.
+fn path(app_id: &str) -> std::io::Result<PathBuf> {
+    let invalid = |message: &str| std::io::Error::new(std::io::ErrorKind::InvalidInput, message);
+
+    // App ids name the file, so they mustn't lead out of the directory.
+    if app_id.is_empty() || app_id.contains(['/', '\n', '\r']) || app_id.starts_with('.') {
+        return Err(invalid("the app id can't name a desktop file"));
+    }
+
+    let dir = dirs::config_dir().ok_or_else(|| invalid("there's no config directory"))?;
+
+    Ok(dir.join("autostart").join(format!("{}.desktop", app_id)))
+}
+
+/// Write the autostart desktop file of `app_id`.
+fn entry(app_id: &str, commandline: &[String], sandboxed: bool, dbus_activatable: bool) -> String {
+    let exec: Vec<String> = match (sandboxed, commandline) {
+        (true, [command, args @ ..]) => [
+            String::from("flatpak"),
+            String::from("run"),
+            format!("--command={}", command),
+            app_id.to_owned(),
+        ]
+        .into_iter()
+        .chain(args.iter().cloned())
+        .collect(),
+
+        _ => commandline.to_vec(),
+    };
+
+    let exec: Vec<String> = exec.iter().map(|arg| quote(arg)).collect();
+
+    let mut entry = format!(
+        "[Desktop Entry]\nType=Application\nName={}\nExec={}\nX-XDP-Autostart={}\n",
+        app_id,
+        escape(&exec.join(" ")),
+        app_id
+    );
+
+    if sandboxed {
+        entry.push_str(&format!("X-Flatpak={}\n", app_id));
+    }
+
+    if dbus_activatable {
+        entry.push_str("DBusActivatable=true\n");
+    }
+
+    entry
+}
+
+/// Quote an argument of an `Exec` line, as the desktop entry spec describes.
+fn quote(arg: &str) -> String {
+    // Field codes are expanded by launchers, so literal percent signs are doubled.
+    let arg = arg.replace('%', "%%");
+
+    if !arg.is_empty() && !arg.contains(RESERVED) {
+        return arg;
+    }
+
+    let mut quoted = String::from("\"");
+
+    for c in arg.chars() {
+        if matches!(c, '"' | '`' | '$' | '\\') {
+            quoted.push('\\');
+        }
+
+        quoted.push(c);
+    }
+
+    quoted.push('"');
+
+    quoted
+}
+
+/// Escape a value of a desktop file, which unescapes backslashes before `Exec` is unquoted.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{entry, path, quote};
+
+    #[test]
+    fn autostart_entries() {
+        assert_eq!(quote("--background"), "--background");
+        assert_eq!(quote("a b"), "\"a b\"");
+        assert_eq!(quote("say \"$HOME\""), "\"say \\\"\\$HOME\\\"\"");
+        assert_eq!(quote("100%"), "100%%");
+        assert_eq!(quote(""), "\"\"");
+
+        let commandline = [String::from("/app/bin/chat"), String::from("--hidden")];
+
+        assert_eq!(
+            entry("org.example.Chat", &commandline, true, true),
+            "[Desktop Entry]\nType=Application\nName=org.example.Chat\n\
+             Exec=flatpak run --command=/app/bin/chat org.example.Chat --hidden\n\
+             X-XDP-Autostart=org.example.Chat\nX-Flatpak=org.example.Chat\n\
+             DBusActivatable=true\n"
+        );
+
+        let commandline = [String::from("chat"), String::from("C:\\x y")];
+
+        assert!(
+            entry("chat", &commandline, false, false).contains("\nExec=chat \"C:\\\\\\\\x y\"\n")
+        );
+
+        assert!(path("../evil").is_err());
+        assert!(path("").is_err());
+    }
+}
@@ -0,0 +1,407 @@
+use std::{io::BufWriter, path::Path, sync::Arc};
+
+use crate::config::{CaptureBackend, ScreenshotConfig};
+
+mod grim;
+mod screencopy;
+mod x11;
+
+/// `Capture` takes pictures of the screen for the Screenshot portal.
+///
+/// Capturing blocks until the picture is taken, so callers run it off the async executor.
+pub trait Capture: Send + Sync {
+    /// Take a picture of every output, laid out as the compositor arranges them,
+    /// with the pointer drawn in if `cursor` is set.
+    fn capture(&self, cursor: bool) -> std::io::Result<Image>;
+
+    /// List the areas of the visible windows in a capture of `screen`, topmost first,
+    /// so a single window can be picked.
+    ///
+    /// Backends that can't ask the compositor for window geometry list none.
+    fn windows(&self, _screen: &Image) -> Vec<Rect> {
+        Vec::new()
+    }
+
+    /// List the outputs shown in a capture of `screen`, in the compositor's order.
+    ///
+    /// Backends that can't tell outputs apart list the whole picture as a single one.
+    fn outputs(&self, screen: &Image) -> Vec<Output> {
+        vec![Output::whole(screen)]
+    }
+
+    /// List the windows that can be shared on their own in a capture of `screen`,
+    /// with their titles.
+    ///
+    /// Backends that can't tell where windows are list none.
+    fn toplevels(&self, _screen: &Image) -> Vec<Toplevel> {
+        Vec::new()
+    }
+
+    /// Get the pointer on its own, for streams sending it apart from the pictures.
+    ///
+    /// Backends that can only draw the pointer into pictures have none.
+    #[cfg_attr(not(feature = "pipewire"), allow(dead_code))]
+    fn cursor(&self) -> Option<Cursor> {
+        None
+    }
+}
+
+/// `Cursor` is a picture of the pointer and where it points.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(not(feature = "pipewire"), allow(dead_code))]
+pub struct Cursor {
+    /// Where the pointer points, in pixels of pictures of the screen.
+    pub position: (i32, i32),
+
+    /// The pixel of `image` that points.
+    pub hotspot: (i32, i32),
+
+    pub image: Image,
+}
+
+/// `Toplevel` is an application window, as it appears in a picture of every output.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Toplevel {
+    /// Identifies the window among the listed ones, so it can be found again after it moved.
+    pub id: String,
+
+    pub title: String,
+
+    /// The app id on Wayland, or the window class on X11.
+    pub app_id: String,
+
+    /// The area of the window in the picture, in pixels.
+    pub area: Rect,
+}
+
+impl Toplevel {
+    /// Describe the window for people, like `notes.txt - Editor (org.example.Editor)`.
+    pub fn label(&self) -> String {
+        match self.app_id.as_str() {
+            "" => self.title.clone(),
+            app_id => format!("{} ({})", self.title, app_id),
+        }
+    }
+}
+
+/// `Output` is a monitor, as it appears in a picture of every output.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Output {
+    /// The connector name, like `DP-1`.
+    pub name: String,
+
+    /// The make and model of the monitor, if the compositor knows them.
+    pub model: String,
+
+    /// The area of the output in the picture, in pixels.
+    pub area: Rect,
+
+    /// The position of the output in the compositor's layout.
+    pub position: (i32, i32),
+
+    /// The size of the output in the compositor's layout.
+    pub size: (i32, i32),
+}
+
+impl Output {
+    /// Describe a picture as a single output.
+    pub fn whole(screen: &Image) -> Self {
+        Self {
+            name: String::from("Screen"),
+            model: String::new(),
+            area: screen.bounds(),
+            position: (0, 0),
+            size: (screen.width as i32, screen.height as i32),
+        }
+    }
+
+    /// Describe the output for people, like `DP-1 (Dell U2720Q), 3840×2160`.
+    pub fn label(&self) -> String {
+        let resolution = format!("{}×{}", self.area.width, self.area.height);
+
+        match self.model.as_str() {
+            "" => format!("{}, {}", self.name, resolution),
+            model => format!("{} ({}), {}", self.name, model, resolution),
+        }
+    }
+}
+
+/// Join the make and model of a monitor, leaving out parts compositors don't know.
+fn make_and_model(make: &str, model: &str) -> String {
+    [make, model]
+        .into_iter()
+        .filter(|part| !part.is_empty() && *part != "Unknown")
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Find the pixel area of each logical `(x, y, width, height)` in a picture of all of them,
+/// which is scaled to the highest output scale.
+fn logical_areas(layouts: &[(i32, i32, i32, i32)], screen: &Image) -> Vec<Rect> {
+    let left = layouts.iter().map(|(x, ..)| *x).min().unwrap_or_default();
+    let top = layouts
+        .iter()
+        .map(|(_, y, ..)| *y)
+        .min()
+        .unwrap_or_default();
+    let right = layouts
+        .iter()
+        .map(|(x, _, w, _)| x + w)
+        .max()
+        .unwrap_or_default();
+
+    let scale = f64::from(screen.width) / f64::from((right - left).max(1));
+
+    let to_pixels = |logical: i32| (f64::from(logical) * scale).round().max(0.0) as u32;
+
+    layouts
+        .iter()
+        .map(|(x, y, width, height)| {
+            screen.bounds().clip(&Rect {
+                x: to_pixels(x - left),
+                y: to_pixels(y - top),
+                width: to_pixels(*width),
+                height: to_pixels(*height),
+            })
+        })
+        .collect()
+}
+
+/// `Rect` is an area of a captured picture, in pixels.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    /// Check whether the pixel at `x`, `y` is inside the area.
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+
+    /// Cut off the parts of `other` outside this area.
+    pub fn clip(&self, other: &Rect) -> Rect {
+        let x = other.x.clamp(self.x, self.x + self.width);
+        let y = other.y.clamp(self.y, self.y + self.height);
+
+        Rect {
+            x,
+            y,
+            width: (other.x + other.width)
+                .min(self.x + self.width)
+                .saturating_sub(x),
+            height: (other.y + other.height)
+                .min(self.y + self.height)
+                .saturating_sub(y),
+        }
+    }
+}
+
+/// `Image` is a captured picture with 8-bit RGBA pixels, row by row.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    /// Get the RGBA channels of the pixel at `x`, `y`.
+    pub fn pixel(&self, x: u32, y: u32) -> Option<[u8; 4]> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        let offset = (y as usize * self.width as usize + x as usize) * 4;
+
+        self.pixels.get(offset..offset + 4)?.try_into().ok()
+    }
+
+    /// Get the area covering the whole image.
+    pub fn bounds(&self) -> Rect {
+        Rect {
+            x: 0,
+            y: 0,
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    /// Copy an area of the image, clipped to its bounds.
+    pub fn crop(&self, area: &Rect) -> Image {
+        let x = area.x.min(self.width);
+        let y = area.y.min(self.height);
+        let width = area.width.min(self.width - x);
+        let height = area.height.min(self.height - y);
+
+        let stride = self.width as usize * 4;
+
+        let pixels = (y..y + height)
+            .flat_map(|row| {
+                let start = row as usize * stride + x as usize * 4;
+                &self.pixels[start..start + width as usize * 4]
+            })
+            .copied()
+            .collect();
+
+        Image {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Decode a PNG image, or `None` if `bytes` are another format or broken.
+    pub fn decode(bytes: &[u8]) -> Option<Image> {
+        let mut decoder = png::Decoder::new(bytes);
+
+        // Expand palettes and low bit depths, and strip 16-bit channels down to 8 bits.
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+
+        let mut reader = decoder
+            .read_info()
+            .map_err(|e| log::debug!("failed to decode an image: {}", e))
+            .ok()?;
+
+        let mut buffer = vec![0; reader.output_buffer_size()];
+
+        let info = reader.next_frame(&mut buffer).ok()?;
+
+        buffer.truncate(info.buffer_size());
+
+        let pixels = match info.color_type {
+            png::ColorType::Rgba => buffer,
+            png::ColorType::Rgb => buffer
+                .chunks_exact(3)
+                .flat_map(|p| [p[0], p[1], p[2], 255])
+                .collect(),
+            png::ColorType::GrayscaleAlpha => buffer
+                .chunks_exact(2)
+                .flat_map(|p| [p[0], p[0], p[0], p[1]])
+                .collect(),
+            png::ColorType::Grayscale | png::ColorType::Indexed => {
+                buffer.iter().flat_map(|&g| [g, g, g, 255]).collect()
+            }
+        };
+
+        Some(Image {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+
+    /// Write the image to a PNG file.
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let file = BufWriter::new(std::fs::File::create(path)?);
+
+        let mut encoder = png::Encoder::new(file, self.width, self.height);
+
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        encoder.write_header()?.write_image_data(&self.pixels)?;
+
+        Ok(())
+    }
+}
+
+/// Create the capture backend selected by the config.
+///
+/// `auto` picks one for the session type: screencopy on Wayland and X11 otherwise.
+pub fn from_config(config: &ScreenshotConfig) -> Arc<dyn Capture> {
+    let backend = match config.backend {
+        CaptureBackend::Auto if std::env::var_os("WAYLAND_DISPLAY").is_some() => {
+            CaptureBackend::Screencopy
+        }
+
+        CaptureBackend::Auto => CaptureBackend::X11,
+
+        backend => backend,
+    };
+
+    log::debug!("capturing the screen with {:?}", backend);
+
+    match backend {
+        CaptureBackend::Screencopy | CaptureBackend::Auto => Arc::new(screencopy::Screencopy),
+        CaptureBackend::Grim => Arc::new(grim::Grim),
+        CaptureBackend::X11 => Arc::new(x11::X11),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{logical_areas, Image, Rect};
+
+    #[test]
+    fn crop() {
+        // A 3x2 image whose pixels are numbered in their red channel.
+        let image = Image {
+            width: 3,
+            height: 2,
+            pixels: (0..6).flat_map(|n| [n, 0, 0, 255]).collect(),
+        };
+
+        let area = Rect {
+            x: 1,
+            y: 0,
+            width: 5,
+            height: 2,
+        };
+
+        let cropped = image.crop(&area);
+
+        assert_eq!((cropped.width, cropped.height), (2, 2));
+        assert_eq!(
+            cropped.pixels.chunks(4).map(|p| p[0]).collect::<Vec<_>>(),
+            [1, 2, 4, 5]
+        );
+
+        assert!(area.contains(5, 1));
+        assert!(!area.contains(0, 0));
+    }
+
+    #[test]
+    fn output_areas() {
+        let screen = Image {
+            width: 8,
+            height: 2,
+            pixels: Vec::new(),
+        };
+
+        // A 2x scaled output left of an unscaled one, composed at 2x, with a gap below the second.
+        let areas = logical_areas(&[(0, 0, 2, 1), (2, 0, 2, 1)], &screen);
+
+        assert_eq!(
+            areas,
+            [
+                Rect {
+                    x: 0,
+                    y: 0,
+                    width: 4,
+                    height: 2
+                },
+                Rect {
+                    x: 4,
+                    y: 0,
+                    width: 4,
+                    height: 2
+                },
+            ]
+        );
+
+        // Areas reaching past the picture are cut off.
+        assert_eq!(
+            logical_areas(&[(0, 0, 4, 4)], &screen)[0],
+            Rect {
+                x: 0,
+                y: 0,
+                width: 8,
+                height: 2
+            }
+        );
+    }
+}
@@ -0,0 +1,351 @@
+use std::process::{Command, Stdio};
+
+use super::{logical_areas, make_and_model, Capture, Image, Output, Rect, Toplevel};
+
+/// `Grim` captures the screen by running `grim`, which works on wlroots compositors.
+pub struct Grim;
+
+impl Capture for Grim {
+    fn capture(&self, cursor: bool) -> std::io::Result<Image> {
+        let mut command = Command::new("grim");
+
+        if cursor {
+            command.arg("-c");
+        }
+
+        // PPM is trivial to parse and skips compressing a picture that's about to be re-encoded.
+        let output = command
+            .args(["-t", "ppm", "-"])
+            .stdin(Stdio::null())
+            .stderr(Stdio::inherit())
+            .output()?;
+
+        if !output.status.success() {
+            return Err(std::io::Error::other(format!(
+                "grim failed with {}",
+                output.status
+            )));
+        }
+
+        parse_ppm(&output.stdout).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "grim printed an invalid PPM image",
+            )
+        })
+    }
+
+    fn windows(&self, screen: &Image) -> Vec<Rect> {
+        // grim runs on any wlroots compositor, but only sway tells where windows are.
+        match swaymsg("get_tree") {
+            Some(tree) => sway_windows(&tree, screen),
+            None => Vec::new(),
+        }
+    }
+
+    fn toplevels(&self, screen: &Image) -> Vec<Toplevel> {
+        match swaymsg("get_tree") {
+            Some(tree) => sway_toplevels(&tree, screen),
+            None => Vec::new(),
+        }
+    }
+
+    fn outputs(&self, screen: &Image) -> Vec<Output> {
+        let outputs = swaymsg("get_outputs")
+            .map(|outputs| sway_outputs(&outputs, screen))
+            .unwrap_or_default();
+
+        match outputs.is_empty() {
+            true => vec![Output::whole(screen)],
+            false => outputs,
+        }
+    }
+}
+
+/// Ask sway for its `kind` of information, like `get_tree`.
+///
+/// Returns `None` on other compositors.
+fn swaymsg(kind: &str) -> Option<serde_json::Value> {
+    let output = Command::new("swaymsg")
+        .args(["-t", kind, "--raw"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+
+    match serde_json::from_slice(&output.stdout) {
+        Ok(value) => Some(value),
+
+        Err(e) => {
+            log::warn!("failed to parse the sway {} reply: {}", kind, e);
+            None
+        }
+    }
+}
+
+/// List the active outputs of a sway `get_outputs` reply, in pixels of `screen`.
+fn sway_outputs(outputs: &serde_json::Value, screen: &Image) -> Vec<Output> {
+    let active: Vec<&serde_json::Value> = outputs
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|output| output["active"].as_bool() == Some(true))
+        .collect();
+
+    let layouts: Vec<(i32, i32, i32, i32)> = active
+        .iter()
+        .filter_map(|output| layout_rect(output))
+        .map(|(x, y, width, height)| (x as i32, y as i32, width as i32, height as i32))
+        .collect();
+
+    if layouts.len() != active.len() {
+        return Vec::new();
+    }
+
+    let areas = logical_areas(&layouts, screen);
+
+    active
+        .into_iter()
+        .zip(layouts)
+        .zip(areas)
+        .map(|((output, (x, y, width, height)), area)| {
+            let text = |key: &str| output[key].as_str().unwrap_or_default().to_owned();
+
+            Output {
+                name: text("name"),
+                model: make_and_model(&text("make"), &text("model")),
+                area,
+                position: (x, y),
+                size: (width, height),
+            }
+        })
+        .collect()
+}
+
+/// List the visible windows of a sway tree, floating ones first, in pixels of `screen`.
+fn sway_windows(tree: &serde_json::Value, screen: &Image) -> Vec<Rect> {
+    sway_leaves(tree, screen)
+        .into_iter()
+        .map(|(_, area)| area)
+        .collect()
+}
+
+/// List the visible windows of a sway tree with their titles, in pixels of `screen`.
+fn sway_toplevels(tree: &serde_json::Value, screen: &Image) -> Vec<Toplevel> {
+    sway_leaves(tree, screen)
+        .into_iter()
+        .map(|(node, area)| {
+            // XWayland windows have a class instead of an app id.
+            let app_id = node["app_id"]
+                .as_str()
+                .or_else(|| node["window_properties"]["class"].as_str());
+
+            Toplevel {
+                id: node["id"].to_string(),
+                title: node["name"].as_str().unwrap_or_default().to_owned(),
+                app_id: app_id.unwrap_or_default().to_owned(),
+                area,
+            }
+        })
+        .collect()
+}
+
+/// List the visible leaves of a sway tree, floating ones first, with their area in pixels
+/// of `screen`.
+///
+/// The tree is in layout coordinates, which are scaled to the picture on HiDPI outputs.
+fn sway_leaves<'a>(
+    tree: &'a serde_json::Value,
+    screen: &Image,
+) -> Vec<(&'a serde_json::Value, Rect)> {
+    let Some(root) = layout_rect(tree) else {
+        return Vec::new();
+    };
+
+    let scale = f64::from(screen.width) / root.2.max(1.0);
+
+    let (mut floating, mut tiled) = (Vec::new(), Vec::new());
+
+    collect_windows(tree, false, &mut floating, &mut tiled);
+
+    floating
+        .into_iter()
+        .chain(tiled)
+        .filter_map(|node| Some((node, layout_rect(node)?)))
+        .map(|(node, (x, y, width, height))| {
+            let area = Rect {
+                x: ((x - root.0) * scale).max(0.0) as u32,
+                y: ((y - root.1) * scale).max(0.0) as u32,
+                width: (width * scale) as u32,
+                height: (height * scale) as u32,
+            };
+
+            (node, area)
+        })
+        .collect()
+}
+
+/// Add the visible leaves below a sway tree node to `floating` or `tiled`.
+fn collect_windows<'a>(
+    node: &'a serde_json::Value,
+    is_floating: bool,
+    floating: &mut Vec<&'a serde_json::Value>,
+    tiled: &mut Vec<&'a serde_json::Value>,
+) {
+    let children = |key: &str| node[key].as_array().map(Vec::as_slice).unwrap_or_default();
+
+    let (nodes, floating_nodes) = (children("nodes"), children("floating_nodes"));
+
+    if nodes.is_empty() && floating_nodes.is_empty() {
+        if node["visible"].as_bool() == Some(true) {
+            match is_floating {
+                true => floating.push(node),
+                false => tiled.push(node),
+            }
+        }
+
+        return;
+    }
+
+    for child in floating_nodes {
+        collect_windows(child, true, floating, tiled);
+    }
+
+    for child in nodes {
+        collect_windows(child, is_floating, floating, tiled);
+    }
+}
+
+/// Get the `(x, y, width, height)` of a sway tree node.
+fn layout_rect(node: &serde_json::Value) -> Option<(f64, f64, f64, f64)> {
+    let rect = &node["rect"];
+
+    Some((
+        rect["x"].as_f64()?,
+        rect["y"].as_f64()?,
+        rect["width"].as_f64()?,
+        rect["height"].as_f64()?,
+    ))
+}
+
+/// Parse a binary PPM (`P6`) image with 8-bit channels.
+fn parse_ppm(data: &[u8]) -> Option<Image> {
+    let mut rest = data.strip_prefix(b"P6")?;
+
+    // The header is the magic number followed by width, height and maximum value,
+    // each after whitespace, and a single whitespace byte before the pixels.
+    let mut fields = [0u32; 3];
+
+    for field in &mut fields {
+        rest = &rest[rest.iter().position(|b| !b.is_ascii_whitespace())?..];
+
+        let digits = rest.iter().take_while(|b| b.is_ascii_digit()).count();
+
+        *field = std::str::from_utf8(&rest[..digits]).ok()?.parse().ok()?;
+
+        rest = &rest[digits..];
+    }
+
+    let [width, height, max] = fields;
+
+    if max != 255 || !rest.first()?.is_ascii_whitespace() {
+        return None;
+    }
+
+    let rgb = rest.get(1..1 + width as usize * height as usize * 3)?;
+
+    Some(Image {
+        width,
+        height,
+        pixels: rgb
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], u8::MAX])
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_ppm, sway_toplevels, sway_windows};
+    use crate::capture::{Image, Rect};
+
+    #[test]
+    fn ppm() {
+        let mut data = b"P6\n2 1\n255\n".to_vec();
+
+        data.extend([255, 0, 0, 0, 0, 255]);
+
+        let image = parse_ppm(&data).unwrap();
+
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(image.pixels, [255, 0, 0, 255, 0, 0, 255, 255]);
+
+        assert_eq!(parse_ppm(b"P6\n2 1\n255\n\x00"), None);
+        assert_eq!(parse_ppm(b"P3\n1 1\n255\n0 0 0"), None);
+    }
+
+    #[test]
+    fn sway_tree() {
+        let tree = serde_json::json!({
+            "rect": { "x": 0, "y": 0, "width": 1000, "height": 500 },
+            "nodes": [{
+                "rect": { "x": 0, "y": 0, "width": 1000, "height": 500 },
+                "nodes": [
+                    {
+                        "id": 4,
+                        "name": "notes.txt",
+                        "app_id": "org.example.Editor",
+                        "rect": { "x": 0, "y": 0, "width": 500, "height": 500 },
+                        "visible": true,
+                    },
+                    {
+                        "rect": { "x": 500, "y": 0, "width": 500, "height": 500 },
+                        "visible": false,
+                    },
+                ],
+                "floating_nodes": [{
+                    "id": 7,
+                    "name": "Calculator",
+                    "window_properties": { "class": "Galculator" },
+                    "rect": { "x": 100, "y": 100, "width": 200, "height": 100 },
+                    "visible": true,
+                }],
+            }],
+        });
+
+        // A 2x scaled output has twice as many pixels as layout units.
+        let screen = Image {
+            width: 2000,
+            height: 1000,
+            pixels: Vec::new(),
+        };
+
+        assert_eq!(
+            sway_windows(&tree, &screen),
+            [
+                Rect {
+                    x: 200,
+                    y: 200,
+                    width: 400,
+                    height: 200
+                },
+                Rect {
+                    x: 0,
+                    y: 0,
+                    width: 1000,
+                    height: 1000
+                },
+            ]
+        );
+
+        let toplevels = sway_toplevels(&tree, &screen);
+
+        assert_eq!(toplevels.len(), 2);
+        assert_eq!(
+            (toplevels[0].id.as_str(), toplevels[0].app_id.as_str()),
+            ("7", "Galculator")
+        );
+        assert_eq!(toplevels[1].label(), "notes.txt (org.example.Editor)");
+    }
+}
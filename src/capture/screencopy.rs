@@ -0,0 +1,670 @@
+use std::os::{
+    fd::{AsFd, FromRawFd, OwnedFd},
+    unix::fs::FileExt,
+};
+
+use wayland_client::{
+    delegate_noop, event_created_child,
+    globals::{registry_queue_init, GlobalList, GlobalListContents},
+    protocol::{wl_buffer, wl_output, wl_registry, wl_shm, wl_shm_pool},
+    Connection, Dispatch, EventQueue, Proxy, QueueHandle, WEnum,
+};
+use wayland_protocols::xdg::xdg_output::zv1::client::{zxdg_output_manager_v1, zxdg_output_v1};
+use wayland_protocols_wlr::{
+    foreign_toplevel::v1::client::{
+        zwlr_foreign_toplevel_handle_v1, zwlr_foreign_toplevel_manager_v1,
+    },
+    screencopy::v1::client::{zwlr_screencopy_frame_v1, zwlr_screencopy_manager_v1},
+};
+
+use super::{logical_areas, make_and_model, Capture, Image, Output, Toplevel};
+
+/// `Screencopy` captures the screen with the wlr-screencopy protocol of wlroots compositors,
+/// like sway, Hyprland and river.
+pub struct Screencopy;
+
+/// `State` collects the events of the Wayland objects used for a capture.
+#[derive(Default)]
+struct State {
+    /// What the compositor said about each output, by its index.
+    outputs: Vec<OutputInfo>,
+
+    /// What the compositor said about each toplevel, once they're listed.
+    toplevels: Vec<(
+        zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1,
+        ToplevelInfo,
+    )>,
+
+    frame: Frame,
+}
+
+/// `OutputInfo` is what the compositor said about an output.
+#[derive(Default)]
+struct OutputInfo {
+    /// The logical `(x, y, width, height)` of the output, from xdg-output.
+    layout: Option<(i32, i32, i32, i32)>,
+
+    /// The size of the current mode, in pixels.
+    mode: Option<(i32, i32)>,
+
+    name: String,
+    make: String,
+    model: String,
+}
+
+/// `ToplevelInfo` is what the compositor said about a toplevel.
+#[derive(Default)]
+struct ToplevelInfo {
+    title: String,
+    app_id: String,
+
+    /// The last output the toplevel entered.
+    output: Option<wl_output::WlOutput>,
+
+    fullscreen: bool,
+}
+
+/// `Frame` is what the compositor said about the frame being captured.
+#[derive(Default)]
+struct Frame {
+    /// The `(format, width, height, stride)` of the shared memory buffer it expects.
+    buffer: Option<(wl_shm::Format, u32, u32, u32)>,
+
+    buffer_done: bool,
+    y_invert: bool,
+    ready: bool,
+    failed: bool,
+}
+
+impl Capture for Screencopy {
+    fn capture(&self, cursor: bool) -> std::io::Result<Image> {
+        let (globals, mut queue, outputs, mut state) = connect()?;
+
+        let qh = queue.handle();
+
+        let shm: wl_shm::WlShm = globals
+            .bind(&qh, 1..=1, ())
+            .map_err(std::io::Error::other)?;
+
+        let manager: zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1 = globals
+            .bind(&qh, 1..=3, ())
+            .map_err(|e| std::io::Error::other(format!("no wlr-screencopy support: {}", e)))?;
+
+        let mut parts = Vec::new();
+        let mut left = 0;
+
+        for (index, output) in outputs.iter().enumerate() {
+            let image = capture_output(&mut queue, &mut state, &shm, &manager, output, cursor)?;
+
+            let layout = state.outputs[index].layout.unwrap_or((
+                left,
+                0,
+                image.width as i32,
+                image.height as i32,
+            ));
+
+            left = left.max(layout.0 + layout.2);
+
+            parts.push((layout, image));
+        }
+
+        compose(parts)
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "no outputs"))
+    }
+
+    fn outputs(&self, screen: &Image) -> Vec<Output> {
+        let outputs = match connect() {
+            Ok((.., state)) => describe(&state.outputs, screen),
+            Err(e) => {
+                log::warn!("failed to list outputs: {}", e);
+                Vec::new()
+            }
+        };
+
+        match outputs.is_empty() {
+            true => vec![Output::whole(screen)],
+            false => outputs,
+        }
+    }
+
+    fn toplevels(&self, screen: &Image) -> Vec<Toplevel> {
+        match fullscreen_toplevels(screen) {
+            Ok(toplevels) => toplevels,
+
+            Err(e) => {
+                log::warn!("failed to list toplevels: {}", e);
+                Vec::new()
+            }
+        }
+    }
+}
+
+/// Describe outputs with their areas in a capture of `screen`.
+fn describe(outputs: &[OutputInfo], screen: &Image) -> Vec<Output> {
+    // Lay outputs out like `capture` does, using their mode where xdg-output is missing.
+    let mut layouts = Vec::new();
+    let mut left = 0;
+
+    for info in outputs {
+        let (width, height) = info.mode.unwrap_or_default();
+
+        let layout = info.layout.unwrap_or((left, 0, width, height));
+
+        left = left.max(layout.0 + layout.2);
+
+        layouts.push(layout);
+    }
+
+    if layouts.is_empty() {
+        return Vec::new();
+    }
+
+    let areas = logical_areas(&layouts, screen);
+
+    outputs
+        .iter()
+        .zip(layouts)
+        .zip(areas)
+        .enumerate()
+        .map(|(index, ((info, (x, y, width, height)), area))| Output {
+            name: match info.name.as_str() {
+                "" => format!("Output {}", index + 1),
+                name => name.to_owned(),
+            },
+            model: make_and_model(&info.make, &info.model),
+            area,
+            position: (x, y),
+            size: (width, height),
+        })
+        .collect()
+}
+
+/// List the fullscreen toplevels with the wlr-foreign-toplevel-management protocol.
+///
+/// The protocol tells which outputs show a window but not where on them, so only fullscreen
+/// windows, which cover their output, can be shared without what's around them.
+fn fullscreen_toplevels(screen: &Image) -> std::io::Result<Vec<Toplevel>> {
+    let (globals, mut queue, outputs, mut state) = connect()?;
+
+    let qh = queue.handle();
+
+    let _manager: zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1 =
+        globals.bind(&qh, 2..=3, ()).map_err(|e| {
+            std::io::Error::other(format!("no wlr-foreign-toplevel-management support: {}", e))
+        })?;
+
+    // The manager announces the toplevels, which then describe themselves.
+    queue.roundtrip(&mut state).map_err(std::io::Error::other)?;
+    queue.roundtrip(&mut state).map_err(std::io::Error::other)?;
+
+    let described = describe(&state.outputs, screen);
+
+    let toplevels = state
+        .toplevels
+        .iter()
+        .filter(|(_, toplevel)| toplevel.fullscreen)
+        .filter_map(|(handle, toplevel)| {
+            let index = outputs
+                .iter()
+                .position(|output| Some(output) == toplevel.output.as_ref())?;
+
+            Some(Toplevel {
+                id: handle.id().protocol_id().to_string(),
+                title: toplevel.title.clone(),
+                app_id: toplevel.app_id.clone(),
+                area: described.get(index)?.area,
+            })
+        })
+        .collect();
+
+    Ok(toplevels)
+}
+
+/// Connect to the compositor and learn about its outputs.
+fn connect() -> std::io::Result<(
+    GlobalList,
+    EventQueue<State>,
+    Vec<wl_output::WlOutput>,
+    State,
+)> {
+    let conn = Connection::connect_to_env().map_err(std::io::Error::other)?;
+
+    let (globals, mut queue) =
+        registry_queue_init::<State>(&conn).map_err(std::io::Error::other)?;
+
+    let qh = queue.handle();
+
+    let outputs: Vec<wl_output::WlOutput> = globals.contents().with_list(|list| {
+        list.iter()
+            .filter(|global| global.interface == "wl_output")
+            .enumerate()
+            .map(|(index, global)| {
+                globals
+                    .registry()
+                    .bind(global.name, global.version.min(4), &qh, index)
+            })
+            .collect()
+    });
+
+    let mut state = State {
+        outputs: outputs.iter().map(|_| OutputInfo::default()).collect(),
+        ..State::default()
+    };
+
+    // Without xdg-output the outputs are laid out left to right.
+    if let Ok(xdg) =
+        globals.bind::<zxdg_output_manager_v1::ZxdgOutputManagerV1, _, _>(&qh, 1..=3, ())
+    {
+        for (index, output) in outputs.iter().enumerate() {
+            xdg.get_xdg_output(output, &qh, index);
+        }
+    }
+
+    queue.roundtrip(&mut state).map_err(std::io::Error::other)?;
+
+    Ok((globals, queue, outputs, state))
+}
+
+/// Capture a single output into a shared memory buffer.
+fn capture_output(
+    queue: &mut EventQueue<State>,
+    state: &mut State,
+    shm: &wl_shm::WlShm,
+    manager: &zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1,
+    output: &wl_output::WlOutput,
+    cursor: bool,
+) -> std::io::Result<Image> {
+    let qh = queue.handle();
+
+    state.frame = Frame::default();
+
+    let frame = manager.capture_output(cursor.into(), output, &qh, ());
+
+    // Version 3 lists every buffer type and ends with buffer_done, older ones only offer shm.
+    let version = manager.version();
+
+    let described =
+        |frame: &Frame| frame.failed || frame.buffer_done || version < 3 && frame.buffer.is_some();
+
+    while !described(&state.frame) {
+        queue
+            .blocking_dispatch(state)
+            .map_err(std::io::Error::other)?;
+    }
+
+    let Some((format, width, height, stride)) = state.frame.buffer.filter(|_| !state.frame.failed)
+    else {
+        frame.destroy();
+        return Err(std::io::Error::other("the compositor refused to capture"));
+    };
+
+    let size = stride as usize * height as usize;
+
+    let file = std::fs::File::from(memfd()?);
+
+    file.set_len(size as u64)?;
+
+    let pool = shm.create_pool(file.as_fd(), size as i32, &qh, ());
+
+    let buffer = pool.create_buffer(
+        0,
+        width as i32,
+        height as i32,
+        stride as i32,
+        format,
+        &qh,
+        (),
+    );
+
+    frame.copy(&buffer);
+
+    while !state.frame.ready && !state.frame.failed {
+        queue
+            .blocking_dispatch(state)
+            .map_err(std::io::Error::other)?;
+    }
+
+    frame.destroy();
+    buffer.destroy();
+    pool.destroy();
+
+    if state.frame.failed {
+        return Err(std::io::Error::other("the compositor failed to capture"));
+    }
+
+    let mut data = vec![0; size];
+
+    file.read_exact_at(&mut data, 0)?;
+
+    convert(&data, format, width, height, stride, state.frame.y_invert).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            format!("unsupported buffer format {:?}", format),
+        )
+    })
+}
+
+/// Create an anonymous file for a shared memory buffer.
+fn memfd() -> std::io::Result<OwnedFd> {
+    // SAFETY: the name is NUL-terminated; the result is checked below.
+    let fd = unsafe { libc::memfd_create(c"screencopy".as_ptr(), libc::MFD_CLOEXEC) };
+
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    // SAFETY: `fd` was just created and isn't owned by anything else.
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// Convert a shared memory buffer to RGBA pixels.
+///
+/// Only the 8-bit formats compositors use for screencopy are supported.
+fn convert(
+    data: &[u8],
+    format: wl_shm::Format,
+    width: u32,
+    height: u32,
+    stride: u32,
+    y_invert: bool,
+) -> Option<Image> {
+    // The formats are little-endian, so ARGB is stored as BGRA.
+    let (swap, opaque) = match format {
+        wl_shm::Format::Argb8888 => (true, false),
+        wl_shm::Format::Xrgb8888 => (true, true),
+        wl_shm::Format::Abgr8888 => (false, false),
+        wl_shm::Format::Xbgr8888 => (false, true),
+        _ => return None,
+    };
+
+    let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+
+    for row in 0..height {
+        let row = if y_invert { height - 1 - row } else { row };
+
+        let start = row as usize * stride as usize;
+
+        let line = data.get(start..start + width as usize * 4)?;
+
+        pixels.extend(line.chunks_exact(4).flat_map(|p| {
+            let alpha = if opaque { u8::MAX } else { p[3] };
+
+            match swap {
+                true => [p[2], p[1], p[0], alpha],
+                false => [p[0], p[1], p[2], alpha],
+            }
+        }));
+    }
+
+    Some(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+/// Lay the captures of several outputs out in one picture, by their logical position.
+///
+/// Outputs with a lower scale are enlarged to the highest one, so no detail is lost.
+fn compose(parts: Vec<((i32, i32, i32, i32), Image)>) -> Option<Image> {
+    if parts.len() == 1 {
+        return parts.into_iter().next().map(|(_, image)| image);
+    }
+
+    let scale = parts
+        .iter()
+        .map(|((_, _, width, _), image)| f64::from(image.width) / f64::from((*width).max(1)))
+        .fold(1.0, f64::max);
+
+    let left = parts.iter().map(|((x, ..), _)| *x).min()?;
+    let top = parts.iter().map(|((_, y, ..), _)| *y).min()?;
+    let right = parts.iter().map(|((x, _, w, _), _)| x + w).max()?;
+    let bottom = parts.iter().map(|((_, y, _, h), _)| y + h).max()?;
+
+    let to_pixels = |logical: i32| (f64::from(logical) * scale).round() as u32;
+
+    let (width, height) = (to_pixels(right - left), to_pixels(bottom - top));
+
+    let mut pixels = vec![0; width as usize * height as usize * 4];
+
+    for ((x, y, w, h), image) in parts {
+        let (x0, y0) = (to_pixels(x - left), to_pixels(y - top));
+        let (w, h) = (to_pixels(w), to_pixels(h));
+
+        for dy in 0..h.min(height - y0) {
+            let sy = (u64::from(dy) * u64::from(image.height) / u64::from(h)) as u32;
+
+            for dx in 0..w.min(width - x0) {
+                let sx = (u64::from(dx) * u64::from(image.width) / u64::from(w)) as u32;
+
+                let Some(pixel) = image.pixel(sx, sy) else {
+                    continue;
+                };
+
+                let offset = ((y0 + dy) as usize * width as usize + (x0 + dx) as usize) * 4;
+
+                pixels[offset..offset + 4].copy_from_slice(&pixel);
+            }
+        }
+    }
+
+    Some(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for State {
+    fn event(
+        _: &mut Self,
+        _: &wl_registry::WlRegistry,
+        _: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<zxdg_output_v1::ZxdgOutputV1, usize> for State {
+    fn event(
+        state: &mut Self,
+        _: &zxdg_output_v1::ZxdgOutputV1,
+        event: zxdg_output_v1::Event,
+        index: &usize,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let Some(info) = state.outputs.get_mut(*index) else {
+            return;
+        };
+
+        let (x, y, width, height) = info.layout.get_or_insert((0, 0, 0, 0));
+
+        match event {
+            zxdg_output_v1::Event::LogicalPosition { x: lx, y: ly } => (*x, *y) = (lx, ly),
+
+            zxdg_output_v1::Event::LogicalSize {
+                width: lw,
+                height: lh,
+            } => (*width, *height) = (lw, lh),
+
+            // wl_output names come with version 4, older compositors name outputs here.
+            zxdg_output_v1::Event::Name { name } if info.name.is_empty() => info.name = name,
+
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<wl_output::WlOutput, usize> for State {
+    fn event(
+        state: &mut Self,
+        _: &wl_output::WlOutput,
+        event: wl_output::Event,
+        index: &usize,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let Some(info) = state.outputs.get_mut(*index) else {
+            return;
+        };
+
+        match event {
+            wl_output::Event::Geometry { make, model, .. } => {
+                (info.make, info.model) = (make, model)
+            }
+
+            wl_output::Event::Mode {
+                flags: WEnum::Value(flags),
+                width,
+                height,
+                ..
+            } if flags.contains(wl_output::Mode::Current) => info.mode = Some((width, height)),
+
+            wl_output::Event::Name { name } => info.name = name,
+
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1,
+        event: zwlr_screencopy_frame_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let frame = &mut state.frame;
+
+        match event {
+            zwlr_screencopy_frame_v1::Event::Buffer {
+                format: WEnum::Value(format),
+                width,
+                height,
+                stride,
+            } => frame.buffer = Some((format, width, height, stride)),
+
+            zwlr_screencopy_frame_v1::Event::Flags {
+                flags: WEnum::Value(flags),
+            } => frame.y_invert = flags.contains(zwlr_screencopy_frame_v1::Flags::YInvert),
+
+            zwlr_screencopy_frame_v1::Event::BufferDone => frame.buffer_done = true,
+
+            zwlr_screencopy_frame_v1::Event::Ready { .. } => frame.ready = true,
+
+            zwlr_screencopy_frame_v1::Event::Failed => frame.failed = true,
+
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1,
+        event: zwlr_foreign_toplevel_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } = event {
+            state.toplevels.push((toplevel, ToplevelInfo::default()));
+        }
+    }
+
+    event_created_child!(State, zwlr_foreign_toplevel_manager_v1::ZwlrForeignToplevelManagerV1, [
+        zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        handle: &zwlr_foreign_toplevel_handle_v1::ZwlrForeignToplevelHandleV1,
+        event: zwlr_foreign_toplevel_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let Some((_, toplevel)) = state.toplevels.iter_mut().find(|(h, _)| h == handle) else {
+            return;
+        };
+
+        match event {
+            zwlr_foreign_toplevel_handle_v1::Event::Title { title } => toplevel.title = title,
+
+            zwlr_foreign_toplevel_handle_v1::Event::AppId { app_id } => toplevel.app_id = app_id,
+
+            zwlr_foreign_toplevel_handle_v1::Event::OutputEnter { output } => {
+                toplevel.output = Some(output);
+            }
+
+            // The state is an array of native-endian 32-bit values.
+            zwlr_foreign_toplevel_handle_v1::Event::State { state } => {
+                let fullscreen = zwlr_foreign_toplevel_handle_v1::State::Fullscreen as u32;
+
+                toplevel.fullscreen = state
+                    .chunks_exact(4)
+                    .any(|value| value == fullscreen.to_ne_bytes());
+            }
+
+            _ => {}
+        }
+    }
+}
+
+delegate_noop!(State: ignore wl_shm::WlShm);
+delegate_noop!(State: wl_shm_pool::WlShmPool);
+delegate_noop!(State: ignore wl_buffer::WlBuffer);
+delegate_noop!(State: zxdg_output_manager_v1::ZxdgOutputManagerV1);
+delegate_noop!(State: zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1);
+
+#[cfg(test)]
+mod tests {
+    use wayland_client::protocol::wl_shm;
+
+    use super::{compose, convert};
+    use crate::capture::Image;
+
+    #[test]
+    fn buffer_formats() {
+        // Two rows with a stride wider than the pixels, bottom row first.
+        let data = [1, 2, 3, 4, 0, 0, 0, 0, 5, 6, 7, 8, 0, 0, 0, 0];
+
+        let image = convert(&data, wl_shm::Format::Xrgb8888, 1, 2, 8, true).unwrap();
+
+        assert_eq!(image.pixels, [7, 6, 5, 255, 3, 2, 1, 255]);
+
+        let image = convert(&data, wl_shm::Format::Abgr8888, 1, 2, 8, false).unwrap();
+
+        assert_eq!(image.pixels, [1, 2, 3, 4, 5, 6, 7, 8]);
+
+        assert!(convert(&data, wl_shm::Format::Rgb565, 1, 2, 8, false).is_none());
+    }
+
+    #[test]
+    fn layout() {
+        let solid = |width, height, value| Image {
+            width,
+            height,
+            pixels: vec![value; width as usize * height as usize * 4],
+        };
+
+        // A 2x scaled output left of an unscaled one of the same logical size.
+        let image = compose(vec![
+            ((0, 0, 2, 1), solid(4, 2, 1)),
+            ((2, 0, 2, 1), solid(2, 1, 2)),
+        ])
+        .unwrap();
+
+        assert_eq!((image.width, image.height), (8, 2));
+        assert_eq!(image.pixel(3, 1), Some([1; 4]));
+        assert_eq!(image.pixel(4, 1), Some([2; 4]));
+    }
+}
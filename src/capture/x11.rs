@@ -0,0 +1,449 @@
+use x11rb::{
+    connection::Connection,
+    protocol::{
+        randr::ConnectionExt as _,
+        xfixes::ConnectionExt as _,
+        xproto::{self, ConnectionExt as _, ImageFormat, ImageOrder, MapState},
+    },
+};
+
+use super::{Capture, Cursor, Image, Output, Rect, Toplevel};
+
+/// `X11` captures the root window of an X11 session, which spans every monitor.
+pub struct X11;
+
+impl Capture for X11 {
+    fn capture(&self, cursor: bool) -> std::io::Result<Image> {
+        let (conn, screen) = x11rb::connect(None).map_err(std::io::Error::other)?;
+
+        let setup = conn.setup();
+        let screen = &setup.roots[screen];
+
+        let (width, height) = (screen.width_in_pixels, screen.height_in_pixels);
+
+        let reply = conn
+            .get_image(ImageFormat::Z_PIXMAP, screen.root, 0, 0, width, height, !0)
+            .map_err(std::io::Error::other)?
+            .reply()
+            .map_err(std::io::Error::other)?;
+
+        let visual = screen
+            .allowed_depths
+            .iter()
+            .flat_map(|depth| &depth.visuals)
+            .find(|visual| visual.visual_id == reply.visual);
+
+        let masks = visual.map(|visual| (visual.red_mask, visual.green_mask, visual.blue_mask));
+
+        let bits_per_pixel = setup
+            .pixmap_formats
+            .iter()
+            .find(|format| format.depth == reply.depth)
+            .map(|format| format.bits_per_pixel);
+
+        // Only the 24-bit true color layout every current X server uses is supported.
+        if masks != Some((0xff0000, 0xff00, 0xff)) || bits_per_pixel != Some(32) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!("unsupported pixel format of depth {}", reply.depth),
+            ));
+        }
+
+        let lsb_first = setup.image_byte_order == ImageOrder::LSB_FIRST;
+
+        let mut image = convert(&reply.data, width.into(), height.into(), lsb_first)
+            .ok_or_else(|| std::io::Error::other("the X server sent a truncated image"))?;
+
+        // Parts of the root window outside every monitor aren't shown anywhere.
+        if let Ok(monitors) = conn.randr_get_monitors(screen.root, true) {
+            if let Ok(monitors) = monitors.reply() {
+                let monitors: Vec<Rect> = monitors
+                    .monitors
+                    .iter()
+                    .map(|monitor| Rect {
+                        x: monitor.x.max(0) as u32,
+                        y: monitor.y.max(0) as u32,
+                        width: monitor.width.into(),
+                        height: monitor.height.into(),
+                    })
+                    .collect();
+
+                clear_outside(&mut image, &monitors);
+            }
+        }
+
+        // The root window doesn't contain the pointer, XFixes hands it out separately.
+        if cursor {
+            if let Err(e) = draw_cursor(&conn, &mut image) {
+                log::warn!("failed to draw the pointer: {}", e);
+            }
+        }
+
+        Ok(image)
+    }
+
+    fn windows(&self, _screen: &Image) -> Vec<Rect> {
+        match windows() {
+            Ok(windows) => windows,
+
+            Err(e) => {
+                log::warn!("failed to list X11 windows: {}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    fn toplevels(&self, screen: &Image) -> Vec<Toplevel> {
+        match toplevels(screen) {
+            Ok(toplevels) => toplevels,
+
+            Err(e) => {
+                log::warn!("failed to list X11 windows: {}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    fn cursor(&self) -> Option<Cursor> {
+        match cursor() {
+            Ok(cursor) => Some(cursor),
+
+            Err(e) => {
+                log::warn!("failed to get the pointer: {}", e);
+                None
+            }
+        }
+    }
+
+    fn outputs(&self, screen: &Image) -> Vec<Output> {
+        match monitors(screen) {
+            Ok(outputs) if !outputs.is_empty() => outputs,
+
+            Ok(_) => vec![Output::whole(screen)],
+
+            Err(e) => {
+                log::warn!("failed to list X11 monitors: {}", e);
+                vec![Output::whole(screen)]
+            }
+        }
+    }
+}
+
+/// List the RandR monitors, which are laid out in root window pixels.
+fn monitors(screen: &Image) -> Result<Vec<Output>, Box<dyn std::error::Error>> {
+    let (conn, index) = x11rb::connect(None)?;
+
+    let root = conn.setup().roots[index].root;
+
+    let mut outputs = Vec::new();
+
+    for monitor in conn.randr_get_monitors(root, true)?.reply()?.monitors {
+        let name = conn.get_atom_name(monitor.name)?.reply()?.name;
+
+        let area = screen.bounds().clip(&Rect {
+            x: monitor.x.max(0) as u32,
+            y: monitor.y.max(0) as u32,
+            width: monitor.width.into(),
+            height: monitor.height.into(),
+        });
+
+        outputs.push(Output {
+            name: String::from_utf8_lossy(&name).into_owned(),
+            model: String::new(),
+            area,
+            position: (monitor.x.into(), monitor.y.into()),
+            size: (monitor.width.into(), monitor.height.into()),
+        });
+    }
+
+    Ok(outputs)
+}
+
+/// Draw the current pointer image over a capture of the root window.
+fn draw_cursor(
+    conn: &impl Connection,
+    image: &mut Image,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // XFixes requests fail until the client said which version it speaks.
+    conn.xfixes_query_version(4, 0)?.reply()?;
+
+    let cursor = conn.xfixes_get_cursor_image()?.reply()?;
+
+    let left = i32::from(cursor.x) - i32::from(cursor.xhot);
+    let top = i32::from(cursor.y) - i32::from(cursor.yhot);
+
+    blend(image, left, top, cursor.width.into(), &cursor.cursor_image);
+
+    Ok(())
+}
+
+/// Get the current pointer image from XFixes.
+fn cursor() -> Result<Cursor, Box<dyn std::error::Error>> {
+    let (conn, _) = x11rb::connect(None)?;
+
+    conn.xfixes_query_version(4, 0)?.reply()?;
+
+    let cursor = conn.xfixes_get_cursor_image()?.reply()?;
+
+    Ok(Cursor {
+        position: (cursor.x.into(), cursor.y.into()),
+        hotspot: (cursor.xhot.into(), cursor.yhot.into()),
+        image: unpremultiply(
+            cursor.width.into(),
+            cursor.height.into(),
+            &cursor.cursor_image,
+        ),
+    })
+}
+
+/// Convert premultiplied ARGB pixels, `width` per row, to an image.
+fn unpremultiply(width: u32, height: u32, argb: &[u32]) -> Image {
+    let pixels = argb
+        .iter()
+        .take(width as usize * height as usize)
+        .flat_map(|pixel| {
+            let [a, r, g, b] = pixel.to_be_bytes();
+
+            let straight = |channel: u8| match a {
+                0 => 0,
+                _ => (u32::from(channel) * u32::from(u8::MAX) / u32::from(a)).min(255) as u8,
+            };
+
+            [straight(r), straight(g), straight(b), a]
+        })
+        .collect();
+
+    Image {
+        width,
+        height,
+        pixels,
+    }
+}
+
+/// Draw premultiplied ARGB pixels, `width` per row, over the image at `left`, `top`.
+fn blend(image: &mut Image, left: i32, top: i32, width: u32, argb: &[u32]) {
+    for (i, &pixel) in argb.iter().enumerate() {
+        let x = left + (i as u32 % width.max(1)) as i32;
+        let y = top + (i as u32 / width.max(1)) as i32;
+
+        if x < 0 || y < 0 || x as u32 >= image.width || y as u32 >= image.height {
+            continue;
+        }
+
+        let [a, r, g, b] = pixel.to_be_bytes();
+
+        let offset = (y as usize * image.width as usize + x as usize) * 4;
+
+        let dst = &mut image.pixels[offset..offset + 4];
+
+        for (channel, source) in dst.iter_mut().zip([r, g, b, a]) {
+            let covered = u32::from(*channel) * u32::from(u8::MAX - a) / u32::from(u8::MAX);
+
+            *channel = (u32::from(source) + covered).min(u32::from(u8::MAX)) as u8;
+        }
+    }
+}
+
+/// List the areas of the visible top-level windows, topmost first.
+fn windows() -> Result<Vec<Rect>, Box<dyn std::error::Error>> {
+    let (conn, screen) = x11rb::connect(None)?;
+
+    let root = conn.setup().roots[screen].root;
+
+    let windows = clients(&conn, root)?;
+
+    Ok(windows.into_iter().map(|(_, area)| area).collect())
+}
+
+/// List the visible top-level windows with their titles and classes, topmost first.
+fn toplevels(screen: &Image) -> Result<Vec<Toplevel>, Box<dyn std::error::Error>> {
+    let (conn, index) = x11rb::connect(None)?;
+
+    let root = conn.setup().roots[index].root;
+
+    let utf8_string = conn.intern_atom(false, b"UTF8_STRING")?.reply()?.atom;
+    let net_wm_name = conn.intern_atom(false, b"_NET_WM_NAME")?.reply()?.atom;
+
+    let mut toplevels = Vec::new();
+
+    for (window, area) in clients(&conn, root)? {
+        let text = |property: xproto::Atom, type_: xproto::Atom| {
+            conn.get_property(false, window, property, type_, 0, u32::MAX)
+                .ok()
+                .and_then(|cookie| cookie.reply().ok())
+                .map(|reply| reply.value)
+                .unwrap_or_default()
+        };
+
+        // Old clients only set the Latin-1 WM_NAME.
+        let mut title = text(net_wm_name, utf8_string);
+
+        if title.is_empty() {
+            title = text(
+                xproto::AtomEnum::WM_NAME.into(),
+                xproto::AtomEnum::STRING.into(),
+            );
+        }
+
+        // WM_CLASS holds the instance and the class name, each NUL-terminated.
+        let class = text(
+            xproto::AtomEnum::WM_CLASS.into(),
+            xproto::AtomEnum::STRING.into(),
+        );
+
+        let app_id = class.split(|byte| *byte == 0).nth(1).unwrap_or_default();
+
+        toplevels.push(Toplevel {
+            id: window.to_string(),
+            title: String::from_utf8_lossy(&title).into_owned(),
+            app_id: String::from_utf8_lossy(app_id).into_owned(),
+            area: screen.bounds().clip(&area),
+        });
+    }
+
+    Ok(toplevels)
+}
+
+/// List the visible top-level windows from the window manager's stacking list with their
+/// areas, topmost first.
+fn clients(
+    conn: &impl Connection,
+    root: xproto::Window,
+) -> Result<Vec<(xproto::Window, Rect)>, Box<dyn std::error::Error>> {
+    let stacking = conn
+        .intern_atom(true, b"_NET_CLIENT_LIST_STACKING")?
+        .reply()?
+        .atom;
+
+    let clients: Vec<xproto::Window> = conn
+        .get_property(false, root, stacking, xproto::AtomEnum::WINDOW, 0, u32::MAX)?
+        .reply()?
+        .value32()
+        .map(Iterator::collect)
+        .unwrap_or_default();
+
+    let mut windows = Vec::new();
+
+    // The list is bottom to top.
+    for client in clients.into_iter().rev() {
+        let attributes = conn.get_window_attributes(client)?.reply()?;
+
+        if attributes.map_state != MapState::VIEWABLE {
+            continue;
+        }
+
+        let geometry = conn.get_geometry(client)?.reply()?;
+
+        let position = conn.translate_coordinates(client, root, 0, 0)?.reply()?;
+
+        let area = Rect {
+            x: position.dst_x.max(0) as u32,
+            y: position.dst_y.max(0) as u32,
+            width: geometry.width.into(),
+            height: geometry.height.into(),
+        };
+
+        windows.push((client, area));
+    }
+
+    Ok(windows)
+}
+
+/// Convert a 32-bit Z pixmap with 8-bit red, green and blue channels to RGBA pixels.
+fn convert(data: &[u8], width: u32, height: u32, lsb_first: bool) -> Option<Image> {
+    let size = width as usize * height as usize * 4;
+
+    let pixels = data
+        .get(..size)?
+        .chunks_exact(4)
+        .flat_map(|p| match lsb_first {
+            true => [p[2], p[1], p[0], u8::MAX],
+            false => [p[1], p[2], p[3], u8::MAX],
+        })
+        .collect();
+
+    Some(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+/// Make the pixels outside every monitor transparent.
+fn clear_outside(image: &mut Image, monitors: &[Rect]) {
+    if monitors.is_empty() {
+        return;
+    }
+
+    for y in 0..image.height {
+        for x in 0..image.width {
+            if monitors.iter().any(|monitor| monitor.contains(x, y)) {
+                continue;
+            }
+
+            let offset = (y as usize * image.width as usize + x as usize) * 4;
+
+            image.pixels[offset..offset + 4].fill(0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{blend, clear_outside, convert, unpremultiply};
+    use crate::capture::Rect;
+
+    #[test]
+    fn pixels_and_monitors() {
+        let data = [1, 2, 3, 0, 4, 5, 6, 0];
+
+        assert_eq!(
+            convert(&data, 2, 1, true).unwrap().pixels,
+            [3, 2, 1, 255, 6, 5, 4, 255]
+        );
+        assert_eq!(
+            convert(&data, 2, 1, false).unwrap().pixels,
+            [2, 3, 0, 255, 5, 6, 0, 255]
+        );
+        assert!(convert(&data, 3, 1, true).is_none());
+
+        // Two monitors of different heights leave a corner of the root window unused.
+        let mut image = convert(&[9; 16], 2, 2, true).unwrap();
+
+        let monitors = [
+            Rect {
+                x: 0,
+                y: 0,
+                width: 1,
+                height: 2,
+            },
+            Rect {
+                x: 1,
+                y: 0,
+                width: 1,
+                height: 1,
+            },
+        ];
+
+        clear_outside(&mut image, &monitors);
+
+        assert_eq!(image.pixel(1, 0), Some([9, 9, 9, 255]));
+        assert_eq!(image.pixel(1, 1), Some([0; 4]));
+    }
+
+    #[test]
+    fn cursor_blending() {
+        let mut image = convert(&[0; 8], 2, 1, true).unwrap();
+
+        // An opaque red pixel and a half transparent white one, hanging off the right edge.
+        blend(&mut image, 0, 0, 3, &[0xffff0000, 0x80808080, 0xffffffff]);
+
+        assert_eq!(image.pixel(0, 0), Some([255, 0, 0, 255]));
+        assert_eq!(image.pixel(1, 0), Some([128, 128, 128, 255]));
+
+        let cursor = unpremultiply(2, 1, &[0x80808080, 0]);
+
+        assert_eq!(cursor.pixel(0, 0), Some([255, 255, 255, 128]));
+        assert_eq!(cursor.pixel(1, 0), Some([0; 4]));
+    }
+}
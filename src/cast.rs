@@ -0,0 +1,181 @@
+use std::sync::Arc;
+
+use crate::capture::{Capture, Output, Rect, Toplevel};
+
+#[cfg(feature = "pipewire")]
+mod headless;
+#[cfg(feature = "pipewire")]
+mod memory;
+#[cfg(feature = "pipewire")]
+mod pipewire;
+
+/// The source type bit of monitors, in ScreenCast options and properties.
+pub const MONITOR: u32 = 1;
+
+/// The source type bit of single windows.
+pub const WINDOW: u32 = 2;
+
+/// The source type bit of virtual displays, made for the stream.
+pub const VIRTUAL: u32 = 4;
+
+/// The cursor mode bit of streams without the pointer, in ScreenCast options and properties.
+pub const HIDDEN: u32 = 1;
+
+/// The cursor mode bit of streams with the pointer drawn into the frames.
+#[cfg_attr(not(feature = "pipewire"), allow(dead_code))]
+pub const EMBEDDED: u32 = 2;
+
+/// The cursor mode bit of streams sending the pointer as metadata next to the frames.
+#[cfg_attr(not(feature = "pipewire"), allow(dead_code))]
+pub const METADATA: u32 = 4;
+
+/// `Cast` streams the screen through PipeWire for the ScreenCast portal.
+///
+/// Starting and stopping streams block, so callers run them off the async executor.
+pub trait Cast: Send + Sync {
+    /// The source type bits of what can be streamed.
+    fn source_types(&self) -> u32;
+
+    /// The cursor mode bits streams can be started with.
+    fn cursor_modes(&self) -> u32;
+
+    /// Start a stream of each of the `sources` the user picked, as `selection` asks for.
+    fn start(&self, selection: &Selection, sources: &[Source]) -> std::io::Result<Vec<Stream>>;
+
+    /// Stop streams started by `start`.
+    fn stop(&self, streams: &[Stream]);
+}
+
+/// `Selection` is what an app asked to stream with SelectSources.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Selection {
+    /// The source type bits the app accepts.
+    pub types: u32,
+
+    /// Whether the app accepts several sources.
+    pub multiple: bool,
+
+    /// The cursor mode bit of the streams.
+    pub cursor_mode: u32,
+}
+
+impl Default for Selection {
+    fn default() -> Self {
+        Self {
+            types: MONITOR,
+            multiple: false,
+            cursor_mode: HIDDEN,
+        }
+    }
+}
+
+/// `Source` is something the user picked to stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// A monitor, streamed as its area of pictures of the screen.
+    Monitor(Output),
+
+    /// A window, streamed as its area of pictures of the screen, which follows it around.
+    Window(Toplevel),
+
+    /// A new virtual display, which is created once the stream starts and removed with it.
+    Virtual,
+
+    /// A part of the screen, streamed as that area of pictures of the screen.
+    ///
+    /// Until the user selected the part, it's the whole screen.
+    Region(Rect),
+}
+
+impl Source {
+    /// The source type bit of the source.
+    #[cfg_attr(not(any(feature = "egui", feature = "pipewire")), allow(dead_code))]
+    pub fn source_type(&self) -> u32 {
+        match self {
+            Source::Monitor(_) | Source::Region(_) => MONITOR,
+            Source::Window(_) => WINDOW,
+            Source::Virtual => VIRTUAL,
+        }
+    }
+
+    /// Describe the source for people.
+    pub fn label(&self) -> String {
+        match self {
+            Source::Monitor(output) => output.label(),
+            Source::Window(toplevel) => toplevel.label(),
+            Source::Virtual => String::from("New virtual display"),
+            Source::Region(_) => String::from("Part of the screen"),
+        }
+    }
+
+    /// The area of the source in pictures of the screen, in pixels.
+    ///
+    /// Virtual displays aren't on the screen until they're streamed, so theirs is empty.
+    #[cfg_attr(not(any(feature = "egui", feature = "pipewire")), allow(dead_code))]
+    pub fn area(&self) -> Rect {
+        match self {
+            Source::Monitor(output) => output.area,
+            Source::Window(toplevel) => toplevel.area,
+            Source::Virtual => Rect::default(),
+            Source::Region(area) => *area,
+        }
+    }
+}
+
+/// `Stream` is a PipeWire stream of a single source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stream {
+    /// The id of the PipeWire node consumers connect to.
+    pub node_id: u32,
+
+    /// The source type bit of what's streamed.
+    pub source_type: u32,
+
+    /// The position of a monitor in the compositor's layout; windows have none.
+    pub position: Option<(i32, i32)>,
+
+    /// The size of the stream, in layout coordinates for monitors and pixels for windows.
+    pub size: (i32, i32),
+}
+
+/// `Unsupported` stands in for streaming in builds without PipeWire support,
+/// offering no sources.
+#[cfg(not(feature = "pipewire"))]
+struct Unsupported;
+
+#[cfg(not(feature = "pipewire"))]
+impl Cast for Unsupported {
+    fn source_types(&self) -> u32 {
+        0
+    }
+
+    fn cursor_modes(&self) -> u32 {
+        0
+    }
+
+    fn start(&self, _selection: &Selection, _sources: &[Source]) -> std::io::Result<Vec<Stream>> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "screen casting isn't supported by this build",
+        ))
+    }
+
+    fn stop(&self, _streams: &[Stream]) {}
+}
+
+/// Create the streaming backend, streaming what `capture` takes.
+///
+/// Streaming needs the pipewire feature; without it, no sources are offered.
+pub fn new(capture: Arc<dyn Capture>) -> Arc<dyn Cast> {
+    #[cfg(feature = "pipewire")]
+    let cast: Arc<dyn Cast> = Arc::new(pipewire::PipeWire::new(capture));
+
+    #[cfg(not(feature = "pipewire"))]
+    let cast: Arc<dyn Cast> = {
+        log::info!("built without the pipewire feature, screen casting is unavailable");
+        drop(capture);
+        Arc::new(Unsupported)
+    };
+
+    cast
+}
@@ -0,0 +1,70 @@
+use std::{
+    process::{Command, Stdio},
+    sync::Mutex,
+};
+
+/// Keeps concurrent streams from taking each other's new output for their own.
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// Whether virtual outputs can be created, which takes sway.
+pub fn supported() -> bool {
+    std::env::var_os("SWAYSOCK").is_some()
+}
+
+/// Create a headless output the compositor lays out like a monitor, returning its name.
+pub fn create() -> std::io::Result<String> {
+    let _guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    let before = outputs()?;
+
+    swaymsg(&["create_output"])?;
+
+    // Sway doesn't say which output it created, only that it did.
+    outputs()?
+        .into_iter()
+        .find(|name| !before.contains(name))
+        .ok_or_else(|| std::io::Error::other("sway didn't create a headless output"))
+}
+
+/// Remove an output made by `create`.
+pub fn remove(name: &str) {
+    match swaymsg(&["output", name, "unplug"]) {
+        Ok(_) => log::info!("removed the virtual output {}", name),
+        Err(e) => log::warn!("failed to remove the virtual output {}: {}", name, e),
+    }
+}
+
+/// List the names of sway's outputs.
+fn outputs() -> std::io::Result<Vec<String>> {
+    let reply = swaymsg(&["-t", "get_outputs", "--raw"])?;
+
+    let outputs: serde_json::Value = serde_json::from_slice(&reply)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+    Ok(outputs
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|output| output["name"].as_str())
+        .map(String::from)
+        .collect())
+}
+
+/// Run swaymsg with `args`, returning what it printed.
+fn swaymsg(args: &[&str]) -> std::io::Result<Vec<u8>> {
+    let output = Command::new("swaymsg")
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()?;
+
+    if !output.status.success() {
+        return Err(std::io::Error::other(format!(
+            "swaymsg {} failed with {}",
+            args.join(" "),
+            output.status
+        )));
+    }
+
+    Ok(output.stdout)
+}
@@ -0,0 +1,149 @@
+use std::{
+    fs::OpenOptions,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+};
+
+/// The device turning memfds into DMA-BUFs.
+const UDMABUF: &str = "/dev/udmabuf";
+
+/// `UDMABUF_CREATE`, `_IOW('u', 0x42, struct udmabuf_create)`.
+const UDMABUF_CREATE: u64 = 0x4018_7542;
+
+/// Makes the DMA-BUF close on exec, like the memfd behind it.
+const UDMABUF_FLAGS_CLOEXEC: u32 = 0x01;
+
+/// `UdmabufCreate` is the argument of `UDMABUF_CREATE`.
+#[repr(C)]
+struct UdmabufCreate {
+    memfd: u32,
+    flags: u32,
+    offset: u64,
+    size: u64,
+}
+
+/// `Memory` is the mapped memory of a stream buffer, shared with consumers by its fd.
+///
+/// DMA-BUFs are made by /dev/udmabuf out of a memfd, so frames are still written by the CPU,
+/// but consumers can import them on the GPU instead of copying every frame again.
+pub struct Memory {
+    /// The memfd, or the DMA-BUF made of it.
+    fd: OwnedFd,
+    map: *mut u8,
+    size: usize,
+}
+
+impl Memory {
+    /// Allocate `size` bytes, as a DMA-BUF if `dmabuf` is set and as a memfd otherwise.
+    pub fn new(size: usize, dmabuf: bool) -> std::io::Result<Self> {
+        let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) }.max(1) as usize;
+
+        // DMA-BUFs are made of whole pages.
+        let size = size.div_ceil(page) * page;
+
+        let memfd = memfd(size)?;
+
+        let map = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                memfd.as_raw_fd(),
+                0,
+            )
+        };
+
+        if map == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        let mut memory = Self {
+            fd: memfd,
+            map: map.cast(),
+            size,
+        };
+
+        // The mapping of the memfd is the memory of the DMA-BUF, so it's written through.
+        if dmabuf {
+            memory.fd = udmabuf(&memory.fd, size)?;
+        }
+
+        Ok(memory)
+    }
+
+    /// Get the fd consumers map or import the memory with.
+    pub fn fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+
+    /// Get the mapped memory.
+    pub fn as_mut_ptr(&self) -> *mut u8 {
+        self.map
+    }
+
+    /// Get the size of the memory, in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+impl Drop for Memory {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.map.cast(), self.size) };
+    }
+}
+
+/// Whether DMA-BUFs can be made, which needs access to /dev/udmabuf.
+pub fn dmabuf_supported() -> bool {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(UDMABUF)
+        .is_ok()
+}
+
+/// Create a memfd of `size` bytes that can't shrink, as udmabuf requires.
+fn memfd(size: usize) -> std::io::Result<OwnedFd> {
+    let fd = unsafe {
+        libc::memfd_create(
+            c"xdg-desktop-portal-rs".as_ptr(),
+            libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING,
+        )
+    };
+
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    if unsafe { libc::ftruncate(fd.as_raw_fd(), size as libc::off_t) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    if unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_ADD_SEALS, libc::F_SEAL_SHRINK) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(fd)
+}
+
+/// Make a DMA-BUF of the first `size` bytes of `memfd`.
+fn udmabuf(memfd: &OwnedFd, size: usize) -> std::io::Result<OwnedFd> {
+    let device = OpenOptions::new().read(true).write(true).open(UDMABUF)?;
+
+    let create = UdmabufCreate {
+        memfd: memfd.as_raw_fd() as u32,
+        flags: UDMABUF_FLAGS_CLOEXEC,
+        offset: 0,
+        size: size as u64,
+    };
+
+    let fd = unsafe { libc::ioctl(device.as_raw_fd(), UDMABUF_CREATE as _, &create) };
+
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
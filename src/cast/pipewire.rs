@@ -0,0 +1,964 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use pipewire as pw;
+use pw::spa::{
+    self,
+    param::{
+        format::{FormatProperties, MediaSubtype, MediaType},
+        video::{VideoFlags, VideoFormat, VideoInfoRaw},
+        ParamType,
+    },
+    pod::{ChoiceValue, Object, Pod, Property, PropertyFlags, Value},
+    utils::{Choice, ChoiceEnum, ChoiceFlags, Fraction, Id, Rectangle, SpaTypes},
+};
+
+use super::{
+    headless,
+    memory::{self, Memory},
+    Cast, Selection, Source, Stream, EMBEDDED, HIDDEN, METADATA, MONITOR, VIRTUAL, WINDOW,
+};
+use crate::capture::{Capture, Cursor, Image, Output, Rect, Toplevel};
+
+/// The most frames per second streams offer; capturing a whole screen rarely keeps up with more.
+const FRAME_RATE: u32 = 30;
+
+/// How often window streams look for where their window moved.
+const FOLLOW_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long PipeWire gets to create the node of a new stream.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a new virtual output gets to show up in captures.
+const OUTPUT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The modifier of DMA-BUFs laid out row by row, the only layout the CPU writes.
+const DRM_FORMAT_MOD_LINEAR: i64 = 0;
+
+/// The largest pointer picture sent as metadata, in pixels each way; bigger ones are cut.
+const CURSOR_SIZE: u32 = 64;
+
+/// The size of the cursor metadata of a buffer, with room for the largest pointer picture.
+const CURSOR_META_SIZE: usize = std::mem::size_of::<spa::sys::spa_meta_cursor>()
+    + std::mem::size_of::<spa::sys::spa_meta_bitmap>()
+    + CURSOR_SIZE as usize * CURSOR_SIZE as usize * 4;
+
+/// `PipeWire` streams pictures of the picked monitors and windows as PipeWire video sources.
+///
+/// PipeWire objects can't leave the thread of their loop, so the streams live on a dedicated
+/// thread, which is sent commands through a channel. Each stream has a thread of its own
+/// capturing frames for it.
+/// The frontend hands consumers a connection to PipeWire that only sees the streams' nodes,
+/// so only their ids are needed for Start.
+pub struct PipeWire {
+    capture: Arc<dyn Capture>,
+    commands: pw::channel::Sender<Command>,
+
+    /// The serial of the next stream, which identifies it before PipeWire assigned a node id.
+    serial: AtomicU64,
+
+    /// The serial and capture thread flag of each stream, by node id.
+    running: Mutex<HashMap<u32, (u64, Arc<AtomicBool>)>>,
+
+    /// The virtual output of each stream of a virtual display, by node id.
+    outputs: Mutex<HashMap<u32, String>>,
+}
+
+/// `Command` is a request to the PipeWire thread.
+enum Command {
+    /// Create a stream of the pictures in `frame`, answering its node id on `reply`.
+    Start {
+        serial: u64,
+
+        /// The name of the node, shown by PipeWire tools.
+        name: String,
+        frame: Arc<Mutex<Frame>>,
+
+        /// Whether buffers carry the pointer as metadata.
+        metadata: bool,
+        reply: mpsc::Sender<std::io::Result<u32>>,
+    },
+
+    /// Send the picture now in the frame of a stream.
+    Frame(u64),
+
+    /// Destroy a stream.
+    Stop(u64),
+}
+
+/// `Frame` is the latest picture of a stream.
+struct Frame {
+    image: Image,
+
+    /// The pointer, relative to the picture, if it's sent as metadata and over the stream.
+    cursor: Option<Cursor>,
+}
+
+impl PipeWire {
+    /// Start the PipeWire thread, streaming what `capture` takes.
+    pub fn new(capture: Arc<dyn Capture>) -> Self {
+        let (commands, receiver) = pw::channel::channel();
+
+        std::thread::Builder::new()
+            .name(String::from("pipewire"))
+            .spawn(move || {
+                if let Err(e) = serve(receiver) {
+                    log::error!("failed to connect to PipeWire: {}", e);
+                }
+            })
+            .expect("failed to spawn the pipewire thread");
+
+        Self {
+            capture,
+            commands,
+            serial: AtomicU64::new(0),
+            running: Mutex::new(HashMap::new()),
+            outputs: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl Cast for PipeWire {
+    fn source_types(&self) -> u32 {
+        match headless::supported() {
+            true => MONITOR | WINDOW | VIRTUAL,
+            false => MONITOR | WINDOW,
+        }
+    }
+
+    fn cursor_modes(&self) -> u32 {
+        HIDDEN | EMBEDDED | METADATA
+    }
+
+    fn start(&self, selection: &Selection, sources: &[Source]) -> std::io::Result<Vec<Stream>> {
+        // Formats are fixed to the size of their source, so the screen is captured before
+        // the streams exist.
+        let screen = self.capture.capture(selection.cursor_mode == EMBEDDED)?;
+
+        let mut streams = Vec::new();
+
+        for source in sources {
+            let started = match source {
+                Source::Virtual => self.start_virtual(selection.cursor_mode),
+                source => self.start_source(&screen, selection.cursor_mode, source),
+            };
+
+            match started {
+                Ok(stream) => streams.push(stream),
+
+                Err(e) => {
+                    self.stop(&streams);
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(streams)
+    }
+
+    fn stop(&self, streams: &[Stream]) {
+        for stream in streams {
+            let running = match self.running.lock() {
+                Ok(mut running) => running.remove(&stream.node_id),
+                Err(_) => None,
+            };
+
+            let Some((serial, running)) = running else {
+                continue;
+            };
+
+            running.store(false, Ordering::Relaxed);
+
+            let _ = self.commands.send(Command::Stop(serial));
+
+            log::info!("stopped streaming on node {}", stream.node_id);
+
+            let output = match self.outputs.lock() {
+                Ok(mut outputs) => outputs.remove(&stream.node_id),
+                Err(_) => None,
+            };
+
+            if let Some(output) = output {
+                headless::remove(&output);
+            }
+        }
+    }
+}
+
+impl PipeWire {
+    /// Start a stream of a single source, cut from pictures like `screen`, showing the pointer
+    /// as `cursor_mode` says.
+    fn start_source(
+        &self,
+        screen: &Image,
+        cursor_mode: u32,
+        source: &Source,
+    ) -> std::io::Result<Stream> {
+        let (name, position, size) = match source {
+            Source::Monitor(output) => (output.name.clone(), Some(output.position), output.size),
+
+            Source::Window(toplevel) => {
+                let size = (toplevel.area.width as i32, toplevel.area.height as i32);
+
+                (toplevel.title.clone(), None, size)
+            }
+
+            Source::Region(area) => {
+                let area = screen.bounds().clip(area);
+
+                (
+                    String::from("region"),
+                    None,
+                    (area.width as i32, area.height as i32),
+                )
+            }
+
+            Source::Virtual => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "virtual displays are streamed as the output made for them",
+                ))
+            }
+        };
+
+        let first = screen.crop(&source.area());
+
+        let serial = self.serial.fetch_add(1, Ordering::Relaxed);
+
+        let frame = Arc::new(Mutex::new(Frame {
+            image: first,
+            cursor: None,
+        }));
+
+        let (reply, answer) = mpsc::channel();
+
+        let command = Command::Start {
+            serial,
+            name: name.clone(),
+            frame: frame.clone(),
+            metadata: cursor_mode == METADATA,
+            reply,
+        };
+
+        if self.commands.send(command).is_err() {
+            return Err(std::io::Error::other("the PipeWire thread is gone"));
+        }
+
+        let node_id = answer
+            .recv_timeout(CONNECT_TIMEOUT)
+            .map_err(|_| std::io::Error::other("PipeWire didn't create the stream"))??;
+
+        let running = Arc::new(AtomicBool::new(true));
+
+        if let Ok(mut streams) = self.running.lock() {
+            streams.insert(node_id, (serial, running.clone()));
+        }
+
+        let capture = self.capture.clone();
+
+        let commands = self.commands.clone();
+
+        let produced = source.clone();
+
+        std::thread::Builder::new()
+            .name(format!("pipewire-{}", node_id))
+            .spawn(move || {
+                produce(
+                    &*capture,
+                    cursor_mode,
+                    &produced,
+                    &frame,
+                    &running,
+                    serial,
+                    &commands,
+                )
+            })?;
+
+        log::info!("streaming {} on node {}", name, node_id);
+
+        Ok(Stream {
+            node_id,
+            source_type: source.source_type(),
+            position,
+            size,
+        })
+    }
+
+    /// Create a virtual output and start a stream of it, which removes it once it's stopped.
+    fn start_virtual(&self, cursor_mode: u32) -> std::io::Result<Stream> {
+        let name = headless::create()?;
+
+        let started = self.wait_for(&name).and_then(|(screen, output)| {
+            self.start_source(&screen, cursor_mode, &Source::Monitor(output))
+        });
+
+        let stream = match started {
+            Ok(stream) => stream,
+
+            Err(e) => {
+                headless::remove(&name);
+                return Err(e);
+            }
+        };
+
+        log::info!("created the virtual output {}", name);
+
+        if let Ok(mut outputs) = self.outputs.lock() {
+            outputs.insert(stream.node_id, name);
+        }
+
+        Ok(Stream {
+            source_type: VIRTUAL,
+            ..stream
+        })
+    }
+
+    /// Capture the screen until the output called `name` shows up in it.
+    fn wait_for(&self, name: &str) -> std::io::Result<(Image, Output)> {
+        let started = Instant::now();
+
+        loop {
+            let screen = self.capture.capture(false)?;
+
+            let output = self
+                .capture
+                .outputs(&screen)
+                .into_iter()
+                .find(|output| output.name == name);
+
+            if let Some(output) = output {
+                return Ok((screen, output));
+            }
+
+            if started.elapsed() >= OUTPUT_TIMEOUT {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!("the virtual output {} didn't show up", name),
+                ));
+            }
+
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    }
+}
+
+/// Capture the area of `source` into `frame` until `running` is cleared, telling the PipeWire
+/// thread about each.
+fn produce(
+    capture: &dyn Capture,
+    cursor_mode: u32,
+    source: &Source,
+    frame: &Mutex<Frame>,
+    running: &AtomicBool,
+    serial: u64,
+    commands: &pw::channel::Sender<Command>,
+) {
+    let interval = Duration::from_secs(1) / FRAME_RATE;
+
+    let mut area = source.area();
+    let mut followed = Instant::now();
+
+    while running.load(Ordering::Relaxed) {
+        let started = Instant::now();
+
+        let cursor = match cursor_mode {
+            METADATA => capture.cursor(),
+            _ => None,
+        };
+
+        // Backends that can't hand out the pointer on its own draw it into the frames instead.
+        let embedded = cursor_mode == EMBEDDED || (cursor_mode == METADATA && cursor.is_none());
+
+        match capture.capture(embedded) {
+            Ok(screen) => {
+                if let Source::Window(toplevel) = source {
+                    if followed.elapsed() >= FOLLOW_INTERVAL {
+                        followed = Instant::now();
+                        area = follow(capture, &screen, toplevel, area);
+                    }
+                }
+
+                let image = screen.crop(&area);
+
+                let cursor = cursor.and_then(|cursor| over(cursor, &area));
+
+                if let Ok(mut frame) = frame.lock() {
+                    // The negotiated size can't change, so a new screen layout needs a new stream.
+                    if (image.width, image.height) == (frame.image.width, frame.image.height) {
+                        *frame = Frame { image, cursor };
+                    }
+                }
+
+                if commands.send(Command::Frame(serial)).is_err() {
+                    return;
+                }
+            }
+
+            Err(e) => log::warn!("failed to capture a frame: {}", e),
+        }
+
+        std::thread::sleep(interval.saturating_sub(started.elapsed()));
+    }
+}
+
+/// Find where the window `toplevel` is now, keeping the size of `area` the stream negotiated.
+///
+/// Windows that can't be found anymore keep streaming where they were last seen.
+fn follow(capture: &dyn Capture, screen: &Image, toplevel: &Toplevel, area: Rect) -> Rect {
+    let moved = capture
+        .toplevels(screen)
+        .into_iter()
+        .find(|candidate| candidate.id == toplevel.id);
+
+    let Some(moved) = moved else {
+        return area;
+    };
+
+    // Keep the whole area on the screen, so frames don't shrink at its edges.
+    Rect {
+        x: moved.area.x.min(screen.width.saturating_sub(area.width)),
+        y: moved.area.y.min(screen.height.saturating_sub(area.height)),
+        ..area
+    }
+}
+
+/// Move the pointer into the coordinates of a stream of `area`, if it points into it.
+///
+/// Pictures too big for the cursor metadata are cut.
+fn over(cursor: Cursor, area: &Rect) -> Option<Cursor> {
+    let (x, y) = cursor.position;
+
+    if x < 0 || y < 0 || !area.contains(x as u32, y as u32) {
+        return None;
+    }
+
+    let image = cursor.image.crop(&Rect {
+        x: 0,
+        y: 0,
+        width: CURSOR_SIZE,
+        height: CURSOR_SIZE,
+    });
+
+    Some(Cursor {
+        position: (x - area.x as i32, y - area.y as i32),
+        hotspot: cursor.hotspot,
+        image,
+    })
+}
+
+/// `Video` is a stream on the PipeWire thread.
+///
+/// The listener is declared first so it's removed before the stream is destroyed.
+struct Video {
+    _listener: pw::stream::StreamListener<Producer>,
+    stream: pw::stream::Stream,
+}
+
+/// `Producer` is the state of a stream's callbacks.
+struct Producer {
+    frame: Arc<Mutex<Frame>>,
+
+    /// Where to answer the node id, until it was.
+    reply: Option<mpsc::Sender<std::io::Result<u32>>>,
+
+    /// Whether the consumer took the DMA-BUF format, so buffers are allocated as DMA-BUFs.
+    dmabuf: bool,
+}
+
+/// Run the PipeWire loop, serving commands until the process exits.
+fn serve(commands: pw::channel::Receiver<Command>) -> Result<(), pw::Error> {
+    pw::init();
+
+    let mainloop = pw::main_loop::MainLoop::new(None)?;
+    let context = pw::context::Context::new(&mainloop)?;
+    let core = context.connect(None)?;
+
+    let videos = RefCell::new(HashMap::<u64, Video>::new());
+
+    let dmabuf = memory::dmabuf_supported();
+
+    if !dmabuf {
+        log::info!("can't open /dev/udmabuf, streaming in shared memory only");
+    }
+
+    let _receiver = commands.attach(mainloop.loop_(), move |command| match command {
+        Command::Start {
+            serial,
+            name,
+            frame,
+            metadata,
+            reply,
+        } => match Video::new(&core, &name, frame, metadata, dmabuf, reply.clone()) {
+            Ok(video) => {
+                videos.borrow_mut().insert(serial, video);
+            }
+
+            Err(e) => {
+                let _ = reply.send(Err(std::io::Error::other(e)));
+            }
+        },
+
+        Command::Frame(serial) => {
+            if let Some(video) = videos.borrow().get(&serial) {
+                if let Err(e) = video.stream.trigger_process() {
+                    log::debug!("failed to send a frame: {}", e);
+                }
+            }
+        }
+
+        Command::Stop(serial) => {
+            // Dropping the stream destroys its node, which disconnects consumers.
+            videos.borrow_mut().remove(&serial);
+        }
+    });
+
+    mainloop.run();
+
+    Ok(())
+}
+
+impl Video {
+    /// Create a stream of the pictures in `frame`, answering its node id on `reply` once it's ready.
+    ///
+    /// If `metadata` is set, buffers carry the pointer as metadata. If `dmabuf` is set,
+    /// consumers that can import DMA-BUFs get those, and shared memory otherwise.
+    fn new(
+        core: &pw::core::Core,
+        name: &str,
+        frame: Arc<Mutex<Frame>>,
+        metadata: bool,
+        dmabuf: bool,
+        reply: mpsc::Sender<std::io::Result<u32>>,
+    ) -> Result<Self, pw::Error> {
+        let (width, height) = frame
+            .lock()
+            .map(|frame| (frame.image.width, frame.image.height))
+            .unwrap_or_default();
+
+        let stream = pw::stream::Stream::new(
+            core,
+            "xdg-desktop-portal-rs",
+            pw::properties::properties! {
+                *pw::keys::MEDIA_CLASS => "Video/Source",
+                *pw::keys::NODE_DESCRIPTION => name,
+            },
+        )?;
+
+        let producer = Producer {
+            frame,
+            reply: Some(reply),
+            dmabuf: false,
+        };
+
+        let listener = stream
+            .add_local_listener_with_user_data(producer)
+            .state_changed(|stream, producer, _, state| {
+                let answer = match state {
+                    // A stream is paused once it's ready and no consumer is connected.
+                    pw::stream::StreamState::Paused => Ok(stream.node_id()),
+                    pw::stream::StreamState::Error(e) => Err(std::io::Error::other(e)),
+                    _ => return,
+                };
+
+                if let Some(reply) = producer.reply.take() {
+                    let _ = reply.send(answer);
+                }
+            })
+            .param_changed(move |stream, producer, id, param| {
+                let Some(param) = param.filter(|_| id == ParamType::Format.as_raw()) else {
+                    return;
+                };
+
+                let mut info = VideoInfoRaw::new();
+
+                if let Err(e) = info.parse(param) {
+                    log::warn!("failed to parse the format of a stream: {}", e);
+                    return;
+                }
+
+                // Only the DMA-BUF format has a modifier.
+                producer.dmabuf = info.flags().contains(VideoFlags::MODIFIER);
+
+                let mut params = vec![serialize(buffers_param(width, height, producer.dmabuf))];
+
+                if metadata {
+                    params.push(serialize(cursor_param()));
+                }
+
+                let mut params: Vec<&Pod> = params
+                    .iter()
+                    .filter_map(|param| Pod::from_bytes(param))
+                    .collect();
+
+                if let Err(e) = stream.update_params(&mut params) {
+                    log::warn!("failed to set the buffers of a stream: {}", e);
+                }
+            })
+            .add_buffer(move |_, producer, buffer| {
+                // Safety: the buffer is being added, so nothing else uses it yet.
+                if let Err(e) = unsafe { allocate(buffer, width, height, producer.dmabuf) } {
+                    log::warn!("failed to allocate a buffer of a stream: {}", e);
+                }
+            })
+            .remove_buffer(|_, _, buffer| {
+                // Safety: the buffer is being removed, so nothing uses it anymore.
+                unsafe { release(buffer) };
+            })
+            .process(|stream, producer| {
+                // The safe buffer type can't reach metadata, so buffers are handled raw.
+                let buffer = unsafe { stream.dequeue_raw_buffer() };
+
+                if buffer.is_null() {
+                    return;
+                }
+
+                if let Ok(frame) = producer.frame.lock() {
+                    // Safety: the buffer was just dequeued, so it's ours until it's queued.
+                    unsafe { fill((*buffer).buffer, &frame) };
+                }
+
+                unsafe { stream.queue_raw_buffer(buffer) };
+            })
+            .register()?;
+
+        // The DMA-BUF format comes first, so consumers pick it if they can.
+        let mut formats = Vec::new();
+
+        if dmabuf {
+            formats.push(serialize(format_param(width, height, true)));
+        }
+
+        formats.push(serialize(format_param(width, height, false)));
+
+        let mut formats = formats
+            .iter()
+            .map(|format| Pod::from_bytes(format).ok_or(pw::Error::CreationFailed))
+            .collect::<Result<Vec<&Pod>, _>>()?;
+
+        // The stream drives itself, sending a buffer whenever a frame was captured, and
+        // allocates its buffers, so they can be DMA-BUFs.
+        stream.connect(
+            spa::utils::Direction::Output,
+            None,
+            pw::stream::StreamFlags::DRIVER | pw::stream::StreamFlags::ALLOC_BUFFERS,
+            &mut formats,
+        )?;
+
+        Ok(Self {
+            _listener: listener,
+            stream,
+        })
+    }
+}
+
+/// Allocate the memory of a buffer of a `width` by `height` picture, as a DMA-BUF if `dmabuf`
+/// is set and as a memfd otherwise, keeping it in the buffer's user data.
+///
+/// # Safety
+///
+/// `buffer` must be a `pw_buffer` being added to a stream, whose memory isn't allocated yet.
+unsafe fn allocate(
+    buffer: *mut pw::sys::pw_buffer,
+    width: u32,
+    height: u32,
+    dmabuf: bool,
+) -> std::io::Result<()> {
+    let Some(spa_buffer) = buffer.as_mut().and_then(|buffer| buffer.buffer.as_mut()) else {
+        return Ok(());
+    };
+
+    if spa_buffer.n_datas == 0 || spa_buffer.datas.is_null() {
+        return Ok(());
+    }
+
+    let size = width as usize * height as usize * 4;
+
+    let memory = Memory::new(size, dmabuf)?;
+
+    let data = &mut *spa_buffer.datas;
+
+    data.type_ = match dmabuf {
+        true => spa::sys::SPA_DATA_DmaBuf,
+        false => spa::sys::SPA_DATA_MemFd,
+    };
+    data.flags = spa::sys::SPA_DATA_FLAG_READABLE;
+    data.fd = memory.fd().into();
+    data.mapoffset = 0;
+    data.maxsize = size as u32;
+    data.data = memory.as_mut_ptr().cast();
+
+    (*buffer).user_data = Box::into_raw(Box::new(memory)).cast();
+
+    Ok(())
+}
+
+/// Free the memory `allocate` gave a buffer.
+///
+/// # Safety
+///
+/// `buffer` must be a `pw_buffer` being removed from a stream.
+unsafe fn release(buffer: *mut pw::sys::pw_buffer) {
+    let Some(buffer) = buffer.as_mut() else {
+        return;
+    };
+
+    if buffer.user_data.is_null() {
+        return;
+    }
+
+    if let Some(spa_buffer) = buffer.buffer.as_mut() {
+        if spa_buffer.n_datas > 0 && !spa_buffer.datas.is_null() {
+            (*spa_buffer.datas).data = std::ptr::null_mut();
+        }
+    }
+
+    drop(Box::from_raw(buffer.user_data.cast::<Memory>()));
+
+    buffer.user_data = std::ptr::null_mut();
+}
+
+/// Copy `frame` into a buffer, with the pointer in its cursor metadata if it has any.
+///
+/// # Safety
+///
+/// `buffer` must be null or the buffer of a dequeued `pw_buffer`.
+unsafe fn fill(buffer: *mut spa::sys::spa_buffer, frame: &Frame) {
+    let Some(buffer) = buffer.as_mut() else {
+        return;
+    };
+
+    if buffer.n_datas > 0 && !buffer.datas.is_null() {
+        let data = &mut *buffer.datas.cast::<spa::buffer::Data>();
+
+        let size = match data.data() {
+            Some(target) => {
+                let size = target.len().min(frame.image.pixels.len());
+                target[..size].copy_from_slice(&frame.image.pixels[..size]);
+                size
+            }
+
+            None => 0,
+        };
+
+        let chunk = data.chunk_mut();
+
+        *chunk.offset_mut() = 0;
+        *chunk.stride_mut() = frame.image.width as i32 * 4;
+        *chunk.size_mut() = size as u32;
+    }
+
+    if buffer.n_metas == 0 || buffer.metas.is_null() {
+        return;
+    }
+
+    let metas = std::slice::from_raw_parts_mut(buffer.metas, buffer.n_metas as usize);
+
+    for meta in metas {
+        if meta.type_ == spa::sys::SPA_META_Cursor {
+            write_cursor(meta, frame.cursor.as_ref());
+        }
+    }
+}
+
+/// Describe the pointer in cursor metadata, laid out as a `spa_meta_cursor` followed by a
+/// `spa_meta_bitmap` and its pixels.
+///
+/// # Safety
+///
+/// `meta` must be the metadata of a dequeued buffer, with `size` bytes at `data`.
+unsafe fn write_cursor(meta: &mut spa::sys::spa_meta, cursor: Option<&Cursor>) {
+    let header = std::mem::size_of::<spa::sys::spa_meta_cursor>();
+    let bitmap_header = std::mem::size_of::<spa::sys::spa_meta_bitmap>();
+
+    if meta.data.is_null() || (meta.size as usize) < header {
+        return;
+    }
+
+    let target = &mut *meta.data.cast::<spa::sys::spa_meta_cursor>();
+
+    // An id of 0 tells consumers there's no pointer over the stream.
+    let Some(cursor) = cursor else {
+        target.id = 0;
+        return;
+    };
+
+    target.id = 1;
+    target.flags = 0;
+    target.position = spa::sys::spa_point {
+        x: cursor.position.0,
+        y: cursor.position.1,
+    };
+    target.hotspot = spa::sys::spa_point {
+        x: cursor.hotspot.0,
+        y: cursor.hotspot.1,
+    };
+    target.bitmap_offset = 0;
+
+    let pixels = &cursor.image.pixels;
+
+    // Without room for the picture, consumers keep drawing the last one at the new position.
+    if (meta.size as usize) < header + bitmap_header + pixels.len() {
+        return;
+    }
+
+    target.bitmap_offset = header as u32;
+
+    let start = meta.data.cast::<u8>().add(header);
+
+    let bitmap = &mut *start.cast::<spa::sys::spa_meta_bitmap>();
+
+    bitmap.format = spa::sys::SPA_VIDEO_FORMAT_RGBA;
+    bitmap.size = spa::sys::spa_rectangle {
+        width: cursor.image.width,
+        height: cursor.image.height,
+    };
+    bitmap.stride = cursor.image.width as i32 * 4;
+    bitmap.offset = bitmap_header as u32;
+
+    std::ptr::copy_nonoverlapping(pixels.as_ptr(), start.add(bitmap_header), pixels.len());
+}
+
+/// Describe the formats a stream of a `width` by `height` picture offers, in linear DMA-BUFs
+/// if `dmabuf` is set and in shared memory otherwise.
+///
+/// Captures are RGBA, which consumers ignoring alpha can take as RGBx.
+fn format_param(width: u32, height: u32, dmabuf: bool) -> Object {
+    let mut format = Object {
+        type_: SpaTypes::ObjectParamFormat.as_raw(),
+        id: ParamType::EnumFormat.as_raw(),
+        properties: vec![
+            Property::new(
+                FormatProperties::MediaType.as_raw(),
+                Value::Id(Id(MediaType::Video.as_raw())),
+            ),
+            Property::new(
+                FormatProperties::MediaSubtype.as_raw(),
+                Value::Id(Id(MediaSubtype::Raw.as_raw())),
+            ),
+            Property::new(
+                FormatProperties::VideoFormat.as_raw(),
+                Value::Choice(ChoiceValue::Id(Choice(
+                    ChoiceFlags::empty(),
+                    ChoiceEnum::Enum {
+                        default: Id(VideoFormat::RGBA.as_raw()),
+                        alternatives: vec![
+                            Id(VideoFormat::RGBA.as_raw()),
+                            Id(VideoFormat::RGBx.as_raw()),
+                        ],
+                    },
+                ))),
+            ),
+            Property::new(
+                FormatProperties::VideoSize.as_raw(),
+                Value::Rectangle(Rectangle { width, height }),
+            ),
+            // Frames come as the screen is captured, so the rate is variable up to a maximum.
+            Property::new(
+                FormatProperties::VideoFramerate.as_raw(),
+                Value::Fraction(Fraction { num: 0, denom: 1 }),
+            ),
+            Property::new(
+                FormatProperties::VideoMaxFramerate.as_raw(),
+                Value::Choice(ChoiceValue::Fraction(Choice(
+                    ChoiceFlags::empty(),
+                    ChoiceEnum::Range {
+                        default: Fraction {
+                            num: FRAME_RATE,
+                            denom: 1,
+                        },
+                        min: Fraction { num: 1, denom: 1 },
+                        max: Fraction {
+                            num: FRAME_RATE,
+                            denom: 1,
+                        },
+                    },
+                ))),
+            ),
+        ],
+    };
+
+    // Consumers that can't import DMA-BUFs don't know the modifier, so they skip the format.
+    if dmabuf {
+        format.properties.push(Property {
+            key: FormatProperties::VideoModifier.as_raw(),
+            flags: PropertyFlags::MANDATORY,
+            value: Value::Long(DRM_FORMAT_MOD_LINEAR),
+        });
+    }
+
+    format
+}
+
+/// Describe the buffers a stream of a `width` by `height` picture needs, which are DMA-BUFs
+/// if `dmabuf` is set and memfds otherwise.
+fn buffers_param(width: u32, height: u32, dmabuf: bool) -> Object {
+    let stride = width as i32 * 4;
+
+    let data_type = match dmabuf {
+        true => spa::sys::SPA_DATA_DmaBuf,
+        false => spa::sys::SPA_DATA_MemFd,
+    };
+
+    Object {
+        type_: SpaTypes::ObjectParamBuffers.as_raw(),
+        id: ParamType::Buffers.as_raw(),
+        properties: vec![
+            Property::new(
+                spa::sys::SPA_PARAM_BUFFERS_buffers,
+                Value::Choice(ChoiceValue::Int(Choice(
+                    ChoiceFlags::empty(),
+                    ChoiceEnum::Range {
+                        default: 4,
+                        min: 2,
+                        max: 16,
+                    },
+                ))),
+            ),
+            Property::new(spa::sys::SPA_PARAM_BUFFERS_blocks, Value::Int(1)),
+            Property::new(
+                spa::sys::SPA_PARAM_BUFFERS_size,
+                Value::Int(stride * height as i32),
+            ),
+            Property::new(spa::sys::SPA_PARAM_BUFFERS_stride, Value::Int(stride)),
+            Property::new(
+                spa::sys::SPA_PARAM_BUFFERS_dataType,
+                Value::Int(1 << data_type),
+            ),
+        ],
+    }
+}
+
+/// Describe the cursor metadata of buffers sending the pointer apart from the pictures.
+fn cursor_param() -> Object {
+    Object {
+        type_: SpaTypes::ObjectParamMeta.as_raw(),
+        id: ParamType::Meta.as_raw(),
+        properties: vec![
+            Property::new(
+                spa::sys::SPA_PARAM_META_type,
+                Value::Id(Id(spa::sys::SPA_META_Cursor)),
+            ),
+            Property::new(
+                spa::sys::SPA_PARAM_META_size,
+                Value::Int(CURSOR_META_SIZE as i32),
+            ),
+        ],
+    }
+}
+
+/// Serialize a param object into a pod.
+fn serialize(object: Object) -> Vec<u8> {
+    spa::pod::serialize::PodSerializer::serialize(
+        std::io::Cursor::new(Vec::new()),
+        &Value::Object(object),
+    )
+    .map(|(cursor, _)| cursor.into_inner())
+    .unwrap_or_default()
+}
@@ -0,0 +1,142 @@
+use zbus::zvariant;
+
+use crate::dialog::{DialogProvider, FileRequest, Message};
+
+/// `Choice` is a serialized combo box or checkbox, with the dbus signature `(ssa(ss)s)`.
+///
+/// The fields are the choice id, its label, the `(id, label)` options and the initial selection.
+/// A choice without options is a checkbox whose selection is either `"true"` or `"false"`.
+pub type Choice = (String, String, Vec<(String, String)>, String);
+
+/// Parse the `choices` option, which has the dbus signature `a(ssa(ss)s)`.
+pub fn parse(value: Option<&zvariant::Value<'_>>) -> Vec<Choice> {
+    value
+        .and_then(|value| Vec::<Choice>::try_from(value.clone()).ok())
+        .unwrap_or_default()
+}
+
+/// Ask the user for a value for each choice and return the `(id, selection)` pairs.
+///
+/// This is the fallback for providers that can't add widgets to their file dialogs:
+/// each choice is presented in turn as a confirmation once the file dialog has closed.
+pub fn prompt(dialogs: &dyn DialogProvider, request: &FileRequest) -> Vec<(String, String)> {
+    let message = Message {
+        title: request.title.clone(),
+        parent: request.parent.clone(),
+        accept_label: request.accept_label.clone(),
+        ..Message::default()
+    };
+
+    ask(dialogs, &message, &request.choices)
+}
+
+/// Ask the user for a value for each of `choices` in turn, with the title and parent of
+/// `message`, and return the `(id, selection)` pairs.
+///
+/// The `accept_label` of `message` labels the button used to pick an option of a combo box.
+pub fn ask(
+    dialogs: &(impl DialogProvider + ?Sized),
+    message: &Message,
+    choices: &[Choice],
+) -> Vec<(String, String)> {
+    choices
+        .iter()
+        .map(|(id, label, options, initial)| {
+            let selection = match options.is_empty() {
+                true => prompt_checkbox(dialogs, message, label),
+                false => prompt_combo(dialogs, message, label, options, initial),
+            };
+
+            (id.clone(), selection)
+        })
+        .collect()
+}
+
+/// Present a checkbox as a yes/no question.
+fn prompt_checkbox(
+    dialogs: &(impl DialogProvider + ?Sized),
+    message: &Message,
+    label: &str,
+) -> String {
+    log::debug!("prompt_checkbox({})", label);
+
+    let checked = dialogs.confirm(&Message {
+        title: message.title.clone(),
+        description: strip_mnemonic(label),
+        parent: message.parent.clone(),
+        accept_label: Some(String::from("Yes")),
+        reject_label: Some(String::from("No")),
+        ..Message::default()
+    });
+
+    checked.to_string()
+}
+
+/// Present a combo box by offering each option in turn, starting at the initial selection.
+///
+/// The message's `accept_label` labels the button used to pick an option.
+/// If the user declines every option, the initial selection is kept.
+fn prompt_combo(
+    dialogs: &(impl DialogProvider + ?Sized),
+    message: &Message,
+    label: &str,
+    options: &[(String, String)],
+    initial: &str,
+) -> String {
+    log::debug!("prompt_combo({}, {:?}, {})", label, options, initial);
+
+    let start = options
+        .iter()
+        .position(|(id, _)| id == initial)
+        .unwrap_or_default();
+
+    let ordered = options[start..].iter().chain(&options[..start]);
+
+    for (id, option_label) in ordered {
+        let selected = dialogs.confirm(&Message {
+            title: message.title.clone(),
+            description: format!(
+                "{}: {}",
+                strip_mnemonic(label),
+                strip_mnemonic(option_label)
+            ),
+            parent: message.parent.clone(),
+            accept_label: Some(
+                message
+                    .accept_label
+                    .clone()
+                    .unwrap_or_else(|| String::from("Select")),
+            ),
+            reject_label: Some(String::from("Next")),
+            ..Message::default()
+        });
+
+        if selected {
+            return id.clone();
+        }
+    }
+
+    match initial.is_empty() {
+        true => options[start].0.clone(),
+        false => initial.to_string(),
+    }
+}
+
+/// Remove GTK-style mnemonic underscores from a label, keeping escaped `__` as `_`.
+pub fn strip_mnemonic(label: &str) -> String {
+    let mut stripped = String::with_capacity(label.len());
+    let mut chars = label.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '_' {
+            if chars.peek() == Some(&'_') {
+                stripped.push('_');
+                chars.next();
+            }
+        } else {
+            stripped.push(c);
+        }
+    }
+
+    stripped
+}
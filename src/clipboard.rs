@@ -0,0 +1,43 @@
+use std::{os::fd::OwnedFd, sync::Arc};
+
+use tokio::sync::mpsc::UnboundedSender;
+
+mod datacontrol;
+
+/// `Clipboard` shares the compositor's clipboard with remote desktop sessions.
+pub trait Clipboard: Send + Sync {
+    /// Share the clipboard with a session, telling `events` what happens to it until the
+    /// returned `Selection` is dropped.
+    ///
+    /// Connecting blocks until the compositor sent the clipboard, so callers run it off the
+    /// async executor.
+    fn connect(&self, events: UnboundedSender<Event>) -> std::io::Result<Box<dyn Selection>>;
+}
+
+/// `Event` is something that happened to the clipboard of a session.
+#[derive(Debug)]
+pub enum Event {
+    /// The clipboard changed to one offering `mime_types`, which is the session's if `own`
+    /// is set.
+    OwnerChanged { mime_types: Vec<String>, own: bool },
+
+    /// Something pastes the session's clipboard as `mime_type`, which the session writes to
+    /// `fd`.
+    Transfer { mime_type: String, fd: OwnedFd },
+}
+
+/// `Selection` is a session's end of the clipboard, which stops sharing it once dropped.
+///
+/// Requests are queued and sent in order, so making them doesn't block.
+pub trait Selection: Send {
+    /// Take the clipboard over, offering `mime_types` the session writes once they're pasted.
+    fn set(&self, mime_types: Vec<String>) -> std::io::Result<()>;
+
+    /// Have the owner of the clipboard write it as `mime_type` to `fd`.
+    fn read(&self, mime_type: &str, fd: OwnedFd) -> std::io::Result<()>;
+}
+
+/// Create the clipboard backend.
+pub fn new() -> Arc<dyn Clipboard> {
+    Arc::new(datacontrol::DataControl)
+}
@@ -0,0 +1,366 @@
+use std::{
+    os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd},
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender},
+    time::Duration,
+};
+
+use tokio::sync::mpsc::UnboundedSender;
+use wayland_client::{
+    delegate_noop, event_created_child,
+    globals::{registry_queue_init, GlobalListContents},
+    protocol::{wl_registry, wl_seat},
+    Connection, Dispatch, EventQueue, QueueHandle,
+};
+use wayland_protocols_wlr::data_control::v1::client::{
+    zwlr_data_control_device_v1, zwlr_data_control_manager_v1, zwlr_data_control_offer_v1,
+    zwlr_data_control_source_v1,
+};
+
+use super::{Clipboard, Event, Selection};
+
+/// How often the connection is checked for the compositor's events while the session makes
+/// no requests.
+const INTERVAL: Duration = Duration::from_millis(20);
+
+/// `DataControl` shares the clipboard with the wlr-data-control protocol, which wlroots
+/// compositors and KWin support.
+///
+/// Each session has its own connection, served by a thread of its own.
+pub struct DataControl;
+
+impl Clipboard for DataControl {
+    fn connect(&self, events: UnboundedSender<Event>) -> std::io::Result<Box<dyn Selection>> {
+        let (requests, receiver) = mpsc::channel();
+
+        let (ready, connected) = mpsc::sync_channel(1);
+
+        std::thread::Builder::new()
+            .name(String::from("data-control"))
+            .spawn(move || run(events, receiver, ready))?;
+
+        connected
+            .recv()
+            .map_err(|_| std::io::Error::other("the data-control thread died"))??;
+
+        Ok(Box::new(Handle { requests }))
+    }
+}
+
+/// `Request` is a request of a session on its way to the thread making it.
+enum Request {
+    Set(Vec<String>),
+    Read(String, OwnedFd),
+}
+
+/// `Handle` is the end of the clipboard of a session, which hands requests to its thread.
+struct Handle {
+    requests: Sender<Request>,
+}
+
+impl Handle {
+    fn send(&self, request: Request) -> std::io::Result<()> {
+        self.requests.send(request).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                "the compositor stopped sharing the clipboard",
+            )
+        })
+    }
+}
+
+impl Selection for Handle {
+    fn set(&self, mime_types: Vec<String>) -> std::io::Result<()> {
+        self.send(Request::Set(mime_types))
+    }
+
+    fn read(&self, mime_type: &str, fd: OwnedFd) -> std::io::Result<()> {
+        self.send(Request::Read(mime_type.to_owned(), fd))
+    }
+}
+
+/// `Client` is the Wayland side of the clipboard of a session.
+struct Client {
+    queue: EventQueue<State>,
+    state: State,
+    manager: zwlr_data_control_manager_v1::ZwlrDataControlManagerV1,
+    device: zwlr_data_control_device_v1::ZwlrDataControlDeviceV1,
+}
+
+/// `State` collects the events of the clipboard.
+struct State {
+    events: UnboundedSender<Event>,
+
+    /// The offers the compositor announced with their mime types, until they're replaced.
+    offers: Vec<(
+        zwlr_data_control_offer_v1::ZwlrDataControlOfferV1,
+        Vec<String>,
+    )>,
+
+    /// The offer of the clipboard.
+    selection: Option<zwlr_data_control_offer_v1::ZwlrDataControlOfferV1>,
+
+    /// The source of the session's clipboard, while it owns the clipboard.
+    source: Option<zwlr_data_control_source_v1::ZwlrDataControlSourceV1>,
+
+    /// Whether the compositor stopped sharing the clipboard, like when the seat went away.
+    finished: bool,
+}
+
+/// Connect to the compositor, report whether that worked on `ready`, then make the `requests`
+/// of a session until its end of the clipboard is dropped or the compositor stops sharing it.
+fn run(
+    events: UnboundedSender<Event>,
+    requests: Receiver<Request>,
+    ready: SyncSender<std::io::Result<()>>,
+) {
+    let mut client = match Client::connect(events) {
+        Ok(client) => {
+            let _ = ready.send(Ok(()));
+            client
+        }
+
+        Err(e) => {
+            let _ = ready.send(Err(e));
+            return;
+        }
+    };
+
+    loop {
+        match requests.recv_timeout(INTERVAL) {
+            Ok(request) => client.request(request),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+
+        match client.dispatch() {
+            Ok(true) => {}
+            Ok(false) => return,
+
+            Err(e) => {
+                log::warn!("lost the data-control connection: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+impl Client {
+    /// Connect to the compositor and get the clipboard of its seat.
+    fn connect(events: UnboundedSender<Event>) -> std::io::Result<Self> {
+        let conn = Connection::connect_to_env().map_err(std::io::Error::other)?;
+
+        let (globals, mut queue) =
+            registry_queue_init::<State>(&conn).map_err(std::io::Error::other)?;
+
+        let qh = queue.handle();
+
+        let seat: wl_seat::WlSeat = globals
+            .bind(&qh, 1..=1, ())
+            .map_err(std::io::Error::other)?;
+
+        let manager: zwlr_data_control_manager_v1::ZwlrDataControlManagerV1 = globals
+            .bind(&qh, 1..=2, ())
+            .map_err(|e| std::io::Error::other(format!("no wlr-data-control support: {}", e)))?;
+
+        let device = manager.get_data_device(&seat, &qh, ());
+
+        let mut state = State {
+            events,
+            offers: Vec::new(),
+            selection: None,
+            source: None,
+            finished: false,
+        };
+
+        // The device sends the clipboard as it is, so the session starts out knowing it.
+        queue.roundtrip(&mut state).map_err(std::io::Error::other)?;
+
+        Ok(Self {
+            queue,
+            state,
+            manager,
+            device,
+        })
+    }
+
+    /// Make a request of the session.
+    fn request(&mut self, request: Request) {
+        match request {
+            Request::Set(mime_types) => {
+                let source = self.manager.create_data_source(&self.queue.handle(), ());
+
+                for mime_type in mime_types {
+                    source.offer(mime_type);
+                }
+
+                self.device.set_selection(Some(&source));
+
+                // A source the session had before is cancelled, and destroyed then.
+                self.state.source = Some(source);
+            }
+
+            // Without a clipboard, the pipe is closed before anything is written to it.
+            Request::Read(mime_type, fd) => {
+                if let Some(offer) = &self.state.selection {
+                    offer.receive(mime_type, fd.as_fd());
+                }
+            }
+        }
+    }
+
+    /// Handle what the compositor sent, returning whether it still shares the clipboard.
+    fn dispatch(&mut self) -> std::io::Result<bool> {
+        self.queue.flush().map_err(std::io::Error::other)?;
+
+        if let Some(guard) = self.queue.prepare_read() {
+            if readable(guard.connection_fd())? {
+                guard.read().map_err(std::io::Error::other)?;
+            }
+        }
+
+        self.queue
+            .dispatch_pending(&mut self.state)
+            .map_err(std::io::Error::other)?;
+
+        self.queue.flush().map_err(std::io::Error::other)?;
+
+        Ok(!self.state.finished)
+    }
+}
+
+impl State {
+    /// Destroy the offer `id`, once it's no longer the clipboard.
+    fn forget(&mut self, id: &zwlr_data_control_offer_v1::ZwlrDataControlOfferV1) {
+        self.offers.retain(|(offer, _)| offer != id);
+
+        id.destroy();
+    }
+}
+
+/// Check whether `fd` is readable, without waiting.
+fn readable(fd: BorrowedFd<'_>) -> std::io::Result<bool> {
+    let mut pollfd = libc::pollfd {
+        fd: fd.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+
+    match unsafe { libc::poll(&mut pollfd, 1, 0) } {
+        count if count >= 0 => Ok(count > 0),
+
+        _ => match std::io::Error::last_os_error() {
+            e if e.kind() == std::io::ErrorKind::Interrupted => Ok(false),
+            e => Err(e),
+        },
+    }
+}
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for State {
+    fn event(
+        _: &mut Self,
+        _: &wl_registry::WlRegistry,
+        _: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<zwlr_data_control_device_v1::ZwlrDataControlDeviceV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &zwlr_data_control_device_v1::ZwlrDataControlDeviceV1,
+        event: zwlr_data_control_device_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_data_control_device_v1::Event::DataOffer { id } => {
+                state.offers.push((id, Vec::new()));
+            }
+
+            zwlr_data_control_device_v1::Event::Selection { id } => {
+                if let Some(previous) = state.selection.take() {
+                    state.forget(&previous);
+                }
+
+                let mime_types = id
+                    .as_ref()
+                    .and_then(|id| state.offers.iter().find(|(offer, _)| offer == id))
+                    .map(|(_, mime_types)| mime_types.clone())
+                    .unwrap_or_default();
+
+                state.selection = id;
+
+                // Sources are cancelled before the clipboard changes to someone else's.
+                let _ = state.events.send(Event::OwnerChanged {
+                    mime_types,
+                    own: state.source.is_some(),
+                });
+            }
+
+            // Only the clipboard is shared, not the primary selection.
+            zwlr_data_control_device_v1::Event::PrimarySelection { id: Some(id) } => {
+                state.forget(&id);
+            }
+
+            zwlr_data_control_device_v1::Event::Finished => state.finished = true,
+
+            _ => {}
+        }
+    }
+
+    event_created_child!(State, zwlr_data_control_device_v1::ZwlrDataControlDeviceV1, [
+        zwlr_data_control_device_v1::EVT_DATA_OFFER_OPCODE => (zwlr_data_control_offer_v1::ZwlrDataControlOfferV1, ()),
+    ]);
+}
+
+impl Dispatch<zwlr_data_control_offer_v1::ZwlrDataControlOfferV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        offer: &zwlr_data_control_offer_v1::ZwlrDataControlOfferV1,
+        event: zwlr_data_control_offer_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let zwlr_data_control_offer_v1::Event::Offer { mime_type } = event {
+            if let Some((_, mime_types)) = state.offers.iter_mut().find(|(o, _)| o == offer) {
+                mime_types.push(mime_type);
+            }
+        }
+    }
+}
+
+impl Dispatch<zwlr_data_control_source_v1::ZwlrDataControlSourceV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        source: &zwlr_data_control_source_v1::ZwlrDataControlSourceV1,
+        event: zwlr_data_control_source_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            // The pipe is closed if the session is gone, ending the paste.
+            zwlr_data_control_source_v1::Event::Send { mime_type, fd } => {
+                let _ = state.events.send(Event::Transfer { mime_type, fd });
+            }
+
+            zwlr_data_control_source_v1::Event::Cancelled => {
+                if state.source.as_ref() == Some(source) {
+                    state.source = None;
+                }
+
+                source.destroy();
+            }
+
+            _ => {}
+        }
+    }
+}
+
+delegate_noop!(State: ignore wl_seat::WlSeat);
+delegate_noop!(State: zwlr_data_control_manager_v1::ZwlrDataControlManagerV1);
@@ -0,0 +1,596 @@
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+
+use serde::Deserialize;
+
+/// `Config` is read from `$XDG_CONFIG_HOME/xdg-desktop-portal-rs/config.toml` at startup.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub dialog: DialogConfig,
+    pub file_chooser: FileChooserConfig,
+    pub app_chooser: AppChooserConfig,
+    pub screenshot: ScreenshotConfig,
+    pub remote_desktop: RemoteDesktopConfig,
+    pub notification: NotificationConfig,
+    pub wallpaper: WallpaperConfig,
+    pub secret: SecretConfig,
+    pub global_shortcuts: GlobalShortcutsConfig,
+    pub input_capture: InputCaptureConfig,
+    pub lockdown: LockdownConfig,
+    pub policy: PolicyConfig,
+    pub audit: AuditConfig,
+
+    /// The settings apps read through the Settings portal, by namespace and key.
+    ///
+    /// Any namespace can be set, with strings, integers, floats, booleans and arrays of them,
+    /// which apps read as they're written here.
+    ///
+    /// ```toml
+    /// [settings."org.freedesktop.appearance"]
+    /// color-scheme = "prefer-dark"
+    /// accent-color = "#3584e4"
+    /// contrast = "high"
+    ///
+    /// [settings."org.gnome.desktop.interface"]
+    /// cursor-size = 24
+    ///
+    /// [settings."com.example.Editor"]
+    /// fonts = ["Iosevka", "monospace"]
+    /// ```
+    pub settings: HashMap<String, HashMap<String, toml::Value>>,
+
+    pub gsettings: GsettingsConfig,
+}
+
+/// `DialogConfig` is the `[dialog]` section of the config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct DialogConfig {
+    /// The provider used to show dialogs.
+    pub backend: DialogBackend,
+
+    /// The response file answering dialogs when `backend` is `scripted`.
+    pub script: Option<PathBuf>,
+
+    /// Seconds after which an unanswered dialog fails its request; unset waits forever.
+    pub timeout: Option<u64>,
+
+    /// What to do when an application asks for a dialog while one of its own is open.
+    pub concurrency: Concurrency,
+
+    /// Open the built-in file chooser in fuzzy finder mode; Ctrl+F toggles it either way.
+    pub fuzzy_finder: bool,
+
+    /// The external program used when `backend` is `command`.
+    pub command: CommandConfig,
+
+    /// The terminal device the `tui` backend draws on, `/dev/tty` by default.
+    pub tty: Option<PathBuf>,
+}
+
+/// `DialogBackend` selects a dialog provider.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DialogBackend {
+    /// Native dialogs through rfd.
+    #[default]
+    Rfd,
+
+    /// The built-in egui file chooser, if compiled with the `egui` feature.
+    Egui,
+
+    /// Answers from a response file, for automated testing.
+    Scripted,
+
+    /// An external program like zenity, kdialog or yad, configured in `[dialog.command]`.
+    Command,
+
+    /// A terminal file browser, if compiled with the `tui` feature.
+    Tui,
+
+    /// Qt dialogs of xdg-desktop-portal-kde, if compiled with the `kde` feature.
+    Kde,
+}
+
+/// `CommandConfig` is the `[dialog.command]` section of the config file.
+///
+/// Each template is a program followed by its arguments; set ones replace the preset's.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct CommandConfig {
+    /// The program whose built-in templates are used.
+    pub preset: CommandPreset,
+
+    pub open_file: Option<Vec<String>>,
+    pub open_files: Option<Vec<String>>,
+    pub save_file: Option<Vec<String>>,
+    pub pick_folder: Option<Vec<String>>,
+    pub confirm: Option<Vec<String>>,
+    pub message: Option<Vec<String>>,
+}
+
+/// `CommandPreset` selects the built-in templates of a dialog program.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CommandPreset {
+    #[default]
+    Zenity,
+    Kdialog,
+    Yad,
+}
+
+/// `Concurrency` selects how overlapping dialogs of one application are scheduled.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Concurrency {
+    /// Show the dialogs one after the other.
+    #[default]
+    Queue,
+
+    /// Fail the new request while a dialog is open.
+    RejectBusy,
+
+    /// Show the dialogs at the same time.
+    AllowParallel,
+}
+
+/// `FileChooserConfig` is the `[file_chooser]` section of the config file.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct FileChooserConfig {
+    /// Ask before returning a SaveFile target that already exists.
+    pub confirm_overwrite: bool,
+
+    /// Move a SaveFile target that already exists to the trash, so the overwrite can be undone.
+    pub trash_on_overwrite: bool,
+
+    /// Export chosen files through the Documents portal when the caller is a Flatpak.
+    pub export_documents: bool,
+
+    /// Return `sftp://`, `smb://` and similar URIs for files in GVFS mounts, instead of their
+    /// FUSE paths, to callers running on the host.
+    pub remote_uris: bool,
+
+    /// Which chosen paths to replace with their target when they go through symlinks.
+    pub resolve_symlinks: ResolveSymlinks,
+
+    /// The folder SaveFile and SaveFiles start in for each app id, when the app doesn't ask
+    /// for one. It takes precedence over the folder the app last saved in.
+    ///
+    /// ```toml
+    /// [file_chooser.save_directories]
+    /// "org.mozilla.firefox" = "~/Downloads"
+    /// "org.gnome.Screenshot" = "~/Pictures"
+    /// ```
+    pub save_directories: HashMap<String, PathBuf>,
+
+    /// Add files chosen with OpenFile and SaveFile to GTK's `recently-used.xbel`.
+    pub recent_files: bool,
+
+    /// Ask whether an app may open a file dialog before showing it.
+    /// "Always Allow" is remembered in the permission store.
+    pub access_prompt: bool,
+
+    /// Add the size, modification time and MIME type of each chosen file to the results,
+    /// under the `org.freedesktop.impl.portal.desktop.rs.metadata` key.
+    ///
+    /// The key isn't part of the portal spec, so it's off by default; it's only seen by callers
+    /// whose frontend passes unknown results on.
+    pub metadata: bool,
+}
+
+impl Default for FileChooserConfig {
+    fn default() -> Self {
+        Self {
+            confirm_overwrite: true,
+            trash_on_overwrite: false,
+            export_documents: true,
+            remote_uris: false,
+            resolve_symlinks: ResolveSymlinks::default(),
+            save_directories: HashMap::new(),
+            recent_files: true,
+            access_prompt: false,
+            metadata: false,
+        }
+    }
+}
+
+/// `ResolveSymlinks` selects which chosen paths have their symlinks resolved.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResolveSymlinks {
+    /// Resolve every path.
+    Always,
+
+    /// Keep paths as the user picked them.
+    #[default]
+    Never,
+
+    /// Resolve paths whose target is outside the home directory, keeping links within it.
+    OutsideHome,
+}
+
+/// `AppChooserConfig` is the `[app_chooser]` section of the config file.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct AppChooserConfig {
+    /// Offer an "Always use this application" checkbox, and skip the dialog for content types
+    /// it was ticked for.
+    pub always_use: bool,
+
+    /// Overrides of `always_use` for single calling applications, keyed by app id.
+    ///
+    /// ```toml
+    /// [app_chooser.apps]
+    /// "org.mozilla.firefox" = false
+    /// ```
+    pub apps: HashMap<String, bool>,
+
+    /// Offer "Other Application…", which lists every installed application and takes a
+    /// custom command, for when the right one isn't among the caller's choices.
+    pub other_applications: bool,
+}
+
+impl Default for AppChooserConfig {
+    fn default() -> Self {
+        Self {
+            always_use: true,
+            apps: HashMap::new(),
+            other_applications: true,
+        }
+    }
+}
+
+impl AppChooserConfig {
+    /// Check whether "Always use this application" applies to calls from `app_id`.
+    pub fn always_use(&self, app_id: &str) -> bool {
+        self.apps.get(app_id).copied().unwrap_or(self.always_use)
+    }
+}
+
+/// `ScreenshotConfig` is the `[screenshot]` section of the config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ScreenshotConfig {
+    /// How the screen is captured.
+    pub backend: CaptureBackend,
+
+    /// The folder screenshots are saved in, `$XDG_PICTURES_DIR` by default.
+    pub directory: Option<PathBuf>,
+
+    /// Seconds to count down before capturing, which interactive screenshots start from.
+    pub delay: u64,
+
+    /// Draw the pointer into screenshots, which interactive screenshots start from.
+    pub include_cursor: bool,
+}
+
+/// `CaptureBackend` selects how screenshots are taken.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CaptureBackend {
+    /// `screencopy` in Wayland sessions and `x11` otherwise.
+    #[default]
+    Auto,
+
+    /// The wlr-screencopy protocol, on wlroots compositors.
+    Screencopy,
+
+    /// Run `grim`, on wlroots compositors.
+    Grim,
+
+    /// The root window of an X11 session.
+    X11,
+}
+
+/// `RemoteDesktopConfig` is the `[remote_desktop]` section of the config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RemoteDesktopConfig {
+    /// How the input of remote desktop sessions reaches the compositor.
+    pub backend: InputBackend,
+}
+
+/// `InputBackend` selects how remote desktop input is injected.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InputBackend {
+    /// libei, on compositors exporting an EIS socket, if compiled with the `libei` feature.
+    #[default]
+    Libei,
+
+    /// Virtual devices made with /dev/uinput, which the user needs to be able to write,
+    /// for older compositors and X11.
+    ///
+    /// Everything reading input devices sees them, not only the compositor, so they're only
+    /// used if selected.
+    Uinput,
+}
+
+/// `WallpaperConfig` is the `[wallpaper]` section of the config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct WallpaperConfig {
+    /// How the wallpaper is set.
+    pub backend: WallpaperBackend,
+}
+
+/// `WallpaperBackend` selects the program that sets the wallpaper.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WallpaperBackend {
+    /// `hyprpaper` on Hyprland, `swww` if its daemon runs, `swaybg` in other Wayland
+    /// sessions and `feh` otherwise.
+    #[default]
+    Auto,
+
+    /// `swaybg`, through sway's own outputs if it's sway.
+    Swaybg,
+
+    /// The `hyprpaper` daemon, through `hyprctl`.
+    Hyprpaper,
+
+    /// The `swww` daemon.
+    Swww,
+
+    /// `feh`, on X11.
+    Feh,
+}
+
+/// `SecretConfig` is the `[secret]` section of the config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SecretConfig {
+    /// Where the master secrets of sandboxed apps are kept.
+    ///
+    /// Secrets kept elsewhere are moved there the next time their app asks for them.
+    pub backend: SecretBackend,
+}
+
+/// `SecretBackend` selects where the Secret portal keeps master secrets.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SecretBackend {
+    /// The Secret Service if one is installed, and the encrypted file otherwise.
+    #[default]
+    Auto,
+
+    /// The default collection of the Secret Service, like GNOME Keyring or KeePassXC.
+    SecretService,
+
+    /// A file encrypted with a password, which is asked for once per login.
+    ///
+    /// Only dialog providers with password fields can ask for it.
+    File,
+}
+
+/// `GlobalShortcutsConfig` is the `[global_shortcuts]` section of the config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct GlobalShortcutsConfig {
+    /// How the triggers of global shortcuts are grabbed.
+    pub backend: ShortcutsBackend,
+}
+
+/// `ShortcutsBackend` selects how the GlobalShortcuts portal grabs triggers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ShortcutsBackend {
+    /// The backend of the compositor the session runs, if there's one.
+    #[default]
+    Auto,
+
+    /// Hyprland's global shortcuts protocol, with the triggers bound through its IPC.
+    Hyprland,
+
+    /// Bindings made through sway's IPC.
+    Sway,
+
+    /// KDE's kglobalaccel daemon, which lets the shortcuts be changed in Plasma's settings.
+    Kde,
+
+    /// Keys grabbed from the X server.
+    X11,
+}
+
+/// `InputCaptureConfig` is the `[input_capture]` section of the config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct InputCaptureConfig {
+    /// How the pointer is found, to tell it crossing the barriers of InputCapture sessions.
+    pub backend: InputCaptureBackend,
+}
+
+/// `InputCaptureBackend` selects how the InputCapture portal finds the pointer.
+///
+/// Input is read from and grabbed on the devices in /dev/input either way, which takes
+/// being in the `input` group.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InputCaptureBackend {
+    /// The backend of the compositor the session runs, if there's one.
+    #[default]
+    Auto,
+
+    /// Hyprland's IPC.
+    Hyprland,
+
+    /// The X server.
+    X11,
+}
+
+/// `NotificationConfig` is the `[notification]` section of the config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct NotificationConfig {
+    /// How the notifications of apps are shown.
+    pub backend: NotificationBackend,
+
+    /// What happens to the notifications of single apps, keyed by app id.
+    ///
+    /// ```toml
+    /// [notification.apps]
+    /// "org.gnome.Software" = "downgrade"
+    /// "com.example.Spammy" = "mute"
+    /// ```
+    pub apps: HashMap<String, NotificationRule>,
+}
+
+impl NotificationConfig {
+    /// Get what happens to the notifications of `app_id`.
+    pub fn rule(&self, app_id: &str) -> NotificationRule {
+        self.apps.get(app_id).copied().unwrap_or_default()
+    }
+}
+
+/// `NotificationRule` selects what happens to the notifications of an app.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NotificationRule {
+    /// Show them as the app asks.
+    #[default]
+    Show,
+
+    /// Show them with low priority and without sound.
+    Downgrade,
+
+    /// Drop them.
+    Mute,
+}
+
+/// `NotificationBackend` selects how notifications are shown.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NotificationBackend {
+    /// The session's notification daemon, like mako, dunst or GNOME Shell, through
+    /// org.freedesktop.Notifications.
+    #[default]
+    Daemon,
+
+    /// Message dialogs of the dialog provider, for sessions without a notification daemon.
+    Popup,
+}
+
+/// `LockdownConfig` is the `[lockdown]` section of the config file.
+///
+/// It turns off what apps can do through portals, e.g. on kiosks or shared machines. The
+/// portals of this backend refuse what's turned off, and xdg-desktop-portal reads the rest.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct LockdownConfig {
+    pub disable_printing: bool,
+
+    /// Apps can't save files where the user picks.
+    pub disable_save_to_disk: bool,
+
+    /// Apps can't open files and URIs in other apps.
+    pub disable_application_handlers: bool,
+
+    pub disable_camera: bool,
+    pub disable_microphone: bool,
+    pub disable_location: bool,
+    pub disable_sound_output: bool,
+}
+
+/// `PolicyConfig` is the `[policy]` section of the config file.
+///
+/// It limits where applications may pick files, e.g. on kiosks or shared machines.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PolicyConfig {
+    /// If set, files can only be picked inside these directories.
+    pub allow: Option<Vec<PathBuf>>,
+
+    /// Files inside these directories can never be picked.
+    pub deny: Vec<PathBuf>,
+
+    /// Rules for single applications, keyed by app id, in `[policy.apps."<app id>"]` sections.
+    pub apps: HashMap<String, AppPolicy>,
+}
+
+/// `AppPolicy` limits where one application may pick files.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct AppPolicy {
+    /// If set, replaces the global `allow` list for this application.
+    pub allow: Option<Vec<PathBuf>>,
+
+    /// Added to the global `deny` list for this application.
+    pub deny: Vec<PathBuf>,
+}
+
+/// `AuditConfig` is the `[audit]` section of the config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct AuditConfig {
+    /// Where to record portal decisions; unset disables auditing.
+    pub target: Option<AuditTarget>,
+
+    /// The log file used by the `file` target, `$XDG_STATE_HOME/xdg-desktop-portal-rs/audit.log`
+    /// by default.
+    pub path: Option<PathBuf>,
+}
+
+/// `GsettingsConfig` is the `[gsettings]` section of the config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct GsettingsConfig {
+    /// The GSettings schemas served through the Settings portal, as namespaces of the same
+    /// name; settings of the config take precedence over theirs.
+    ///
+    /// ```toml
+    /// [gsettings]
+    /// schemas = ["org.gnome.desktop.interface", "org.gnome.desktop.wm.preferences"]
+    /// ```
+    pub schemas: Vec<String>,
+}
+
+/// `AuditTarget` selects where audit records go.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuditTarget {
+    /// A file with one JSON record per line.
+    File,
+
+    /// The systemd journal, with the record in structured fields.
+    Journald,
+}
+
+impl DialogConfig {
+    /// Get the time after which an unanswered dialog fails its request.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout.map(Duration::from_secs)
+    }
+}
+
+impl Config {
+    /// Load the config file, falling back to the defaults if it's missing or invalid.
+    pub fn load() -> Self {
+        let Some(path) = path() else {
+            return Self::default();
+        };
+
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents).unwrap_or_else(|e| {
+                log::error!("ignoring invalid config file {:?}: {}", path, e);
+                Self::default()
+            }),
+
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+
+            Err(e) => {
+                log::error!("failed to read config file {:?}: {}", path, e);
+                Self::default()
+            }
+        }
+    }
+}
+
+/// Get the path of the config file.
+pub fn path() -> Option<PathBuf> {
+    Some(
+        dirs::config_dir()?
+            .join("xdg-desktop-portal-rs")
+            .join("config.toml"),
+    )
+}
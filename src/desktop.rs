@@ -0,0 +1,549 @@
+use std::{
+    collections::HashMap,
+    ffi::CString,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    sync::{Mutex, Once},
+};
+
+/// The icon sizes looked up in the hicolor theme, best first.
+#[cfg_attr(not(feature = "egui"), allow(dead_code))]
+const ICON_SIZES: [&str; 6] = ["48x48", "64x64", "32x32", "128x128", "256x256", "24x24"];
+
+/// The parsed desktop files by id, or `None` until they're scanned again after a change.
+static CACHE: Mutex<Option<HashMap<String, DesktopEntry>>> = Mutex::new(None);
+
+/// Starts the thread watching the applications directories once.
+static WATCH: Once = Once::new();
+
+/// `DesktopEntry` is the part of an application's `.desktop` file shown when choosing apps.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DesktopEntry {
+    /// The desktop file id without the `.desktop` suffix, like `org.gnome.TextEditor`.
+    pub id: String,
+    pub name: String,
+    pub comment: Option<String>,
+
+    /// An icon name in the icon theme, or an absolute path.
+    pub icon: Option<String>,
+
+    /// Whether the app is left out of application lists, like `NoDisplay` ones and links.
+    pub no_display: bool,
+
+    /// The command line starting the app, with field codes like `%u` for what it opens.
+    pub exec: Option<String>,
+}
+
+impl DesktopEntry {
+    /// Describe an app without a desktop file by its id alone.
+    pub fn unknown(id: &str) -> Self {
+        Self {
+            id: id.to_owned(),
+            name: id.to_owned(),
+            ..Self::default()
+        }
+    }
+}
+
+/// Look up the desktop file of an app by its id, falling back to the id alone.
+///
+/// The desktop files are scanned once and cached until one of the applications directories
+/// changes.
+pub fn lookup(id: &str) -> DesktopEntry {
+    WATCH.call_once(watch);
+
+    let mut cache = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+
+    cache
+        .get_or_insert_with(|| scan(&data_dirs(), &locales()))
+        .get(id)
+        .cloned()
+        .unwrap_or_else(|| DesktopEntry::unknown(id))
+}
+
+/// List the applications shown in menus, sorted by name.
+#[cfg_attr(not(feature = "egui"), allow(dead_code))]
+pub fn all() -> Vec<DesktopEntry> {
+    WATCH.call_once(watch);
+
+    let mut cache = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut entries: Vec<DesktopEntry> = cache
+        .get_or_insert_with(|| scan(&data_dirs(), &locales()))
+        .values()
+        .filter(|entry| !entry.no_display)
+        .cloned()
+        .collect();
+
+    entries.sort_by_cached_key(|entry| entry.name.to_lowercase());
+
+    entries
+}
+
+/// Add a desktop file running a custom command, so it can be chosen like an installed app.
+///
+/// The file is hidden from menus and named after the program, so entering a command for the
+/// same program again replaces it. Files are appended as `%f` if the command takes none.
+#[cfg_attr(not(feature = "egui"), allow(dead_code))]
+pub fn create(command: &str) -> std::io::Result<DesktopEntry> {
+    let invalid = |message: &str| std::io::Error::new(std::io::ErrorKind::InvalidInput, message);
+
+    if command.contains(['\n', '\r']) {
+        return Err(invalid("commands can't span lines"));
+    }
+
+    let program = command
+        .split_whitespace()
+        .next()
+        .and_then(|program| Path::new(program).file_name())
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| invalid("the command is empty"))?;
+
+    let name: String = program
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    let id = format!("xdg-desktop-portal-rs-{}", name);
+
+    let exec = if command.contains('%') {
+        command.to_owned()
+    } else {
+        format!("{} %f", command)
+    };
+
+    let applications = dirs::data_dir()
+        .ok_or_else(|| invalid("there's no data directory"))?
+        .join("applications");
+
+    std::fs::create_dir_all(&applications)?;
+
+    std::fs::write(
+        applications.join(format!("{}.desktop", id)),
+        format!(
+            "[Desktop Entry]\nType=Application\nName={}\nExec={}\nNoDisplay=true\n",
+            program, exec
+        ),
+    )?;
+
+    // Don't wait for the watch, the caller will look the app up right away.
+    *CACHE.lock().unwrap_or_else(|e| e.into_inner()) = None;
+
+    Ok(DesktopEntry {
+        id,
+        name: program.to_owned(),
+        no_display: true,
+        exec: Some(exec),
+        ..DesktopEntry::default()
+    })
+}
+
+/// Parse every desktop file in the applications directories, keyed by desktop file id.
+///
+/// Directories earlier in the list take precedence, and `Hidden` entries mask later ones.
+fn scan(data_dirs: &[PathBuf], locales: &[String]) -> HashMap<String, DesktopEntry> {
+    let mut entries = HashMap::new();
+
+    for dir in data_dirs.iter().rev() {
+        let applications = dir.join("applications");
+
+        for path in desktop_files(&applications) {
+            let Some(id) = desktop_id(&applications, &path) else {
+                continue;
+            };
+
+            let Ok(contents) = std::fs::read_to_string(&path) else {
+                continue;
+            };
+
+            match parse(&id, &contents, locales) {
+                Some(entry) => entries.insert(id, entry),
+                None => entries.remove(&id),
+            };
+        }
+    }
+
+    log::debug!("found {} desktop files", entries.len());
+
+    entries
+}
+
+/// List the `.desktop` files in a directory and its subdirectories.
+fn desktop_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(read_dir) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut files = Vec::new();
+
+    for path in read_dir.filter_map(Result::ok).map(|entry| entry.path()) {
+        if path.is_dir() {
+            files.extend(desktop_files(&path));
+        } else if path.extension().is_some_and(|e| e == "desktop") {
+            files.push(path);
+        }
+    }
+
+    files
+}
+
+/// Get the desktop file id of a file, which is its path below `applications` joined with `-`.
+fn desktop_id(applications: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(applications).ok()?.with_extension("");
+
+    let parts: Option<Vec<&str>> = relative.iter().map(|part| part.to_str()).collect();
+
+    Some(parts?.join("-"))
+}
+
+/// Parse the `[Desktop Entry]` group of a desktop file, preferring the given locales.
+///
+/// Returns `None` for files without a name and for `Hidden` ones, which count as deleted.
+fn parse(id: &str, contents: &str, locales: &[String]) -> Option<DesktopEntry> {
+    let mut values: HashMap<&str, HashMap<Option<&str>, String>> = HashMap::new();
+    let mut in_group = false;
+
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_group = line == "[Desktop Entry]";
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=').filter(|_| in_group) else {
+            continue;
+        };
+
+        let (key, locale) = match key.trim().split_once('[') {
+            Some((key, locale)) => (key, locale.strip_suffix(']')),
+            None => (key.trim(), None),
+        };
+
+        values
+            .entry(key)
+            .or_default()
+            .insert(locale, unescape(value.trim()));
+    }
+
+    let localized = |key: &str| -> Option<String> {
+        let values = values.get(key)?;
+
+        locales
+            .iter()
+            .find_map(|locale| values.get(&Some(locale.as_str())))
+            .or_else(|| values.get(&None))
+            .cloned()
+    };
+
+    if localized("Hidden").is_some_and(|hidden| hidden == "true") {
+        return None;
+    }
+
+    Some(DesktopEntry {
+        id: id.to_owned(),
+        name: localized("Name")?,
+        comment: localized("Comment"),
+        icon: localized("Icon"),
+        no_display: localized("NoDisplay").is_some_and(|value| value == "true")
+            || localized("Type").is_some_and(|value| value != "Application"),
+        exec: localized("Exec"),
+    })
+}
+
+/// Replace the escape sequences allowed in desktop file values.
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('s') => unescaped.push(' '),
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('r') => unescaped.push('\r'),
+            Some(c) => unescaped.push(c),
+            None => unescaped.push('\\'),
+        }
+    }
+
+    unescaped
+}
+
+/// Get the locale keys to look for, most specific first, from the message locale.
+///
+/// `de_DE.UTF-8@euro` matches `de_DE@euro`, `de_DE`, `de@euro` and `de`, in that order.
+pub fn locales() -> Vec<String> {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .into_iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default();
+
+    locale_keys(&locale)
+}
+
+/// Get the locale keys matching a POSIX locale name, most specific first.
+fn locale_keys(locale: &str) -> Vec<String> {
+    let (rest, modifier) = match locale.split_once('@') {
+        Some((rest, modifier)) => (rest, Some(modifier)),
+        None => (locale, None),
+    };
+
+    let rest = rest.split('.').next().unwrap_or_default();
+
+    let (lang, country) = match rest.split_once('_') {
+        Some((lang, country)) => (lang, Some(country)),
+        None => (rest, None),
+    };
+
+    if lang.is_empty() || lang == "C" || lang == "POSIX" {
+        return Vec::new();
+    }
+
+    let mut keys = Vec::new();
+
+    if let (Some(country), Some(modifier)) = (country, modifier) {
+        keys.push(format!("{}_{}@{}", lang, country, modifier));
+    }
+
+    if let Some(country) = country {
+        keys.push(format!("{}_{}", lang, country));
+    }
+
+    if let Some(modifier) = modifier {
+        keys.push(format!("{}@{}", lang, modifier));
+    }
+
+    keys.push(lang.to_owned());
+
+    keys
+}
+
+/// Clear the cache whenever an applications directory changes, using inotify.
+///
+/// Directories that don't exist yet aren't watched, so apps installed into them are only
+/// noticed after a change to another one or a restart.
+fn watch() {
+    // SAFETY: inotify_init1 has no preconditions; the result is checked below.
+    let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+
+    if fd < 0 {
+        log::warn!(
+            "failed to watch desktop files: {}",
+            std::io::Error::last_os_error()
+        );
+        return;
+    }
+
+    let mask = libc::IN_CREATE
+        | libc::IN_DELETE
+        | libc::IN_CLOSE_WRITE
+        | libc::IN_MOVED_FROM
+        | libc::IN_MOVED_TO
+        | libc::IN_ATTRIB;
+
+    let add_watches = move || {
+        for dir in data_dirs() {
+            let applications = dir.join("applications");
+
+            let dirs = std::iter::once(applications.clone()).chain(subdirectories(&applications));
+
+            for dir in dirs.filter(|dir| dir.is_dir()) {
+                let Ok(path) = CString::new(dir.as_os_str().as_bytes()) else {
+                    continue;
+                };
+
+                // SAFETY: `fd` is an open inotify descriptor and `path` is NUL-terminated.
+                // Watching a directory again just returns its existing watch.
+                unsafe { libc::inotify_add_watch(fd, path.as_ptr(), mask) };
+            }
+        }
+    };
+
+    add_watches();
+
+    let spawned = std::thread::Builder::new()
+        .name(String::from("desktop-watch"))
+        .spawn(move || {
+            let mut buffer = [0u8; 4096];
+
+            loop {
+                // SAFETY: `buffer` is valid for writes of its whole length.
+                let read = unsafe { libc::read(fd, buffer.as_mut_ptr().cast(), buffer.len()) };
+
+                if read < 0 {
+                    let e = std::io::Error::last_os_error();
+
+                    if e.kind() == std::io::ErrorKind::Interrupted {
+                        continue;
+                    }
+
+                    log::warn!("stopped watching desktop files: {}", e);
+                    return;
+                }
+
+                log::debug!("applications changed, rescanning desktop files");
+
+                *CACHE.lock().unwrap_or_else(|e| e.into_inner()) = None;
+
+                // New subdirectories need watches of their own.
+                add_watches();
+            }
+        });
+
+    if let Err(e) = spawned {
+        log::warn!("failed to watch desktop files: {}", e);
+    }
+}
+
+/// List the subdirectories of a directory, recursively.
+fn subdirectories(dir: &Path) -> Vec<PathBuf> {
+    let Ok(read_dir) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    read_dir
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .flat_map(|path| {
+            let nested = subdirectories(&path);
+            std::iter::once(path).chain(nested)
+        })
+        .collect()
+}
+
+/// Find a PNG file for an icon in the hicolor theme or the legacy pixmaps directory.
+///
+/// SVG icons are skipped, as the built-in dialogs can't render them.
+#[cfg_attr(not(feature = "egui"), allow(dead_code))]
+pub fn icon_path(icon: &str) -> Option<PathBuf> {
+    let path = Path::new(icon);
+
+    if path.is_absolute() {
+        return path.is_file().then(|| path.to_path_buf());
+    }
+
+    let file_name = format!("{}.png", icon);
+
+    let dirs = data_dirs();
+
+    let themed = ICON_SIZES.iter().flat_map(|size| {
+        dirs.iter()
+            .map(move |dir| dir.join("icons/hicolor").join(size).join("apps"))
+    });
+
+    let pixmaps = dirs.iter().map(|dir| dir.join("pixmaps"));
+
+    themed
+        .chain(pixmaps)
+        .map(|dir| dir.join(&file_name))
+        .find(|path| path.is_file())
+}
+
+/// List `$XDG_DATA_HOME` and `$XDG_DATA_DIRS`, most important first.
+pub fn data_dirs() -> Vec<PathBuf> {
+    let system = std::env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| String::from("/usr/local/share:/usr/share"));
+
+    dirs::data_dir()
+        .into_iter()
+        .chain(system.split(':').map(PathBuf::from))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{desktop_id, locale_keys, parse, scan, DesktopEntry};
+
+    #[test]
+    fn desktop_entry() {
+        let contents = "[Desktop Entry]\n\
+                        Type=Application\n\
+                        Name=Text Editor\n\
+                        Name[de]=Texteditor\n\
+                        Name[de_CH]=Texteditor (Schweiz)\n\
+                        Comment=Edit text files\\sand more\n\
+                        Icon=org.gnome.TextEditor\n\
+                        \n\
+                        [Desktop Action new-window]\n\
+                        Name=New Window\n";
+
+        let locales = locale_keys("de_AT.UTF-8");
+
+        assert_eq!(locales, ["de_AT", "de"]);
+
+        assert_eq!(
+            parse("org.gnome.TextEditor", contents, &locales),
+            Some(DesktopEntry {
+                id: String::from("org.gnome.TextEditor"),
+                name: String::from("Texteditor"),
+                comment: Some(String::from("Edit text files and more")),
+                icon: Some(String::from("org.gnome.TextEditor")),
+                no_display: false,
+                exec: None,
+            })
+        );
+
+        let link = parse("link", "[Desktop Entry]\nType=Link\nName=Docs\n", &[]);
+
+        assert!(link.is_some_and(|link| link.no_display));
+
+        assert_eq!(parse("broken", "[Desktop Action x]\nName=X\n", &[]), None);
+        assert_eq!(
+            parse("gone", "[Desktop Entry]\nName=Gone\nHidden=true\n", &[]),
+            None
+        );
+    }
+
+    #[test]
+    fn locale_fallbacks() {
+        assert_eq!(
+            locale_keys("sr_RS.UTF-8@latin"),
+            ["sr_RS@latin", "sr_RS", "sr@latin", "sr"]
+        );
+        assert!(locale_keys("C.UTF-8").is_empty());
+        assert!(locale_keys("").is_empty());
+    }
+
+    #[test]
+    fn ids_and_precedence() {
+        let applications = Path::new("/usr/share/applications");
+
+        assert_eq!(
+            desktop_id(applications, &applications.join("kde4/konsole.desktop")).as_deref(),
+            Some("kde4-konsole")
+        );
+
+        let root = std::env::temp_dir().join(format!("desktop-test-{}", std::process::id()));
+        let (user, system) = (root.join("user"), root.join("system"));
+
+        for dir in [&user, &system] {
+            std::fs::create_dir_all(dir.join("applications")).unwrap();
+        }
+
+        let write = |dir: &Path, name: &str, contents: &str| {
+            std::fs::write(dir.join("applications").join(name), contents).unwrap();
+        };
+
+        write(&system, "a.desktop", "[Desktop Entry]\nName=System A\n");
+        write(&system, "b.desktop", "[Desktop Entry]\nName=System B\n");
+        write(&user, "a.desktop", "[Desktop Entry]\nName=User A\n");
+        write(&user, "b.desktop", "[Desktop Entry]\nName=B\nHidden=true\n");
+
+        let entries = scan(&[user, system], &[]);
+
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(entries["a"].name, "User A");
+        assert!(!entries.contains_key("b"));
+    }
+}
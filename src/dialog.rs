@@ -0,0 +1,740 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{
+    capture::{Capture, Image, Rect},
+    cast::Source,
+    choices::{self, Choice},
+    config::{Config, DialogBackend},
+    desktop::DesktopEntry,
+    filter::Filter,
+    print::{self, Printer},
+    shortcuts::{Shortcut, Trigger},
+    window::ParentWindow,
+};
+
+mod command;
+#[cfg(feature = "egui")]
+mod egui;
+#[cfg(feature = "kde")]
+mod kde;
+mod rfd;
+mod scripted;
+#[cfg(feature = "tui")]
+mod tui;
+
+/// The label of the option that cancels a dialog.
+const CANCEL: &str = "Cancel";
+
+/// `DialogProvider` shows the dialogs the portal interfaces need.
+///
+/// Every method blocks until the user answers, so callers run them off the async executor.
+pub trait DialogProvider: Send + Sync {
+    /// Ask the user to open one or more files, or folders if `request.directory` is set.
+    fn open_file(&self, request: &FileRequest) -> Option<FileResponse>;
+
+    /// Ask the user where to save a file.
+    fn save_file(&self, request: &FileRequest) -> Option<FileResponse>;
+
+    /// Ask the user to pick a single folder.
+    fn pick_folder(&self, request: &FileRequest) -> Option<PathBuf>;
+
+    /// Ask the user to accept or reject the message, returning whether they accepted.
+    fn confirm(&self, message: &Message) -> bool;
+
+    /// Ask the user to pick one of the labelled options, returning its index,
+    /// or `None` if they dismissed the dialog.
+    ///
+    /// Providers that can only show two buttons offer the options in turn, each to be picked
+    /// or passed over for the next one, returning `None` once all of them were passed over,
+    /// as they can't tell dismissing a dialog from rejecting it.
+    /// A last option labelled "Cancel" isn't offered, as passing over the others cancels.
+    fn choose(&self, message: &Message, options: &[String]) -> Option<usize> {
+        let offered = match options.split_last() {
+            Some((last, rest)) if last == CANCEL && !rest.is_empty() => rest,
+            _ => options,
+        };
+
+        offered.iter().enumerate().find_map(|(i, option)| {
+            let next = match i + 1 == offered.len() {
+                true => CANCEL,
+                false => "Next",
+            };
+
+            let message = Message {
+                accept_label: Some(option.clone()),
+                reject_label: Some(String::from(next)),
+                ..message.clone()
+            };
+
+            self.confirm(&message).then_some(i)
+        })
+    }
+
+    /// Show the message until the user dismisses it.
+    fn message(&self, message: &Message);
+
+    /// Ask the user to click a pixel of a picture of the screen, returning its position.
+    ///
+    /// Providers that can't show the picture over the whole screen don't support picking.
+    fn pick_pixel(&self, _screen: &Image) -> Option<(u32, u32)> {
+        log::warn!("the dialog backend can't pick pixels, cancelling");
+        None
+    }
+
+    /// Ask the user to confirm a screenshot, letting them change its options from `defaults`.
+    ///
+    /// Providers without a form for the options ask to confirm it with the defaults.
+    fn screenshot_options(
+        &self,
+        message: &Message,
+        defaults: ScreenshotOptions,
+    ) -> Option<ScreenshotOptions> {
+        self.confirm(message).then_some(defaults)
+    }
+
+    /// Count down the `seconds` before a screenshot is taken, returning once they passed.
+    ///
+    /// Providers that can't show an overlay just wait.
+    fn countdown(&self, seconds: u64) {
+        std::thread::sleep(Duration::from_secs(seconds));
+    }
+
+    /// Ask the user which area of a picture of the screen to keep, offering to pick one of
+    /// `windows` or the whole screen.
+    ///
+    /// Providers that can't show the picture over the whole screen keep all of it,
+    /// since the screenshot was already confirmed with `screenshot_options`.
+    fn select_region(&self, _message: &Message, screen: &Image, _windows: &[Rect]) -> Option<Rect> {
+        Some(screen.bounds())
+    }
+
+    /// Ask the user to accept the message, showing `preview` of what accepting it looks like,
+    /// returning whether they accepted.
+    ///
+    /// Providers that can't show pictures ask to confirm the message without it.
+    fn confirm_preview(&self, message: &Message, _preview: &Image) -> bool {
+        self.confirm(message)
+    }
+
+    /// Ask the user whether to share their name and picture, letting them change the name
+    /// or ask to pick another picture.
+    ///
+    /// Providers without a form offer the options of `choose`, so the name can't be changed.
+    fn share_account(&self, request: &AccountRequest) -> Option<AccountAnswer> {
+        let picture = match &request.picture {
+            Some(picture) => picture.display().to_string(),
+            None => String::from("none"),
+        };
+
+        let message = Message {
+            description: format!(
+                "{}\n\nName: {}\nPicture: {}",
+                request.message.description, request.name, picture
+            ),
+            ..request.message.clone()
+        };
+
+        let options = ["Share", "Choose Picture…", CANCEL].map(String::from);
+
+        match self.choose(&message, &options)? {
+            0 => Some(AccountAnswer::Share(request.name.clone())),
+            1 => Some(AccountAnswer::ChoosePicture(request.name.clone())),
+            _ => None,
+        }
+    }
+
+    /// Ask the user how to print a document, returning the settings they picked.
+    ///
+    /// Providers without a form offer the printers as options of `choose`, so the document
+    /// is printed on the picked one as the app set it up.
+    fn prepare_print(&self, request: &PrintRequest) -> Option<print::Settings> {
+        let mut options: Vec<String> = request
+            .printers
+            .iter()
+            .map(|printer| printer.description.clone())
+            .collect();
+
+        options.push(String::from(CANCEL));
+
+        let printer = self.choose(&request.message, &options)?;
+
+        Some(print::Settings {
+            printer: request.printers.get(printer)?.name.clone(),
+            ..request.settings.clone()
+        })
+    }
+
+    /// Ask the user for a password, or to choose one if `confirm` is set, returning it.
+    ///
+    /// Providers without a password field can't ask, so they always return `None`.
+    fn ask_password(&self, _request: &PasswordRequest) -> Option<String> {
+        None
+    }
+
+    /// Show the user the global shortcuts of apps, letting them change their triggers,
+    /// returning the shortcuts as they left them.
+    ///
+    /// Providers without a form list the shortcuts in a confirmation, so they stay as they are.
+    fn edit_shortcuts(&self, request: &ShortcutsRequest) -> Option<Vec<AppShortcuts>> {
+        let several = request.apps.len() > 1;
+
+        let mut lines = vec![request.message.description.clone()];
+
+        // Several apps each get a paragraph, headed by their name.
+        if !several {
+            lines.push(String::new());
+        }
+
+        for app in &request.apps {
+            if several {
+                lines.push(format!("\n{}:", app.name));
+            }
+
+            for shortcut in &app.shortcuts {
+                let trigger = match &shortcut.trigger {
+                    Some(trigger) => trigger.describe(),
+                    None => String::from("none"),
+                };
+
+                lines.push(format!("{}: {}", shortcut.description, trigger));
+            }
+        }
+
+        let message = Message {
+            description: lines.join("\n"),
+            ..request.message.clone()
+        };
+
+        self.confirm(&message).then(|| request.apps.clone())
+    }
+
+    /// Ask the user to grant an app what it asks for, returning the `(id, selection)` pair of
+    /// each choice if they did.
+    ///
+    /// Providers without a form ask to confirm the message without the icon, then ask for
+    /// each choice in turn.
+    fn grant_access(&self, request: &AccessRequest) -> Option<Vec<(String, String)>> {
+        let description = match request.subtitle.is_empty() {
+            true => request.message.description.clone(),
+            false => format!("{}\n\n{}", request.subtitle, request.message.description),
+        };
+
+        let message = Message {
+            description,
+            ..request.message.clone()
+        };
+
+        if !self.confirm(&message) {
+            return None;
+        }
+
+        let message = Message {
+            accept_label: None,
+            ..request.message.clone()
+        };
+
+        Some(choices::ask(self, &message, &request.choices))
+    }
+
+    /// Ask the user which monitors or windows to share, returning the indices of the picked
+    /// sources.
+    ///
+    /// Providers without a list dialog offer the sources as options of `choose`,
+    /// so a single one is picked and there are no previews.
+    fn pick_sources(&self, request: &SourceRequest) -> Option<Vec<usize>> {
+        let message = Message {
+            title: request.title.clone(),
+            description: request.description.clone(),
+            parent: request.parent.clone(),
+            accept_label: Some(String::from("Share")),
+            reject_label: Some(String::from("Cancel")),
+            ..Message::default()
+        };
+
+        if request.sources.len() == 1 {
+            return self.confirm(&message).then(|| vec![0]);
+        }
+
+        let mut options: Vec<String> = request.sources.iter().map(Source::label).collect();
+
+        options.push(String::from(CANCEL));
+
+        let index = self.choose(&message, &options)?;
+
+        (index < request.sources.len()).then(|| vec![index])
+    }
+
+    /// Ask the user which application to use.
+    ///
+    /// Providers without a list dialog offer the applications as options of `choose`, along
+    /// with "Cancel", don't pick up updates of the list while it's shown and never offer
+    /// "always use".
+    fn choose_application(&self, request: &AppRequest) -> Option<AppResponse> {
+        let mut choices = request.choices.get();
+
+        // The first option is the default one, so offer the last choice first.
+        if let Some(last) = choices
+            .iter()
+            .position(|choice| Some(&choice.id) == request.last_choice.as_ref())
+        {
+            let last = choices.remove(last);
+            choices.insert(0, last);
+        }
+
+        let message = Message {
+            title: request.title.clone(),
+            description: request.description.clone(),
+            parent: request.parent.clone(),
+            ..Message::default()
+        };
+
+        let mut names: Vec<String> = choices.iter().map(|choice| choice.name.clone()).collect();
+
+        names.push(String::from(CANCEL));
+
+        // Cancel is past the end of the choices.
+        let index = self.choose(&message, &names)?;
+
+        Some(AppResponse {
+            id: choices.get(index)?.id.clone(),
+            always: false,
+        })
+    }
+}
+
+/// `FileRequest` describes a file dialog to show.
+#[derive(Debug, Clone, Default)]
+pub struct FileRequest {
+    pub title: String,
+    pub accept_label: Option<String>,
+    pub parent: Option<ParentWindow>,
+    pub modal: bool,
+    pub multiple: bool,
+    pub directory: bool,
+    pub folder: Option<PathBuf>,
+    pub file_name: Option<String>,
+    pub filters: Vec<Filter>,
+    pub choices: Vec<Choice>,
+}
+
+/// `FileResponse` is what the user picked in a file dialog.
+#[derive(Debug, Clone, Default)]
+pub struct FileResponse {
+    pub paths: Vec<PathBuf>,
+
+    /// The filter that was active, if the provider knows it.
+    pub current_filter: Option<Filter>,
+
+    /// The `(id, selection)` pair of each choice, if the provider rendered them.
+    pub choices: Option<Vec<(String, String)>>,
+}
+
+/// `AccountRequest` describes the information about the user an app asks for.
+#[derive(Debug, Clone, Default)]
+pub struct AccountRequest {
+    /// Why the app asks, with the title and parent of the dialog.
+    pub message: Message,
+
+    /// The name to share, which the user can change.
+    pub name: String,
+
+    /// The picture to share, if there's one.
+    pub picture: Option<PathBuf>,
+}
+
+/// `AccountAnswer` is how the user answered an `AccountRequest`, with the name they left.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AccountAnswer {
+    /// Share the name and the picture.
+    Share(String),
+
+    /// Pick another picture before sharing.
+    ChoosePicture(String),
+}
+
+/// `AccessRequest` describes what an app asks the user to grant it, like using a device.
+#[derive(Debug, Clone, Default)]
+pub struct AccessRequest {
+    /// The title and body of the dialog, with the labels of its grant and deny buttons.
+    pub message: Message,
+
+    /// What's asked for, shown above the body.
+    pub subtitle: String,
+
+    /// The icon shown beside the message, an icon name in the icon theme or an absolute path.
+    pub icon: Option<String>,
+
+    /// The choices the user makes along with granting access.
+    pub choices: Vec<Choice>,
+}
+
+/// `PrintRequest` describes how an app asks to print a document.
+#[derive(Debug, Clone)]
+pub struct PrintRequest {
+    /// What's printed, with the title and parent of the dialog.
+    pub message: Message,
+
+    /// The printers to pick from, the default one first.
+    pub printers: Vec<Printer>,
+
+    /// The settings the dialog starts out with, on one of `printers`.
+    pub settings: print::Settings,
+}
+
+/// `PasswordRequest` describes a password to ask the user for.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(not(feature = "egui"), allow(dead_code))]
+pub struct PasswordRequest {
+    /// What the password is for, with the title and parent of the dialog.
+    pub message: Message,
+
+    /// Whether the password is being chosen, so it's typed twice.
+    pub confirm: bool,
+}
+
+/// `ShortcutsRequest` describes the global shortcuts of apps to show the user.
+#[derive(Debug, Clone, Default)]
+pub struct ShortcutsRequest {
+    /// What the shortcuts are shown for, with the title and parent of the dialog.
+    pub message: Message,
+
+    pub apps: Vec<AppShortcuts>,
+
+    /// The triggers of apps that aren't shown, which the shortcuts can't take.
+    #[cfg_attr(not(feature = "egui"), allow(dead_code))]
+    pub taken: Vec<Trigger>,
+}
+
+/// `AppShortcuts` is the global shortcuts of an app.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppShortcuts {
+    pub app_id: String,
+
+    /// The name of the app, for people.
+    pub name: String,
+
+    pub shortcuts: Vec<Shortcut>,
+}
+
+/// `AppRequest` describes an application chooser to show.
+#[derive(Debug, Clone, Default)]
+pub struct AppRequest {
+    pub title: String,
+
+    /// What the application is chosen for, like `Choose an application to open "notes.txt".`
+    pub description: String,
+    pub parent: Option<ParentWindow>,
+    pub choices: AppChoices,
+
+    /// The desktop file id of the application chosen last time, which starts out selected.
+    pub last_choice: Option<String>,
+
+    /// Whether to offer an "Always use this application" checkbox.
+    pub offer_always: bool,
+
+    /// Whether applications outside `choices`, or a custom command, may be picked.
+    pub offer_other: bool,
+}
+
+/// `AppResponse` is the application the user picked.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AppResponse {
+    /// The desktop file id of the application.
+    pub id: String,
+
+    /// Whether the user ticked "Always use this application".
+    pub always: bool,
+}
+
+/// `AppChoices` is the list of an application chooser, which may be updated while it's shown.
+#[derive(Debug, Clone, Default)]
+pub struct AppChoices(Arc<Mutex<(usize, Vec<DesktopEntry>)>>);
+
+impl AppChoices {
+    pub fn new(choices: Vec<DesktopEntry>) -> Self {
+        Self(Arc::new(Mutex::new((0, choices))))
+    }
+
+    /// Get the current list.
+    pub fn get(&self) -> Vec<DesktopEntry> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).1.clone()
+    }
+
+    /// Get the revision of the list, which changes with every update.
+    #[cfg_attr(not(feature = "egui"), allow(dead_code))]
+    pub fn revision(&self) -> usize {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).0
+    }
+
+    /// Replace the list.
+    pub fn set(&self, choices: Vec<DesktopEntry>) {
+        let mut list = self.0.lock().unwrap_or_else(|e| e.into_inner());
+
+        *list = (list.0 + 1, choices);
+    }
+}
+
+/// `SourceRequest` describes a picker of the monitors or windows to share in a screen cast.
+#[derive(Clone)]
+pub struct SourceRequest {
+    pub title: String,
+    pub description: String,
+    pub parent: Option<ParentWindow>,
+
+    /// The monitors, then the windows, to pick from, with their areas in pictures taken
+    /// by `capture`.
+    pub sources: Vec<Source>,
+
+    /// Whether several sources may be picked.
+    pub multiple: bool,
+
+    /// Takes the pictures of the screen previews are cut from.
+    #[cfg_attr(not(feature = "egui"), allow(dead_code))]
+    pub capture: Arc<dyn Capture>,
+}
+
+/// `ScreenshotOptions` are the settings of a screenshot the user can change before it's taken.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScreenshotOptions {
+    /// Seconds to wait before capturing the screen.
+    pub delay: u64,
+
+    /// Whether the pointer is drawn into the picture.
+    pub cursor: bool,
+}
+
+/// `Message` describes a message or confirmation dialog to show.
+#[derive(Debug, Clone, Default)]
+pub struct Message {
+    pub title: String,
+    pub description: String,
+    pub level: Level,
+    pub parent: Option<ParentWindow>,
+
+    /// The label of the accept button, if not the provider's default.
+    pub accept_label: Option<String>,
+
+    /// The label of the reject button, if not the provider's default.
+    pub reject_label: Option<String>,
+}
+
+/// `Level` is the severity of a message.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Level {
+    #[default]
+    Info,
+    Warning,
+    Error,
+}
+
+/// Create the dialog provider selected in the config.
+///
+/// Setting `XDG_DESKTOP_PORTAL_RS_SCRIPT` to a response file selects the scripted provider
+/// regardless of the config, so test runners don't need to write one.
+/// Without a Wayland or X11 display, the default rfd provider is replaced by the terminal one
+/// if it's compiled in, since rfd can't show anything there.
+/// On KDE Plasma it's replaced by the Qt one, if that's compiled in.
+pub fn from_config(config: &Config) -> Arc<dyn DialogProvider> {
+    let script = std::env::var_os("XDG_DESKTOP_PORTAL_RS_SCRIPT").map(PathBuf::from);
+
+    if let Some(script) = script.or_else(|| {
+        (config.dialog.backend == DialogBackend::Scripted)
+            .then(|| config.dialog.script.clone())
+            .flatten()
+    }) {
+        match scripted::Scripted::load(&script) {
+            Ok(scripted) => return Arc::new(scripted),
+            Err(e) => log::error!("failed to load script {:?}: {}", script, e),
+        }
+    }
+
+    let headless =
+        std::env::var_os("WAYLAND_DISPLAY").is_none() && std::env::var_os("DISPLAY").is_none();
+
+    let backend = match config.dialog.backend {
+        DialogBackend::Rfd if headless && cfg!(feature = "tui") => {
+            log::info!("no display available, showing dialogs in the terminal");
+            DialogBackend::Tui
+        }
+
+        DialogBackend::Rfd if is_kde() && cfg!(feature = "kde") => DialogBackend::Kde,
+
+        backend => backend,
+    };
+
+    match backend {
+        DialogBackend::Rfd => Arc::new(rfd::Rfd),
+
+        DialogBackend::Command => Arc::new(command::Command::new(&config.dialog.command)),
+
+        #[cfg(feature = "egui")]
+        DialogBackend::Egui => Arc::new(egui::Egui::new(config.dialog.fuzzy_finder)),
+
+        #[cfg(not(feature = "egui"))]
+        DialogBackend::Egui => {
+            log::warn!("built without the egui feature, falling back to rfd");
+            Arc::new(rfd::Rfd)
+        }
+
+        #[cfg(feature = "tui")]
+        DialogBackend::Tui => {
+            let tty = config.dialog.tty.clone();
+
+            Arc::new(tui::Tui::new(
+                tty.unwrap_or_else(|| PathBuf::from("/dev/tty")),
+            ))
+        }
+
+        #[cfg(not(feature = "tui"))]
+        DialogBackend::Tui => {
+            log::warn!("built without the tui feature, falling back to rfd");
+            Arc::new(rfd::Rfd)
+        }
+
+        #[cfg(feature = "kde")]
+        DialogBackend::Kde => Arc::new(kde::Kde::new()),
+
+        #[cfg(not(feature = "kde"))]
+        DialogBackend::Kde => {
+            log::warn!("built without the kde feature, falling back to rfd");
+            Arc::new(rfd::Rfd)
+        }
+
+        DialogBackend::Scripted => {
+            log::warn!("no usable script for the scripted backend, falling back to rfd");
+            Arc::new(rfd::Rfd)
+        }
+    }
+}
+
+/// Check whether the session is KDE Plasma.
+pub fn is_kde() -> bool {
+    std::env::var("XDG_CURRENT_DESKTOP")
+        .is_ok_and(|desktops| desktops.split(':').any(|desktop| desktop == "KDE"))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{path::PathBuf, sync::Mutex};
+
+    use super::{
+        AppChoices, AppRequest, AppResponse, DialogProvider, FileRequest, FileResponse, Message,
+    };
+    use crate::desktop::DesktopEntry;
+
+    /// `Buttons` answers confirmations in order, recording the buttons each one had.
+    pub(crate) struct Buttons {
+        answers: Mutex<Vec<bool>>,
+        asked: Mutex<Vec<(String, String)>>,
+    }
+
+    impl Buttons {
+        pub(crate) fn new(answers: &[bool]) -> Self {
+            Self {
+                answers: Mutex::new(answers.iter().rev().copied().collect()),
+                asked: Mutex::new(Vec::new()),
+            }
+        }
+
+        fn asked(&self) -> Vec<(String, String)> {
+            self.asked.lock().unwrap().clone()
+        }
+    }
+
+    impl DialogProvider for Buttons {
+        fn open_file(&self, _: &FileRequest) -> Option<FileResponse> {
+            None
+        }
+
+        fn save_file(&self, _: &FileRequest) -> Option<FileResponse> {
+            None
+        }
+
+        fn pick_folder(&self, _: &FileRequest) -> Option<PathBuf> {
+            None
+        }
+
+        fn confirm(&self, message: &Message) -> bool {
+            self.asked.lock().unwrap().push((
+                message.accept_label.clone().unwrap_or_default(),
+                message.reject_label.clone().unwrap_or_default(),
+            ));
+
+            self.answers.lock().unwrap().pop().unwrap_or(false)
+        }
+
+        fn message(&self, _: &Message) {}
+    }
+
+    fn options(labels: &[&str]) -> Vec<String> {
+        labels.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn choose_in_turn() {
+        let options = options(&["Allow", "Always Allow", "Deny"]);
+
+        let buttons = Buttons::new(&[false, true]);
+
+        assert_eq!(buttons.choose(&Message::default(), &options), Some(1));
+        assert_eq!(
+            buttons.asked(),
+            [("Allow", "Next"), ("Always Allow", "Next")].map(|(a, r)| (a.into(), r.into()))
+        );
+
+        // Passing over every option, like dismissing each dialog, picks none.
+        let buttons = Buttons::new(&[]);
+
+        assert_eq!(buttons.choose(&Message::default(), &options), None);
+        assert_eq!(buttons.asked().len(), 3);
+        assert_eq!(buttons.asked()[2], ("Deny".into(), "Cancel".into()));
+    }
+
+    #[test]
+    fn choose_without_cancel() {
+        let options = options(&["Firefox", "Chromium", "Cancel"]);
+
+        let buttons = Buttons::new(&[false, false]);
+
+        assert_eq!(buttons.choose(&Message::default(), &options), None);
+        assert_eq!(
+            buttons.asked(),
+            [("Firefox", "Next"), ("Chromium", "Cancel")].map(|(a, r)| (a.into(), r.into()))
+        );
+    }
+
+    #[test]
+    fn choose_any_application() {
+        let request = AppRequest {
+            choices: AppChoices::new(
+                [
+                    "org.mozilla.firefox",
+                    "org.chromium.Chromium",
+                    "org.gnome.Epiphany",
+                ]
+                .map(DesktopEntry::unknown)
+                .to_vec(),
+            ),
+            last_choice: Some(String::from("org.gnome.Epiphany")),
+            ..AppRequest::default()
+        };
+
+        // The last choice comes first, then the others in order.
+        let buttons = Buttons::new(&[false, false, true]);
+
+        assert_eq!(
+            buttons.choose_application(&request),
+            Some(AppResponse {
+                id: String::from("org.chromium.Chromium"),
+                always: false,
+            })
+        );
+
+        // Dismissing the dialogs launches nothing.
+        assert_eq!(Buttons::new(&[]).choose_application(&request), None);
+    }
+}
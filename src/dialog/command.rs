@@ -0,0 +1,590 @@
+use std::{
+    io::Read,
+    path::PathBuf,
+    process::{Child, Stdio},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use super::{DialogProvider, FileRequest, FileResponse, Level, Message};
+use crate::{
+    config::{CommandConfig, CommandPreset},
+    filter,
+    request::Ended,
+    window::ParentWindow,
+};
+
+/// How often a running program is checked for having exited, or the request it's shown for
+/// having ended.
+const ENDED_INTERVAL: Duration = Duration::from_millis(250);
+
+/// `Command` shows dialogs by running an external program like zenity, kdialog or yad.
+///
+/// Each dialog has a template: a program and its arguments, with `{placeholders}` filled in
+/// from the request. An argument is left out if one of its placeholders has no value, and
+/// arguments with `{filter_name}` or `{filter_globs}` are repeated for each filter.
+/// The program answers through its exit status, 0 for accept and anything else for cancel,
+/// and prints the chosen paths to stdout, one per line.
+/// The program is killed once the request it's shown for ended, which dismisses its dialog.
+pub struct Command {
+    templates: Templates,
+}
+
+/// `Templates` are the command lines of each kind of dialog.
+#[derive(Debug, Clone)]
+struct Templates {
+    open_file: Vec<String>,
+    open_files: Vec<String>,
+    save_file: Vec<String>,
+    pick_folder: Vec<String>,
+    confirm: Vec<String>,
+    message: Vec<String>,
+}
+
+/// `Values` are the placeholders of a template and what they expand to.
+type Values = Vec<(&'static str, Option<String>)>;
+
+impl Command {
+    /// Build the templates from a preset and the configured overrides.
+    pub fn new(config: &CommandConfig) -> Self {
+        let preset = preset(config.preset);
+
+        let pick = |custom: &Option<Vec<String>>, preset: Vec<String>| match custom {
+            Some(custom) if !custom.is_empty() => custom.clone(),
+            _ => preset,
+        };
+
+        Self {
+            templates: Templates {
+                open_file: pick(&config.open_file, preset.open_file),
+                open_files: pick(&config.open_files, preset.open_files),
+                save_file: pick(&config.save_file, preset.save_file),
+                pick_folder: pick(&config.pick_folder, preset.pick_folder),
+                confirm: pick(&config.confirm, preset.confirm),
+                message: pick(&config.message, preset.message),
+            },
+        }
+    }
+
+    /// Run a file dialog and parse the paths it printed.
+    fn paths(
+        &self,
+        template: &[String],
+        request: &FileRequest,
+        default_title: &str,
+    ) -> Option<Vec<PathBuf>> {
+        let stdout = run(&expand(
+            template,
+            &file_values(request, default_title),
+            &request.filters,
+        ))?;
+
+        let paths: Vec<PathBuf> = stdout
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(PathBuf::from)
+            .collect();
+
+        (!paths.is_empty()).then_some(paths)
+    }
+}
+
+impl DialogProvider for Command {
+    fn open_file(&self, request: &FileRequest) -> Option<FileResponse> {
+        let template = match (request.directory, request.multiple) {
+            (true, _) => &self.templates.pick_folder,
+            (false, true) => &self.templates.open_files,
+            (false, false) => &self.templates.open_file,
+        };
+
+        Some(FileResponse {
+            paths: self.paths(template, request, "Open File")?,
+            ..FileResponse::default()
+        })
+    }
+
+    fn save_file(&self, request: &FileRequest) -> Option<FileResponse> {
+        let mut paths = self.paths(&self.templates.save_file, request, "Save File")?;
+
+        paths.truncate(1);
+
+        Some(FileResponse {
+            paths,
+            ..FileResponse::default()
+        })
+    }
+
+    fn pick_folder(&self, request: &FileRequest) -> Option<PathBuf> {
+        self.paths(&self.templates.pick_folder, request, "Select Folder")?
+            .into_iter()
+            .next()
+    }
+
+    fn confirm(&self, message: &Message) -> bool {
+        run(&expand(
+            &self.templates.confirm,
+            &message_values(message),
+            &[],
+        ))
+        .is_some()
+    }
+
+    fn message(&self, message: &Message) {
+        run(&expand(
+            &self.templates.message,
+            &message_values(message),
+            &[],
+        ));
+    }
+}
+
+/// Get the placeholder values of a file dialog.
+fn file_values(request: &FileRequest, default_title: &str) -> Values {
+    let folder = request
+        .folder
+        .clone()
+        .or_else(dirs::home_dir)
+        .unwrap_or_else(|| PathBuf::from("/"));
+
+    // Pointing at the folder itself needs a trailing slash, or zenity selects it as a file.
+    let path = match &request.file_name {
+        Some(name) => folder.join(name).display().to_string(),
+        None => format!("{}/", folder.display()),
+    };
+
+    let filters = request
+        .filters
+        .iter()
+        .map(|(name, patterns)| format!("{} ({})", name, globs(patterns)))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    vec![
+        ("title", Some(non_empty(&request.title, default_title))),
+        ("folder", Some(folder.display().to_string())),
+        ("file_name", request.file_name.clone()),
+        ("path", Some(path)),
+        (
+            "accept_label",
+            request
+                .accept_label
+                .clone()
+                .filter(|label| !label.is_empty()),
+        ),
+        ("parent", parent(&request.parent)),
+        ("filters", (!filters.is_empty()).then_some(filters)),
+    ]
+}
+
+/// Get the placeholder values of a message or confirmation dialog.
+fn message_values(message: &Message) -> Values {
+    let level = match message.level {
+        Level::Info => "info",
+        Level::Warning => "warning",
+        Level::Error => "error",
+    };
+
+    vec![
+        ("title", Some(non_empty(&message.title, "Message"))),
+        ("description", Some(message.description.clone())),
+        ("level", Some(String::from(level))),
+        (
+            "accept_label",
+            Some(non_empty(
+                message.accept_label.as_deref().unwrap_or_default(),
+                "OK",
+            )),
+        ),
+        (
+            "reject_label",
+            Some(non_empty(
+                message.reject_label.as_deref().unwrap_or_default(),
+                "Cancel",
+            )),
+        ),
+        ("parent", parent(&message.parent)),
+    ]
+}
+
+/// Use `fallback` for an empty string, so positional arguments never disappear.
+fn non_empty(value: &str, fallback: &str) -> String {
+    match value.is_empty() {
+        true => fallback.to_owned(),
+        false => value.to_owned(),
+    }
+}
+
+/// Get the window id of an X11 parent, which zenity-like programs can attach to.
+fn parent(parent: &Option<ParentWindow>) -> Option<String> {
+    match parent {
+        Some(ParentWindow::X11(id)) => Some(id.to_string()),
+        _ => None,
+    }
+}
+
+/// Convert filter patterns to space separated globs like `*.png *.jpg`.
+fn globs(patterns: &[(u32, String)]) -> String {
+    filter::extensions(patterns)
+        .iter()
+        .map(|extension| match extension.as_str() {
+            "*" => String::from("*"),
+            extension => format!("*.{}", extension),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Fill in the placeholders of a template.
+fn expand(template: &[String], values: &Values, filters: &[filter::Filter]) -> Vec<String> {
+    let mut args = Vec::with_capacity(template.len());
+
+    for arg in template {
+        if arg.contains("{filter_name}") || arg.contains("{filter_globs}") {
+            args.extend(filters.iter().map(|(name, patterns)| {
+                arg.replace("{filter_name}", name)
+                    .replace("{filter_globs}", &globs(patterns))
+            }));
+
+            continue;
+        }
+
+        let mut expanded = arg.clone();
+        let mut missing = false;
+
+        for (key, value) in values {
+            let placeholder = format!("{{{}}}", key);
+
+            if !expanded.contains(&placeholder) {
+                continue;
+            }
+
+            match value {
+                Some(value) => expanded = expanded.replace(&placeholder, value),
+                None => missing = true,
+            }
+        }
+
+        if !missing {
+            args.push(expanded);
+        }
+    }
+
+    args
+}
+
+/// Run a command line, returning its stdout if it exited successfully, or `None` if it
+/// didn't or was killed because the request it's shown for ended.
+fn run(args: &[String]) -> Option<String> {
+    let (program, args) = args.split_first()?;
+
+    log::debug!("running {} {:?}", program, args);
+
+    let output = std::process::Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .and_then(|child| wait(child, &Ended::current()));
+
+    let output = match output {
+        Ok(Some(output)) => output,
+
+        Ok(None) => {
+            log::debug!("dismissed {}, its request ended", program);
+            return None;
+        }
+
+        Err(e) => {
+            log::error!("failed to run {}: {}", program, e);
+            return None;
+        }
+    };
+
+    match output.status.code() {
+        Some(0) => Some(String::from_utf8_lossy(&output.stdout).into_owned()),
+
+        // 1 is how zenity, kdialog and yad report a cancelled dialog.
+        Some(1) => None,
+
+        _ => {
+            log::warn!(
+                "{} failed with {}: {}",
+                program,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            None
+        }
+    }
+}
+
+/// Wait for `child` to exit and collect its output, or kill it once `ended` and return `None`.
+fn wait(mut child: Child, ended: &Ended) -> std::io::Result<Option<std::process::Output>> {
+    // The pipes are drained while waiting, so a program printing a lot doesn't block on them.
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+
+        if ended.get() {
+            child.kill()?;
+            child.wait()?;
+            return Ok(None);
+        }
+
+        std::thread::sleep(ENDED_INTERVAL);
+    };
+
+    let collect = |pipe: JoinHandle<Vec<u8>>| pipe.join().unwrap_or_default();
+
+    Ok(Some(std::process::Output {
+        status,
+        stdout: collect(stdout),
+        stderr: collect(stderr),
+    }))
+}
+
+/// Read all of `pipe` on a thread of its own.
+fn drain(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut bytes = Vec::new();
+
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut bytes);
+        }
+
+        bytes
+    })
+}
+
+/// Get the templates of a preset.
+fn preset(preset: CommandPreset) -> Templates {
+    let strings = |args: &[&str]| args.iter().map(ToString::to_string).collect();
+
+    match preset {
+        CommandPreset::Zenity => Templates {
+            open_file: strings(&[
+                "zenity",
+                "--file-selection",
+                "--title={title}",
+                "--filename={folder}/",
+                "--file-filter={filter_name} | {filter_globs}",
+            ]),
+            open_files: strings(&[
+                "zenity",
+                "--file-selection",
+                "--multiple",
+                "--separator=\n",
+                "--title={title}",
+                "--filename={folder}/",
+                "--file-filter={filter_name} | {filter_globs}",
+            ]),
+            save_file: strings(&[
+                "zenity",
+                "--file-selection",
+                "--save",
+                "--title={title}",
+                "--filename={path}",
+                "--file-filter={filter_name} | {filter_globs}",
+            ]),
+            pick_folder: strings(&[
+                "zenity",
+                "--file-selection",
+                "--directory",
+                "--title={title}",
+                "--filename={folder}/",
+            ]),
+            confirm: strings(&[
+                "zenity",
+                "--question",
+                "--no-markup",
+                "--title={title}",
+                "--text={description}",
+                "--ok-label={accept_label}",
+                "--cancel-label={reject_label}",
+            ]),
+            message: strings(&[
+                "zenity",
+                "--{level}",
+                "--no-markup",
+                "--title={title}",
+                "--text={description}",
+                "--ok-label={accept_label}",
+            ]),
+        },
+
+        CommandPreset::Kdialog => Templates {
+            open_file: strings(&[
+                "kdialog",
+                "--title",
+                "{title}",
+                "--attach={parent}",
+                "--getopenfilename",
+                "{folder}",
+                "{filters}",
+            ]),
+            open_files: strings(&[
+                "kdialog",
+                "--title",
+                "{title}",
+                "--attach={parent}",
+                "--multiple",
+                "--separate-output",
+                "--getopenfilename",
+                "{folder}",
+                "{filters}",
+            ]),
+            save_file: strings(&[
+                "kdialog",
+                "--title",
+                "{title}",
+                "--attach={parent}",
+                "--getsavefilename",
+                "{path}",
+                "{filters}",
+            ]),
+            pick_folder: strings(&[
+                "kdialog",
+                "--title",
+                "{title}",
+                "--attach={parent}",
+                "--getexistingdirectory",
+                "{folder}",
+            ]),
+            confirm: strings(&[
+                "kdialog",
+                "--title",
+                "{title}",
+                "--attach={parent}",
+                "--yes-label",
+                "{accept_label}",
+                "--no-label",
+                "{reject_label}",
+                "--yesno",
+                "{description}",
+            ]),
+            message: strings(&[
+                "kdialog",
+                "--title",
+                "{title}",
+                "--attach={parent}",
+                "--msgbox",
+                "{description}",
+            ]),
+        },
+
+        CommandPreset::Yad => Templates {
+            open_file: strings(&[
+                "yad",
+                "--file",
+                "--title={title}",
+                "--filename={folder}/",
+                "--file-filter={filter_name} | {filter_globs}",
+            ]),
+            open_files: strings(&[
+                "yad",
+                "--file",
+                "--multiple",
+                "--separator=\n",
+                "--title={title}",
+                "--filename={folder}/",
+                "--file-filter={filter_name} | {filter_globs}",
+            ]),
+            save_file: strings(&[
+                "yad",
+                "--file",
+                "--save",
+                "--title={title}",
+                "--filename={path}",
+                "--file-filter={filter_name} | {filter_globs}",
+            ]),
+            pick_folder: strings(&[
+                "yad",
+                "--file",
+                "--directory",
+                "--title={title}",
+                "--filename={folder}/",
+            ]),
+            confirm: strings(&[
+                "yad",
+                "--no-markup",
+                "--title={title}",
+                "--text={description}",
+                "--button={reject_label}:1",
+                "--button={accept_label}:0",
+            ]),
+            message: strings(&[
+                "yad",
+                "--no-markup",
+                "--title={title}",
+                "--text={description}",
+                "--button={accept_label}:0",
+            ]),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::expand;
+
+    fn template(args: &[&str]) -> Vec<String> {
+        args.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn placeholders() {
+        let values = vec![
+            ("title", Some(String::from("Open Image"))),
+            ("parent", None),
+        ];
+
+        assert_eq!(
+            expand(
+                &template(&[
+                    "zenity",
+                    "--title={title}",
+                    "--attach={parent}",
+                    "--multiple"
+                ]),
+                &values,
+                &[]
+            ),
+            ["zenity", "--title=Open Image", "--multiple"]
+        );
+    }
+
+    #[test]
+    fn filters_repeat() {
+        let filters = vec![
+            (
+                String::from("Images"),
+                vec![(0, String::from("*.png")), (1, String::from("image/jpeg"))],
+            ),
+            (String::from("All"), vec![(0, String::from("*"))]),
+        ];
+
+        let args = expand(
+            &template(&["--file-filter={filter_name} | {filter_globs}"]),
+            &Vec::new(),
+            &filters,
+        );
+
+        assert_eq!(args.len(), 2);
+        assert!(args[0].starts_with("--file-filter=Images | "));
+        assert!(args[0].contains("*.png"));
+        assert!(args[0].contains("*.jpg") || args[0].contains("*.jpeg"));
+        assert_eq!(args[1], "--file-filter=All | *");
+
+        assert!(expand(
+            &template(&["--file-filter={filter_name}"]),
+            &Vec::new(),
+            &[]
+        )
+        .is_empty());
+    }
+}
@@ -0,0 +1,2370 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use eframe::egui;
+
+use super::{
+    AccessRequest, AccountAnswer, AccountRequest, AppChoices, AppRequest, AppResponse,
+    AppShortcuts, DialogProvider, FileRequest, FileResponse, Level, Message, PasswordRequest,
+    PrintRequest, ScreenshotOptions, ShortcutsRequest, SourceRequest,
+};
+use crate::{
+    capture::{Image, Rect},
+    cast::Source,
+    choices::{self, Choice},
+    desktop::DesktopEntry,
+    filter, print,
+    request::Ended,
+    shortcuts::{self, Trigger},
+    state,
+};
+
+mod fuzzy;
+mod icons;
+mod places;
+
+/// The most fuzzy finder matches listed at once.
+const MAX_MATCHES: usize = 200;
+
+/// The number of screen pixels across the color picker's magnifier.
+const ZOOM_PIXELS: f32 = 11.0;
+
+/// The size of the color picker's magnifier, in points.
+const ZOOM_SIZE: f32 = 132.0;
+
+/// The longest screenshot delay offered, in seconds.
+const MAX_DELAY: u64 = 60;
+
+/// How long the compositor gets to take the countdown window off the screen before capturing.
+const UNMAP_DELAY: Duration = Duration::from_millis(200);
+
+/// The width of monitor previews, in points.
+const PREVIEW_WIDTH: f32 = 160.0;
+
+/// How often monitor previews are refreshed.
+const PREVIEW_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often a window checks whether the request it's shown for ended.
+const ENDED_INTERVAL: Duration = Duration::from_millis(250);
+
+/// `Job` is a dialog waiting to be shown on the UI thread.
+type Job = Box<dyn FnOnce() + Send>;
+
+/// `Egui` shows self-drawn dialogs with egui, without depending on a desktop toolkit.
+///
+/// winit only allows one event loop per thread, so every window is shown on a single
+/// dedicated UI thread and requests wait for their turn.
+/// Windows can't be made transient for their parent, so `parent` and `modal` are ignored.
+pub struct Egui {
+    jobs: mpsc::Sender<Job>,
+    fuzzy_finder: bool,
+}
+
+impl Egui {
+    /// Start the UI thread, opening file browsers in fuzzy finder mode if `fuzzy_finder` is set.
+    pub fn new(fuzzy_finder: bool) -> Self {
+        let (jobs, queue) = mpsc::channel::<Job>();
+
+        std::thread::Builder::new()
+            .name(String::from("egui"))
+            .spawn(move || queue.into_iter().for_each(|job| job()))
+            .expect("failed to spawn the egui thread");
+
+        Self { jobs, fuzzy_finder }
+    }
+
+    /// Show a window on the UI thread and wait for its answer.
+    fn show<W: Window>(&self, title: &str, size: [f32; 2], window: W) -> Option<W::Output> {
+        let (answer, receiver) = mpsc::channel();
+
+        let title = title.to_owned();
+
+        let ended = Ended::current();
+
+        let job = Box::new(move || {
+            let _ = answer.send(run(&title, size, window, ended));
+        });
+
+        self.jobs.send(job).ok()?;
+
+        receiver.recv().ok().flatten()
+    }
+}
+
+impl DialogProvider for Egui {
+    fn open_file(&self, request: &FileRequest) -> Option<FileResponse> {
+        let browser = FileBrowser::new(request.clone(), Mode::Open, self.fuzzy_finder);
+
+        self.show(&request.title, [720.0, 520.0], browser).flatten()
+    }
+
+    fn save_file(&self, request: &FileRequest) -> Option<FileResponse> {
+        let browser = FileBrowser::new(request.clone(), Mode::Save, self.fuzzy_finder);
+
+        self.show(&request.title, [720.0, 520.0], browser).flatten()
+    }
+
+    fn pick_folder(&self, request: &FileRequest) -> Option<PathBuf> {
+        let browser = FileBrowser::new(request.clone(), Mode::Folder, self.fuzzy_finder);
+
+        let response = self
+            .show(&request.title, [720.0, 520.0], browser)
+            .flatten()?;
+
+        response.paths.into_iter().next()
+    }
+
+    fn confirm(&self, message: &Message) -> bool {
+        let buttons = vec![
+            message
+                .accept_label
+                .clone()
+                .unwrap_or_else(|| String::from("OK")),
+            message
+                .reject_label
+                .clone()
+                .unwrap_or_else(|| String::from("Cancel")),
+        ];
+
+        self.choose(message, &buttons) == Some(0)
+    }
+
+    fn choose(&self, message: &Message, options: &[String]) -> Option<usize> {
+        let window = MessageWindow {
+            message: message.clone(),
+            buttons: options.to_vec(),
+        };
+
+        self.show(&message.title, [420.0, 160.0], window)
+    }
+
+    fn message(&self, message: &Message) {
+        let window = MessageWindow {
+            message: message.clone(),
+            buttons: vec![message
+                .accept_label
+                .clone()
+                .unwrap_or_else(|| String::from("OK"))],
+        };
+
+        self.show(&message.title, [420.0, 160.0], window);
+    }
+
+    fn choose_application(&self, request: &AppRequest) -> Option<AppResponse> {
+        let window = AppWindow {
+            description: request.description.clone(),
+            always: request.offer_always.then_some(false),
+            offer_other: request.offer_other,
+            other: None,
+            source: request.choices.clone(),
+            revision: request.choices.revision(),
+            selected: request
+                .choices
+                .get()
+                .iter()
+                .position(|choice| Some(&choice.id) == request.last_choice.as_ref())
+                .unwrap_or_default(),
+            choices: request.choices.get(),
+            icons: icons::Icons::default(),
+        };
+
+        self.show(&request.title, [420.0, 360.0], window)
+    }
+
+    fn screenshot_options(
+        &self,
+        message: &Message,
+        defaults: ScreenshotOptions,
+    ) -> Option<ScreenshotOptions> {
+        let window = OptionsWindow {
+            message: message.clone(),
+            options: defaults,
+        };
+
+        self.show(&message.title, [420.0, 200.0], window).flatten()
+    }
+
+    fn countdown(&self, seconds: u64) {
+        if seconds == 0 {
+            return;
+        }
+
+        let window = CountdownWindow {
+            end: Instant::now() + Duration::from_secs(seconds),
+        };
+
+        self.show("Screenshot", [200.0, 120.0], window);
+
+        // The window would end up in the picture if it's still on the screen.
+        std::thread::sleep(UNMAP_DELAY);
+    }
+
+    fn select_region(&self, message: &Message, screen: &Image, windows: &[Rect]) -> Option<Rect> {
+        let window = RegionWindow {
+            message: message.clone(),
+            screen: screen.clone(),
+            windows: windows.to_vec(),
+            texture: None,
+        };
+
+        self.show(&message.title, [800.0, 600.0], window)
+    }
+
+    fn pick_sources(&self, request: &SourceRequest) -> Option<Vec<usize>> {
+        // The first source starts out selected, since most people share their only monitor.
+        let window = SourceWindow {
+            selected: (0..request.sources.len()).map(|i| i == 0).collect(),
+            request: request.clone(),
+            previews: Vec::new(),
+            refresh: None,
+            refreshed: None,
+        };
+
+        self.show(&request.title, [480.0, 420.0], window).flatten()
+    }
+
+    fn confirm_preview(&self, message: &Message, preview: &Image) -> bool {
+        let window = PreviewWindow {
+            message: message.clone(),
+            preview: preview.clone(),
+            texture: None,
+        };
+
+        let size = [
+            (preview.width as f32 + 32.0).max(420.0),
+            preview.height as f32 + 120.0,
+        ];
+
+        self.show(&message.title, size, window) == Some(true)
+    }
+
+    fn share_account(&self, request: &AccountRequest) -> Option<AccountAnswer> {
+        let window = AccountWindow {
+            request: request.clone(),
+        };
+
+        self.show(&request.message.title, [420.0, 220.0], window)
+            .flatten()
+    }
+
+    fn grant_access(&self, request: &AccessRequest) -> Option<Vec<(String, String)>> {
+        let window = AccessWindow {
+            selections: initial_choices(&request.choices),
+            request: request.clone(),
+            icons: icons::Icons::default(),
+        };
+
+        let height = 180.0 + 28.0 * request.choices.len() as f32;
+
+        self.show(&request.message.title, [440.0, height], window)
+            .flatten()
+    }
+
+    fn prepare_print(&self, request: &PrintRequest) -> Option<print::Settings> {
+        let window = PrintWindow {
+            printer: request
+                .printers
+                .iter()
+                .position(|printer| printer.name == request.settings.printer)
+                .unwrap_or(0),
+            ranges: print::format_ranges(&request.settings.ranges),
+            request: request.clone(),
+        };
+
+        self.show(&request.message.title, [420.0, 300.0], window)
+            .flatten()
+    }
+
+    fn ask_password(&self, request: &PasswordRequest) -> Option<String> {
+        let window = PasswordWindow {
+            request: request.clone(),
+            password: String::new(),
+            repeated: String::new(),
+        };
+
+        let height = match request.confirm {
+            true => 200.0,
+            false => 170.0,
+        };
+
+        self.show(&request.message.title, [380.0, height], window)
+            .flatten()
+    }
+
+    fn edit_shortcuts(&self, request: &ShortcutsRequest) -> Option<Vec<AppShortcuts>> {
+        let window = ShortcutsWindow {
+            triggers: request
+                .apps
+                .iter()
+                .map(|app| {
+                    app.shortcuts
+                        .iter()
+                        .map(|shortcut| {
+                            shortcut
+                                .trigger
+                                .as_ref()
+                                .map(Trigger::to_string)
+                                .unwrap_or_default()
+                        })
+                        .collect()
+                })
+                .collect(),
+            request: request.clone(),
+        };
+
+        self.show(&request.message.title, [480.0, 360.0], window)
+            .flatten()
+    }
+
+    fn pick_pixel(&self, screen: &Image) -> Option<(u32, u32)> {
+        let window = PixelWindow {
+            screen: screen.clone(),
+            texture: None,
+        };
+
+        self.show("Pick a Color", [800.0, 600.0], window)
+    }
+}
+
+/// `Window` is the contents of a dialog window, drawn until it produces an answer.
+trait Window: Send + 'static {
+    type Output: Send + 'static;
+
+    /// Whether the window covers the whole screen, without decorations.
+    const FULLSCREEN: bool = false;
+
+    /// Draw the window, returning the answer once the user gave one.
+    fn ui(&mut self, ctx: &egui::Context) -> Option<Self::Output>;
+}
+
+/// `Runner` adapts a Window to eframe, closing the window once it has an answer, or without
+/// one once the request it's shown for ended.
+struct Runner<W: Window> {
+    window: W,
+    answer: Arc<Mutex<Option<W::Output>>>,
+    ended: Ended,
+}
+
+impl<W: Window> eframe::App for Runner<W> {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        if self.ended.get() {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+            return;
+        }
+
+        ctx.request_repaint_after(ENDED_INTERVAL);
+
+        if let Some(output) = self.window.ui(ctx) {
+            if let Ok(mut answer) = self.answer.lock() {
+                *answer = Some(output);
+            }
+
+            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+        }
+    }
+}
+
+/// Run a window until it's answered or closed, or the request it's shown for `ended`, on the
+/// current thread.
+fn run<W: Window>(title: &str, size: [f32; 2], window: W, ended: Ended) -> Option<W::Output> {
+    let answer = Arc::new(Mutex::new(None));
+
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default()
+            .with_title(title)
+            .with_inner_size(size)
+            .with_fullscreen(W::FULLSCREEN)
+            .with_decorations(!W::FULLSCREEN),
+
+        // The UI thread isn't the main thread, which winit refuses by default.
+        event_loop_builder: Some(Box::new(|builder| {
+            winit::platform::wayland::EventLoopBuilderExtWayland::with_any_thread(builder, true);
+            winit::platform::x11::EventLoopBuilderExtX11::with_any_thread(builder, true);
+        })),
+
+        ..eframe::NativeOptions::default()
+    };
+
+    let runner = Runner {
+        window,
+        answer: answer.clone(),
+        ended,
+    };
+
+    if let Err(e) = eframe::run_native(title, options, Box::new(|_| Box::new(runner))) {
+        log::error!("failed to show egui window: {}", e);
+    }
+
+    let output = answer.lock().ok()?.take();
+
+    output
+}
+
+/// `MessageWindow` shows a message with a row of buttons, answering the index of the one clicked.
+struct MessageWindow {
+    message: Message,
+
+    /// The button labels, shown right to left so the first one ends up in the corner.
+    buttons: Vec<String>,
+}
+
+impl Window for MessageWindow {
+    type Output = usize;
+
+    fn ui(&mut self, ctx: &egui::Context) -> Option<usize> {
+        let mut answer = None;
+
+        egui::TopBottomPanel::bottom("buttons").show(ctx, |ui| {
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                for (i, label) in self.buttons.iter().enumerate() {
+                    if ui.button(label).clicked() {
+                        answer = Some(i);
+                    }
+                }
+            });
+        });
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            let text = egui::RichText::new(&self.message.description);
+
+            match self.message.level {
+                Level::Info => ui.label(text),
+                Level::Warning => ui.label(text.color(ui.visuals().warn_fg_color)),
+                Level::Error => ui.label(text.color(ui.visuals().error_fg_color)),
+            };
+        });
+
+        answer
+    }
+}
+
+/// `OptionsWindow` asks to take a screenshot, with its delay and whether to include the pointer,
+/// answering the options if it's accepted.
+struct OptionsWindow {
+    message: Message,
+    options: ScreenshotOptions,
+}
+
+impl Window for OptionsWindow {
+    type Output = Option<ScreenshotOptions>;
+
+    fn ui(&mut self, ctx: &egui::Context) -> Option<Option<ScreenshotOptions>> {
+        let mut answer = None;
+
+        egui::TopBottomPanel::bottom("buttons").show(ctx, |ui| {
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                let accept = self.message.accept_label.as_deref().unwrap_or("OK");
+                let reject = self.message.reject_label.as_deref().unwrap_or("Cancel");
+
+                if ui.button(accept).clicked() || ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                    answer = Some(Some(self.options));
+                }
+
+                if ui.button(reject).clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                    answer = Some(None);
+                }
+            });
+        });
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.label(&self.message.description);
+
+            ui.add_space(8.0);
+
+            ui.horizontal(|ui| {
+                ui.label("Delay");
+                ui.add(
+                    egui::DragValue::new(&mut self.options.delay)
+                        .clamp_range(0..=MAX_DELAY)
+                        .suffix(" s"),
+                );
+            });
+
+            ui.checkbox(&mut self.options.cursor, "Include pointer");
+        });
+
+        answer
+    }
+}
+
+/// `PreviewWindow` shows a message over a picture of what accepting it looks like, answering
+/// whether it's accepted.
+struct PreviewWindow {
+    message: Message,
+    preview: Image,
+    texture: Option<egui::TextureHandle>,
+}
+
+impl Window for PreviewWindow {
+    type Output = bool;
+
+    fn ui(&mut self, ctx: &egui::Context) -> Option<bool> {
+        let mut answer = None;
+
+        egui::TopBottomPanel::bottom("buttons").show(ctx, |ui| {
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                let accept = self.message.accept_label.as_deref().unwrap_or("OK");
+                let reject = self.message.reject_label.as_deref().unwrap_or("Cancel");
+
+                if ui.button(accept).clicked() || ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                    answer = Some(true);
+                }
+
+                if ui.button(reject).clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                    answer = Some(false);
+                }
+            });
+        });
+
+        let preview = &self.preview;
+
+        let texture = self.texture.get_or_insert_with(|| {
+            let size = [preview.width as usize, preview.height as usize];
+            let image = egui::ColorImage::from_rgba_unmultiplied(size, &preview.pixels);
+
+            ctx.load_texture("preview", image, egui::TextureOptions::LINEAR)
+        });
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.label(&self.message.description);
+
+            ui.add_space(8.0);
+
+            ui.centered_and_justified(|ui| {
+                ui.add(egui::Image::new(&*texture).shrink_to_fit());
+            });
+        });
+
+        answer
+    }
+}
+
+/// `AccountWindow` asks to share the name and picture of the user, with the name editable,
+/// answering how it's shared if it is.
+struct AccountWindow {
+    request: AccountRequest,
+}
+
+impl Window for AccountWindow {
+    type Output = Option<AccountAnswer>;
+
+    fn ui(&mut self, ctx: &egui::Context) -> Option<Option<AccountAnswer>> {
+        let mut answer = None;
+
+        let name = &self.request.name;
+
+        egui::TopBottomPanel::bottom("buttons").show(ctx, |ui| {
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if ui.button("Share").clicked() || ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                    answer = Some(Some(AccountAnswer::Share(name.clone())));
+                }
+
+                if ui.button("Cancel").clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                    answer = Some(None);
+                }
+            });
+        });
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.label(&self.request.message.description);
+
+            ui.add_space(8.0);
+
+            ui.horizontal(|ui| {
+                ui.label("Name");
+                ui.text_edit_singleline(&mut self.request.name);
+            });
+
+            ui.horizontal(|ui| {
+                let picture = match &self.request.picture {
+                    Some(picture) => picture.display().to_string(),
+                    None => String::from("None"),
+                };
+
+                ui.label(format!("Picture: {}", picture));
+
+                if ui.button("Choose…").clicked() {
+                    answer = Some(Some(AccountAnswer::ChoosePicture(
+                        self.request.name.clone(),
+                    )));
+                }
+            });
+        });
+
+        answer
+    }
+}
+
+/// `AccessWindow` asks to grant an app access, showing its icon beside what it asks for,
+/// answering the selections of the choices if it's granted.
+struct AccessWindow {
+    request: AccessRequest,
+    selections: Vec<String>,
+    icons: icons::Icons,
+}
+
+impl Window for AccessWindow {
+    type Output = Option<Vec<(String, String)>>;
+
+    fn ui(&mut self, ctx: &egui::Context) -> Option<Option<Vec<(String, String)>>> {
+        let mut answer = None;
+
+        let message = &self.request.message;
+
+        let grant = message.accept_label.as_deref().unwrap_or("Grant Access");
+        let deny = message.reject_label.as_deref().unwrap_or("Deny Access");
+
+        egui::TopBottomPanel::bottom("buttons").show(ctx, |ui| {
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if ui.button(grant).clicked() {
+                    let selections = self.request.choices.iter().zip(&self.selections);
+
+                    answer = Some(Some(
+                        selections
+                            .map(|((id, ..), selection)| (id.clone(), selection.clone()))
+                            .collect(),
+                    ));
+                }
+
+                if ui.button(deny).clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                    answer = Some(None);
+                }
+            });
+        });
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.horizontal_top(|ui| {
+                let icon = self
+                    .request
+                    .icon
+                    .as_deref()
+                    .and_then(|icon| self.icons.get(ctx, icon));
+
+                if let Some(icon) = icon {
+                    ui.add(egui::Image::new((icon.id(), egui::vec2(48.0, 48.0))));
+                }
+
+                ui.vertical(|ui| {
+                    if !self.request.subtitle.is_empty() {
+                        ui.strong(&self.request.subtitle);
+                    }
+
+                    ui.label(&self.request.message.description);
+                });
+            });
+
+            ui.add_space(8.0);
+
+            choices_ui(ui, &self.request.choices, &mut self.selections);
+        });
+
+        answer
+    }
+}
+
+/// `PrintWindow` asks how to print a document, answering with the settings if it's printed.
+struct PrintWindow {
+    request: PrintRequest,
+
+    /// The index of the picked printer.
+    printer: usize,
+
+    /// The page ranges as they're typed, which may not parse yet.
+    ranges: String,
+}
+
+impl Window for PrintWindow {
+    type Output = Option<print::Settings>;
+
+    fn ui(&mut self, ctx: &egui::Context) -> Option<Option<print::Settings>> {
+        let mut answer = None;
+
+        let ranges = print::parse_ranges(&self.ranges);
+
+        egui::TopBottomPanel::bottom("buttons").show(ctx, |ui| {
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                let print = ui.add_enabled(ranges.is_some(), egui::Button::new("Print"));
+
+                let enter = ranges.is_some() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+
+                if print.clicked() || enter {
+                    let printer = &self.request.printers[self.printer];
+
+                    answer = Some(Some(print::Settings {
+                        printer: printer.name.clone(),
+                        ranges: ranges.clone().unwrap_or_default(),
+                        ..self.request.settings.clone()
+                    }));
+                }
+
+                if ui.button("Cancel").clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                    answer = Some(None);
+                }
+            });
+        });
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.label(&self.request.message.description);
+
+            ui.add_space(8.0);
+
+            let printers = &self.request.printers;
+            let settings = &mut self.request.settings;
+
+            egui::Grid::new("settings").num_columns(2).show(ui, |ui| {
+                ui.label("Printer");
+
+                let before = self.printer;
+
+                egui::ComboBox::from_id_source("printer")
+                    .selected_text(&printers[self.printer].description)
+                    .show_ui(ui, |ui| {
+                        for (i, printer) in printers.iter().enumerate() {
+                            ui.selectable_value(&mut self.printer, i, &printer.description);
+                        }
+                    });
+
+                let printer = &printers[self.printer];
+
+                // Paper sizes the new printer doesn't take go back to its default.
+                if self.printer != before
+                    && settings
+                        .media
+                        .as_ref()
+                        .is_some_and(|media| !printer.media.contains(media))
+                {
+                    settings.media = None;
+                }
+
+                ui.end_row();
+
+                ui.label("Paper");
+
+                let paper = match &settings.media {
+                    Some(media) => print::media_name(media),
+                    None => String::from("Default"),
+                };
+
+                egui::ComboBox::from_id_source("paper")
+                    .selected_text(paper)
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut settings.media, None, "Default");
+
+                        for media in &printer.media {
+                            ui.selectable_value(
+                                &mut settings.media,
+                                Some(media.clone()),
+                                print::media_name(media),
+                            );
+                        }
+                    });
+
+                ui.end_row();
+
+                ui.label("Orientation");
+
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut settings.landscape, false, "Portrait");
+                    ui.radio_value(&mut settings.landscape, true, "Landscape");
+                });
+
+                ui.end_row();
+
+                ui.label("Copies");
+                ui.add(egui::DragValue::new(&mut settings.copies).clamp_range(1..=999));
+                ui.end_row();
+
+                ui.label("Pages");
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.ranges).hint_text("All, or like 1-3, 5"),
+                );
+                ui.end_row();
+            });
+
+            if ranges.is_none() {
+                ui.colored_label(ui.visuals().error_fg_color, "The pages can't be read.");
+            }
+        });
+
+        answer
+    }
+}
+
+/// `PasswordWindow` asks for a password, typed twice when it's being chosen, answering it
+/// unless the user cancels.
+struct PasswordWindow {
+    request: PasswordRequest,
+    password: String,
+    repeated: String,
+}
+
+impl Window for PasswordWindow {
+    type Output = Option<String>;
+
+    fn ui(&mut self, ctx: &egui::Context) -> Option<Option<String>> {
+        let mut answer = None;
+
+        let valid =
+            !self.password.is_empty() && (!self.request.confirm || self.password == self.repeated);
+
+        let accept = self
+            .request
+            .message
+            .accept_label
+            .as_deref()
+            .unwrap_or("Unlock");
+
+        egui::TopBottomPanel::bottom("buttons").show(ctx, |ui| {
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                let unlock = ui.add_enabled(valid, egui::Button::new(accept));
+
+                let enter = valid && ui.input(|i| i.key_pressed(egui::Key::Enter));
+
+                if unlock.clicked() || enter {
+                    answer = Some(Some(self.password.clone()));
+                }
+
+                if ui.button("Cancel").clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                    answer = Some(None);
+                }
+            });
+        });
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.label(&self.request.message.description);
+
+            ui.add_space(8.0);
+
+            egui::Grid::new("password").num_columns(2).show(ui, |ui| {
+                ui.label("Password");
+
+                let password =
+                    ui.add(egui::TextEdit::singleline(&mut self.password).password(true));
+
+                // Start out typing the password.
+                if ctx.memory(|memory| memory.focus().is_none()) {
+                    password.request_focus();
+                }
+
+                ui.end_row();
+
+                if self.request.confirm {
+                    ui.label("Repeat");
+                    ui.add(egui::TextEdit::singleline(&mut self.repeated).password(true));
+                    ui.end_row();
+                }
+            });
+
+            if self.request.confirm && !self.repeated.is_empty() && self.password != self.repeated {
+                ui.label("The passwords don't match.");
+            }
+        });
+
+        answer
+    }
+}
+
+/// `ShortcutsWindow` lists the global shortcuts of apps with their triggers, which can be
+/// changed, answering with the shortcuts unless the user cancels.
+struct ShortcutsWindow {
+    request: ShortcutsRequest,
+
+    /// The trigger of each shortcut of each app as it's typed, which may not parse yet.
+    triggers: Vec<Vec<String>>,
+}
+
+impl ShortcutsWindow {
+    /// Get the shortcuts with the triggers typed, or `None` if one can't be read.
+    fn edited(&self) -> Option<Vec<AppShortcuts>> {
+        self.request
+            .apps
+            .iter()
+            .zip(&self.triggers)
+            .map(|(app, triggers)| {
+                let shortcuts = app
+                    .shortcuts
+                    .iter()
+                    .zip(triggers)
+                    .map(|(shortcut, trigger)| {
+                        let trigger = match trigger.trim() {
+                            "" => None,
+                            trigger => Some(Trigger::parse(trigger)?),
+                        };
+
+                        Some(shortcuts::Shortcut {
+                            trigger,
+                            ..shortcut.clone()
+                        })
+                    })
+                    .collect::<Option<_>>()?;
+
+                Some(AppShortcuts {
+                    shortcuts,
+                    ..app.clone()
+                })
+            })
+            .collect()
+    }
+}
+
+impl Window for ShortcutsWindow {
+    type Output = Option<Vec<AppShortcuts>>;
+
+    fn ui(&mut self, ctx: &egui::Context) -> Option<Option<Vec<AppShortcuts>>> {
+        let mut answer = None;
+
+        let edited = self.edited();
+
+        let conflicts = match &edited {
+            Some(apps) => shortcuts::conflicts(
+                apps.iter().flat_map(|app| &app.shortcuts),
+                &self.request.taken,
+            ),
+            None => Vec::new(),
+        };
+
+        let valid = edited.is_some() && conflicts.is_empty();
+
+        let accept = self
+            .request
+            .message
+            .accept_label
+            .as_deref()
+            .unwrap_or("Save");
+
+        let reject = self
+            .request
+            .message
+            .reject_label
+            .as_deref()
+            .unwrap_or("Cancel");
+
+        egui::TopBottomPanel::bottom("buttons").show(ctx, |ui| {
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if ui.add_enabled(valid, egui::Button::new(accept)).clicked() {
+                    answer = Some(edited.clone());
+                }
+
+                if ui.button(reject).clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                    answer = Some(None);
+                }
+            });
+        });
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.label(&self.request.message.description);
+
+            ui.add_space(8.0);
+
+            if self.request.apps.is_empty() {
+                ui.label("No application has global shortcuts.");
+            }
+
+            let error = ui.visuals().error_fg_color;
+
+            egui::ScrollArea::vertical()
+                .max_height(ui.available_height() - 40.0)
+                .show(ui, |ui| {
+                    for (i, app) in self.request.apps.iter().enumerate() {
+                        ui.strong(&app.name);
+
+                        egui::Grid::new(("shortcuts", i))
+                            .num_columns(2)
+                            .show(ui, |ui| {
+                                for (shortcut, trigger) in
+                                    app.shortcuts.iter().zip(&mut self.triggers[i])
+                                {
+                                    ui.label(&shortcut.description);
+
+                                    let parsed = Trigger::parse(trigger.trim());
+
+                                    let wrong = !trigger.trim().is_empty()
+                                        && parsed.as_ref().is_none_or(|t| conflicts.contains(t));
+
+                                    let mut edit = egui::TextEdit::singleline(trigger)
+                                        .hint_text("None, or like CTRL+SHIFT+m");
+
+                                    if wrong {
+                                        edit = edit.text_color(error);
+                                    }
+
+                                    ui.add(edit);
+                                    ui.end_row();
+                                }
+                            });
+
+                        ui.add_space(8.0);
+                    }
+                });
+
+            if edited.is_none() {
+                ui.colored_label(error, "A trigger can't be read.");
+            }
+
+            for trigger in &conflicts {
+                let message = match self.request.taken.contains(trigger) {
+                    true => format!("{} is taken by another application.", trigger.describe()),
+                    false => format!("{} triggers several shortcuts.", trigger.describe()),
+                };
+
+                ui.colored_label(error, message);
+            }
+        });
+
+        answer
+    }
+}
+
+/// `CountdownWindow` shows the seconds left until a screenshot is taken, closing once they passed.
+struct CountdownWindow {
+    end: Instant,
+}
+
+impl Window for CountdownWindow {
+    type Output = ();
+
+    fn ui(&mut self, ctx: &egui::Context) -> Option<()> {
+        let left = self.end.saturating_duration_since(Instant::now());
+
+        if left.is_zero() {
+            return Some(());
+        }
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.centered_and_justified(|ui| {
+                // Round up, so the last second shows 1 rather than 0.
+                let seconds = left.as_millis().div_ceil(1000);
+
+                ui.label(egui::RichText::new(seconds.to_string()).size(48.0));
+            });
+        });
+
+        // Nothing happens while the countdown runs, so wake up to redraw it.
+        ctx.request_repaint_after(Duration::from_millis(100));
+
+        None
+    }
+}
+
+/// `PixelWindow` shows a still of the screen over the whole screen, with a magnifier under
+/// the pointer, and answers the pixel clicked.
+struct PixelWindow {
+    screen: Image,
+    texture: Option<egui::TextureHandle>,
+}
+
+impl Window for PixelWindow {
+    type Output = (u32, u32);
+
+    const FULLSCREEN: bool = true;
+
+    fn ui(&mut self, ctx: &egui::Context) -> Option<(u32, u32)> {
+        if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+            return None;
+        }
+
+        let (width, height) = (self.screen.width, self.screen.height);
+
+        let texture = self
+            .texture
+            .get_or_insert_with(|| screen_texture(ctx, &self.screen));
+
+        ctx.set_cursor_icon(egui::CursorIcon::Crosshair);
+
+        let mut answer = None;
+
+        egui::CentralPanel::default()
+            .frame(egui::Frame::none())
+            .show(ctx, |ui| {
+                let rect = ui.max_rect();
+
+                let response = ui.allocate_rect(rect, egui::Sense::click());
+
+                let painter = ui.painter();
+
+                let whole = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+
+                painter.image(texture.id(), rect, whole, egui::Color32::WHITE);
+
+                let Some(pointer) = response.hover_pos() else {
+                    return;
+                };
+
+                // The still may be scaled to the window, e.g. on HiDPI outputs.
+                let offset = pointer - rect.min;
+
+                let x = (offset.x / rect.width() * width as f32) as u32;
+                let y = (offset.y / rect.height() * height as f32) as u32;
+
+                let (x, y) = (x.min(width - 1), y.min(height - 1));
+
+                let texel = egui::vec2(1.0 / width as f32, 1.0 / height as f32);
+
+                let center = egui::pos2((x as f32 + 0.5) * texel.x, (y as f32 + 0.5) * texel.y);
+
+                let zoomed = egui::Rect::from_center_size(center, texel * ZOOM_PIXELS);
+
+                // Keep the magnifier next to the pointer, flipping it at the screen's edges.
+                let mut lens = egui::Rect::from_min_size(
+                    pointer + egui::vec2(16.0, 16.0),
+                    egui::vec2(ZOOM_SIZE, ZOOM_SIZE),
+                );
+
+                if lens.max.x > rect.max.x {
+                    lens = lens.translate(egui::vec2(-ZOOM_SIZE - 32.0, 0.0));
+                }
+
+                if lens.max.y > rect.max.y {
+                    lens = lens.translate(egui::vec2(0.0, -ZOOM_SIZE - 32.0));
+                }
+
+                painter.image(texture.id(), lens, zoomed, egui::Color32::WHITE);
+                painter.rect_stroke(lens, 0.0, egui::Stroke::new(2.0, egui::Color32::WHITE));
+
+                let cell = ZOOM_SIZE / ZOOM_PIXELS;
+
+                painter.rect_stroke(
+                    egui::Rect::from_center_size(lens.center(), egui::vec2(cell, cell)),
+                    0.0,
+                    egui::Stroke::new(1.0, egui::Color32::BLACK),
+                );
+
+                if response.clicked() {
+                    answer = Some((x, y));
+                }
+            });
+
+        answer
+    }
+}
+
+/// `RegionWindow` shows a still of the screen over the whole screen and answers the area
+/// dragged over, the window clicked, or the whole screen on Enter.
+struct RegionWindow {
+    message: Message,
+    screen: Image,
+    windows: Vec<Rect>,
+    texture: Option<egui::TextureHandle>,
+}
+
+impl Window for RegionWindow {
+    type Output = Rect;
+
+    const FULLSCREEN: bool = true;
+
+    fn ui(&mut self, ctx: &egui::Context) -> Option<Rect> {
+        if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+            return None;
+        }
+
+        if ctx.input(|i| i.key_pressed(egui::Key::Enter)) {
+            return Some(self.screen.bounds());
+        }
+
+        let (width, height) = (self.screen.width, self.screen.height);
+
+        let texture = self
+            .texture
+            .get_or_insert_with(|| screen_texture(ctx, &self.screen));
+
+        ctx.set_cursor_icon(egui::CursorIcon::Crosshair);
+
+        let mut answer = None;
+
+        egui::CentralPanel::default()
+            .frame(egui::Frame::none())
+            .show(ctx, |ui| {
+                let rect = ui.max_rect();
+
+                let response = ui.allocate_rect(rect, egui::Sense::click_and_drag());
+
+                let painter = ui.painter();
+
+                // The still may be scaled to the window, e.g. on HiDPI outputs.
+                let scale = egui::vec2(
+                    rect.width() / width as f32,
+                    rect.height() / height as f32,
+                );
+
+                let to_pixel = |pos: egui::Pos2| {
+                    let offset = pos - rect.min;
+
+                    (
+                        ((offset.x / scale.x) as u32).min(width),
+                        ((offset.y / scale.y) as u32).min(height),
+                    )
+                };
+
+                let whole = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+
+                painter.image(texture.id(), rect, whole, egui::Color32::WHITE);
+                painter.rect_filled(rect, 0.0, egui::Color32::from_black_alpha(128));
+
+                let pointer = response.hover_pos().or(response.interact_pointer_pos());
+
+                let origin = ctx.input(|i| i.pointer.press_origin());
+
+                let dragged = match (origin, pointer) {
+                    (Some(origin), Some(pointer)) if response.dragged() || response.drag_released() => {
+                        let ((x0, y0), (x1, y1)) = (to_pixel(origin), to_pixel(pointer));
+
+                        Some(Rect {
+                            x: x0.min(x1),
+                            y: y0.min(y1),
+                            width: x0.abs_diff(x1),
+                            height: y0.abs_diff(y1),
+                        })
+                    }
+
+                    _ => None,
+                };
+
+                let hovered = pointer.and_then(|pointer| {
+                    let (x, y) = to_pixel(pointer);
+
+                    self.windows.iter().find(|window| window.contains(x, y)).copied()
+                });
+
+                if let Some(selection) = dragged.or(hovered) {
+                    let area = egui::Rect::from_min_size(
+                        rect.min + egui::vec2(selection.x as f32, selection.y as f32) * scale,
+                        egui::vec2(selection.width as f32, selection.height as f32) * scale,
+                    );
+
+                    let uv = egui::Rect::from_min_size(
+                        egui::pos2(
+                            selection.x as f32 / width as f32,
+                            selection.y as f32 / height as f32,
+                        ),
+                        egui::vec2(
+                            selection.width as f32 / width as f32,
+                            selection.height as f32 / height as f32,
+                        ),
+                    );
+
+                    // The selection is drawn again undimmed, so it stands out.
+                    painter.image(texture.id(), area, uv, egui::Color32::WHITE);
+                    painter.rect_stroke(area, 0.0, egui::Stroke::new(2.0, egui::Color32::WHITE));
+                }
+
+                painter.text(
+                    rect.center_top() + egui::vec2(0.0, 32.0),
+                    egui::Align2::CENTER_TOP,
+                    format!(
+                        "{}\nDrag to select an area, click a window, or press Enter for the whole screen.",
+                        self.message.description
+                    ),
+                    egui::FontId::proportional(18.0),
+                    egui::Color32::WHITE,
+                );
+
+                if response.drag_released() {
+                    answer = dragged.filter(|area| area.width > 0 && area.height > 0);
+                } else if response.clicked() {
+                    answer = Some(hovered.unwrap_or_else(|| self.screen.bounds()));
+                }
+            });
+
+        answer
+    }
+}
+
+/// Load a still of the screen into a texture.
+fn screen_texture(ctx: &egui::Context, screen: &Image) -> egui::TextureHandle {
+    let size = [screen.width as usize, screen.height as usize];
+
+    let image = egui::ColorImage::from_rgba_unmultiplied(size, &screen.pixels);
+
+    // Magnified pixels stay sharp squares.
+    ctx.load_texture("screen", image, egui::TextureOptions::NEAREST)
+}
+
+/// `SourceWindow` lists monitors and windows with live previews and lets the user pick one,
+/// or several if the request allows it, answering their indices if it's accepted.
+struct SourceWindow {
+    request: SourceRequest,
+    selected: Vec<bool>,
+
+    /// A texture of each source, once the first picture arrived.
+    previews: Vec<egui::TextureHandle>,
+
+    /// The picture being taken in the background, if any.
+    refresh: Option<mpsc::Receiver<Image>>,
+
+    /// When the last picture was asked for.
+    refreshed: Option<Instant>,
+}
+
+impl SourceWindow {
+    /// Take a picture of the screen in the background every `PREVIEW_INTERVAL`,
+    /// updating the previews once it arrives.
+    fn refresh(&mut self, ctx: &egui::Context) {
+        if let Some(Ok(screen)) = self.refresh.as_ref().map(mpsc::Receiver::try_recv) {
+            self.refresh = None;
+
+            let previews = self.request.sources.iter().map(|source| {
+                // A virtual display has nothing to show yet, so it gets a transparent pixel.
+                let part = match source {
+                    Source::Virtual => Image {
+                        width: 1,
+                        height: 1,
+                        pixels: vec![0; 4],
+                    },
+
+                    source => screen.crop(&source.area()),
+                };
+
+                let size = [part.width as usize, part.height as usize];
+
+                egui::ColorImage::from_rgba_unmultiplied(size, &part.pixels)
+            });
+
+            match self.previews.is_empty() {
+                true => {
+                    self.previews = previews
+                        .map(|image| ctx.load_texture("source", image, Default::default()))
+                        .collect();
+                }
+
+                false => {
+                    for (texture, image) in self.previews.iter_mut().zip(previews) {
+                        texture.set(image, Default::default());
+                    }
+                }
+            }
+        }
+
+        let due = self
+            .refreshed
+            .is_none_or(|refreshed| refreshed.elapsed() >= PREVIEW_INTERVAL);
+
+        if self.refresh.is_none() && due {
+            let (sender, receiver) = mpsc::channel();
+
+            let capture = self.request.capture.clone();
+
+            let ctx = ctx.clone();
+
+            std::thread::spawn(move || match capture.capture(false) {
+                Ok(screen) => {
+                    let _ = sender.send(screen);
+                    ctx.request_repaint();
+                }
+
+                Err(e) => log::warn!("failed to capture a preview: {}", e),
+            });
+
+            self.refresh = Some(receiver);
+            self.refreshed = Some(Instant::now());
+        }
+
+        ctx.request_repaint_after(PREVIEW_INTERVAL);
+    }
+
+    /// Draw the preview of the source at `index`, or a placeholder until it arrived.
+    fn preview_ui(&self, ui: &mut egui::Ui, index: usize) {
+        let area = match self.request.sources[index] {
+            // Virtual displays are laid out like a common monitor.
+            Source::Virtual => Rect {
+                width: 16,
+                height: 9,
+                ..Rect::default()
+            },
+
+            ref source => source.area(),
+        };
+
+        let height = PREVIEW_WIDTH * area.height as f32 / area.width.max(1) as f32;
+
+        let size = egui::vec2(PREVIEW_WIDTH, height);
+
+        match self.previews.get(index) {
+            Some(texture) => {
+                ui.add(egui::Image::new((texture.id(), size)));
+            }
+
+            None => {
+                let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+                ui.painter()
+                    .rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+            }
+        }
+    }
+}
+
+impl Window for SourceWindow {
+    type Output = Option<Vec<usize>>;
+
+    fn ui(&mut self, ctx: &egui::Context) -> Option<Option<Vec<usize>>> {
+        let mut answer = None;
+
+        self.refresh(ctx);
+
+        let picked: Vec<usize> = (0..self.selected.len())
+            .filter(|&i| self.selected[i])
+            .collect();
+
+        egui::TopBottomPanel::bottom("buttons").show(ctx, |ui| {
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                let share = ui.add_enabled(!picked.is_empty(), egui::Button::new("Share"));
+
+                if share.clicked() {
+                    answer = Some(Some(picked.clone()));
+                }
+
+                if ui.button("Cancel").clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                    answer = Some(None);
+                }
+            });
+        });
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.label(&self.request.description);
+
+            ui.add_space(8.0);
+
+            let sources = &self.request.sources;
+
+            // Headings only help telling monitors from windows if both are listed.
+            let mixed = sources
+                .windows(2)
+                .any(|pair| pair[0].source_type() != pair[1].source_type());
+
+            let mut clicked = None;
+
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                for (i, source) in sources.iter().enumerate() {
+                    let first_of_type =
+                        i == 0 || sources[i - 1].source_type() != source.source_type();
+
+                    if mixed && first_of_type {
+                        match source {
+                            Source::Monitor(_) | Source::Region(_) => ui.heading("Monitors"),
+                            Source::Window(_) => ui.heading("Windows"),
+                            Source::Virtual => ui.heading("Virtual displays"),
+                        };
+                    }
+
+                    let row = ui.horizontal(|ui| {
+                        self.preview_ui(ui, i);
+
+                        ui.add(egui::SelectableLabel::new(self.selected[i], source.label()))
+                    });
+
+                    let label = row.inner;
+
+                    if label.double_clicked() && !self.request.multiple {
+                        answer = Some(Some(vec![i]));
+                    } else if label.clicked() {
+                        clicked = Some(i);
+                    }
+                }
+            });
+
+            if let Some(i) = clicked {
+                match self.request.multiple {
+                    true => self.selected[i] = !self.selected[i],
+                    false => self.selected = (0..self.selected.len()).map(|j| j == i).collect(),
+                }
+            }
+        });
+
+        answer
+    }
+}
+
+/// `AppWindow` lists applications with their icons and lets the user pick one.
+struct AppWindow {
+    description: String,
+
+    /// The list as the caller keeps it, polled for updates.
+    source: AppChoices,
+    revision: usize,
+    choices: Vec<DesktopEntry>,
+    selected: usize,
+    icons: icons::Icons,
+
+    /// The state of the "Always use this application" checkbox, if it's offered.
+    always: Option<bool>,
+
+    /// Whether applications outside `choices` may be picked.
+    offer_other: bool,
+
+    /// The installed applications, once the user asked for another one.
+    other: Option<OtherApps>,
+}
+
+/// `OtherApps` lists every installed application, with a search and a custom command.
+struct OtherApps {
+    apps: Vec<DesktopEntry>,
+    query: String,
+    command: String,
+}
+
+impl AppWindow {
+    /// Draw the listed applications, returning the index of a double-clicked one.
+    fn list_ui(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) -> Option<usize> {
+        let mut activated = None;
+
+        let query = self.other.as_ref().map(|other| other.query.to_lowercase());
+
+        let apps = match &self.other {
+            Some(other) => &other.apps,
+            None => &self.choices,
+        };
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for (i, app) in apps.iter().enumerate() {
+                if query
+                    .as_ref()
+                    .is_some_and(|query| !app.name.to_lowercase().contains(query))
+                {
+                    continue;
+                }
+
+                let row = ui.horizontal(|ui| {
+                    let icon = app
+                        .icon
+                        .as_deref()
+                        .and_then(|icon| self.icons.get(ctx, icon));
+
+                    match icon {
+                        Some(icon) => {
+                            ui.add(egui::Image::new((icon.id(), egui::vec2(32.0, 32.0))));
+                        }
+
+                        None => ui.add_space(32.0),
+                    }
+
+                    ui.add(egui::SelectableLabel::new(i == self.selected, &app.name))
+                });
+
+                let label = match &app.comment {
+                    Some(comment) => row.inner.on_hover_text(comment),
+                    None => row.inner,
+                };
+
+                if label.double_clicked() {
+                    activated = Some(i);
+                } else if label.clicked() {
+                    self.selected = i;
+                }
+            }
+        });
+
+        activated
+    }
+
+    /// Get the desktop file id of the listed application at `index`.
+    fn id(&self, index: usize) -> Option<String> {
+        let apps = match &self.other {
+            Some(other) => &other.apps,
+            None => &self.choices,
+        };
+
+        Some(apps.get(index)?.id.clone())
+    }
+
+    /// Save the custom command as a desktop file, so it's launched like any application.
+    fn command_id(&self) -> Option<String> {
+        let command = self.other.as_ref()?.command.trim();
+
+        match crate::desktop::create(command) {
+            Ok(entry) => Some(entry.id),
+
+            Err(e) => {
+                log::error!("failed to add an application for {:?}: {}", command, e);
+                None
+            }
+        }
+    }
+}
+
+impl Window for AppWindow {
+    type Output = AppResponse;
+
+    fn ui(&mut self, ctx: &egui::Context) -> Option<AppResponse> {
+        let mut answer = None;
+
+        if self.source.revision() != self.revision {
+            let selected = self
+                .choices
+                .get(self.selected)
+                .map(|choice| choice.id.clone());
+
+            self.revision = self.source.revision();
+            self.choices = self.source.get();
+
+            // Keep the selected application selected if it's still listed.
+            if self.other.is_none() {
+                self.selected = self
+                    .choices
+                    .iter()
+                    .position(|choice| Some(&choice.id) == selected.as_ref())
+                    .unwrap_or_default();
+            }
+        }
+
+        // Updates arrive from another thread, so look for them even without input.
+        ctx.request_repaint_after(std::time::Duration::from_millis(500));
+
+        egui::TopBottomPanel::bottom("buttons").show(ctx, |ui| {
+            if let Some(other) = &mut self.other {
+                let used = ui
+                    .horizontal(|ui| {
+                        ui.label("Command:");
+
+                        let command = ui.text_edit_singleline(&mut other.command);
+
+                        let entered =
+                            command.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+
+                        let usable = !other.command.trim().is_empty();
+
+                        let clicked = ui
+                            .add_enabled(usable, egui::Button::new("Use Command"))
+                            .clicked();
+
+                        clicked || entered && usable
+                    })
+                    .inner;
+
+                if used {
+                    answer = Some(self.command_id());
+                }
+            }
+
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                if ui.button("Open").clicked() {
+                    answer = Some(self.id(self.selected));
+                }
+
+                if ui.button("Cancel").clicked() {
+                    answer = Some(None);
+                }
+
+                if let Some(always) = &mut self.always {
+                    ui.checkbox(always, "Always use this application");
+                }
+
+                let browse = self.offer_other && self.other.is_none();
+
+                if browse && ui.button("Other Application…").clicked() {
+                    self.other = Some(OtherApps {
+                        apps: crate::desktop::all(),
+                        query: String::new(),
+                        command: String::new(),
+                    });
+
+                    self.selected = 0;
+                }
+            });
+        });
+
+        egui::TopBottomPanel::top("description").show(ctx, |ui| {
+            ui.label(&self.description);
+
+            if let Some(other) = &mut self.other {
+                ui.add(
+                    egui::TextEdit::singleline(&mut other.query)
+                        .hint_text("Search applications")
+                        .desired_width(f32::INFINITY),
+                );
+            }
+        });
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            if let Some(i) = self.list_ui(ctx, ui) {
+                answer = Some(self.id(i));
+            }
+        });
+
+        // In the full list, Enter belongs to the search and command fields.
+        if self.other.is_none() && ctx.input(|i| i.key_pressed(egui::Key::Enter)) {
+            answer = Some(self.id(self.selected));
+        }
+
+        // Cancelling closes the window without an answer, like closing it does.
+        match answer? {
+            Some(id) => Some(AppResponse {
+                id,
+                always: self.always.unwrap_or_default(),
+            }),
+
+            None => {
+                ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                None
+            }
+        }
+    }
+}
+
+/// `Mode` is what a file browser picks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Open,
+    Save,
+    Folder,
+}
+
+/// `Entry` is a file or directory listed in a file browser.
+struct Entry {
+    path: PathBuf,
+    name: String,
+    is_dir: bool,
+}
+
+/// `Finder` is the state of a file browser's fuzzy finder mode.
+struct Finder {
+    query: String,
+    candidates: Vec<fuzzy::Candidate>,
+
+    /// Indices of the best matching candidates, best first.
+    matches: Vec<usize>,
+
+    /// The highlighted row of `matches`.
+    cursor: usize,
+}
+
+/// `FileBrowser` lets the user pick files or folders, with filters and choices.
+struct FileBrowser {
+    request: FileRequest,
+    mode: Mode,
+    directory: PathBuf,
+    location: String,
+    entries: Vec<Entry>,
+    selected: Vec<usize>,
+    file_name: String,
+    filter: usize,
+    extensions: Option<Vec<String>>,
+    choices: Vec<String>,
+    places: Vec<places::Place>,
+
+    /// Whether the recently used files are listed instead of a directory.
+    recent: bool,
+    show_hidden: bool,
+    finder: Option<Finder>,
+    error: Option<String>,
+}
+
+impl FileBrowser {
+    /// Create a file browser for the request, starting in its folder or the home directory.
+    fn new(request: FileRequest, mode: Mode, fuzzy_finder: bool) -> Self {
+        let directory = request
+            .folder
+            .clone()
+            .or_else(dirs::home_dir)
+            .unwrap_or_else(|| PathBuf::from("/"));
+
+        let choices = initial_choices(&request.choices);
+
+        let mut browser = Self {
+            file_name: request.file_name.clone().unwrap_or_default(),
+            extensions: request
+                .filters
+                .first()
+                .map(|(_, patterns)| filter::extensions(patterns)),
+            request,
+            mode,
+            directory: PathBuf::new(),
+            location: String::new(),
+            entries: Vec::new(),
+            selected: Vec::new(),
+            filter: 0,
+            choices,
+            places: places::places(),
+            recent: false,
+            show_hidden: state::show_hidden(),
+            finder: None,
+            error: None,
+        };
+
+        browser.open(directory);
+
+        if fuzzy_finder {
+            browser.toggle_finder();
+        }
+
+        browser
+    }
+
+    /// Whether the browser picks directories rather than files.
+    fn picks_directories(&self) -> bool {
+        self.mode == Mode::Folder || (self.mode == Mode::Open && self.request.directory)
+    }
+
+    /// Change to the given directory and list its entries.
+    fn open(&mut self, directory: PathBuf) {
+        let read_dir = match std::fs::read_dir(&directory) {
+            Ok(read_dir) => read_dir,
+
+            Err(e) => {
+                self.error = Some(format!("{}: {}", directory.display(), e));
+                return;
+            }
+        };
+
+        let mut entries: Vec<Entry> = read_dir
+            .filter_map(Result::ok)
+            .map(|entry| Entry {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                is_dir: entry.path().is_dir(),
+                path: entry.path(),
+            })
+            .filter(|entry| self.show_hidden || !entry.name.starts_with('.'))
+            .collect();
+
+        entries.sort_by(|a, b| {
+            b.is_dir
+                .cmp(&a.is_dir)
+                .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+        });
+
+        self.location = directory.display().to_string();
+        self.directory = directory;
+        self.entries = entries;
+        self.selected.clear();
+        self.recent = false;
+        self.error = None;
+    }
+
+    /// List the recently used files, or the folders they're in when picking folders.
+    fn open_recent(&mut self) {
+        let mut entries: Vec<Entry> = Vec::new();
+
+        for path in crate::recent::files() {
+            let path = match self.picks_directories() && !path.is_dir() {
+                true => match path.parent() {
+                    Some(parent) => parent.to_path_buf(),
+                    None => continue,
+                },
+                false => path,
+            };
+
+            if entries.iter().any(|entry| entry.path == path) {
+                continue;
+            }
+
+            entries.push(Entry {
+                name: path.display().to_string(),
+                is_dir: path.is_dir(),
+                path,
+            });
+        }
+
+        self.location = String::from("Recent");
+        self.entries = entries;
+        self.selected.clear();
+        self.recent = true;
+        self.error = None;
+    }
+
+    /// List the current directory or the recent files again.
+    fn reload(&mut self) {
+        match self.recent {
+            true => self.open_recent(),
+            false => self.open(self.directory.clone()),
+        }
+    }
+
+    /// Whether an entry with the given name is listed under the active filter.
+    fn visible(&self, name: &str, is_dir: bool) -> bool {
+        if is_dir {
+            return true;
+        }
+
+        if self.picks_directories() {
+            return false;
+        }
+
+        let Some(extensions) = &self.extensions else {
+            return true;
+        };
+
+        extensions.iter().any(|extension| {
+            extension == "*"
+                || Path::new(name)
+                    .extension()
+                    .is_some_and(|e| e.eq_ignore_ascii_case(extension))
+        })
+    }
+
+    /// Select an entry, toggling it into a multiple selection when ctrl is held.
+    fn select(&mut self, index: usize, toggle: bool) {
+        let entry = &self.entries[index];
+
+        if self.mode == Mode::Save && !entry.is_dir {
+            // Recent files are listed by path, so saving over one saves next to it.
+            if let (Some(parent), Some(name)) = (entry.path.parent(), entry.path.file_name()) {
+                self.directory = parent.to_path_buf();
+                self.file_name = name.to_string_lossy().into_owned();
+            }
+        }
+
+        if toggle && self.request.multiple {
+            match self.selected.iter().position(|&i| i == index) {
+                Some(position) => {
+                    self.selected.remove(position);
+                }
+
+                None => self.selected.push(index),
+            }
+        } else {
+            self.selected = vec![index];
+        }
+    }
+
+    /// Activate an entry: enter directories and accept files.
+    fn activate(&mut self, index: usize) -> Option<FileResponse> {
+        if self.entries[index].is_dir {
+            self.open(self.entries[index].path.clone());
+            return None;
+        }
+
+        self.select(index, false);
+        self.accept()
+    }
+
+    /// Build the response for the current selection, if it's acceptable.
+    fn accept(&mut self) -> Option<FileResponse> {
+        let paths: Vec<PathBuf> = match self.mode {
+            Mode::Save if self.file_name.is_empty() => return None,
+
+            Mode::Save => vec![self.directory.join(&self.file_name)],
+
+            _ if self.picks_directories() => {
+                let selected: Vec<PathBuf> = self
+                    .selected
+                    .iter()
+                    .map(|&i| &self.entries[i])
+                    .filter(|entry| entry.is_dir)
+                    .map(|entry| entry.path.clone())
+                    .collect();
+
+                match selected.is_empty() {
+                    true => vec![self.directory.clone()],
+                    false => selected,
+                }
+            }
+
+            _ => self
+                .selected
+                .iter()
+                .map(|&i| &self.entries[i])
+                .filter(|entry| !entry.is_dir)
+                .map(|entry| entry.path.clone())
+                .collect(),
+        };
+
+        if paths.is_empty() {
+            return None;
+        }
+
+        let choices = (!self.request.choices.is_empty()).then(|| {
+            self.request
+                .choices
+                .iter()
+                .map(|(id, _, _, _)| id.clone())
+                .zip(self.choices.iter().cloned())
+                .collect()
+        });
+
+        Some(FileResponse {
+            paths,
+            current_filter: self.request.filters.get(self.filter).cloned(),
+            choices,
+        })
+    }
+
+    /// Switch between browsing directories and the fuzzy finder, indexing the current directory.
+    fn toggle_finder(&mut self) {
+        if self.finder.take().is_some() {
+            return;
+        }
+
+        self.finder = Some(Finder {
+            query: String::new(),
+            candidates: fuzzy::index(&self.directory, self.show_hidden),
+            matches: Vec::new(),
+            cursor: 0,
+        });
+
+        self.refine();
+    }
+
+    /// Rank the finder's candidates against its query.
+    fn refine(&mut self) {
+        let Some(finder) = &self.finder else {
+            return;
+        };
+
+        let mut scored: Vec<(i32, usize)> = finder
+            .candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, candidate)| self.visible(&candidate.relative, candidate.is_dir))
+            .filter_map(|(i, candidate)| {
+                Some((fuzzy::score(&finder.query, &candidate.relative)?, i))
+            })
+            .collect();
+
+        scored.sort_by_key(|&(score, i)| (std::cmp::Reverse(score), i));
+
+        let matches = scored
+            .into_iter()
+            .take(MAX_MATCHES)
+            .map(|(_, i)| i)
+            .collect();
+
+        if let Some(finder) = &mut self.finder {
+            finder.matches = matches;
+            finder.cursor = 0;
+        }
+    }
+
+    /// Leave the finder at a candidate, accepting it if that's all there's left to do.
+    ///
+    /// Directories are entered, and files are selected in the directory they're in.
+    fn pick(&mut self, index: usize) -> Option<FileResponse> {
+        let finder = self.finder.take()?;
+
+        let candidate = finder.candidates.get(index)?;
+
+        if candidate.is_dir {
+            self.open(candidate.path.clone());
+
+            return match self.picks_directories() {
+                true => self.accept(),
+                false => None,
+            };
+        }
+
+        self.open(candidate.path.parent()?.to_path_buf());
+
+        let position = self
+            .entries
+            .iter()
+            .position(|entry| entry.path == candidate.path)?;
+
+        self.select(position, false);
+
+        match self.mode {
+            Mode::Open => self.accept(),
+            _ => None,
+        }
+    }
+
+    /// Draw the fuzzy finder, returning a response if a pick was accepted.
+    fn finder_ui(&mut self, ui: &mut egui::Ui) -> Option<FileResponse> {
+        let finder = self.finder.as_mut()?;
+
+        let query = ui.add(
+            egui::TextEdit::singleline(&mut finder.query)
+                .hint_text("Type to find files")
+                .desired_width(f32::INFINITY),
+        );
+
+        query.request_focus();
+
+        let (up, down, enter, escape) = ui.input(|i| {
+            (
+                i.key_pressed(egui::Key::ArrowUp),
+                i.key_pressed(egui::Key::ArrowDown),
+                i.key_pressed(egui::Key::Enter),
+                i.key_pressed(egui::Key::Escape),
+            )
+        });
+
+        if up {
+            finder.cursor = finder.cursor.saturating_sub(1);
+        }
+
+        if down && finder.cursor + 1 < finder.matches.len() {
+            finder.cursor += 1;
+        }
+
+        let mut picked = match enter {
+            true => finder.matches.get(finder.cursor).copied(),
+            false => None,
+        };
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for (row, &i) in finder.matches.iter().enumerate() {
+                let candidate = &finder.candidates[i];
+
+                let name = match candidate.is_dir {
+                    true => format!("🗀 {}", candidate.relative),
+                    false => format!("🗋 {}", candidate.relative),
+                };
+
+                let label = ui.add(egui::SelectableLabel::new(row == finder.cursor, name));
+
+                if row == finder.cursor && (up || down) {
+                    label.scroll_to_me(None);
+                }
+
+                if label.double_clicked() {
+                    picked = Some(i);
+                } else if label.clicked() {
+                    finder.cursor = row;
+                }
+            }
+        });
+
+        if escape {
+            self.finder = None;
+            return None;
+        }
+
+        if query.changed() {
+            self.refine();
+        }
+
+        self.pick(picked?)
+    }
+
+    /// Draw the filter and choices widgets.
+    fn options_ui(&mut self, ui: &mut egui::Ui) {
+        if !self.request.filters.is_empty() && !self.picks_directories() {
+            let current = self.filter;
+
+            egui::ComboBox::from_id_source("filter")
+                .selected_text(&self.request.filters[current].0)
+                .show_ui(ui, |ui| {
+                    for (i, (name, _)) in self.request.filters.iter().enumerate() {
+                        ui.selectable_value(&mut self.filter, i, name);
+                    }
+                });
+
+            if self.filter != current {
+                self.extensions = Some(filter::extensions(&self.request.filters[self.filter].1));
+                self.selected.clear();
+            }
+        }
+
+        choices_ui(ui, &self.request.choices, &mut self.choices);
+    }
+}
+
+/// The initial selection of each of `choices`, or its first option, or unchecked.
+fn initial_choices(choices: &[Choice]) -> Vec<String> {
+    choices
+        .iter()
+        .map(
+            |(_, _, options, initial)| match (options.first(), initial) {
+                (None, initial) if initial.is_empty() => String::from("false"),
+                (Some((id, _)), initial) if initial.is_empty() => id.clone(),
+                (_, initial) => initial.clone(),
+            },
+        )
+        .collect()
+}
+
+/// Draw `choices` as checkboxes and combo boxes, with their `selections`.
+fn choices_ui(ui: &mut egui::Ui, choices: &[Choice], selections: &mut [String]) {
+    for (i, (_, label, options, _)) in choices.iter().enumerate() {
+        let label = choices::strip_mnemonic(label);
+
+        if options.is_empty() {
+            let mut checked = selections[i] == "true";
+
+            ui.checkbox(&mut checked, label);
+
+            selections[i] = checked.to_string();
+        } else {
+            ui.label(label);
+
+            let selected = options
+                .iter()
+                .find(|(id, _)| *id == selections[i])
+                .map(|(_, label)| choices::strip_mnemonic(label))
+                .unwrap_or_default();
+
+            egui::ComboBox::from_id_source(("choice", i))
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    for (id, option_label) in options {
+                        let text = choices::strip_mnemonic(option_label);
+
+                        ui.selectable_value(&mut selections[i], id.clone(), text);
+                    }
+                });
+        }
+    }
+}
+
+impl Window for FileBrowser {
+    type Output = Option<FileResponse>;
+
+    fn ui(&mut self, ctx: &egui::Context) -> Option<Option<FileResponse>> {
+        let mut answer = None;
+
+        if ctx.input(|i| i.modifiers.command && i.key_pressed(egui::Key::F)) {
+            self.toggle_finder();
+        }
+
+        egui::TopBottomPanel::top("location").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let finding = self.finder.is_some();
+
+                if ui
+                    .selectable_label(finding, "🔍")
+                    .on_hover_text("Find files (Ctrl+F)")
+                    .clicked()
+                {
+                    self.toggle_finder();
+                }
+
+                if ui.button("⬆").on_hover_text("Parent folder").clicked() {
+                    if let Some(parent) = self.directory.parent() {
+                        self.open(parent.to_path_buf());
+                    }
+                }
+
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    if ui.checkbox(&mut self.show_hidden, "Hidden files").changed() {
+                        state::set_show_hidden(self.show_hidden);
+                        self.reload();
+                    }
+
+                    let location = ui.add(
+                        egui::TextEdit::singleline(&mut self.location).desired_width(f32::INFINITY),
+                    );
+
+                    if location.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                        self.open(PathBuf::from(&self.location));
+                    }
+                });
+            });
+        });
+
+        egui::TopBottomPanel::bottom("options").show(ctx, |ui| {
+            if let Some(error) = &self.error {
+                ui.colored_label(ui.visuals().error_fg_color, error);
+            }
+
+            if self.mode == Mode::Save {
+                ui.horizontal(|ui| {
+                    ui.label("Name:");
+
+                    let name = ui.text_edit_singleline(&mut self.file_name);
+
+                    if name.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                        answer = self.accept().map(Some);
+                    }
+                });
+            }
+
+            ui.horizontal_wrapped(|ui| self.options_ui(ui));
+
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                let default_label = match self.mode {
+                    Mode::Open => "Open",
+                    Mode::Save => "Save",
+                    Mode::Folder => "Select",
+                };
+
+                let accept_label = self
+                    .request
+                    .accept_label
+                    .as_deref()
+                    .unwrap_or(default_label);
+
+                if ui.button(accept_label).clicked() {
+                    answer = self.accept().map(Some);
+                }
+
+                if ui.button("Cancel").clicked() {
+                    answer = Some(None);
+                }
+            });
+        });
+
+        egui::SidePanel::left("places").show(ctx, |ui| {
+            let mut opened = None;
+            let mut recent = false;
+
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                if ui
+                    .add(egui::SelectableLabel::new(self.recent, "Recent"))
+                    .clicked()
+                {
+                    recent = true;
+                }
+
+                for place in &self.places {
+                    let current = !self.recent && place.path == self.directory;
+
+                    let row = ui
+                        .add(egui::SelectableLabel::new(current, &place.name))
+                        .on_hover_text(place.path.display().to_string());
+
+                    if row.clicked() {
+                        opened = Some(place.path.clone());
+                    }
+                }
+            });
+
+            if recent {
+                self.open_recent();
+            }
+
+            if let Some(path) = opened {
+                self.open(path);
+            }
+        });
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            if self.finder.is_some() {
+                if let Some(response) = self.finder_ui(ui) {
+                    answer = Some(Some(response));
+                }
+
+                return;
+            }
+
+            let toggle = ui.input(|i| i.modifiers.command);
+
+            let mut clicked = None;
+            let mut activated = None;
+
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                for (i, entry) in self.entries.iter().enumerate() {
+                    if !self.visible(&entry.name, entry.is_dir) {
+                        continue;
+                    }
+
+                    let name = match entry.is_dir {
+                        true => format!("🗀 {}", entry.name),
+                        false => format!("🗋 {}", entry.name),
+                    };
+
+                    let row = ui.add(egui::SelectableLabel::new(self.selected.contains(&i), name));
+
+                    if row.double_clicked() {
+                        activated = Some(i);
+                    } else if row.clicked() {
+                        clicked = Some(i);
+                    }
+                }
+            });
+
+            if let Some(i) = clicked {
+                self.select(i, toggle);
+            }
+
+            if let Some(i) = activated {
+                if let Some(response) = self.activate(i) {
+                    answer = Some(Some(response));
+                }
+            }
+        });
+
+        answer
+    }
+}
@@ -0,0 +1,161 @@
+use std::path::{Path, PathBuf};
+
+/// The deepest a directory tree is indexed, relative to where the finder was opened.
+const MAX_DEPTH: usize = 8;
+
+/// The most entries indexed, so opening the finder in `/` doesn't hang the dialog.
+const MAX_ENTRIES: usize = 20_000;
+
+/// `Candidate` is an indexed file or directory the finder can match.
+pub struct Candidate {
+    pub path: PathBuf,
+
+    /// The path relative to the indexed directory, which is what queries match against.
+    pub relative: String,
+
+    pub is_dir: bool,
+}
+
+/// Index the tree under `root` breadth-first, so shallow entries survive the entry limit.
+///
+/// Symlinked directories are listed but not entered, which keeps loops out of the index.
+pub fn index(root: &Path, show_hidden: bool) -> Vec<Candidate> {
+    let mut candidates = Vec::new();
+    let mut queue = std::collections::VecDeque::from([(root.to_path_buf(), 0)]);
+
+    while let Some((directory, depth)) = queue.pop_front() {
+        let Ok(read_dir) = std::fs::read_dir(&directory) else {
+            continue;
+        };
+
+        for entry in read_dir.filter_map(Result::ok) {
+            if candidates.len() >= MAX_ENTRIES {
+                log::debug!("stopped indexing {:?} at {} entries", root, MAX_ENTRIES);
+                return candidates;
+            }
+
+            let name = entry.file_name();
+
+            if !show_hidden && name.to_string_lossy().starts_with('.') {
+                continue;
+            }
+
+            let path = entry.path();
+            let is_dir = path.is_dir();
+
+            let is_symlink = entry.file_type().is_ok_and(|kind| kind.is_symlink());
+
+            if is_dir && !is_symlink && depth < MAX_DEPTH {
+                queue.push_back((path.clone(), depth + 1));
+            }
+
+            let relative = path
+                .strip_prefix(root)
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .into_owned();
+
+            candidates.push(Candidate {
+                path,
+                relative,
+                is_dir,
+            });
+        }
+    }
+
+    candidates
+}
+
+/// Score how well `candidate` matches `query`, fzf style, or `None` if it doesn't match.
+///
+/// Every query character has to appear in order. Matches right after a separator or one
+/// after another score higher, and gaps and long paths score lower.
+/// The match is case-insensitive unless the query contains an uppercase letter.
+pub fn score(query: &str, candidate: &str) -> Option<i32> {
+    let case_sensitive = query.chars().any(char::is_uppercase);
+
+    let fold = |c: char| match case_sensitive {
+        true => c,
+        false => c.to_ascii_lowercase(),
+    };
+
+    let basename_start = candidate.rfind('/').map_or(0, |i| i + 1);
+
+    let mut query = query.chars().map(fold).peekable();
+    let mut score = 0;
+    let mut previous: Option<char> = None;
+    let mut last_match: Option<usize> = None;
+
+    for (i, c) in candidate.char_indices() {
+        let Some(&wanted) = query.peek() else {
+            break;
+        };
+
+        if fold(c) == wanted {
+            query.next();
+
+            score += 16;
+
+            match last_match {
+                Some(last) if last + previous.map_or(1, char::len_utf8) == i => score += 24,
+                Some(last) => score -= ((i - last) as i32).min(16),
+                None => {}
+            }
+
+            let boundary = previous.is_none_or(|p| {
+                matches!(p, '/' | '_' | '-' | '.' | ' ') || (p.is_lowercase() && c.is_uppercase())
+            });
+
+            if boundary {
+                score += 20;
+            }
+
+            if i >= basename_start {
+                score += 8;
+            }
+
+            last_match = Some(i);
+        }
+
+        previous = Some(c);
+    }
+
+    if query.peek().is_some() {
+        return None;
+    }
+
+    Some(score - candidate.len() as i32 / 4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::score;
+
+    #[test]
+    fn subsequence() {
+        assert!(score("rdme", "README.md").is_some());
+        assert!(score("xyz", "README.md").is_none());
+        assert!(score("", "README.md").is_some());
+    }
+
+    #[test]
+    fn smart_case() {
+        assert!(score("readme", "README.md").is_some());
+        assert!(score("Readme", "README.md").is_none());
+    }
+
+    #[test]
+    fn ranking() {
+        let query = "main";
+
+        let mut candidates = [
+            "src/domain/index.rs",
+            "src/main.rs",
+            "maintainers/big/list.txt",
+        ];
+
+        candidates.sort_by_key(|candidate| std::cmp::Reverse(score(query, candidate)));
+
+        assert_eq!(candidates[0], "src/main.rs");
+    }
+}
@@ -0,0 +1,65 @@
+use std::{collections::HashMap, path::Path};
+
+use eframe::egui;
+
+/// `Icons` loads application icons into textures on first use and keeps them for the window.
+#[derive(Default)]
+pub struct Icons {
+    textures: HashMap<String, Option<egui::TextureHandle>>,
+}
+
+impl Icons {
+    /// Get the texture of an icon, or `None` if it has no PNG file or can't be decoded.
+    pub fn get(&mut self, ctx: &egui::Context, icon: &str) -> Option<egui::TextureHandle> {
+        self.textures
+            .entry(icon.to_owned())
+            .or_insert_with(|| {
+                let path = crate::desktop::icon_path(icon)?;
+
+                let image = decode(&path)
+                    .map_err(|e| log::debug!("failed to decode icon {:?}: {}", path, e))
+                    .ok()?;
+
+                Some(ctx.load_texture(icon, image, egui::TextureOptions::LINEAR))
+            })
+            .clone()
+    }
+}
+
+/// Decode a PNG file into an RGBA image.
+fn decode(path: &Path) -> Result<egui::ColorImage, png::DecodingError> {
+    let mut decoder = png::Decoder::new(std::fs::File::open(path)?);
+
+    // Expand palettes and low bit depths, and strip 16-bit channels down to 8 bits.
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+
+    let mut reader = decoder.read_info()?;
+
+    let mut buffer = vec![0; reader.output_buffer_size()];
+
+    let info = reader.next_frame(&mut buffer)?;
+
+    let pixels = &buffer[..info.buffer_size()];
+
+    let rgba: Vec<u8> = match info.color_type {
+        png::ColorType::Rgba => pixels.to_vec(),
+
+        png::ColorType::Rgb => pixels
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], u8::MAX])
+            .collect(),
+
+        png::ColorType::GrayscaleAlpha => pixels
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+
+        png::ColorType::Grayscale | png::ColorType::Indexed => {
+            pixels.iter().flat_map(|&g| [g, g, g, u8::MAX]).collect()
+        }
+    };
+
+    let size = [info.width as usize, info.height as usize];
+
+    Ok(egui::ColorImage::from_rgba_unmultiplied(size, &rgba))
+}
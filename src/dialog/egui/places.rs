@@ -0,0 +1,119 @@
+use std::path::PathBuf;
+
+/// `Place` is a shortcut in the file browser's sidebar.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Place {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+impl Place {
+    fn new(name: impl Into<String>, path: PathBuf) -> Self {
+        Self {
+            name: name.into(),
+            path,
+        }
+    }
+}
+
+/// List the places shown in the sidebar, like GTK's file chooser does.
+///
+/// These are the home folder, the XDG user directories that exist, the user's GTK bookmarks,
+/// the remote locations mounted by GVFS and the root of the file system.
+pub fn places() -> Vec<Place> {
+    let mut places = Vec::new();
+
+    let home = dirs::home_dir();
+
+    if let Some(home) = &home {
+        places.push(Place::new("Home", home.clone()));
+    }
+
+    let user_dirs = [
+        ("Desktop", dirs::desktop_dir()),
+        ("Documents", dirs::document_dir()),
+        ("Downloads", dirs::download_dir()),
+        ("Music", dirs::audio_dir()),
+        ("Pictures", dirs::picture_dir()),
+        ("Videos", dirs::video_dir()),
+    ];
+
+    // Unset user dirs point at the home folder, which is already listed.
+    for (name, path) in user_dirs {
+        if let Some(path) = path.filter(|path| Some(path) != home.as_ref() && path.is_dir()) {
+            places.push(Place::new(name, path));
+        }
+    }
+
+    if let Some(path) = dirs::config_dir().map(|dir| dir.join("gtk-3.0").join("bookmarks")) {
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => places.extend(parse_bookmarks(&contents)),
+
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+
+            Err(e) => log::warn!("failed to read bookmarks {:?}: {}", path, e),
+        }
+    }
+
+    places.extend(
+        crate::gvfs::mounts()
+            .into_iter()
+            .map(|mount| Place::new(mount.name, mount.path)),
+    );
+
+    places.push(Place::new("File System", PathBuf::from("/")));
+
+    places
+}
+
+/// Parse a GTK bookmarks file, which has a `file://` URI and an optional label on each line.
+///
+/// Bookmarks of remote locations are skipped, as the browser only lists local directories.
+fn parse_bookmarks(contents: &str) -> Vec<Place> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let (uri, label) = match line.split_once(' ') {
+                Some((uri, label)) => (uri, Some(label.trim())),
+                None => (line.trim(), None),
+            };
+
+            let path = crate::uri::file_path(uri)?;
+
+            let name = match label.filter(|label| !label.is_empty()) {
+                Some(label) => label.to_owned(),
+                None => path.file_name().map_or_else(
+                    || path.display().to_string(),
+                    |name| name.to_string_lossy().into_owned(),
+                ),
+            };
+
+            Some(Place::new(name, path))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{parse_bookmarks, Place};
+
+    #[test]
+    fn bookmarks() {
+        let contents = "file:///home/user/Projects\n\
+                        file:///home/user/My%20Files Shared stuff\n\
+                        sftp://example.com/srv Server\n\
+                        \n\
+                        file://localhost/tmp\n";
+
+        assert_eq!(
+            parse_bookmarks(contents),
+            vec![
+                Place::new("Projects", PathBuf::from("/home/user/Projects")),
+                Place::new("Shared stuff", PathBuf::from("/home/user/My Files")),
+                Place::new("tmp", PathBuf::from("/tmp")),
+            ]
+        );
+    }
+}
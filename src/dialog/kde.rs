@@ -0,0 +1,295 @@
+use std::{
+    collections::HashMap,
+    os::unix::ffi::OsStrExt,
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use zbus::{dbus_proxy, zvariant};
+
+use super::{DialogProvider, FileRequest, FileResponse, Level, Message};
+use crate::{filter::Filter, uri};
+
+/// The bus name of xdg-desktop-portal-kde, which draws its dialogs with Qt.
+const SERVICE: &str = "org.freedesktop.impl.portal.desktop.kde";
+
+/// `Results` is the results vardict returned by a backend portal.
+type Results = HashMap<String, zvariant::OwnedValue>;
+
+/// The FileChooser interface of another portal backend.
+#[dbus_proxy(
+    interface = "org.freedesktop.impl.portal.FileChooser",
+    default_service = "org.freedesktop.impl.portal.desktop.kde",
+    default_path = "/org/freedesktop/portal/desktop"
+)]
+trait BackendFileChooser {
+    #[dbus_proxy(name = "OpenFile")]
+    fn open_file(
+        &self,
+        handle: &zvariant::ObjectPath<'_>,
+        app_id: &str,
+        parent_window: &str,
+        title: &str,
+        options: HashMap<&str, zvariant::Value<'_>>,
+    ) -> zbus::Result<(u32, Results)>;
+
+    #[dbus_proxy(name = "SaveFile")]
+    fn save_file(
+        &self,
+        handle: &zvariant::ObjectPath<'_>,
+        app_id: &str,
+        parent_window: &str,
+        title: &str,
+        options: HashMap<&str, zvariant::Value<'_>>,
+    ) -> zbus::Result<(u32, Results)>;
+}
+
+/// The Access interface of another portal backend, used for its question dialogs.
+#[dbus_proxy(
+    interface = "org.freedesktop.impl.portal.Access",
+    default_service = "org.freedesktop.impl.portal.desktop.kde",
+    default_path = "/org/freedesktop/portal/desktop"
+)]
+trait BackendAccess {
+    #[allow(clippy::too_many_arguments)]
+    fn access_dialog(
+        &self,
+        handle: &zvariant::ObjectPath<'_>,
+        app_id: &str,
+        parent_window: &str,
+        title: &str,
+        subtitle: &str,
+        body: &str,
+        options: HashMap<&str, zvariant::Value<'_>>,
+    ) -> zbus::Result<(u32, Results)>;
+}
+
+/// `Kde` shows native Qt dialogs by handing requests to xdg-desktop-portal-kde.
+///
+/// The KDE backend is started through D-Bus activation when it isn't running,
+/// so it only has to be installed, not configured as the desktop's FileChooser.
+/// It renders filters and choices itself and reports the active ones.
+pub struct Kde {
+    runtime: tokio::runtime::Handle,
+    connection: tokio::sync::OnceCell<zbus::Connection>,
+    requests: AtomicU64,
+}
+
+impl Kde {
+    /// Create the provider; it must be called from within the tokio runtime.
+    pub fn new() -> Self {
+        Self {
+            runtime: tokio::runtime::Handle::current(),
+            connection: tokio::sync::OnceCell::new(),
+            requests: AtomicU64::new(0),
+        }
+    }
+
+    /// Get the session bus connection used to talk to the KDE backend.
+    async fn connection(&self) -> zbus::Result<&zbus::Connection> {
+        self.connection
+            .get_or_try_init(zbus::Connection::session)
+            .await
+    }
+
+    /// Get a fresh handle for a request to the KDE backend.
+    fn handle(&self) -> zvariant::OwnedObjectPath {
+        let n = self.requests.fetch_add(1, Ordering::Relaxed);
+
+        let path = format!("/org/freedesktop/portal/desktop/request/rs/kde{}", n);
+
+        zvariant::OwnedObjectPath::try_from(path).expect("request handles are valid paths")
+    }
+
+    /// Run a call to the KDE backend from a dialog thread, logging failures.
+    fn call<T>(&self, call: impl std::future::Future<Output = zbus::Result<T>>) -> Option<T> {
+        match self.runtime.block_on(call) {
+            Ok(output) => Some(output),
+
+            Err(e) => {
+                log::error!("failed to call {}: {}", SERVICE, e);
+                None
+            }
+        }
+    }
+
+    /// Show a FileChooser dialog of the KDE backend.
+    fn file(&self, request: &FileRequest, save: bool) -> Option<FileResponse> {
+        let handle = self.handle();
+        let parent_window = parent_window(request);
+        let options = file_options(request);
+
+        let (response, results) = self.call(async {
+            let proxy = BackendFileChooserProxy::new(self.connection().await?).await?;
+
+            match save {
+                true => {
+                    proxy
+                        .save_file(&handle, "", &parent_window, &request.title, options)
+                        .await
+                }
+
+                false => {
+                    proxy
+                        .open_file(&handle, "", &parent_window, &request.title, options)
+                        .await
+                }
+            }
+        })?;
+
+        if response != 0 {
+            return None;
+        }
+
+        let paths = match results.get("uris") {
+            Some(uris) => Vec::<String>::try_from(zvariant::Value::from(uris.clone()))
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|uri| uri::file_path(uri))
+                .collect(),
+
+            None => Vec::new(),
+        };
+
+        if paths.is_empty() {
+            return None;
+        }
+
+        Some(FileResponse {
+            paths,
+            current_filter: results
+                .get("current_filter")
+                .and_then(|value| Filter::try_from(zvariant::Value::from(value.clone())).ok()),
+            choices: results.get("choices").and_then(|value| {
+                Vec::<(String, String)>::try_from(zvariant::Value::from(value.clone())).ok()
+            }),
+        })
+    }
+
+    /// Ask a question with an AccessDialog of the KDE backend.
+    fn access(&self, message: &Message, grant_label: &str, deny_label: &str) -> bool {
+        let handle = self.handle();
+
+        let parent_window = message
+            .parent
+            .as_ref()
+            .map(ToString::to_string)
+            .unwrap_or_default();
+
+        let icon = match message.level {
+            Level::Info => "dialog-information",
+            Level::Warning => "dialog-warning",
+            Level::Error => "dialog-error",
+        };
+
+        let options = HashMap::from([
+            ("grant_label", zvariant::Value::from(grant_label)),
+            ("deny_label", zvariant::Value::from(deny_label)),
+            ("icon", zvariant::Value::from(icon)),
+        ]);
+
+        let answer = self.call(async {
+            BackendAccessProxy::new(self.connection().await?)
+                .await?
+                .access_dialog(
+                    &handle,
+                    "",
+                    &parent_window,
+                    &message.title,
+                    &message.description,
+                    "",
+                    options,
+                )
+                .await
+        });
+
+        matches!(answer, Some((0, _)))
+    }
+}
+
+impl DialogProvider for Kde {
+    fn open_file(&self, request: &FileRequest) -> Option<FileResponse> {
+        self.file(request, false)
+    }
+
+    fn save_file(&self, request: &FileRequest) -> Option<FileResponse> {
+        self.file(request, true)
+    }
+
+    fn pick_folder(&self, request: &FileRequest) -> Option<PathBuf> {
+        let request = FileRequest {
+            directory: true,
+            multiple: false,
+            ..request.clone()
+        };
+
+        self.file(&request, false)?.paths.into_iter().next()
+    }
+
+    fn confirm(&self, message: &Message) -> bool {
+        self.access(
+            message,
+            message.accept_label.as_deref().unwrap_or("OK"),
+            message.reject_label.as_deref().unwrap_or("Cancel"),
+        )
+    }
+
+    fn message(&self, message: &Message) {
+        // AccessDialog always has two buttons, so both just close the message.
+        let label = message.accept_label.as_deref().unwrap_or("OK");
+
+        self.access(message, label, "Close");
+    }
+}
+
+/// Format the request's parent window for the KDE backend.
+fn parent_window(request: &FileRequest) -> String {
+    request
+        .parent
+        .as_ref()
+        .map(ToString::to_string)
+        .unwrap_or_default()
+}
+
+/// Build the options vardict of a FileChooser call from a request.
+fn file_options(request: &FileRequest) -> HashMap<&'static str, zvariant::Value<'static>> {
+    let mut options = HashMap::new();
+
+    options.insert("modal", request.modal.into());
+    options.insert("multiple", request.multiple.into());
+    options.insert("directory", request.directory.into());
+
+    if let Some(label) = &request.accept_label {
+        options.insert("accept_label", label.clone().into());
+    }
+
+    if !request.filters.is_empty() {
+        options.insert(
+            "filters",
+            zvariant::Array::from(request.filters.clone()).into(),
+        );
+
+        // The service moves the current filter to the front.
+        options.insert("current_filter", request.filters[0].clone().into());
+    }
+
+    if !request.choices.is_empty() {
+        options.insert(
+            "choices",
+            zvariant::Array::from(request.choices.clone()).into(),
+        );
+    }
+
+    if let Some(folder) = &request.folder {
+        let mut bytes = folder.as_os_str().as_bytes().to_vec();
+        bytes.push(0);
+
+        options.insert("current_folder", zvariant::Array::from(bytes).into());
+    }
+
+    if let Some(name) = &request.file_name {
+        options.insert("current_name", name.clone().into());
+    }
+
+    options
+}
@@ -0,0 +1,134 @@
+use std::path::PathBuf;
+
+use super::{DialogProvider, FileRequest, FileResponse, Level, Message};
+use crate::{filter, window::ParentWindow};
+
+/// `Rfd` shows dialogs with rfd, which uses GTK on Linux.
+///
+/// rfd can't add widgets or relabel buttons in its file dialogs,
+/// so it leaves `choices` and `accept_label` to the caller's fallbacks.
+/// Its message dialogs have two buttons and don't tell dismissing from rejecting, so options
+/// are chosen from in turn.
+pub struct Rfd;
+
+impl DialogProvider for Rfd {
+    fn open_file(&self, request: &FileRequest) -> Option<FileResponse> {
+        let dialog = file_dialog(request);
+
+        let paths = match (request.multiple, request.directory) {
+            (false, false) => dialog.pick_file().map(|path| vec![path]),
+            (false, true) => dialog.pick_folder().map(|path| vec![path]),
+            (true, false) => dialog.pick_files(),
+            (true, true) => dialog.pick_folders(),
+        }?;
+
+        Some(FileResponse {
+            paths,
+            ..FileResponse::default()
+        })
+    }
+
+    fn save_file(&self, request: &FileRequest) -> Option<FileResponse> {
+        let path = file_dialog(request).save_file()?;
+
+        Some(FileResponse {
+            paths: vec![path],
+            ..FileResponse::default()
+        })
+    }
+
+    fn pick_folder(&self, request: &FileRequest) -> Option<PathBuf> {
+        file_dialog(request).pick_folder()
+    }
+
+    fn confirm(&self, message: &Message) -> bool {
+        let buttons = match (&message.accept_label, &message.reject_label) {
+            (None, None) => ::rfd::MessageButtons::OkCancel,
+
+            (accept, reject) => ::rfd::MessageButtons::OkCancelCustom(
+                accept.clone().unwrap_or_else(|| String::from("OK")),
+                reject.clone().unwrap_or_else(|| String::from("Cancel")),
+            ),
+        };
+
+        message_dialog(message).set_buttons(buttons).show()
+    }
+
+    fn message(&self, message: &Message) {
+        let buttons = match &message.accept_label {
+            Some(label) => ::rfd::MessageButtons::OkCustom(label.clone()),
+            None => ::rfd::MessageButtons::Ok,
+        };
+
+        message_dialog(message).set_buttons(buttons).show();
+    }
+}
+
+/// Create a file dialog from the request.
+fn file_dialog(request: &FileRequest) -> ::rfd::FileDialog {
+    let mut dialog = ::rfd::FileDialog::new().set_title(&request.title);
+
+    if request.modal {
+        dialog = set_parent(dialog, request.parent.as_ref());
+    }
+
+    for (name, patterns) in &request.filters {
+        let extensions = filter::extensions(patterns);
+
+        if extensions.is_empty() {
+            log::debug!("skipping filter {:?}: no usable patterns", name);
+            continue;
+        }
+
+        let extensions: Vec<&str> = extensions.iter().map(String::as_str).collect();
+
+        dialog = dialog.add_filter(name, &extensions);
+    }
+
+    if let Some(folder) = &request.folder {
+        dialog = dialog.set_directory(folder);
+    }
+
+    if let Some(file_name) = &request.file_name {
+        dialog = dialog.set_file_name(file_name);
+    }
+
+    dialog
+}
+
+/// Create a message dialog from the message, without buttons.
+fn message_dialog(message: &Message) -> ::rfd::MessageDialog {
+    let level = match message.level {
+        Level::Info => ::rfd::MessageLevel::Info,
+        Level::Warning => ::rfd::MessageLevel::Warning,
+        Level::Error => ::rfd::MessageLevel::Error,
+    };
+
+    let dialog = ::rfd::MessageDialog::new()
+        .set_level(level)
+        .set_title(&message.title)
+        .set_description(&message.description);
+
+    match &message.parent {
+        Some(ParentWindow::X11(xid)) => dialog.set_parent(&crate::window::X11Parent(*xid)),
+        _ => dialog,
+    }
+}
+
+/// Make the dialog transient for the parent window.
+///
+/// rfd makes every dialog with a parent modal to it, so callers only attach modal dialogs.
+fn set_parent(dialog: ::rfd::FileDialog, parent: Option<&ParentWindow>) -> ::rfd::FileDialog {
+    match parent {
+        Some(ParentWindow::X11(xid)) => dialog.set_parent(&crate::window::X11Parent(*xid)),
+
+        // Importing an exported surface needs xdg-foreign on our own Wayland connection,
+        // which rfd doesn't give access to.
+        Some(ParentWindow::Wayland(handle)) => {
+            log::debug!("can't attach dialog to wayland parent {}", handle);
+            dialog
+        }
+
+        None => dialog,
+    }
+}
@@ -0,0 +1,588 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use serde::Deserialize;
+
+use super::{
+    AppRequest, AppResponse, DialogProvider, FileRequest, FileResponse, Message, ScreenshotOptions,
+    SourceRequest,
+};
+use crate::{
+    capture::{Image, Rect},
+    cast::Source,
+};
+
+/// `Scripted` answers dialogs from a response file instead of asking the user.
+///
+/// It's meant for automated tests of sandboxed applications, e.g. in CI where nobody can click.
+/// Each dialog consumes the first remaining response for its method, and is cancelled
+/// if there is none left.
+pub struct Scripted {
+    responses: Mutex<VecDeque<Response>>,
+}
+
+/// `Script` is the contents of a response file.
+///
+/// ```toml
+/// [[response]]
+/// method = "open_file"
+/// paths = ["/tmp/fixture.png"]
+///
+/// [[response]]
+/// method = "confirm"
+/// accept = false
+/// ```
+#[derive(Debug, Default, Deserialize)]
+struct Script {
+    #[serde(default, rename = "response")]
+    responses: VecDeque<Response>,
+}
+
+/// `Response` is the scripted answer to a single dialog.
+#[derive(Debug, Clone, Deserialize)]
+struct Response {
+    method: Method,
+
+    /// The paths picked in a file dialog; a file dialog without paths is cancelled.
+    #[serde(default)]
+    paths: Vec<PathBuf>,
+
+    /// The name of the filter to report as active.
+    current_filter: Option<String>,
+
+    /// The selection of each choice, keyed by choice id.
+    choices: Option<BTreeMap<String, String>>,
+
+    /// Whether a confirmation is accepted.
+    #[serde(default)]
+    accept: bool,
+
+    /// Whether "Always use this application" is ticked in an application chooser.
+    #[serde(default)]
+    always: bool,
+
+    /// The label of the option picked in a choice between several,
+    /// or the desktop file id of the application picked in an application chooser.
+    option: Option<String>,
+
+    /// The `[x, y]` position of the pixel picked on the screen.
+    point: Option<(u32, u32)>,
+
+    /// The `[x, y, width, height]` area selected on the screen.
+    region: Option<(u32, u32, u32, u32)>,
+
+    /// The screenshot delay to set instead of the default one.
+    delay: Option<u64>,
+
+    /// Whether to include the pointer in the screenshot instead of the default.
+    cursor: Option<bool>,
+
+    /// The monitor names, or window app ids or titles, of the sources picked to share;
+    /// picking none cancels.
+    #[serde(default)]
+    sources: Vec<String>,
+}
+
+/// `Method` is the dialog provider method a response answers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Method {
+    OpenFile,
+    SaveFile,
+    PickFolder,
+    Confirm,
+    Choose,
+    Message,
+    ChooseApplication,
+    PickPixel,
+    SelectRegion,
+    ScreenshotOptions,
+    PickSources,
+}
+
+impl Scripted {
+    /// Load the responses from the given file.
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+
+        let script: Script = toml::from_str(&contents)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+        log::info!("loaded {} scripted responses", script.responses.len());
+
+        Ok(Self {
+            responses: Mutex::new(script.responses),
+        })
+    }
+
+    /// Take the next response for the given method.
+    fn next(&self, method: Method) -> Option<Response> {
+        let mut responses = self.responses.lock().ok()?;
+
+        let Some(index) = responses.iter().position(|r| r.method == method) else {
+            log::warn!("no scripted response left for {:?}, cancelling", method);
+            return None;
+        };
+
+        responses.remove(index)
+    }
+
+    /// Answer a file dialog with the next response for the given method.
+    fn file(&self, method: Method, request: &FileRequest) -> Option<FileResponse> {
+        let response = self.next(method)?;
+
+        if response.paths.is_empty() {
+            return None;
+        }
+
+        let current_filter = response.current_filter.and_then(|name| {
+            request
+                .filters
+                .iter()
+                .find(|(filter, _)| *filter == name)
+                .cloned()
+        });
+
+        let choices = response
+            .choices
+            .map(|choices| choices.into_iter().collect());
+
+        Some(FileResponse {
+            paths: response.paths,
+            current_filter,
+            choices,
+        })
+    }
+}
+
+impl DialogProvider for Scripted {
+    fn open_file(&self, request: &FileRequest) -> Option<FileResponse> {
+        self.file(Method::OpenFile, request)
+    }
+
+    fn save_file(&self, request: &FileRequest) -> Option<FileResponse> {
+        self.file(Method::SaveFile, request)
+    }
+
+    fn pick_folder(&self, request: &FileRequest) -> Option<PathBuf> {
+        self.file(Method::PickFolder, request)?
+            .paths
+            .into_iter()
+            .next()
+    }
+
+    fn confirm(&self, message: &Message) -> bool {
+        log::info!("confirm({:?})", message.description);
+
+        self.next(Method::Confirm)
+            .is_some_and(|response| response.accept)
+    }
+
+    fn choose(&self, message: &Message, options: &[String]) -> Option<usize> {
+        log::info!("choose({:?}, {:?})", message.description, options);
+
+        let option = self.next(Method::Choose)?.option?;
+
+        options.iter().position(|label| *label == option)
+    }
+
+    fn message(&self, message: &Message) {
+        log::info!("message({:?})", message.description);
+
+        self.next(Method::Message);
+    }
+
+    fn choose_application(&self, request: &AppRequest) -> Option<AppResponse> {
+        let response = self.next(Method::ChooseApplication)?;
+
+        let id = response.option?;
+
+        let listed = request.choices.get().iter().any(|choice| choice.id == id);
+
+        (listed || request.offer_other).then_some(AppResponse {
+            id,
+            always: response.always && request.offer_always,
+        })
+    }
+
+    fn screenshot_options(
+        &self,
+        message: &Message,
+        defaults: ScreenshotOptions,
+    ) -> Option<ScreenshotOptions> {
+        log::info!(
+            "screenshot_options({:?}, {:?})",
+            message.description,
+            defaults
+        );
+
+        let response = self
+            .next(Method::ScreenshotOptions)
+            .filter(|response| response.accept)?;
+
+        Some(ScreenshotOptions {
+            delay: response.delay.unwrap_or(defaults.delay),
+            cursor: response.cursor.unwrap_or(defaults.cursor),
+        })
+    }
+
+    fn pick_sources(&self, request: &SourceRequest) -> Option<Vec<usize>> {
+        let labels: Vec<String> = request.sources.iter().map(Source::label).collect();
+
+        log::info!("pick_sources({:?}, {:?})", request.description, labels);
+
+        let response = self.next(Method::PickSources)?;
+
+        // Monitors are picked by name, windows by app id or title.
+        let matches = |source: &Source, name: &str| match source {
+            Source::Monitor(output) => output.name == name,
+            Source::Window(toplevel) => toplevel.app_id == name || toplevel.title == name,
+            Source::Virtual => name == "virtual",
+            Source::Region(_) => name == "region",
+        };
+
+        let picked: Option<Vec<usize>> = response
+            .sources
+            .iter()
+            .map(|name| {
+                request
+                    .sources
+                    .iter()
+                    .position(|source| matches(source, name))
+            })
+            .collect();
+
+        picked.filter(|picked| !picked.is_empty() && (request.multiple || picked.len() == 1))
+    }
+
+    fn countdown(&self, seconds: u64) {
+        // Scripted runs are tests, which shouldn't wait out the delay.
+        log::info!("countdown({})", seconds);
+    }
+
+    fn pick_pixel(&self, screen: &Image) -> Option<(u32, u32)> {
+        log::info!("pick_pixel({}x{})", screen.width, screen.height);
+
+        let (x, y) = self.next(Method::PickPixel)?.point?;
+
+        (x < screen.width && y < screen.height).then_some((x, y))
+    }
+
+    fn select_region(&self, message: &Message, screen: &Image, windows: &[Rect]) -> Option<Rect> {
+        log::info!(
+            "select_region({:?}, {}x{}, {} windows)",
+            message.description,
+            screen.width,
+            screen.height,
+            windows.len()
+        );
+
+        let (x, y, width, height) = self.next(Method::SelectRegion)?.region?;
+
+        let region = Rect {
+            x,
+            y,
+            width,
+            height,
+        };
+
+        let inside = x + width <= screen.width && y + height <= screen.height;
+
+        (inside && width > 0 && height > 0).then_some(region)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, sync::Arc};
+
+    use super::{Script, Scripted};
+    use crate::{
+        capture::{Capture, Image, Output, Rect, Toplevel},
+        cast::Source,
+        desktop::DesktopEntry,
+        dialog::{
+            AppChoices, AppRequest, AppResponse, DialogProvider, FileRequest, Message,
+            ScreenshotOptions, SourceRequest,
+        },
+    };
+
+    fn scripted(contents: &str) -> Scripted {
+        let script: Script = toml::from_str(contents).unwrap();
+
+        Scripted {
+            responses: std::sync::Mutex::new(script.responses),
+        }
+    }
+
+    #[test]
+    fn responses_are_consumed_per_method() {
+        let dialogs = scripted(
+            r#"
+            [[response]]
+            method = "confirm"
+            accept = true
+
+            [[response]]
+            method = "open_file"
+            paths = ["/tmp/a.png"]
+
+            [[response]]
+            method = "open_file"
+            paths = ["/tmp/b.png"]
+            "#,
+        );
+
+        let request = FileRequest::default();
+
+        let first = dialogs.open_file(&request).unwrap();
+        let second = dialogs.open_file(&request).unwrap();
+
+        assert_eq!(first.paths, [PathBuf::from("/tmp/a.png")]);
+        assert_eq!(second.paths, [PathBuf::from("/tmp/b.png")]);
+        assert!(dialogs.open_file(&request).is_none());
+        assert!(dialogs.confirm(&Message::default()));
+        assert!(!dialogs.confirm(&Message::default()));
+    }
+
+    #[test]
+    fn choose_by_label() {
+        let dialogs = scripted(
+            r#"
+            [[response]]
+            method = "choose"
+            option = "Always Allow"
+            "#,
+        );
+
+        let options = ["Allow", "Always Allow", "Deny"].map(String::from);
+
+        assert_eq!(dialogs.choose(&Message::default(), &options), Some(1));
+        assert_eq!(dialogs.choose(&Message::default(), &options), None);
+    }
+
+    #[test]
+    fn pick_pixel_on_screen() {
+        let dialogs = scripted(
+            r#"
+            [[response]]
+            method = "pick_pixel"
+            point = [1, 0]
+
+            [[response]]
+            method = "pick_pixel"
+            point = [2, 0]
+            "#,
+        );
+
+        let screen = Image {
+            width: 2,
+            height: 1,
+            pixels: vec![0; 8],
+        };
+
+        assert_eq!(dialogs.pick_pixel(&screen), Some((1, 0)));
+        assert_eq!(dialogs.pick_pixel(&screen), None);
+    }
+
+    #[test]
+    fn screenshot_options_over_defaults() {
+        let dialogs = scripted(
+            r#"
+            [[response]]
+            method = "screenshot_options"
+            accept = true
+            delay = 5
+
+            [[response]]
+            method = "screenshot_options"
+            accept = false
+            cursor = true
+            "#,
+        );
+
+        let defaults = ScreenshotOptions {
+            delay: 0,
+            cursor: true,
+        };
+
+        assert_eq!(
+            dialogs.screenshot_options(&Message::default(), defaults),
+            Some(ScreenshotOptions {
+                delay: 5,
+                cursor: true
+            })
+        );
+        assert_eq!(
+            dialogs.screenshot_options(&Message::default(), defaults),
+            None
+        );
+    }
+
+    #[test]
+    fn pick_sources_by_name() {
+        struct Blank;
+
+        impl Capture for Blank {
+            fn capture(&self, _cursor: bool) -> std::io::Result<Image> {
+                Ok(Image::default())
+            }
+        }
+
+        let dialogs = scripted(
+            r#"
+            [[response]]
+            method = "pick_sources"
+            sources = ["org.example.Editor", "DP-1"]
+
+            [[response]]
+            method = "pick_sources"
+            sources = ["HDMI-A-1", "DP-1"]
+
+            [[response]]
+            method = "pick_sources"
+            sources = ["DP-2"]
+            "#,
+        );
+
+        let output = |name: &str| {
+            Source::Monitor(Output {
+                name: name.to_owned(),
+                ..Output::default()
+            })
+        };
+
+        let window = Source::Window(Toplevel {
+            title: String::from("notes.txt"),
+            app_id: String::from("org.example.Editor"),
+            ..Toplevel::default()
+        });
+
+        let mut request = SourceRequest {
+            title: String::new(),
+            description: String::new(),
+            parent: None,
+            sources: vec![output("DP-1"), output("HDMI-A-1"), window],
+            multiple: true,
+            capture: Arc::new(Blank),
+        };
+
+        assert_eq!(dialogs.pick_sources(&request), Some(vec![2, 0]));
+
+        // Picking several isn't allowed unless the app asked for it, and unknown ones never are.
+        request.multiple = false;
+
+        assert_eq!(dialogs.pick_sources(&request), None);
+        assert_eq!(dialogs.pick_sources(&request), None);
+    }
+
+    #[test]
+    fn select_region_on_screen() {
+        let dialogs = scripted(
+            r#"
+            [[response]]
+            method = "select_region"
+            region = [10, 10, 20, 20]
+
+            [[response]]
+            method = "select_region"
+            region = [10, 10, 100, 20]
+            "#,
+        );
+
+        let screen = Image {
+            width: 50,
+            height: 50,
+            pixels: Vec::new(),
+        };
+
+        assert_eq!(
+            dialogs.select_region(&Message::default(), &screen, &[]),
+            Some(Rect {
+                x: 10,
+                y: 10,
+                width: 20,
+                height: 20
+            })
+        );
+        assert_eq!(
+            dialogs.select_region(&Message::default(), &screen, &[]),
+            None
+        );
+    }
+
+    #[test]
+    fn choose_application_by_id() {
+        let dialogs = scripted(
+            r#"
+            [[response]]
+            method = "choose_application"
+            option = "org.gnome.TextEditor"
+            always = true
+
+            [[response]]
+            method = "choose_application"
+            option = "org.example.Missing"
+
+            [[response]]
+            method = "choose_application"
+            option = "org.example.Missing"
+            "#,
+        );
+
+        let mut request = AppRequest {
+            choices: AppChoices::new(vec![
+                DesktopEntry::unknown("org.gnome.Evince"),
+                DesktopEntry::unknown("org.gnome.TextEditor"),
+            ]),
+            ..AppRequest::default()
+        };
+
+        assert_eq!(
+            dialogs.choose_application(&request),
+            Some(AppResponse {
+                id: String::from("org.gnome.TextEditor"),
+                always: false,
+            })
+        );
+        assert_eq!(dialogs.choose_application(&request), None);
+
+        request.offer_other = true;
+
+        assert_eq!(
+            dialogs
+                .choose_application(&request)
+                .map(|response| response.id),
+            Some(String::from("org.example.Missing"))
+        );
+    }
+
+    #[test]
+    fn filters_and_choices() {
+        let dialogs = scripted(
+            r#"
+            [[response]]
+            method = "save_file"
+            paths = ["/tmp/out.txt"]
+            current_filter = "Text"
+            choices = { encoding = "utf8" }
+            "#,
+        );
+
+        let request = FileRequest {
+            filters: vec![(String::from("Text"), vec![(0, String::from("*.txt"))])],
+            ..FileRequest::default()
+        };
+
+        let response = dialogs.save_file(&request).unwrap();
+
+        assert_eq!(response.current_filter, request.filters.first().cloned());
+        assert_eq!(
+            response.choices,
+            Some(vec![(String::from("encoding"), String::from("utf8"))])
+        );
+    }
+}
@@ -0,0 +1,604 @@
+use std::{
+    fs::File,
+    io::Read,
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+use ratatui::{
+    backend::TermionBackend,
+    layout::{Constraint, Layout, Rect},
+    style::{Style, Stylize},
+    text::Line,
+    widgets::{Block, Clear, List, ListItem, ListState, Paragraph, Wrap},
+    Terminal,
+};
+use termion::{
+    event::Key,
+    input::{Keys, TermRead},
+    raw::{IntoRawMode, RawTerminal},
+    screen::{AlternateScreen, IntoAlternateScreen},
+};
+
+use super::{DialogProvider, FileRequest, FileResponse, Level, Message};
+use crate::{filter, request::Ended, state};
+
+/// How often waiting for a key checks whether the request the dialog is shown for ended.
+const ENDED_INTERVAL: Duration = Duration::from_millis(250);
+
+/// `Backend` draws on a terminal device in raw mode, on its alternate screen.
+type Backend = TermionBackend<AlternateScreen<RawTerminal<File>>>;
+
+/// `Tui` shows dialogs in a terminal, for sessions without a display like SSH logins.
+///
+/// The portal has no terminal of its own, so dialogs are drawn on the configured `tty`,
+/// which defaults to the controlling terminal the portal was started from.
+/// Only one dialog is shown at a time, as they'd fight over the terminal otherwise.
+pub struct Tui {
+    tty: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl Tui {
+    pub fn new(tty: PathBuf) -> Self {
+        Self {
+            tty,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Take over the terminal, run `dialog` on it and restore it afterwards.
+    fn session<T>(
+        &self,
+        dialog: impl FnOnce(&mut Terminal<Backend>, &mut Keys<Input>) -> std::io::Result<T>,
+    ) -> Option<T> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+
+        let result = (|| {
+            let tty = std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&self.tty)?;
+
+            let mut keys = Input {
+                tty: tty.try_clone()?,
+                ended: Ended::current(),
+            }
+            .keys();
+
+            let screen = tty.into_raw_mode()?.into_alternate_screen()?;
+
+            let mut terminal = Terminal::new(TermionBackend::new(screen))?;
+
+            let output = dialog(&mut terminal, &mut keys);
+
+            terminal.show_cursor()?;
+
+            output
+        })();
+
+        match result {
+            Ok(output) => Some(output),
+
+            Err(e) => {
+                log::error!("failed to show a dialog on {:?}: {}", self.tty, e);
+                None
+            }
+        }
+    }
+
+    /// Run a file browser until the user accepts or cancels it.
+    fn browse(&self, request: &FileRequest, mode: Mode) -> Option<Vec<PathBuf>> {
+        self.session(|terminal, keys| {
+            let mut browser = Browser::new(request, mode);
+
+            loop {
+                terminal.draw(|frame| browser.draw(frame))?;
+
+                let Some(key) = keys.next().transpose()? else {
+                    return Ok(None);
+                };
+
+                if let Some(answer) = browser.key(key) {
+                    return Ok(answer);
+                }
+            }
+        })
+        .flatten()
+    }
+}
+
+/// `Input` reads keys from the terminal until the request the dialog is shown for ended,
+/// reading nothing afterwards so the dialog is dismissed.
+struct Input {
+    tty: File,
+    ended: Ended,
+}
+
+impl Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut pollfd = libc::pollfd {
+            fd: self.tty.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        let timeout = ENDED_INTERVAL.as_millis() as libc::c_int;
+
+        loop {
+            if self.ended.get() {
+                return Ok(0);
+            }
+
+            match unsafe { libc::poll(&mut pollfd, 1, timeout) } {
+                0 => continue,
+                count if count > 0 => return self.tty.read(buf),
+
+                _ => match std::io::Error::last_os_error() {
+                    e if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                    e => return Err(e),
+                },
+            }
+        }
+    }
+}
+
+impl DialogProvider for Tui {
+    fn open_file(&self, request: &FileRequest) -> Option<FileResponse> {
+        let mode = match request.directory {
+            true => Mode::Folder,
+            false => Mode::Open,
+        };
+
+        Some(FileResponse {
+            paths: self.browse(request, mode)?,
+            ..FileResponse::default()
+        })
+    }
+
+    fn save_file(&self, request: &FileRequest) -> Option<FileResponse> {
+        Some(FileResponse {
+            paths: self.browse(request, Mode::Save)?,
+            ..FileResponse::default()
+        })
+    }
+
+    fn pick_folder(&self, request: &FileRequest) -> Option<PathBuf> {
+        self.browse(request, Mode::Folder)?.into_iter().next()
+    }
+
+    fn confirm(&self, message: &Message) -> bool {
+        let accept = message.accept_label.as_deref().unwrap_or("OK");
+        let reject = message.reject_label.as_deref().unwrap_or("Cancel");
+
+        let hint = format!("[y] {}   [n] {}", accept, reject);
+
+        self.session(|terminal, keys| loop {
+            terminal.draw(|frame| draw_message(frame, message, &hint))?;
+
+            match keys.next().transpose()? {
+                Some(Key::Char('y') | Key::Char('Y') | Key::Char('\n')) => return Ok(true),
+                Some(Key::Char('n') | Key::Char('N') | Key::Esc) | None => return Ok(false),
+                Some(_) => {}
+            }
+        })
+        .unwrap_or(false)
+    }
+
+    fn choose(&self, message: &Message, options: &[String]) -> Option<usize> {
+        let hint = options
+            .iter()
+            .enumerate()
+            .map(|(i, label)| format!("[{}] {}", i + 1, label))
+            .collect::<Vec<_>>()
+            .join("   ");
+
+        self.session(|terminal, keys| loop {
+            terminal.draw(|frame| draw_message(frame, message, &hint))?;
+
+            match keys.next().transpose()? {
+                Some(Key::Char(digit)) => {
+                    let index = digit.to_digit(10).and_then(|n| (n as usize).checked_sub(1));
+
+                    if let Some(index) = index.filter(|&index| index < options.len()) {
+                        return Ok(Some(index));
+                    }
+                }
+
+                Some(Key::Esc) | None => return Ok(None),
+                Some(_) => {}
+            }
+        })
+        .flatten()
+    }
+
+    fn message(&self, message: &Message) {
+        let hint = format!(
+            "[Enter] {}",
+            message.accept_label.as_deref().unwrap_or("OK")
+        );
+
+        self.session(|terminal, keys| {
+            terminal.draw(|frame| draw_message(frame, message, &hint))?;
+
+            keys.next().transpose()?;
+
+            Ok(())
+        });
+    }
+}
+
+/// Draw a message box in the middle of the terminal.
+fn draw_message(frame: &mut ratatui::Frame<'_>, message: &Message, hint: &str) {
+    let level = match message.level {
+        Level::Info => "",
+        Level::Warning => "Warning: ",
+        Level::Error => "Error: ",
+    };
+
+    let area = centered(frame.area(), 60, 9);
+
+    let block = Block::bordered().title(format!(" {}{} ", level, message.title));
+
+    let text = vec![
+        Line::from(message.description.as_str()),
+        Line::default(),
+        Line::from(hint).bold(),
+    ];
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(text).wrap(Wrap { trim: false }).block(block),
+        area,
+    );
+}
+
+/// Get a rectangle of at most `width` by `height` cells in the middle of `area`.
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+/// `Mode` is what a file browser picks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Open,
+    Save,
+    Folder,
+}
+
+/// `Entry` is a file or directory listed in a file browser.
+struct Entry {
+    path: PathBuf,
+    name: String,
+    is_dir: bool,
+}
+
+/// `Browser` is a keyboard driven file browser.
+///
+/// Arrows move and enter directories, Space marks files for a multiple selection,
+/// Tab cycles the filters, Enter accepts and Esc cancels.
+/// In Save mode, typing edits the file name.
+struct Browser<'a> {
+    request: &'a FileRequest,
+    mode: Mode,
+    directory: PathBuf,
+    entries: Vec<Entry>,
+    list: ListState,
+    marked: Vec<PathBuf>,
+    file_name: String,
+    filter: usize,
+    show_hidden: bool,
+    error: Option<String>,
+}
+
+impl<'a> Browser<'a> {
+    /// Create a browser for the request, starting in its folder or the home directory.
+    fn new(request: &'a FileRequest, mode: Mode) -> Self {
+        let directory = request
+            .folder
+            .clone()
+            .or_else(dirs::home_dir)
+            .unwrap_or_else(|| PathBuf::from("/"));
+
+        let mut browser = Self {
+            request,
+            mode,
+            directory: PathBuf::new(),
+            entries: Vec::new(),
+            list: ListState::default(),
+            marked: Vec::new(),
+            file_name: request.file_name.clone().unwrap_or_default(),
+            filter: 0,
+            show_hidden: state::show_hidden(),
+            error: None,
+        };
+
+        browser.open(directory);
+
+        browser
+    }
+
+    /// Change to the given directory and list the entries visible under the active filter.
+    fn open(&mut self, directory: PathBuf) {
+        let read_dir = match std::fs::read_dir(&directory) {
+            Ok(read_dir) => read_dir,
+
+            Err(e) => {
+                self.error = Some(format!("{}: {}", directory.display(), e));
+                return;
+            }
+        };
+
+        let extensions = match self.mode {
+            Mode::Folder => None,
+            _ => self
+                .request
+                .filters
+                .get(self.filter)
+                .map(|(_, patterns)| filter::extensions(patterns)),
+        };
+
+        let visible = |entry: &Entry| {
+            if entry.is_dir {
+                return true;
+            }
+
+            if self.mode == Mode::Folder {
+                return false;
+            }
+
+            let Some(extensions) = &extensions else {
+                return true;
+            };
+
+            extensions.iter().any(|extension| {
+                extension == "*"
+                    || Path::new(&entry.name)
+                        .extension()
+                        .is_some_and(|e| e.eq_ignore_ascii_case(extension))
+            })
+        };
+
+        let mut entries: Vec<Entry> = read_dir
+            .filter_map(Result::ok)
+            .map(|entry| Entry {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                is_dir: entry.path().is_dir(),
+                path: entry.path(),
+            })
+            .filter(|entry| self.show_hidden || !entry.name.starts_with('.'))
+            .filter(visible)
+            .collect();
+
+        entries.sort_by(|a, b| {
+            b.is_dir
+                .cmp(&a.is_dir)
+                .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+        });
+
+        // Folders are picked with Enter, so the current one needs a row to be pickable.
+        if self.mode == Mode::Folder {
+            entries.insert(
+                0,
+                Entry {
+                    path: directory.clone(),
+                    name: String::from("."),
+                    is_dir: true,
+                },
+            );
+        }
+
+        self.directory = directory;
+        self.entries = entries;
+        self.list.select((!self.entries.is_empty()).then_some(0));
+        self.error = None;
+    }
+
+    /// Get the highlighted entry.
+    fn current(&self) -> Option<&Entry> {
+        self.entries.get(self.list.selected()?)
+    }
+
+    /// Handle a key, returning the answer once the dialog is done.
+    fn key(&mut self, key: Key) -> Option<Option<Vec<PathBuf>>> {
+        match key {
+            Key::Esc | Key::Ctrl('c') => return Some(None),
+
+            Key::Up => self.list.select_previous(),
+            Key::Down => self.list.select_next(),
+            Key::PageUp => self.list.scroll_up_by(10),
+            Key::PageDown => self.list.scroll_down_by(10),
+            Key::Home => self.list.select_first(),
+            Key::End => self.list.select_last(),
+
+            Key::Left => self.parent(),
+            Key::Backspace if self.mode != Mode::Save => self.parent(),
+
+            Key::Right => {
+                let current = self
+                    .current()
+                    .filter(|entry| entry.is_dir && entry.name != ".");
+
+                if let Some(entry) = current {
+                    self.open(entry.path.clone());
+                }
+            }
+
+            Key::Char('\t') if !self.request.filters.is_empty() => {
+                self.filter = (self.filter + 1) % self.request.filters.len();
+                self.open(self.directory.clone());
+            }
+
+            Key::Char(' ') if self.request.multiple && self.mode == Mode::Open => {
+                if let Some(entry) = self.current().filter(|entry| !entry.is_dir) {
+                    let path = entry.path.clone();
+
+                    match self.marked.iter().position(|marked| *marked == path) {
+                        Some(position) => {
+                            self.marked.remove(position);
+                        }
+
+                        None => self.marked.push(path),
+                    }
+                }
+
+                self.list.select_next();
+            }
+
+            Key::Char('\n') => return self.accept().map(Some),
+
+            Key::Backspace => {
+                self.file_name.pop();
+            }
+
+            Key::Char(c) if self.mode == Mode::Save && !c.is_control() => self.file_name.push(c),
+
+            _ => {}
+        }
+
+        None
+    }
+
+    /// Go to the parent directory.
+    fn parent(&mut self) {
+        if let Some(parent) = self.directory.parent() {
+            self.open(parent.to_path_buf());
+        }
+    }
+
+    /// Accept the highlighted entry, entering it instead if it's a directory to browse.
+    fn accept(&mut self) -> Option<Vec<PathBuf>> {
+        let current = self
+            .current()
+            .map(|entry| (entry.path.clone(), entry.is_dir, entry.name.clone()));
+
+        match (self.mode, current) {
+            (Mode::Folder, Some((path, _, _))) => Some(vec![path]),
+
+            (Mode::Folder, None) => Some(vec![self.directory.clone()]),
+
+            (Mode::Save, _) if !self.file_name.is_empty() => {
+                Some(vec![self.directory.join(&self.file_name)])
+            }
+
+            (_, Some((path, true, _))) => {
+                self.open(path);
+                None
+            }
+
+            (Mode::Save, Some((_, false, name))) => {
+                self.file_name = name;
+                None
+            }
+
+            (Mode::Open, Some((path, false, _))) => match self.marked.is_empty() {
+                true => Some(vec![path]),
+                false => Some(self.marked.clone()),
+            },
+
+            _ => None,
+        }
+    }
+
+    /// Draw the browser.
+    fn draw(&mut self, frame: &mut ratatui::Frame<'_>) {
+        let marking = self.request.multiple && self.mode == Mode::Open;
+
+        let footer_height = 1 + u16::from(self.mode == Mode::Save) + u16::from(marking);
+
+        let [header, body, footer] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(1),
+            Constraint::Length(footer_height),
+        ])
+        .areas(frame.area());
+
+        let title = match self.request.title.is_empty() {
+            true => match self.mode {
+                Mode::Open => "Open File",
+                Mode::Save => "Save File",
+                Mode::Folder => "Select Folder",
+            },
+            false => &self.request.title,
+        };
+
+        frame.render_widget(
+            Line::from(format!("{} — {}", title, self.directory.display())).bold(),
+            header,
+        );
+
+        let items: Vec<ListItem<'_>> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let mark = match self.marked.contains(&entry.path) {
+                    true => "* ",
+                    false => "  ",
+                };
+
+                let suffix = match entry.is_dir {
+                    true => "/",
+                    false => "",
+                };
+
+                ListItem::new(format!("{}{}{}", mark, entry.name, suffix))
+            })
+            .collect();
+
+        let list = List::new(items)
+            .block(Block::bordered())
+            .highlight_style(Style::new().reversed());
+
+        frame.render_stateful_widget(list, body, &mut self.list);
+
+        let mut lines = Vec::new();
+
+        if self.mode == Mode::Save {
+            lines.push(Line::from(format!("Name: {}", self.file_name)));
+        }
+
+        match &self.error {
+            Some(error) => lines.push(Line::from(error.as_str()).red()),
+
+            None => {
+                let filter = self
+                    .request
+                    .filters
+                    .get(self.filter)
+                    .map(|(name, _)| format!("   [Tab] {}", name))
+                    .unwrap_or_default();
+
+                let accept = self
+                    .request
+                    .accept_label
+                    .as_deref()
+                    .unwrap_or(match self.mode {
+                        Mode::Open => "Open",
+                        Mode::Save => "Save",
+                        Mode::Folder => "Select",
+                    });
+
+                lines.push(Line::from(format!(
+                    "[Enter] {}   [Esc] Cancel   [←/→] Folders{}",
+                    accept, filter
+                )));
+            }
+        }
+
+        if marking {
+            lines.push(Line::from("[Space] Mark files"));
+        }
+
+        frame.render_widget(Paragraph::new(lines), footer);
+    }
+}
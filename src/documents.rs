@@ -0,0 +1,174 @@
+use std::{
+    collections::HashMap,
+    os::unix::{ffi::OsStrExt, fs::OpenOptionsExt, io::AsRawFd},
+    path::{Path, PathBuf},
+};
+
+use zbus::{dbus_proxy, zvariant};
+
+/// Reuse an existing document for the same file instead of adding a duplicate.
+const REUSE_EXISTING: u32 = 1;
+
+/// Keep the document after the portal restarts.
+const PERSISTENT: u32 = 2;
+
+/// Export a directory instead of a single file.
+const EXPORT_DIRECTORY: u32 = 8;
+
+/// The Documents portal, which exposes host files inside sandboxes through a FUSE mount.
+#[dbus_proxy(
+    interface = "org.freedesktop.portal.Documents",
+    default_service = "org.freedesktop.portal.Documents",
+    default_path = "/org/freedesktop/portal/documents"
+)]
+trait Documents {
+    /// Add existing files and grant `app_id` the given permissions on them.
+    fn add_full(
+        &self,
+        o_path_fds: &[zvariant::Fd],
+        flags: u32,
+        app_id: &str,
+        permissions: &[&str],
+    ) -> zbus::Result<(Vec<String>, HashMap<String, zvariant::OwnedValue>)>;
+
+    /// Add a file that may not exist yet by its parent directory and name.
+    fn add_named_full(
+        &self,
+        o_path_fd: zvariant::Fd,
+        filename: &[u8],
+        flags: u32,
+        app_id: &str,
+        permissions: &[&str],
+    ) -> zbus::Result<(String, HashMap<String, zvariant::OwnedValue>)>;
+
+    /// Get the path the document store is mounted at on the host.
+    fn get_mount_point(&self) -> zbus::Result<Vec<u8>>;
+}
+
+/// Check whether `app_id` belongs to an installed Flatpak, whose sandbox can't see host paths.
+///
+/// Apps running on the host have an empty app id, or one derived from their systemd unit.
+pub fn is_sandboxed(app_id: &str) -> bool {
+    if app_id.is_empty() || app_id.contains('/') {
+        return false;
+    }
+
+    let user = dirs::data_dir().map(|dir| dir.join("flatpak").join("app").join(app_id));
+    let system = Path::new("/var/lib/flatpak/app").join(app_id);
+
+    user.is_some_and(|dir| dir.is_dir()) || system.is_dir()
+}
+
+/// Export `paths` through the Documents portal, returning the paths inside the document mount.
+///
+/// Paths that don't exist yet, like new SaveFile targets, are added by their parent directory.
+pub async fn export(
+    conn: &zbus::Connection,
+    app_id: &str,
+    paths: &[PathBuf],
+    writable: bool,
+) -> zbus::Result<Vec<PathBuf>> {
+    let documents = DocumentsProxy::new(conn).await?;
+
+    let mount_point = PathBuf::from(std::ffi::OsStr::from_bytes(
+        documents
+            .get_mount_point()
+            .await?
+            .strip_suffix(&[0])
+            .unwrap_or_default(),
+    ));
+
+    let permissions: &[&str] = match writable {
+        true => &["read", "write"],
+        false => &["read"],
+    };
+
+    let mut exported = Vec::with_capacity(paths.len());
+
+    for path in paths {
+        let name = path
+            .file_name()
+            .ok_or_else(|| zbus::Error::Failure(format!("{:?} has no file name", path)))?;
+
+        let id = match path.exists() {
+            true => {
+                let flags = match path.is_dir() {
+                    true => REUSE_EXISTING | PERSISTENT | EXPORT_DIRECTORY,
+                    false => REUSE_EXISTING | PERSISTENT,
+                };
+
+                let file = open_path(path)?;
+
+                let (ids, _) = documents
+                    .add_full(&[file.as_raw_fd().into()], flags, app_id, permissions)
+                    .await?;
+
+                ids.into_iter().next().unwrap_or_default()
+            }
+
+            false => {
+                let parent = path.parent().unwrap_or(Path::new("/"));
+
+                let file = open_path(parent)?;
+
+                let (id, _) = documents
+                    .add_named_full(
+                        file.as_raw_fd().into(),
+                        name.as_bytes(),
+                        REUSE_EXISTING | PERSISTENT,
+                        app_id,
+                        permissions,
+                    )
+                    .await?;
+
+                id
+            }
+        };
+
+        exported.push(mount_point.join(id).join(name));
+    }
+
+    Ok(exported)
+}
+
+/// Open `path` with `O_PATH`, as the Documents portal expects, which neither reads the file
+/// nor waits for a writer of a FIFO, so files the user can't read can be exported too.
+fn open_path(path: &Path) -> std::io::Result<std::fs::File> {
+    std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_PATH | libc::O_CLOEXEC)
+        .open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::{ffi::OsStrExt, fs::PermissionsExt};
+
+    use super::open_path;
+
+    #[test]
+    fn open_paths() {
+        let dir = std::env::temp_dir().join(format!("documents-test-{}", std::process::id()));
+
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // A file picked to be overwritten, which only its owner can write.
+        let file = dir.join("write-only");
+
+        std::fs::write(&file, b"").unwrap();
+        std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o200)).unwrap();
+
+        assert!(open_path(&file).is_ok());
+
+        // A FIFO nothing writes to.
+        let fifo = dir.join("fifo");
+        let name = std::ffi::CString::new(fifo.as_os_str().as_bytes()).unwrap();
+
+        // SAFETY: the name is NUL-terminated.
+        assert_eq!(unsafe { libc::mkfifo(name.as_ptr(), 0o600) }, 0);
+
+        assert!(open_path(&fifo).is_ok());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
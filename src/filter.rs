@@ -0,0 +1,93 @@
+/// `Filter` is a named list of `(kind, pattern)` pairs, where kind 0 is a glob and 1 is a MIME type.
+pub type Filter = (String, Vec<(u32, String)>);
+
+/// Move the current filter to the front of the list, since dialogs activate the first filter.
+///
+/// The spec allows `current_filter` to name a filter that is not part of `filters`;
+/// in that case it is added so it can still be selected.
+pub fn order(mut filters: Vec<Filter>, current: Option<&Filter>) -> Vec<Filter> {
+    if let Some(current) = current {
+        filters.retain(|filter| filter != current);
+        filters.insert(0, current.clone());
+    }
+
+    filters
+}
+
+/// Guess which filter the user had active from the chosen paths.
+///
+/// Used for dialogs that don't report the active filter: pick the first filter matching
+/// every path, falling back to the filter requested by the caller.
+pub fn guess(
+    paths: &[std::path::PathBuf],
+    filters: &[Filter],
+    current: Option<Filter>,
+) -> Option<Filter> {
+    let matches = |filter: &&Filter| {
+        let extensions = extensions(&filter.1);
+
+        paths.iter().all(|path| {
+            extensions.iter().any(|extension| {
+                extension == "*"
+                    || path
+                        .extension()
+                        .is_some_and(|e| e.eq_ignore_ascii_case(extension))
+            })
+        })
+    };
+
+    filters.iter().find(matches).cloned().or(current)
+}
+
+/// Convert glob patterns and MIME types to plain file extensions.
+pub fn extensions(patterns: &[(u32, String)]) -> Vec<String> {
+    let mut extensions = Vec::new();
+
+    for (kind, pattern) in patterns {
+        match kind {
+            0 => extensions.extend(glob_extension(pattern)),
+
+            1 => {
+                if let Some((top, sub)) = pattern.split_once('/') {
+                    let known = mime_guess::get_extensions(top, sub).unwrap_or_default();
+
+                    extensions.extend(known.iter().map(ToString::to_string));
+                }
+            }
+
+            _ => log::warn!("unknown filter kind {} for {:?}", kind, pattern),
+        }
+    }
+
+    extensions.sort();
+    extensions.dedup();
+
+    extensions
+}
+
+/// Extract the extension from a glob such as `*.png` or `*.[pP][nN][gG]`.
+fn glob_extension(pattern: &str) -> Option<String> {
+    if pattern == "*" {
+        return Some(String::from("*"));
+    }
+
+    let mut extension = String::new();
+    let mut chars = pattern.strip_prefix("*.")?.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            // Case-insensitive classes like `[pP]` collapse to their first character.
+            '[' => {
+                let class: String = chars.by_ref().take_while(|&c| c != ']').collect();
+
+                extension.push(class.chars().next()?.to_ascii_lowercase());
+            }
+
+            '*' | '?' => return None,
+
+            c => extension.push(c),
+        }
+    }
+
+    (!extension.is_empty()).then_some(extension)
+}
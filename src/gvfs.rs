@@ -0,0 +1,232 @@
+use std::path::{Path, PathBuf};
+
+/// `Mount` is a remote location mounted by GVFS and exposed through its FUSE daemon.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mount {
+    /// A human readable name like `example.com (sftp)`.
+    pub name: String,
+
+    /// The local path of the mount under `$XDG_RUNTIME_DIR/gvfs`.
+    pub path: PathBuf,
+}
+
+/// Get the directory GVFS's FUSE daemon exposes its mounts in.
+fn fuse_dir() -> Option<PathBuf> {
+    Some(dirs::runtime_dir()?.join("gvfs"))
+}
+
+/// List the remote locations currently mounted by GVFS.
+///
+/// Mounts are found through the FUSE daemon, so this works without linking against gio.
+#[cfg_attr(not(feature = "egui"), allow(dead_code))]
+pub fn mounts() -> Vec<Mount> {
+    let Some(read_dir) = fuse_dir().and_then(|dir| std::fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+
+    let mut mounts: Vec<Mount> = read_dir
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let file_name = entry.file_name();
+
+            let spec = Spec::parse(file_name.to_str()?)?;
+
+            Some(Mount {
+                name: spec.name(),
+                path: entry.path(),
+            })
+        })
+        .collect();
+
+    mounts.sort_by(|a, b| a.name.cmp(&b.name));
+
+    mounts
+}
+
+/// Convert a path inside a GVFS FUSE mount to the URI of the remote location it refers to.
+///
+/// Returns `None` for local paths and for mount types without a well-known URI scheme.
+pub fn remote_uri(path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(fuse_dir()?).ok()?;
+
+    let mut components = relative.components();
+
+    let spec = Spec::parse(components.next()?.as_os_str().to_str()?)?;
+
+    spec.uri(components.as_path())
+}
+
+/// `Spec` is a GVFS mount spec like `sftp:host=example.com,user=bob`, as used for FUSE dir names.
+struct Spec<'a> {
+    kind: &'a str,
+    keys: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Spec<'a> {
+    /// Parse a mount spec.
+    fn parse(spec: &'a str) -> Option<Self> {
+        let (kind, keys) = spec.split_once(':')?;
+
+        let keys = keys
+            .split(',')
+            .filter_map(|key| key.split_once('='))
+            .collect();
+
+        Some(Self { kind, keys })
+    }
+
+    /// Get the value of a key; values are kept URI-escaped as GVFS stores them.
+    fn get(&self, key: &str) -> Option<&'a str> {
+        self.keys.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+    }
+
+    /// Get a human readable name for the mount.
+    fn name(&self) -> String {
+        let host = self
+            .get("host")
+            .or_else(|| self.get("server"))
+            .unwrap_or_default();
+
+        match self.get("share").or_else(|| self.get("volume")) {
+            Some(share) => format!("{} on {}", decode(share), decode(host)),
+            None if host.is_empty() => self.kind.to_owned(),
+            None => format!("{} ({})", decode(host), self.kind),
+        }
+    }
+
+    /// Build the URI of `path` inside the mount.
+    fn uri(&self, path: &Path) -> Option<String> {
+        let user = self.get("user").map(|user| format!("{}@", user));
+        let user = user.as_deref().unwrap_or_default();
+
+        let port = self.get("port").map(|port| format!(":{}", port));
+        let port = port.as_deref().unwrap_or_default();
+
+        let path = crate::uri::encode_path(path);
+
+        let separator = match path.is_empty() {
+            true => "",
+            false => "/",
+        };
+
+        match self.kind {
+            "sftp" | "ftp" | "ftps" => Some(format!(
+                "{}://{}{}{}{}{}",
+                self.kind,
+                user,
+                self.get("host")?,
+                port,
+                separator,
+                path
+            )),
+
+            "dav" => Some(format!(
+                "{}://{}{}{}{}{}{}",
+                match self.get("ssl") {
+                    Some("true") => "davs",
+                    _ => "dav",
+                },
+                user,
+                self.get("host")?,
+                port,
+                crate::uri::encode_path(Path::new(&decode(self.get("prefix").unwrap_or_default()))),
+                separator,
+                path
+            )),
+
+            "smb-share" => Some(format!(
+                "smb://{}{}{}{}/{}{}{}",
+                self.get("domain")
+                    .map(|domain| format!("{};", domain))
+                    .unwrap_or_default(),
+                user,
+                self.get("server")?,
+                port,
+                self.get("share")?,
+                separator,
+                path
+            )),
+
+            "afp-volume" => Some(format!(
+                "afp://{}{}/{}{}{}",
+                user,
+                self.get("host")?,
+                self.get("volume")?,
+                separator,
+                path
+            )),
+
+            _ => None,
+        }
+    }
+}
+
+/// Decode a URI-escaped value for display.
+fn decode(value: &str) -> String {
+    percent_encoding::percent_decode_str(value)
+        .decode_utf8_lossy()
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::Spec;
+
+    fn uri(spec: &str, path: &str) -> Option<String> {
+        Spec::parse(spec)?.uri(Path::new(path))
+    }
+
+    #[test]
+    fn sftp() {
+        assert_eq!(
+            uri("sftp:host=example.com,user=bob", "home/bob/notes.txt").as_deref(),
+            Some("sftp://bob@example.com/home/bob/notes.txt")
+        );
+
+        assert_eq!(
+            uri("sftp:host=example.com,port=2222", "").as_deref(),
+            Some("sftp://example.com:2222")
+        );
+    }
+
+    #[test]
+    fn smb_and_dav() {
+        assert_eq!(
+            uri("smb-share:server=nas,share=media", "My Movies/a.mkv").as_deref(),
+            Some("smb://nas/media/My%20Movies/a.mkv")
+        );
+
+        assert_eq!(
+            uri(
+                "dav:host=cloud.example.com,ssl=true,prefix=%2Fremote.php",
+                "a.txt"
+            )
+            .as_deref(),
+            Some("davs://cloud.example.com/remote.php/a.txt")
+        );
+    }
+
+    #[test]
+    fn unknown_kind() {
+        assert_eq!(uri("mtp:host=Phone", "DCIM"), None);
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(
+            Spec::parse("sftp:host=example.com,user=bob")
+                .unwrap()
+                .name(),
+            "example.com (sftp)"
+        );
+
+        assert_eq!(
+            Spec::parse("smb-share:server=nas,share=my%20media")
+                .unwrap()
+                .name(),
+            "my media on nas"
+        );
+    }
+}
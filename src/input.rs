@@ -0,0 +1,121 @@
+use std::sync::Arc;
+
+use crate::{
+    capture::Capture,
+    config::{InputBackend, RemoteDesktopConfig},
+};
+
+#[cfg(feature = "libei")]
+mod eis;
+mod keymap;
+#[cfg(feature = "libei")]
+mod libei;
+mod uinput;
+
+#[cfg(feature = "libei")]
+pub use eis::serve;
+
+/// The device type bit of keyboards, in RemoteDesktop options and properties.
+pub const KEYBOARD: u32 = 1;
+
+/// The device type bit of pointers.
+pub const POINTER: u32 = 2;
+
+/// The device type bit of touchscreens.
+pub const TOUCHSCREEN: u32 = 4;
+
+/// `Input` injects the input of remote desktop sessions into the compositor.
+pub trait Input: Send + Sync {
+    /// The device type bits of what can be controlled.
+    fn device_types(&self) -> u32;
+
+    /// Connect the devices of the given `types` for a session, once the user allowed it.
+    ///
+    /// Connecting blocks until the compositor accepted the devices, so callers run it off
+    /// the async executor.
+    fn connect(&self, types: u32) -> std::io::Result<Box<dyn Devices>>;
+}
+
+/// `Devices` are the connected devices of a remote desktop session, which are disconnected
+/// once they're dropped.
+///
+/// Events are queued and sent in order, so sending them doesn't block.
+pub trait Devices: Send {
+    /// Move the pointer by `dx` and `dy`, in logical pixels.
+    fn pointer_motion(&self, dx: f64, dy: f64) -> std::io::Result<()>;
+
+    /// Move the pointer to `x` and `y` in the compositor's layout.
+    fn pointer_motion_absolute(&self, x: f64, y: f64) -> std::io::Result<()>;
+
+    /// Press or release the evdev `button`.
+    fn pointer_button(&self, button: u32, pressed: bool) -> std::io::Result<()>;
+
+    /// Scroll smoothly by `dx` and `dy`, ending the scroll if `finish` is set.
+    fn pointer_axis(&self, dx: f64, dy: f64, finish: bool) -> std::io::Result<()>;
+
+    /// Scroll by `steps` wheel clicks, vertically if `axis` is 0 and horizontally otherwise.
+    fn pointer_axis_discrete(&self, axis: u32, steps: i32) -> std::io::Result<()>;
+
+    /// Press or release the evdev `keycode`.
+    fn keyboard_keycode(&self, keycode: u32, pressed: bool) -> std::io::Result<()>;
+
+    /// Press or release the key typing the X11 `keysym` with the compositor's keymap,
+    /// holding the modifiers it takes while it's down.
+    fn keyboard_keysym(&self, keysym: u32, pressed: bool) -> std::io::Result<()>;
+
+    /// Put a finger down at `x` and `y` in the compositor's layout, known as `slot` until
+    /// it's lifted.
+    fn touch_down(&self, slot: u32, x: f64, y: f64) -> std::io::Result<()>;
+
+    /// Move the finger of `slot` to `x` and `y` in the compositor's layout.
+    fn touch_motion(&self, slot: u32, x: f64, y: f64) -> std::io::Result<()>;
+
+    /// Lift the finger of `slot`.
+    fn touch_up(&self, slot: u32) -> std::io::Result<()>;
+}
+
+/// `Unsupported` stands in for input injection in builds without libei support,
+/// offering no devices.
+#[cfg(not(feature = "libei"))]
+struct Unsupported;
+
+#[cfg(not(feature = "libei"))]
+impl Input for Unsupported {
+    fn device_types(&self) -> u32 {
+        0
+    }
+
+    fn connect(&self, _types: u32) -> std::io::Result<Box<dyn Devices>> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "remote desktop input isn't supported by this build",
+        ))
+    }
+}
+
+/// Create the input backend selected in the config, which finds the outputs with `capture`.
+///
+/// Injecting input through libei needs the libei feature; without it, no devices are offered
+/// unless uinput is selected.
+pub fn from_config(config: &RemoteDesktopConfig, capture: Arc<dyn Capture>) -> Arc<dyn Input> {
+    log::debug!("injecting remote desktop input with {:?}", config.backend);
+
+    match config.backend {
+        InputBackend::Uinput => Arc::new(uinput::Uinput::new(capture)),
+        InputBackend::Libei => libei(),
+    }
+}
+
+/// Create the libei backend, if it's compiled in.
+fn libei() -> Arc<dyn Input> {
+    #[cfg(feature = "libei")]
+    let input: Arc<dyn Input> = Arc::new(libei::Libei);
+
+    #[cfg(not(feature = "libei"))]
+    let input: Arc<dyn Input> = {
+        log::info!("built without the libei feature, remote desktop input is unavailable");
+        Arc::new(Unsupported)
+    };
+
+    input
+}
@@ -0,0 +1,339 @@
+use std::{
+    os::{fd::OwnedFd, unix::net::UnixStream},
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    time::Duration,
+};
+
+use reis::{
+    eis::{self, connection::DisconnectReason, device::DeviceType, handshake::ContextType},
+    handshake::EisHandshaker,
+    request::{Device, DeviceCapability, DeviceInterface, EisRequest, EisRequestConverter, Seat},
+    PendingRequestResult,
+};
+
+use super::libei::{now, readable};
+use crate::intercept::Captured;
+
+/// How often the socket is checked for what the app sent while no input is captured.
+const INTERVAL: Duration = Duration::from_millis(20);
+
+/// Serve the input captured for an InputCapture session to its app, as the EIS server of a
+/// libei receiver, returning the app's end of the socket and where captured input goes.
+///
+/// The connection is served by a thread of its own, until the sender is dropped or the app
+/// disconnects.
+pub fn serve() -> std::io::Result<(OwnedFd, Sender<Captured>)> {
+    let (ours, theirs) = UnixStream::pair()?;
+
+    let context = eis::Context::new(ours)?;
+
+    let (events, receiver) = mpsc::channel();
+
+    std::thread::Builder::new()
+        .name(String::from("eis"))
+        .spawn(move || run(context, receiver))?;
+
+    Ok((theirs.into(), events))
+}
+
+/// `Server` is the EIS side of an app's connection.
+struct Server {
+    context: eis::Context,
+
+    /// Finishes the handshake, until it's done.
+    handshaker: EisHandshaker,
+
+    /// Tracks what the app bound, once the handshake is done.
+    converter: Option<EisRequestConverter>,
+
+    seat: Option<Seat>,
+    devices: Vec<Device>,
+
+    /// The activation input is captured for, if it is.
+    activation: Option<u32>,
+
+    /// The devices that sent events since the last frame.
+    framing: Vec<Device>,
+}
+
+/// Send the `events` captured for a session to the app connected through `context`.
+fn run(context: eis::Context, events: Receiver<Captured>) {
+    let mut server = Server {
+        handshaker: EisHandshaker::new(&context, 0),
+        context,
+        converter: None,
+        seat: None,
+        devices: Vec::new(),
+        activation: None,
+        framing: Vec::new(),
+    };
+
+    loop {
+        match events.recv_timeout(INTERVAL) {
+            Ok(event) => server.emit(event),
+            Err(RecvTimeoutError::Timeout) => {}
+
+            Err(RecvTimeoutError::Disconnected) => {
+                server.disconnect();
+                return;
+            }
+        }
+
+        match server.dispatch() {
+            Ok(true) => {}
+            Ok(false) => return,
+
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                log::debug!("an app closed its EIS connection");
+                return;
+            }
+
+            Err(e) => {
+                log::warn!("lost the EIS connection of an app: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+impl Server {
+    /// Handle what the app sent, returning whether it's still connected.
+    fn dispatch(&mut self) -> std::io::Result<bool> {
+        if readable(&self.context, Duration::ZERO)? {
+            self.context.read()?;
+
+            while let Some(result) = self.context.pending_request() {
+                let request = match result {
+                    PendingRequestResult::Request(request) => request,
+
+                    PendingRequestResult::ParseError(e) => {
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            e.to_string(),
+                        ))
+                    }
+
+                    PendingRequestResult::InvalidObject(id) => {
+                        if let Some(converter) = &self.converter {
+                            let connection = converter.handle();
+
+                            connection
+                                .connection()
+                                .invalid_object(connection.last_serial(), id);
+                        }
+
+                        continue;
+                    }
+                };
+
+                match &mut self.converter {
+                    Some(converter) => converter
+                        .handle_request(request)
+                        .map_err(std::io::Error::other)?,
+
+                    None => {
+                        let Some(response) = self
+                            .handshaker
+                            .handle_request(request)
+                            .map_err(std::io::Error::other)?
+                        else {
+                            continue;
+                        };
+
+                        // Captured input is sent to apps, which don't send any.
+                        if response.context_type != ContextType::Receiver {
+                            return Err(std::io::Error::new(
+                                std::io::ErrorKind::InvalidData,
+                                "the app connected as a sender",
+                            ));
+                        }
+
+                        let converter = EisRequestConverter::new(&self.context, response, 1);
+
+                        self.seat = Some(converter.handle().add_seat(
+                            Some("default"),
+                            &[
+                                DeviceCapability::Pointer,
+                                DeviceCapability::Button,
+                                DeviceCapability::Scroll,
+                                DeviceCapability::Keyboard,
+                            ],
+                        ));
+
+                        self.converter = Some(converter);
+                    }
+                }
+            }
+        }
+
+        while let Some(request) = self
+            .converter
+            .as_mut()
+            .and_then(|converter| converter.next_request())
+        {
+            match request {
+                EisRequest::Disconnect => return Ok(false),
+                EisRequest::Bind(bind) => self.bind(bind.capabilities),
+                _ => {}
+            }
+        }
+
+        self.flush();
+
+        Ok(true)
+    }
+
+    /// Add the devices of the capabilities the app bound on the seat, replacing those added
+    /// before.
+    fn bind(&mut self, capabilities: u64) {
+        let (Some(converter), Some(seat)) = (&self.converter, &self.seat) else {
+            return;
+        };
+
+        for device in self.devices.drain(..) {
+            device.remove();
+        }
+
+        self.framing.clear();
+
+        let connection = converter.handle();
+
+        let bound = |capability: DeviceCapability, interface: &str| {
+            capabilities & 2 << capability as u64 != 0 && connection.has_interface(interface)
+        };
+
+        let pointer: Vec<DeviceCapability> = [
+            (DeviceCapability::Pointer, "ei_pointer"),
+            (DeviceCapability::Button, "ei_button"),
+            (DeviceCapability::Scroll, "ei_scroll"),
+        ]
+        .into_iter()
+        .filter(|&(capability, interface)| bound(capability, interface))
+        .map(|(capability, _)| capability)
+        .collect();
+
+        if !pointer.is_empty() {
+            self.devices.push(seat.add_device(
+                Some("captured pointer"),
+                DeviceType::Virtual,
+                &pointer,
+                |_| {},
+            ));
+        }
+
+        if bound(DeviceCapability::Keyboard, "ei_keyboard") {
+            self.devices.push(seat.add_device(
+                Some("captured keyboard"),
+                DeviceType::Virtual,
+                &[DeviceCapability::Keyboard],
+                |_| {},
+            ));
+        }
+
+        for device in &self.devices {
+            device.resumed();
+
+            if let Some(activation) = self.activation {
+                device.start_emulating(activation);
+            }
+        }
+    }
+
+    /// Send `event` to the app, if input is captured and the app bound a device taking it.
+    fn emit(&mut self, event: Captured) {
+        match event {
+            Captured::Start(activation) => {
+                self.activation = Some(activation);
+
+                for device in &self.devices {
+                    device.start_emulating(activation);
+                }
+            }
+
+            Captured::Stop => {
+                self.frame();
+                self.activation = None;
+
+                for device in &self.devices {
+                    device.stop_emulating();
+                }
+            }
+
+            _ if self.activation.is_none() => return,
+
+            Captured::Motion(dx, dy) => self.on::<eis::Pointer>(|pointer| {
+                pointer.motion_relative(dx as f32, dy as f32);
+            }),
+
+            Captured::Button(button, pressed) => self.on::<eis::Button>(|device| {
+                let state = match pressed {
+                    true => eis::button::ButtonState::Press,
+                    false => eis::button::ButtonState::Released,
+                };
+
+                device.button(button, state);
+            }),
+
+            Captured::Scroll(dx, dy) => self.on::<eis::Scroll>(|scroll| {
+                scroll.scroll_discrete(dx, dy);
+            }),
+
+            Captured::Key(keycode, pressed) => self.on::<eis::Keyboard>(|keyboard| {
+                let state = match pressed {
+                    true => eis::keyboard::KeyState::Press,
+                    false => eis::keyboard::KeyState::Released,
+                };
+
+                keyboard.key(keycode, state);
+            }),
+
+            Captured::Frame => self.frame(),
+        }
+
+        self.flush();
+    }
+
+    /// Run `f` on the `T` interface of the first device having it, which the event is framed
+    /// on.
+    fn on<T: DeviceInterface>(&mut self, f: impl FnOnce(T)) {
+        let Some((device, interface)) = self
+            .devices
+            .iter()
+            .find_map(|device| Some((device, device.interface::<T>()?)))
+        else {
+            return;
+        };
+
+        f(interface);
+
+        if !self.framing.contains(device) {
+            self.framing.push(device.clone());
+        }
+    }
+
+    /// End the frame of the devices that sent events since the last one.
+    fn frame(&mut self) {
+        let time = now();
+
+        for device in self.framing.drain(..) {
+            device.frame(time);
+        }
+    }
+
+    /// Tell the app the session closed.
+    fn disconnect(&self) {
+        if let Some(converter) = &self.converter {
+            converter
+                .handle()
+                .disconnected(DisconnectReason::Disconnected, "the session closed");
+        }
+
+        self.flush();
+    }
+
+    /// Send what's queued.
+    fn flush(&self) {
+        // A full socket is flushed with the next event; a closed one shows up on the next read.
+        let _ = self.context.flush();
+    }
+}
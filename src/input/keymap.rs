@@ -0,0 +1,244 @@
+use std::ffi::CString;
+
+use xkbcommon_dl::{
+    xkb_context, xkb_context_flags, xkb_keymap, xkb_keymap_compile_flags, xkb_keymap_format,
+    xkbcommon_option, XkbCommon,
+};
+
+/// `KEY_LEFTSHIFT`, from `linux/input-event-codes.h`.
+const KEY_LEFTSHIFT: u32 = 42;
+
+/// `KEY_RIGHTALT`, which is AltGr in layouts having it.
+const KEY_RIGHTALT: u32 = 100;
+
+/// The modifiers reaching the levels of keys of the usual key types: none, Shift, AltGr,
+/// and both.
+const LEVELS: [&[u32]; 4] = [
+    &[],
+    &[KEY_LEFTSHIFT],
+    &[KEY_RIGHTALT],
+    &[KEY_LEFTSHIFT, KEY_RIGHTALT],
+];
+
+/// XKB keycodes are evdev keycodes moved past those X11 reserves.
+const EVDEV_OFFSET: u32 = 8;
+
+/// `Keymap` is an XKB keymap, which tells the keys typing keysyms.
+///
+/// libxkbcommon is loaded when the first keymap is compiled, so it's only needed once
+/// keysyms are sent.
+pub struct Keymap {
+    xkb: &'static XkbCommon,
+    keymap: *mut xkb_keymap,
+}
+
+// Keymaps don't change once they're compiled, so xkbcommon lets any thread read them.
+unsafe impl Send for Keymap {}
+
+impl Keymap {
+    /// Compile a keymap in the XKB text format, as compositors hand them to clients.
+    #[cfg_attr(not(feature = "libei"), allow(dead_code))]
+    pub fn from_string(text: &str) -> std::io::Result<Self> {
+        let text = CString::new(text.trim_end_matches('\0'))
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+        Self::compile(|xkb, context| unsafe {
+            (xkb.xkb_keymap_new_from_string)(
+                context,
+                text.as_ptr(),
+                xkb_keymap_format::XKB_KEYMAP_FORMAT_TEXT_V1,
+                xkb_keymap_compile_flags::XKB_KEYMAP_COMPILE_NO_FLAGS,
+            )
+        })
+    }
+
+    /// Compile the default keymap, which `XKB_DEFAULT_LAYOUT` and the like pick,
+    /// as they do for most compositors.
+    pub fn from_env() -> std::io::Result<Self> {
+        Self::compile(|xkb, context| unsafe {
+            (xkb.xkb_keymap_new_from_names)(
+                context,
+                std::ptr::null(),
+                xkb_keymap_compile_flags::XKB_KEYMAP_COMPILE_NO_FLAGS,
+            )
+        })
+    }
+
+    /// Compile a keymap with `f`, in a context of its own.
+    fn compile(
+        f: impl FnOnce(&XkbCommon, *mut xkb_context) -> *mut xkb_keymap,
+    ) -> std::io::Result<Self> {
+        let xkb = xkbcommon_option().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "libxkbcommon isn't installed")
+        })?;
+
+        let context = unsafe { (xkb.xkb_context_new)(xkb_context_flags::XKB_CONTEXT_NO_FLAGS) };
+
+        if context.is_null() {
+            return Err(std::io::Error::other(
+                "failed to create an xkbcommon context",
+            ));
+        }
+
+        // The keymap holds on to the context as long as it needs it.
+        let keymap = f(xkb, context);
+
+        unsafe { (xkb.xkb_context_unref)(context) };
+
+        if keymap.is_null() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "failed to compile the keymap",
+            ));
+        }
+
+        Ok(Self { xkb, keymap })
+    }
+
+    /// Find the evdev keycodes to press, in order, to type `keysym` with the layout at index
+    /// `layout`: the modifiers reaching the level it's on, then its key.
+    ///
+    /// Keys typing it without modifiers are preferred. Levels past Shift and AltGr take
+    /// modifiers that differ between layouts, so keysyms only found there can't be typed.
+    pub fn keys(&self, keysym: u32, layout: u32) -> Option<Vec<u32>> {
+        let xkb = self.xkb;
+
+        let (min, max) = unsafe {
+            (
+                (xkb.xkb_keymap_min_keycode)(self.keymap),
+                (xkb.xkb_keymap_max_keycode)(self.keymap),
+            )
+        };
+
+        let mut found: Option<(usize, u32)> = None;
+
+        for keycode in min.max(EVDEV_OFFSET)..=max {
+            let layouts = unsafe { (xkb.xkb_keymap_num_layouts_for_key)(self.keymap, keycode) };
+
+            if layouts == 0 {
+                continue;
+            }
+
+            // Keys with fewer layouts than the keymap wrap around, as in xkbcommon.
+            let layout = layout % layouts;
+
+            let levels =
+                unsafe { (xkb.xkb_keymap_num_levels_for_key)(self.keymap, keycode, layout) };
+
+            for level in 0..(levels as usize).min(LEVELS.len()) {
+                if found.is_some_and(|(best, _)| best <= level) {
+                    break;
+                }
+
+                if self.syms(keycode, layout, level as u32).contains(&keysym) {
+                    found = Some((level, keycode));
+                }
+            }
+        }
+
+        let (level, keycode) = found?;
+
+        let mut keys = LEVELS[level].to_vec();
+
+        keys.push(keycode - EVDEV_OFFSET);
+
+        Some(keys)
+    }
+
+    /// Press or release the keys of `keysym` in `layout` with `key`: the modifiers first when
+    /// pressing, and last when releasing.
+    pub fn send(
+        &self,
+        keysym: u32,
+        layout: u32,
+        pressed: bool,
+        mut key: impl FnMut(u32, bool) -> std::io::Result<()>,
+    ) -> std::io::Result<()> {
+        let mut keys = self.keys(keysym, layout).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("the keysym {:#x} can't be typed with the keymap", keysym),
+            )
+        })?;
+
+        if !pressed {
+            keys.reverse();
+        }
+
+        keys.into_iter()
+            .try_for_each(|keycode| key(keycode, pressed))
+    }
+
+    /// Get the keysyms of `keycode` at `level` of `layout`.
+    fn syms(&self, keycode: u32, layout: u32, level: u32) -> &[u32] {
+        let mut syms = std::ptr::null();
+
+        let count = unsafe {
+            (self.xkb.xkb_keymap_key_get_syms_by_level)(
+                self.keymap,
+                keycode,
+                layout,
+                level,
+                &mut syms,
+            )
+        };
+
+        match count {
+            count if count > 0 && !syms.is_null() => unsafe {
+                std::slice::from_raw_parts(syms, count as usize)
+            },
+            _ => &[],
+        }
+    }
+}
+
+impl Drop for Keymap {
+    fn drop(&mut self) {
+        unsafe { (self.xkb.xkb_keymap_unref)(self.keymap) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Keymap;
+
+    /// A keymap of a single key typing `a` and `A`, without includes so it compiles
+    /// without XKB data.
+    const KEYMAP: &str = r#"
+        xkb_keymap {
+            xkb_keycodes {
+                minimum = 8;
+                maximum = 255;
+                <AC01> = 38;
+            };
+            xkb_types {
+                type "ALPHABETIC" {
+                    modifiers = Shift;
+                    map[Shift] = Level2;
+                    level_name[Level1] = "Base";
+                    level_name[Level2] = "Caps";
+                };
+            };
+            xkb_compatibility {
+            };
+            xkb_symbols {
+                key <AC01> { type = "ALPHABETIC", [ a, A ] };
+            };
+        };
+    "#;
+
+    #[test]
+    fn keys_of_keysyms() {
+        // Machines without libxkbcommon can't compile keymaps at all.
+        if xkbcommon_dl::xkbcommon_option().is_none() {
+            return;
+        }
+
+        let keymap = Keymap::from_string(KEYMAP).unwrap();
+
+        // `a` is KEY_A, and `A` takes Shift.
+        assert_eq!(keymap.keys(0x61, 0), Some(vec![30]));
+        assert_eq!(keymap.keys(0x41, 0), Some(vec![42, 30]));
+        assert_eq!(keymap.keys(0x62, 0), None);
+    }
+}
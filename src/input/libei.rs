@@ -0,0 +1,513 @@
+use std::{
+    fs::File,
+    os::{
+        fd::AsRawFd,
+        unix::{fs::FileExt, net::UnixStream},
+    },
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender},
+    time::{Duration, Instant},
+};
+
+use reis::{
+    ei,
+    event::{DeviceCapability, EiEvent, EiEventConverter},
+    handshake::{EiHandshaker, HandshakeResp},
+    PendingRequestResult,
+};
+
+use super::{keymap::Keymap, Devices, Input, KEYBOARD, POINTER, TOUCHSCREEN};
+
+/// The name the compositor knows the connections of this backend by.
+const NAME: &str = "xdg-desktop-portal-rs";
+
+/// How long the compositor gets to answer the handshake.
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How often the connection is checked for the compositor's events while no input comes in,
+/// which it pings to see the client is still there.
+const INTERVAL: Duration = Duration::from_millis(20);
+
+/// `Libei` injects input as a libei sender, connecting to the EIS socket the compositor
+/// exports in `LIBEI_SOCKET` or `$XDG_RUNTIME_DIR/eis-0`.
+///
+/// Each session has its own connection, served by a thread of its own.
+pub struct Libei;
+
+impl Input for Libei {
+    fn device_types(&self) -> u32 {
+        match socket() {
+            Some(_) => KEYBOARD | POINTER | TOUCHSCREEN,
+            None => 0,
+        }
+    }
+
+    fn connect(&self, types: u32) -> std::io::Result<Box<dyn Devices>> {
+        let (events, receiver) = mpsc::channel();
+
+        let (ready, connected) = mpsc::sync_channel(1);
+
+        std::thread::Builder::new()
+            .name(String::from("libei"))
+            .spawn(move || run(types, receiver, ready))?;
+
+        connected
+            .recv()
+            .map_err(|_| std::io::Error::other("the libei thread died"))??;
+
+        Ok(Box::new(Connection { events }))
+    }
+}
+
+/// `Event` is an input event on its way to the thread sending it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Event {
+    Motion(f64, f64),
+    MotionAbsolute(f64, f64),
+    Button(u32, bool),
+    Axis(f64, f64, bool),
+    AxisDiscrete(u32, i32),
+    Key(u32, bool),
+    Keysym(u32, bool),
+    TouchDown(u32, f64, f64),
+    TouchMotion(u32, f64, f64),
+    TouchUp(u32),
+}
+
+/// `Connection` is the connected devices of a session, which hands events to its thread.
+struct Connection {
+    events: Sender<Event>,
+}
+
+impl Connection {
+    fn send(&self, event: Event) -> std::io::Result<()> {
+        self.events.send(event).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                "the compositor closed the libei connection",
+            )
+        })
+    }
+}
+
+impl Devices for Connection {
+    fn pointer_motion(&self, dx: f64, dy: f64) -> std::io::Result<()> {
+        self.send(Event::Motion(dx, dy))
+    }
+
+    fn pointer_motion_absolute(&self, x: f64, y: f64) -> std::io::Result<()> {
+        self.send(Event::MotionAbsolute(x, y))
+    }
+
+    fn pointer_button(&self, button: u32, pressed: bool) -> std::io::Result<()> {
+        self.send(Event::Button(button, pressed))
+    }
+
+    fn pointer_axis(&self, dx: f64, dy: f64, finish: bool) -> std::io::Result<()> {
+        self.send(Event::Axis(dx, dy, finish))
+    }
+
+    fn pointer_axis_discrete(&self, axis: u32, steps: i32) -> std::io::Result<()> {
+        self.send(Event::AxisDiscrete(axis, steps))
+    }
+
+    fn keyboard_keycode(&self, keycode: u32, pressed: bool) -> std::io::Result<()> {
+        self.send(Event::Key(keycode, pressed))
+    }
+
+    fn keyboard_keysym(&self, keysym: u32, pressed: bool) -> std::io::Result<()> {
+        self.send(Event::Keysym(keysym, pressed))
+    }
+
+    fn touch_down(&self, slot: u32, x: f64, y: f64) -> std::io::Result<()> {
+        self.send(Event::TouchDown(slot, x, y))
+    }
+
+    fn touch_motion(&self, slot: u32, x: f64, y: f64) -> std::io::Result<()> {
+        self.send(Event::TouchMotion(slot, x, y))
+    }
+
+    fn touch_up(&self, slot: u32) -> std::io::Result<()> {
+        self.send(Event::TouchUp(slot))
+    }
+}
+
+/// `Client` is the libei side of a session's connection.
+struct Client {
+    context: ei::Context,
+    converter: EiEventConverter,
+
+    /// The capabilities bound on every seat.
+    capabilities: Vec<DeviceCapability>,
+
+    /// The devices the compositor lets the client emulate input on.
+    emulating: Vec<reis::event::Device>,
+
+    /// The sequence of the next emulation, which goes up with each.
+    sequence: u32,
+
+    /// The keymap of the compositor's keyboard, which keysyms are typed with.
+    keymap: Option<Keymap>,
+
+    /// The active layout of the keymap.
+    group: u32,
+}
+
+/// Connect to the compositor, report whether that worked on `ready`, then send the `events`
+/// of a session until its devices are dropped or the compositor disconnects.
+fn run(types: u32, events: Receiver<Event>, ready: SyncSender<std::io::Result<()>>) {
+    let mut client = match Client::connect(types) {
+        Ok(client) => {
+            let _ = ready.send(Ok(()));
+            client
+        }
+
+        Err(e) => {
+            let _ = ready.send(Err(e));
+            return;
+        }
+    };
+
+    loop {
+        match events.recv_timeout(INTERVAL) {
+            Ok(event) => client.emit(event),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+
+        match client.dispatch() {
+            Ok(true) => {}
+            Ok(false) => return,
+
+            Err(e) => {
+                log::warn!("lost the libei connection: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+impl Client {
+    /// Connect to the compositor, asking for the devices of the given `types`.
+    fn connect(types: u32) -> std::io::Result<Self> {
+        let path = socket().ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "the compositor doesn't export an EIS socket",
+            )
+        })?;
+
+        let context = ei::Context::new(UnixStream::connect(path)?)?;
+
+        let response = handshake(&context)?;
+
+        let mut capabilities = Vec::new();
+
+        if types & POINTER != 0 {
+            capabilities.extend([
+                DeviceCapability::Pointer,
+                DeviceCapability::PointerAbsolute,
+                DeviceCapability::Button,
+                DeviceCapability::Scroll,
+            ]);
+        }
+
+        if types & KEYBOARD != 0 {
+            capabilities.push(DeviceCapability::Keyboard);
+        }
+
+        if types & TOUCHSCREEN != 0 {
+            capabilities.push(DeviceCapability::Touch);
+        }
+
+        Ok(Self {
+            converter: EiEventConverter::new(&context, response),
+            context,
+            capabilities,
+            emulating: Vec::new(),
+            sequence: 0,
+            keymap: None,
+            group: 0,
+        })
+    }
+
+    /// Handle what the compositor sent, returning whether it's still connected.
+    fn dispatch(&mut self) -> std::io::Result<bool> {
+        if readable(&self.context, Duration::ZERO)? {
+            self.context.read()?;
+
+            while let Some(result) = self.context.pending_event() {
+                match result {
+                    PendingRequestResult::Request(event) => self
+                        .converter
+                        .handle_event(event)
+                        .map_err(std::io::Error::other)?,
+
+                    PendingRequestResult::ParseError(e) => {
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            e.to_string(),
+                        ))
+                    }
+
+                    // Events of objects the client destroyed are still on their way.
+                    PendingRequestResult::InvalidObject(_) => {}
+                }
+            }
+        }
+
+        while let Some(event) = self.converter.next_event() {
+            match event {
+                EiEvent::SeatAdded(added) => added.seat.bind_capabilities(&self.capabilities),
+
+                EiEvent::DeviceAdded(added) => {
+                    if let Some(keymap) = added.device.keymap() {
+                        match load(keymap) {
+                            Ok(keymap) => self.keymap = Some(keymap),
+                            Err(e) => log::warn!("failed to load the keymap of libei: {}", e),
+                        }
+                    }
+                }
+
+                EiEvent::KeyboardModifiers(modifiers) => self.group = modifiers.group,
+
+                EiEvent::DeviceResumed(resumed) => {
+                    resumed
+                        .device
+                        .device()
+                        .start_emulating(resumed.serial, self.sequence);
+
+                    self.sequence = self.sequence.wrapping_add(1);
+                    self.emulating.push(resumed.device);
+                }
+
+                EiEvent::DevicePaused(paused) => {
+                    self.emulating.retain(|device| *device != paused.device);
+                }
+
+                EiEvent::DeviceRemoved(removed) => {
+                    self.emulating.retain(|device| *device != removed.device);
+                }
+
+                EiEvent::Disconnected(disconnected) => {
+                    log::info!(
+                        "the compositor closed the libei connection: {}",
+                        disconnected.explanation
+                    );
+                    return Ok(false);
+                }
+
+                _ => {}
+            }
+        }
+
+        self.flush();
+
+        Ok(true)
+    }
+
+    /// Send `event` on the first device that can, as a frame of its own.
+    ///
+    /// Events no device can take, such as those sent before the compositor resumed any,
+    /// are dropped.
+    fn emit(&self, event: Event) {
+        let sent = match event {
+            Event::Motion(dx, dy) => self.on::<ei::Pointer>(|pointer| {
+                pointer.motion_relative(dx as f32, dy as f32);
+            }),
+
+            Event::MotionAbsolute(x, y) => self.on::<ei::PointerAbsolute>(|pointer| {
+                pointer.motion_absolute(x as f32, y as f32);
+            }),
+
+            Event::Button(button, pressed) => self.on::<ei::Button>(|device| {
+                let state = match pressed {
+                    true => ei::button::ButtonState::Press,
+                    false => ei::button::ButtonState::Released,
+                };
+
+                device.button(button, state);
+            }),
+
+            Event::Axis(dx, dy, finish) => self.on::<ei::Scroll>(|scroll| {
+                scroll.scroll(dx as f32, dy as f32);
+
+                if finish {
+                    scroll.scroll_stop(1, 1, 0);
+                }
+            }),
+
+            // libei counts wheel clicks in 120ths, like high-resolution scroll wheels.
+            Event::AxisDiscrete(axis, steps) => self.on::<ei::Scroll>(|scroll| {
+                match axis {
+                    0 => scroll.scroll_discrete(0, steps * 120),
+                    _ => scroll.scroll_discrete(steps * 120, 0),
+                };
+            }),
+
+            Event::Key(keycode, pressed) => self.on::<ei::Keyboard>(|keyboard| {
+                let state = match pressed {
+                    true => ei::keyboard::KeyState::Press,
+                    false => ei::keyboard::KeyState::Released,
+                };
+
+                keyboard.key(keycode, state);
+            }),
+
+            Event::Keysym(keysym, pressed) => match &self.keymap {
+                Some(keymap) => {
+                    let typed = keymap.send(keysym, self.group, pressed, |keycode, pressed| {
+                        self.emit(Event::Key(keycode, pressed));
+                        Ok(())
+                    });
+
+                    if let Err(e) = typed {
+                        log::warn!("dropping a keysym: {}", e);
+                    }
+
+                    true
+                }
+
+                None => false,
+            },
+
+            Event::TouchDown(slot, x, y) => self.on::<ei::Touchscreen>(|touchscreen| {
+                touchscreen.down(slot, x as f32, y as f32);
+            }),
+
+            Event::TouchMotion(slot, x, y) => self.on::<ei::Touchscreen>(|touchscreen| {
+                touchscreen.motion(slot, x as f32, y as f32);
+            }),
+
+            Event::TouchUp(slot) => self.on::<ei::Touchscreen>(|touchscreen| {
+                touchscreen.up(slot);
+            }),
+        };
+
+        if !sent {
+            log::debug!("no libei device takes {:?}, dropping it", event);
+        }
+
+        self.flush();
+    }
+
+    /// Run `f` on the `T` interface of the first emulating device having it, ending the frame,
+    /// and return whether there was one.
+    fn on<T: ei::Interface>(&self, f: impl FnOnce(T)) -> bool {
+        let Some((device, interface)) = self
+            .emulating
+            .iter()
+            .find_map(|device| Some((device, device.interface::<T>()?)))
+        else {
+            return false;
+        };
+
+        f(interface);
+
+        device
+            .device()
+            .frame(self.converter.connection().serial(), now());
+
+        true
+    }
+
+    /// Send what's queued.
+    fn flush(&self) {
+        // A full socket is flushed with the next event; a closed one shows up on the next read.
+        let _ = self.context.flush();
+    }
+}
+
+/// Compile a keymap the compositor sent.
+fn load(keymap: &reis::event::Keymap) -> std::io::Result<Keymap> {
+    let mut text = vec![0; keymap.size as usize];
+
+    // Reading at an offset leaves the position of the file alone, which the compositor shares.
+    File::from(keymap.fd.try_clone()?).read_exact_at(&mut text, 0)?;
+
+    Keymap::from_string(&String::from_utf8_lossy(&text))
+}
+
+/// Do the handshake of a sender with the compositor.
+fn handshake(context: &ei::Context) -> std::io::Result<HandshakeResp> {
+    let mut handshaker = EiHandshaker::new(NAME, ei::handshake::ContextType::Sender);
+
+    let deadline = Instant::now() + TIMEOUT;
+
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+
+        if left.is_zero() || !readable(context, left)? {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "the compositor didn't answer the libei handshake",
+            ));
+        }
+
+        context.read()?;
+
+        while let Some(result) = context.pending_event() {
+            let PendingRequestResult::Request(event) = result else {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "the compositor sent an invalid libei handshake",
+                ));
+            };
+
+            if let Some(response) = handshaker
+                .handle_event(event)
+                .map_err(std::io::Error::other)?
+            {
+                return Ok(response);
+            }
+        }
+    }
+}
+
+/// Wait up to `timeout` for `fd` to become readable, returning whether it did.
+pub(super) fn readable(fd: &impl AsRawFd, timeout: Duration) -> std::io::Result<bool> {
+    let mut pollfd = libc::pollfd {
+        fd: fd.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+
+    let timeout = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+
+    match unsafe { libc::poll(&mut pollfd, 1, timeout) } {
+        count if count >= 0 => Ok(count > 0),
+
+        _ => match std::io::Error::last_os_error() {
+            e if e.kind() == std::io::ErrorKind::Interrupted => Ok(false),
+            e => Err(e),
+        },
+    }
+}
+
+/// Get the path of the compositor's EIS socket, if it has one.
+///
+/// Relative paths in `LIBEI_SOCKET` are relative to `$XDG_RUNTIME_DIR`, as in libei.
+fn socket() -> Option<PathBuf> {
+    let path = match std::env::var_os("LIBEI_SOCKET") {
+        Some(path) => match PathBuf::from(path) {
+            path if path.is_relative() => dirs::runtime_dir()?.join(path),
+            path => path,
+        },
+
+        None => reis::default_socket_path()?,
+    };
+
+    path.exists().then_some(path)
+}
+
+/// Get the time of a frame, in microseconds of `CLOCK_MONOTONIC`.
+pub(super) fn now() -> u64 {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut time) };
+
+    time.tv_sec as u64 * 1_000_000 + time.tv_nsec as u64 / 1_000
+}
@@ -0,0 +1,587 @@
+use std::{
+    cell::Cell,
+    fs::{File, OpenOptions},
+    io::Write,
+    os::{fd::AsRawFd, unix::fs::OpenOptionsExt},
+    sync::Arc,
+};
+
+use super::{keymap::Keymap, Devices, Input, KEYBOARD, POINTER, TOUCHSCREEN};
+use crate::capture::{Capture, Output};
+
+/// The device virtual input devices are made with.
+const UINPUT: &str = "/dev/uinput";
+
+/// Event types, from `linux/input-event-codes.h`.
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_REL: u16 = 0x02;
+const EV_ABS: u16 = 0x03;
+
+const SYN_REPORT: u16 = 0x00;
+
+const REL_X: u16 = 0x00;
+const REL_Y: u16 = 0x01;
+const REL_HWHEEL: u16 = 0x06;
+const REL_WHEEL: u16 = 0x08;
+const REL_WHEEL_HI_RES: u16 = 0x0b;
+const REL_HWHEEL_HI_RES: u16 = 0x0c;
+
+const ABS_X: u16 = 0x00;
+const ABS_Y: u16 = 0x01;
+const ABS_MT_SLOT: u16 = 0x2f;
+const ABS_MT_POSITION_X: u16 = 0x35;
+const ABS_MT_POSITION_Y: u16 = 0x36;
+const ABS_MT_TRACKING_ID: u16 = 0x39;
+
+const BTN_TOUCH: u16 = 0x14a;
+
+/// Marks devices whose positions are on the screen, like touchscreens.
+const INPUT_PROP_DIRECT: u16 = 0x01;
+
+/// `BTN_LEFT` up to `BTN_TASK`, the buttons of mice.
+const BUTTONS: std::ops::RangeInclusive<u16> = 0x110..=0x117;
+
+/// The keys of keyboards; codes from `BTN_MISC` on are buttons of other devices.
+const KEYS: std::ops::Range<u16> = 1..0x100;
+
+/// How many fingers the touchscreen tracks at once.
+const SLOTS: u32 = 10;
+
+/// The bus virtual devices claim to be on.
+const BUS_VIRTUAL: u16 = 0x06;
+
+/// How far a wheel click scrolls, in the logical pixels of smooth scrolling.
+const CLICK: f64 = 15.0;
+
+/// The high-resolution wheel counts a click as this many steps.
+const HI_RES_CLICK: i32 = 120;
+
+const UI_DEV_CREATE: u64 = io(1);
+const UI_DEV_SETUP: u64 = iow(3, std::mem::size_of::<libc::uinput_setup>());
+const UI_ABS_SETUP: u64 = iow(4, std::mem::size_of::<libc::uinput_abs_setup>());
+const UI_SET_EVBIT: u64 = iow(100, std::mem::size_of::<libc::c_int>());
+const UI_SET_KEYBIT: u64 = iow(101, std::mem::size_of::<libc::c_int>());
+const UI_SET_RELBIT: u64 = iow(102, std::mem::size_of::<libc::c_int>());
+const UI_SET_ABSBIT: u64 = iow(103, std::mem::size_of::<libc::c_int>());
+const UI_SET_PROPBIT: u64 = iow(110, std::mem::size_of::<libc::c_int>());
+
+/// `_IO('U', nr)`.
+const fn io(nr: u64) -> u64 {
+    (b'U' as u64) << 8 | nr
+}
+
+/// `_IOW('U', nr, size)`.
+const fn iow(nr: u64, size: usize) -> u64 {
+    1 << 30 | (size as u64) << 16 | io(nr)
+}
+
+/// `Uinput` injects input through virtual devices made with /dev/uinput, for compositors
+/// without libei support and X11 sessions.
+///
+/// The kernel only lets those who can write /dev/uinput make devices, which usually takes
+/// a udev rule like `KERNEL=="uinput", GROUP="input", MODE="0660"` and being in the `input`
+/// group. The devices are seen by everything reading input, not only the compositor.
+pub struct Uinput {
+    /// Tells where the outputs are, which absolute motion is relative to.
+    capture: Arc<dyn Capture>,
+}
+
+impl Uinput {
+    pub fn new(capture: Arc<dyn Capture>) -> Self {
+        if !writable() {
+            log::warn!(
+                "{} isn't writable, so remote desktop input is unavailable; \
+                 it takes a udev rule giving the user access, or being in its group",
+                UINPUT
+            );
+        }
+
+        Self { capture }
+    }
+}
+
+impl Input for Uinput {
+    fn device_types(&self) -> u32 {
+        match writable() {
+            true => KEYBOARD | POINTER | TOUCHSCREEN,
+            false => 0,
+        }
+    }
+
+    fn connect(&self, types: u32) -> std::io::Result<Box<dyn Devices>> {
+        let keyboard = match types & KEYBOARD {
+            0 => None,
+            _ => Some(Device::keyboard()?),
+        };
+
+        // The compositor's keymap can't be asked for without libei, so keysyms are typed with
+        // the default one, which it likely uses too.
+        let keymap = match keyboard {
+            Some(_) => Keymap::from_env()
+                .map_err(|e| log::warn!("keysyms can't be typed without a keymap: {}", e))
+                .ok(),
+            None => None,
+        };
+
+        // Positions span the outputs, which are only looked up for devices taking them.
+        let bounds = match types & (POINTER | TOUCHSCREEN) {
+            0 => None,
+
+            _ => {
+                let screen = self.capture.capture(false)?;
+
+                Some(bounds(&self.capture.outputs(&screen)).ok_or_else(|| {
+                    std::io::Error::new(std::io::ErrorKind::NotFound, "there are no outputs")
+                })?)
+            }
+        };
+
+        let pointers = match (types & POINTER, bounds) {
+            (0, _) | (_, None) => None,
+            (_, Some(bounds)) => Some((Device::pointer()?, Device::absolute(bounds)?)),
+        };
+
+        let touchscreen = match (types & TOUCHSCREEN, bounds) {
+            (0, _) | (_, None) => None,
+            (_, Some(bounds)) => Some(Device::touchscreen(bounds)?),
+        };
+
+        Ok(Box::new(Virtual {
+            keyboard,
+            keymap,
+            pointers,
+            touchscreen,
+            touching: Cell::new(0),
+        }))
+    }
+}
+
+/// `Virtual` is the virtual devices of a session.
+struct Virtual {
+    keyboard: Option<Device>,
+    keymap: Option<Keymap>,
+
+    /// A mouse, and a tablet-like pointer for absolute motion.
+    pointers: Option<(Device, Device)>,
+
+    touchscreen: Option<Device>,
+
+    /// The bits of the slots with a finger down.
+    touching: Cell<u32>,
+}
+
+impl Virtual {
+    fn keyboard(&self) -> std::io::Result<&Device> {
+        self.keyboard.as_ref().ok_or_else(unavailable)
+    }
+
+    fn pointer(&self) -> std::io::Result<&Device> {
+        Ok(&self.pointers.as_ref().ok_or_else(unavailable)?.0)
+    }
+
+    fn absolute(&self) -> std::io::Result<&Device> {
+        Ok(&self.pointers.as_ref().ok_or_else(unavailable)?.1)
+    }
+
+    /// Get the touchscreen and the bit of `slot`, which has to be one it tracks.
+    fn touchscreen(&self, slot: u32) -> std::io::Result<(&Device, u32)> {
+        let touchscreen = self.touchscreen.as_ref().ok_or_else(unavailable)?;
+
+        if slot >= SLOTS {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("only {} fingers can touch at once", SLOTS),
+            ));
+        }
+
+        Ok((touchscreen, 1 << slot))
+    }
+}
+
+impl Devices for Virtual {
+    fn pointer_motion(&self, dx: f64, dy: f64) -> std::io::Result<()> {
+        self.pointer()?.emit(&[
+            (EV_REL, REL_X, dx.round() as i32),
+            (EV_REL, REL_Y, dy.round() as i32),
+        ])
+    }
+
+    fn pointer_motion_absolute(&self, x: f64, y: f64) -> std::io::Result<()> {
+        self.absolute()?.emit(&[
+            (EV_ABS, ABS_X, x.round() as i32),
+            (EV_ABS, ABS_Y, y.round() as i32),
+        ])
+    }
+
+    fn pointer_button(&self, button: u32, pressed: bool) -> std::io::Result<()> {
+        let button = u16::try_from(button)
+            .ok()
+            .filter(|button| BUTTONS.contains(button))
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("{} isn't a mouse button", button),
+                )
+            })?;
+
+        self.pointer()?
+            .emit(&[(EV_KEY, button, i32::from(pressed))])
+    }
+
+    fn pointer_axis(&self, dx: f64, dy: f64, _finish: bool) -> std::io::Result<()> {
+        let (horizontal, vertical) = smooth(dx, dy);
+
+        self.pointer()?.emit(&[
+            (EV_REL, REL_HWHEEL_HI_RES, horizontal),
+            (EV_REL, REL_WHEEL_HI_RES, vertical),
+        ])
+    }
+
+    fn pointer_axis_discrete(&self, axis: u32, steps: i32) -> std::io::Result<()> {
+        // The wheel turns up where Wayland scrolls down, but left and right agree.
+        let (wheel, hi_res, steps) = match axis {
+            0 => (REL_WHEEL, REL_WHEEL_HI_RES, -steps),
+            _ => (REL_HWHEEL, REL_HWHEEL_HI_RES, steps),
+        };
+
+        self.pointer()?.emit(&[
+            (EV_REL, wheel, steps),
+            (EV_REL, hi_res, steps * HI_RES_CLICK),
+        ])
+    }
+
+    fn keyboard_keycode(&self, keycode: u32, pressed: bool) -> std::io::Result<()> {
+        let key = u16::try_from(keycode)
+            .ok()
+            .filter(|key| KEYS.contains(key))
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("{} isn't a keyboard key", keycode),
+                )
+            })?;
+
+        self.keyboard()?.emit(&[(EV_KEY, key, i32::from(pressed))])
+    }
+
+    fn keyboard_keysym(&self, keysym: u32, pressed: bool) -> std::io::Result<()> {
+        let keymap = self.keymap.as_ref().ok_or_else(unavailable)?;
+
+        keymap.send(keysym, 0, pressed, |keycode, pressed| {
+            self.keyboard_keycode(keycode, pressed)
+        })
+    }
+
+    fn touch_down(&self, slot: u32, x: f64, y: f64) -> std::io::Result<()> {
+        let (touchscreen, bit) = self.touchscreen(slot)?;
+
+        let (x, y) = (x.round() as i32, y.round() as i32);
+
+        // Slots are the tracking ids too, which only have to differ between fingers that
+        // are down.
+        touchscreen.emit(&[
+            (EV_ABS, ABS_MT_SLOT, slot as i32),
+            (EV_ABS, ABS_MT_TRACKING_ID, slot as i32),
+            (EV_ABS, ABS_MT_POSITION_X, x),
+            (EV_ABS, ABS_MT_POSITION_Y, y),
+            (EV_KEY, BTN_TOUCH, 1),
+            (EV_ABS, ABS_X, x),
+            (EV_ABS, ABS_Y, y),
+        ])?;
+
+        self.touching.set(self.touching.get() | bit);
+
+        Ok(())
+    }
+
+    fn touch_motion(&self, slot: u32, x: f64, y: f64) -> std::io::Result<()> {
+        let (touchscreen, _) = self.touchscreen(slot)?;
+
+        let (x, y) = (x.round() as i32, y.round() as i32);
+
+        touchscreen.emit(&[
+            (EV_ABS, ABS_MT_SLOT, slot as i32),
+            (EV_ABS, ABS_MT_POSITION_X, x),
+            (EV_ABS, ABS_MT_POSITION_Y, y),
+            (EV_ABS, ABS_X, x),
+            (EV_ABS, ABS_Y, y),
+        ])
+    }
+
+    fn touch_up(&self, slot: u32) -> std::io::Result<()> {
+        let (touchscreen, bit) = self.touchscreen(slot)?;
+
+        let touching = self.touching.get() & !bit;
+
+        // The screen stays touched while any finger is down.
+        touchscreen.emit(&[
+            (EV_ABS, ABS_MT_SLOT, slot as i32),
+            (EV_ABS, ABS_MT_TRACKING_ID, -1),
+            (EV_KEY, BTN_TOUCH, i32::from(touching != 0)),
+        ])?;
+
+        self.touching.set(touching);
+
+        Ok(())
+    }
+}
+
+/// `Device` is a virtual input device, which is removed once its file is closed.
+struct Device {
+    file: File,
+}
+
+impl Device {
+    /// Make a keyboard.
+    fn keyboard() -> std::io::Result<Self> {
+        let file = open()?;
+
+        enable(&file, UI_SET_EVBIT, EV_KEY)?;
+
+        for key in KEYS {
+            enable(&file, UI_SET_KEYBIT, key)?;
+        }
+
+        Self::create(file, "xdg-desktop-portal-rs keyboard")
+    }
+
+    /// Make a mouse with a wheel.
+    fn pointer() -> std::io::Result<Self> {
+        let file = open()?;
+
+        enable(&file, UI_SET_EVBIT, EV_KEY)?;
+
+        for button in BUTTONS {
+            enable(&file, UI_SET_KEYBIT, button)?;
+        }
+
+        enable(&file, UI_SET_EVBIT, EV_REL)?;
+
+        for axis in [
+            REL_X,
+            REL_Y,
+            REL_HWHEEL,
+            REL_WHEEL,
+            REL_WHEEL_HI_RES,
+            REL_HWHEEL_HI_RES,
+        ] {
+            enable(&file, UI_SET_RELBIT, axis)?;
+        }
+
+        Self::create(file, "xdg-desktop-portal-rs pointer")
+    }
+
+    /// Make a pointer moved to positions in the layout, spanning the `bounds` of the outputs.
+    ///
+    /// It has buttons so it's taken for a pointer like those of virtual machines, not for
+    /// a touchscreen, though presses are sent with the mouse.
+    fn absolute(bounds: (i32, i32, i32, i32)) -> std::io::Result<Self> {
+        let file = open()?;
+
+        enable(&file, UI_SET_EVBIT, EV_KEY)?;
+
+        for button in BUTTONS {
+            enable(&file, UI_SET_KEYBIT, button)?;
+        }
+
+        enable(&file, UI_SET_EVBIT, EV_ABS)?;
+
+        let (left, top, right, bottom) = bounds;
+
+        axis(&file, ABS_X, left, right - 1)?;
+        axis(&file, ABS_Y, top, bottom - 1)?;
+
+        Self::create(file, "xdg-desktop-portal-rs absolute pointer")
+    }
+
+    /// Make a multi-touch screen spanning the `bounds` of the outputs.
+    fn touchscreen(bounds: (i32, i32, i32, i32)) -> std::io::Result<Self> {
+        let file = open()?;
+
+        enable(&file, UI_SET_EVBIT, EV_KEY)?;
+        enable(&file, UI_SET_KEYBIT, BTN_TOUCH)?;
+
+        enable(&file, UI_SET_EVBIT, EV_ABS)?;
+
+        let (left, top, right, bottom) = bounds;
+        let last = SLOTS as i32 - 1;
+
+        axis(&file, ABS_X, left, right - 1)?;
+        axis(&file, ABS_Y, top, bottom - 1)?;
+        axis(&file, ABS_MT_SLOT, 0, last)?;
+        axis(&file, ABS_MT_TRACKING_ID, 0, last)?;
+        axis(&file, ABS_MT_POSITION_X, left, right - 1)?;
+        axis(&file, ABS_MT_POSITION_Y, top, bottom - 1)?;
+
+        // Touches land where they are on the outputs, rather than moving a pointer.
+        enable(&file, UI_SET_PROPBIT, INPUT_PROP_DIRECT)?;
+
+        Self::create(file, "xdg-desktop-portal-rs touchscreen")
+    }
+
+    /// Create the device set up on `file`, calling it `name`.
+    fn create(file: File, name: &str) -> std::io::Result<Self> {
+        let mut setup = libc::uinput_setup {
+            id: libc::input_id {
+                bustype: BUS_VIRTUAL,
+                vendor: 0,
+                product: 0,
+                version: 1,
+            },
+            name: [0; libc::UINPUT_MAX_NAME_SIZE],
+            ff_effects_max: 0,
+        };
+
+        for (to, from) in setup.name.iter_mut().zip(name.bytes()) {
+            *to = from as libc::c_char;
+        }
+
+        ioctl(&file, UI_DEV_SETUP, &setup)?;
+
+        ioctl(&file, UI_DEV_CREATE, &0)?;
+
+        Ok(Self { file })
+    }
+
+    /// Send `events`, each a type, code and value, followed by a report ending the frame.
+    fn emit(&self, events: &[(u16, u16, i32)]) -> std::io::Result<()> {
+        let events: Vec<libc::input_event> = events
+            .iter()
+            .chain([&(EV_SYN, SYN_REPORT, 0)])
+            .map(|&(type_, code, value)| libc::input_event {
+                time: libc::timeval {
+                    tv_sec: 0,
+                    tv_usec: 0,
+                },
+                type_,
+                code,
+                value,
+            })
+            .collect();
+
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                events.as_ptr().cast::<u8>(),
+                std::mem::size_of_val(events.as_slice()),
+            )
+        };
+
+        (&self.file).write_all(bytes)
+    }
+}
+
+/// Whether virtual devices can be made.
+fn writable() -> bool {
+    OpenOptions::new().write(true).open(UINPUT).is_ok()
+}
+
+/// Open /dev/uinput to make a device with.
+fn open() -> std::io::Result<File> {
+    OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(UINPUT)
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::PermissionDenied => {
+                std::io::Error::new(e.kind(), format!("{} isn't writable by this user", UINPUT))
+            }
+            _ => e,
+        })
+}
+
+/// Let the device set up on `file` send the event `code` of the kind `request` sets.
+fn enable(file: &File, request: u64, code: u16) -> std::io::Result<()> {
+    if unsafe { libc::ioctl(file.as_raw_fd(), request as _, libc::c_int::from(code)) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Let the device set up on `file` send the absolute `axis`, from `minimum` to `maximum`.
+fn axis(file: &File, axis: u16, minimum: i32, maximum: i32) -> std::io::Result<()> {
+    enable(file, UI_SET_ABSBIT, axis)?;
+
+    let setup = libc::uinput_abs_setup {
+        code: axis,
+        absinfo: libc::input_absinfo {
+            value: minimum,
+            minimum,
+            maximum,
+            fuzz: 0,
+            flat: 0,
+            resolution: 0,
+        },
+    };
+
+    ioctl(file, UI_ABS_SETUP, &setup)
+}
+
+/// Run the uinput `request` taking `argument` on `file`.
+fn ioctl<T>(file: &File, request: u64, argument: &T) -> std::io::Result<()> {
+    if unsafe { libc::ioctl(file.as_raw_fd(), request as _, argument as *const T) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Get the `(left, top, right, bottom)` bounds of `outputs` in the layout.
+fn bounds(outputs: &[Output]) -> Option<(i32, i32, i32, i32)> {
+    outputs
+        .iter()
+        .map(|output| {
+            let (x, y) = output.position;
+            let (width, height) = output.size;
+
+            (x, y, x + width, y + height)
+        })
+        .reduce(|a, b| (a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3)))
+}
+
+/// Turn smooth scrolling by `dx` and `dy` into steps of the high-resolution wheels.
+fn smooth(dx: f64, dy: f64) -> (i32, i32) {
+    let steps = |delta: f64| (delta / CLICK * f64::from(HI_RES_CLICK)).round() as i32;
+
+    (steps(dx), -steps(dy))
+}
+
+/// The error of input on a device the session doesn't have.
+fn unavailable() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "the session has no such device",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bounds, iow, smooth, UI_DEV_SETUP};
+    use crate::capture::Output;
+
+    #[test]
+    fn layout_and_scrolling() {
+        let output = |position: (i32, i32), size: (i32, i32)| Output {
+            position,
+            size,
+            ..Output::default()
+        };
+
+        assert_eq!(
+            bounds(&[
+                output((0, 0), (1920, 1080)),
+                output((-1280, 200), (1280, 1024))
+            ]),
+            Some((-1280, 0, 1920, 1224))
+        );
+        assert_eq!(bounds(&[]), None);
+
+        // A click is 15 pixels, and down is negative on the wheel.
+        assert_eq!(smooth(0.0, 15.0), (0, -120));
+        assert_eq!(smooth(7.5, -30.0), (60, 240));
+
+        assert_eq!(UI_DEV_SETUP, 0x405c_5503);
+        assert_eq!(iow(100, 4), 0x4004_5564);
+    }
+}
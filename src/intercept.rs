@@ -0,0 +1,351 @@
+use std::{os::fd::OwnedFd, sync::Arc};
+
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::config::{InputCaptureBackend, InputCaptureConfig};
+
+#[cfg(feature = "libei")]
+mod evdev;
+#[cfg(feature = "libei")]
+mod hyprland;
+#[cfg(feature = "libei")]
+mod x11;
+
+/// `Zone` is an output as the compositor lays it out, which barriers are set on the edges of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Zone {
+    pub width: u32,
+    pub height: u32,
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Zone {
+    /// Swap the axes of the zone, so its top and bottom edges become its left and right.
+    fn transposed(self) -> Self {
+        Self {
+            width: self.height,
+            height: self.width,
+            x: self.y,
+            y: self.x,
+        }
+    }
+
+    fn right(&self) -> i32 {
+        self.x + self.width as i32
+    }
+
+    fn bottom(&self) -> i32 {
+        self.y + self.height as i32
+    }
+}
+
+/// `Barrier` is a line on the edge of a zone, which the pointer crosses to have input
+/// captured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Barrier {
+    pub id: u32,
+
+    /// The ends of the line, as `x1`, `y1`, `x2` and `y2` in the compositor's layout, with the
+    /// first end above or left of the second.
+    pub position: (i32, i32, i32, i32),
+}
+
+impl Barrier {
+    /// Make a barrier from the ends of a straight line, in either order, if it's one.
+    pub fn new(id: u32, (x1, y1, x2, y2): (i32, i32, i32, i32)) -> Option<Self> {
+        // Lines along neither axis, and points, aren't barriers.
+        if (x1 == x2) == (y1 == y2) {
+            return None;
+        }
+
+        Some(Self {
+            id,
+            position: (x1.min(x2), y1.min(y2), x1.max(x2), y1.max(y2)),
+        })
+    }
+
+    /// Whether the barrier lies on the edge of one of `zones`, where the pointer can't move
+    /// on to another.
+    ///
+    /// Vertical barriers are on the left edge of a zone at its `x`, and on the right edge one
+    /// past its last column; horizontal ones likewise.
+    pub fn fits(&self, zones: &[Zone]) -> bool {
+        let (x1, y1, x2, y2) = self.position;
+
+        match x1 == x2 {
+            true => on_edge(zones.iter().copied(), x1, (y1, y2)),
+            false => on_edge(zones.iter().map(|zone| zone.transposed()), y1, (x1, x2)),
+        }
+    }
+
+    /// Whether the pointer at `position`, moving by `motion`, pushes through the barrier.
+    ///
+    /// The compositor keeps the pointer in the zones, so pushing through a barrier leaves it
+    /// on the pixels along the barrier, moving towards it.
+    #[cfg_attr(not(feature = "libei"), allow(dead_code))]
+    pub fn hit(&self, (x, y): (f64, f64), (dx, dy): (f64, f64)) -> bool {
+        let (x1, y1, x2, y2) = self.position;
+
+        let (x, y) = (x.floor() as i32, y.floor() as i32);
+
+        match x1 == x2 {
+            true => (y1..=y2).contains(&y) && ((x == x1 && dx < 0.0) || (x == x1 - 1 && dx > 0.0)),
+            false => (x1..=x2).contains(&x) && ((y == y1 && dy < 0.0) || (y == y1 - 1 && dy > 0.0)),
+        }
+    }
+}
+
+/// Whether the vertical line at `x` from `top` to `bottom` lies on the left or right edge of
+/// one of `zones`, with none on its other side.
+fn on_edge(zones: impl Iterator<Item = Zone> + Clone, x: i32, (top, bottom): (i32, i32)) -> bool {
+    zones.clone().any(|zone| {
+        let outside = match x {
+            x if x == zone.x => x - 1,
+            x if x == zone.right() => x,
+            _ => return false,
+        };
+
+        zone.y <= top
+            && bottom < zone.bottom()
+            && !zones.clone().any(|other| {
+                (other.x..other.right()).contains(&outside)
+                    && other.y <= bottom
+                    && top < other.bottom()
+            })
+    })
+}
+
+/// `Event` tells about input being captured for an InputCapture session, or handed back.
+#[cfg_attr(not(feature = "libei"), allow(dead_code))]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// The pointer crossed the barrier `barrier_id` of `session` at `position`, so input is
+    /// captured for it.
+    Activated {
+        session: String,
+        activation_id: u32,
+        barrier_id: u32,
+        position: (f64, f64),
+    },
+
+    /// Input captured for `session` went back to the compositor.
+    Deactivated { session: String, activation_id: u32 },
+}
+
+/// `Captured` is input taken away from the compositor, on its way to the app of the session
+/// it's captured for.
+#[cfg_attr(not(feature = "libei"), allow(dead_code))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Captured {
+    /// Input is captured from now on, for the activation with this id.
+    Start(u32),
+
+    /// Input isn't captured anymore.
+    Stop,
+
+    /// The pointer moved by `dx` and `dy`, unaccelerated.
+    Motion(f64, f64),
+
+    /// The evdev `button` was pressed or released.
+    Button(u32, bool),
+
+    /// The wheel turned by `dx` and `dy`, in 120ths of a click.
+    Scroll(i32, i32),
+
+    /// The evdev `keycode` was pressed or released.
+    Key(u32, bool),
+
+    /// The events since the last frame happened at once.
+    Frame,
+}
+
+/// `Intercept` takes input away from the compositor once the pointer crosses a barrier of an
+/// InputCapture session, handing it to the session's app until it's released.
+///
+/// The sessions are known by their handles. Crossing barriers and releasing input are told
+/// about with an `Event`.
+pub trait Intercept: Send + Sync {
+    /// The capabilities input can be captured with.
+    fn capabilities(&self) -> u32;
+
+    /// Connect the app of `session` to the input captured for it, returning the app's end of
+    /// the EIS socket it's sent through.
+    fn connect(&self, session: &str) -> std::io::Result<OwnedFd>;
+
+    /// Capture input for `session` once the pointer crosses one of `barriers`, replacing those
+    /// watched before; with none, input captured for it is released.
+    fn watch(&self, session: &str, barriers: &[Barrier]);
+
+    /// Hand input captured for `session` back to the compositor, with the pointer moved to
+    /// `position` if it's given.
+    fn release(&self, session: &str, position: Option<(f64, f64)>);
+
+    /// Forget `session`, releasing its input and disconnecting its app.
+    fn close(&self, session: &str);
+}
+
+/// `Unsupported` stands in where the pointer can't be found or input can't be grabbed, so
+/// InputCapture sessions capture nothing.
+struct Unsupported;
+
+impl Intercept for Unsupported {
+    fn capabilities(&self) -> u32 {
+        0
+    }
+
+    fn connect(&self, _session: &str) -> std::io::Result<OwnedFd> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "input can't be captured in this session",
+        ))
+    }
+
+    fn watch(&self, _session: &str, barriers: &[Barrier]) {
+        if !barriers.is_empty() {
+            log::warn!("input can't be captured in this session");
+        }
+    }
+
+    fn release(&self, _session: &str, _position: Option<(f64, f64)>) {}
+
+    fn close(&self, _session: &str) {}
+}
+
+/// Create the interceptor selected in the config, which sends crossings to `events`.
+///
+/// Captured input is handed to apps over libei, so capturing needs the libei feature.
+pub fn from_config(
+    config: &InputCaptureConfig,
+    events: UnboundedSender<Event>,
+) -> Arc<dyn Intercept> {
+    let backend = match config.backend {
+        InputCaptureBackend::Auto => match detect() {
+            Some(backend) => backend,
+
+            None => {
+                log::debug!("no way to find the pointer in this session");
+                return Arc::new(Unsupported);
+            }
+        },
+
+        backend => backend,
+    };
+
+    log::debug!("finding the pointer with {:?}", backend);
+
+    intercept(backend, events).unwrap_or_else(|e| {
+        log::warn!("failed to capture input with {:?}: {}", backend, e);
+        Arc::new(Unsupported)
+    })
+}
+
+/// Create the evdev interceptor, finding the pointer with `backend`, if libei is compiled in.
+#[cfg(feature = "libei")]
+fn intercept(
+    backend: InputCaptureBackend,
+    events: UnboundedSender<Event>,
+) -> std::io::Result<Arc<dyn Intercept>> {
+    let cursor: Box<dyn Cursor> = match backend {
+        InputCaptureBackend::Hyprland | InputCaptureBackend::Auto => {
+            Box::new(hyprland::Hyprland::new()?)
+        }
+        InputCaptureBackend::X11 => Box::new(x11::X11::new()?),
+    };
+
+    Ok(Arc::new(evdev::Evdev::new(cursor, events)?))
+}
+
+#[cfg(not(feature = "libei"))]
+fn intercept(
+    _backend: InputCaptureBackend,
+    _events: UnboundedSender<Event>,
+) -> std::io::Result<Arc<dyn Intercept>> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "built without the libei feature",
+    ))
+}
+
+/// Pick the way to find the pointer that fits the session, if there's one.
+fn detect() -> Option<InputCaptureBackend> {
+    if std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+        return Some(InputCaptureBackend::Hyprland);
+    }
+
+    // Wayland compositors don't tell X clients where the pointer is outside their windows.
+    match std::env::var_os("WAYLAND_DISPLAY") {
+        None if std::env::var_os("DISPLAY").is_some() => Some(InputCaptureBackend::X11),
+        _ => None,
+    }
+}
+
+/// `Cursor` finds the pointer in the compositor's layout, and moves it.
+#[cfg(feature = "libei")]
+trait Cursor: Send + Sync {
+    fn position(&self) -> std::io::Result<(f64, f64)>;
+
+    fn warp(&self, position: (f64, f64)) -> std::io::Result<()>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Barrier, Zone};
+
+    /// Two 1920x1080 outputs side by side, the right one 200 pixels lower.
+    const ZONES: [Zone; 2] = [
+        Zone {
+            width: 1920,
+            height: 1080,
+            x: 0,
+            y: 0,
+        },
+        Zone {
+            width: 1920,
+            height: 1080,
+            x: 1920,
+            y: 200,
+        },
+    ];
+
+    #[test]
+    fn barriers() {
+        let fits = |position| Barrier::new(1, position).unwrap().fits(&ZONES);
+
+        // Outer edges, given in either order.
+        assert!(fits((0, 0, 0, 1079)));
+        assert!(fits((3840, 1279, 3840, 200)));
+        assert!(fits((0, 0, 1919, 0)));
+        assert!(fits((1920, 1280, 3839, 1280)));
+
+        // Where the outputs meet, the pointer moves on to the other one, unless it's where
+        // the left one sticks out above the right one.
+        assert!(!fits((1920, 0, 1920, 1079)));
+        assert!(fits((1920, 0, 1920, 199)));
+
+        // Lines inside outputs, past their ends, and off their edges aren't.
+        assert!(!fits((100, 0, 100, 1079)));
+        assert!(!fits((0, 0, 0, 1080)));
+        assert!(!fits((1919, 0, 1919, 1079)));
+
+        assert_eq!(Barrier::new(1, (0, 0, 5, 5)), None);
+        assert_eq!(Barrier::new(1, (5, 5, 5, 5)), None);
+    }
+
+    #[test]
+    fn hits() {
+        let left = Barrier::new(1, (0, 0, 0, 1079)).unwrap();
+        let right = Barrier::new(2, (3840, 200, 3840, 1279)).unwrap();
+        let top = Barrier::new(3, (0, 0, 1919, 0)).unwrap();
+
+        assert!(left.hit((0.0, 500.0), (-3.0, 1.0)));
+        assert!(!left.hit((0.0, 500.0), (3.0, 1.0)));
+        assert!(!left.hit((1.0, 500.0), (-3.0, 1.0)));
+
+        assert!(right.hit((3839.5, 500.0), (2.0, 0.0)));
+        assert!(!right.hit((3839.5, 100.0), (2.0, 0.0)));
+
+        assert!(top.hit((800.0, 0.0), (0.0, -1.0)));
+        assert!(!top.hit((800.0, 0.0), (-1.0, 0.0)));
+    }
+}
@@ -0,0 +1,840 @@
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    os::{
+        fd::{AsRawFd, OwnedFd},
+        unix::{fs::OpenOptionsExt, net::UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::{mpsc::Sender, Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use tokio::sync::mpsc::UnboundedSender;
+
+use super::{Barrier, Captured, Cursor, Event, Intercept};
+use crate::input::{KEYBOARD, POINTER};
+
+/// Where the kernel puts the devices input is read from.
+const DEVICES: &str = "/dev/input";
+
+/// Event types, from `linux/input-event-codes.h`.
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_REL: u16 = 0x02;
+
+const SYN_REPORT: u16 = 0x00;
+
+const REL_X: u16 = 0x00;
+const REL_Y: u16 = 0x01;
+const REL_HWHEEL: u16 = 0x06;
+const REL_WHEEL: u16 = 0x08;
+const REL_WHEEL_HI_RES: u16 = 0x0b;
+const REL_HWHEEL_HI_RES: u16 = 0x0c;
+
+/// Keys only keyboards have.
+const KEY_A: u16 = 30;
+const KEY_SPACE: u16 = 57;
+
+const KEY_ESC: u16 = 1;
+const KEY_LEFTSHIFT: u16 = 42;
+const KEY_RIGHTSHIFT: u16 = 54;
+const KEY_LEFTMETA: u16 = 125;
+const KEY_RIGHTMETA: u16 = 126;
+
+/// `BTN_LEFT` up to `BTN_TASK`, the buttons of mice.
+const BUTTONS: std::ops::RangeInclusive<u16> = 0x110..=0x117;
+
+/// The highest key code, which sizes the key bits of devices.
+const KEY_MAX: usize = 0x2ff;
+
+/// The value of key events repeating a held key.
+const REPEAT: i32 = 2;
+
+/// The high-resolution wheel counts a click as this many steps, as libei does.
+const HI_RES_CLICK: i32 = 120;
+
+const EVIOCGRAB: u64 = iow(0x90, std::mem::size_of::<libc::c_int>());
+
+/// How often the pointer is looked for while it moves.
+const THROTTLE: Duration = Duration::from_millis(10);
+
+/// How often the devices are looked for, to read those plugged in since.
+const RESCAN: Duration = Duration::from_secs(2);
+
+/// `_IOR('E', nr, size)`.
+const fn ior(nr: u64, size: usize) -> u64 {
+    2 << 30 | (size as u64) << 16 | (b'E' as u64) << 8 | nr
+}
+
+/// `_IOW('E', nr, size)`.
+const fn iow(nr: u64, size: usize) -> u64 {
+    1 << 30 | (size as u64) << 16 | (b'E' as u64) << 8 | nr
+}
+
+/// `EVIOCGBIT(ev, size)`, which gets the codes of events of type `ev` a device sends.
+const fn eviocgbit(ev: u16, size: usize) -> u64 {
+    ior(0x20 + ev as u64, size)
+}
+
+/// `Evdev` captures input by reading the keyboards and mice in /dev/input, and grabbing them
+/// from the compositor while it's captured, finding the pointer with `cursor` as they move.
+///
+/// A thread reads the devices, telling the sessions' apps about captured input. Input is
+/// handed back when the app releases it or disconnects, or when the user presses
+/// Super+Shift+Escape, so apps can't keep it from the compositor.
+pub struct Evdev {
+    shared: Arc<Shared>,
+
+    /// The capabilities of the devices found at startup.
+    capabilities: u32,
+
+    /// Wakes the thread, to grab or release the devices.
+    wake: UnixStream,
+}
+
+/// `Shared` is what the thread reading the devices shares with the portal.
+struct Shared {
+    state: Mutex<State>,
+    cursor: Box<dyn Cursor>,
+    events: UnboundedSender<Event>,
+}
+
+#[derive(Default)]
+struct State {
+    sessions: HashMap<String, Session>,
+
+    /// The session input is captured for, and the id of the activation.
+    active: Option<(String, u32)>,
+
+    /// The id of the last activation.
+    activation_id: u32,
+}
+
+/// `Session` is an InputCapture session input is captured for.
+#[derive(Default)]
+struct Session {
+    /// The barriers watched, in the order they were set.
+    barriers: Vec<Barrier>,
+
+    /// Where captured input goes, once the app connected.
+    eis: Option<Sender<Captured>>,
+}
+
+impl Evdev {
+    pub fn new(cursor: Box<dyn Cursor>, events: UnboundedSender<Event>) -> std::io::Result<Self> {
+        let devices = scan(&[]);
+
+        if devices.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "no keyboard or mouse in /dev/input can be read, is the user in the input group?",
+            ));
+        }
+
+        let capabilities = devices
+            .iter()
+            .fold(0, |capabilities, device| capabilities | device.kinds);
+
+        let (wake, woken) = UnixStream::pair()?;
+
+        woken.set_nonblocking(true)?;
+
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State::default()),
+            cursor,
+            events,
+        });
+
+        let reading = shared.clone();
+
+        std::thread::Builder::new()
+            .name(String::from("evdev"))
+            .spawn(move || run(&reading, devices, woken))?;
+
+        Ok(Self {
+            shared,
+            capabilities,
+            wake,
+        })
+    }
+
+    /// Wake the thread, so it grabs or releases the devices.
+    fn wake(&self) {
+        if let Err(e) = (&self.wake).write_all(&[0]) {
+            log::error!("failed to wake the evdev thread: {}", e);
+        }
+    }
+}
+
+impl Intercept for Evdev {
+    fn capabilities(&self) -> u32 {
+        self.capabilities
+    }
+
+    fn connect(&self, session: &str) -> std::io::Result<OwnedFd> {
+        let (fd, eis) = crate::input::serve()?;
+
+        let mut state = self.shared.lock();
+
+        // Apps connecting again while input is captured for them get it from then on.
+        if let Some((_, activation_id)) = state.active.as_ref().filter(|(s, _)| s == session) {
+            let _ = eis.send(Captured::Start(*activation_id));
+        }
+
+        state.sessions.entry(String::from(session)).or_default().eis = Some(eis);
+
+        Ok(fd)
+    }
+
+    fn watch(&self, session: &str, barriers: &[Barrier]) {
+        let mut state = self.shared.lock();
+
+        state
+            .sessions
+            .entry(String::from(session))
+            .or_default()
+            .barriers = barriers.to_vec();
+
+        if barriers.is_empty() {
+            self.shared.deactivate(&mut state, session);
+        }
+
+        drop(state);
+
+        self.wake();
+    }
+
+    fn release(&self, session: &str, position: Option<(f64, f64)>) {
+        let released = self.shared.deactivate(&mut self.shared.lock(), session);
+
+        if !released {
+            return;
+        }
+
+        if let Some(position) = position {
+            if let Err(e) = self.shared.cursor.warp(position) {
+                log::warn!("failed to move the pointer: {}", e);
+            }
+        }
+
+        self.wake();
+    }
+
+    fn close(&self, session: &str) {
+        let mut state = self.shared.lock();
+
+        self.shared.deactivate(&mut state, session);
+
+        // Dropping its sender disconnects the app.
+        state.sessions.remove(session);
+
+        drop(state);
+
+        self.wake();
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Capture input for `session`, whose barrier `barrier_id` the pointer crossed at
+    /// `position`.
+    fn activate(&self, state: &mut State, session: String, barrier_id: u32, position: (f64, f64)) {
+        state.activation_id = state.activation_id.wrapping_add(1);
+
+        let activation_id = state.activation_id;
+
+        log::debug!("capturing input for {}", session);
+
+        if let Some(eis) = state
+            .sessions
+            .get(&session)
+            .and_then(|session| session.eis.as_ref())
+        {
+            let _ = eis.send(Captured::Start(activation_id));
+        }
+
+        state.active = Some((session.clone(), activation_id));
+
+        let _ = self.events.send(Event::Activated {
+            session,
+            activation_id,
+            barrier_id,
+            position,
+        });
+    }
+
+    /// Stop capturing input for `session`, returning whether it was.
+    fn deactivate(&self, state: &mut State, session: &str) -> bool {
+        let Some((_, activation_id)) = state.active.take_if(|(active, _)| active == session) else {
+            return false;
+        };
+
+        log::debug!("releasing the input captured for {}", session);
+
+        self.send(state, Captured::Stop);
+
+        let _ = self.events.send(Event::Deactivated {
+            session: String::from(session),
+            activation_id,
+        });
+
+        true
+    }
+
+    /// Send captured input to the app of the session it's captured for, returning `false` if
+    /// the app disconnected.
+    fn send(&self, state: &State, captured: Captured) -> bool {
+        let eis = state
+            .active
+            .as_ref()
+            .and_then(|(session, _)| state.sessions.get(session))
+            .and_then(|session| session.eis.as_ref());
+
+        match eis {
+            Some(eis) => eis.send(captured).is_ok(),
+            None => true,
+        }
+    }
+}
+
+/// `Handled` is what an event a device sent comes to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Handled {
+    Nothing,
+
+    /// A frame ended with the pointer moving by `dx` and `dy`, while the device isn't grabbed.
+    Moved(f64, f64),
+
+    /// The user pressed the chord that takes captured input back, while the device is grabbed.
+    Release,
+}
+
+/// `Device` is a keyboard or mouse in /dev/input.
+struct Device {
+    path: PathBuf,
+    file: File,
+
+    /// The device type bits of what the device is.
+    kinds: u32,
+
+    /// Whether the device is grabbed from the compositor.
+    grabbed: bool,
+
+    /// The keys and buttons held down, so the device isn't grabbed before they're released.
+    pressed: Vec<u16>,
+
+    /// The motion since the last frame.
+    motion: (f64, f64),
+
+    /// The wheel clicks since the last frame, and the high-resolution steps.
+    wheel: (i32, i32),
+    hi_res: (i32, i32),
+}
+
+impl Device {
+    /// Open the device at `path`, if it's a keyboard or mouse.
+    fn open(path: &Path) -> std::io::Result<Option<Self>> {
+        let file = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK | libc::O_CLOEXEC)
+            .open(path)?;
+
+        let keys = bits(&file, EV_KEY, KEY_MAX / 8 + 1)?;
+        let relative = bits(&file, EV_REL, 2)?;
+
+        let mut kinds = 0;
+
+        if has(&keys, KEY_A) && has(&keys, KEY_SPACE) {
+            kinds |= KEYBOARD;
+        }
+
+        if has(&relative, REL_X) && has(&relative, REL_Y) {
+            kinds |= POINTER;
+        }
+
+        Ok((kinds != 0).then(|| Self {
+            path: PathBuf::from(path),
+            file,
+            kinds,
+            grabbed: false,
+            pressed: Vec::new(),
+            motion: (0.0, 0.0),
+            wheel: (0, 0),
+            hi_res: (0, 0),
+        }))
+    }
+
+    /// Grab the device from the compositor, or hand it back.
+    fn grab(&mut self, grab: bool) -> std::io::Result<()> {
+        let grabbing = libc::c_int::from(grab);
+
+        if unsafe { libc::ioctl(self.file.as_raw_fd(), EVIOCGRAB as _, grabbing) } < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        self.grabbed = grab;
+
+        Ok(())
+    }
+
+    /// Read the events the device queued.
+    fn read(&self) -> std::io::Result<Vec<libc::input_event>> {
+        let mut events = Vec::new();
+
+        loop {
+            // SAFETY: input_event is plain data, which any bytes are valid for.
+            let mut buffer: [libc::input_event; 64] = unsafe { std::mem::zeroed() };
+
+            let read = unsafe {
+                libc::read(
+                    self.file.as_raw_fd(),
+                    buffer.as_mut_ptr().cast(),
+                    std::mem::size_of_val(&buffer),
+                )
+            };
+
+            if read < 0 {
+                return match std::io::Error::last_os_error() {
+                    e if e.kind() == std::io::ErrorKind::WouldBlock => Ok(events),
+                    e => Err(e),
+                };
+            }
+
+            let count = read as usize / std::mem::size_of::<libc::input_event>();
+
+            events.extend_from_slice(&buffer[..count]);
+
+            if count < buffer.len() {
+                return Ok(events);
+            }
+        }
+    }
+
+    /// Handle an `event` the device sent, sending it on with `send` if the device is grabbed.
+    fn handle(&mut self, event: &libc::input_event, send: &mut impl FnMut(Captured)) -> Handled {
+        match (event.type_, event.code) {
+            (EV_KEY, code) if event.value != REPEAT => {
+                let pressed = event.value != 0;
+
+                self.pressed.retain(|&held| held != code);
+
+                if pressed {
+                    self.pressed.push(code);
+                }
+
+                // Super+Shift+Escape is the portal's, so it can't be captured.
+                let held = |keys: [u16; 2]| keys.iter().any(|key| self.pressed.contains(key));
+
+                if self.grabbed
+                    && pressed
+                    && code == KEY_ESC
+                    && held([KEY_LEFTMETA, KEY_RIGHTMETA])
+                    && held([KEY_LEFTSHIFT, KEY_RIGHTSHIFT])
+                {
+                    return Handled::Release;
+                }
+
+                if self.grabbed {
+                    send(match BUTTONS.contains(&code) {
+                        true => Captured::Button(u32::from(code), pressed),
+                        false => Captured::Key(u32::from(code), pressed),
+                    });
+                }
+            }
+
+            (EV_REL, REL_X) => self.motion.0 += f64::from(event.value),
+            (EV_REL, REL_Y) => self.motion.1 += f64::from(event.value),
+            (EV_REL, REL_HWHEEL) => self.wheel.0 += event.value,
+            (EV_REL, REL_WHEEL) => self.wheel.1 += event.value,
+            (EV_REL, REL_HWHEEL_HI_RES) => self.hi_res.0 += event.value,
+            (EV_REL, REL_WHEEL_HI_RES) => self.hi_res.1 += event.value,
+
+            (EV_SYN, SYN_REPORT) => {
+                let motion = std::mem::take(&mut self.motion);
+                let wheel = std::mem::take(&mut self.wheel);
+                let hi_res = std::mem::take(&mut self.hi_res);
+
+                if !self.grabbed {
+                    return Handled::Moved(motion.0, motion.1);
+                }
+
+                if motion != (0.0, 0.0) {
+                    send(Captured::Motion(motion.0, motion.1));
+                }
+
+                // Wheels with high-resolution steps send clicks too, which would scroll twice.
+                let (dx, dy) = match hi_res {
+                    (0, 0) => (wheel.0 * HI_RES_CLICK, wheel.1 * HI_RES_CLICK),
+                    hi_res => hi_res,
+                };
+
+                // Turning the wheel up scrolls up, which libei counts as negative.
+                if (dx, dy) != (0, 0) {
+                    send(Captured::Scroll(dx, -dy));
+                }
+
+                send(Captured::Frame);
+            }
+
+            _ => {}
+        }
+
+        Handled::Nothing
+    }
+}
+
+/// Read `devices`, and those plugged in later, until the portal exits, capturing their input
+/// once the pointer crosses a barrier.
+fn run(shared: &Shared, mut devices: Vec<Device>, woken: UnixStream) {
+    let mut scanned = Instant::now();
+    let mut located = Instant::now();
+
+    // The motion since the pointer was last looked for.
+    let mut motion = (0.0, 0.0);
+
+    loop {
+        let mut fds: Vec<libc::pollfd> = devices
+            .iter()
+            .map(|device| device.file.as_raw_fd())
+            .chain([woken.as_raw_fd()])
+            .map(|fd| libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            })
+            .collect();
+
+        let timeout = RESCAN.as_millis() as libc::c_int;
+
+        if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) } < 0 {
+            match std::io::Error::last_os_error() {
+                e if e.kind() == std::io::ErrorKind::Interrupted => continue,
+
+                e => {
+                    log::error!("failed to read input devices: {}", e);
+                    return;
+                }
+            }
+        }
+
+        // Only waking up matters, not how often it happened.
+        if fds.last().is_some_and(|fd| fd.revents != 0) {
+            let _ = (&woken).read(&mut [0; 64]);
+        }
+
+        let mut state = shared.lock();
+
+        let mut i = 0;
+
+        // Whether the user pressed the release chord, or the app stopped taking input.
+        let mut released = false;
+        let mut disconnected = false;
+
+        devices.retain_mut(|device| {
+            let revents = fds[i].revents;
+
+            i += 1;
+
+            if revents == 0 {
+                return true;
+            }
+
+            let events = match device.read() {
+                Ok(events) => events,
+
+                // Unplugged devices go away.
+                Err(e) => {
+                    log::debug!("stopped reading {}: {}", device.path.display(), e);
+                    return false;
+                }
+            };
+
+            for event in &events {
+                let handled = device.handle(event, &mut |captured| {
+                    disconnected |= !shared.send(&state, captured);
+                });
+
+                match handled {
+                    Handled::Moved(dx, dy) => {
+                        motion.0 += dx;
+                        motion.1 += dy;
+                    }
+
+                    Handled::Release => released = true,
+                    Handled::Nothing => {}
+                }
+            }
+
+            true
+        });
+
+        if let Some((session, _)) = state.active.clone().filter(|_| released || disconnected) {
+            match released {
+                true => log::info!("the user took back the input captured for {}", session),
+                false => log::warn!("the app of {} disconnected, releasing its input", session),
+            }
+
+            shared.deactivate(&mut state, &session);
+        }
+
+        let watching = state
+            .sessions
+            .values()
+            .any(|session| !session.barriers.is_empty());
+
+        if state.active.is_none()
+            && watching
+            && motion != (0.0, 0.0)
+            && located.elapsed() >= THROTTLE
+        {
+            match shared.cursor.position() {
+                Ok(position) => {
+                    if let Some((session, barrier_id)) = crossed(&state, position, motion) {
+                        shared.activate(&mut state, session, barrier_id, position);
+                    }
+                }
+
+                Err(e) => log::warn!("failed to find the pointer: {}", e),
+            }
+
+            located = Instant::now();
+            motion = (0.0, 0.0);
+        }
+
+        // Devices are grabbed once nothing is held on them, so the compositor sees what's
+        // released, and handed back as input isn't captured anymore.
+        let active = state.active.is_some();
+
+        drop(state);
+
+        for device in &mut devices {
+            let grab = active && (device.grabbed || device.pressed.is_empty());
+
+            if grab != device.grabbed {
+                if let Err(e) = device.grab(grab) {
+                    log::warn!("failed to grab {}: {}", device.path.display(), e);
+                }
+            }
+        }
+
+        if scanned.elapsed() >= RESCAN {
+            devices.extend(scan(&devices));
+            scanned = Instant::now();
+        }
+    }
+}
+
+/// Find the session and barrier the pointer at `position`, moving by `motion`, pushes
+/// through, if it does.
+fn crossed(state: &State, position: (f64, f64), motion: (f64, f64)) -> Option<(String, u32)> {
+    state.sessions.iter().find_map(|(session, watched)| {
+        let barrier = watched
+            .barriers
+            .iter()
+            .find(|barrier| barrier.hit(position, motion))?;
+
+        Some((session.clone(), barrier.id))
+    })
+}
+
+/// Open the keyboards and mice in /dev/input, leaving out those in `known`.
+fn scan(known: &[Device]) -> Vec<Device> {
+    let entries = match std::fs::read_dir(DEVICES) {
+        Ok(entries) => entries,
+
+        Err(e) => {
+            log::warn!("failed to list {}: {}", DEVICES, e);
+            return Vec::new();
+        }
+    };
+
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with("event"))
+        })
+        .filter(|path| !known.iter().any(|device| device.path == *path))
+        .filter_map(|path| match Device::open(&path) {
+            Ok(device) => device,
+
+            Err(e) => {
+                log::debug!("failed to open {}: {}", path.display(), e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Get the bits of the codes of events of type `ev` the device on `file` sends.
+fn bits(file: &File, ev: u16, size: usize) -> std::io::Result<Vec<u8>> {
+    let mut bits = vec![0u8; size];
+
+    let request = eviocgbit(ev, size);
+
+    if unsafe { libc::ioctl(file.as_raw_fd(), request as _, bits.as_mut_ptr()) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(bits)
+}
+
+/// Whether `code` is set in `bits`.
+fn has(bits: &[u8], code: u16) -> bool {
+    let code = usize::from(code);
+
+    bits.get(code / 8)
+        .is_some_and(|byte| byte & 1 << (code % 8) != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        has, Captured, Device, Handled, EV_KEY, EV_REL, EV_SYN, KEY_ESC, KEY_LEFTSHIFT,
+        KEY_RIGHTMETA, REL_WHEEL, REL_WHEEL_HI_RES, REL_X,
+    };
+
+    fn event(type_: u16, code: u16, value: i32) -> libc::input_event {
+        libc::input_event {
+            time: libc::timeval {
+                tv_sec: 0,
+                tv_usec: 0,
+            },
+            type_,
+            code,
+            value,
+        }
+    }
+
+    fn device() -> Device {
+        Device {
+            path: "/dev/input/event0".into(),
+            file: std::fs::File::open("/dev/null").unwrap(),
+            kinds: 0,
+            grabbed: false,
+            pressed: Vec::new(),
+            motion: (0.0, 0.0),
+            wheel: (0, 0),
+            hi_res: (0, 0),
+        }
+    }
+
+    #[test]
+    fn frames() {
+        let mut device = device();
+
+        let mut sent = Vec::new();
+
+        let mut handle = |device: &mut Device, events: &[libc::input_event]| {
+            events
+                .iter()
+                .filter_map(|event| {
+                    match device.handle(event, &mut |captured| sent.push(captured)) {
+                        Handled::Moved(dx, dy) => Some((dx, dy)),
+                        _ => None,
+                    }
+                })
+                .last()
+        };
+
+        // Motion is only looked at while the device isn't grabbed.
+        assert_eq!(
+            handle(
+                &mut device,
+                &[
+                    event(EV_REL, REL_X, -4),
+                    event(EV_KEY, 30, 1),
+                    event(EV_SYN, 0, 0)
+                ]
+            ),
+            Some((-4.0, 0.0))
+        );
+        assert_eq!(device.pressed, [30]);
+
+        device.grabbed = true;
+
+        assert_eq!(
+            handle(
+                &mut device,
+                &[
+                    event(EV_KEY, 30, 2),
+                    event(EV_KEY, 30, 0),
+                    event(EV_KEY, 0x110, 1),
+                    event(EV_REL, REL_WHEEL, 1),
+                    event(EV_REL, REL_WHEEL_HI_RES, 60),
+                    event(EV_SYN, 0, 0),
+                ]
+            ),
+            None
+        );
+        assert_eq!(device.pressed, [0x110]);
+
+        // Repeats are left out, and high-resolution steps win over clicks.
+        assert_eq!(
+            sent,
+            [
+                Captured::Key(30, false),
+                Captured::Button(0x110, true),
+                Captured::Scroll(0, -60),
+                Captured::Frame,
+            ]
+        );
+    }
+
+    #[test]
+    fn release_chord() {
+        let mut device = device();
+
+        let mut sent = Vec::new();
+
+        let mut handle = |device: &mut Device, events: &[libc::input_event]| {
+            events
+                .iter()
+                .map(|event| device.handle(event, &mut |captured| sent.push(captured)))
+                .collect::<Vec<_>>()
+        };
+
+        let chord = [
+            event(EV_KEY, KEY_RIGHTMETA, 1),
+            event(EV_KEY, KEY_LEFTSHIFT, 1),
+            event(EV_KEY, KEY_ESC, 1),
+        ];
+
+        // The chord is only the portal's while input is captured.
+        assert!(!handle(&mut device, &chord).contains(&Handled::Release));
+
+        device.pressed.clear();
+        device.grabbed = true;
+
+        assert_eq!(handle(&mut device, &chord)[2], Handled::Release);
+
+        // Escape alone goes to the app, the chord's doesn't.
+        device.pressed.clear();
+
+        assert_eq!(
+            handle(&mut device, &[event(EV_KEY, KEY_ESC, 1)]),
+            [Handled::Nothing]
+        );
+        assert_eq!(
+            sent,
+            [
+                Captured::Key(u32::from(KEY_RIGHTMETA), true),
+                Captured::Key(u32::from(KEY_LEFTSHIFT), true),
+                Captured::Key(u32::from(KEY_ESC), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn bits() {
+        assert!(has(&[0, 0b100], 10));
+        assert!(!has(&[0, 0b100], 9));
+        assert!(!has(&[0, 0b100], 64));
+    }
+}
@@ -0,0 +1,98 @@
+use std::{
+    io::{Read, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+};
+
+use super::Cursor;
+
+/// `Hyprland` finds the pointer through Hyprland's IPC socket.
+///
+/// The socket is asked directly rather than through `hyprctl`, since the pointer is looked
+/// for as it moves.
+pub struct Hyprland {
+    socket: PathBuf,
+}
+
+impl Hyprland {
+    pub fn new() -> std::io::Result<Self> {
+        let signature = std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE").ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, "Hyprland isn't running")
+        })?;
+
+        // Hyprland moved its sockets from /tmp to the runtime directory in 0.40.
+        let socket = [dirs::runtime_dir(), Some(PathBuf::from("/tmp"))]
+            .into_iter()
+            .flatten()
+            .map(|dir| dir.join("hypr").join(&signature).join(".socket.sock"))
+            .find(|socket| socket.exists())
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    "Hyprland's IPC socket is missing",
+                )
+            })?;
+
+        Ok(Self { socket })
+    }
+
+    /// Send `request` to Hyprland, returning its reply.
+    fn request(&self, request: &str) -> std::io::Result<String> {
+        let mut stream = UnixStream::connect(&self.socket)?;
+
+        stream.write_all(request.as_bytes())?;
+
+        let mut reply = String::new();
+
+        stream.read_to_string(&mut reply)?;
+
+        Ok(reply)
+    }
+}
+
+impl Cursor for Hyprland {
+    fn position(&self) -> std::io::Result<(f64, f64)> {
+        let reply = self.request("cursorpos")?;
+
+        parse(&reply).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "Hyprland sent an invalid pointer position: {}",
+                    reply.trim()
+                ),
+            )
+        })
+    }
+
+    fn warp(&self, (x, y): (f64, f64)) -> std::io::Result<()> {
+        let reply = self.request(&format!("dispatch movecursor {} {}", x as i32, y as i32))?;
+
+        match reply.trim() {
+            "ok" => Ok(()),
+            reply => Err(std::io::Error::other(format!(
+                "Hyprland refused to move the pointer: {}",
+                reply
+            ))),
+        }
+    }
+}
+
+/// Parse the `x, y` position `cursorpos` replies with.
+fn parse(reply: &str) -> Option<(f64, f64)> {
+    let (x, y) = reply.trim().split_once(',')?;
+
+    Some((x.trim().parse().ok()?, y.trim().parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+
+    #[test]
+    fn positions() {
+        assert_eq!(parse("1919, 540\n"), Some((1919.0, 540.0)));
+        assert_eq!(parse("-5, 12"), Some((-5.0, 12.0)));
+        assert_eq!(parse("unknown request"), None);
+    }
+}
@@ -0,0 +1,44 @@
+use x11rb::{
+    connection::Connection,
+    protocol::xproto::{ConnectionExt, Window},
+    rust_connection::RustConnection,
+};
+
+use super::Cursor;
+
+/// `X11` finds the pointer on the root window of the X server.
+pub struct X11 {
+    conn: RustConnection,
+    root: Window,
+}
+
+impl X11 {
+    pub fn new() -> std::io::Result<Self> {
+        let (conn, screen) = x11rb::connect(None).map_err(std::io::Error::other)?;
+
+        let root = conn.setup().roots[screen].root;
+
+        Ok(Self { conn, root })
+    }
+}
+
+impl Cursor for X11 {
+    fn position(&self) -> std::io::Result<(f64, f64)> {
+        let pointer = self
+            .conn
+            .query_pointer(self.root)
+            .map_err(std::io::Error::other)?
+            .reply()
+            .map_err(std::io::Error::other)?;
+
+        Ok((f64::from(pointer.root_x), f64::from(pointer.root_y)))
+    }
+
+    fn warp(&self, (x, y): (f64, f64)) -> std::io::Result<()> {
+        self.conn
+            .warp_pointer(x11rb::NONE, self.root, 0, 0, 0, 0, x as i16, y as i16)
+            .map_err(std::io::Error::other)?;
+
+        self.conn.flush().map_err(std::io::Error::other)
+    }
+}
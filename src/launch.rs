@@ -0,0 +1,225 @@
+use std::{
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+use crate::desktop;
+
+/// Find the desktop file id of the default app for `content_type`, like
+/// `x-scheme-handler/mailto`, as the `mimeapps.list` files set it.
+///
+/// The files are read in the order of the spec, and the first default that's installed and
+/// can be started wins.
+pub fn default_app(content_type: &str) -> Option<String> {
+    mimeapps_lists()
+        .iter()
+        .filter_map(|path| std::fs::read_to_string(path).ok())
+        .flat_map(|contents| defaults(&contents, content_type))
+        .find(|id| desktop::lookup(id).exec.is_some())
+}
+
+/// Get the command that starts the app with desktop file id `id` on `uris`, from its `Exec`
+/// line.
+pub fn command(id: &str, uris: &[String]) -> std::io::Result<Vec<String>> {
+    let entry = desktop::lookup(id);
+
+    let invalid = |message: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+
+    let exec = entry
+        .exec
+        .ok_or_else(|| invalid("the desktop file has no command"))?;
+
+    let args = expand(&exec, uris).ok_or_else(|| invalid("the command can't be parsed"))?;
+
+    match args.is_empty() {
+        true => Err(invalid("the command is empty")),
+        false => Ok(args),
+    }
+}
+
+/// Start the program of `args[0]` with the rest of `args`, without waiting for it.
+pub fn spawn(args: &[String]) -> std::io::Result<()> {
+    let (program, args) = args
+        .split_first()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "no program"))?;
+
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .spawn()?;
+
+    // The app runs on its own, but it has to be waited for once it exits.
+    std::thread::Builder::new()
+        .name(String::from("launch"))
+        .spawn(move || child.wait())?;
+
+    Ok(())
+}
+
+/// List the `mimeapps.list` files, most important first: the desktop specific and general
+/// ones of the config directories, then those of the data directories.
+fn mimeapps_lists() -> Vec<PathBuf> {
+    let desktops: Vec<String> = std::env::var("XDG_CURRENT_DESKTOP")
+        .unwrap_or_default()
+        .split(':')
+        .filter(|desktop| !desktop.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+
+    let system = std::env::var("XDG_CONFIG_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| String::from("/etc/xdg"));
+
+    let names: Vec<String> = desktops
+        .iter()
+        .map(|desktop| format!("{}-mimeapps.list", desktop))
+        .chain([String::from("mimeapps.list")])
+        .collect();
+
+    let config = dirs::config_dir()
+        .into_iter()
+        .chain(system.split(':').map(PathBuf::from));
+
+    let data = desktop::data_dirs()
+        .into_iter()
+        .map(|dir| dir.join("applications"));
+
+    config
+        .chain(data)
+        .flat_map(|dir| names.iter().map(move |name| dir.join(name)))
+        .collect()
+}
+
+/// List the default apps of `content_type` in a `mimeapps.list` file, by desktop file id.
+fn defaults(contents: &str, content_type: &str) -> Vec<String> {
+    let mut in_group = false;
+
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_group = line == "[Default Applications]";
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=').filter(|_| in_group) else {
+            continue;
+        };
+
+        if key.trim() == content_type {
+            return value
+                .split(';')
+                .map(str::trim)
+                .filter_map(|id| id.strip_suffix(".desktop"))
+                .map(String::from)
+                .collect();
+        }
+    }
+
+    Vec::new()
+}
+
+/// Split an `Exec` line into arguments and fill in its field codes with `uris`, as the
+/// desktop entry spec describes, or `None` if its quoting is broken.
+///
+/// `%u` and `%f` take the first URI and `%U` and `%F` all of them; other field codes are
+/// dropped.
+fn expand(exec: &str, uris: &[String]) -> Option<Vec<String>> {
+    let mut args = Vec::new();
+
+    let mut chars = exec.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| *c == ' ').is_some() {}
+
+        let Some(&first) = chars.peek() else {
+            return Some(args);
+        };
+
+        // Quoted arguments are taken as they are, without field codes.
+        if first == '"' {
+            chars.next();
+
+            let mut arg = String::new();
+
+            loop {
+                match chars.next()? {
+                    '"' => break,
+                    '\\' => arg.push(chars.next()?),
+                    c => arg.push(c),
+                }
+            }
+
+            args.push(arg);
+            continue;
+        }
+
+        let mut arg = String::new();
+
+        while let Some(c) = chars.next_if(|c| *c != ' ') {
+            arg.push(c);
+        }
+
+        match arg.as_str() {
+            "%U" | "%F" => args.extend(uris.iter().cloned()),
+            "%u" | "%f" => args.extend(uris.first().cloned()),
+
+            arg => {
+                let mut expanded = String::new();
+                let mut codes = arg.chars();
+
+                while let Some(c) = codes.next() {
+                    match c {
+                        '%' if codes.next() == Some('%') => expanded.push('%'),
+                        '%' => {}
+                        c => expanded.push(c),
+                    }
+                }
+
+                if !expanded.is_empty() {
+                    args.push(expanded);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{defaults, expand};
+
+    #[test]
+    fn mimeapps() {
+        let contents = "[Added Associations]\n\
+                        x-scheme-handler/mailto=org.gnome.Evolution.desktop;\n\
+                        \n\
+                        [Default Applications]\n\
+                        text/plain=org.gnome.TextEditor.desktop\n\
+                        x-scheme-handler/mailto=thunderbird.desktop;org.gnome.Geary.desktop;\n";
+
+        assert_eq!(
+            defaults(contents, "x-scheme-handler/mailto"),
+            ["thunderbird", "org.gnome.Geary"]
+        );
+        assert!(defaults(contents, "image/png").is_empty());
+    }
+
+    #[test]
+    fn exec_lines() {
+        let uris = [String::from("mailto:a@example.org")];
+
+        assert_eq!(
+            expand("thunderbird -compose %u", &uris).unwrap(),
+            ["thunderbird", "-compose", "mailto:a@example.org"]
+        );
+        assert_eq!(
+            expand("\"/opt/My Mail/mail\" --name=%c %U", &uris).unwrap(),
+            ["/opt/My Mail/mail", "--name=", "mailto:a@example.org"]
+        );
+        assert_eq!(
+            expand("mail \"say \\\"hi\\\"\" 100%%", &[]).unwrap(),
+            ["mail", "say \"hi\"", "100%"]
+        );
+        assert_eq!(expand("mail %u", &[]).unwrap(), ["mail"]);
+        assert_eq!(expand("mail \"broken", &uris), None);
+    }
+}
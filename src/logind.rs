@@ -0,0 +1,41 @@
+use zbus::{dbus_proxy, zvariant};
+
+/// The login manager of systemd-logind, on the system bus.
+#[dbus_proxy(
+    interface = "org.freedesktop.login1.Manager",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1"
+)]
+pub trait Manager {
+    /// Take a lock on `what`, like `sleep:shutdown`, which lasts until the fd is closed.
+    ///
+    /// A `delay` lock holds the operation off for a short while, a `block` lock until it's
+    /// released.
+    fn inhibit(
+        &self,
+        what: &str,
+        who: &str,
+        why: &str,
+        mode: &str,
+    ) -> zbus::Result<zvariant::OwnedFd>;
+
+    /// Sent with `start` before the system suspends, and without it once it resumed.
+    #[dbus_proxy(signal)]
+    fn prepare_for_sleep(&self, start: bool) -> zbus::Result<()>;
+
+    /// Sent with `start` before the system shuts down.
+    #[dbus_proxy(signal)]
+    fn prepare_for_shutdown(&self, start: bool) -> zbus::Result<()>;
+}
+
+/// The login session this process belongs to.
+#[dbus_proxy(
+    interface = "org.freedesktop.login1.Session",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1/session/auto"
+)]
+pub trait Session {
+    /// Whether the screen of the session is locked, as far as its screen locker told logind.
+    #[dbus_proxy(property)]
+    fn locked_hint(&self) -> zbus::Result<bool>;
+}
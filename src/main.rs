@@ -1,4 +1,39 @@
+mod account;
+mod audit;
+mod autostart;
+mod capture;
+mod cast;
+mod choices;
+mod clipboard;
+mod config;
+mod desktop;
+mod dialog;
+mod documents;
+mod filter;
+mod gvfs;
+mod input;
+mod intercept;
+mod launch;
+mod logind;
+mod mime;
+mod notify;
+mod permissions;
+mod policy;
+mod print;
+mod recent;
+mod request;
+mod resolve;
+mod restore;
+mod schedule;
+mod secret;
 mod service;
+mod session;
+mod settings;
+mod shortcuts;
+mod state;
+mod uri;
+mod wallpaper;
+mod window;
 
 #[warn(clippy::all)]
 #[warn(clippy::pedantic)]
@@ -7,12 +42,62 @@ mod service;
 async fn main() -> zbus::Result<()> {
     env_logger::init();
 
-    let _conn = zbus::ConnectionBuilder::session()?
-        .name("org.freedesktop.impl.portal.desktop.rs")?
-        .serve_at("/org/freedesktop/portal/desktop", service::FileChooser {})?
-        .serve_at("/org/freedesktop/portal/desktop", service::AppChooser {})?
-        .build()
-        .await?;
+    // `xdg-desktop-portal-rs shortcuts` shows the settings of global shortcuts of the running
+    // portal, which is started if it isn't.
+    if let Some(command) = std::env::args().nth(1) {
+        return match command.as_str() {
+            "shortcuts" => service::configure_shortcuts().await,
+            _ => Err(zbus::Error::Failure(format!("unknown command {command:?}"))),
+        };
+    }
+
+    let config = std::sync::Arc::new(config::Config::load());
+
+    let dialogs = dialog::from_config(&config);
+
+    let audit = std::sync::Arc::new(audit::Audit::new(&config.audit));
+
+    let scheduler = std::sync::Arc::new(schedule::Scheduler::new(config.dialog.concurrency));
+
+    let capture = capture::from_config(&config.screenshot);
+
+    let cast = cast::new(capture.clone());
+
+    let input = input::from_config(&config.remote_desktop, capture.clone());
+
+    let clipboard = clipboard::new();
+
+    let (activations, activated) = tokio::sync::mpsc::unbounded_channel();
+
+    let notifier = notify::from_config(&config.notification, dialogs.clone(), activations);
+
+    let schemas = config.gsettings.schemas.clone();
+
+    let setter = wallpaper::from_config(&config.wallpaper);
+
+    let (events, pressed) = tokio::sync::mpsc::unbounded_channel();
+
+    let grabber = shortcuts::from_config(&config.global_shortcuts, events);
+
+    let (events, crossed) = tokio::sync::mpsc::unbounded_channel();
+
+    let intercept = intercept::from_config(&config.input_capture, events);
+
+    let builder =
+        zbus::ConnectionBuilder::session()?.name("org.freedesktop.impl.portal.desktop.rs")?;
+
+    let conn = service::serve(
+        builder, config, dialogs, scheduler, audit, capture, cast, input, clipboard, notifier,
+        setter, grabber, intercept,
+    )?
+    .build()
+    .await?;
+
+    tokio::spawn(service::invoke_actions(conn.clone(), activated));
+    tokio::spawn(service::monitor_session(conn.clone()));
+    tokio::spawn(service::watch_settings(conn.clone(), schemas));
+    tokio::spawn(service::activate_shortcuts(conn.clone(), pressed));
+    tokio::spawn(service::signal_captures(conn.clone(), crossed));
 
     std::future::pending::<()>().await;
 
@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::desktop;
+
+/// Describe a content type for people, like `PDF document` for `application/pdf`.
+///
+/// The description comes from the shared-mime-info database, in the user's language
+/// if it's translated.
+pub fn description(content_type: &str) -> Option<String> {
+    // Content types come from the caller, so make sure they stay inside the database.
+    let (media, subtype) = content_type.split_once('/')?;
+
+    if [media, subtype]
+        .iter()
+        .any(|part| part.is_empty() || part.contains('/') || part.starts_with('.'))
+    {
+        return None;
+    }
+
+    let contents = desktop::data_dirs()
+        .into_iter()
+        .map(|dir| {
+            dir.join("mime")
+                .join(media)
+                .join(format!("{}.xml", subtype))
+        })
+        .find_map(|path| std::fs::read_to_string(path).ok())?;
+
+    comment(&contents, &desktop::locales())
+}
+
+/// Get the comment of a shared-mime-info type file, preferring the given locales.
+fn comment(contents: &str, locales: &[String]) -> Option<String> {
+    let mut comments: HashMap<Option<String>, String> = HashMap::new();
+    let mut lang: Option<Option<String>> = None;
+
+    for event in EventReader::new(contents.as_bytes()) {
+        match event.ok()? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } if name.local_name == "comment" => {
+                lang = Some(
+                    attributes
+                        .into_iter()
+                        .find(|attribute| attribute.name.local_name == "lang")
+                        .map(|attribute| attribute.value),
+                );
+            }
+
+            XmlEvent::Characters(text) => {
+                if let Some(lang) = lang.take() {
+                    comments.insert(lang, text);
+                }
+            }
+
+            XmlEvent::EndElement { .. } => lang = None,
+
+            _ => {}
+        }
+    }
+
+    locales
+        .iter()
+        .find_map(|locale| comments.remove(&Some(locale.clone())))
+        .or_else(|| comments.remove(&None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::comment;
+
+    #[test]
+    fn localized_comment() {
+        let contents = r#"<?xml version="1.0" encoding="utf-8"?>
+<mime-type xmlns="http://www.freedesktop.org/standards/shared-mime-info" type="application/pdf">
+  <comment>PDF document</comment>
+  <comment xml:lang="de">PDF-Dokument</comment>
+  <comment xml:lang="pt_BR">Documento PDF</comment>
+  <acronym>PDF</acronym>
+</mime-type>"#;
+
+        let locales = |keys: &[&str]| keys.iter().map(|key| key.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            comment(contents, &locales(&["de_AT", "de"])).as_deref(),
+            Some("PDF-Dokument")
+        );
+        assert_eq!(
+            comment(contents, &locales(&["fr"])).as_deref(),
+            Some("PDF document")
+        );
+    }
+}
@@ -0,0 +1,120 @@
+use std::{path::PathBuf, sync::Arc};
+
+use tokio::sync::mpsc::UnboundedSender;
+use zbus::zvariant;
+
+use crate::{
+    config::{NotificationBackend, NotificationConfig},
+    dialog::DialogProvider,
+};
+
+mod daemon;
+mod image;
+mod popup;
+
+/// `Notification` is a notification an app asked to show.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Notification {
+    pub title: String,
+    pub body: String,
+    pub icon: Option<Icon>,
+    pub priority: Priority,
+
+    /// The sound played with the notification, or the daemon's choice if `None`.
+    pub sound: Option<Sound>,
+
+    /// What activating the notification itself does, if anything.
+    pub default_action: Option<Action>,
+
+    /// The buttons, in the order the app gave them.
+    pub buttons: Vec<Button>,
+}
+
+/// `Icon` is the icon of a notification, as apps give it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Icon {
+    /// Names of the icon in the icon theme, best first.
+    Themed(Vec<String>),
+
+    /// An image file, like a PNG.
+    Bytes(Vec<u8>),
+
+    /// A file with the image.
+    File(PathBuf),
+}
+
+/// `Priority` is how much a notification asks for the user's attention.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+    Urgent,
+}
+
+/// `Sound` is the sound of a notification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sound {
+    /// The usual sound of notifications.
+    Default,
+
+    /// No sound.
+    Silent,
+
+    /// A sound file.
+    File(PathBuf),
+}
+
+/// `Button` is a button of a notification.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Button {
+    pub label: String,
+    pub action: Action,
+}
+
+/// `Action` is an action of the app, invoked with `target` as its parameter if there's one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Action {
+    pub name: String,
+    pub target: Option<zvariant::OwnedValue>,
+}
+
+/// `Activation` is the user activating the notification called `id` of `app_id`, through
+/// the button at the index `button`, or through the notification itself if that's `None`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Activation {
+    pub app_id: String,
+    pub id: String,
+    pub button: Option<usize>,
+}
+
+/// `Notifier` delivers the notifications of apps to the user.
+///
+/// Showing and withdrawing notifications may block, so callers run them off the async
+/// executor.
+pub trait Notifier: Send + Sync {
+    /// Show `notification` as the one called `id` of `app_id`, replacing the one shown with
+    /// that id before.
+    fn show(&self, app_id: &str, id: &str, notification: &Notification) -> std::io::Result<()>;
+
+    /// Withdraw the notification called `id` of `app_id`.
+    fn withdraw(&self, app_id: &str, id: &str);
+}
+
+/// Create the notification backend selected in the config, which shows popups with `dialogs`
+/// and tells `activations` what the user activates.
+///
+/// It must be called from within the tokio runtime.
+pub fn from_config(
+    config: &NotificationConfig,
+    dialogs: Arc<dyn DialogProvider>,
+    activations: UnboundedSender<Activation>,
+) -> Arc<dyn Notifier> {
+    log::debug!("showing notifications with {:?}", config.backend);
+
+    match config.backend {
+        NotificationBackend::Daemon => Arc::new(daemon::Daemon::new(activations)),
+        NotificationBackend::Popup => Arc::new(popup::Popup::new(dialogs, activations)),
+    }
+}
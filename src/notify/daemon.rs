@@ -0,0 +1,362 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use futures_util::StreamExt;
+use tokio::sync::mpsc::UnboundedSender;
+use zbus::{dbus_proxy, zvariant};
+
+use super::{image, Activation, Icon, Notification, Notifier, Priority, Sound};
+use crate::{desktop, uri};
+
+/// The key of the action activating a notification itself, which daemons don't show as
+/// a button.
+const DEFAULT_ACTION: &str = "default";
+
+/// The org.freedesktop.Notifications interface of notification daemons.
+#[dbus_proxy(
+    interface = "org.freedesktop.Notifications",
+    default_service = "org.freedesktop.Notifications",
+    default_path = "/org/freedesktop/Notifications"
+)]
+trait Notifications {
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &self,
+        app_name: &str,
+        replaces_id: u32,
+        app_icon: &str,
+        summary: &str,
+        body: &str,
+        actions: &[&str],
+        hints: HashMap<&str, zvariant::Value<'_>>,
+        expire_timeout: i32,
+    ) -> zbus::Result<u32>;
+
+    fn close_notification(&self, id: u32) -> zbus::Result<()>;
+
+    #[dbus_proxy(signal)]
+    fn action_invoked(&self, id: u32, action_key: &str) -> zbus::Result<()>;
+
+    #[dbus_proxy(signal)]
+    fn notification_closed(&self, id: u32, reason: u32) -> zbus::Result<()>;
+}
+
+/// `Ids` maps the notifications shown, by app id and id, to the daemon's ids of them.
+type Ids = Arc<Mutex<HashMap<(String, String), u32>>>;
+
+/// `Daemon` hands notifications to the session's notification daemon, like mako, dunst or
+/// GNOME Shell.
+///
+/// The daemon has ids of its own, so it's told which notification an app replaces or
+/// withdraws by those of the notifications it was given, and tells which one the user
+/// activated by them too.
+pub struct Daemon {
+    runtime: tokio::runtime::Handle,
+    connection: tokio::sync::OnceCell<zbus::Connection>,
+    ids: Ids,
+    activations: UnboundedSender<Activation>,
+}
+
+impl Daemon {
+    /// Create the backend; it must be called from within the tokio runtime.
+    pub fn new(activations: UnboundedSender<Activation>) -> Self {
+        Self {
+            runtime: tokio::runtime::Handle::current(),
+            connection: tokio::sync::OnceCell::new(),
+            ids: Ids::default(),
+            activations,
+        }
+    }
+
+    /// Get the session bus connection used to talk to the daemon, listening for what the user
+    /// does with the notifications once it's connected.
+    async fn connection(&self) -> zbus::Result<&zbus::Connection> {
+        self.connection
+            .get_or_try_init(|| async {
+                let conn = zbus::Connection::session().await?;
+
+                let proxy = NotificationsProxy::new(&conn).await?;
+
+                let invoked = proxy.receive_action_invoked().await?;
+                let closed = proxy.receive_notification_closed().await?;
+
+                tokio::spawn(listen(
+                    invoked,
+                    closed,
+                    self.ids.clone(),
+                    self.activations.clone(),
+                ));
+
+                Ok(conn)
+            })
+            .await
+    }
+}
+
+impl Notifier for Daemon {
+    fn show(&self, app_id: &str, id: &str, notification: &Notification) -> std::io::Result<()> {
+        let key = (app_id.to_owned(), id.to_owned());
+
+        let replaces = match self.ids.lock() {
+            Ok(ids) => ids.get(&key).copied().unwrap_or(0),
+            Err(_) => 0,
+        };
+
+        let app_name = match app_id {
+            "" => String::new(),
+            app_id => desktop::lookup(app_id).name,
+        };
+
+        let actions = actions(notification);
+        let actions: Vec<&str> = actions.iter().map(String::as_str).collect();
+
+        // Daemons find the app's icon and settings by its desktop entry.
+        let mut hints = HashMap::new();
+
+        if !app_id.is_empty() {
+            hints.insert("desktop-entry", zvariant::Value::from(app_id));
+        }
+
+        hints.insert("urgency", urgency(notification.priority).into());
+
+        if let Some(icon) = &notification.icon {
+            if let Some((hint, value)) = icon_hint(icon) {
+                hints.insert(hint, value);
+            }
+        }
+
+        if let Some(sound) = &notification.sound {
+            let (hint, value) = sound_hint(sound);
+            hints.insert(hint, value);
+        }
+
+        let daemon_id = self
+            .runtime
+            .block_on(async {
+                let proxy = NotificationsProxy::new(self.connection().await?).await?;
+
+                proxy
+                    .notify(
+                        &app_name,
+                        replaces,
+                        "",
+                        &notification.title,
+                        &notification.body,
+                        &actions,
+                        hints,
+                        -1,
+                    )
+                    .await
+            })
+            .map_err(std::io::Error::other)?;
+
+        if let Ok(mut ids) = self.ids.lock() {
+            ids.insert(key, daemon_id);
+        }
+
+        Ok(())
+    }
+
+    fn withdraw(&self, app_id: &str, id: &str) {
+        let daemon_id = match self.ids.lock() {
+            Ok(mut ids) => ids.remove(&(app_id.to_owned(), id.to_owned())),
+            Err(_) => None,
+        };
+
+        let Some(daemon_id) = daemon_id else {
+            return;
+        };
+
+        let closed = self.runtime.block_on(async {
+            let proxy = NotificationsProxy::new(self.connection().await?).await?;
+
+            proxy.close_notification(daemon_id).await
+        });
+
+        if let Err(e) = closed {
+            log::warn!("failed to close a notification of {}: {}", app_id, e);
+        }
+    }
+}
+
+/// Tell `activations` about the actions the user invokes, and forget the notifications the
+/// daemon closes.
+async fn listen(
+    mut invoked: ActionInvokedStream<'static>,
+    mut closed: NotificationClosedStream<'static>,
+    ids: Ids,
+    activations: UnboundedSender<Activation>,
+) {
+    loop {
+        tokio::select! {
+            Some(signal) = invoked.next() => {
+                let Ok(args) = signal.args() else {
+                    continue;
+                };
+
+                let key = match ids.lock() {
+                    Ok(ids) => find(&ids, *args.id()),
+                    Err(_) => None,
+                };
+
+                // Other clients of the daemon are told about their notifications too.
+                let Some((app_id, id)) = key else {
+                    continue;
+                };
+
+                let button = match *args.action_key() {
+                    DEFAULT_ACTION => None,
+
+                    key => match key.parse() {
+                        Ok(index) => Some(index),
+                        Err(_) => continue,
+                    },
+                };
+
+                let _ = activations.send(Activation { app_id, id, button });
+            }
+
+            Some(signal) = closed.next() => {
+                let Ok(args) = signal.args() else {
+                    continue;
+                };
+
+                if let Ok(mut ids) = ids.lock() {
+                    ids.retain(|_, daemon_id| daemon_id != args.id());
+                }
+            }
+
+            else => return,
+        }
+    }
+}
+
+/// Find the app id and id of the notification the daemon knows as `daemon_id`.
+fn find(ids: &HashMap<(String, String), u32>, daemon_id: u32) -> Option<(String, String)> {
+    ids.iter()
+        .find(|(_, id)| **id == daemon_id)
+        .map(|(key, _)| key.clone())
+}
+
+/// Get the hint showing `icon` as the image of a notification.
+///
+/// Images are handed over decoded, or as a file in the cache if they can't be decoded here.
+fn icon_hint(icon: &Icon) -> Option<(&'static str, zvariant::Value<'static>)> {
+    let path = match icon {
+        // Daemons look names up in the icon theme.
+        Icon::Themed(names) => return Some(("image-path", names.first()?.clone().into())),
+
+        Icon::File(path) => path.clone(),
+
+        Icon::Bytes(bytes) => match image::decode(bytes) {
+            Some(image) => {
+                let data = zvariant::StructureBuilder::new()
+                    .add_field(image.width)
+                    .add_field(image.height)
+                    .add_field(image.rowstride)
+                    .add_field(image.has_alpha)
+                    .add_field(8i32)
+                    .add_field(image.channels)
+                    .add_field(image.data)
+                    .build();
+
+                return Some(("image-data", data.into()));
+            }
+
+            None => match image::cache(bytes) {
+                Ok(path) => path,
+
+                Err(e) => {
+                    log::warn!("failed to cache a notification image: {}", e);
+                    return None;
+                }
+            },
+        },
+    };
+
+    Some(("image-path", uri::file_uri(&path).into()))
+}
+
+/// Get the urgency level of the daemon for `priority`, which has no level between normal and
+/// critical.
+fn urgency(priority: Priority) -> u8 {
+    match priority {
+        Priority::Low => 0,
+        Priority::Normal | Priority::High => 1,
+        Priority::Urgent => 2,
+    }
+}
+
+/// Get the hint playing `sound` with a notification.
+fn sound_hint(sound: &Sound) -> (&'static str, zvariant::Value<'static>) {
+    match sound {
+        Sound::Default => ("sound-name", "message-new-instant".into()),
+        Sound::Silent => ("suppress-sound", true.into()),
+        Sound::File(path) => ("sound-file", path.to_string_lossy().into_owned().into()),
+    }
+}
+
+/// List the actions of `notification` for the daemon, as pairs of a key and a label.
+///
+/// Buttons are keyed by their index, which tells them apart even if apps give several
+/// the same action.
+fn actions(notification: &Notification) -> Vec<String> {
+    let default = notification
+        .default_action
+        .as_ref()
+        .map(|_| [String::from(DEFAULT_ACTION), String::from("Open")]);
+
+    let buttons = notification
+        .buttons
+        .iter()
+        .enumerate()
+        .map(|(index, button)| [index.to_string(), button.label.clone()]);
+
+    default.into_iter().chain(buttons).flatten().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{actions, urgency};
+    use crate::notify::{Action, Button, Notification, Priority};
+
+    #[test]
+    fn daemon_actions() {
+        let action = |name: &str| Action {
+            name: name.to_owned(),
+            target: None,
+        };
+
+        let mut notification = Notification {
+            buttons: vec![
+                Button {
+                    label: String::from("Reply"),
+                    action: action("reply"),
+                },
+                Button {
+                    label: String::from("Mark as read"),
+                    action: action("read"),
+                },
+            ],
+            ..Notification::default()
+        };
+
+        assert_eq!(actions(&notification), ["0", "Reply", "1", "Mark as read"]);
+
+        notification.default_action = Some(action("open"));
+
+        assert_eq!(
+            actions(&notification),
+            ["default", "Open", "0", "Reply", "1", "Mark as read"]
+        );
+    }
+
+    #[test]
+    fn daemon_urgency() {
+        assert_eq!(urgency(Priority::Low), 0);
+        assert_eq!(urgency(Priority::High), 1);
+        assert_eq!(urgency(Priority::Urgent), 2);
+    }
+}
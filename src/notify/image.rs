@@ -0,0 +1,121 @@
+use std::{
+    hash::{Hash, Hasher},
+    path::PathBuf,
+};
+
+/// `Image` is a decoded image in the layout of the `image-data` hint of notifications.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: i32,
+    pub height: i32,
+    pub rowstride: i32,
+    pub has_alpha: bool,
+    pub channels: i32,
+
+    /// The pixels, 8 bits per sample, row after row.
+    pub data: Vec<u8>,
+}
+
+/// Decode a PNG image, or `None` if `bytes` are another format or broken.
+pub fn decode(bytes: &[u8]) -> Option<Image> {
+    let mut decoder = png::Decoder::new(bytes);
+
+    // Expand palettes and low bit depths, and strip 16-bit channels down to 8 bits.
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+
+    let mut reader = decoder
+        .read_info()
+        .map_err(|e| log::debug!("failed to decode a notification image: {}", e))
+        .ok()?;
+
+    let mut buffer = vec![0; reader.output_buffer_size()];
+
+    let info = reader.next_frame(&mut buffer).ok()?;
+
+    buffer.truncate(info.buffer_size());
+
+    // Daemons take RGB and RGBA, so gray pixels are spread over the three colors.
+    let (channels, data) = match info.color_type {
+        png::ColorType::Rgba => (4, buffer),
+        png::ColorType::Rgb => (3, buffer),
+
+        png::ColorType::GrayscaleAlpha => (
+            4,
+            buffer
+                .chunks_exact(2)
+                .flat_map(|p| [p[0], p[0], p[0], p[1]])
+                .collect(),
+        ),
+
+        png::ColorType::Grayscale | png::ColorType::Indexed => {
+            (3, buffer.iter().flat_map(|&g| [g, g, g]).collect())
+        }
+    };
+
+    Some(Image {
+        width: info.width.try_into().ok()?,
+        height: info.height.try_into().ok()?,
+        rowstride: (info.width * channels).try_into().ok()?,
+        has_alpha: channels == 4,
+        channels: channels.try_into().ok()?,
+        data,
+    })
+}
+
+/// Write image `bytes` to the cache, for daemons to load images that can't be decoded here,
+/// returning the path of the file.
+///
+/// Files are named by the hash of the image, so showing it again doesn't write it again.
+pub fn cache(bytes: &[u8]) -> std::io::Result<PathBuf> {
+    let dir = dirs::cache_dir()
+        .ok_or_else(|| std::io::Error::other("no cache directory"))?
+        .join("xdg-desktop-portal-rs")
+        .join("notifications");
+
+    let path = dir.join(file_name(bytes));
+
+    if !path.is_file() {
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(&path, bytes)?;
+    }
+
+    Ok(path)
+}
+
+/// Name the cache file of image `bytes`.
+fn file_name(bytes: &[u8]) -> String {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+
+    bytes.hash(&mut hasher);
+
+    format!("{:016x}", hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, file_name};
+
+    #[test]
+    fn images() {
+        let mut png = Vec::new();
+
+        let mut encoder = png::Encoder::new(&mut png, 2, 1);
+        encoder.set_color(png::ColorType::GrayscaleAlpha);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[0, 255, 200, 128]).unwrap();
+        writer.finish().unwrap();
+
+        let image = decode(&png).unwrap();
+
+        assert_eq!((image.width, image.height, image.rowstride), (2, 1, 8));
+        assert!(image.has_alpha);
+        assert_eq!(image.data, [0, 0, 0, 255, 200, 200, 200, 128]);
+
+        assert_eq!(decode(b"GIF89a"), None);
+
+        assert_eq!(file_name(&png), file_name(&png));
+        assert_ne!(file_name(&png), file_name(b"GIF89a"));
+    }
+}
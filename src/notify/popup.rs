@@ -0,0 +1,137 @@
+use std::sync::Arc;
+
+use tokio::sync::mpsc::UnboundedSender;
+
+use super::{Activation, Notification, Notifier, Priority};
+use crate::{
+    desktop,
+    dialog::{DialogProvider, Level, Message},
+};
+
+/// `Popup` shows notifications as message dialogs of the dialog provider, with a button for
+/// each of their buttons, and one opening them if they have a default action.
+///
+/// Dialogs can't be closed by anyone but the user, so withdrawn or replaced notifications
+/// stay open until they're dismissed. Message dialogs have no room for images, so icons
+/// aren't shown.
+pub struct Popup {
+    dialogs: Arc<dyn DialogProvider>,
+    activations: UnboundedSender<Activation>,
+}
+
+impl Popup {
+    pub fn new(dialogs: Arc<dyn DialogProvider>, activations: UnboundedSender<Activation>) -> Self {
+        Self {
+            dialogs,
+            activations,
+        }
+    }
+}
+
+impl Notifier for Popup {
+    fn show(&self, app_id: &str, id: &str, notification: &Notification) -> std::io::Result<()> {
+        let message = message(app_id, notification);
+
+        let opens = notification.default_action.is_some();
+
+        let labels: Vec<String> = opens
+            .then(|| String::from("Open"))
+            .into_iter()
+            .chain(
+                notification
+                    .buttons
+                    .iter()
+                    .map(|button| button.label.clone()),
+            )
+            .collect();
+
+        let dialogs = self.dialogs.clone();
+
+        let activations = self.activations.clone();
+
+        let (app_id, id) = (app_id.to_owned(), id.to_owned());
+
+        // Notifications don't wait for the user, so each popup has a thread of its own.
+        std::thread::Builder::new()
+            .name(String::from("notification"))
+            .spawn(move || {
+                if labels.is_empty() {
+                    return dialogs.message(&message);
+                }
+
+                if let Some(index) = dialogs.choose(&message, &labels) {
+                    let _ = activations.send(Activation {
+                        app_id,
+                        id,
+                        button: button(opens, index),
+                    });
+                }
+            })?;
+
+        Ok(())
+    }
+
+    fn withdraw(&self, _app_id: &str, _id: &str) {}
+}
+
+/// Describe a notification of `app_id` as a message, titled with the app's name, and warning
+/// if the notification is urgent.
+fn message(app_id: &str, notification: &Notification) -> Message {
+    let app = match app_id {
+        "" => None,
+        app_id => Some(desktop::lookup(app_id).name),
+    };
+
+    let (title, description) = match (app, notification.body.is_empty()) {
+        (Some(app), true) => (app, notification.title.clone()),
+        (Some(app), false) => (
+            app,
+            format!("{}\n\n{}", notification.title, notification.body),
+        ),
+        (None, _) => (notification.title.clone(), notification.body.clone()),
+    };
+
+    let level = match notification.priority {
+        Priority::Urgent => Level::Warning,
+        _ => Level::Info,
+    };
+
+    Message {
+        title,
+        description,
+        level,
+        ..Message::default()
+    }
+}
+
+/// Get the button of the option at `index` of a popup, which `opens` the notification first.
+fn button(opens: bool, index: usize) -> Option<usize> {
+    match opens {
+        true => index.checked_sub(1),
+        false => Some(index),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{button, message, Level, Notification};
+
+    #[test]
+    fn messages() {
+        let notification = Notification {
+            title: String::from("Download complete"),
+            body: String::from("report.pdf"),
+            ..Notification::default()
+        };
+
+        let message = message("", &notification);
+
+        assert_eq!(message.title, "Download complete");
+        assert_eq!(message.description, "report.pdf");
+        assert_eq!(message.level, Level::Info);
+
+        assert_eq!(button(true, 0), None);
+        assert_eq!(button(true, 2), Some(1));
+        assert_eq!(button(false, 0), Some(0));
+    }
+}
@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+
+use zbus::{dbus_proxy, zvariant};
+
+/// The permission store of xdg-desktop-portal, which keeps decisions across sessions.
+#[dbus_proxy(
+    interface = "org.freedesktop.impl.portal.PermissionStore",
+    default_service = "org.freedesktop.impl.portal.PermissionStore",
+    default_path = "/org/freedesktop/impl/portal/PermissionStore"
+)]
+trait PermissionStore {
+    /// Get the permissions of every app on the entry `id` of `table`.
+    fn lookup(
+        &self,
+        table: &str,
+        id: &str,
+    ) -> zbus::Result<(HashMap<String, Vec<String>>, zvariant::OwnedValue)>;
+
+    /// Set the permissions of `app` on the entry `id` of `table`.
+    fn set_permission(
+        &self,
+        table: &str,
+        create: bool,
+        id: &str,
+        app: &str,
+        permissions: &[&str],
+    ) -> zbus::Result<()>;
+}
+
+/// Get the permissions `app_id` was given on an entry, which are empty if there are none.
+pub async fn lookup(
+    conn: &zbus::Connection,
+    table: &str,
+    id: &str,
+    app_id: &str,
+) -> zbus::Result<Vec<String>> {
+    let store = PermissionStoreProxy::new(conn).await?;
+
+    match store.lookup(table, id).await {
+        Ok((mut permissions, _)) => Ok(permissions.remove(app_id).unwrap_or_default()),
+
+        // The store answers NotFound for tables and entries nobody has written yet.
+        Err(zbus::Error::MethodError(name, _, _))
+            if name.as_str() == "org.freedesktop.portal.Error.NotFound" =>
+        {
+            Ok(Vec::new())
+        }
+
+        Err(e) => Err(e),
+    }
+}
+
+/// Give `app_id` the permissions on an entry, creating the table if needed.
+pub async fn set(
+    conn: &zbus::Connection,
+    table: &str,
+    id: &str,
+    app_id: &str,
+    permissions: &[&str],
+) -> zbus::Result<()> {
+    PermissionStoreProxy::new(conn)
+        .await?
+        .set_permission(table, true, id, app_id, permissions)
+        .await
+}
@@ -0,0 +1,137 @@
+use std::path::{Path, PathBuf};
+
+use crate::{config::PolicyConfig, resolve::canonical};
+
+/// `Rules` are the directories one application may and may not pick files in.
+#[derive(Debug, Default, Clone)]
+pub struct Rules {
+    /// If set, only paths inside these directories are allowed.
+    allow: Option<Vec<PathBuf>>,
+
+    /// Paths inside these directories are never allowed.
+    deny: Vec<PathBuf>,
+}
+
+impl Rules {
+    /// Build the rules for `app_id`.
+    ///
+    /// An app's `allow` list replaces the global one, and its `deny` list adds to the global one.
+    pub fn new(config: &PolicyConfig, app_id: &str) -> Self {
+        let app = config.apps.get(app_id);
+
+        let allow = app
+            .and_then(|app| app.allow.as_ref())
+            .or(config.allow.as_ref())
+            .map(|allow| allow.iter().map(|dir| canonical(&expand(dir))).collect());
+
+        let deny = config
+            .deny
+            .iter()
+            .chain(app.into_iter().flat_map(|app| &app.deny))
+            .map(|dir| canonical(&expand(dir)))
+            .collect();
+
+        Self { allow, deny }
+    }
+
+    /// Get the first of `paths` these rules forbid, if any.
+    pub fn denied<'a>(&self, paths: &'a [PathBuf]) -> Option<&'a PathBuf> {
+        if self.allow.is_none() && self.deny.is_empty() {
+            return None;
+        }
+
+        // Paths are resolved so they can't slip out of a directory through a link.
+        paths.iter().find(|path| !self.permits(&canonical(path)))
+    }
+
+    /// Whether a resolved path is allowed.
+    fn permits(&self, path: &Path) -> bool {
+        if self.deny.iter().any(|dir| path.starts_with(dir)) {
+            return false;
+        }
+
+        match &self.allow {
+            Some(allow) => allow.iter().any(|dir| path.starts_with(dir)),
+            None => true,
+        }
+    }
+}
+
+/// Expand a leading `~` to the home directory.
+pub fn expand(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::Rules;
+    use crate::config::{AppPolicy, PolicyConfig};
+
+    fn paths(paths: &[&str]) -> Vec<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn unrestricted() {
+        let rules = Rules::new(&PolicyConfig::default(), "org.example.App");
+
+        assert_eq!(rules.denied(&paths(&["/etc/shadow"])), None);
+    }
+
+    #[test]
+    fn allow_and_deny() {
+        let config = PolicyConfig {
+            allow: Some(paths(&["/nonexistent/kiosk"])),
+            deny: paths(&["/nonexistent/kiosk/private"]),
+            ..PolicyConfig::default()
+        };
+
+        let rules = Rules::new(&config, "org.example.App");
+
+        assert_eq!(rules.denied(&paths(&["/nonexistent/kiosk/a.txt"])), None);
+
+        assert_eq!(
+            rules.denied(&paths(&["/nonexistent/kiosk/a.txt", "/nonexistent/other"])),
+            Some(&PathBuf::from("/nonexistent/other"))
+        );
+
+        assert_eq!(
+            rules.denied(&paths(&["/nonexistent/kiosk/private/key"])),
+            Some(&PathBuf::from("/nonexistent/kiosk/private/key"))
+        );
+
+        // Prefixes only match whole components.
+        assert!(rules.denied(&paths(&["/nonexistent/kiosk2"])).is_some());
+    }
+
+    #[test]
+    fn per_app() {
+        let mut config = PolicyConfig {
+            allow: Some(paths(&["/nonexistent/shared"])),
+            ..PolicyConfig::default()
+        };
+
+        config.apps.insert(
+            String::from("org.example.Editor"),
+            AppPolicy {
+                allow: Some(paths(&["/nonexistent/projects"])),
+                deny: paths(&["/nonexistent/projects/secret"]),
+            },
+        );
+
+        let editor = Rules::new(&config, "org.example.Editor");
+        let other = Rules::new(&config, "org.example.Other");
+
+        assert_eq!(editor.denied(&paths(&["/nonexistent/projects/a"])), None);
+        assert!(editor.denied(&paths(&["/nonexistent/shared/a"])).is_some());
+        assert!(editor
+            .denied(&paths(&["/nonexistent/projects/secret/a"]))
+            .is_some());
+        assert!(other.denied(&paths(&["/nonexistent/projects/a"])).is_some());
+    }
+}
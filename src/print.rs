@@ -0,0 +1,454 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::account::User;
+
+mod ipp;
+
+use ipp::{Message, Value};
+
+/// The `document-format` of printed documents, which has cupsd work out their type.
+const AUTO_FORMAT: &str = "application/octet-stream";
+
+/// How long the settings of PreparePrint are kept for a Print call, as apps may never make it.
+const PREPARED_LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+/// The attributes of printers PreparePrint offers.
+const PRINTER_ATTRIBUTES: [&str; 4] = [
+    "printer-name",
+    "printer-info",
+    "media-supported",
+    "media-default",
+];
+
+/// `Printer` is a CUPS queue that can be printed on.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Printer {
+    pub name: String,
+
+    /// The description set for the queue, or its name if there's none.
+    pub description: String,
+
+    /// The PWG names of the paper sizes it takes.
+    pub media: Vec<String>,
+
+    /// The paper size it uses unless it's told otherwise.
+    pub default_media: Option<String>,
+}
+
+/// `Settings` is how a document is printed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Settings {
+    /// The CUPS queue printed on.
+    pub printer: String,
+
+    pub copies: u32,
+
+    /// The PWG name of the paper size, like `iso_a4_210x297mm`, or `None` for the printer's
+    /// default.
+    pub media: Option<String>,
+
+    pub landscape: bool,
+
+    /// The pages printed, as inclusive ranges counted from 1, or all if it's empty.
+    pub ranges: Vec<(u32, u32)>,
+}
+
+impl Settings {
+    /// Print on `printer` as it's set up.
+    pub fn new(printer: String) -> Self {
+        Self {
+            printer,
+            copies: 1,
+            media: None,
+            landscape: false,
+            ranges: Vec::new(),
+        }
+    }
+}
+
+/// `Prepared` keeps the settings of PreparePrint until the Print calls they're for.
+#[derive(Debug, Default)]
+pub struct Prepared {
+    settings: Mutex<HashMap<u32, (String, Settings, Instant)>>,
+    next: AtomicU32,
+}
+
+impl Prepared {
+    /// Keep `settings` for a Print call of `app_id`, returning the token it's passed.
+    pub fn insert(&self, app_id: &str, settings: Settings) -> u32 {
+        // Tokens start at 1, as apps treat 0 as none.
+        let token = self.next.fetch_add(1, Ordering::Relaxed) + 1;
+
+        let now = Instant::now();
+
+        self.expire(now)
+            .insert(token, (String::from(app_id), settings, now));
+
+        token
+    }
+
+    /// Take the settings `token` stands for, if `app_id` got it.
+    pub fn take(&self, app_id: &str, token: u32) -> Option<Settings> {
+        let mut prepared = self.expire(Instant::now());
+
+        match prepared.get(&token) {
+            Some((owner, ..)) if owner == app_id => prepared.remove(&token).map(|(_, s, _)| s),
+            _ => None,
+        }
+    }
+
+    /// Drop the settings kept longer than `PREPARED_LIFETIME` at `now`, returning the rest.
+    fn expire(&self, now: Instant) -> MutexGuard<'_, HashMap<u32, (String, Settings, Instant)>> {
+        let mut prepared = self.settings.lock().unwrap_or_else(|e| e.into_inner());
+
+        prepared.retain(|_, (.., kept)| now.duration_since(*kept) < PREPARED_LIFETIME);
+
+        prepared
+    }
+}
+
+/// List the CUPS queues, the default one first.
+pub fn printers() -> std::io::Result<Vec<Printer>> {
+    let mut request = Message::request(ipp::CUPS_GET_PRINTERS);
+
+    let requested = PRINTER_ATTRIBUTES
+        .iter()
+        .map(|name| Value::String(ipp::KEYWORD, String::from(*name)))
+        .collect();
+
+    request.add(
+        ipp::OPERATION,
+        (String::from("requested-attributes"), requested),
+    );
+
+    let response = ipp::send("/", &request, &[])?;
+
+    // CUPS answers that it has no printers with a status rather than an empty list.
+    if !response.succeeded() {
+        return Ok(Vec::new());
+    }
+
+    let mut printers: Vec<Printer> = response.groups(ipp::PRINTER).filter_map(printer).collect();
+
+    let default = default_printer().unwrap_or_default();
+
+    printers.sort_by_key(|printer| Some(&printer.name) != default.as_ref());
+
+    Ok(printers)
+}
+
+/// Read a printer from its attributes, if it has a name.
+fn printer(attributes: &[ipp::Attribute]) -> Option<Printer> {
+    let values = |name: &str| -> Vec<String> {
+        attributes
+            .iter()
+            .find(|(attribute, _)| attribute == name)
+            .map(|(_, values)| {
+                values
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    let name = values("printer-name").into_iter().next()?;
+
+    let description = values("printer-info")
+        .into_iter()
+        .next()
+        .filter(|info| !info.is_empty())
+        .unwrap_or_else(|| name.clone());
+
+    Some(Printer {
+        name,
+        description,
+        media: values("media-supported"),
+        default_media: values("media-default").into_iter().next(),
+    })
+}
+
+/// Get the width and height in millimeters of the paper a self-describing PWG media name
+/// like `iso_a4_210x297mm` or `na_letter_8.5x11in` stands for.
+pub fn media_size(media: &str) -> Option<(f64, f64)> {
+    let size = media.rsplit('_').next()?;
+
+    let (size, scale) = match size.strip_suffix("mm") {
+        Some(size) => (size, 1.0),
+        None => (size.strip_suffix("in")?, 25.4),
+    };
+
+    let (width, height) = size.split_once('x')?;
+
+    Some((
+        width.parse::<f64>().ok()? * scale,
+        height.parse::<f64>().ok()? * scale,
+    ))
+}
+
+/// Name a PWG media name for people, like `A4` for `iso_a4_210x297mm`.
+pub fn media_name(media: &str) -> String {
+    let parts: Vec<&str> = media.split('_').collect();
+
+    // The name is between the class and the size, and may contain underscores itself.
+    let name = match parts.as_slice() {
+        [_, name @ .., _] if !name.is_empty() => name.join(" "),
+        _ => return media.to_owned(),
+    };
+
+    let mut chars = name.chars();
+
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => media.to_owned(),
+    }
+}
+
+/// Parse page ranges as people write them, like `1-3, 5`, or `None` if they can't be.
+#[cfg_attr(not(feature = "egui"), allow(dead_code))]
+pub fn parse_ranges(text: &str) -> Option<Vec<(u32, u32)>> {
+    text.split(',')
+        .map(str::trim)
+        .filter(|range| !range.is_empty())
+        .map(|range| {
+            let (first, last) = match range.split_once('-') {
+                Some((first, last)) => (first.trim().parse().ok()?, last.trim().parse().ok()?),
+                None => (range.parse().ok()?, range.parse().ok()?),
+            };
+
+            (0 < first && first <= last).then_some((first, last))
+        })
+        .collect()
+}
+
+/// Write page ranges as people do, like `1-3, 5`.
+#[cfg_attr(not(feature = "egui"), allow(dead_code))]
+pub fn format_ranges(ranges: &[(u32, u32)]) -> String {
+    let ranges: Vec<String> = ranges
+        .iter()
+        .map(|(first, last)| match first == last {
+            true => first.to_string(),
+            false => format!("{}-{}", first, last),
+        })
+        .collect();
+
+    ranges.join(", ")
+}
+
+/// Get the name of the default CUPS queue, or `None` if there's none.
+pub fn default_printer() -> std::io::Result<Option<String>> {
+    let response = ipp::send("/", &Message::request(ipp::CUPS_GET_DEFAULT), &[])?;
+
+    let name = response
+        .get(ipp::PRINTER, "printer-name")
+        .and_then(|values| values.first()?.as_str());
+
+    Ok(name.filter(|_| response.succeeded()).map(String::from))
+}
+
+/// Print `document` as `settings` say, naming the job `title`, and return its id.
+pub fn submit(settings: &Settings, title: &str, document: &[u8]) -> std::io::Result<u32> {
+    let path = format!("/printers/{}", settings.printer);
+
+    let response = ipp::send(&path, &job(settings, title), document)?;
+
+    if !response.succeeded() {
+        let message = response
+            .get(ipp::OPERATION, "status-message")
+            .and_then(|values| values.first()?.as_str())
+            .map(String::from)
+            .unwrap_or_else(|| format!("status {:#06x}", response.code));
+
+        return Err(std::io::Error::other(format!(
+            "cupsd refused the job: {}",
+            message
+        )));
+    }
+
+    let id = response
+        .get(ipp::JOB, "job-id")
+        .and_then(|values| match values.first()? {
+            Value::Integer(id) => u32::try_from(*id).ok(),
+            _ => None,
+        });
+
+    Ok(id.unwrap_or_default())
+}
+
+/// Make the Print-Job request for `settings`.
+fn job(settings: &Settings, title: &str) -> Message {
+    let mut request = Message::request(ipp::PRINT_JOB);
+
+    let uri = format!("ipp://localhost/printers/{}", settings.printer);
+
+    request.add(ipp::OPERATION, ipp::string("printer-uri", ipp::URI, &uri));
+    request.add(
+        ipp::OPERATION,
+        ipp::string("requesting-user-name", ipp::NAME, &User::current().login),
+    );
+    request.add(ipp::OPERATION, ipp::string("job-name", ipp::NAME, title));
+    request.add(
+        ipp::OPERATION,
+        ipp::string("document-format", ipp::MIME_TYPE, AUTO_FORMAT),
+    );
+
+    let attribute = |name: &str, values| (String::from(name), values);
+
+    let copies = i32::try_from(settings.copies.max(1)).unwrap_or(1);
+
+    request.add(ipp::JOB, attribute("copies", vec![Value::Integer(copies)]));
+
+    if let Some(media) = &settings.media {
+        request.add(ipp::JOB, ipp::string("media", ipp::KEYWORD, media));
+    }
+
+    // 3 is portrait and 4 landscape.
+    let orientation = if settings.landscape { 4 } else { 3 };
+
+    request.add(
+        ipp::JOB,
+        attribute("orientation-requested", vec![Value::Enum(orientation)]),
+    );
+
+    let ranges: Vec<Value> = settings
+        .ranges
+        .iter()
+        .filter_map(|(first, last)| {
+            Some(Value::Range(
+                i32::try_from(*first).ok()?,
+                i32::try_from(*last).ok()?,
+            ))
+        })
+        .collect();
+
+    if !ranges.is_empty() {
+        request.add(ipp::JOB, attribute("page-ranges", ranges));
+    }
+
+    request
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        format_ranges, ipp, job, media_name, media_size, parse_ranges, printer, Prepared, Settings,
+        Value, PREPARED_LIFETIME,
+    };
+
+    #[test]
+    fn tokens() {
+        let prepared = Prepared::default();
+
+        let token = prepared.insert("org.example.App", Settings::new(String::from("office")));
+
+        assert_ne!(token, 0);
+        assert_eq!(prepared.take("org.example.Other", token), None);
+        assert_eq!(
+            prepared.take("org.example.App", token).unwrap().printer,
+            "office"
+        );
+
+        // Tokens are used once.
+        assert_eq!(prepared.take("org.example.App", token), None);
+
+        // Tokens that are never used expire.
+        let token = prepared.insert("org.example.App", Settings::new(String::from("office")));
+
+        let later = std::time::Instant::now() + PREPARED_LIFETIME;
+
+        assert!(prepared.expire(later).is_empty());
+        assert_eq!(prepared.take("org.example.App", token), None);
+    }
+
+    #[test]
+    fn printers() {
+        let string = |name: &str, values: &[&str]| {
+            let values = values
+                .iter()
+                .map(|value| Value::String(ipp::KEYWORD, String::from(*value)))
+                .collect();
+
+            (String::from(name), values)
+        };
+
+        let office = printer(&[
+            string("printer-name", &["office"]),
+            string("printer-info", &[""]),
+            string(
+                "media-supported",
+                &["iso_a4_210x297mm", "na_letter_8.5x11in"],
+            ),
+        ])
+        .unwrap();
+
+        assert_eq!(office.description, "office");
+        assert_eq!(office.media.len(), 2);
+        assert_eq!(office.default_media, None);
+
+        assert_eq!(printer(&[string("printer-info", &["Office"])]), None);
+    }
+
+    #[test]
+    fn media() {
+        assert_eq!(media_size("iso_a4_210x297mm"), Some((210.0, 297.0)));
+
+        let (width, height) = media_size("na_letter_8.5x11in").unwrap();
+
+        assert!((width - 215.9).abs() < 0.01 && (height - 279.4).abs() < 0.01);
+        assert_eq!(media_size("letter"), None);
+
+        assert_eq!(media_name("iso_a4_210x297mm"), "A4");
+        assert_eq!(media_name("na_index-4x6_4x6in"), "Index-4x6");
+        assert_eq!(media_name("custom"), "custom");
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(parse_ranges("1-3, 5"), Some(vec![(1, 3), (5, 5)]));
+        assert_eq!(parse_ranges(" "), Some(vec![]));
+        assert_eq!(parse_ranges("3-1"), None);
+        assert_eq!(parse_ranges("0"), None);
+        assert_eq!(parse_ranges("a"), None);
+
+        assert_eq!(format_ranges(&[(1, 3), (5, 5)]), "1-3, 5");
+    }
+
+    #[test]
+    fn jobs() {
+        let settings = Settings {
+            copies: 2,
+            media: Some(String::from("iso_a4_210x297mm")),
+            landscape: true,
+            ranges: vec![(1, 2), (4, 4)],
+            ..Settings::new(String::from("office"))
+        };
+
+        let request = job(&settings, "Report");
+
+        let value = |group, name| request.get(group, name).unwrap().to_vec();
+
+        assert_eq!(
+            value(ipp::OPERATION, "printer-uri")[0].as_str(),
+            Some("ipp://localhost/printers/office")
+        );
+        assert_eq!(value(ipp::JOB, "copies"), [Value::Integer(2)]);
+        assert_eq!(value(ipp::JOB, "orientation-requested"), [Value::Enum(4)]);
+        assert_eq!(
+            value(ipp::JOB, "page-ranges"),
+            [Value::Range(1, 2), Value::Range(4, 4)]
+        );
+
+        let request = job(&Settings::new(String::from("office")), "Report");
+
+        assert!(request.get(ipp::JOB, "media").is_none());
+        assert!(request.get(ipp::JOB, "page-ranges").is_none());
+    }
+}
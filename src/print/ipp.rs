@@ -0,0 +1,505 @@
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    os::unix::net::UnixStream,
+    path::Path,
+    time::Duration,
+};
+
+/// The version of IPP spoken, 2.0, which CUPS has understood since 1.4.
+const VERSION: [u8; 2] = [2, 0];
+
+/// The sockets cupsd listens on for local clients, in order.
+const SOCKETS: [&str; 2] = ["/run/cups/cups.sock", "/var/run/cups/cups.sock"];
+
+/// The address of cupsd if there's no socket.
+const ADDRESS: &str = "localhost:631";
+
+/// The longest value of `NAME` attributes, in octets, as RFC 8011 allows for
+/// nameWithoutLanguage.
+const NAME_LENGTH: usize = 255;
+
+/// How long cupsd has to answer, which includes taking documents.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+pub const PRINT_JOB: u16 = 0x0002;
+pub const CUPS_GET_DEFAULT: u16 = 0x4001;
+pub const CUPS_GET_PRINTERS: u16 = 0x4002;
+
+/// The tags of attribute groups.
+pub const OPERATION: u8 = 0x01;
+pub const JOB: u8 = 0x02;
+const END: u8 = 0x03;
+pub const PRINTER: u8 = 0x04;
+
+/// The tags of string values.
+pub const TEXT: u8 = 0x41;
+pub const NAME: u8 = 0x42;
+pub const KEYWORD: u8 = 0x44;
+pub const URI: u8 = 0x45;
+pub const CHARSET: u8 = 0x47;
+pub const LANGUAGE: u8 = 0x48;
+pub const MIME_TYPE: u8 = 0x49;
+
+const INTEGER: u8 = 0x21;
+const BOOLEAN: u8 = 0x22;
+const ENUM: u8 = 0x23;
+const RANGE: u8 = 0x33;
+
+/// `Value` is a value of an IPP attribute.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Integer(i32),
+    Boolean(bool),
+    Enum(i32),
+    Range(i32, i32),
+
+    /// A string, with the tag of its type, like `KEYWORD` or `NAME`.
+    String(u8, String),
+
+    /// A value of another type, as it's encoded.
+    Other(u8, Vec<u8>),
+}
+
+impl Value {
+    /// Get the string of the value, if it's one.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(_, value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn tag(&self) -> u8 {
+        match self {
+            Self::Integer(_) => INTEGER,
+            Self::Boolean(_) => BOOLEAN,
+            Self::Enum(_) => ENUM,
+            Self::Range(..) => RANGE,
+            Self::String(tag, _) | Self::Other(tag, _) => *tag,
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        match self {
+            Self::Integer(value) | Self::Enum(value) => value.to_be_bytes().to_vec(),
+            Self::Boolean(value) => vec![u8::from(*value)],
+            Self::Range(low, high) => [low.to_be_bytes(), high.to_be_bytes()].concat(),
+            // Longer names, like titles of documents, are cut on a character boundary.
+            Self::String(NAME, value) if value.len() > NAME_LENGTH => {
+                let end = (0..=NAME_LENGTH)
+                    .rev()
+                    .find(|end| value.is_char_boundary(*end))
+                    .unwrap_or_default();
+
+                value.as_bytes()[..end].to_vec()
+            }
+
+            Self::String(_, value) => value.as_bytes().to_vec(),
+            Self::Other(_, value) => value.clone(),
+        }
+    }
+
+    fn decode(tag: u8, bytes: &[u8]) -> Self {
+        let integer = |bytes: &[u8]| bytes.try_into().ok().map(i32::from_be_bytes);
+
+        match tag {
+            INTEGER | ENUM => match integer(bytes) {
+                Some(value) if tag == INTEGER => Self::Integer(value),
+                Some(value) => Self::Enum(value),
+                None => Self::Other(tag, bytes.to_vec()),
+            },
+
+            BOOLEAN => Self::Boolean(bytes.first().is_some_and(|value| *value != 0)),
+
+            RANGE if bytes.len() == 8 => match (integer(&bytes[..4]), integer(&bytes[4..])) {
+                (Some(low), Some(high)) => Self::Range(low, high),
+                _ => Self::Other(tag, bytes.to_vec()),
+            },
+
+            TEXT | NAME | KEYWORD | URI | CHARSET | LANGUAGE | MIME_TYPE => {
+                Self::String(tag, String::from_utf8_lossy(bytes).into_owned())
+            }
+
+            tag => Self::Other(tag, bytes.to_vec()),
+        }
+    }
+}
+
+/// `Attribute` is a named attribute with one or more values.
+pub type Attribute = (String, Vec<Value>);
+
+/// `Message` is an IPP request or response.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Message {
+    /// The operation of requests, or the status of responses.
+    pub code: u16,
+
+    pub request_id: u32,
+
+    /// The attribute groups, with their tags.
+    pub groups: Vec<(u8, Vec<Attribute>)>,
+}
+
+impl Message {
+    /// Start a request for `operation`, with the operation attributes every request needs.
+    pub fn request(operation: u16) -> Self {
+        Self {
+            code: operation,
+            request_id: 1,
+            groups: vec![(
+                OPERATION,
+                vec![
+                    string("attributes-charset", CHARSET, "utf-8"),
+                    string("attributes-natural-language", LANGUAGE, "en"),
+                ],
+            )],
+        }
+    }
+
+    /// Add `attribute` to the last group with `tag`, or a new one.
+    pub fn add(&mut self, tag: u8, attribute: Attribute) {
+        match self
+            .groups
+            .iter_mut()
+            .rev()
+            .find(|(group, _)| *group == tag)
+        {
+            Some((_, attributes)) => attributes.push(attribute),
+            None => self.groups.push((tag, vec![attribute])),
+        }
+    }
+
+    /// List the groups with `tag`, like the printers of a CUPS-Get-Printers response.
+    pub fn groups(&self, tag: u8) -> impl Iterator<Item = &[Attribute]> {
+        self.groups
+            .iter()
+            .filter(move |(group, _)| *group == tag)
+            .map(|(_, attributes)| attributes.as_slice())
+    }
+
+    /// Get the values of the attribute `name` in the first group with `tag`.
+    pub fn get(&self, tag: u8, name: &str) -> Option<&[Value]> {
+        let (_, attributes) = self.groups.iter().find(|(group, _)| *group == tag)?;
+
+        attributes
+            .iter()
+            .find(|(attribute, _)| attribute == name)
+            .map(|(_, values)| values.as_slice())
+    }
+
+    /// Check whether the status of a response is one of success.
+    pub fn succeeded(&self) -> bool {
+        self.code < 0x0100
+    }
+
+    /// Encode the message, failing if a name or value is too long for its length prefix.
+    pub fn encode(&self) -> std::io::Result<Vec<u8>> {
+        let sized = |bytes: &[u8]| {
+            u16::try_from(bytes.len())
+                .map(u16::to_be_bytes)
+                .map_err(|_| invalid("an attribute is too long to send"))
+        };
+
+        let mut bytes = Vec::new();
+
+        bytes.extend(VERSION);
+        bytes.extend(self.code.to_be_bytes());
+        bytes.extend(self.request_id.to_be_bytes());
+
+        for (tag, attributes) in &self.groups {
+            bytes.push(*tag);
+
+            for (name, values) in attributes {
+                // Values after the first have an empty name, which adds them to the attribute.
+                for (i, value) in values.iter().enumerate() {
+                    let name = if i == 0 { name.as_bytes() } else { &[] };
+                    let value = value.encode();
+
+                    bytes.push(values[i].tag());
+                    bytes.extend(sized(name)?);
+                    bytes.extend(name);
+                    bytes.extend(sized(&value)?);
+                    bytes.extend(value);
+                }
+            }
+        }
+
+        bytes.push(END);
+
+        Ok(bytes)
+    }
+
+    /// Decode a message, or return `None` if it's cut short.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let mut reader = Reader(bytes);
+
+        reader.take(2)?;
+
+        let mut message = Self {
+            code: u16::from_be_bytes(reader.take(2)?.try_into().ok()?),
+            request_id: u32::from_be_bytes(reader.take(4)?.try_into().ok()?),
+            groups: Vec::new(),
+        };
+
+        loop {
+            let tag = reader.take(1)?[0];
+
+            match tag {
+                END => return Some(message),
+
+                // Tags below 0x10 start groups, the others are of values.
+                tag if tag < 0x10 => message.groups.push((tag, Vec::new())),
+
+                tag => {
+                    let name = reader.sized()?;
+                    let value = Value::decode(tag, reader.sized()?);
+
+                    let (_, attributes) = message.groups.last_mut()?;
+
+                    match (name.is_empty(), attributes.last_mut()) {
+                        (true, Some((_, values))) => values.push(value),
+                        _ => attributes
+                            .push((String::from_utf8_lossy(name).into_owned(), vec![value])),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Make an attribute with a single string value.
+pub fn string(name: &str, tag: u8, value: &str) -> Attribute {
+    (
+        String::from(name),
+        vec![Value::String(tag, String::from(value))],
+    )
+}
+
+/// Send `request` to cupsd at `path`, like `/` or `/printers/office`, followed by
+/// `document`, and return its response.
+///
+/// cupsd is reached at the server `CUPS_SERVER` names, or else its local socket.
+pub fn send(path: &str, request: &Message, document: &[u8]) -> std::io::Result<Message> {
+    let mut body = request.encode()?;
+
+    body.extend_from_slice(document);
+
+    let server = std::env::var("CUPS_SERVER")
+        .ok()
+        .filter(|server| !server.is_empty());
+
+    let socket = match &server {
+        Some(server) if server.starts_with('/') => Some(server.as_str()),
+        Some(_) => None,
+        None => SOCKETS
+            .into_iter()
+            .find(|socket| Path::new(socket).exists()),
+    };
+
+    let response = match socket {
+        Some(socket) => {
+            let stream = UnixStream::connect(socket)?;
+
+            stream.set_read_timeout(Some(TIMEOUT))?;
+            stream.set_write_timeout(Some(TIMEOUT))?;
+
+            exchange(stream, path, &body)?
+        }
+
+        None => {
+            let address = server.as_deref().unwrap_or(ADDRESS);
+
+            let address = match address.contains(':') {
+                true => address.to_owned(),
+                false => format!("{}:631", address),
+            };
+
+            let stream = TcpStream::connect(address)?;
+
+            stream.set_read_timeout(Some(TIMEOUT))?;
+            stream.set_write_timeout(Some(TIMEOUT))?;
+
+            exchange(stream, path, &body)?
+        }
+    };
+
+    Message::decode(&response).ok_or_else(|| invalid("the response of cupsd is cut short"))
+}
+
+/// POST `body` to `path` over `stream` and return the body of the response.
+fn exchange(mut stream: impl Read + Write, path: &str, body: &[u8]) -> std::io::Result<Vec<u8>> {
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\n\
+         Host: localhost\r\n\
+         Content-Type: application/ipp\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n",
+        path,
+        body.len()
+    )?;
+
+    stream.write_all(body)?;
+    stream.flush()?;
+
+    let mut response = Vec::new();
+
+    stream.read_to_end(&mut response)?;
+
+    let end = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| invalid("the response of cupsd has no body"))?;
+
+    let head = String::from_utf8_lossy(&response[..end]).to_lowercase();
+    let body = &response[end + 4..];
+
+    let status = head.split_whitespace().nth(1).unwrap_or_default();
+
+    match status {
+        "200" => {}
+        "401" => return Err(invalid("cupsd asks for authentication")),
+        status => return Err(invalid(&format!("cupsd answered with HTTP {}", status))),
+    }
+
+    match head.contains("transfer-encoding: chunked") {
+        true => dechunk(body).ok_or_else(|| invalid("the response of cupsd is cut short")),
+        false => Ok(body.to_vec()),
+    }
+}
+
+/// Join the chunks of a body sent with chunked transfer encoding.
+fn dechunk(mut body: &[u8]) -> Option<Vec<u8>> {
+    let mut joined = Vec::new();
+
+    loop {
+        let line = body.windows(2).position(|window| window == b"\r\n")?;
+
+        // Chunk extensions follow the size after a `;`.
+        let size = std::str::from_utf8(&body[..line]).ok()?;
+        let size = usize::from_str_radix(size.split(';').next()?.trim(), 16).ok()?;
+
+        if size == 0 {
+            return Some(joined);
+        }
+
+        let chunk = body.get(line + 2..line + 2 + size)?;
+
+        joined.extend_from_slice(chunk);
+
+        body = body.get(line + 2 + size + 2..)?;
+    }
+}
+
+fn invalid(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// Reads the fields of encoded messages.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Option<&'a [u8]> {
+        let (taken, rest) = (self.0.get(..length)?, self.0.get(length..)?);
+
+        self.0 = rest;
+
+        Some(taken)
+    }
+
+    /// Take a field preceded by its length.
+    fn sized(&mut self) -> Option<&'a [u8]> {
+        let length = u16::from_be_bytes(self.take(2)?.try_into().ok()?);
+
+        self.take(usize::from(length))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        dechunk, string, Message, Value, JOB, KEYWORD, NAME, NAME_LENGTH, OPERATION, PRINT_JOB,
+        TEXT,
+    };
+
+    #[test]
+    fn messages() {
+        let mut request = Message::request(PRINT_JOB);
+
+        request.add(OPERATION, string("job-name", NAME, "Report"));
+        request.add(JOB, (String::from("copies"), vec![Value::Integer(2)]));
+        request.add(
+            JOB,
+            (
+                String::from("page-ranges"),
+                vec![Value::Range(1, 3), Value::Range(5, 5)],
+            ),
+        );
+
+        let decoded = Message::decode(&request.encode().unwrap()).unwrap();
+
+        assert_eq!(decoded, request);
+        assert_eq!(
+            decoded.get(OPERATION, "job-name").unwrap()[0].as_str(),
+            Some("Report")
+        );
+        assert_eq!(decoded.get(JOB, "page-ranges").unwrap().len(), 2);
+
+        // Responses cut short aren't decoded.
+        let encoded = request.encode().unwrap();
+
+        assert_eq!(Message::decode(&encoded[..encoded.len() - 1]), None);
+
+        // Values of unknown types are kept as they are.
+        let mut response = Message::request(0);
+
+        response.add(
+            JOB,
+            (String::from("x"), vec![Value::Other(0x30, vec![1, 2])]),
+        );
+        response.add(JOB, string("job-state-reasons", KEYWORD, "none"));
+
+        assert_eq!(
+            Message::decode(&response.encode().unwrap()).unwrap(),
+            response
+        );
+    }
+
+    #[test]
+    fn oversized() {
+        // Titles of any length only make the job name, cut short on a character boundary.
+        let title = "é".repeat(40_000);
+
+        let mut request = Message::request(PRINT_JOB);
+
+        request.add(OPERATION, string("job-name", NAME, &title));
+
+        let decoded = Message::decode(&request.encode().unwrap()).unwrap();
+
+        let name = decoded.get(OPERATION, "job-name").unwrap()[0]
+            .as_str()
+            .unwrap()
+            .to_owned();
+
+        assert_eq!(name.len(), NAME_LENGTH - 1);
+        assert!(title.starts_with(&name));
+        assert_eq!(decoded.groups[0].1.len(), 3);
+
+        // Other values too long for their length fail to encode.
+        let mut request = Message::request(PRINT_JOB);
+
+        request.add(OPERATION, string("job-name", TEXT, &title));
+
+        assert!(request.encode().is_err());
+    }
+
+    #[test]
+    fn chunks() {
+        assert_eq!(
+            dechunk(b"4\r\nabcd\r\n2;x=y\r\nef\r\n0\r\n\r\n").unwrap(),
+            b"abcdef"
+        );
+        assert_eq!(dechunk(b"4\r\nab"), None);
+    }
+}
@@ -0,0 +1,423 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use xml::{
+    escape::{escape_str_attribute, escape_str_pcdata},
+    reader::{EventReader, XmlEvent},
+};
+
+use crate::uri;
+
+/// Serializes read-modify-write cycles of the recent files list between concurrent requests.
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// The number of recent files listed by the built-in file chooser.
+#[cfg_attr(not(feature = "egui"), allow(dead_code))]
+const MAX_FILES: usize = 100;
+
+/// `Bookmark` is an entry of `recently-used.xbel`, as written by GLib's `GBookmarkFile`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Bookmark {
+    href: String,
+    added: String,
+    modified: String,
+    visited: String,
+    title: Option<String>,
+    description: Option<String>,
+    mime_type: Option<String>,
+    groups: Vec<String>,
+    applications: Vec<Application>,
+
+    /// Private bookmarks are only meant for the applications that registered them.
+    private: bool,
+}
+
+/// `Application` is an application that registered a bookmark.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Application {
+    name: String,
+    exec: String,
+    modified: String,
+    count: u32,
+}
+
+/// Record that the given application chose the paths, like GTK does for its file choosers.
+pub fn add(app_id: &str, paths: &[PathBuf]) {
+    let Some(path) = path() else {
+        return;
+    };
+
+    let _guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut bookmarks = match std::fs::read_to_string(&path) {
+        Ok(contents) => match parse(&contents) {
+            Ok(bookmarks) => bookmarks,
+
+            // Don't clobber a file GTK would still read some of.
+            Err(e) => {
+                log::warn!("not updating invalid recent files {:?}: {}", path, e);
+                return;
+            }
+        },
+
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+
+        Err(e) => {
+            log::warn!("failed to read recent files {:?}: {}", path, e);
+            return;
+        }
+    };
+
+    let now = humantime::format_rfc3339_seconds(SystemTime::now()).to_string();
+
+    for chosen in paths {
+        touch(&mut bookmarks, app_id, chosen, &now);
+    }
+
+    if let Err(e) = save(&path, &bookmarks) {
+        log::warn!("failed to save recent files {:?}: {}", path, e);
+    }
+}
+
+/// List the existing recent local files, most recently used first.
+#[cfg_attr(not(feature = "egui"), allow(dead_code))]
+pub fn files() -> Vec<PathBuf> {
+    let Some(path) = path() else {
+        return Vec::new();
+    };
+
+    let bookmarks = match std::fs::read_to_string(&path) {
+        Ok(contents) => parse(&contents).unwrap_or_else(|e| {
+            log::warn!("ignoring invalid recent files {:?}: {}", path, e);
+            Vec::new()
+        }),
+
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+
+        Err(e) => {
+            log::warn!("failed to read recent files {:?}: {}", path, e);
+            Vec::new()
+        }
+    };
+
+    let mut recent: Vec<(SystemTime, PathBuf)> = bookmarks
+        .into_iter()
+        .filter(|bookmark| !bookmark.private)
+        .filter_map(|bookmark| {
+            let path = uri::file_path(&bookmark.href)?;
+
+            let used = [&bookmark.modified, &bookmark.visited, &bookmark.added]
+                .into_iter()
+                .filter_map(|stamp| humantime::parse_rfc3339_weak(stamp).ok())
+                .max()
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+
+            Some((used, path))
+        })
+        .filter(|(_, path)| path.exists())
+        .collect();
+
+    recent.sort_by_key(|&(used, _)| std::cmp::Reverse(used));
+
+    recent
+        .into_iter()
+        .take(MAX_FILES)
+        .map(|(_, path)| path)
+        .collect()
+}
+
+/// Add the path to the bookmarks, or bump it if it's already there.
+fn touch(bookmarks: &mut Vec<Bookmark>, app_id: &str, path: &Path, now: &str) {
+    let href = uri::file_uri(path);
+
+    let index = match bookmarks.iter().position(|bookmark| bookmark.href == href) {
+        Some(index) => index,
+
+        None => {
+            bookmarks.push(Bookmark {
+                href,
+                added: now.to_owned(),
+                mime_type: Some(mime_type(path)),
+                ..Bookmark::default()
+            });
+
+            bookmarks.len() - 1
+        }
+    };
+
+    let bookmark = &mut bookmarks[index];
+
+    bookmark.modified = now.to_owned();
+    bookmark.visited = now.to_owned();
+
+    // Apps without an id are still recorded, under the portal's own name.
+    let name = match app_id.is_empty() {
+        true => "xdg-desktop-portal-rs",
+        false => app_id,
+    };
+
+    match bookmark
+        .applications
+        .iter_mut()
+        .find(|application| application.name == name)
+    {
+        Some(application) => {
+            application.modified = now.to_owned();
+            application.count += 1;
+        }
+
+        None => bookmark.applications.push(Application {
+            name: name.to_owned(),
+            exec: format!("'gtk-launch {} %u'", name),
+            modified: now.to_owned(),
+            count: 1,
+        }),
+    }
+}
+
+/// Guess the MIME type of a file from its name, as its contents may not be written yet.
+fn mime_type(path: &Path) -> String {
+    if path.is_dir() {
+        return String::from("inode/directory");
+    }
+
+    mime_guess::from_path(path)
+        .first_or_octet_stream()
+        .essence_str()
+        .to_owned()
+}
+
+/// Parse the bookmarks of an XBEL file.
+fn parse(contents: &str) -> xml::reader::Result<Vec<Bookmark>> {
+    let mut bookmarks = Vec::new();
+
+    let mut current: Option<Bookmark> = None;
+    let mut element = String::new();
+
+    for event in EventReader::new(contents.as_bytes()) {
+        match event? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => {
+                let attribute = |key: &str| {
+                    attributes
+                        .iter()
+                        .find(|attribute| attribute.name.local_name == key)
+                        .map(|attribute| attribute.value.clone())
+                };
+
+                match (name.local_name.as_str(), &mut current) {
+                    ("bookmark", _) => {
+                        current = Some(Bookmark {
+                            href: attribute("href").unwrap_or_default(),
+                            added: attribute("added").unwrap_or_default(),
+                            modified: attribute("modified").unwrap_or_default(),
+                            visited: attribute("visited").unwrap_or_default(),
+                            ..Bookmark::default()
+                        });
+                    }
+
+                    ("mime-type", Some(bookmark)) => bookmark.mime_type = attribute("type"),
+
+                    ("application", Some(bookmark)) => {
+                        bookmark.applications.push(Application {
+                            name: attribute("name").unwrap_or_default(),
+                            exec: attribute("exec").unwrap_or_default(),
+                            modified: attribute("modified").unwrap_or_default(),
+                            count: attribute("count")
+                                .and_then(|count| count.parse().ok())
+                                .unwrap_or(1),
+                        });
+                    }
+
+                    ("private", Some(bookmark)) => bookmark.private = true,
+
+                    _ => {}
+                }
+
+                element = name.local_name;
+            }
+
+            XmlEvent::Characters(text) => match (element.as_str(), &mut current) {
+                ("title", Some(bookmark)) => bookmark.title = Some(text),
+                ("desc", Some(bookmark)) => bookmark.description = Some(text),
+                ("group", Some(bookmark)) => bookmark.groups.push(text),
+                _ => {}
+            },
+
+            XmlEvent::EndElement { name } => {
+                if name.local_name == "bookmark" {
+                    bookmarks.extend(current.take());
+                }
+
+                element.clear();
+            }
+
+            _ => {}
+        }
+    }
+
+    Ok(bookmarks)
+}
+
+/// Serialize the bookmarks in the layout GLib writes.
+fn serialize(bookmarks: &[Bookmark]) -> String {
+    let mut xbel = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <xbel version=\"1.0\"\n      \
+         xmlns:bookmark=\"http://www.freedesktop.org/standards/desktop-bookmarks\"\n      \
+         xmlns:mime=\"http://www.freedesktop.org/standards/shared-mime-info\"\n>\n",
+    );
+
+    for bookmark in bookmarks {
+        xbel += &format!(
+            "  <bookmark href=\"{}\" added=\"{}\" modified=\"{}\" visited=\"{}\">\n",
+            escape_str_attribute(&bookmark.href),
+            escape_str_attribute(&bookmark.added),
+            escape_str_attribute(&bookmark.modified),
+            escape_str_attribute(&bookmark.visited),
+        );
+
+        if let Some(title) = &bookmark.title {
+            xbel += &format!("    <title>{}</title>\n", escape_str_pcdata(title));
+        }
+
+        if let Some(description) = &bookmark.description {
+            xbel += &format!("    <desc>{}</desc>\n", escape_str_pcdata(description));
+        }
+
+        xbel += "    <info>\n      <metadata owner=\"http://freedesktop.org\">\n";
+
+        if let Some(mime_type) = &bookmark.mime_type {
+            xbel += &format!(
+                "        <mime:mime-type type=\"{}\"/>\n",
+                escape_str_attribute(mime_type)
+            );
+        }
+
+        if !bookmark.groups.is_empty() {
+            xbel += "        <bookmark:groups>\n";
+
+            for group in &bookmark.groups {
+                xbel += &format!(
+                    "          <bookmark:group>{}</bookmark:group>\n",
+                    escape_str_pcdata(group)
+                );
+            }
+
+            xbel += "        </bookmark:groups>\n";
+        }
+
+        xbel += "        <bookmark:applications>\n";
+
+        for application in &bookmark.applications {
+            xbel += &format!(
+                "          <bookmark:application name=\"{}\" exec=\"{}\" modified=\"{}\" count=\"{}\"/>\n",
+                escape_str_attribute(&application.name),
+                escape_str_attribute(&application.exec),
+                escape_str_attribute(&application.modified),
+                application.count,
+            );
+        }
+
+        xbel += "        </bookmark:applications>\n";
+
+        if bookmark.private {
+            xbel += "        <bookmark:private/>\n";
+        }
+
+        xbel += "      </metadata>\n    </info>\n  </bookmark>\n";
+    }
+
+    xbel += "</xbel>";
+
+    xbel
+}
+
+/// Replace the file with the bookmarks, so readers never see it half written.
+fn save(path: &Path, bookmarks: &[Bookmark]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let temporary = path.with_extension("xbel.tmp");
+
+    let mut file = std::fs::File::create(&temporary)?;
+
+    file.write_all(serialize(bookmarks).as_bytes())?;
+    file.sync_all()?;
+
+    std::fs::rename(temporary, path)
+}
+
+/// Get the path of the recent files list shared with GTK.
+fn path() -> Option<PathBuf> {
+    Some(dirs::data_dir()?.join("recently-used.xbel"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{parse, serialize, touch};
+
+    #[test]
+    fn round_trip() {
+        let contents = r#"<?xml version="1.0" encoding="UTF-8"?>
+<xbel version="1.0"
+      xmlns:bookmark="http://www.freedesktop.org/standards/desktop-bookmarks"
+      xmlns:mime="http://www.freedesktop.org/standards/shared-mime-info"
+>
+  <bookmark href="file:///home/user/Notes%20&amp;%20Todo.txt" added="2024-01-02T10:00:00.123456Z" modified="2024-01-02T10:00:00.123456Z" visited="2024-01-02T10:00:00.123456Z">
+    <title>Notes &amp; Todo</title>
+    <info>
+      <metadata owner="http://freedesktop.org">
+        <mime:mime-type type="text/plain"/>
+        <bookmark:groups>
+          <bookmark:group>gedit</bookmark:group>
+        </bookmark:groups>
+        <bookmark:applications>
+          <bookmark:application name="org.gnome.gedit" exec="&apos;gedit %u&apos;" modified="2024-01-02T10:00:00.123456Z" count="3"/>
+        </bookmark:applications>
+      </metadata>
+    </info>
+  </bookmark>
+</xbel>"#;
+
+        let mut bookmarks = parse(contents).unwrap();
+
+        assert_eq!(bookmarks.len(), 1);
+        assert_eq!(bookmarks[0].title.as_deref(), Some("Notes & Todo"));
+        assert_eq!(bookmarks[0].groups, ["gedit"]);
+        assert_eq!(bookmarks[0].applications[0].exec, "'gedit %u'");
+        assert_eq!(bookmarks[0].applications[0].count, 3);
+
+        let now = "2024-02-03T04:05:06Z";
+
+        touch(
+            &mut bookmarks,
+            "org.gnome.gedit",
+            Path::new("/home/user/Notes & Todo.txt"),
+            now,
+        );
+        touch(
+            &mut bookmarks,
+            "org.example.App",
+            Path::new("/tmp/report.pdf"),
+            now,
+        );
+
+        assert_eq!(bookmarks.len(), 2);
+        assert_eq!(bookmarks[0].applications[0].count, 4);
+        assert_eq!(bookmarks[0].visited, now);
+        assert_eq!(bookmarks[1].href, "file:///tmp/report.pdf");
+        assert_eq!(bookmarks[1].mime_type.as_deref(), Some("application/pdf"));
+        assert_eq!(bookmarks[1].applications[0].name, "org.example.App");
+
+        assert_eq!(parse(&serialize(&bookmarks)).unwrap(), bookmarks);
+    }
+}
@@ -0,0 +1,180 @@
+use std::{
+    cell::RefCell,
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use zbus::{dbus_interface, zvariant};
+
+tokio::task_local! {
+    /// The request the current task runs for.
+    static RUNNING: Ended;
+}
+
+thread_local! {
+    /// The request the dialog on the current thread is shown for.
+    static SHOWING: RefCell<Option<Ended>> = const { RefCell::new(None) };
+}
+
+/// Request implements the org.freedesktop.impl.portal.Request interface.
+///
+/// One is exported at the `handle` path of each portal call while its dialog is showing.
+pub struct Request {
+    closed: Arc<tokio::sync::Notify>,
+}
+
+#[dbus_interface(name = "org.freedesktop.impl.portal.Request")]
+impl Request {
+    /// Ends the user interaction this request refers to.
+    async fn close(&self) {
+        log::info!("close()");
+
+        // `notify_one` stores a permit, so a close that races the registration isn't lost.
+        self.closed.notify_one();
+    }
+}
+
+/// `Ended` tells whether a request ended, being closed or timing out, before its dialog was
+/// answered.
+#[derive(Debug, Clone, Default)]
+pub struct Ended(Arc<AtomicBool>);
+
+impl Ended {
+    /// The request the current task runs for, or the dialog on the current thread is shown
+    /// for, or one that never ends outside of requests.
+    pub fn current() -> Self {
+        RUNNING
+            .try_with(Self::clone)
+            .ok()
+            .or_else(|| SHOWING.with(|showing| showing.borrow().clone()))
+            .unwrap_or_default()
+    }
+
+    /// Whether the request ended.
+    pub fn get(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Show the blocking `dialog` on the current thread for the request, so providers can
+    /// dismiss it once the request ended.
+    pub fn show<T>(self, dialog: impl FnOnce() -> T) -> T {
+        let previous = SHOWING.with(|showing| showing.replace(Some(self)));
+
+        let output = dialog();
+
+        SHOWING.with(|showing| *showing.borrow_mut() = previous);
+
+        output
+    }
+}
+
+/// Whether the request of the current task or dialog thread ended, so its answer is discarded
+/// and whatever it would do is left undone.
+pub fn ended() -> bool {
+    Ended::current().get()
+}
+
+/// Export a Request at `handle` while `future` runs, returning `None` if the request was closed
+/// or `timeout` elapsed first.
+///
+/// Dialogs shown for the request are told it ended through [`Ended`], so providers that can
+/// dismiss them do. rfd has no way to dismiss a dialog from another thread, so its dialogs stay
+/// on screen until the user answers them; their answers are then discarded.
+pub async fn run<T>(
+    conn: &zbus::Connection,
+    handle: &zvariant::ObjectPath<'_>,
+    timeout: Option<Duration>,
+    future: impl Future<Output = zbus::fdo::Result<T>>,
+) -> zbus::fdo::Result<Option<T>> {
+    let closed = Arc::new(tokio::sync::Notify::new());
+
+    let request = Request {
+        closed: closed.clone(),
+    };
+
+    conn.object_server().at(handle, request).await?;
+
+    let ended = Ended::default();
+
+    let output = tokio::select! {
+        output = RUNNING.scope(ended.clone(), future) => Some(output),
+        _ = closed.notified() => None,
+        _ = expire(timeout) => {
+            log::warn!("request {} timed out", handle);
+            None
+        }
+    };
+
+    if output.is_none() {
+        ended.0.store(true, Ordering::Relaxed);
+    }
+
+    conn.object_server().remove::<Request, _>(handle).await?;
+
+    output.transpose()
+}
+
+/// Export a Request at `handle` for something lasting past the call that made it, like an
+/// inhibitor, returning a future that resolves once the request is closed.
+pub async fn hold(
+    conn: &zbus::Connection,
+    handle: &zvariant::ObjectPath<'_>,
+) -> zbus::Result<impl Future<Output = ()>> {
+    let closed = Arc::new(tokio::sync::Notify::new());
+
+    let request = Request {
+        closed: closed.clone(),
+    };
+
+    conn.object_server().at(handle, request).await?;
+
+    let (conn, handle) = (
+        conn.clone(),
+        zvariant::OwnedObjectPath::from(handle.to_owned()),
+    );
+
+    Ok(async move {
+        closed.notified().await;
+
+        if let Err(e) = conn.object_server().remove::<Request, _>(&handle).await {
+            log::warn!("failed to remove request {}: {}", handle, e);
+        }
+    })
+}
+
+/// Sleep for `timeout`, or forever if there's none.
+async fn expire(timeout: Option<Duration>) {
+    match timeout {
+        Some(timeout) => tokio::time::sleep(timeout).await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use super::{ended, Ended};
+
+    #[test]
+    fn dialogs_see_their_request() {
+        let request = Ended::default();
+
+        assert!(!ended());
+
+        request.clone().show(|| {
+            assert!(!ended());
+
+            request.0.store(true, Ordering::Relaxed);
+
+            assert!(ended());
+        });
+
+        // Outside of the dialog, the thread is back to no request.
+        assert!(!ended());
+    }
+}
@@ -0,0 +1,70 @@
+use std::path::{Path, PathBuf};
+
+use crate::config::ResolveSymlinks;
+
+/// Resolve symlinks and `..` in a path.
+///
+/// Files that don't exist yet, like new SaveFile targets, are resolved through their parent.
+pub fn canonical(path: &Path) -> PathBuf {
+    if let Ok(path) = path.canonicalize() {
+        return path;
+    }
+
+    match (path.parent().map(Path::canonicalize), path.file_name()) {
+        (Some(Ok(parent)), Some(name)) => parent.join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Resolve the symlinks in chosen paths as configured, before they're handed to the caller.
+pub fn symlinks(mode: ResolveSymlinks, paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    if mode == ResolveSymlinks::Never {
+        return paths;
+    }
+
+    let home = dirs::home_dir().map(|home| canonical(&home));
+
+    paths
+        .into_iter()
+        .map(|path| {
+            let resolved = canonical(&path);
+
+            match mode {
+                ResolveSymlinks::OutsideHome
+                    if home.as_ref().is_some_and(|home| resolved.starts_with(home)) =>
+                {
+                    path
+                }
+
+                _ => resolved,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::symlinks;
+    use crate::config::ResolveSymlinks;
+
+    #[test]
+    fn resolve_modes() {
+        let dir = std::env::temp_dir().join(format!("resolve-test-{}", std::process::id()));
+        let target = dir.join("target");
+        let link = dir.join("link");
+
+        std::fs::create_dir_all(&target).unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        let chosen = vec![link.join("new.txt")];
+        let resolved = target.canonicalize().unwrap().join("new.txt");
+
+        let never = symlinks(ResolveSymlinks::Never, chosen.clone());
+        let always = symlinks(ResolveSymlinks::Always, chosen);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(never, vec![link.join("new.txt")]);
+        assert_eq!(always, vec![resolved]);
+    }
+}
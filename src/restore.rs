@@ -0,0 +1,355 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{capture::Rect, cast::Source};
+
+/// The `persist_mode` of sessions that can't be restored.
+pub const DONT_PERSIST: u32 = 0;
+
+/// The `persist_mode` of sessions that can be restored while the app is running.
+pub const TRANSIENT: u32 = 1;
+
+/// The `persist_mode` of sessions that can be restored until the user revokes it.
+pub const PERSISTENT: u32 = 2;
+
+/// Serializes read-modify-write cycles of the token file between concurrent requests.
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// `Tokens` keeps what the restore tokens handed out to apps let them share again.
+///
+/// Tokens are spent once they're used, and a new one is issued with the restored streams.
+/// Transient tokens live in memory; persistent ones are kept in
+/// `$XDG_STATE_HOME/xdg-desktop-portal-rs/restore.toml`, which only the user can read
+/// since a token is all it takes to share the screen.
+pub struct Tokens {
+    transient: Mutex<HashMap<String, Grant>>,
+}
+
+/// `Grant` is what a token lets an app share without asking.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Grant {
+    pub app_id: String,
+    pub sources: Vec<Saved>,
+}
+
+/// `Saved` describes a picked source well enough to find it again after a restart.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Saved {
+    /// A monitor, by connector name.
+    Monitor { name: String },
+
+    /// A window, by app id and title.
+    Window { app_id: String, title: String },
+
+    /// A new virtual display.
+    Virtual,
+
+    /// A part of the screen, in pixels of pictures of it.
+    Region {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    },
+}
+
+/// `Persisted` is the contents of the token file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Persisted {
+    tokens: HashMap<String, Grant>,
+}
+
+impl Saved {
+    /// Describe a source to save.
+    pub fn of(source: &Source) -> Self {
+        match source {
+            Source::Monitor(output) => Saved::Monitor {
+                name: output.name.clone(),
+            },
+
+            Source::Window(toplevel) => Saved::Window {
+                app_id: toplevel.app_id.clone(),
+                title: toplevel.title.clone(),
+            },
+
+            Source::Virtual => Saved::Virtual,
+
+            Source::Region(area) => Saved::Region {
+                x: area.x,
+                y: area.y,
+                width: area.width,
+                height: area.height,
+            },
+        }
+    }
+
+    /// Find the saved source among the current `sources`.
+    ///
+    /// Titles change as apps are used, so a window is found by its title if several windows
+    /// of its app are open, and otherwise by its app id alone.
+    pub fn find(&self, sources: &[Source]) -> Option<Source> {
+        let found = match self {
+            Saved::Monitor { name } => sources
+                .iter()
+                .find(|source| matches!(source, Source::Monitor(output) if output.name == *name)),
+
+            Saved::Window { app_id, title } => {
+                let windows: Vec<&Source> = sources
+                    .iter()
+                    .filter(|source| {
+                        matches!(source, Source::Window(toplevel) if toplevel.app_id == *app_id)
+                    })
+                    .collect();
+
+                match windows.as_slice() {
+                    [window] => Some(*window),
+
+                    _ => windows.into_iter().find(
+                        |source| matches!(source, Source::Window(toplevel) if toplevel.title == *title),
+                    ),
+                }
+            }
+
+            Saved::Virtual => sources
+                .iter()
+                .find(|source| matches!(source, Source::Virtual)),
+
+            // Parts of the screen can be shared as long as a part can be picked.
+            Saved::Region {
+                x,
+                y,
+                width,
+                height,
+            } => {
+                let offered = sources
+                    .iter()
+                    .any(|source| matches!(source, Source::Region(_)));
+
+                return offered.then_some(Source::Region(Rect {
+                    x: *x,
+                    y: *y,
+                    width: *width,
+                    height: *height,
+                }));
+            }
+        };
+
+        found.cloned()
+    }
+}
+
+impl Tokens {
+    /// Start without transient tokens.
+    pub fn new() -> Self {
+        Self {
+            transient: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Spend the token of `app_id`, returning its grant and persist mode.
+    ///
+    /// Tokens of other apps are left alone, so an app can't spend what it doesn't own.
+    pub fn take(&self, token: &str, app_id: &str) -> Option<(Grant, u32)> {
+        if let Ok(mut transient) = self.transient.lock() {
+            if transient
+                .get(token)
+                .is_some_and(|grant| grant.app_id == app_id)
+            {
+                return transient.remove(token).map(|grant| (grant, TRANSIENT));
+            }
+        }
+
+        let mut taken = None;
+
+        let spent = update(|persisted| {
+            if persisted
+                .tokens
+                .get(token)
+                .is_some_and(|grant| grant.app_id == app_id)
+            {
+                taken = persisted.tokens.remove(token);
+            }
+
+            taken.is_some()
+        });
+
+        // A token that can't be spent would restore the session forever.
+        if let Err(e) = spent {
+            log::warn!("failed to spend a restore token: {}", e);
+            return None;
+        }
+
+        taken.map(|grant| (grant, PERSISTENT))
+    }
+
+    /// Issue a token for `grant`, kept as `persist_mode` says.
+    pub fn issue(&self, grant: Grant, persist_mode: u32) -> std::io::Result<String> {
+        let token = new_token()?;
+
+        match persist_mode {
+            PERSISTENT => update(|persisted| {
+                persisted.tokens.insert(token.clone(), grant);
+                true
+            })?,
+
+            _ => {
+                if let Ok(mut transient) = self.transient.lock() {
+                    transient.insert(token.clone(), grant);
+                }
+            }
+        }
+
+        Ok(token)
+    }
+
+    /// Forget every persistent token of `app_id`, once the user revoked them.
+    pub fn revoke(&self, app_id: &str) {
+        let revoked = update(|persisted| {
+            let count = persisted.tokens.len();
+
+            persisted.tokens.retain(|_, grant| grant.app_id != app_id);
+
+            persisted.tokens.len() != count
+        });
+
+        if let Err(e) = revoked {
+            log::warn!("failed to forget the restore tokens of {}: {}", app_id, e);
+        }
+    }
+}
+
+/// Create a random token, as 128 bits in hex.
+fn new_token() -> std::io::Result<String> {
+    let mut bytes = [0; 16];
+
+    std::fs::File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Load the token file and apply `f` to it, saving it again if `f` says it changed.
+fn update(f: impl FnOnce(&mut Persisted) -> bool) -> std::io::Result<()> {
+    let _guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    let path = path().ok_or(std::io::ErrorKind::NotFound)?;
+
+    let mut persisted = match std::fs::read_to_string(&path) {
+        Ok(contents) => toml::from_str(&contents).unwrap_or_else(|e| {
+            log::warn!("ignoring invalid token file {:?}: {}", path, e);
+            Persisted::default()
+        }),
+
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Persisted::default(),
+
+        Err(e) => return Err(e),
+    };
+
+    match f(&mut persisted) {
+        true => save(&path, &persisted),
+        false => Ok(()),
+    }
+}
+
+/// Write the token file, readable only by the user.
+fn save(path: &std::path::Path, persisted: &Persisted) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let contents = toml::to_string(persisted)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+    // Write a new file and move it over the old one, so the mode applies to it.
+    let temporary = path.with_extension("toml.new");
+
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&temporary)?;
+
+    file.write_all(contents.as_bytes())?;
+
+    std::fs::rename(temporary, path)
+}
+
+/// Get the path of the token file.
+fn path() -> Option<PathBuf> {
+    Some(
+        dirs::state_dir()?
+            .join("xdg-desktop-portal-rs")
+            .join("restore.toml"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Grant, Saved, Tokens, TRANSIENT};
+    use crate::{
+        capture::{Output, Toplevel},
+        cast::Source,
+    };
+
+    #[test]
+    fn saved_sources() {
+        let window = |title: &str| {
+            Source::Window(Toplevel {
+                title: title.to_owned(),
+                app_id: String::from("org.example.Editor"),
+                ..Toplevel::default()
+            })
+        };
+
+        let monitor = Source::Monitor(Output {
+            name: String::from("DP-1"),
+            ..Output::default()
+        });
+
+        let saved = Saved::of(&window("notes.txt"));
+
+        // The only window of the app is found even after its title changed.
+        assert_eq!(
+            saved.find(&[monitor.clone(), window("todo.txt")]),
+            Some(window("todo.txt"))
+        );
+        assert_eq!(
+            saved.find(&[window("todo.txt"), window("notes.txt")]),
+            Some(window("notes.txt"))
+        );
+        assert_eq!(Saved::of(&monitor).find(&[window("notes.txt")]), None);
+    }
+
+    #[test]
+    fn transient_tokens() {
+        let tokens = Tokens::new();
+
+        let grant = Grant {
+            app_id: String::from("org.example.App"),
+            sources: vec![Saved::Monitor {
+                name: String::from("DP-1"),
+            }],
+        };
+
+        let token = tokens.issue(grant.clone(), TRANSIENT).unwrap();
+
+        assert_eq!(token.len(), 32);
+
+        // Only the app a token was issued to can spend it, and only once.
+        assert_eq!(tokens.take(&token, "org.example.Other"), None);
+        assert_eq!(
+            tokens.take(&token, "org.example.App"),
+            Some((grant, TRANSIENT))
+        );
+        assert_eq!(tokens.take(&token, "org.example.App"), None);
+    }
+}
@@ -0,0 +1,168 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use crate::config::Concurrency;
+
+tokio::task_local! {
+    /// The turn of the ticket the current task runs for.
+    static TURN: Turn;
+}
+
+/// `Scheduler` decides when each application's dialogs may be shown.
+///
+/// Each app id has its own lock, so one app opening a dialog never holds up another.
+pub struct Scheduler {
+    concurrency: Concurrency,
+    apps: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+/// `Ticket` is a place in an application's queue.
+pub enum Ticket {
+    /// The dialog can be shown right away, holding the lock if there is one.
+    Ready(Option<tokio::sync::OwnedMutexGuard<()>>),
+
+    /// The dialog has to wait for the app's earlier dialogs to be answered.
+    Waiting(Arc<tokio::sync::Mutex<()>>),
+}
+
+/// `Turn` is an app's hold on its lock, shared by the dialogs shown in it.
+///
+/// Dialogs run on blocking threads outlive a request that ended before they were answered,
+/// so they keep the turn until they close, and the app's next dialog doesn't open over them.
+#[derive(Clone, Default)]
+pub struct Turn {
+    _guard: Option<Arc<tokio::sync::OwnedMutexGuard<()>>>,
+}
+
+impl Turn {
+    /// The turn of the ticket the current task runs for, if there's one.
+    pub fn current() -> Self {
+        TURN.try_with(Self::clone).unwrap_or_default()
+    }
+}
+
+impl Scheduler {
+    pub fn new(concurrency: Concurrency) -> Self {
+        Self {
+            concurrency,
+            apps: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Get a ticket for a dialog of `app_id`, or `None` if it's rejected because one is open.
+    pub fn ticket(&self, app_id: &str) -> Option<Ticket> {
+        if self.concurrency == Concurrency::AllowParallel {
+            return Some(Ticket::Ready(None));
+        }
+
+        let lock = {
+            let mut apps = self.apps.lock().unwrap_or_else(|e| e.into_inner());
+
+            // Forget the locks nobody holds or waits for anymore.
+            apps.retain(|_, lock| Arc::strong_count(lock) > 1);
+
+            apps.entry(app_id.to_owned()).or_default().clone()
+        };
+
+        match self.concurrency {
+            Concurrency::RejectBusy => lock
+                .try_lock_owned()
+                .ok()
+                .map(|guard| Ticket::Ready(Some(guard))),
+
+            _ => Some(Ticket::Waiting(lock)),
+        }
+    }
+}
+
+impl Ticket {
+    /// Wait for this ticket's turn, then run `future` while holding it, along with the dialogs
+    /// it shows that take the [`Turn`].
+    pub async fn run<F: Future>(self, future: F) -> F::Output {
+        let guard = match self {
+            Ticket::Ready(guard) => guard,
+            Ticket::Waiting(lock) => Some(lock.lock_owned().await),
+        };
+
+        let turn = Turn {
+            _guard: guard.map(Arc::new),
+        };
+
+        TURN.scope(turn, future).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Scheduler, Turn};
+    use crate::config::Concurrency;
+
+    #[tokio::test]
+    async fn reject_busy() {
+        let scheduler = Scheduler::new(Concurrency::RejectBusy);
+
+        let first = scheduler.ticket("org.example.App").unwrap();
+
+        assert!(scheduler.ticket("org.example.App").is_none());
+        assert!(scheduler.ticket("org.example.Other").is_some());
+
+        first.run(async {}).await;
+
+        assert!(scheduler.ticket("org.example.App").is_some());
+    }
+
+    #[tokio::test]
+    async fn held_by_dialogs() {
+        let scheduler = Scheduler::new(Concurrency::RejectBusy);
+
+        let ticket = scheduler.ticket("org.example.App").unwrap();
+
+        // A dialog still showing once its request ended keeps the turn.
+        let turn = ticket.run(async { Turn::current() }).await;
+
+        assert!(scheduler.ticket("org.example.App").is_none());
+
+        drop(turn);
+
+        assert!(scheduler.ticket("org.example.App").is_some());
+    }
+
+    #[tokio::test]
+    async fn queue_in_order() {
+        let scheduler = Scheduler::new(Concurrency::Queue);
+
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+
+        let (release, released) = tokio::sync::oneshot::channel::<()>();
+
+        let first = scheduler.ticket("org.example.App").unwrap();
+        let second = scheduler.ticket("org.example.App").unwrap();
+
+        let first = tokio::spawn(first.run({
+            let sender = sender.clone();
+            async move {
+                let _ = released.await;
+                sender.send(1).unwrap();
+            }
+        }));
+
+        tokio::task::yield_now().await;
+
+        let second = tokio::spawn(second.run(async move { sender.send(2).unwrap() }));
+
+        tokio::task::yield_now().await;
+
+        assert!(receiver.try_recv().is_err());
+
+        release.send(()).unwrap();
+
+        first.await.unwrap();
+        second.await.unwrap();
+
+        assert_eq!(receiver.recv().await, Some(1));
+        assert_eq!(receiver.recv().await, Some(2));
+    }
+}
@@ -0,0 +1,423 @@
+mod crypto;
+mod service;
+mod vault;
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    io::{Read, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+
+pub use service::SecretService;
+pub use vault::Vault;
+
+/// The length of master secrets, in bytes.
+const SECRET_LENGTH: usize = 64;
+
+/// Serializes read-modify-write cycles of secret files between concurrent requests.
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// `Stored` is what a secret file holds, whether it's the vault or the plaintext file of
+/// earlier versions.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Stored {
+    /// The master secret of each app, keyed by app id.
+    secrets: HashMap<String, Encoded>,
+}
+
+/// `Encoded` is an `Entry` as secret files keep it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Encoded {
+    /// The secret, in hex.
+    secret: String,
+
+    token: String,
+}
+
+/// `Entry` is the master secret of an app.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub secret: Vec<u8>,
+
+    /// The token handed out with the secret, which the app passes back when it asks again.
+    pub token: String,
+}
+
+impl Entry {
+    /// Make up a new secret and token.
+    fn new() -> std::io::Result<Self> {
+        Ok(Self {
+            secret: random(SECRET_LENGTH)?,
+            token: encode(&random(16)?),
+        })
+    }
+
+    fn encode(&self) -> Encoded {
+        Encoded {
+            secret: encode(&self.secret),
+            token: self.token.clone(),
+        }
+    }
+
+    fn decode(encoded: Encoded) -> std::io::Result<Self> {
+        Ok(Self {
+            secret: decode(&encoded.secret)
+                .ok_or_else(|| std::io::Error::other("the secret is corrupt"))?,
+            token: encoded.token,
+        })
+    }
+}
+
+/// `Keyring` is where master secrets are kept.
+pub enum Keyring {
+    SecretService(SecretService),
+    Vault(Vault),
+
+    /// The plaintext secret file of earlier versions, which secrets are only moved out of.
+    Plain(PathBuf),
+}
+
+impl Keyring {
+    /// Get the plaintext secret file of earlier versions, if it's still around.
+    pub fn plain() -> Option<Self> {
+        let path = dirs::data_dir()?
+            .join("xdg-desktop-portal-rs")
+            .join("secrets.toml");
+
+        path.is_file().then_some(Self::Plain(path))
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::SecretService(_) => "the Secret Service",
+            Self::Vault(_) => "the vault",
+            Self::Plain(_) => "the plaintext secret file",
+        }
+    }
+
+    async fn get(&self, app_id: &str) -> std::io::Result<Option<Entry>> {
+        let app_id = app_id.to_owned();
+
+        match self {
+            Self::SecretService(service) => {
+                service.get(&app_id).await.map_err(std::io::Error::other)
+            }
+
+            Self::Vault(vault) => {
+                let vault = vault.clone();
+
+                blocking(move || vault.get(&app_id)).await
+            }
+
+            Self::Plain(path) => {
+                let path = path.clone();
+
+                blocking(move || {
+                    let _guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+                    load(&path)?
+                        .secrets
+                        .remove(&app_id)
+                        .map(Entry::decode)
+                        .transpose()
+                })
+                .await
+            }
+        }
+    }
+
+    async fn set(&self, app_id: &str, entry: &Entry) -> std::io::Result<()> {
+        let (app_id, entry) = (app_id.to_owned(), entry.clone());
+
+        match self {
+            Self::SecretService(service) => service
+                .set(&app_id, &entry)
+                .await
+                .map_err(std::io::Error::other),
+
+            Self::Vault(vault) => {
+                let vault = vault.clone();
+
+                blocking(move || vault.set(&app_id, &entry)).await
+            }
+
+            Self::Plain(path) => {
+                let path = path.clone();
+
+                blocking(move || {
+                    let _guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+                    let mut stored = load(&path)?;
+
+                    stored.secrets.insert(app_id, entry.encode());
+
+                    save(&path, &stored)
+                })
+                .await
+            }
+        }
+    }
+
+    async fn remove(&self, app_id: &str) -> std::io::Result<()> {
+        let app_id = app_id.to_owned();
+
+        match self {
+            Self::SecretService(service) => {
+                service.remove(&app_id).await.map_err(std::io::Error::other)
+            }
+
+            Self::Vault(vault) => {
+                let vault = vault.clone();
+
+                blocking(move || vault.remove(&app_id)).await
+            }
+
+            Self::Plain(path) => {
+                let path = path.clone();
+
+                blocking(move || {
+                    let _guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+                    let mut stored = load(&path)?;
+
+                    if stored.secrets.remove(&app_id).is_none() {
+                        return Ok(());
+                    }
+
+                    // Once the last secret is moved out, there's no plaintext left behind.
+                    match stored.secrets.is_empty() {
+                        true => std::fs::remove_file(&path),
+                        false => save(&path, &stored),
+                    }
+                })
+                .await
+            }
+        }
+    }
+}
+
+/// Get the master secret of `app_id` and its token from `keyring`, making one up the first
+/// time it's asked for.
+///
+/// A secret kept in one of the keyrings `others` resolves to, which are only asked for if
+/// `keyring` hasn't got it, is moved into `keyring`.
+///
+/// The secret is all it takes to open the keyring of the app, so it never changes; `token` is
+/// the one the app got before, if it kept it, which tells whether it's the same secret.
+pub async fn retrieve(
+    keyring: &Keyring,
+    others: impl Future<Output = Vec<Keyring>>,
+    app_id: &str,
+    token: Option<&str>,
+) -> std::io::Result<(Vec<u8>, String)> {
+    let entry = match keyring.get(app_id).await? {
+        Some(entry) => Some(entry),
+        None => migrate(keyring, others.await, app_id).await?,
+    };
+
+    if let Some(entry) = entry {
+        if token.is_some_and(|token| token != entry.token) {
+            log::warn!("{} passed the token of another secret than its own", app_id);
+        }
+
+        return Ok((entry.secret, entry.token));
+    }
+
+    if token.is_some() {
+        log::warn!(
+            "{} had a secret before, what it encrypted with it can't be read anymore",
+            app_id
+        );
+    }
+
+    let entry = Entry::new()?;
+
+    keyring.set(app_id, &entry).await?;
+
+    Ok((entry.secret, entry.token))
+}
+
+/// Move the master secret of `app_id` from the first of `others` that has it to `keyring`.
+async fn migrate(
+    keyring: &Keyring,
+    others: Vec<Keyring>,
+    app_id: &str,
+) -> std::io::Result<Option<Entry>> {
+    for other in others {
+        let entry = match other.get(app_id).await {
+            Ok(Some(entry)) => entry,
+            Ok(None) => continue,
+
+            Err(e) => {
+                log::warn!("failed to look in {} for a secret: {}", other.name(), e);
+                continue;
+            }
+        };
+
+        keyring.set(app_id, &entry).await?;
+
+        // The secret is safe in its new place, so failing to remove the old copy isn't fatal.
+        if let Err(e) = other.remove(app_id).await {
+            log::warn!("failed to remove a secret from {}: {}", other.name(), e);
+        }
+
+        log::info!(
+            "moved the secret of {} from {} to {}",
+            app_id,
+            other.name(),
+            keyring.name()
+        );
+
+        return Ok(Some(entry));
+    }
+
+    Ok(None)
+}
+
+/// Run `operation` on a secret file on the blocking pool, as reading, writing and encrypting
+/// the files blocks.
+async fn blocking<T, F>(operation: F) -> std::io::Result<T>
+where
+    F: FnOnce() -> std::io::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(operation)
+        .await
+        .map_err(std::io::Error::other)?
+}
+
+/// Read `length` random bytes.
+fn random(length: usize) -> std::io::Result<Vec<u8>> {
+    let mut bytes = vec![0; length];
+
+    std::fs::File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+
+    Ok(bytes)
+}
+
+fn encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode(hex: &str) -> Option<Vec<u8>> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Read a plaintext secret file, which is empty if there's none.
+fn load(path: &Path) -> std::io::Result<Stored> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => toml::from_str(&contents)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Stored::default()),
+
+        Err(e) => Err(e),
+    }
+}
+
+/// Write a plaintext secret file, readable only by the user.
+fn save(path: &Path, stored: &Stored) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let contents = toml::to_string(stored)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+    // Write a new file and move it over the old one, so the mode applies to it.
+    let temporary = path.with_extension("toml.new");
+
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&temporary)?;
+
+    file.write_all(contents.as_bytes())?;
+
+    std::fs::rename(temporary, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, retrieve, Keyring, SECRET_LENGTH};
+
+    #[tokio::test]
+    async fn secrets() {
+        let dir = std::env::temp_dir().join(format!("secret-test-{}", std::process::id()));
+
+        let keyring = Keyring::Plain(dir.join("secrets.toml"));
+        let none = || async { Vec::new() };
+
+        let (secret, token) = retrieve(&keyring, none(), "org.example.App", None)
+            .await
+            .unwrap();
+
+        assert_eq!(secret.len(), SECRET_LENGTH);
+
+        // The secret stays the same, whether the app kept its token or not.
+        assert_eq!(
+            retrieve(&keyring, none(), "org.example.App", Some(&token))
+                .await
+                .unwrap(),
+            (secret.clone(), token.clone())
+        );
+        assert_eq!(
+            retrieve(&keyring, none(), "org.example.App", None)
+                .await
+                .unwrap()
+                .0,
+            secret
+        );
+
+        // Other apps get their own.
+        assert_ne!(
+            retrieve(&keyring, none(), "org.example.Other", None)
+                .await
+                .unwrap()
+                .0,
+            secret
+        );
+
+        // Secrets move out of other keyrings, which are removed once they're empty.
+        let other = dir.join("other.toml");
+
+        std::fs::rename(dir.join("secrets.toml"), &other).unwrap();
+
+        let others = || async { vec![Keyring::Plain(dir.join("other.toml"))] };
+
+        assert_eq!(
+            retrieve(&keyring, others(), "org.example.App", None)
+                .await
+                .unwrap(),
+            (secret.clone(), token.clone())
+        );
+        assert!(other.is_file());
+
+        retrieve(&keyring, others(), "org.example.Other", None)
+            .await
+            .unwrap();
+
+        assert!(!other.exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(encode(&[0, 15, 255]), "000fff");
+        assert_eq!(decode("000fff"), Some(vec![0, 15, 255]));
+        assert_eq!(decode("0g"), None);
+        assert_eq!(decode("abc"), None);
+    }
+}
@@ -0,0 +1,108 @@
+use aes_gcm::{
+    aead::{Aead, KeyInit, Payload},
+    Aes256Gcm,
+};
+use hmac::Hmac;
+use sha2::Sha256;
+
+/// The length of keys, for AES-256.
+pub const KEY_LENGTH: usize = 32;
+
+/// The length of GCM nonces.
+pub const NONCE_LENGTH: usize = 12;
+
+/// The length of GCM tags.
+const TAG_LENGTH: usize = 16;
+
+/// Derive a key from `password` with PBKDF2-HMAC-SHA256.
+pub fn pbkdf2(password: &[u8], salt: &[u8], rounds: u32) -> [u8; KEY_LENGTH] {
+    let mut key = [0; KEY_LENGTH];
+
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(password, salt, rounds, &mut key)
+        .expect("HMAC takes keys of any length");
+
+    key
+}
+
+/// Encrypt and authenticate `plaintext` with AES-256-GCM, along with `associated` data that
+/// isn't encrypted, returning the ciphertext followed by its tag.
+pub fn seal(
+    key: &[u8; KEY_LENGTH],
+    nonce: &[u8; NONCE_LENGTH],
+    plaintext: &[u8],
+    associated: &[u8],
+) -> std::io::Result<Vec<u8>> {
+    let payload = Payload {
+        msg: plaintext,
+        aad: associated,
+    };
+
+    Aes256Gcm::new(key.into())
+        .encrypt(nonce.into(), payload)
+        .map_err(|_| std::io::Error::other("encryption failed"))
+}
+
+/// Check and decrypt what `seal` returned, or return `None` if it wasn't sealed with `key`
+/// and `associated`, or either was changed since.
+pub fn open(
+    key: &[u8; KEY_LENGTH],
+    nonce: &[u8; NONCE_LENGTH],
+    sealed: &[u8],
+    associated: &[u8],
+) -> Option<Vec<u8>> {
+    if sealed.len() < TAG_LENGTH {
+        return None;
+    }
+
+    let payload = Payload {
+        msg: sealed,
+        aad: associated,
+    };
+
+    Aes256Gcm::new(key.into())
+        .decrypt(nonce.into(), payload)
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{open, pbkdf2, seal};
+
+    #[test]
+    fn pbkdf2_vectors() {
+        let hex = |key: [u8; 32]| -> String { key.iter().map(|b| format!("{:02x}", b)).collect() };
+
+        // From RFC 7914, section 11.
+        assert_eq!(
+            hex(pbkdf2(b"passwd", b"salt", 1)),
+            "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc"
+        );
+        assert_eq!(
+            hex(pbkdf2(b"Password", b"NaCl", 80000)),
+            "4ddcd8f60b98be21830cee5ef22701f9641a4418d04c0414aeff08876b34ab56"
+        );
+    }
+
+    #[test]
+    fn gcm() {
+        let (key, nonce) = ([7; 32], [9; 12]);
+
+        let sealed = seal(&key, &nonce, b"secret", b"header").unwrap();
+
+        assert_eq!(sealed.len(), 6 + 16);
+        assert_eq!(
+            open(&key, &nonce, &sealed, b"header").as_deref(),
+            Some(&b"secret"[..])
+        );
+
+        // Other keys, changed messages and other associated data don't open.
+        assert_eq!(open(&[8; 32], &nonce, &sealed, b"header"), None);
+        assert_eq!(open(&key, &nonce, &sealed, b"other"), None);
+
+        let mut changed = sealed.clone();
+        changed[0] ^= 1;
+
+        assert_eq!(open(&key, &nonce, &changed, b"header"), None);
+        assert_eq!(open(&key, &nonce, &sealed[..15], b"header"), None);
+    }
+}
@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+
+use futures_util::StreamExt;
+use zbus::{dbus_proxy, zvariant};
+
+use super::Entry;
+
+/// The bus name of the Secret Service.
+const SERVICE: &str = "org.freedesktop.secrets";
+
+/// The `xdg:schema` attribute of the items master secrets are kept in.
+const SCHEMA: &str = "org.freedesktop.impl.portal.Secret";
+
+/// `Secret` is a secret as the Secret Service passes it: its session, parameters, value and
+/// content type.
+type Secret = (zvariant::OwnedObjectPath, Vec<u8>, Vec<u8>, String);
+
+/// The Secret Service, as GNOME Keyring and KeePassXC provide it.
+#[dbus_proxy(
+    interface = "org.freedesktop.Secret.Service",
+    default_service = "org.freedesktop.secrets",
+    default_path = "/org/freedesktop/secrets"
+)]
+trait Service {
+    fn open_session(
+        &self,
+        algorithm: &str,
+        input: &zvariant::Value<'_>,
+    ) -> zbus::Result<(zvariant::OwnedValue, zvariant::OwnedObjectPath)>;
+
+    fn search_items(
+        &self,
+        attributes: HashMap<&str, &str>,
+    ) -> zbus::Result<(
+        Vec<zvariant::OwnedObjectPath>,
+        Vec<zvariant::OwnedObjectPath>,
+    )>;
+
+    fn unlock(
+        &self,
+        objects: &[zvariant::ObjectPath<'_>],
+    ) -> zbus::Result<(Vec<zvariant::OwnedObjectPath>, zvariant::OwnedObjectPath)>;
+
+    fn get_secrets(
+        &self,
+        items: &[zvariant::ObjectPath<'_>],
+        session: &zvariant::ObjectPath<'_>,
+    ) -> zbus::Result<HashMap<zvariant::OwnedObjectPath, Secret>>;
+
+    fn read_alias(&self, name: &str) -> zbus::Result<zvariant::OwnedObjectPath>;
+}
+
+#[dbus_proxy(
+    interface = "org.freedesktop.Secret.Collection",
+    default_service = "org.freedesktop.secrets"
+)]
+trait Collection {
+    fn create_item(
+        &self,
+        properties: HashMap<&str, zvariant::Value<'_>>,
+        secret: &Secret,
+        replace: bool,
+    ) -> zbus::Result<(zvariant::OwnedObjectPath, zvariant::OwnedObjectPath)>;
+}
+
+#[dbus_proxy(
+    interface = "org.freedesktop.Secret.Item",
+    default_service = "org.freedesktop.secrets"
+)]
+trait Item {
+    fn delete(&self) -> zbus::Result<zvariant::OwnedObjectPath>;
+
+    #[dbus_proxy(property)]
+    fn attributes(&self) -> zbus::Result<HashMap<String, String>>;
+}
+
+/// The prompts the Secret Service shows to unlock collections or confirm changes.
+#[dbus_proxy(
+    interface = "org.freedesktop.Secret.Prompt",
+    default_service = "org.freedesktop.secrets"
+)]
+trait Prompt {
+    fn prompt(&self, window_id: &str) -> zbus::Result<()>;
+
+    #[dbus_proxy(signal)]
+    fn completed(&self, dismissed: bool, result: zvariant::Value<'_>) -> zbus::Result<()>;
+}
+
+/// `SecretService` keeps master secrets as items of the default collection of the Secret
+/// Service, one per app.
+pub struct SecretService {
+    conn: zbus::Connection,
+    session: zvariant::OwnedObjectPath,
+}
+
+impl SecretService {
+    /// Open a session with the Secret Service, or return `None` if there's none running or
+    /// to be started.
+    pub async fn connect(conn: &zbus::Connection) -> zbus::Result<Option<Self>> {
+        let bus = zbus::fdo::DBusProxy::new(conn).await?;
+
+        let name = zbus::names::BusName::try_from(SERVICE)?;
+
+        let installed = bus.name_has_owner(name).await?
+            || bus
+                .list_activatable_names()
+                .await?
+                .iter()
+                .any(|activatable| activatable.as_str() == SERVICE);
+
+        if !installed {
+            return Ok(None);
+        }
+
+        // Secrets only travel over the bus to the portal, so they aren't encrypted on the way.
+        let (_, session) = ServiceProxy::new(conn)
+            .await?
+            .open_session("plain", &zvariant::Value::from(""))
+            .await?;
+
+        Ok(Some(Self {
+            conn: conn.clone(),
+            session,
+        }))
+    }
+
+    pub async fn get(&self, app_id: &str) -> zbus::Result<Option<Entry>> {
+        let Some(item) = self.find(app_id).await? else {
+            return Ok(None);
+        };
+
+        let service = ServiceProxy::new(&self.conn).await?;
+
+        let mut secrets = service
+            .get_secrets(&[item.as_ref()], &self.session.as_ref())
+            .await?;
+
+        let Some((_, _, secret, _)) = secrets.remove(&item) else {
+            return Ok(None);
+        };
+
+        let attributes = ItemProxy::builder(&self.conn)
+            .path(item)?
+            .build()
+            .await?
+            .attributes()
+            .await?;
+
+        Ok(Some(Entry {
+            secret,
+            token: attributes.get("token").cloned().unwrap_or_default(),
+        }))
+    }
+
+    pub async fn set(&self, app_id: &str, entry: &Entry) -> zbus::Result<()> {
+        let service = ServiceProxy::new(&self.conn).await?;
+
+        let collection = service.read_alias("default").await?;
+
+        if collection.as_str() == "/" {
+            return Err(zbus::Error::Failure(String::from(
+                "there's no default collection",
+            )));
+        }
+
+        // Collections are locked until the user logs in, or unlocks them.
+        let (unlocked, prompt) = service.unlock(&[collection.as_ref()]).await?;
+
+        if unlocked.is_empty() && !self.prompt(prompt).await? {
+            return Err(zbus::Error::Failure(String::from(
+                "the keyring stays locked",
+            )));
+        }
+
+        let attributes = HashMap::from([
+            ("xdg:schema", SCHEMA),
+            ("app_id", app_id),
+            ("token", entry.token.as_str()),
+        ]);
+
+        let properties = HashMap::from([
+            (
+                "org.freedesktop.Secret.Item.Label",
+                zvariant::Value::from(format!("Master secret of {}", app_id)),
+            ),
+            (
+                "org.freedesktop.Secret.Item.Attributes",
+                zvariant::Value::from(attributes),
+            ),
+        ]);
+
+        let secret = (
+            self.session.clone(),
+            Vec::new(),
+            entry.secret.clone(),
+            String::from("application/octet-stream"),
+        );
+
+        let (_, prompt) = CollectionProxy::builder(&self.conn)
+            .path(collection)?
+            .build()
+            .await?
+            .create_item(properties, &secret, true)
+            .await?;
+
+        match self.prompt(prompt).await? {
+            true => Ok(()),
+            false => Err(zbus::Error::Failure(String::from("the secret wasn't kept"))),
+        }
+    }
+
+    pub async fn remove(&self, app_id: &str) -> zbus::Result<()> {
+        let Some(item) = self.find(app_id).await? else {
+            return Ok(());
+        };
+
+        let prompt = ItemProxy::builder(&self.conn)
+            .path(item)?
+            .build()
+            .await?
+            .delete()
+            .await?;
+
+        self.prompt(prompt).await?;
+
+        Ok(())
+    }
+
+    /// Find the item of the master secret of `app_id`, unlocking it if needed.
+    async fn find(&self, app_id: &str) -> zbus::Result<Option<zvariant::OwnedObjectPath>> {
+        let service = ServiceProxy::new(&self.conn).await?;
+
+        let attributes = HashMap::from([("xdg:schema", SCHEMA), ("app_id", app_id)]);
+
+        let (unlocked, locked) = service.search_items(attributes).await?;
+
+        if let Some(item) = unlocked.into_iter().next() {
+            return Ok(Some(item));
+        }
+
+        let Some(item) = locked.into_iter().next() else {
+            return Ok(None);
+        };
+
+        let (unlocked, prompt) = service.unlock(&[item.as_ref()]).await?;
+
+        if unlocked.is_empty() && !self.prompt(prompt).await? {
+            return Err(zbus::Error::Failure(String::from(
+                "the keyring stays locked",
+            )));
+        }
+
+        Ok(Some(item))
+    }
+
+    /// Show the prompt at `path`, unless it's `/` for none, returning whether it was
+    /// completed rather than dismissed.
+    async fn prompt(&self, path: zvariant::OwnedObjectPath) -> zbus::Result<bool> {
+        if path.as_str() == "/" {
+            return Ok(true);
+        }
+
+        let prompt = PromptProxy::builder(&self.conn).path(path)?.build().await?;
+
+        let mut completed = prompt.receive_completed().await?;
+
+        // The portal has no window of its own to put the prompt over.
+        prompt.prompt("").await?;
+
+        let completed = completed
+            .next()
+            .await
+            .ok_or_else(|| zbus::Error::Failure(String::from("the prompt went away")))?;
+
+        Ok(!completed.args()?.dismissed)
+    }
+}
@@ -0,0 +1,346 @@
+use std::{io::Write, os::unix::fs::OpenOptionsExt, path::PathBuf};
+
+use super::{
+    crypto::{self, KEY_LENGTH, NONCE_LENGTH},
+    Entry, Stored,
+};
+
+/// The start of vault files, with the version of their format.
+const MAGIC: &[u8; 8] = b"XDPRSV1\0";
+
+const SALT_LENGTH: usize = 16;
+
+/// The PBKDF2 rounds keys are derived from passwords with.
+const ROUNDS: u32 = 200_000;
+
+/// The kernel's `KEY_SPEC_SESSION_KEYRING`, the session keyring of the calling process.
+///
+/// Processes without one are given the user's session keyring, which lives as long as the
+/// user is logged in.
+const SESSION_KEYRING: libc::c_long = -3;
+
+/// `Vault` is the encrypted file master secrets are kept in without a Secret Service,
+/// `$XDG_DATA_HOME/xdg-desktop-portal-rs/secrets.vault`.
+///
+/// It's unlocked with a password once per login: the key derived from the password is kept
+/// in the session keyring of the kernel until the user logs out.
+#[derive(Clone)]
+pub struct Vault {
+    path: PathBuf,
+    salt: [u8; SALT_LENGTH],
+    key: [u8; KEY_LENGTH],
+}
+
+impl Vault {
+    /// Unlock the vault, with the key in the session keyring or else the password `ask`
+    /// returns, which is told whether the vault is new and the password is being chosen.
+    pub fn unlock(ask: impl FnOnce(bool) -> Option<String>) -> std::io::Result<Self> {
+        let path = path().ok_or(std::io::ErrorKind::NotFound)?;
+
+        Self::unlock_at(path, true, ask)
+    }
+
+    /// Unlock the vault at `path`, keeping its key in the session keyring only if `keyring`.
+    fn unlock_at(
+        path: PathBuf,
+        keyring: bool,
+        ask: impl FnOnce(bool) -> Option<String>,
+    ) -> std::io::Result<Self> {
+        let cancelled = || std::io::Error::new(std::io::ErrorKind::PermissionDenied, "no password");
+
+        let contents = match std::fs::read(&path) {
+            Ok(contents) => contents,
+
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let password = ask(true).ok_or_else(cancelled)?;
+
+                let salt: [u8; SALT_LENGTH] = super::random(SALT_LENGTH)?
+                    .try_into()
+                    .map_err(|_| std::io::Error::other("short salt"))?;
+
+                let vault = Self {
+                    key: crypto::pbkdf2(password.as_bytes(), &salt, ROUNDS),
+                    salt,
+                    path,
+                };
+
+                vault.write(&Stored::default())?;
+
+                if keyring {
+                    vault.remember();
+                }
+
+                return Ok(vault);
+            }
+
+            Err(e) => return Err(e),
+        };
+
+        let salt: [u8; SALT_LENGTH] = contents
+            .strip_prefix(MAGIC)
+            .and_then(|rest| rest.get(..SALT_LENGTH)?.try_into().ok())
+            .ok_or_else(|| invalid("the vault isn't one"))?;
+
+        let mut vault = Self {
+            path,
+            salt,
+            key: [0; KEY_LENGTH],
+        };
+
+        if let Some(key) = remembered(&salt).filter(|_| keyring) {
+            vault.key = key;
+
+            if vault.open(&contents)?.is_some() {
+                return Ok(vault);
+            }
+        }
+
+        let password = ask(false).ok_or_else(cancelled)?;
+
+        vault.key = crypto::pbkdf2(password.as_bytes(), &salt, ROUNDS);
+
+        if vault.open(&contents)?.is_none() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "wrong password",
+            ));
+        }
+
+        if keyring {
+            vault.remember();
+        }
+
+        Ok(vault)
+    }
+
+    /// Check whether there's a vault, without unlocking it.
+    pub fn exists() -> bool {
+        path().is_some_and(|path| path.is_file())
+    }
+
+    pub fn get(&self, app_id: &str) -> std::io::Result<Option<Entry>> {
+        self.read()?
+            .secrets
+            .remove(app_id)
+            .map(Entry::decode)
+            .transpose()
+    }
+
+    pub fn set(&self, app_id: &str, entry: &Entry) -> std::io::Result<()> {
+        let _guard = super::LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let mut stored = self.read()?;
+
+        stored.secrets.insert(String::from(app_id), entry.encode());
+
+        self.write(&stored)
+    }
+
+    pub fn remove(&self, app_id: &str) -> std::io::Result<()> {
+        let _guard = super::LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let mut stored = self.read()?;
+
+        if stored.secrets.remove(app_id).is_some() {
+            self.write(&stored)?;
+        }
+
+        Ok(())
+    }
+
+    fn read(&self) -> std::io::Result<Stored> {
+        let contents = std::fs::read(&self.path)?;
+
+        let plaintext = self
+            .open(&contents)?
+            .ok_or_else(|| invalid("the vault changed its password or is corrupt"))?;
+
+        toml::from_str(&String::from_utf8_lossy(&plaintext)).map_err(invalid)
+    }
+
+    /// Decrypt the contents of a vault file, or return `None` if the key doesn't fit.
+    ///
+    /// The header, the magic, salt and nonce, is authenticated along with the secrets.
+    fn open(&self, contents: &[u8]) -> std::io::Result<Option<Vec<u8>>> {
+        let length = MAGIC.len() + SALT_LENGTH + NONCE_LENGTH;
+
+        let header = contents
+            .get(..length)
+            .ok_or_else(|| invalid("the vault is cut short"))?;
+
+        let nonce: [u8; NONCE_LENGTH] = header[length - NONCE_LENGTH..]
+            .try_into()
+            .map_err(|_| invalid("the vault is cut short"))?;
+
+        Ok(crypto::open(&self.key, &nonce, &contents[length..], header))
+    }
+
+    /// Encrypt `stored` with a new nonce and replace the vault file with it.
+    fn write(&self, stored: &Stored) -> std::io::Result<()> {
+        let plaintext = toml::to_string(stored).map_err(invalid)?;
+
+        let nonce: [u8; NONCE_LENGTH] = super::random(NONCE_LENGTH)?
+            .try_into()
+            .map_err(|_| std::io::Error::other("short nonce"))?;
+
+        let header = [&MAGIC[..], &self.salt, &nonce].concat();
+
+        let sealed = crypto::seal(&self.key, &nonce, plaintext.as_bytes(), &header)?;
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        // Write a new file and move it over the old one, so a failed write loses nothing.
+        let temporary = self.path.with_extension("vault.new");
+
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&temporary)?;
+
+        file.write_all(&header)?;
+        file.write_all(&sealed)?;
+        file.sync_all()?;
+
+        std::fs::rename(temporary, &self.path)
+    }
+
+    /// Keep the key in the session keyring, so the password isn't asked again this login.
+    fn remember(&self) {
+        let description = description(&self.salt);
+
+        // SAFETY: the strings are NUL-terminated and the key is valid for its length.
+        let id = unsafe {
+            libc::syscall(
+                libc::SYS_add_key,
+                c"user".as_ptr(),
+                description.as_ptr(),
+                self.key.as_ptr(),
+                self.key.len(),
+                SESSION_KEYRING,
+            )
+        };
+
+        if id < 0 {
+            log::warn!(
+                "failed to keep the vault key in the session keyring: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+}
+
+/// Get the key of the vault with `salt` from the session keyring, if it's there.
+fn remembered(salt: &[u8; SALT_LENGTH]) -> Option<[u8; KEY_LENGTH]> {
+    /// The `KEYCTL_READ` operation of keyctl.
+    const KEYCTL_READ: libc::c_long = 11;
+
+    let description = description(salt);
+
+    // SAFETY: the strings are NUL-terminated, and no callout is asked for.
+    let id = unsafe {
+        libc::syscall(
+            libc::SYS_request_key,
+            c"user".as_ptr(),
+            description.as_ptr(),
+            std::ptr::null::<libc::c_char>(),
+            0,
+        )
+    };
+
+    if id < 0 {
+        return None;
+    }
+
+    let mut key = [0; KEY_LENGTH];
+
+    // SAFETY: the buffer is valid for its length.
+    let length = unsafe {
+        libc::syscall(
+            libc::SYS_keyctl,
+            KEYCTL_READ,
+            id,
+            key.as_mut_ptr(),
+            key.len(),
+        )
+    };
+
+    (length == KEY_LENGTH as libc::c_long).then_some(key)
+}
+
+/// Describe the key of the vault with `salt` in the keyring, so vaults don't share keys.
+fn description(salt: &[u8; SALT_LENGTH]) -> std::ffi::CString {
+    let description = format!("xdg-desktop-portal-rs:vault:{}", super::encode(salt));
+
+    std::ffi::CString::new(description).expect("hex has no NUL")
+}
+
+fn invalid(e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+}
+
+/// Get the path of the vault.
+fn path() -> Option<PathBuf> {
+    Some(
+        dirs::data_dir()?
+            .join("xdg-desktop-portal-rs")
+            .join("secrets.vault"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Entry, Vault, MAGIC};
+
+    #[test]
+    fn vault() {
+        let dir = std::env::temp_dir().join(format!("vault-test-{}", std::process::id()));
+        let path = dir.join("secrets.vault");
+
+        let password = |new: bool| {
+            assert!(new);
+            Some(String::from("password"))
+        };
+
+        let entry = Entry {
+            secret: vec![1, 2, 3],
+            token: String::from("token"),
+        };
+
+        // The test leaves the session keyring alone, so the password is asked every time.
+        let vault = Vault::unlock_at(path.clone(), false, password).unwrap();
+
+        vault.set("org.example.App", &entry).unwrap();
+
+        let password = |new: bool| {
+            assert!(!new);
+            Some(String::from("password"))
+        };
+
+        let vault = Vault::unlock_at(path.clone(), false, password).unwrap();
+
+        assert_eq!(vault.get("org.example.App").unwrap(), Some(entry));
+
+        vault.remove("org.example.App").unwrap();
+
+        assert_eq!(vault.get("org.example.App").unwrap(), None);
+
+        // Nothing in the file is readable without the key.
+        let contents = std::fs::read(&path).unwrap();
+
+        assert!(!String::from_utf8_lossy(&contents).contains("secrets"));
+
+        // Nor does a changed header go unnoticed.
+        let mut changed = contents.clone();
+        changed[MAGIC.len()] ^= 1;
+
+        std::fs::write(&path, changed).unwrap();
+
+        assert!(vault.get("org.example.App").is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,22 +1,320 @@
 use zbus::{dbus_interface, zvariant};
 
+use std::sync::Arc;
+
+use crate::{
+    audit::{Audit, Outcome},
+    capture::Capture,
+    cast::Cast,
+    choices,
+    config::Config,
+    desktop,
+    dialog::{AppChoices, AppRequest, DialogProvider, FileRequest, FileResponse, Level, Message},
+    documents,
+    filter::{self, Filter},
+    gvfs,
+    input::Input,
+    intercept::Intercept,
+    mime,
+    notify::Notifier,
+    permissions,
+    policy::{self, Rules},
+    recent, request, resolve,
+    schedule::{Scheduler, Turn},
+    session::Sessions,
+    shortcuts::Grabber,
+    state, uri,
+    wallpaper::Setter,
+    window::ParentWindow,
+};
+
+mod access;
+mod account;
+mod background;
+mod clipboard;
+mod email;
+mod global_shortcuts;
+mod inhibit;
+mod input_capture;
+mod lockdown;
+mod notification;
+mod print;
+mod remotedesktop;
+mod screencast;
+mod screenshot;
+mod secret;
+mod settings;
+mod wallpaper;
+
+pub use access::Access;
+pub use account::Account;
+pub use background::Background;
+pub use clipboard::Clipboard;
+pub use email::Email;
+pub use global_shortcuts::{
+    activate_shortcuts, configure_shortcuts, GlobalShortcuts, ShortcutSettings,
+};
+pub use inhibit::{monitor_session, Inhibit};
+pub use input_capture::{signal_captures, InputCapture};
+pub use lockdown::Lockdown;
+pub use notification::{invoke_actions, Notification};
+pub use print::Print;
+pub use remotedesktop::RemoteDesktop;
+pub use screencast::ScreenCast;
+pub use screenshot::Screenshot;
+pub use secret::Secret;
+pub use settings::{watch_settings, Settings};
+pub use wallpaper::Wallpaper;
+
+/// The permission store table holding the answers of the file dialog access prompt.
+const PERMISSION_TABLE: &str = "file-chooser";
+
+/// The entry of `PERMISSION_TABLE` apps are allowed on.
+const PERMISSION_ID: &str = "access";
+
+/// The results key of the file metadata extension, prefixed with this backend's bus name.
+const METADATA_KEY: &str = "org.freedesktop.impl.portal.desktop.rs.metadata";
+
+/// The object path xdg-desktop-portal looks for every backend interface at.
+const PATH: &str = "/org/freedesktop/portal/desktop";
+
 /// `StrMap` is similar to dbus's `Dict<String, Variant>` but uses `&str` instead of `String`.
 type StrMap<'a> = std::collections::HashMap<&'a str, zvariant::Value<'a>>;
 
+/// Export every implemented portal interface on a connection being built.
+///
+/// Interfaces added here also need to be listed in `service/rs.portal`, or
+/// xdg-desktop-portal won't use them, unless they're this backend's own.
+#[allow(clippy::too_many_arguments)]
+pub fn serve<'a>(
+    builder: zbus::ConnectionBuilder<'a>,
+    config: Arc<Config>,
+    dialogs: Arc<dyn DialogProvider>,
+    scheduler: Arc<Scheduler>,
+    audit: Arc<Audit>,
+    capture: Arc<dyn Capture>,
+    cast: Arc<dyn Cast>,
+    input: Arc<dyn Input>,
+    clipboard: Arc<dyn crate::clipboard::Clipboard>,
+    notifier: Arc<dyn Notifier>,
+    setter: Arc<dyn Setter>,
+    grabber: Arc<dyn Grabber>,
+    intercept: Arc<dyn Intercept>,
+) -> zbus::Result<zbus::ConnectionBuilder<'a>> {
+    // Remote desktop sessions share the screen through ScreenCast.
+    let sessions = Arc::new(Sessions::new());
+
+    let shortcut_sessions = Arc::new(Sessions::new());
+
+    builder
+        .serve_at(
+            PATH,
+            FileChooser {
+                config: config.clone(),
+                dialogs: dialogs.clone(),
+                scheduler: scheduler.clone(),
+                audit: audit.clone(),
+            },
+        )?
+        .serve_at(
+            PATH,
+            AppChooser::new(config.clone(), dialogs.clone(), scheduler.clone()),
+        )?
+        .serve_at(
+            PATH,
+            Screenshot {
+                config: config.clone(),
+                dialogs: dialogs.clone(),
+                scheduler: scheduler.clone(),
+                audit: audit.clone(),
+                capture: capture.clone(),
+            },
+        )?
+        .serve_at(
+            PATH,
+            ScreenCast::new(
+                config.clone(),
+                dialogs.clone(),
+                scheduler.clone(),
+                audit.clone(),
+                capture.clone(),
+                cast.clone(),
+                sessions.clone(),
+            ),
+        )?
+        .serve_at(
+            PATH,
+            Background {
+                config: config.clone(),
+                dialogs: dialogs.clone(),
+                scheduler: scheduler.clone(),
+                audit: audit.clone(),
+            },
+        )?
+        .serve_at(
+            PATH,
+            Lockdown {
+                config: config.clone(),
+            },
+        )?
+        .serve_at(
+            PATH,
+            Access {
+                config: config.clone(),
+                dialogs: dialogs.clone(),
+                scheduler: scheduler.clone(),
+                audit: audit.clone(),
+            },
+        )?
+        .serve_at(
+            PATH,
+            Account {
+                config: config.clone(),
+                dialogs: dialogs.clone(),
+                scheduler: scheduler.clone(),
+                audit: audit.clone(),
+            },
+        )?
+        .serve_at(
+            PATH,
+            Email {
+                config: config.clone(),
+                dialogs: dialogs.clone(),
+                scheduler: scheduler.clone(),
+                audit: audit.clone(),
+            },
+        )?
+        .serve_at(
+            PATH,
+            Print {
+                config: config.clone(),
+                dialogs: dialogs.clone(),
+                scheduler: scheduler.clone(),
+                audit: audit.clone(),
+                prepared: Default::default(),
+            },
+        )?
+        .serve_at(
+            PATH,
+            Secret {
+                config: config.clone(),
+                dialogs: dialogs.clone(),
+                scheduler: scheduler.clone(),
+                audit: audit.clone(),
+                lock: Default::default(),
+            },
+        )?
+        .serve_at(
+            PATH,
+            GlobalShortcuts {
+                config: config.clone(),
+                dialogs: dialogs.clone(),
+                scheduler: scheduler.clone(),
+                audit: audit.clone(),
+                grabber: grabber.clone(),
+                sessions: shortcut_sessions.clone(),
+            },
+        )?
+        .serve_at(
+            PATH,
+            ShortcutSettings {
+                dialogs: dialogs.clone(),
+                scheduler: scheduler.clone(),
+                grabber,
+                sessions: shortcut_sessions,
+            },
+        )?
+        .serve_at(
+            PATH,
+            Wallpaper {
+                config: config.clone(),
+                dialogs: dialogs.clone(),
+                scheduler: scheduler.clone(),
+                audit: audit.clone(),
+                setter,
+                capture: capture.clone(),
+            },
+        )?
+        .serve_at(
+            PATH,
+            InputCapture {
+                config: config.clone(),
+                dialogs: dialogs.clone(),
+                scheduler: scheduler.clone(),
+                audit: audit.clone(),
+                capture: capture.clone(),
+                intercept,
+                sessions: Arc::new(Sessions::new()),
+            },
+        )?
+        .serve_at(
+            PATH,
+            RemoteDesktop {
+                config: config.clone(),
+                dialogs,
+                scheduler,
+                audit,
+                capture,
+                cast,
+                input,
+                clipboard,
+                sessions: sessions.clone(),
+            },
+        )?
+        .serve_at(PATH, Clipboard { sessions })?
+        .serve_at(PATH, Notification::new(config.clone(), notifier))?
+        .serve_at(PATH, Inhibit::default())?
+        .serve_at(PATH, Settings::new(&config))
+}
+
 /// AppChooser implements the org.freedesktop.impl.portal.AppChooser interface.
-pub struct AppChooser {}
+pub struct AppChooser {
+    config: Arc<Config>,
+    dialogs: Arc<dyn DialogProvider>,
+    scheduler: Arc<Scheduler>,
+
+    /// The lists of the dialogs being shown, by request handle, for UpdateChoices.
+    open: std::sync::Mutex<std::collections::HashMap<String, AppChoices>>,
+}
 
-#[dbus_interface(name = "org.freedesktop.portal.AppChooser")]
 impl AppChooser {
+    pub fn new(
+        config: Arc<Config>,
+        dialogs: Arc<dyn DialogProvider>,
+        scheduler: Arc<Scheduler>,
+    ) -> Self {
+        Self {
+            config,
+            dialogs,
+            scheduler,
+            open: std::sync::Mutex::default(),
+        }
+    }
+
+    /// Get the list of open dialogs.
+    fn open(&self) -> std::sync::MutexGuard<'_, std::collections::HashMap<String, AppChoices>> {
+        self.open.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[dbus_interface(name = "org.freedesktop.impl.portal.AppChooser")]
+impl AppChooser {
+    /// The version of the interface this backend implements.
+    #[dbus_interface(property, name = "version")]
+    fn version(&self) -> u32 {
+        2
+    }
+
     /// Interface for choosing an application.
     #[dbus_interface(out_args("response", "results"))]
     async fn choose_application(
         &self,
+        #[zbus(connection)] conn: &zbus::Connection,
         handle: zvariant::ObjectPath<'_>,
         app_id: &str,
         parent_window: &str,
         choices: Vec<&str>,
-        _options: StrMap<'_>,
+        options: StrMap<'_>,
     ) -> zbus::fdo::Result<(u32, StrMap<'_>)> {
         log::info!(
             "choose_application({}, {}, {}, {:?})",
@@ -26,32 +324,190 @@ impl AppChooser {
             choices
         );
 
-        // Just return an empty result for now;
-        // pretend we don't know any applications.
-        zbus::fdo::Result::Ok((1, StrMap::new()))
+        if self.config.lockdown.disable_application_handlers {
+            log::warn!("rejecting {}, opening applications is locked down", handle);
+            return zbus::fdo::Result::Ok((2, StrMap::new()));
+        }
+
+        if choices.is_empty() {
+            return zbus::fdo::Result::Ok((1, StrMap::new()));
+        }
+
+        let content_type = parse_content_type(&options);
+
+        let always_use = self.config.app_chooser.always_use(app_id) && content_type.is_some();
+
+        let last_choice = match options.get("last_choice") {
+            Some(zvariant::Value::Str(last_choice)) => Some(last_choice.to_string()),
+            _ => None,
+        };
+
+        let activation_token = match options.get("activation_token") {
+            Some(zvariant::Value::Str(token)) if !token.is_empty() => Some(token.to_string()),
+            _ => None,
+        };
+
+        // The frontend passes the app it used last time, which is the one to always use.
+        if let Some(content_type) = content_type.as_deref().filter(|_| always_use) {
+            let always = state::always_use(content_type)
+                .filter(|app| Some(app) == last_choice.as_ref() && choices.contains(&app.as_str()));
+
+            if let Some(app) = always {
+                log::info!("always using {} for {}", app, content_type);
+
+                return zbus::fdo::Result::Ok((0, choice_results(app, activation_token)));
+            }
+        }
+
+        let Some(ticket) = self.scheduler.ticket(app_id) else {
+            log::warn!("rejecting {}, {} already has a dialog open", handle, app_id);
+            return zbus::fdo::Result::Ok((2, StrMap::new()));
+        };
+
+        let request = AppRequest {
+            title: String::from("Open With"),
+            description: describe_target(&options, content_type.as_deref()),
+            parent: ParentWindow::parse(parent_window),
+            choices: AppChoices::new(choices.iter().map(|id| desktop::lookup(id)).collect()),
+            last_choice,
+            offer_always: always_use,
+            offer_other: self.config.app_chooser.other_applications,
+        };
+
+        self.open()
+            .insert(handle.to_string(), request.choices.clone());
+
+        let dialogs = self.dialogs.clone();
+
+        let dialog = show(move || dialogs.choose_application(&request));
+
+        let timeout = self.config.dialog.timeout();
+
+        let choice = request::run(conn, &handle, timeout, ticket.run(dialog)).await;
+
+        self.open().remove(handle.as_str());
+
+        let choice = match choice? {
+            Some(choice) => choice,
+            None => return zbus::fdo::Result::Ok((2, StrMap::new())),
+        };
+
+        match choice {
+            Some(response) => {
+                if let Some(content_type) = content_type.as_deref().filter(|_| always_use) {
+                    let always = response.always.then_some(response.id.as_str());
+
+                    state::set_always_use(content_type, always);
+                }
+
+                zbus::fdo::Result::Ok((0, choice_results(response.id, activation_token)))
+            }
+
+            None => zbus::fdo::Result::Ok((1, StrMap::new())),
+        }
     }
 
-    /// Interface for choosing an application.
+    /// Updates the list of applications shown by an open dialog.
     async fn update_choices(&self, handle: zvariant::ObjectPath<'_>, choices: Vec<&str>) {
         log::info!("update_choices({}, {:?})", handle, choices);
+
+        let Some(list) = self.open().get(handle.as_str()).cloned() else {
+            log::warn!("no open dialog {} to update", handle);
+            return;
+        };
+
+        list.set(choices.iter().map(|id| desktop::lookup(id)).collect());
     }
 }
 
 /// FileChooser implements the org.freedesktop.impl.portal.FileChooser interface.
-pub struct FileChooser {}
+pub struct FileChooser {
+    pub config: Arc<Config>,
+    pub dialogs: Arc<dyn DialogProvider>,
+    pub scheduler: Arc<Scheduler>,
+    pub audit: Arc<Audit>,
+}
 
 #[dbus_interface(name = "org.freedesktop.impl.portal.FileChooser")]
 impl FileChooser {
+    /// The version of the interface this backend implements.
+    #[dbus_interface(property, name = "version")]
+    fn version(&self) -> u32 {
+        4
+    }
+
     /// Presents a file chooser dialog to the user to open one or more files.
     #[dbus_interface(out_args("response", "results"))]
     async fn open_file(
         &self,
+        #[zbus(connection)] conn: &zbus::Connection,
         handle: zvariant::ObjectPath<'_>,
         app_id: &str,
         parent_window: &str,
         title: &str,
         options: StrMap<'_>,
     ) -> zbus::fdo::Result<(u32, StrMap<'_>)> {
+        let result = self
+            .open_file_dialog(conn, &handle, app_id, parent_window, title, options)
+            .await;
+
+        self.audit(app_id, "OpenFile", &result);
+
+        result
+    }
+
+    /// Presents a file chooser dialog to the user to save a file.
+    #[dbus_interface(out_args("response", "results"))]
+    async fn save_file(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        parent_window: &str,
+        title: &str,
+        options: StrMap<'_>,
+    ) -> zbus::fdo::Result<(u32, StrMap<'_>)> {
+        let result = self
+            .save_file_dialog(conn, &handle, app_id, parent_window, title, options)
+            .await;
+
+        self.audit(app_id, "SaveFile", &result);
+
+        result
+    }
+
+    /// Asks for a folder as a location to save one or more files.
+    #[dbus_interface(out_args("response", "results"))]
+    async fn save_files(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        parent_window: &str,
+        title: &str,
+        options: StrMap<'_>,
+    ) -> zbus::fdo::Result<(u32, StrMap<'_>)> {
+        let result = self
+            .save_files_dialog(conn, &handle, app_id, parent_window, title, options)
+            .await;
+
+        self.audit(app_id, "SaveFiles", &result);
+
+        result
+    }
+}
+
+impl FileChooser {
+    /// Show an OpenFile dialog and turn the user's answer into a response.
+    async fn open_file_dialog(
+        &self,
+        conn: &zbus::Connection,
+        handle: &zvariant::ObjectPath<'_>,
+        app_id: &str,
+        parent_window: &str,
+        title: &str,
+        options: StrMap<'_>,
+    ) -> zbus::fdo::Result<(u32, StrMap<'static>)> {
         log::info!(
             "open_file({}, {}, {}, {})",
             handle,
@@ -60,65 +516,114 @@ impl FileChooser {
             title
         );
 
-        let multiple = matches!(options.get("multiple"), Some(zvariant::Value::Bool(true)));
+        let Some(ticket) = self.scheduler.ticket(app_id) else {
+            log::warn!("rejecting {}, {} already has a dialog open", handle, app_id);
+            return zbus::fdo::Result::Ok((2, StrMap::new()));
+        };
 
-        let directory = matches!(options.get("directory"), Some(zvariant::Value::Bool(true)));
+        let writable = matches!(options.get("writable"), Some(zvariant::Value::Bool(true)));
 
-        let dialog = rfd::FileDialog::new().set_title(title);
+        let current_filter = parse_current_filter(&options);
 
-        if multiple {
-            let choices = match directory {
-                false => dialog.pick_files(),
-                true => dialog.pick_folders(),
-            };
+        let request = FileRequest {
+            multiple: matches!(options.get("multiple"), Some(zvariant::Value::Bool(true))),
+            directory: matches!(options.get("directory"), Some(zvariant::Value::Bool(true))),
+            folder: parse_current_folder(&options).or_else(|| state::last_directory(app_id)),
+            filters: filter::order(parse_filters(&options), current_filter.as_ref()),
+            ..file_request(title, parent_window, &options)
+        };
 
-            match choices {
-                Some(paths) => {
-                    let uris = pathbuf_to_file_uri(paths)
-                        .map_err(|e| zbus::fdo::Error::Failed(e.to_string()))?;
+        let rules = Rules::new(&self.config.policy, app_id);
 
-                    let mut results = StrMap::new();
+        let export = self.export_for(app_id);
 
-                    results.insert("uris", zvariant::Array::from(uris).into());
+        let remote = self.config.file_chooser.remote_uris && export.is_none();
 
-                    zbus::fdo::Result::Ok((0, results))
-                }
+        let access = self.access(conn, app_id, request.parent.clone(), "open files");
+
+        let dialogs = self.dialogs.clone();
+
+        let caller = app_id.to_owned();
+
+        let dialog = show(move || {
+            let response = dialogs.open_file(&request)?;
+
+            // The answer to a request that ended is discarded, along with what it'd do.
+            if request::ended() {
+                return None;
+            }
+
+            let response = with_choices(&*dialogs, &request, response);
+
+            if request::ended() {
+                return None;
+            }
 
-                None => zbus::fdo::Result::Ok((1, StrMap::new())),
+            if let Some(parent) = response.paths.first().and_then(|path| path.parent()) {
+                state::set_last_directory(&caller, parent);
             }
-        } else {
-            let choice = match directory {
-                false => dialog.pick_file(),
-                true => dialog.pick_folder(),
+
+            Some((request, response))
+        });
+
+        let timeout = self.config.dialog.timeout();
+
+        let choice =
+            match request::run(conn, handle, timeout, ticket.run(gated(access, dialog))).await? {
+                Some(choice) => choice,
+                None => return zbus::fdo::Result::Ok((2, StrMap::new())),
             };
 
-            match choice {
-                Some(path) => {
-                    let uris = pathbuf_to_file_uri(vec![path])
-                        .map_err(|e| zbus::fdo::Error::Failed(e.to_string()))?;
+        match choice {
+            Some((request, response)) => {
+                if !self.permitted(&rules, &request, &response.paths).await? {
+                    return zbus::fdo::Result::Ok((2, StrMap::new()));
+                }
+
+                let current_filter = response
+                    .current_filter
+                    .or_else(|| filter::guess(&response.paths, &request.filters, current_filter));
+
+                let paths =
+                    resolve::symlinks(self.config.file_chooser.resolve_symlinks, response.paths);
 
-                    let mut results = StrMap::new();
+                let recent = self.config.file_chooser.recent_files.then(|| paths.clone());
 
-                    results.insert("uris", zvariant::Array::from(uris).into());
+                let metadata = self.metadata(&paths);
 
-                    zbus::fdo::Result::Ok((0, results))
+                let Some(paths) = export_documents(conn, export, paths, writable).await else {
+                    return zbus::fdo::Result::Ok((2, StrMap::new()));
+                };
+
+                if let Some(recent) = recent {
+                    recent::add(app_id, &recent);
                 }
 
-                None => zbus::fdo::Result::Ok((1, StrMap::new())),
+                let results = Results {
+                    current_filter,
+                    choices: response.choices,
+                    writable: Some(writable),
+                    uris: pathbuf_to_uri(paths, remote),
+                    metadata,
+                };
+
+                zbus::fdo::Result::Ok((0, results.into_map()))
             }
+
+            None => zbus::fdo::Result::Ok((1, StrMap::new())),
         }
     }
 
-    /// Presents a file chooser dialog to the user to save a file.
-    #[dbus_interface(out_args("response", "results"))]
-    async fn save_file(
+    /// Show a SaveFile dialog and turn the user's answer into a response.
+    async fn save_file_dialog(
         &self,
-        handle: zvariant::ObjectPath<'_>,
+        conn: &zbus::Connection,
+        handle: &zvariant::ObjectPath<'_>,
         app_id: &str,
         parent_window: &str,
         title: &str,
         options: StrMap<'_>,
-    ) -> zbus::fdo::Result<(u32, StrMap<'_>)> {
+    ) -> zbus::fdo::Result<(u32, StrMap<'static>)> {
         log::info!(
             "save_file({}, {}, {}, {})",
             handle,
@@ -127,44 +632,184 @@ impl FileChooser {
             title
         );
 
+        if self.config.lockdown.disable_save_to_disk {
+            log::warn!("rejecting {}, saving to disk is locked down", handle);
+            return zbus::fdo::Result::Ok((2, StrMap::new()));
+        }
+
+        let Some(ticket) = self.scheduler.ticket(app_id) else {
+            log::warn!("rejecting {}, {} already has a dialog open", handle, app_id);
+            return zbus::fdo::Result::Ok((2, StrMap::new()));
+        };
+
         if let Some(zvariant::Value::Bool(true)) = options.get("multiple") {
             return zbus::fdo::Result::Err(zbus::fdo::Error::NotSupported(String::from(
                 "multiple save not supported",
             )));
         };
 
-        let mut dialog = rfd::FileDialog::new().set_title(title);
+        let current_filter = parse_current_filter(&options);
 
-        if let Some(zvariant::Value::Str(current_name)) = options.get("current_name") {
-            dialog = dialog.set_file_name(current_name);
+        let mut request = FileRequest {
+            folder: parse_current_folder(&options).or_else(|| self.save_directory(app_id)),
+            file_name: match options.get("current_name") {
+                Some(zvariant::Value::Str(current_name)) => Some(current_name.to_string()),
+                _ => None,
+            },
+            filters: filter::order(parse_filters(&options), current_filter.as_ref()),
+            ..file_request(title, parent_window, &options)
+        };
+
+        // `current_file` is the file being re-saved, so it overrides the folder and name.
+        if let Some(file) = parse_path(&options, "current_file") {
+            if let Some(parent) = file.parent().filter(|parent| parent.is_dir()) {
+                request.folder = Some(parent.to_path_buf());
+            }
+
+            if let Some(name) = file.file_name() {
+                request.file_name = Some(name.to_string_lossy().into_owned());
+            }
         }
 
-        match dialog.save_file() {
-            Some(path) => {
-                let uris = pathbuf_to_file_uri(vec![path])
-                    .map_err(|e| zbus::fdo::Error::Failed(e.to_string()))?;
+        let confirm_overwrite = self.config.file_chooser.confirm_overwrite;
+
+        let trash_on_overwrite = self.config.file_chooser.trash_on_overwrite;
 
-                let mut results = StrMap::new();
+        let rules = Rules::new(&self.config.policy, app_id);
 
-                results.insert("uris", zvariant::Array::from(uris).into());
+        let export = self.export_for(app_id);
 
-                zbus::fdo::Result::Ok((0, results))
+        let remote = self.config.file_chooser.remote_uris && export.is_none();
+
+        let access = self.access(conn, app_id, request.parent.clone(), "save a file");
+
+        let dialogs = self.dialogs.clone();
+
+        let caller = app_id.to_owned();
+
+        let policy = rules.clone();
+
+        let dialog = show(move || {
+            let response = loop {
+                let response = dialogs.save_file(&request)?;
+
+                // The answer to a request that ended is discarded, along with what it'd do.
+                if request::ended() {
+                    return None;
+                }
+
+                let path = response.paths.first()?;
+
+                if path.is_dir() {
+                    dialogs.message(&Message {
+                        title: request.title.clone(),
+                        description: format!("\"{}\" is a folder.", path.display()),
+                        level: Level::Error,
+                        parent: request.parent.clone(),
+                        ..Message::default()
+                    });
+                } else if !path.exists()
+                    // Denied targets are rejected below; don't offer to replace them.
+                    || policy.denied(&response.paths).is_some()
+                    || !confirm_overwrite
+                    || confirm_replace(&*dialogs, &request, path)
+                {
+                    break response;
+                }
+
+                // Reopen the dialog where the user left it so they can pick another name.
+                request.folder = path.parent().map(std::path::Path::to_path_buf);
+                request.file_name = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned());
+            };
+
+            let response = with_choices(&*dialogs, &request, response);
+
+            if request::ended() {
+                return None;
+            }
+
+            if let Some(parent) = response.paths.first().and_then(|path| path.parent()) {
+                state::set_last_directory(&caller, parent);
+            }
+
+            // The file being replaced is only trashed once the app is sure to get the new one.
+            let replaced = response
+                .paths
+                .first()
+                .filter(|path| trash_on_overwrite && path.is_file())
+                .cloned();
+
+            Some((request, response, replaced))
+        });
+
+        let timeout = self.config.dialog.timeout();
+
+        let choice =
+            match request::run(conn, handle, timeout, ticket.run(gated(access, dialog))).await? {
+                Some(choice) => choice,
+                None => return zbus::fdo::Result::Ok((2, StrMap::new())),
+            };
+
+        match choice {
+            Some((request, response, replaced)) => {
+                if !self.permitted(&rules, &request, &response.paths).await? {
+                    return zbus::fdo::Result::Ok((2, StrMap::new()));
+                }
+
+                let current_filter = response
+                    .current_filter
+                    .or_else(|| filter::guess(&response.paths, &request.filters, current_filter));
+
+                let paths =
+                    resolve::symlinks(self.config.file_chooser.resolve_symlinks, response.paths);
+
+                let recent = self.config.file_chooser.recent_files.then(|| paths.clone());
+
+                let metadata = self.metadata(&paths);
+
+                let Some(paths) = export_documents(conn, export, paths, true).await else {
+                    return zbus::fdo::Result::Ok((2, StrMap::new()));
+                };
+
+                if let Some(replaced) = replaced {
+                    let dialogs = self.dialogs.clone();
+
+                    if !show(move || move_to_trash(&*dialogs, &request, &replaced)).await? {
+                        return zbus::fdo::Result::Ok((2, StrMap::new()));
+                    }
+                }
+
+                if let Some(recent) = recent {
+                    recent::add(app_id, &recent);
+                }
+
+                let results = Results {
+                    current_filter,
+                    choices: response.choices,
+                    uris: pathbuf_to_uri(paths, remote),
+                    metadata,
+                    ..Results::default()
+                };
+
+                zbus::fdo::Result::Ok((0, results.into_map()))
             }
 
             None => zbus::fdo::Result::Ok((1, StrMap::new())),
         }
     }
 
-    /// Asks for a folder as a location to save one or more files.
-    #[dbus_interface(out_args("response", "results"))]
-    async fn save_files(
+    /// Show a SaveFiles dialog and turn the user's answer into a response.
+    async fn save_files_dialog(
         &self,
-        handle: zvariant::ObjectPath<'_>,
+        conn: &zbus::Connection,
+        handle: &zvariant::ObjectPath<'_>,
         app_id: &str,
         parent_window: &str,
         title: &str,
-        _options: StrMap<'_>,
-    ) -> zbus::fdo::Result<(u32, StrMap<'_>)> {
+        options: StrMap<'_>,
+    ) -> zbus::fdo::Result<(u32, StrMap<'static>)> {
         log::info!(
             "save_files({}, {}, {}, {})",
             handle,
@@ -173,36 +818,718 @@ impl FileChooser {
             title
         );
 
-        match rfd::FileDialog::new().set_title(title).pick_folder() {
-            Some(path) => {
-                let uris = pathbuf_to_file_uri(vec![path])
-                    .map_err(|e| zbus::fdo::Error::Failed(e.to_string()))?;
+        if self.config.lockdown.disable_save_to_disk {
+            log::warn!("rejecting {}, saving to disk is locked down", handle);
+            return zbus::fdo::Result::Ok((2, StrMap::new()));
+        }
+
+        let Some(ticket) = self.scheduler.ticket(app_id) else {
+            log::warn!("rejecting {}, {} already has a dialog open", handle, app_id);
+            return zbus::fdo::Result::Ok((2, StrMap::new()));
+        };
+
+        let request = FileRequest {
+            folder: parse_current_folder(&options).or_else(|| self.save_directory(app_id)),
+            ..file_request(title, parent_window, &options)
+        };
+
+        // Each file has a target in the results, so the request fails on one without a name.
+        let files: Option<Vec<_>> = parse_paths(&options, "files")
+            .iter()
+            .map(|file| file.file_name().map(std::ffi::OsStr::to_os_string))
+            .collect();
+
+        let Some(files) = files else {
+            log::warn!("rejecting {}, one of the files has no name", handle);
+            return zbus::fdo::Result::Ok((2, StrMap::new()));
+        };
 
-                let mut results = StrMap::new();
+        let rules = Rules::new(&self.config.policy, app_id);
 
-                results.insert("uris", zvariant::Array::from(uris).into());
+        let export = self.export_for(app_id);
 
-                zbus::fdo::Result::Ok((0, results))
+        let remote = self.config.file_chooser.remote_uris && export.is_none();
+
+        let access = self.access(conn, app_id, request.parent.clone(), "save files");
+
+        let dialogs = self.dialogs.clone();
+
+        let app_id = app_id.to_owned();
+
+        let dialog = show(move || {
+            let folder = dialogs.pick_folder(&request)?;
+
+            // The answer to a request that ended is discarded, along with what it'd do.
+            if request::ended() {
+                return None;
+            }
+
+            state::set_last_directory(&app_id, &folder);
+
+            let targets = match files.is_empty() {
+                true => vec![folder],
+                false => compose_targets(&*dialogs, &request, &folder, &files),
+            };
+
+            Some((request, targets))
+        });
+
+        let timeout = self.config.dialog.timeout();
+
+        let choice =
+            match request::run(conn, handle, timeout, ticket.run(gated(access, dialog))).await? {
+                Some(choice) => choice,
+                None => return zbus::fdo::Result::Ok((2, StrMap::new())),
+            };
+
+        match choice {
+            Some((request, targets)) => {
+                if !self.permitted(&rules, &request, &targets).await? {
+                    return zbus::fdo::Result::Ok((2, StrMap::new()));
+                }
+
+                let targets = resolve::symlinks(self.config.file_chooser.resolve_symlinks, targets);
+
+                let metadata = self.metadata(&targets);
+
+                let Some(targets) = export_documents(conn, export, targets, true).await else {
+                    return zbus::fdo::Result::Ok((2, StrMap::new()));
+                };
+
+                let results = Results {
+                    uris: pathbuf_to_uri(targets, remote),
+                    metadata,
+                    ..Results::default()
+                };
+
+                zbus::fdo::Result::Ok((0, results.into_map()))
             }
 
             None => zbus::fdo::Result::Ok((1, StrMap::new())),
         }
     }
+
+    /// Record the outcome of a FileChooser call in the audit log.
+    fn audit(
+        &self,
+        app_id: &str,
+        method: &str,
+        result: &zbus::fdo::Result<(u32, StrMap<'static>)>,
+    ) {
+        let (outcome, uris) = match result {
+            Ok((0, results)) => (Outcome::Chosen, results_uris(results)),
+            Ok((1, _)) => (Outcome::Cancelled, Vec::new()),
+            _ => (Outcome::Failed, Vec::new()),
+        };
+
+        self.audit.record(app_id, method, outcome, &uris);
+    }
+
+    /// Get the folder to save in when the app doesn't ask for one.
+    ///
+    /// The configured folder for the app wins over the one it last saved in.
+    fn save_directory(&self, app_id: &str) -> Option<std::path::PathBuf> {
+        let configured = self
+            .config
+            .file_chooser
+            .save_directories
+            .get(app_id)
+            .map(|folder| policy::expand(folder))
+            .filter(|folder| {
+                let exists = folder.is_dir();
+
+                if !exists {
+                    log::warn!(
+                        "save directory {:?} of {} is not a directory",
+                        folder,
+                        app_id
+                    );
+                }
+
+                exists
+            });
+
+        configured.or_else(|| state::last_directory(app_id))
+    }
+
+    /// Ask the user whether `app_id` may show a file dialog to `action`, if the config wants that.
+    ///
+    /// "Always Allow" is kept in the permission store, so the app isn't asked about again.
+    async fn access(
+        &self,
+        conn: &zbus::Connection,
+        app_id: &str,
+        parent: Option<ParentWindow>,
+        action: &str,
+    ) -> zbus::fdo::Result<bool> {
+        // Host apps have no id to name them by or to remember the answer for.
+        if !self.config.file_chooser.access_prompt || app_id.is_empty() {
+            return Ok(true);
+        }
+
+        match permissions::lookup(conn, PERMISSION_TABLE, PERMISSION_ID, app_id).await {
+            Ok(permissions) if permissions.iter().any(|p| p == "yes") => return Ok(true),
+
+            Ok(_) => {}
+
+            Err(e) => log::warn!("failed to look up the permission of {}: {}", app_id, e),
+        }
+
+        let message = Message {
+            title: String::from("File Access"),
+            description: format!("{} wants to {}.", app_id, action),
+            parent,
+            ..Message::default()
+        };
+
+        let options = ["Allow", "Always Allow", "Deny"].map(String::from);
+
+        let dialogs = self.dialogs.clone();
+
+        let answer = show(move || dialogs.choose(&message, &options)).await?;
+
+        if answer == Some(1) {
+            let permissions = ["yes"];
+
+            if let Err(e) =
+                permissions::set(conn, PERMISSION_TABLE, PERMISSION_ID, app_id, &permissions).await
+            {
+                log::warn!("failed to store the permission of {}: {}", app_id, e);
+            }
+        }
+
+        Ok(matches!(answer, Some(0 | 1)))
+    }
+
+    /// Describe each chosen file for the metadata extension, if the config enables it.
+    fn metadata(&self, paths: &[std::path::PathBuf]) -> Option<Vec<StrMap<'static>>> {
+        self.config
+            .file_chooser
+            .metadata
+            .then(|| paths.iter().map(|path| file_metadata(path)).collect())
+    }
+
+    /// Get the app id to export chosen files to, if the caller is sandboxed.
+    fn export_for(&self, app_id: &str) -> Option<String> {
+        (self.config.file_chooser.export_documents && documents::is_sandboxed(app_id))
+            .then(|| app_id.to_owned())
+    }
+
+    /// Check the chosen paths against the policy, telling the user if one of them isn't allowed.
+    async fn permitted(
+        &self,
+        rules: &Rules,
+        request: &FileRequest,
+        paths: &[std::path::PathBuf],
+    ) -> zbus::fdo::Result<bool> {
+        let Some(path) = rules.denied(paths) else {
+            return Ok(true);
+        };
+
+        log::warn!("{:?} is outside the path policy", path);
+
+        let message = Message {
+            title: request.title.clone(),
+            description: format!(
+                "\"{}\" is in a location you aren't allowed to use.",
+                path.display()
+            ),
+            level: Level::Error,
+            parent: request.parent.clone(),
+            ..Message::default()
+        };
+
+        let dialogs = self.dialogs.clone();
+
+        show(move || dialogs.message(&message)).await?;
+
+        Ok(false)
+    }
+}
+
+/// `Results` is the results vardict of a successful FileChooser call.
+///
+/// Optional keys are only included when the caller sent the corresponding option.
+#[derive(Debug, Default)]
+struct Results {
+    uris: Vec<String>,
+    current_filter: Option<Filter>,
+    choices: Option<Vec<(String, String)>>,
+    writable: Option<bool>,
+
+    /// The metadata of the file behind each URI, if the extension is enabled.
+    metadata: Option<Vec<StrMap<'static>>>,
+}
+
+impl Results {
+    /// Convert the results to the vardict sent over dbus.
+    fn into_map(self) -> StrMap<'static> {
+        let mut results = StrMap::new();
+
+        results.insert("uris", zvariant::Array::from(self.uris.clone()).into());
+
+        if let Some(filter) = self.current_filter {
+            results.insert("current_filter", zvariant::Value::from(filter));
+        }
+
+        if let Some(choices) = self.choices {
+            results.insert("choices", zvariant::Array::from(choices).into());
+        }
+
+        if let Some(writable) = self.writable {
+            results.insert("writable", writable.into());
+        }
+
+        if let Some(metadata) = self.metadata {
+            let metadata: std::collections::HashMap<String, StrMap<'static>> =
+                self.uris.iter().cloned().zip(metadata).collect();
+
+            results.insert(METADATA_KEY, metadata.into());
+        }
+
+        results
+    }
+}
+
+/// Get the size, modification time and MIME type of a file.
+///
+/// SaveFile targets often don't exist yet, so they only get the MIME type guessed from their name.
+fn file_metadata(path: &std::path::Path) -> StrMap<'static> {
+    let mut metadata = StrMap::new();
+
+    if let Ok(stat) = std::fs::metadata(path) {
+        metadata.insert("size", stat.len().into());
+
+        let modified = stat
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok());
+
+        if let Some(modified) = modified {
+            metadata.insert("mtime", modified.as_secs().into());
+        }
+    }
+
+    let mime_type = match path.is_dir() {
+        true => "inode/directory",
+        false => mime_guess::from_path(path)
+            .first_raw()
+            .unwrap_or("application/octet-stream"),
+    };
+
+    metadata.insert("mime_type", mime_type.into());
+
+    metadata
+}
+
+/// Build the results of a ChooseApplication call that picked `choice`.
+///
+/// The choice is always a desktop file id, also for apps picked outside the caller's choices,
+/// so the frontend launches it like any other with the activation token passed back.
+fn choice_results(choice: String, activation_token: Option<String>) -> StrMap<'static> {
+    let mut results = StrMap::new();
+
+    results.insert("choice", choice.into());
+
+    if let Some(token) = activation_token {
+        results.insert("activation_token", token.into());
+    }
+
+    results
+}
+
+/// Get the content type an AppChooser call is about from its hints.
+///
+/// Without a `content_type` it's guessed from the `filename`, or taken from the scheme
+/// of a non-file `uri` the way GIO names URI handlers.
+fn parse_content_type(options: &StrMap<'_>) -> Option<String> {
+    let hint = |key: &str| match options.get(key) {
+        Some(zvariant::Value::Str(value)) if !value.is_empty() => Some(value.as_str()),
+        _ => None,
+    };
+
+    if let Some(content_type) = hint("content_type") {
+        return Some(content_type.to_owned());
+    }
+
+    if let Some(filename) = hint("filename") {
+        return mime_guess::from_path(filename)
+            .first_raw()
+            .map(str::to_owned);
+    }
+
+    let uri = hint("uri")?;
+
+    match uri.split_once(':')? {
+        ("file", _) => mime_guess::from_path(uri).first_raw().map(str::to_owned),
+        (scheme, _) => Some(format!("x-scheme-handler/{}", scheme.to_lowercase())),
+    }
+}
+
+/// Describe what an AppChooser call is choosing an application for, from its hints.
+fn describe_target(options: &StrMap<'_>, content_type: Option<&str>) -> String {
+    let hint = |key: &str| match options.get(key) {
+        Some(zvariant::Value::Str(value)) if !value.is_empty() => Some(value.to_string()),
+        _ => None,
+    };
+
+    // Local files are named by their file name, other locations by their whole URI.
+    let target = hint("filename").or_else(|| {
+        let uri = hint("uri")?;
+
+        match uri::file_path(&uri) {
+            Some(path) => Some(path.file_name()?.to_string_lossy().into_owned()),
+            None => Some(uri),
+        }
+    });
+
+    let kind = content_type.and_then(mime::description);
+
+    match (target, kind) {
+        (Some(target), Some(kind)) => {
+            format!("Choose an application to open \"{}\" ({}).", target, kind)
+        }
+
+        (Some(target), None) => format!("Choose an application to open \"{}\".", target),
+
+        (None, Some(kind)) => format!("Choose an application to open the {}.", kind),
+
+        (None, None) => String::from("Choose an application."),
+    }
+}
+
+/// Get the URIs of a successful FileChooser call from its results.
+fn results_uris(results: &StrMap<'_>) -> Vec<String> {
+    let Some(zvariant::Value::Array(uris)) = results.get("uris") else {
+        return Vec::new();
+    };
+
+    uris.iter()
+        .filter_map(|uri| match uri {
+            zvariant::Value::Str(uri) => Some(uri.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Run a blocking dialog on tokio's blocking thread pool so other requests keep being served.
+async fn show<T, F>(dialog: F) -> zbus::fdo::Result<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let (ended, turn) = (request::Ended::current(), Turn::current());
+
+    tokio::task::spawn_blocking(move || {
+        // The app's turn lasts until its dialog closes, even if the request ended before.
+        let _turn = turn;
+
+        ended.show(dialog)
+    })
+    .await
+    .map_err(|e| zbus::fdo::Error::Failed(e.to_string()))
+}
+
+/// Name the app behind a request for people.
+fn requester(app_id: &str) -> String {
+    match app_id {
+        "" => String::from("An application"),
+        app_id => desktop::lookup(app_id).name,
+    }
+}
+
+/// Run `dialog` once `access` is granted, treating a denial like a cancelled dialog.
+async fn gated<T>(
+    access: impl std::future::Future<Output = zbus::fdo::Result<bool>>,
+    dialog: impl std::future::Future<Output = zbus::fdo::Result<Option<T>>>,
+) -> zbus::fdo::Result<Option<T>> {
+    match access.await? {
+        true => dialog.await,
+        false => Ok(None),
+    }
+}
+
+/// Export `paths` to the sandbox of `app_id`, or return them unchanged if there's none.
+///
+/// Returns `None` if the export failed, as host paths are useless inside the sandbox.
+async fn export_documents(
+    conn: &zbus::Connection,
+    app_id: Option<String>,
+    paths: Vec<std::path::PathBuf>,
+    writable: bool,
+) -> Option<Vec<std::path::PathBuf>> {
+    let Some(app_id) = app_id else {
+        return Some(paths);
+    };
+
+    match documents::export(conn, &app_id, &paths, writable).await {
+        Ok(paths) => Some(paths),
+        Err(e) => {
+            log::error!("failed to export {:?} to {}: {}", paths, app_id, e);
+            None
+        }
+    }
 }
 
-/// Convert one or more PathBuf to URI file strings.
-fn pathbuf_to_file_uri(paths: Vec<std::path::PathBuf>) -> Result<Vec<String>, http::Error> {
+/// Build the parts of a file request shared by every FileChooser method.
+fn file_request(title: &str, parent_window: &str, options: &StrMap<'_>) -> FileRequest {
+    FileRequest {
+        title: title.to_owned(),
+        accept_label: parse_accept_label(options),
+        parent: ParentWindow::parse(parent_window),
+        modal: !matches!(options.get("modal"), Some(zvariant::Value::Bool(false))),
+        choices: choices::parse(options.get("choices")),
+        ..FileRequest::default()
+    }
+}
+
+/// Fill in the choices of a response whose provider didn't render them.
+fn with_choices(
+    dialogs: &dyn DialogProvider,
+    request: &FileRequest,
+    mut response: FileResponse,
+) -> FileResponse {
+    if response.choices.is_none() && !request.choices.is_empty() {
+        response.choices = Some(choices::prompt(dialogs, request));
+    }
+
+    response
+}
+
+/// Parse the `accept_label` option, removing its mnemonic underscore.
+fn parse_accept_label(options: &StrMap<'_>) -> Option<String> {
+    match options.get("accept_label") {
+        Some(zvariant::Value::Str(label)) => Some(choices::strip_mnemonic(label)),
+        _ => None,
+    }
+}
+
+/// Parse a path option such as `current_folder`, which is sent as a NUL-terminated byte array.
+fn parse_path(options: &StrMap<'_>, key: &str) -> Option<std::path::PathBuf> {
+    let bytes = Vec::<u8>::try_from(options.get(key)?.clone()).ok()?;
+
+    bytes_to_path(&bytes)
+}
+
+/// Parse a list of paths option such as `files`, which is sent as an array of byte arrays.
+fn parse_paths(options: &StrMap<'_>, key: &str) -> Vec<std::path::PathBuf> {
+    options
+        .get(key)
+        .and_then(|value| Vec::<Vec<u8>>::try_from(value.clone()).ok())
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|bytes| bytes_to_path(bytes))
+        .collect()
+}
+
+/// Convert a NUL-terminated byte array to a path.
+fn bytes_to_path(bytes: &[u8]) -> Option<std::path::PathBuf> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let bytes = bytes.strip_suffix(&[0]).unwrap_or(bytes);
+
+    (!bytes.is_empty()).then(|| std::ffi::OsStr::from_bytes(bytes).into())
+}
+
+/// Ask the user whether to replace an existing file.
+fn confirm_replace(
+    dialogs: &dyn DialogProvider,
+    request: &FileRequest,
+    path: &std::path::Path,
+) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+
+    dialogs.confirm(&Message {
+        title: request.title.clone(),
+        description: format!(
+            "A file named \"{}\" already exists. Do you want to replace it?",
+            name
+        ),
+        level: Level::Warning,
+        parent: request.parent.clone(),
+        accept_label: Some(String::from("Replace")),
+        reject_label: Some(String::from("Cancel")),
+    })
+}
+
+/// Move a file about to be overwritten to the trash, so the overwrite can be undone.
+///
+/// If that fails, the user is told and the file is left alone, so the save fails.
+fn move_to_trash(
+    dialogs: &dyn DialogProvider,
+    request: &FileRequest,
+    path: &std::path::Path,
+) -> bool {
+    let Err(e) = trash::delete(path) else {
+        log::info!("moved {:?} to the trash", path);
+        return true;
+    };
+
+    log::warn!("failed to move {:?} to the trash: {}", path, e);
+
+    dialogs.message(&Message {
+        title: request.title.clone(),
+        description: format!(
+            "\"{}\" couldn't be moved to the trash, so it wasn't replaced.",
+            path.display()
+        ),
+        level: Level::Error,
+        parent: request.parent.clone(),
+        ..Message::default()
+    });
+
+    false
+}
+
+/// Compose the target path of each proposed file name inside the chosen folder, in order.
+///
+/// If any target already exists, the user decides whether to replace the existing files
+/// or to rename the new ones to the next free name.
+fn compose_targets(
+    dialogs: &dyn DialogProvider,
+    request: &FileRequest,
+    folder: &std::path::Path,
+    files: &[std::ffi::OsString],
+) -> Vec<std::path::PathBuf> {
+    let targets: Vec<_> = files.iter().map(|name| folder.join(name)).collect();
+
+    let existing = targets.iter().filter(|target| target.exists()).count();
+
+    if existing == 0 {
+        return targets;
+    }
+
+    let replace = dialogs.confirm(&Message {
+        title: request.title.clone(),
+        description: format!(
+            "{} of the files already exist in {}.",
+            existing,
+            folder.display()
+        ),
+        level: Level::Warning,
+        parent: request.parent.clone(),
+        accept_label: Some(String::from("Replace")),
+        reject_label: Some(String::from("Keep Both")),
+    });
+
+    if replace {
+        return targets;
+    }
+
+    let mut renamed: Vec<std::path::PathBuf> = Vec::with_capacity(targets.len());
+
+    for target in targets {
+        let mut candidate = target.clone();
+
+        // Also skip names already taken by an earlier file of the same request.
+        for n in 1.. {
+            if !candidate.exists() && !renamed.contains(&candidate) {
+                break;
+            }
+
+            candidate = numbered_path(&target, n);
+        }
+
+        renamed.push(candidate);
+    }
+
+    renamed
+}
+
+/// Insert a counter before the extension, turning `name.ext` into `name (n).ext`.
+///
+/// The extension starts at the first dot after the leading ones, so `archive.tar.gz` becomes
+/// `archive (n).tar.gz` and `.bashrc` becomes `.bashrc (n)`.
+fn numbered_path(path: &std::path::Path, n: usize) -> std::path::PathBuf {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    let name = path.file_name().unwrap_or_default().as_bytes();
+
+    let leading = name.iter().take_while(|&&b| b == b'.').count();
+
+    let split = name[leading..]
+        .iter()
+        .position(|&b| b == b'.')
+        .map_or(name.len(), |dot| leading + dot);
+
+    let (stem, extension) = name.split_at(split);
+
+    let mut numbered = OsStr::from_bytes(stem).to_os_string();
+
+    numbered.push(format!(" ({})", n));
+    numbered.push(OsStr::from_bytes(extension));
+
+    path.with_file_name(numbered)
+}
+
+/// Parse the `current_folder` option, ignoring folders that don't exist.
+fn parse_current_folder(options: &StrMap<'_>) -> Option<std::path::PathBuf> {
+    let folder = parse_path(options, "current_folder")?;
+
+    if !folder.is_dir() {
+        log::warn!("current_folder {:?} is not a directory", folder);
+
+        return None;
+    }
+
+    Some(folder)
+}
+
+/// Parse the `filters` option, which has the dbus signature `a(sa(us))`.
+fn parse_filters(options: &StrMap<'_>) -> Vec<Filter> {
+    options
+        .get("filters")
+        .and_then(|value| Vec::<Filter>::try_from(value.clone()).ok())
+        .unwrap_or_default()
+}
+
+/// Parse the `current_filter` option, which has the dbus signature `(sa(us))`.
+fn parse_current_filter(options: &StrMap<'_>) -> Option<Filter> {
+    options
+        .get("current_filter")
+        .and_then(|value| Filter::try_from(value.clone()).ok())
+}
+
+/// Convert one or more PathBuf to URI strings.
+///
+/// With `remote`, paths inside GVFS mounts become URIs of the remote location they refer to.
+fn pathbuf_to_uri(paths: Vec<std::path::PathBuf>, remote: bool) -> Vec<String> {
     log::debug!("pathbuf_to_uri({:?})", paths);
 
     paths
         .iter()
         .map(|path| {
-            http::Uri::builder()
-                .scheme("file")
-                .authority("localhost")
-                .path_and_query(path.to_string_lossy().as_ref())
-                .build()
-                .map(|uri| uri.to_string())
+            remote
+                .then(|| gvfs::remote_uri(path))
+                .flatten()
+                .unwrap_or_else(|| uri::file_uri(path))
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::numbered_path;
+
+    #[test]
+    fn numbered_paths() {
+        let numbered = |path: &str| numbered_path(Path::new(path), 2);
+
+        assert_eq!(
+            numbered("/tmp/report.pdf"),
+            PathBuf::from("/tmp/report (2).pdf")
+        );
+        assert_eq!(
+            numbered("/tmp/archive.tar.gz"),
+            PathBuf::from("/tmp/archive (2).tar.gz")
+        );
+        assert_eq!(numbered("/tmp/README"), PathBuf::from("/tmp/README (2)"));
+        assert_eq!(numbered("/tmp/.bashrc"), PathBuf::from("/tmp/.bashrc (2)"));
+        assert_eq!(
+            numbered("/tmp/.config.json"),
+            PathBuf::from("/tmp/.config (2).json")
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use std::sync::Arc;
+
+use zbus::{dbus_interface, zvariant};
+
+use super::{show, StrMap};
+use crate::{
+    audit::{Audit, Outcome},
+    choices,
+    config::Config,
+    desktop,
+    dialog::{AccessRequest, DialogProvider, Message},
+    request,
+    schedule::Scheduler,
+    window::ParentWindow,
+};
+
+/// Access implements the org.freedesktop.impl.portal.Access interface, which
+/// xdg-desktop-portal asks to let apps use the camera, microphone and other devices.
+pub struct Access {
+    pub config: Arc<Config>,
+    pub dialogs: Arc<dyn DialogProvider>,
+    pub scheduler: Arc<Scheduler>,
+    pub audit: Arc<Audit>,
+}
+
+#[dbus_interface(name = "org.freedesktop.impl.portal.Access")]
+impl Access {
+    /// Ask the user to grant the app what the frontend describes, with the app's icon unless
+    /// it names another one, returning the choices the user made if it's granted.
+    #[allow(clippy::too_many_arguments)]
+    #[dbus_interface(out_args("response", "results"))]
+    async fn access_dialog(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        parent_window: &str,
+        title: &str,
+        subtitle: &str,
+        body: &str,
+        options: StrMap<'_>,
+    ) -> zbus::fdo::Result<(u32, StrMap<'static>)> {
+        log::info!(
+            "access_dialog({}, {}, {}, {})",
+            handle,
+            app_id,
+            parent_window,
+            title
+        );
+
+        let mut access = parse_request(title, subtitle, body, &options);
+
+        access.message.parent = ParentWindow::parse(parent_window);
+
+        if access.icon.is_none() && !app_id.is_empty() {
+            access.icon = desktop::lookup(app_id).icon;
+        }
+
+        let Some(ticket) = self.scheduler.ticket(app_id) else {
+            log::warn!("rejecting {}, {} already has a dialog open", handle, app_id);
+            return zbus::fdo::Result::Ok((2, StrMap::new()));
+        };
+
+        let dialogs = self.dialogs.clone();
+
+        let dialog = show(move || dialogs.grant_access(&access));
+
+        let timeout = self.config.dialog.timeout();
+
+        let (response, results) =
+            match request::run(conn, &handle, timeout, ticket.run(dialog)).await? {
+                Some(Some(choices)) => {
+                    let mut results = StrMap::new();
+
+                    results.insert("choices", zvariant::Array::from(choices).into());
+
+                    (0, results)
+                }
+
+                Some(None) => (1, StrMap::new()),
+                None => (2, StrMap::new()),
+            };
+
+        let outcome = match response {
+            0 => Outcome::Chosen,
+            1 => Outcome::Cancelled,
+            _ => Outcome::Failed,
+        };
+
+        self.audit.record(app_id, "AccessDialog", outcome, &[]);
+
+        zbus::fdo::Result::Ok((response, results))
+    }
+}
+
+/// Read the dialog AccessDialog asks for, without its parent, from its arguments.
+fn parse_request(title: &str, subtitle: &str, body: &str, options: &StrMap<'_>) -> AccessRequest {
+    let label = |key: &str| match options.get(key) {
+        Some(zvariant::Value::Str(label)) => Some(choices::strip_mnemonic(label)),
+        _ => None,
+    };
+
+    let icon = match options.get("icon") {
+        Some(zvariant::Value::Str(icon)) if !icon.is_empty() => Some(icon.to_string()),
+        _ => None,
+    };
+
+    AccessRequest {
+        message: Message {
+            title: title.to_owned(),
+            description: body.to_owned(),
+            accept_label: Some(
+                label("grant_label").unwrap_or_else(|| String::from("Grant Access")),
+            ),
+            reject_label: Some(label("deny_label").unwrap_or_else(|| String::from("Deny Access"))),
+            ..Message::default()
+        },
+        subtitle: subtitle.to_owned(),
+        icon,
+        choices: choices::parse(options.get("choices")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zbus::zvariant;
+
+    use super::{parse_request, StrMap};
+
+    #[test]
+    fn requests() {
+        let choices = vec![(
+            String::from("remember"),
+            String::from("_Remember"),
+            Vec::<(String, String)>::new(),
+            String::from("true"),
+        )];
+
+        let mut options = StrMap::new();
+
+        options.insert("grant_label", zvariant::Value::from("_Allow"));
+        options.insert("icon", zvariant::Value::from("camera-web-symbolic"));
+        options.insert("choices", zvariant::Value::from(choices.clone()));
+
+        let request = parse_request(
+            "Turn On Camera?",
+            "An app wants to use your camera",
+            "Access can be changed in the settings.",
+            &options,
+        );
+
+        assert_eq!(request.message.title, "Turn On Camera?");
+        assert_eq!(request.subtitle, "An app wants to use your camera");
+        assert_eq!(request.message.accept_label.as_deref(), Some("Allow"));
+        assert_eq!(request.message.reject_label.as_deref(), Some("Deny Access"));
+        assert_eq!(request.icon.as_deref(), Some("camera-web-symbolic"));
+        assert_eq!(request.choices, choices);
+
+        let request = parse_request("", "", "", &StrMap::new());
+
+        assert_eq!(request.icon, None);
+        assert!(request.choices.is_empty());
+    }
+}
@@ -0,0 +1,212 @@
+use std::{path::PathBuf, sync::Arc};
+
+use zbus::{dbus_interface, zvariant};
+
+use super::{requester, show, StrMap};
+use crate::{
+    account::{self, User},
+    audit::{Audit, Outcome},
+    config::Config,
+    dialog::{AccountAnswer, AccountRequest, DialogProvider, FileRequest, Message},
+    documents, request,
+    schedule::Scheduler,
+    uri,
+    window::ParentWindow,
+};
+
+/// Account implements the org.freedesktop.impl.portal.Account interface.
+pub struct Account {
+    pub config: Arc<Config>,
+    pub dialogs: Arc<dyn DialogProvider>,
+    pub scheduler: Arc<Scheduler>,
+    pub audit: Arc<Audit>,
+}
+
+#[dbus_interface(name = "org.freedesktop.impl.portal.Account")]
+impl Account {
+    /// Asks the user whether to share their login name, name and picture with an app,
+    /// starting from what the password database and AccountsService have.
+    ///
+    /// The user can change the name and pick another picture; the login name is always the
+    /// real one. Pictures are shared as square avatars, through the document portal if the
+    /// app is sandboxed.
+    #[dbus_interface(out_args("response", "results"))]
+    async fn get_user_information(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        window: &str,
+        options: StrMap<'_>,
+    ) -> zbus::fdo::Result<(u32, StrMap<'static>)> {
+        log::info!("get_user_information({}, {}, {})", handle, app_id, window);
+
+        let Some(ticket) = self.scheduler.ticket(app_id) else {
+            log::warn!("rejecting {}, {} already has a dialog open", handle, app_id);
+            return zbus::fdo::Result::Ok((2, StrMap::new()));
+        };
+
+        let reason = match options.get("reason") {
+            Some(zvariant::Value::Str(reason)) if !reason.is_empty() => Some(reason.to_string()),
+            _ => None,
+        };
+
+        let description = match reason {
+            Some(reason) => format!(
+                "{} wants your name and picture: {}",
+                requester(app_id),
+                reason
+            ),
+            None => format!("{} wants your name and picture.", requester(app_id)),
+        };
+
+        let parent = ParentWindow::parse(window);
+
+        let dialogs = self.dialogs.clone();
+
+        let dialog = show(move || {
+            let user = User::current();
+
+            let request = AccountRequest {
+                message: Message {
+                    title: String::from("Share Details"),
+                    description,
+                    parent,
+                    ..Message::default()
+                },
+                name: user.name.clone(),
+                picture: user.picture.clone(),
+            };
+
+            let (name, picture) = ask(dialogs.as_ref(), request)?;
+
+            Some((user.login, name, picture.as_deref().map(account::avatar)))
+        });
+
+        let timeout = self.config.dialog.timeout();
+
+        let (response, results) =
+            match request::run(conn, &handle, timeout, ticket.run(dialog)).await? {
+                Some(Some((id, name, picture))) => {
+                    let picture = match picture {
+                        Some(picture) => export(conn, app_id, picture).await,
+                        None => None,
+                    };
+
+                    (0, results(id, name, picture))
+                }
+
+                Some(None) => (1, StrMap::new()),
+                None => (2, StrMap::new()),
+            };
+
+        let outcome = match response {
+            0 => Outcome::Chosen,
+            1 => Outcome::Cancelled,
+            _ => Outcome::Failed,
+        };
+
+        self.audit
+            .record(app_id, "GetUserInformation", outcome, &[]);
+
+        zbus::fdo::Result::Ok((response, results))
+    }
+}
+
+/// Ask the user what to share until they share it or cancel, picking pictures in between,
+/// returning the name and picture to share.
+fn ask(
+    dialogs: &dyn DialogProvider,
+    mut request: AccountRequest,
+) -> Option<(String, Option<PathBuf>)> {
+    loop {
+        match dialogs.share_account(&request)? {
+            AccountAnswer::Share(name) => return Some((name, request.picture)),
+
+            AccountAnswer::ChoosePicture(name) => {
+                request.name = name;
+
+                let pick = FileRequest {
+                    title: String::from("Choose Picture"),
+                    parent: request.message.parent.clone(),
+                    modal: true,
+                    folder: dirs::picture_dir(),
+                    filters: vec![(
+                        String::from("Images"),
+                        ["*.png", "*.jpg", "*.jpeg", "*.svg"]
+                            .map(|glob| (0, String::from(glob)))
+                            .to_vec(),
+                    )],
+                    ..FileRequest::default()
+                };
+
+                // Dismissing the file dialog keeps the picture there was.
+                if let Some(picked) = dialogs.open_file(&pick) {
+                    request.picture = picked.paths.into_iter().next().or(request.picture);
+                }
+            }
+        }
+    }
+}
+
+/// Export `picture` to the sandbox of `app_id`, or return it unchanged if there's none.
+///
+/// Returns `None` if the export failed, as host paths are useless inside the sandbox.
+async fn export(conn: &zbus::Connection, app_id: &str, picture: PathBuf) -> Option<PathBuf> {
+    if !documents::is_sandboxed(app_id) {
+        return Some(picture);
+    }
+
+    match documents::export(conn, app_id, std::slice::from_ref(&picture), false).await {
+        Ok(exported) => exported.into_iter().next(),
+
+        Err(e) => {
+            log::error!("failed to export {:?} to {}: {}", picture, app_id, e);
+            None
+        }
+    }
+}
+
+/// Describe the shared details as the results of GetUserInformation.
+fn results(id: String, name: String, picture: Option<PathBuf>) -> StrMap<'static> {
+    let mut results = StrMap::new();
+
+    results.insert("id", id.into());
+    results.insert("name", name.into());
+
+    // The image is a URI, empty if there's no picture to share.
+    let image = picture.as_deref().map(uri::file_uri).unwrap_or_default();
+
+    results.insert("image", image.into());
+
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use zbus::zvariant;
+
+    use super::results;
+
+    #[test]
+    fn user_information() {
+        let shared = results(
+            String::from("ada"),
+            String::from("Ada L."),
+            Some(PathBuf::from("/home/ada/.face")),
+        );
+
+        assert_eq!(shared["id"], zvariant::Value::from("ada"));
+        assert_eq!(shared["name"], zvariant::Value::from("Ada L."));
+        assert_eq!(
+            shared["image"],
+            zvariant::Value::from("file:///home/ada/.face")
+        );
+
+        let shared = results(String::from("ada"), String::from("ada"), None);
+
+        assert_eq!(shared["image"], zvariant::Value::from(""));
+    }
+}
@@ -0,0 +1,220 @@
+use std::{collections::HashMap, sync::Arc};
+
+use zbus::{dbus_interface, zvariant};
+
+use super::{requester, show, StrMap};
+use crate::{
+    audit::{Audit, Outcome},
+    autostart,
+    config::Config,
+    dialog::{DialogProvider, Message},
+    documents, permissions, request,
+    schedule::Scheduler,
+};
+
+/// The permission store table the frontend keeps background decisions in.
+const PERMISSION_TABLE: &str = "background";
+
+/// The entry of `PERMISSION_TABLE` apps are allowed or forbidden on.
+const PERMISSION_ID: &str = "background";
+
+/// The result of NotifyBackground stopping the app.
+const FORBID: u32 = 0;
+
+/// The result of NotifyBackground letting the app run in the background from now on.
+const ALLOW: u32 = 1;
+
+/// The result of NotifyBackground letting the app run in the background this time.
+const ALLOW_ONCE: u32 = 2;
+
+/// The flag of EnableAutostart for apps started through D-Bus activation.
+const DBUS_ACTIVATABLE: u32 = 1;
+
+/// Background implements the org.freedesktop.impl.portal.Background interface.
+pub struct Background {
+    pub config: Arc<Config>,
+    pub dialogs: Arc<dyn DialogProvider>,
+    pub scheduler: Arc<Scheduler>,
+    pub audit: Arc<Audit>,
+}
+
+#[dbus_interface(name = "org.freedesktop.impl.portal.Background")]
+impl Background {
+    /// Lists the state of the running apps, by app id.
+    ///
+    /// Windows can't be told apart by app without capturing the screen, so no app is listed
+    /// and the frontend sees none running in the background.
+    async fn get_app_state(&self) -> HashMap<String, zvariant::OwnedValue> {
+        HashMap::new()
+    }
+
+    /// Asks the user whether an app found running in the background may keep running.
+    ///
+    /// The frontend stores the answer, and stops the app if it's forbidden.
+    #[dbus_interface(out_args("response", "results"))]
+    async fn notify_background(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        name: &str,
+    ) -> zbus::fdo::Result<(u32, StrMap<'_>)> {
+        log::info!("notify_background({}, {}, {})", handle, app_id, name);
+
+        // Apps the user decided on before aren't asked about again.
+        match permissions::lookup(conn, PERMISSION_TABLE, PERMISSION_ID, app_id).await {
+            Ok(permissions) => {
+                if let Some(result) = remembered(&permissions) {
+                    return zbus::fdo::Result::Ok((0, results(result)));
+                }
+            }
+
+            Err(e) => log::warn!("failed to look up the permission of {}: {}", app_id, e),
+        }
+
+        let Some(ticket) = self.scheduler.ticket(app_id) else {
+            log::warn!("rejecting {}, {} already has a dialog open", handle, app_id);
+            return zbus::fdo::Result::Ok((2, StrMap::new()));
+        };
+
+        let app = match name {
+            "" => requester(app_id),
+            name => name.to_owned(),
+        };
+
+        let message = Message {
+            title: String::from("Background Activity"),
+            description: format!(
+                "{} is running in the background. Allow it to keep running without a window?",
+                app
+            ),
+            ..Message::default()
+        };
+
+        let dialogs = self.dialogs.clone();
+
+        let dialog = show(move || ask(&*dialogs, &message));
+
+        let timeout = self.config.dialog.timeout();
+
+        let result = match request::run(conn, &handle, timeout, ticket.run(dialog)).await? {
+            Some(Some(result)) => (0, results(result)),
+
+            // A dismissed dialog lets the app keep running, without remembering it.
+            Some(None) => (1, StrMap::new()),
+            None => (2, StrMap::new()),
+        };
+
+        let outcome = match result.0 {
+            0 => Outcome::Chosen,
+            1 => Outcome::Cancelled,
+            _ => Outcome::Failed,
+        };
+
+        self.audit.record(app_id, "NotifyBackground", outcome, &[]);
+
+        zbus::fdo::Result::Ok(result)
+    }
+
+    /// Starts an app with `commandline` when the user logs in, or stops starting it, returning
+    /// whether it's started then.
+    ///
+    /// The frontend only calls this once the user allowed the app to run in the background.
+    async fn enable_autostart(
+        &self,
+        app_id: &str,
+        enable: bool,
+        commandline: Vec<String>,
+        flags: u32,
+    ) -> zbus::fdo::Result<bool> {
+        log::info!(
+            "enable_autostart({}, {}, {:?})",
+            app_id,
+            enable,
+            commandline
+        );
+
+        let sandboxed = documents::is_sandboxed(app_id);
+
+        let app_id = app_id.to_owned();
+
+        let changed = show(move || match enable {
+            true => autostart::enable(
+                &app_id,
+                &commandline,
+                sandboxed,
+                flags & DBUS_ACTIVATABLE != 0,
+            ),
+
+            false => autostart::disable(&app_id),
+        })
+        .await?;
+
+        match changed {
+            Ok(()) => Ok(enable),
+
+            Err(e) => {
+                log::error!("failed to change the autostart file: {}", e);
+                Err(zbus::fdo::Error::Failed(e.to_string()))
+            }
+        }
+    }
+}
+
+/// Get the result remembered by the `permissions` an app has in the permission store.
+fn remembered(permissions: &[String]) -> Option<u32> {
+    match permissions.first().map(String::as_str) {
+        Some("yes") => Some(ALLOW),
+        Some("no") => Some(FORBID),
+        _ => None,
+    }
+}
+
+/// Ask whether to let the app keep running with `message`, returning the result of
+/// NotifyBackground, or `None` if the dialog was dismissed.
+fn ask(dialogs: &dyn DialogProvider, message: &Message) -> Option<u32> {
+    let options = ["Allow Once", "Always Allow", "Stop"].map(String::from);
+
+    match dialogs.choose(message, &options)? {
+        0 => Some(ALLOW_ONCE),
+        1 => Some(ALLOW),
+        _ => Some(FORBID),
+    }
+}
+
+/// Describe a `result` of NotifyBackground as its results.
+fn results(result: u32) -> StrMap<'static> {
+    let mut results = StrMap::new();
+
+    results.insert("result", result.into());
+
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ask, remembered, ALLOW, ALLOW_ONCE, FORBID};
+    use crate::dialog::{tests::Buttons, Message};
+
+    #[test]
+    fn remembered_permissions() {
+        assert_eq!(remembered(&[String::from("yes")]), Some(ALLOW));
+        assert_eq!(remembered(&[String::from("no")]), Some(FORBID));
+        assert_eq!(remembered(&[]), None);
+    }
+
+    #[test]
+    fn answers() {
+        let message = Message::default();
+
+        assert_eq!(ask(&Buttons::new(&[true]), &message), Some(ALLOW_ONCE));
+        assert_eq!(ask(&Buttons::new(&[false, true]), &message), Some(ALLOW));
+        assert_eq!(
+            ask(&Buttons::new(&[false, false, true]), &message),
+            Some(FORBID)
+        );
+
+        // Dismissing the dialogs lets the app keep running rather than stopping it.
+        assert_eq!(ask(&Buttons::new(&[]), &message), None);
+    }
+}
@@ -0,0 +1,324 @@
+use std::{
+    collections::HashMap,
+    os::fd::{FromRawFd, IntoRawFd, OwnedFd},
+    sync::Arc,
+};
+
+use tokio::sync::mpsc::UnboundedReceiver;
+use zbus::{dbus_interface, zvariant, SignalContext};
+
+use super::{screencast::CastSession, StrMap, PATH};
+use crate::{
+    clipboard::{Event, Selection},
+    session::Sessions,
+};
+
+/// Clipboard implements the org.freedesktop.impl.portal.Clipboard interface.
+///
+/// It shares the clipboard with the remote desktop sessions that asked for it before they
+/// were started.
+pub struct Clipboard {
+    pub sessions: Arc<Sessions<CastSession>>,
+}
+
+/// `Shared` is the clipboard side of a remote desktop session.
+#[derive(Default)]
+pub struct Shared {
+    /// Whether the app asked for the clipboard, which it does before starting the session.
+    pub(super) requested: bool,
+
+    /// The session's end of the clipboard, once it was started with it.
+    pub(super) selection: Option<Box<dyn Selection>>,
+
+    /// The mime types of the clipboard, while someone else owns it.
+    offered: Vec<String>,
+
+    /// The pipes of pastes of the session's clipboard by serial, until the app writes them.
+    transfers: HashMap<u32, OwnedFd>,
+
+    /// The serial of the next paste.
+    serial: u32,
+}
+
+#[dbus_interface(name = "org.freedesktop.impl.portal.Clipboard")]
+impl Clipboard {
+    /// The version of the interface this backend implements.
+    #[dbus_interface(property, name = "version")]
+    fn version(&self) -> u32 {
+        1
+    }
+
+    /// Ask for the clipboard in a remote desktop session, before it's started.
+    async fn request_clipboard(
+        &self,
+        session_handle: zvariant::ObjectPath<'_>,
+        _options: StrMap<'_>,
+    ) -> zbus::fdo::Result<()> {
+        log::info!("request_clipboard({})", session_handle);
+
+        let requested = self
+            .sessions
+            .find(&session_handle, |session| match &mut session.remote {
+                Some(remote) if !remote.started() => {
+                    remote.clipboard.requested = true;
+                    true
+                }
+
+                _ => false,
+            });
+
+        match requested {
+            Some(true) => Ok(()),
+
+            _ => Err(zbus::fdo::Error::Failed(format!(
+                "{} isn't a remote desktop session that can ask for the clipboard",
+                session_handle
+            ))),
+        }
+    }
+
+    /// Take the clipboard over for the session, offering the mime types it can write.
+    async fn set_selection(
+        &self,
+        session_handle: zvariant::ObjectPath<'_>,
+        options: StrMap<'_>,
+    ) -> zbus::fdo::Result<()> {
+        let mime_types = parse_mime_types(&options);
+
+        self.with(&session_handle, |clipboard| {
+            clipboard.selection()?.set(mime_types).map_err(failed)
+        })
+    }
+
+    /// Get the pipe to write a paste of the session's clipboard to.
+    async fn selection_write(
+        &self,
+        session_handle: zvariant::ObjectPath<'_>,
+        serial: u32,
+    ) -> zbus::fdo::Result<zvariant::OwnedFd> {
+        let fd = self.with(&session_handle, |clipboard| {
+            clipboard.transfers.remove(&serial).ok_or_else(|| {
+                zbus::fdo::Error::InvalidArgs(format!("{} isn't a pending paste", serial))
+            })
+        })?;
+
+        Ok(unsafe { zvariant::OwnedFd::from_raw_fd(fd.into_raw_fd()) })
+    }
+
+    /// Finish a paste of the session's clipboard.
+    async fn selection_write_done(
+        &self,
+        session_handle: zvariant::ObjectPath<'_>,
+        serial: u32,
+        success: bool,
+    ) -> zbus::fdo::Result<()> {
+        if !success {
+            log::debug!("{} failed to write the paste {}", session_handle, serial);
+        }
+
+        // Pipes that weren't asked for are closed, so whatever pastes doesn't wait on them.
+        self.with(&session_handle, |clipboard| {
+            clipboard.transfers.remove(&serial);
+            Ok(())
+        })
+    }
+
+    /// Get a pipe the clipboard is written to, as one of the mime types it offers.
+    async fn selection_read(
+        &self,
+        session_handle: zvariant::ObjectPath<'_>,
+        mime_type: &str,
+    ) -> zbus::fdo::Result<zvariant::OwnedFd> {
+        let (read, write) = pipe().map_err(failed)?;
+
+        self.with(&session_handle, |clipboard| {
+            if !clipboard.offered.iter().any(|offered| offered == mime_type) {
+                return Err(zbus::fdo::Error::InvalidArgs(format!(
+                    "the clipboard isn't offered as {}",
+                    mime_type
+                )));
+            }
+
+            clipboard
+                .selection()?
+                .read(mime_type, write)
+                .map_err(failed)
+        })?;
+
+        Ok(unsafe { zvariant::OwnedFd::from_raw_fd(read.into_raw_fd()) })
+    }
+
+    /// Tells the app the clipboard changed.
+    #[dbus_interface(signal)]
+    async fn selection_owner_changed(
+        ctxt: &SignalContext<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        options: StrMap<'_>,
+    ) -> zbus::Result<()>;
+
+    /// Asks the app to write its clipboard as a mime type, to the pipe of `serial`.
+    #[dbus_interface(signal)]
+    async fn selection_transfer(
+        ctxt: &SignalContext<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        mime_type: &str,
+        serial: u32,
+    ) -> zbus::Result<()>;
+}
+
+impl Clipboard {
+    /// Run `f` on the clipboard of the session at `session_handle`.
+    fn with<R>(
+        &self,
+        session_handle: &zvariant::ObjectPath<'_>,
+        f: impl FnOnce(&mut Shared) -> zbus::fdo::Result<R>,
+    ) -> zbus::fdo::Result<R> {
+        let result = self.sessions.find(session_handle, |session| {
+            session
+                .remote
+                .as_mut()
+                .map(|remote| f(&mut remote.clipboard))
+        });
+
+        result.flatten().unwrap_or_else(|| {
+            Err(zbus::fdo::Error::InvalidArgs(format!(
+                "{} isn't a remote desktop session",
+                session_handle
+            )))
+        })
+    }
+}
+
+impl Shared {
+    /// Get the session's end of the clipboard, as long as it was started with it.
+    fn selection(&self) -> zbus::fdo::Result<&dyn Selection> {
+        self.selection.as_deref().ok_or_else(|| {
+            zbus::fdo::Error::AccessDenied(String::from(
+                "the session wasn't started with the clipboard",
+            ))
+        })
+    }
+}
+
+/// Tell the app of the session at `session_handle` what happens to its clipboard, as the
+/// `events` come, until the session's end of it is dropped.
+pub(super) async fn forward(
+    conn: zbus::Connection,
+    sessions: Arc<Sessions<CastSession>>,
+    session_handle: zvariant::OwnedObjectPath,
+    mut events: UnboundedReceiver<Event>,
+) {
+    let ctxt = match SignalContext::new(&conn, PATH) {
+        Ok(ctxt) => ctxt,
+
+        Err(e) => {
+            log::error!("failed to signal clipboard changes: {}", e);
+            return;
+        }
+    };
+
+    while let Some(event) = events.recv().await {
+        let signalled = match event {
+            Event::OwnerChanged { mime_types, own } => {
+                sessions.find(&session_handle, |session| {
+                    if let Some(remote) = &mut session.remote {
+                        remote.clipboard.offered = match own {
+                            true => Vec::new(),
+                            false => mime_types.clone(),
+                        };
+                    }
+                });
+
+                let mut options = StrMap::new();
+
+                options.insert("mime_types", mime_types.into());
+                options.insert("session_is_owner", own.into());
+
+                Clipboard::selection_owner_changed(&ctxt, session_handle.as_ref(), options).await
+            }
+
+            Event::Transfer { mime_type, fd } => {
+                let serial = sessions.find(&session_handle, |session| {
+                    let clipboard = &mut session.remote.as_mut()?.clipboard;
+
+                    let serial = clipboard.serial;
+
+                    clipboard.serial = serial.wrapping_add(1);
+                    clipboard.transfers.insert(serial, fd);
+
+                    Some(serial)
+                });
+
+                let Some(Some(serial)) = serial else {
+                    continue;
+                };
+
+                Clipboard::selection_transfer(&ctxt, session_handle.as_ref(), &mime_type, serial)
+                    .await
+            }
+        };
+
+        if let Err(e) = signalled {
+            log::warn!(
+                "failed to tell {} about its clipboard: {}",
+                session_handle,
+                e
+            );
+        }
+    }
+}
+
+/// Parse the `mime_types` option of SetSelection.
+fn parse_mime_types(options: &StrMap<'_>) -> Vec<String> {
+    match options.get("mime_types") {
+        Some(zvariant::Value::Array(array)) => array
+            .iter()
+            .filter_map(|value| match value {
+                zvariant::Value::Str(mime_type) => Some(mime_type.to_string()),
+                _ => None,
+            })
+            .collect(),
+
+        _ => Vec::new(),
+    }
+}
+
+/// Make a pipe, returning its read and write ends.
+fn pipe() -> std::io::Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0; 2];
+
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) })
+}
+
+/// Report an error of the clipboard to the app.
+fn failed(e: std::io::Error) -> zbus::fdo::Error {
+    zbus::fdo::Error::Failed(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use zbus::zvariant;
+
+    use super::{parse_mime_types, StrMap};
+
+    #[test]
+    fn mime_types() {
+        let mut options = StrMap::new();
+
+        assert!(parse_mime_types(&options).is_empty());
+
+        options.insert(
+            "mime_types",
+            zvariant::Value::from(vec!["text/plain;charset=utf-8", "text/html"]),
+        );
+
+        assert_eq!(
+            parse_mime_types(&options),
+            ["text/plain;charset=utf-8", "text/html"]
+        );
+    }
+}
@@ -0,0 +1,642 @@
+use std::{
+    io::{Read, Seek, SeekFrom, Write},
+    os::{
+        fd::{AsRawFd, BorrowedFd},
+        unix::fs::MetadataExt,
+    },
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC};
+use zbus::{dbus_interface, zvariant};
+
+use super::{requester, show, StrMap};
+use crate::{
+    audit::{Audit, Outcome},
+    config::Config,
+    desktop,
+    dialog::{DialogProvider, Message},
+    launch, request,
+    schedule::Scheduler,
+    uri,
+    window::ParentWindow,
+};
+
+/// The characters escaped in the fields of `mailto:` URLs, as RFC 6068 describes.
+const FIELD: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~')
+    .remove(b'@');
+
+/// The content type of the apps that handle `mailto:` URLs.
+const MAILTO: &str = "x-scheme-handler/mailto";
+
+/// How much of the body the confirmation shows, in characters.
+const BODY_PREVIEW: usize = 200;
+
+/// The mail clients that take attachments in a `-compose` argument, as they ignore them in
+/// `mailto:` URLs.
+const COMPOSE_ARGUMENT: [&str; 4] = ["thunderbird", "betterbird", "icedove", "seamonkey"];
+
+/// How many bytes of attachments a request can have copied.
+const COPY_LIMIT: u64 = 256 * 1024 * 1024;
+
+/// How long the attachments of a request can take to copy.
+const COPY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Counts the requests that copied attachments in this process, to give each its own directory.
+static COPIES: AtomicU64 = AtomicU64::new(0);
+
+/// Email implements the org.freedesktop.impl.portal.Email interface.
+pub struct Email {
+    pub config: Arc<Config>,
+    pub dialogs: Arc<dyn DialogProvider>,
+    pub scheduler: Arc<Scheduler>,
+    pub audit: Arc<Audit>,
+}
+
+#[dbus_interface(name = "org.freedesktop.impl.portal.Email")]
+impl Email {
+    /// Starts writing an email in the default mail client, once the user confirmed what's
+    /// shared with it.
+    ///
+    /// The email is passed as a `mailto:` URL, or a `-compose` argument for Thunderbird and
+    /// its forks. Attachments are passed by path or, in `attachment_fds`, as open files,
+    /// which are copied to `$XDG_RUNTIME_DIR` once the user confirmed, unless they're files
+    /// the client can open too.
+    #[dbus_interface(out_args("response", "results"))]
+    async fn compose_email(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        parent_window: &str,
+        options: StrMap<'_>,
+    ) -> zbus::fdo::Result<(u32, StrMap<'static>)> {
+        log::info!("compose_email({}, {}, {})", handle, app_id, parent_window);
+
+        let mut draft = Draft::parse(&options);
+
+        let attachments = attachments(&options);
+
+        let Some(client) = launch::default_app(MAILTO) else {
+            log::warn!("rejecting {}, there's no default mail client", handle);
+            return zbus::fdo::Result::Ok((2, StrMap::new()));
+        };
+
+        let Some(ticket) = self.scheduler.ticket(app_id) else {
+            log::warn!("rejecting {}, {} already has a dialog open", handle, app_id);
+            return zbus::fdo::Result::Ok((2, StrMap::new()));
+        };
+
+        let message = Message {
+            title: String::from("Compose Email"),
+            description: format!(
+                "{} wants to write an email with {}.\n\n{}",
+                requester(app_id),
+                desktop::lookup(&client).name,
+                draft.describe(&attachments)
+            ),
+            parent: ParentWindow::parse(parent_window),
+            accept_label: Some(String::from("Compose")),
+            ..Message::default()
+        };
+
+        let dialogs = self.dialogs.clone();
+
+        let dialog = show(move || dialogs.confirm(&message));
+
+        let timeout = self.config.dialog.timeout();
+
+        let response = match request::run(conn, &handle, timeout, ticket.run(dialog)).await? {
+            Some(true) => {
+                let dir = copies_dir();
+
+                let copies = {
+                    let dir = dir.clone();
+                    show(move || materialize(attachments, &dir)).await?
+                };
+
+                match copies {
+                    Ok(uris) => {
+                        draft.attachments.extend(uris);
+
+                        let command = command(&client, &draft);
+
+                        match show(move || launch::spawn(&command?)).await? {
+                            Ok(()) => 0,
+
+                            Err(e) => {
+                                log::error!("failed to start the mail client: {}", e);
+
+                                let _ = std::fs::remove_dir_all(&dir);
+
+                                2
+                            }
+                        }
+                    }
+
+                    Err(e) => {
+                        log::error!("failed to copy the attachments: {}", e);
+                        2
+                    }
+                }
+            }
+
+            Some(false) => 1,
+            None => 2,
+        };
+
+        let outcome = match response {
+            0 => Outcome::Chosen,
+            1 => Outcome::Cancelled,
+            _ => Outcome::Failed,
+        };
+
+        self.audit
+            .record(app_id, "ComposeEmail", outcome, &draft.attachments);
+
+        zbus::fdo::Result::Ok((response, StrMap::new()))
+    }
+}
+
+/// `Draft` is the email an app asks to write.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Draft {
+    addresses: Vec<String>,
+    cc: Vec<String>,
+    bcc: Vec<String>,
+    subject: String,
+    body: String,
+
+    /// The URIs of the attached files.
+    attachments: Vec<String>,
+}
+
+impl Draft {
+    /// Read the draft from the options of ComposeEmail.
+    fn parse(options: &StrMap<'_>) -> Self {
+        let string = |key: &str| match options.get(key) {
+            Some(zvariant::Value::Str(value)) => value.to_string(),
+            _ => String::new(),
+        };
+
+        let strings = |key: &str| -> Vec<String> {
+            match options.get(key) {
+                Some(value @ zvariant::Value::Array(_)) => {
+                    Vec::<String>::try_from(value.clone()).unwrap_or_default()
+                }
+                _ => Vec::new(),
+            }
+        };
+
+        // `address` is the single address of the first version of the interface.
+        let addresses = Some(string("address"))
+            .into_iter()
+            .chain(strings("addresses"))
+            .filter(|address| !address.is_empty())
+            .collect();
+
+        Self {
+            addresses,
+            cc: strings("cc"),
+            bcc: strings("bcc"),
+            subject: string("subject"),
+            body: string("body"),
+            attachments: strings("attachments")
+                .into_iter()
+                .map(|attachment| match attachment.starts_with('/') {
+                    true => uri::file_uri(std::path::Path::new(&attachment)),
+                    false => attachment,
+                })
+                .collect(),
+        }
+    }
+
+    /// Write the draft as a `mailto:` URL.
+    fn mailto(&self) -> String {
+        let encode = |field: &str| percent_encoding::utf8_percent_encode(field, FIELD).to_string();
+
+        let to: Vec<String> = self.addresses.iter().map(|a| encode(a)).collect();
+
+        let mut fields = Vec::new();
+
+        for (name, addresses) in [("cc", &self.cc), ("bcc", &self.bcc)] {
+            if !addresses.is_empty() {
+                let addresses: Vec<String> = addresses.iter().map(|a| encode(a)).collect();
+                fields.push(format!("{}={}", name, addresses.join(",")));
+            }
+        }
+
+        for (name, value) in [("subject", &self.subject), ("body", &self.body)] {
+            if !value.is_empty() {
+                fields.push(format!("{}={}", name, encode(value)));
+            }
+        }
+
+        for attachment in &self.attachments {
+            fields.push(format!("attach={}", encode(attachment)));
+        }
+
+        match fields.is_empty() {
+            true => format!("mailto:{}", to.join(",")),
+            false => format!("mailto:{}?{}", to.join(","), fields.join("&")),
+        }
+    }
+
+    /// Write the draft as the `-compose` argument of Thunderbird.
+    ///
+    /// Its values are quoted with `'`, which they can't contain, so those are replaced with
+    /// `’`.
+    fn compose_argument(&self) -> String {
+        let quote = |value: &str| format!("'{}'", value.replace('\'', "\u{2019}"));
+
+        let fields = [
+            ("to", self.addresses.join(",")),
+            ("cc", self.cc.join(",")),
+            ("bcc", self.bcc.join(",")),
+            ("subject", self.subject.clone()),
+            ("body", self.body.clone()),
+            ("attachment", self.attachments.join(",")),
+        ];
+
+        let fields: Vec<String> = fields
+            .iter()
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(name, value)| format!("{}={}", name, quote(value)))
+            .collect();
+
+        fields.join(",")
+    }
+
+    /// Describe what the draft shares with the files of `attachment_fds`, for people.
+    fn describe(&self, attachments: &[Attachment]) -> String {
+        let mut lines = Vec::new();
+
+        for (label, addresses) in [
+            ("To", &self.addresses),
+            ("Cc", &self.cc),
+            ("Bcc", &self.bcc),
+        ] {
+            if !addresses.is_empty() {
+                lines.push(format!("{}: {}", label, addresses.join(", ")));
+            }
+        }
+
+        if !self.subject.is_empty() {
+            lines.push(format!("Subject: {}", self.subject));
+        }
+
+        for attachment in &self.attachments {
+            let name = uri::file_path(attachment)
+                .and_then(|path| Some(path.file_name()?.to_string_lossy().into_owned()))
+                .unwrap_or_else(|| attachment.clone());
+
+            lines.push(format!("Attachment: {}", name));
+        }
+
+        for attachment in attachments {
+            lines.push(format!("Attachment: {}", attachment.name()));
+        }
+
+        if !self.body.is_empty() {
+            let mut body: String = self.body.chars().take(BODY_PREVIEW).collect();
+
+            if body.len() < self.body.len() {
+                body.push('…');
+            }
+
+            lines.push(format!("\n{}", body));
+        }
+
+        match lines.is_empty() {
+            true => String::from("The email is empty."),
+            false => lines.join("\n"),
+        }
+    }
+}
+
+/// Get the command that opens `draft` in the mail client with desktop file id `client`.
+fn command(client: &str, draft: &Draft) -> std::io::Result<Vec<String>> {
+    let command = launch::command(client, &[draft.mailto()])?;
+
+    let program = Path::new(&command[0])
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+
+    match COMPOSE_ARGUMENT.contains(&program.as_ref()) {
+        true => Ok(vec![
+            command[0].clone(),
+            String::from("-compose"),
+            draft.compose_argument(),
+        ]),
+        false => Ok(command),
+    }
+}
+
+/// `Attachment` is a file passed in `attachment_fds`.
+enum Attachment {
+    /// A file the mail client can open at the path it was opened at.
+    Shared(PathBuf),
+
+    /// A file only readable through its descriptor, copied to `name` once the user confirmed.
+    Copied { file: std::fs::File, name: String },
+}
+
+impl Attachment {
+    /// Find out how the mail client can get the file of `fd`, without reading it.
+    fn open(fd: BorrowedFd<'_>, index: usize) -> std::io::Result<Self> {
+        let file = std::fs::File::from(fd.try_clone_to_owned()?);
+
+        let metadata = file.metadata()?;
+
+        // Pipes and deleted files link to names like `pipe:[1234]` or `/tmp/a (deleted)`.
+        let linked = std::fs::read_link(format!("/proc/self/fd/{}", fd.as_raw_fd()))
+            .ok()
+            .filter(|path| path.is_absolute());
+
+        if let Some(path) = &linked {
+            let same = std::fs::metadata(path).is_ok_and(|opened| {
+                (opened.dev(), opened.ino()) == (metadata.dev(), metadata.ino())
+            });
+
+            if same {
+                return Ok(Self::Shared(path.clone()));
+            }
+        }
+
+        let name = linked
+            .as_deref()
+            .and_then(Path::file_name)
+            .map(|name| {
+                name.to_string_lossy()
+                    .trim_end_matches(" (deleted)")
+                    .to_owned()
+            })
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| format!("attachment-{}", index));
+
+        Ok(Self::Copied { file, name })
+    }
+
+    /// Get the name of the file, for people.
+    fn name(&self) -> String {
+        match self {
+            Self::Shared(path) => path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+            Self::Copied { name, .. } => name.clone(),
+        }
+    }
+}
+
+/// Get the files passed in `attachment_fds`, skipping those that can't be read.
+fn attachments(options: &StrMap<'_>) -> Vec<Attachment> {
+    let Some(zvariant::Value::Array(fds)) = options.get("attachment_fds") else {
+        return Vec::new();
+    };
+
+    fds.iter()
+        .enumerate()
+        .filter_map(|(i, fd)| {
+            let zvariant::Value::Fd(fd) = fd else {
+                return None;
+            };
+
+            // SAFETY: the file descriptors of a message stay open while it's handled.
+            let fd = unsafe { BorrowedFd::borrow_raw(fd.as_raw_fd()) };
+
+            match Attachment::open(fd, i + 1) {
+                Ok(attachment) => Some(attachment),
+
+                Err(e) => {
+                    log::warn!("failed to read attachment {}: {}", i + 1, e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Get a new directory of `$XDG_RUNTIME_DIR` for the copies of the attachments of a request.
+fn copies_dir() -> PathBuf {
+    dirs::runtime_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("xdg-desktop-portal-rs")
+        .join("attachments")
+        .join(format!(
+            "{}-{}",
+            std::process::id(),
+            COPIES.fetch_add(1, Ordering::Relaxed)
+        ))
+}
+
+/// Get the URIs the mail client reads `attachments` from, copying those it can't open into
+/// `dir`, which is removed again if one can't be copied.
+///
+/// Copies are left for the mail client, and go with `$XDG_RUNTIME_DIR` at logout.
+fn materialize(attachments: Vec<Attachment>, dir: &Path) -> std::io::Result<Vec<String>> {
+    let deadline = Instant::now() + COPY_TIMEOUT;
+
+    let mut left = COPY_LIMIT;
+
+    let mut copy = |index: usize, mut file: std::fs::File, name: &str| {
+        // Each copy has its own directory, so copies with the same name don't clash.
+        let copy = dir.join(index.to_string()).join(name);
+
+        std::fs::create_dir_all(copy.parent().unwrap_or(dir))?;
+
+        // The file may have been read from already; pipes and sockets can't be rewound.
+        let _ = file.seek(SeekFrom::Start(0));
+
+        copy_until(&mut file, &copy, &mut left, deadline)?;
+
+        std::io::Result::Ok(copy)
+    };
+
+    let uris: std::io::Result<Vec<String>> = attachments
+        .into_iter()
+        .enumerate()
+        .map(|(i, attachment)| match attachment {
+            Attachment::Shared(path) => Ok(uri::file_uri(&path)),
+            Attachment::Copied { file, name } => Ok(uri::file_uri(&copy(i + 1, file, &name)?)),
+        })
+        .collect();
+
+    if uris.is_err() {
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    uris
+}
+
+/// Copy `file` to `path`, failing once it's more than `left` bytes, which is counted down, or
+/// takes until `deadline`, as apps can pass pipes they never close.
+fn copy_until(
+    file: &mut std::fs::File,
+    path: &Path,
+    left: &mut u64,
+    deadline: Instant,
+) -> std::io::Result<()> {
+    let mut copy = std::fs::File::create(path)?;
+
+    let mut buffer = [0; 64 * 1024];
+
+    let mut pollfd = libc::pollfd {
+        fd: file.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+
+    loop {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+
+        if timeout.is_zero() {
+            return Err(std::io::ErrorKind::TimedOut.into());
+        }
+
+        let timeout = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+
+        match unsafe { libc::poll(&mut pollfd, 1, timeout) } {
+            0 => return Err(std::io::ErrorKind::TimedOut.into()),
+            count if count > 0 => {}
+
+            _ => match std::io::Error::last_os_error() {
+                e if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                e => return Err(e),
+            },
+        }
+
+        let read = match file.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(read) => read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        *left = left
+            .checked_sub(read as u64)
+            .ok_or_else(|| std::io::Error::other("the attachments are too large"))?;
+
+        copy.write_all(&buffer[..read])?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Write,
+        os::fd::AsFd,
+        time::{Duration, Instant},
+    };
+
+    use zbus::zvariant;
+
+    use super::{copy_until, materialize, Attachment, Draft, StrMap};
+
+    #[test]
+    fn drafts() {
+        let options = StrMap::from([
+            ("address", zvariant::Value::from("a@example.org")),
+            ("addresses", zvariant::Value::from(vec!["b@example.org"])),
+            ("cc", zvariant::Value::from(vec!["c@example.org"])),
+            ("subject", zvariant::Value::from("Hello & bye")),
+            ("body", zvariant::Value::from("Line 1\nLine 2")),
+            ("attachments", zvariant::Value::from(vec!["/tmp/a b.txt"])),
+        ]);
+
+        let draft = Draft::parse(&options);
+
+        assert_eq!(draft.addresses, ["a@example.org", "b@example.org"]);
+        assert_eq!(draft.attachments, ["file:///tmp/a%20b.txt"]);
+
+        assert_eq!(
+            draft.mailto(),
+            "mailto:a@example.org,b@example.org?cc=c@example.org\
+             &subject=Hello%20%26%20bye&body=Line%201%0ALine%202\
+             &attach=file%3A%2F%2F%2Ftmp%2Fa%2520b.txt"
+        );
+
+        assert!(draft.describe(&[]).contains("Attachment: a b.txt"));
+
+        assert_eq!(Draft::default().mailto(), "mailto:");
+
+        let draft = Draft {
+            addresses: vec![String::from("a@example.org")],
+            subject: String::from("Ada's notes"),
+            attachments: vec![String::from("file:///tmp/a"), String::from("file:///tmp/b")],
+            ..Draft::default()
+        };
+
+        assert_eq!(
+            draft.compose_argument(),
+            "to='a@example.org',subject='Ada\u{2019}s notes',\
+             attachment='file:///tmp/a,file:///tmp/b'"
+        );
+    }
+
+    #[test]
+    fn attachment_fds() {
+        let dir = std::env::temp_dir().join(format!("email-test-{}", std::process::id()));
+        let copies = dir.join("copies");
+
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // Files that are still where they were opened are shared as they are.
+        let path = dir.join("notes.txt");
+
+        std::fs::write(&path, "notes").unwrap();
+
+        let file = std::fs::File::open(&path).unwrap();
+
+        let shared = Attachment::open(file.as_fd(), 1).unwrap();
+
+        assert!(matches!(&shared, Attachment::Shared(shared) if *shared == path));
+
+        // Others are copied, like what's sent over sockets.
+        let (mut writer, reader) = std::os::unix::net::UnixStream::pair().unwrap();
+
+        writer.write_all(b"sent").unwrap();
+        drop(writer);
+
+        let copied = Attachment::open(reader.as_fd(), 2).unwrap();
+
+        assert_eq!(copied.name(), "attachment-2");
+
+        let uris = materialize(vec![shared, copied], &copies).unwrap();
+
+        assert_eq!(uris[0], format!("file://{}", path.display()));
+        assert_eq!(
+            std::fs::read_to_string(copies.join("2").join("attachment-2")).unwrap(),
+            "sent"
+        );
+
+        // Copies fail past the limit, or when the app never closes its end.
+        let (mut writer, reader) = std::os::unix::net::UnixStream::pair().unwrap();
+
+        writer.write_all(b"sent").unwrap();
+
+        let mut file = std::fs::File::from(std::os::fd::OwnedFd::from(reader));
+        let deadline = Instant::now() + Duration::from_millis(100);
+
+        assert!(copy_until(&mut file, &dir.join("large"), &mut 2, deadline).is_err());
+
+        let error = copy_until(&mut file, &dir.join("open"), &mut 1024, deadline).unwrap_err();
+
+        assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+
+        drop(writer);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,656 @@
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use tokio::sync::mpsc::UnboundedReceiver;
+use zbus::{dbus_interface, dbus_proxy, zvariant, SignalContext};
+
+use super::{requester, show, StrMap, PATH};
+use crate::{
+    audit::{Audit, Outcome},
+    config::Config,
+    dialog::{AppShortcuts, DialogProvider, Message, ShortcutsRequest},
+    request,
+    schedule::Scheduler,
+    session::Sessions,
+    shortcuts::{self, Binding, Event, Grabber, Requested, Shortcut, Trigger},
+    window::ParentWindow,
+};
+
+/// GlobalShortcuts implements the org.freedesktop.impl.portal.GlobalShortcuts interface.
+pub struct GlobalShortcuts {
+    pub config: Arc<Config>,
+    pub dialogs: Arc<dyn DialogProvider>,
+    pub scheduler: Arc<Scheduler>,
+    pub audit: Arc<Audit>,
+    pub grabber: Arc<dyn Grabber>,
+    pub sessions: Arc<Sessions<ShortcutSession>>,
+}
+
+/// ShortcutSettings implements the org.freedesktop.impl.portal.desktop.rs.Shortcuts interface,
+/// which `xdg-desktop-portal-rs shortcuts` calls to let the user rebind global shortcuts.
+pub struct ShortcutSettings {
+    pub dialogs: Arc<dyn DialogProvider>,
+    pub scheduler: Arc<Scheduler>,
+    pub grabber: Arc<dyn Grabber>,
+
+    /// The sessions of GlobalShortcuts, which are told about their new triggers.
+    pub sessions: Arc<Sessions<ShortcutSession>>,
+}
+
+/// The settings of global shortcuts, as the portal serves them.
+#[dbus_proxy(
+    interface = "org.freedesktop.impl.portal.desktop.rs.Shortcuts",
+    default_service = "org.freedesktop.impl.portal.desktop.rs",
+    default_path = "/org/freedesktop/portal/desktop"
+)]
+trait Shortcuts {
+    fn configure(&self) -> zbus::Result<u32>;
+}
+
+/// `ShortcutSession` is the session of an app using global shortcuts.
+pub struct ShortcutSession {
+    app_id: String,
+
+    /// The shortcuts the session bound, none until it does.
+    shortcuts: Vec<Shortcut>,
+}
+
+#[dbus_interface(name = "org.freedesktop.impl.portal.GlobalShortcuts")]
+impl GlobalShortcuts {
+    /// The version of the interface this backend implements.
+    #[dbus_interface(property, name = "version")]
+    fn version(&self) -> u32 {
+        1
+    }
+
+    /// Create a global shortcuts session, whose shortcuts are grabbed until it's closed.
+    #[dbus_interface(out_args("response", "results"))]
+    async fn create_session(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        handle: zvariant::ObjectPath<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        _options: StrMap<'_>,
+    ) -> zbus::fdo::Result<(u32, StrMap<'static>)> {
+        log::info!("create_session({}, {}, {})", handle, session_handle, app_id);
+
+        let session = ShortcutSession {
+            app_id: String::from(app_id),
+            shortcuts: Vec::new(),
+        };
+
+        let sessions = Arc::downgrade(&self.sessions);
+        let grabber = self.grabber.clone();
+
+        // The shortcuts of the session are released with it.
+        let on_close = move |_| {
+            if let Some(sessions) = sessions.upgrade() {
+                grabber.grab(&bindings(&sessions));
+            }
+        };
+
+        self.sessions
+            .create(conn, &session_handle, app_id, session, on_close)
+            .await?;
+
+        let results = StrMap::from([("session_handle", session_handle.to_string().into())]);
+
+        zbus::fdo::Result::Ok((0, results))
+    }
+
+    /// Bind the shortcuts of a session, asking the user first if any of them weren't bound
+    /// before, which lets them change the triggers.
+    ///
+    /// Shortcuts get the trigger they were bound to before, or else the one the app prefers
+    /// if no other shortcut has it; the others are left without one.
+    #[dbus_interface(out_args("response", "results"))]
+    async fn bind_shortcuts(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        handle: zvariant::ObjectPath<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        shortcuts: Vec<(&str, StrMap<'_>)>,
+        parent_window: &str,
+        _options: StrMap<'_>,
+    ) -> zbus::fdo::Result<(u32, StrMap<'static>)> {
+        log::info!(
+            "bind_shortcuts({}, {}, {})",
+            handle,
+            session_handle,
+            parent_window
+        );
+
+        // The frontend checks the caller owns the session, since it isn't told the app.
+        let Some(app_id) = self
+            .sessions
+            .find(&session_handle, |session| session.app_id.clone())
+        else {
+            log::warn!("rejecting {}, {} isn't a session", handle, session_handle);
+            return zbus::fdo::Result::Ok((2, StrMap::new()));
+        };
+
+        let requested = parse_shortcuts(&shortcuts);
+
+        let (bound, taken) = shortcuts::load(&app_id);
+
+        let (mut shortcuts, new) = shortcuts::assign(&requested, &bound, &taken);
+
+        if new {
+            let Some(ticket) = self.scheduler.ticket(&app_id) else {
+                log::warn!("rejecting {}, {} already has a dialog open", handle, app_id);
+                return zbus::fdo::Result::Ok((2, StrMap::new()));
+            };
+
+            let request = ShortcutsRequest {
+                message: Message {
+                    title: String::from("Global Shortcuts"),
+                    description: format!(
+                        "{} wants shortcuts that work while you use other applications.",
+                        requester(&app_id)
+                    ),
+                    parent: ParentWindow::parse(parent_window),
+                    accept_label: Some(String::from("Allow")),
+                    reject_label: Some(String::from("Deny")),
+                    ..Message::default()
+                },
+                apps: vec![AppShortcuts {
+                    app_id: app_id.clone(),
+                    name: requester(&app_id),
+                    shortcuts: shortcuts.clone(),
+                }],
+                taken: taken.clone(),
+            };
+
+            let dialogs = self.dialogs.clone();
+
+            let dialog = show(move || dialogs.edit_shortcuts(&request));
+
+            let timeout = self.config.dialog.timeout();
+
+            let (response, edited) =
+                match request::run(conn, &handle, timeout, ticket.run(dialog)).await? {
+                    Some(Some(apps)) => (0, apps),
+                    Some(None) => (1, Vec::new()),
+                    None => (2, Vec::new()),
+                };
+
+            if response != 0 {
+                let outcome = match response {
+                    1 => Outcome::Cancelled,
+                    _ => Outcome::Failed,
+                };
+
+                self.audit.record(&app_id, "BindShortcuts", outcome, &[]);
+
+                return zbus::fdo::Result::Ok((response, StrMap::new()));
+            }
+
+            // Only the triggers can be changed, and not to those of other shortcuts.
+            if let Some(app) = edited.into_iter().next() {
+                let edited = retrigger(&shortcuts, &app.shortcuts);
+
+                match shortcuts::conflicts(&edited, &taken).as_slice() {
+                    [] => shortcuts = edited,
+                    [trigger, ..] => {
+                        log::warn!("ignoring the triggers chosen, {} is taken", trigger);
+                    }
+                }
+            }
+
+            if let Err(e) = shortcuts::save(&app_id, &shortcuts) {
+                log::warn!("failed to remember the shortcuts of {}: {}", app_id, e);
+            }
+        }
+
+        let stored = self.sessions.find(&session_handle, |session| {
+            session.shortcuts = shortcuts.clone();
+        });
+
+        // The session may have been closed while the user was asked.
+        if stored.is_none() {
+            return zbus::fdo::Result::Ok((2, StrMap::new()));
+        }
+
+        self.grabber.grab(&bindings(&self.sessions));
+
+        self.audit
+            .record(&app_id, "BindShortcuts", Outcome::Chosen, &[]);
+
+        let results = StrMap::from([("shortcuts", shortcut_results(&shortcuts).into())]);
+
+        zbus::fdo::Result::Ok((0, results))
+    }
+
+    /// List the shortcuts a session bound, with their triggers.
+    #[dbus_interface(out_args("response", "results"))]
+    async fn list_shortcuts(
+        &self,
+        handle: zvariant::ObjectPath<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+    ) -> zbus::fdo::Result<(u32, StrMap<'static>)> {
+        log::info!("list_shortcuts({}, {})", handle, session_handle);
+
+        let Some(shortcuts) = self
+            .sessions
+            .find(&session_handle, |session| session.shortcuts.clone())
+        else {
+            return zbus::fdo::Result::Ok((2, StrMap::new()));
+        };
+
+        let results = StrMap::from([("shortcuts", shortcut_results(&shortcuts).into())]);
+
+        zbus::fdo::Result::Ok((0, results))
+    }
+
+    /// Tells the app the trigger of a shortcut of the session at `session_handle` was
+    /// pressed.
+    #[dbus_interface(signal)]
+    async fn activated(
+        ctxt: &SignalContext<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        shortcut_id: &str,
+        timestamp: u64,
+        options: StrMap<'_>,
+    ) -> zbus::Result<()>;
+
+    /// Tells the app the triggers of the shortcuts of the session at `session_handle` were
+    /// changed in the settings.
+    #[dbus_interface(signal)]
+    async fn shortcuts_changed(
+        ctxt: &SignalContext<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        shortcuts: &[(String, StrMap<'_>)],
+    ) -> zbus::Result<()>;
+
+    /// Tells the app the trigger of a shortcut of the session at `session_handle` was
+    /// released.
+    #[dbus_interface(signal)]
+    async fn deactivated(
+        ctxt: &SignalContext<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        shortcut_id: &str,
+        timestamp: u64,
+        options: StrMap<'_>,
+    ) -> zbus::Result<()>;
+}
+
+#[dbus_interface(name = "org.freedesktop.impl.portal.desktop.rs.Shortcuts")]
+impl ShortcutSettings {
+    /// Show the shortcuts of every app, letting the user change their triggers, and return
+    /// 0 once the changes are saved, 1 if the user cancelled, or 2 if another dialog is open
+    /// or the triggers conflict.
+    ///
+    /// Sessions whose triggers changed are told with ShortcutsChanged.
+    async fn configure(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+    ) -> zbus::fdo::Result<u32> {
+        log::info!("configure()");
+
+        // The settings aren't asked for by an app, so they get a turn of their own.
+        let Some(ticket) = self.scheduler.ticket("") else {
+            log::warn!("not showing the shortcut settings, a dialog is open");
+            return zbus::fdo::Result::Ok(2);
+        };
+
+        let apps: Vec<AppShortcuts> = shortcuts::load_all()
+            .into_iter()
+            .map(|(app_id, shortcuts)| AppShortcuts {
+                name: requester(&app_id),
+                app_id,
+                shortcuts,
+            })
+            .collect();
+
+        let request = ShortcutsRequest {
+            message: Message {
+                title: String::from("Global Shortcuts"),
+                description: String::from("These shortcuts work while you use other applications."),
+                accept_label: Some(String::from("Save")),
+                ..Message::default()
+            },
+            apps: apps.clone(),
+            taken: Vec::new(),
+        };
+
+        let dialogs = self.dialogs.clone();
+
+        let Some(edited) = ticket
+            .run(show(move || dialogs.edit_shortcuts(&request)))
+            .await?
+        else {
+            return zbus::fdo::Result::Ok(1);
+        };
+
+        let conflicts = shortcuts::conflicts(edited.iter().flat_map(|app| &app.shortcuts), &[]);
+
+        if let Some(trigger) = conflicts.first() {
+            log::warn!("not saving the shortcuts, {} triggers several", trigger);
+            return zbus::fdo::Result::Ok(2);
+        }
+
+        let mut changed: Vec<AppShortcuts> = Vec::new();
+
+        for app in edited {
+            let Some(before) = apps.iter().find(|before| before.app_id == app.app_id) else {
+                continue;
+            };
+
+            if *before == app {
+                continue;
+            }
+
+            let shortcuts = retrigger(&before.shortcuts, &app.shortcuts);
+
+            if let Err(e) = shortcuts::save(&app.app_id, &shortcuts) {
+                log::warn!("failed to remember the shortcuts of {}: {}", app.app_id, e);
+            }
+
+            changed.push(AppShortcuts { shortcuts, ..app });
+        }
+
+        self.rebind(conn, &changed).await;
+
+        zbus::fdo::Result::Ok(0)
+    }
+}
+
+impl ShortcutSettings {
+    /// Give the sessions of the apps whose shortcuts `changed` their new triggers, and grab
+    /// them.
+    async fn rebind(&self, conn: &zbus::Connection, changed: &[AppShortcuts]) {
+        if changed.is_empty() {
+            return;
+        }
+
+        for path in self.sessions.paths() {
+            let Ok(session_handle) = zvariant::ObjectPath::try_from(path.as_str()) else {
+                continue;
+            };
+
+            let rebound = self.sessions.find(&session_handle, |session| {
+                let app = changed.iter().find(|app| app.app_id == session.app_id)?;
+
+                // Sessions only get the shortcuts they bound.
+                session.shortcuts = retrigger(&session.shortcuts, &app.shortcuts);
+
+                Some(session.shortcuts.clone())
+            });
+
+            let Some(shortcuts) = rebound.flatten().filter(|shortcuts| !shortcuts.is_empty())
+            else {
+                continue;
+            };
+
+            let signalled = match SignalContext::new(conn, PATH) {
+                Ok(ctxt) => {
+                    GlobalShortcuts::shortcuts_changed(
+                        &ctxt,
+                        session_handle,
+                        &shortcut_results(&shortcuts),
+                    )
+                    .await
+                }
+
+                Err(e) => Err(e),
+            };
+
+            if let Err(e) = signalled {
+                log::warn!("failed to tell {} its shortcuts changed: {}", path, e);
+            }
+        }
+
+        self.grabber.grab(&bindings(&self.sessions));
+    }
+}
+
+/// Ask the running portal to show the settings of global shortcuts, for the `shortcuts`
+/// subcommand.
+pub async fn configure_shortcuts() -> zbus::Result<()> {
+    let conn = zbus::Connection::session().await?;
+
+    match ShortcutsProxy::new(&conn).await?.configure().await? {
+        0 => Ok(()),
+        1 => Err(zbus::Error::Failure(String::from("cancelled"))),
+        _ => Err(zbus::Error::Failure(String::from(
+            "the shortcuts weren't saved",
+        ))),
+    }
+}
+
+/// Tell apps about the triggers of their shortcuts being pressed and released, by the
+/// `events` of the grabber.
+pub async fn activate_shortcuts(conn: zbus::Connection, mut events: UnboundedReceiver<Event>) {
+    let sessions = match conn
+        .object_server()
+        .interface::<_, GlobalShortcuts>(PATH)
+        .await
+    {
+        Ok(iface) => iface.get().await.sessions.clone(),
+
+        Err(e) => {
+            log::error!("failed to signal global shortcuts: {}", e);
+            return;
+        }
+    };
+
+    let ctxt = match SignalContext::new(&conn, PATH) {
+        Ok(ctxt) => ctxt,
+
+        Err(e) => {
+            log::error!("failed to signal global shortcuts: {}", e);
+            return;
+        }
+    };
+
+    while let Some(event) = events.recv().await {
+        log::debug!(
+            "{} of {} pressed: {}",
+            event.id,
+            event.app_id,
+            event.pressed
+        );
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+
+        // Every session of the app that bound the shortcut is told.
+        for path in sessions.paths() {
+            let Ok(session_handle) = zvariant::ObjectPath::try_from(path.as_str()) else {
+                continue;
+            };
+
+            let bound = sessions.find(&session_handle, |session| {
+                session.app_id == event.app_id
+                    && session
+                        .shortcuts
+                        .iter()
+                        .any(|shortcut| shortcut.id == event.id)
+            });
+
+            if bound != Some(true) {
+                continue;
+            }
+
+            let signalled = match event.pressed {
+                true => {
+                    GlobalShortcuts::activated(
+                        &ctxt,
+                        session_handle,
+                        &event.id,
+                        timestamp,
+                        StrMap::new(),
+                    )
+                    .await
+                }
+
+                false => {
+                    GlobalShortcuts::deactivated(
+                        &ctxt,
+                        session_handle,
+                        &event.id,
+                        timestamp,
+                        StrMap::new(),
+                    )
+                    .await
+                }
+            };
+
+            if let Err(e) = signalled {
+                log::warn!("failed to tell {} about a shortcut: {}", event.app_id, e);
+            }
+        }
+    }
+}
+
+/// Get the triggers of the shortcuts bound by the open `sessions`.
+///
+/// Apps binding a shortcut in several sessions get it grabbed once.
+fn bindings(sessions: &Sessions<ShortcutSession>) -> Vec<Binding> {
+    let mut bindings: Vec<Binding> = Vec::new();
+
+    for path in sessions.paths() {
+        let Ok(path) = zvariant::ObjectPath::try_from(path.as_str()) else {
+            continue;
+        };
+
+        let session = sessions.find(&path, |session| {
+            (session.app_id.clone(), session.shortcuts.clone())
+        });
+
+        let Some((app_id, shortcuts)) = session else {
+            continue;
+        };
+
+        for shortcut in shortcuts {
+            let Some(trigger) = shortcut.trigger else {
+                continue;
+            };
+
+            let binding = Binding {
+                app_id: app_id.clone(),
+                id: shortcut.id,
+                description: shortcut.description,
+                trigger,
+            };
+
+            if !bindings.contains(&binding) {
+                bindings.push(binding);
+            }
+        }
+    }
+
+    bindings
+}
+
+/// Give `shortcuts` the triggers the shortcuts with the same ids have in `edited`.
+fn retrigger(shortcuts: &[Shortcut], edited: &[Shortcut]) -> Vec<Shortcut> {
+    shortcuts
+        .iter()
+        .map(
+            |shortcut| match edited.iter().find(|other| other.id == shortcut.id) {
+                Some(other) => Shortcut {
+                    trigger: other.trigger.clone(),
+                    ..shortcut.clone()
+                },
+
+                None => shortcut.clone(),
+            },
+        )
+        .collect()
+}
+
+/// Parse the `a(sa{sv})` shortcuts of BindShortcuts, leaving out those without an id.
+fn parse_shortcuts(shortcuts: &[(&str, StrMap<'_>)]) -> Vec<Requested> {
+    let text = |properties: &StrMap<'_>, key| match properties.get(key) {
+        Some(zvariant::Value::Str(text)) => Some(text.to_string()),
+        _ => None,
+    };
+
+    shortcuts
+        .iter()
+        .filter(|(id, _)| !id.is_empty())
+        .map(|(id, properties)| Requested {
+            id: String::from(*id),
+            description: text(properties, "description").unwrap_or_else(|| String::from(*id)),
+            preferred: text(properties, "preferred_trigger")
+                .as_deref()
+                .and_then(Trigger::parse),
+        })
+        .collect()
+}
+
+/// Describe the `a(sa{sv})` shortcuts result of BindShortcuts and ListShortcuts.
+fn shortcut_results(shortcuts: &[Shortcut]) -> Vec<(String, StrMap<'static>)> {
+    shortcuts
+        .iter()
+        .map(|shortcut| {
+            let trigger = match &shortcut.trigger {
+                Some(trigger) => trigger.describe(),
+                None => String::new(),
+            };
+
+            let properties = StrMap::from([
+                ("description", shortcut.description.clone().into()),
+                ("trigger_description", trigger.into()),
+            ]);
+
+            (shortcut.id.clone(), properties)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use zbus::zvariant;
+
+    use super::{parse_shortcuts, retrigger, shortcut_results, StrMap};
+    use crate::shortcuts::{self, Trigger};
+
+    #[test]
+    fn shortcuts() {
+        let talk = StrMap::from([
+            ("description", zvariant::Value::from("Push to talk")),
+            ("preferred_trigger", zvariant::Value::from("CTRL+t")),
+        ]);
+
+        let mute = StrMap::from([("preferred_trigger", zvariant::Value::from("CTRL+t"))]);
+
+        let requested = parse_shortcuts(&[("talk", talk), ("mute", mute), ("", StrMap::new())]);
+
+        assert_eq!(requested.len(), 2);
+        assert_eq!(requested[1].description, "mute");
+        assert_eq!(requested[0].preferred, Trigger::parse("CTRL+t"));
+
+        let (bound, _) = shortcuts::assign(&requested, &[], &[]);
+
+        assert_eq!(bound[1].trigger, None);
+
+        // Only the triggers of edited shortcuts change.
+        let mut edited = bound.clone();
+
+        edited[0].description = String::from("Shout");
+        edited[1].trigger = Trigger::parse("CTRL+m");
+
+        let bound = retrigger(&bound, &edited);
+
+        assert_eq!(bound[0].description, "Push to talk");
+        assert_eq!(bound[1].trigger, Trigger::parse("CTRL+m"));
+
+        let results = shortcut_results(&bound);
+
+        assert_eq!(results[0].0, "talk");
+        assert_eq!(
+            results[0].1["trigger_description"],
+            zvariant::Value::from("Ctrl+T")
+        );
+        assert_eq!(
+            results[1].1["trigger_description"],
+            zvariant::Value::from("Ctrl+M")
+        );
+    }
+}
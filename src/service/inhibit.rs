@@ -0,0 +1,543 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures_util::StreamExt;
+use zbus::{dbus_interface, zvariant, SignalContext};
+
+use super::{StrMap, PATH};
+use crate::{logind, request, session::Sessions};
+
+/// Inhibiting logging out.
+pub const LOGOUT: u32 = 1;
+
+/// Inhibiting switching to another user.
+pub const USER_SWITCH: u32 = 2;
+
+/// Inhibiting suspending.
+pub const SUSPEND: u32 = 4;
+
+/// Inhibiting marking the session idle, which blanks and locks the screen.
+pub const IDLE: u32 = 8;
+
+/// The session state of a session running as usual.
+const RUNNING: u32 = 1;
+
+/// The session state of a session about to end, which waits for monitors to answer.
+const QUERY_END: u32 = 2;
+
+/// The session state of a session ending.
+const ENDING: u32 = 3;
+
+/// How long monitors have to answer the session ending before it ends without them; logind
+/// waits 5 seconds for delay locks by default.
+const QUERY_END_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Inhibit implements the org.freedesktop.impl.portal.Inhibit interface.
+#[derive(Default)]
+pub struct Inhibit {
+    inhibitors: Arc<Inhibitors>,
+    monitors: Arc<Monitors>,
+
+    /// The system bus connection inhibitors take their locks from logind with.
+    system: tokio::sync::OnceCell<zbus::Connection>,
+}
+
+/// `Inhibitors` keeps what apps inhibit, by the handle of the request that asked for it.
+#[derive(Default)]
+struct Inhibitors {
+    inhibitors: Mutex<HashMap<zvariant::OwnedObjectPath, Inhibitor>>,
+}
+
+/// `Inhibitor` is an app inhibiting parts of the session.
+struct Inhibitor {
+    app_id: String,
+    flags: u32,
+
+    /// logind's lock, released as the inhibitor is dropped; `None` if logind has nothing to
+    /// lock for the flags, or isn't running.
+    _lock: Option<zvariant::OwnedFd>,
+}
+
+/// `Monitors` keeps the sessions of apps monitoring the session, and the state they're told.
+struct Monitors {
+    sessions: Arc<Sessions<Monitor>>,
+    state: Mutex<State>,
+
+    /// Notified as monitors answer the session ending, or go away.
+    answered: Arc<tokio::sync::Notify>,
+}
+
+/// `Monitor` is the session of an app monitoring the session.
+#[derive(Default)]
+struct Monitor {
+    /// Whether the app answered the session ending since it was last asked.
+    answered: bool,
+}
+
+/// `State` is the state of the session, as monitors are told it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct State {
+    screensaver_active: bool,
+    session_state: u32,
+}
+
+#[dbus_interface(name = "org.freedesktop.impl.portal.Inhibit")]
+impl Inhibit {
+    /// Inhibits logging out, user switching, suspending or idling, as `flags` say, until the
+    /// request at `handle` is closed.
+    async fn inhibit(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        _window: &str,
+        flags: u32,
+        options: StrMap<'_>,
+    ) -> zbus::fdo::Result<()> {
+        log::info!("inhibit({}, {}, {})", app_id, flags, describe(flags));
+
+        let reason = match options.get("reason") {
+            Some(zvariant::Value::Str(reason)) => reason.as_str(),
+            _ => "",
+        };
+
+        let lock = match what(flags) {
+            Some(what) => self.lock(&what, app_id, reason).await,
+            None => None,
+        };
+
+        let closed = request::hold(conn, &handle).await?;
+
+        let handle = zvariant::OwnedObjectPath::from(handle.to_owned());
+
+        self.inhibitors.add(handle.clone(), app_id, flags, lock);
+
+        let inhibitors = self.inhibitors.clone();
+
+        tokio::spawn(async move {
+            closed.await;
+
+            inhibitors.remove(&handle);
+        });
+
+        Ok(())
+    }
+
+    /// Starts telling an app how the session is, with StateChanged, until the session at
+    /// `session_handle` is closed.
+    #[dbus_interface(out_args("response"))]
+    async fn create_monitor(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        handle: zvariant::ObjectPath<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        _window: &str,
+    ) -> zbus::fdo::Result<u32> {
+        log::info!("create_monitor({}, {}, {})", handle, session_handle, app_id);
+
+        let answered = self.monitors.answered.clone();
+
+        // A monitor going away no longer holds the session ending off.
+        self.monitors
+            .sessions
+            .create(
+                conn,
+                &session_handle,
+                app_id,
+                Monitor::default(),
+                move |_| answered.notify_one(),
+            )
+            .await?;
+
+        let state = self.monitors.state();
+
+        Self::state_changed(&ctxt, session_handle, state.to_map()).await?;
+
+        Ok(0)
+    }
+
+    /// Tells that the app monitoring the session at `session_handle` is ready for the session
+    /// to end.
+    async fn query_end_response(
+        &self,
+        session_handle: zvariant::ObjectPath<'_>,
+    ) -> zbus::fdo::Result<()> {
+        log::info!("query_end_response({})", session_handle);
+
+        self.monitors
+            .sessions
+            .find(&session_handle, |monitor| monitor.answered = true);
+
+        self.monitors.answered.notify_one();
+
+        Ok(())
+    }
+
+    /// Tells the app monitoring the session at `session_handle` how the session is.
+    #[dbus_interface(signal)]
+    async fn state_changed(
+        ctxt: &SignalContext<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        state: StrMap<'_>,
+    ) -> zbus::Result<()>;
+}
+
+impl Inhibit {
+    /// Take a block lock from logind on `what`, like `sleep:idle`, for `app_id`.
+    ///
+    /// Without logind, apps still get their inhibitors, which then inhibit nothing.
+    async fn lock(&self, what: &str, app_id: &str, reason: &str) -> Option<zvariant::OwnedFd> {
+        let who = match app_id {
+            "" => "xdg-desktop-portal-rs",
+            app_id => app_id,
+        };
+
+        let why = match reason {
+            "" => "Requested through the Inhibit portal",
+            reason => reason,
+        };
+
+        let lock = async {
+            let system = self
+                .system
+                .get_or_try_init(zbus::Connection::system)
+                .await?;
+
+            let manager = logind::ManagerProxy::new(system).await?;
+
+            manager.inhibit(what, who, why, "block").await
+        };
+
+        lock.await
+            .map_err(|e| log::warn!("failed to inhibit {} for {}: {}", what, app_id, e))
+            .ok()
+    }
+}
+
+impl Inhibitors {
+    /// Record the inhibitor of `app_id` asked for at `handle`, holding logind's `lock`.
+    fn add(
+        &self,
+        handle: zvariant::OwnedObjectPath,
+        app_id: &str,
+        flags: u32,
+        lock: Option<zvariant::OwnedFd>,
+    ) {
+        let inhibitor = Inhibitor {
+            app_id: app_id.to_owned(),
+            flags,
+            _lock: lock,
+        };
+
+        if let Ok(mut inhibitors) = self.inhibitors.lock() {
+            inhibitors.insert(handle, inhibitor);
+        }
+    }
+
+    /// Release the inhibitor asked for at `handle`.
+    fn remove(&self, handle: &zvariant::OwnedObjectPath) {
+        let removed = match self.inhibitors.lock() {
+            Ok(mut inhibitors) => inhibitors.remove(handle),
+            Err(_) => None,
+        };
+
+        if let Some(inhibitor) = removed {
+            log::info!(
+                "{} stopped inhibiting {}, leaving {} inhibited",
+                inhibitor.app_id,
+                describe(inhibitor.flags),
+                describe(self.inhibited())
+            );
+        }
+    }
+
+    /// Get the flags of everything inhibited by any app.
+    fn inhibited(&self) -> u32 {
+        match self.inhibitors.lock() {
+            Ok(inhibitors) => inhibitors.values().fold(0, |flags, i| flags | i.flags),
+            Err(_) => 0,
+        }
+    }
+}
+
+impl Default for Monitors {
+    fn default() -> Self {
+        Self {
+            sessions: Arc::new(Sessions::new()),
+            state: Mutex::new(State {
+                screensaver_active: false,
+                session_state: RUNNING,
+            }),
+            answered: Arc::default(),
+        }
+    }
+}
+
+impl Monitors {
+    /// Get the state of the session.
+    fn state(&self) -> State {
+        match self.state.lock() {
+            Ok(state) => *state,
+
+            Err(_) => State {
+                screensaver_active: false,
+                session_state: RUNNING,
+            },
+        }
+    }
+
+    /// Change the state of the session with `f`, and tell the monitors.
+    async fn update(&self, ctxt: &SignalContext<'_>, f: impl FnOnce(&mut State)) {
+        let state = match self.state.lock() {
+            Ok(mut state) => {
+                f(&mut state);
+                *state
+            }
+
+            Err(_) => return,
+        };
+
+        for path in self.sessions.paths() {
+            let Ok(session_handle) = zvariant::ObjectPath::try_from(path.as_str()) else {
+                continue;
+            };
+
+            let signalled = Inhibit::state_changed(ctxt, session_handle, state.to_map()).await;
+
+            if let Err(e) = signalled {
+                log::warn!("failed to tell {} how the session is: {}", path, e);
+            }
+        }
+    }
+
+    /// Ask the monitors whether the session may end, and end it once they all answered or
+    /// `QUERY_END_TIMEOUT` elapsed.
+    async fn end(&self, ctxt: &SignalContext<'_>) {
+        for path in self.sessions.paths() {
+            if let Ok(path) = zvariant::ObjectPath::try_from(path.as_str()) {
+                self.sessions
+                    .find(&path, |monitor| monitor.answered = false);
+            }
+        }
+
+        self.update(ctxt, |state| state.session_state = QUERY_END)
+            .await;
+
+        let deadline = tokio::time::Instant::now() + QUERY_END_TIMEOUT;
+
+        while !self.all_answered() {
+            let answered = tokio::time::timeout_at(deadline, self.answered.notified()).await;
+
+            // Apps that never answer don't get to hold the session ending off.
+            if answered.is_err() {
+                log::warn!("monitors didn't answer the session ending in time");
+                break;
+            }
+        }
+
+        self.update(ctxt, |state| state.session_state = ENDING)
+            .await;
+    }
+
+    /// Check whether every monitor answered the session ending.
+    fn all_answered(&self) -> bool {
+        self.sessions.paths().iter().all(|path| {
+            let Ok(path) = zvariant::ObjectPath::try_from(path.as_str()) else {
+                return true;
+            };
+
+            self.sessions
+                .find(&path, |monitor| monitor.answered)
+                .unwrap_or(true)
+        })
+    }
+}
+
+impl State {
+    /// Describe the state as the `state` of StateChanged.
+    fn to_map(self) -> StrMap<'static> {
+        let mut map = StrMap::new();
+
+        map.insert("screensaver-active", self.screensaver_active.into());
+        map.insert("session-state", self.session_state.into());
+
+        map
+    }
+}
+
+/// Tell the monitors of the session when logind is about to suspend or shut the system down,
+/// and when the screen locks, holding suspending and shutting down off while monitors answer.
+///
+/// logind doesn't announce logging out, which compositors do on their own.
+pub async fn monitor_session(conn: zbus::Connection) {
+    if let Err(e) = watch_logind(&conn).await {
+        log::warn!("not telling apps about the session ending: {}", e);
+    }
+}
+
+/// Follow logind's signals for `monitor_session`, until logind goes away.
+async fn watch_logind(conn: &zbus::Connection) -> zbus::Result<()> {
+    let monitors = conn
+        .object_server()
+        .interface::<_, Inhibit>(PATH)
+        .await?
+        .get()
+        .await
+        .monitors
+        .clone();
+
+    let ctxt = SignalContext::new(conn, PATH)?;
+
+    let system = zbus::Connection::system().await?;
+
+    let manager = logind::ManagerProxy::new(&system).await?;
+    let session = logind::SessionProxy::new(&system).await?;
+
+    let mut sleep = manager.receive_prepare_for_sleep().await?;
+    let mut shutdown = manager.receive_prepare_for_shutdown().await?;
+    let mut locked = session.receive_locked_hint_changed().await;
+
+    let mut lock = delay(&manager).await;
+
+    loop {
+        tokio::select! {
+            Some(signal) = sleep.next() => {
+                match signal.args()?.start {
+                    true => {
+                        monitors.end(&ctxt).await;
+
+                        // Suspending waits for the delay lock until it's released.
+                        drop(lock.take());
+                    }
+
+                    false => {
+                        monitors.update(&ctxt, |state| state.session_state = RUNNING).await;
+
+                        if lock.is_none() {
+                            lock = delay(&manager).await;
+                        }
+                    }
+                }
+            }
+
+            Some(signal) = shutdown.next() => {
+                if signal.args()?.start {
+                    monitors.end(&ctxt).await;
+
+                    drop(lock.take());
+                }
+            }
+
+            Some(change) = locked.next() => {
+                if let Ok(active) = change.get().await {
+                    monitors.update(&ctxt, |state| state.screensaver_active = active).await;
+                }
+            }
+
+            else => return Ok(()),
+        }
+    }
+}
+
+/// Take a delay lock on suspending and shutting down, so monitors get to answer first.
+async fn delay(manager: &logind::ManagerProxy<'_>) -> Option<zvariant::OwnedFd> {
+    let lock = manager
+        .inhibit(
+            "sleep:shutdown",
+            "xdg-desktop-portal-rs",
+            "Telling apps the session ends",
+            "delay",
+        )
+        .await;
+
+    lock.map_err(|e| log::warn!("failed to take a delay lock: {}", e))
+        .ok()
+}
+
+/// Get what logind locks for inhibition `flags`, like `sleep:idle`.
+///
+/// logind can't hold logging out or switching users off, which the compositor does; logging
+/// out is inhibited as shutting down, which ends the session too.
+fn what(flags: u32) -> Option<String> {
+    let locks = [(LOGOUT, "shutdown"), (SUSPEND, "sleep"), (IDLE, "idle")];
+
+    let what: Vec<&str> = locks
+        .iter()
+        .filter(|(flag, _)| flags & flag != 0)
+        .map(|(_, what)| *what)
+        .collect();
+
+    (!what.is_empty()).then(|| what.join(":"))
+}
+
+/// Describe inhibition `flags` for the log, like `logout, suspend`.
+fn describe(flags: u32) -> String {
+    let names = [
+        (LOGOUT, "logout"),
+        (USER_SWITCH, "user switch"),
+        (SUSPEND, "suspend"),
+        (IDLE, "idle"),
+    ];
+
+    let names: Vec<&str> = names
+        .iter()
+        .filter(|(flag, _)| flags & flag != 0)
+        .map(|(_, name)| *name)
+        .collect();
+
+    match names.is_empty() {
+        true => String::from("nothing"),
+        false => names.join(", "),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zbus::zvariant;
+
+    use super::{describe, what, Inhibitors, State, IDLE, LOGOUT, QUERY_END, SUSPEND, USER_SWITCH};
+
+    #[test]
+    fn inhibitors() {
+        let inhibitors = Inhibitors::default();
+
+        let handle = |path: &str| zvariant::OwnedObjectPath::try_from(path).unwrap();
+
+        inhibitors.add(handle("/a"), "org.example.Player", SUSPEND | IDLE, None);
+        inhibitors.add(handle("/b"), "org.example.Editor", LOGOUT, None);
+
+        assert_eq!(inhibitors.inhibited(), LOGOUT | SUSPEND | IDLE);
+
+        inhibitors.remove(&handle("/a"));
+
+        assert_eq!(inhibitors.inhibited(), LOGOUT);
+
+        assert_eq!(describe(SUSPEND | IDLE), "suspend, idle");
+        assert_eq!(describe(0), "nothing");
+    }
+
+    #[test]
+    fn logind_locks() {
+        assert_eq!(what(SUSPEND | IDLE).as_deref(), Some("sleep:idle"));
+        assert_eq!(what(LOGOUT | USER_SWITCH).as_deref(), Some("shutdown"));
+        assert_eq!(what(USER_SWITCH), None);
+    }
+
+    #[test]
+    fn states() {
+        let state = State {
+            screensaver_active: true,
+            session_state: QUERY_END,
+        };
+
+        let map = state.to_map();
+
+        assert_eq!(map["screensaver-active"], zvariant::Value::from(true));
+        assert_eq!(map["session-state"], zvariant::Value::from(QUERY_END));
+    }
+}
@@ -0,0 +1,534 @@
+use std::{
+    os::fd::{FromRawFd, IntoRawFd},
+    sync::Arc,
+};
+
+use tokio::sync::mpsc::UnboundedReceiver;
+use zbus::{dbus_interface, zvariant, SignalContext};
+
+use super::{remotedesktop::devices, requester, show, StrMap, PATH};
+use crate::{
+    audit::{Audit, Outcome},
+    capture::Capture,
+    config::Config,
+    dialog::{DialogProvider, Message},
+    intercept::{Barrier, Event, Intercept, Zone},
+    request,
+    schedule::Scheduler,
+    session::Sessions,
+    window::ParentWindow,
+};
+
+/// InputCapture implements the org.freedesktop.impl.portal.InputCapture interface.
+pub struct InputCapture {
+    pub config: Arc<Config>,
+    pub dialogs: Arc<dyn DialogProvider>,
+    pub scheduler: Arc<Scheduler>,
+    pub audit: Arc<Audit>,
+    pub capture: Arc<dyn Capture>,
+    pub intercept: Arc<dyn Intercept>,
+    pub sessions: Arc<Sessions<CaptureSession>>,
+}
+
+/// `CaptureSession` is a session of an app capturing input, which the user allowed.
+#[derive(Default)]
+pub struct CaptureSession {
+    /// The zones the app was told about last, none until it asks.
+    zones: Vec<Zone>,
+
+    /// The id of `zones`, which changes along with them.
+    zone_set: u32,
+
+    /// The barriers set on the edges of `zones`.
+    barriers: Vec<Barrier>,
+
+    /// Whether input is captured once the pointer crosses a barrier.
+    enabled: bool,
+}
+
+#[dbus_interface(name = "org.freedesktop.impl.portal.InputCapture")]
+impl InputCapture {
+    /// The version of the interface this backend implements.
+    #[dbus_interface(property, name = "version")]
+    fn version(&self) -> u32 {
+        1
+    }
+
+    /// The capabilities input can be captured with.
+    #[dbus_interface(property)]
+    fn supported_capabilities(&self) -> u32 {
+        self.intercept.capabilities()
+    }
+
+    /// Create an input capture session, once the user allowed the app to capture the
+    /// capabilities it asked for.
+    #[dbus_interface(out_args("response", "results"))]
+    async fn create_session(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        handle: zvariant::ObjectPath<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        parent_window: &str,
+        options: StrMap<'_>,
+    ) -> zbus::fdo::Result<(u32, StrMap<'static>)> {
+        log::info!(
+            "create_session({}, {}, {}, {})",
+            handle,
+            session_handle,
+            app_id,
+            parent_window
+        );
+
+        let capabilities = match options.get("capabilities") {
+            Some(zvariant::Value::U32(capabilities)) => {
+                capabilities & self.intercept.capabilities()
+            }
+            _ => 0,
+        };
+
+        if capabilities == 0 {
+            log::warn!("rejecting {}, it captures nothing that can be", handle);
+            return zbus::fdo::Result::Ok((2, StrMap::new()));
+        }
+
+        let Some(ticket) = self.scheduler.ticket(app_id) else {
+            log::warn!("rejecting {}, {} already has a dialog open", handle, app_id);
+            return zbus::fdo::Result::Ok((2, StrMap::new()));
+        };
+
+        let message = Message {
+            title: String::from("Input Capture"),
+            description: format!(
+                "{} wants to take over your {} when the pointer leaves the screen, like to \
+                 control another computer with them.",
+                requester(app_id),
+                devices(capabilities)
+            ),
+            parent: ParentWindow::parse(parent_window),
+            accept_label: Some(String::from("Allow")),
+            reject_label: Some(String::from("Deny")),
+            ..Message::default()
+        };
+
+        let dialogs = self.dialogs.clone();
+
+        let dialog = show(move || Some(dialogs.confirm(&message)));
+
+        let timeout = self.config.dialog.timeout();
+
+        let response = match request::run(conn, &handle, timeout, ticket.run(dialog)).await? {
+            Some(Some(true)) => 0,
+            Some(_) => 1,
+            None => 2,
+        };
+
+        let outcome = match response {
+            0 => Outcome::Chosen,
+            1 => Outcome::Cancelled,
+            _ => Outcome::Failed,
+        };
+
+        self.audit.record(app_id, "InputCapture", outcome, &[]);
+
+        if response != 0 {
+            return zbus::fdo::Result::Ok((response, StrMap::new()));
+        }
+
+        let intercept = self.intercept.clone();
+        let path = session_handle.to_string();
+
+        self.sessions
+            .create(
+                conn,
+                &session_handle,
+                app_id,
+                CaptureSession::default(),
+                move |_| intercept.close(&path),
+            )
+            .await?;
+
+        let results = StrMap::from([("capabilities", capabilities.into())]);
+
+        zbus::fdo::Result::Ok((0, results))
+    }
+
+    /// List the zones of a session, the outputs as the compositor lays them out, which
+    /// barriers are set on the edges of.
+    #[dbus_interface(out_args("response", "results"))]
+    async fn get_zones(
+        &self,
+        handle: zvariant::ObjectPath<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        _options: StrMap<'_>,
+    ) -> zbus::fdo::Result<(u32, StrMap<'static>)> {
+        log::info!("get_zones({}, {}, {})", handle, session_handle, app_id);
+
+        let capture = self.capture.clone();
+
+        let zones = match show(move || zones(&*capture)).await? {
+            Ok(zones) => zones,
+
+            Err(e) => {
+                log::error!("failed to find the outputs: {}", e);
+                return zbus::fdo::Result::Ok((2, StrMap::new()));
+            }
+        };
+
+        let zone_set = self.sessions.with(&session_handle, app_id, |session| {
+            // Barriers on the edges of other zones are left behind.
+            if session.zones != zones {
+                session.zones = zones.clone();
+                session.zone_set += 1;
+                session.barriers.clear();
+
+                if session.enabled {
+                    self.intercept.watch(&session_handle, &[]);
+                }
+            }
+
+            session.zone_set
+        });
+
+        let Some(zone_set) = zone_set else {
+            return zbus::fdo::Result::Ok((2, StrMap::new()));
+        };
+
+        let zones: Vec<(u32, u32, i32, i32)> = zones
+            .iter()
+            .map(|zone| (zone.width, zone.height, zone.x, zone.y))
+            .collect();
+
+        let results = StrMap::from([("zones", zones.into()), ("zone_set", zone_set.into())]);
+
+        zbus::fdo::Result::Ok((0, results))
+    }
+
+    /// Set the barriers of a session on the edges of the zones of `zone_set`, replacing
+    /// those set before, and list the ids of the barriers that couldn't be set.
+    #[dbus_interface(out_args("response", "results"))]
+    async fn set_pointer_barriers(
+        &self,
+        handle: zvariant::ObjectPath<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        _options: StrMap<'_>,
+        barriers: Vec<StrMap<'_>>,
+        zone_set: u32,
+    ) -> zbus::fdo::Result<(u32, StrMap<'static>)> {
+        log::info!(
+            "set_pointer_barriers({}, {}, {}, {})",
+            handle,
+            session_handle,
+            app_id,
+            zone_set
+        );
+
+        let (barriers, mut failed) = parse_barriers(&barriers);
+
+        let fitting = self.sessions.with(&session_handle, app_id, |session| {
+            // Barriers of zones the app no longer knows fail, until it asks for the new ones.
+            if zone_set != session.zone_set {
+                return None;
+            }
+
+            let (fitting, misplaced): (Vec<Barrier>, Vec<Barrier>) = barriers
+                .iter()
+                .partition(|barrier| barrier.fits(&session.zones));
+
+            session.barriers = fitting;
+
+            if session.enabled {
+                self.intercept.watch(&session_handle, &session.barriers);
+            }
+
+            Some(misplaced)
+        });
+
+        match fitting {
+            Some(Some(misplaced)) => failed.extend(misplaced.iter().map(|barrier| barrier.id)),
+            Some(None) => failed.extend(barriers.iter().map(|barrier| barrier.id)),
+            None => return zbus::fdo::Result::Ok((2, StrMap::new())),
+        }
+
+        let results = StrMap::from([("failed_barriers", failed.into())]);
+
+        zbus::fdo::Result::Ok((0, results))
+    }
+
+    /// Start capturing input once the pointer crosses a barrier of a session.
+    #[dbus_interface(out_args("response", "results"))]
+    async fn enable(
+        &self,
+        session_handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        _options: StrMap<'_>,
+    ) -> zbus::fdo::Result<(u32, StrMap<'static>)> {
+        log::info!("enable({}, {})", session_handle, app_id);
+
+        let enabled = self.sessions.with(&session_handle, app_id, |session| {
+            session.enabled = true;
+            self.intercept.watch(&session_handle, &session.barriers);
+        });
+
+        match enabled {
+            Some(()) => zbus::fdo::Result::Ok((0, StrMap::new())),
+            None => zbus::fdo::Result::Ok((2, StrMap::new())),
+        }
+    }
+
+    /// Stop capturing input for a session, until it's enabled again.
+    #[dbus_interface(out_args("response", "results"))]
+    async fn disable(
+        &self,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        _options: StrMap<'_>,
+    ) -> zbus::fdo::Result<(u32, StrMap<'static>)> {
+        log::info!("disable({}, {})", session_handle, app_id);
+
+        let disabled = self.sessions.with(&session_handle, app_id, |session| {
+            self.intercept.watch(&session_handle, &[]);
+            std::mem::replace(&mut session.enabled, false)
+        });
+
+        match disabled {
+            Some(true) => {
+                Self::disabled(&ctxt, session_handle, StrMap::new()).await?;
+                zbus::fdo::Result::Ok((0, StrMap::new()))
+            }
+
+            Some(false) => zbus::fdo::Result::Ok((0, StrMap::new())),
+            None => zbus::fdo::Result::Ok((2, StrMap::new())),
+        }
+    }
+
+    /// Hand input back to the compositor after it was captured, with the pointer at
+    /// `cursor_position` if the app passes one.
+    #[dbus_interface(out_args("response", "results"))]
+    async fn release(
+        &self,
+        session_handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        options: StrMap<'_>,
+    ) -> zbus::fdo::Result<(u32, StrMap<'static>)> {
+        log::info!("release({}, {})", session_handle, app_id);
+
+        let position = options
+            .get("cursor_position")
+            .and_then(|position| <(f64, f64)>::try_from(position.clone()).ok());
+
+        let released = self.sessions.with(&session_handle, app_id, |_| {
+            self.intercept.release(&session_handle, position);
+        });
+
+        match released {
+            Some(()) => zbus::fdo::Result::Ok((0, StrMap::new())),
+            None => zbus::fdo::Result::Ok((2, StrMap::new())),
+        }
+    }
+
+    /// Get the socket of the EIS server the captured input of a session is sent to, as to a
+    /// libei receiver.
+    async fn connect_to_eis(
+        &self,
+        session_handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        _options: StrMap<'_>,
+    ) -> zbus::fdo::Result<zvariant::OwnedFd> {
+        log::info!("connect_to_eis({}, {})", session_handle, app_id);
+
+        let connected = self.sessions.with(&session_handle, app_id, |_| {
+            self.intercept.connect(&session_handle)
+        });
+
+        match connected {
+            // SAFETY: the descriptor is taken out of the OwnedFd owning it.
+            Some(Ok(fd)) => Ok(unsafe { zvariant::OwnedFd::from_raw_fd(fd.into_raw_fd()) }),
+
+            Some(Err(e)) => {
+                log::error!("failed to connect {} to captured input: {}", app_id, e);
+                Err(zbus::fdo::Error::Failed(e.to_string()))
+            }
+
+            None => Err(zbus::fdo::Error::AccessDenied(String::from(
+                "there's no such session",
+            ))),
+        }
+    }
+
+    /// Tells the app its session stopped capturing input.
+    #[dbus_interface(signal)]
+    async fn disabled(
+        ctxt: &SignalContext<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        options: StrMap<'_>,
+    ) -> zbus::Result<()>;
+
+    /// Tells the app the pointer crossed a barrier of its session, so input is captured.
+    #[dbus_interface(signal)]
+    async fn activated(
+        ctxt: &SignalContext<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        options: StrMap<'_>,
+    ) -> zbus::Result<()>;
+
+    /// Tells the app input is no longer captured for its session.
+    #[dbus_interface(signal)]
+    async fn deactivated(
+        ctxt: &SignalContext<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        options: StrMap<'_>,
+    ) -> zbus::Result<()>;
+
+    /// Tells the app the zones of its session changed, so it asks for them again.
+    #[dbus_interface(signal)]
+    async fn zones_changed(
+        ctxt: &SignalContext<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        options: StrMap<'_>,
+    ) -> zbus::Result<()>;
+}
+
+/// Tell apps about input being captured for their sessions and handed back, by the `events`
+/// of the interceptor.
+pub async fn signal_captures(conn: zbus::Connection, mut events: UnboundedReceiver<Event>) {
+    let ctxt = match SignalContext::new(&conn, PATH) {
+        Ok(ctxt) => ctxt,
+
+        Err(e) => {
+            log::error!("failed to signal captured input: {}", e);
+            return;
+        }
+    };
+
+    while let Some(event) = events.recv().await {
+        let (session, signalled) = match event {
+            Event::Activated {
+                session,
+                activation_id,
+                barrier_id,
+                position,
+            } => {
+                let options = StrMap::from([
+                    ("activation_id", activation_id.into()),
+                    ("cursor_position", position.into()),
+                    ("barrier_id", barrier_id.into()),
+                ]);
+
+                let signalled = match zvariant::ObjectPath::try_from(session.as_str()) {
+                    Ok(path) => InputCapture::activated(&ctxt, path, options).await,
+                    Err(e) => Err(e.into()),
+                };
+
+                (session, signalled)
+            }
+
+            Event::Deactivated {
+                session,
+                activation_id,
+            } => {
+                let options = StrMap::from([("activation_id", activation_id.into())]);
+
+                let signalled = match zvariant::ObjectPath::try_from(session.as_str()) {
+                    Ok(path) => InputCapture::deactivated(&ctxt, path, options).await,
+                    Err(e) => Err(e.into()),
+                };
+
+                (session, signalled)
+            }
+        };
+
+        if let Err(e) = signalled {
+            log::warn!("failed to signal captured input to {}: {}", session, e);
+        }
+    }
+}
+
+/// Get the zones of the outputs `capture` finds, as the compositor lays them out.
+fn zones(capture: &dyn Capture) -> std::io::Result<Vec<Zone>> {
+    let screen = capture.capture(false)?;
+
+    Ok(capture
+        .outputs(&screen)
+        .iter()
+        .map(|output| {
+            let (width, height) = output.size;
+            let (x, y) = output.position;
+
+            Zone {
+                width: width.max(0) as u32,
+                height: height.max(0) as u32,
+                x,
+                y,
+            }
+        })
+        .collect())
+}
+
+/// Parse the `aa{sv}` barriers of SetPointerBarriers, returning them and the ids of those
+/// that aren't straight lines.
+///
+/// Barriers without an id can't be told about, so they're left out.
+fn parse_barriers(barriers: &[StrMap<'_>]) -> (Vec<Barrier>, Vec<u32>) {
+    let mut parsed = Vec::new();
+    let mut failed = Vec::new();
+
+    for barrier in barriers {
+        let id = match barrier.get("barrier_id") {
+            Some(zvariant::Value::U32(id)) if *id != 0 => *id,
+            _ => continue,
+        };
+
+        let barrier = barrier
+            .get("position")
+            .and_then(|position| <(i32, i32, i32, i32)>::try_from(position.clone()).ok())
+            .and_then(|position| Barrier::new(id, position));
+
+        match barrier {
+            Some(barrier) => parsed.push(barrier),
+            None => failed.push(id),
+        }
+    }
+
+    (parsed, failed)
+}
+
+#[cfg(test)]
+mod tests {
+    use zbus::zvariant;
+
+    use super::{parse_barriers, Barrier, StrMap};
+
+    #[test]
+    fn barriers() {
+        let barrier = |id: u32, position: (i32, i32, i32, i32)| {
+            StrMap::from([
+                ("barrier_id", zvariant::Value::from(id)),
+                ("position", zvariant::Value::from(position)),
+            ])
+        };
+
+        let (barriers, failed) = parse_barriers(&[
+            barrier(1, (0, 1079, 0, 0)),
+            barrier(2, (0, 0, 1919, 1079)),
+            barrier(3, (5, 5, 5, 5)),
+            barrier(0, (1920, 0, 1920, 1079)),
+            StrMap::from([("barrier_id", zvariant::Value::from(4_u32))]),
+        ]);
+
+        // The ends are put in order.
+        assert_eq!(
+            barriers,
+            [Barrier {
+                id: 1,
+                position: (0, 0, 0, 1079),
+            }]
+        );
+
+        // Diagonal lines and points aren't barriers.
+        assert_eq!(failed, [2, 3, 4]);
+    }
+}
@@ -0,0 +1,159 @@
+use std::{collections::HashMap, fmt::Write, sync::Arc};
+
+use zbus::{
+    fdo,
+    names::{InterfaceName, MemberName},
+    zvariant::{OwnedValue, Value},
+    Connection, DispatchResult, Interface, Message, ObjectServer, SignalContext,
+};
+
+use crate::config::{Config, LockdownConfig};
+
+/// `Flag` reads one of the flags of the config.
+type Flag = fn(&LockdownConfig) -> bool;
+
+/// The properties of the interface, by name, with the flag of the config each one reads.
+///
+/// The names have dashes, which `dbus_interface` can't name properties with, so the interface
+/// is implemented by hand.
+const FLAGS: [(&str, Flag); 7] = [
+    ("disable-printing", |lockdown| lockdown.disable_printing),
+    ("disable-save-to-disk", |lockdown| {
+        lockdown.disable_save_to_disk
+    }),
+    ("disable-application-handlers", |lockdown| {
+        lockdown.disable_application_handlers
+    }),
+    ("disable-location", |lockdown| lockdown.disable_location),
+    ("disable-camera", |lockdown| lockdown.disable_camera),
+    ("disable-microphone", |lockdown| lockdown.disable_microphone),
+    ("disable-sound-output", |lockdown| {
+        lockdown.disable_sound_output
+    }),
+];
+
+/// Lockdown implements the org.freedesktop.impl.portal.Lockdown interface, which
+/// xdg-desktop-portal reads before letting apps print, pick where to save files, open other
+/// apps, or use the camera, microphone, location and sound output.
+///
+/// The flags come from the `[lockdown]` section of the config, so they can't be set here.
+pub struct Lockdown {
+    pub config: Arc<Config>,
+}
+
+impl Lockdown {
+    /// The flag of the property `name`, if there's one.
+    fn flag(&self, name: &str) -> Option<bool> {
+        FLAGS
+            .iter()
+            .find(|(flag, _)| *flag == name)
+            .map(|(_, read)| read(&self.config.lockdown))
+    }
+}
+
+#[zbus::export::async_trait::async_trait]
+impl Interface for Lockdown {
+    fn name() -> InterfaceName<'static> {
+        InterfaceName::from_static_str_unchecked("org.freedesktop.impl.portal.Lockdown")
+    }
+
+    async fn get(&self, property_name: &str) -> Option<fdo::Result<OwnedValue>> {
+        self.flag(property_name).map(|flag| Ok(flag.into()))
+    }
+
+    async fn get_all(&self) -> HashMap<String, OwnedValue> {
+        FLAGS
+            .iter()
+            .map(|(name, read)| (name.to_string(), read(&self.config.lockdown).into()))
+            .collect()
+    }
+
+    async fn set_mut(
+        &mut self,
+        property_name: &str,
+        _value: &Value<'_>,
+        _ctxt: &SignalContext<'_>,
+    ) -> Option<fdo::Result<()>> {
+        self.flag(property_name).map(|_| {
+            Err(fdo::Error::PropertyReadOnly(format!(
+                "{} is set in the config",
+                property_name
+            )))
+        })
+    }
+
+    fn call<'call>(
+        &'call self,
+        _server: &'call ObjectServer,
+        _connection: &'call Connection,
+        _msg: &'call Message,
+        _name: MemberName<'call>,
+    ) -> DispatchResult<'call> {
+        DispatchResult::NotFound
+    }
+
+    fn call_mut<'call>(
+        &'call mut self,
+        _server: &'call ObjectServer,
+        _connection: &'call Connection,
+        _msg: &'call Message,
+        _name: MemberName<'call>,
+    ) -> DispatchResult<'call> {
+        DispatchResult::NotFound
+    }
+
+    fn introspect_to_writer(&self, writer: &mut dyn Write, level: usize) {
+        let _ = writeln!(
+            writer,
+            "{:indent$}<interface name=\"{}\">",
+            "",
+            Self::name(),
+            indent = level
+        );
+
+        for (name, _) in FLAGS {
+            let _ = writeln!(
+                writer,
+                "{:indent$}<property name=\"{}\" type=\"b\" access=\"read\"/>",
+                "",
+                name,
+                indent = level + 2
+            );
+        }
+
+        let _ = writeln!(writer, "{:indent$}</interface>", "", indent = level);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use zbus::Interface;
+
+    use super::Lockdown;
+    use crate::config::Config;
+
+    #[test]
+    fn introspect() {
+        let mut config = Config::default();
+        config.lockdown.disable_camera = true;
+
+        let lockdown = Lockdown {
+            config: Arc::new(config),
+        };
+
+        assert_eq!(lockdown.flag("disable-camera"), Some(true));
+        assert_eq!(lockdown.flag("disable-printing"), Some(false));
+        assert_eq!(lockdown.flag("disable-everything"), None);
+
+        let mut xml = String::new();
+        lockdown.introspect_to_writer(&mut xml, 0);
+
+        assert!(xml.starts_with("<interface name=\"org.freedesktop.impl.portal.Lockdown\">"));
+        assert!(
+            xml.contains("  <property name=\"disable-sound-output\" type=\"b\" access=\"read\"/>")
+        );
+        assert_eq!(xml.matches("<property").count(), 7);
+    }
+}
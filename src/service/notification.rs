@@ -0,0 +1,480 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::mpsc::UnboundedReceiver;
+use zbus::{dbus_interface, zvariant, SignalContext};
+
+use super::{show, StrMap, PATH};
+use crate::{
+    config::{Config, NotificationRule},
+    notify::{self, Action, Activation, Button, Icon, Notifier, Priority, Sound},
+    uri,
+};
+
+/// Notification implements the org.freedesktop.impl.portal.Notification interface.
+pub struct Notification {
+    config: Arc<Config>,
+    notifier: Arc<dyn Notifier>,
+
+    /// The notifications shown, by app id and id.
+    shown: Mutex<HashMap<(String, String), notify::Notification>>,
+}
+
+impl Notification {
+    pub fn new(config: Arc<Config>, notifier: Arc<dyn Notifier>) -> Self {
+        Self {
+            config,
+            notifier,
+            shown: Mutex::default(),
+        }
+    }
+
+    /// Get the action the user invoked by `activation`, which is done with the notification.
+    fn activate(&self, activation: &Activation) -> Option<Action> {
+        let key = (activation.app_id.clone(), activation.id.clone());
+
+        let notification = self.shown.lock().ok()?.remove(&key)?;
+
+        match activation.button {
+            None => notification.default_action,
+
+            Some(index) => notification
+                .buttons
+                .into_iter()
+                .nth(index)
+                .map(|button| button.action),
+        }
+    }
+}
+
+#[dbus_interface(name = "org.freedesktop.impl.portal.Notification")]
+impl Notification {
+    /// The version of the interface this backend implements.
+    #[dbus_interface(property, name = "version")]
+    fn version(&self) -> u32 {
+        1
+    }
+
+    /// Show a notification of an app, replacing the one it showed with the same id.
+    async fn add_notification(
+        &self,
+        app_id: &str,
+        id: &str,
+        notification: StrMap<'_>,
+    ) -> zbus::fdo::Result<()> {
+        log::info!("add_notification({}, {})", app_id, id);
+
+        let mut notification = parse_notification(&notification);
+
+        match self.config.notification.rule(app_id) {
+            NotificationRule::Show => {}
+
+            NotificationRule::Downgrade => {
+                notification.priority = Priority::Low;
+                notification.sound = Some(Sound::Silent);
+            }
+
+            // Muted apps aren't told, as if the user dismissed their notifications right away.
+            NotificationRule::Mute => {
+                log::debug!("muted a notification of {}", app_id);
+                return Ok(());
+            }
+        }
+
+        let notifier = self.notifier.clone();
+
+        let shown = show({
+            let (app_id, id, notification) =
+                (app_id.to_owned(), id.to_owned(), notification.clone());
+            move || notifier.show(&app_id, &id, &notification)
+        })
+        .await?;
+
+        if let Err(e) = shown {
+            log::error!("failed to show a notification of {}: {}", app_id, e);
+            return Err(zbus::fdo::Error::Failed(e.to_string()));
+        }
+
+        if let Ok(mut shown) = self.shown.lock() {
+            shown.insert((app_id.to_owned(), id.to_owned()), notification);
+        }
+
+        Ok(())
+    }
+
+    /// Withdraw a notification of an app.
+    async fn remove_notification(&self, app_id: &str, id: &str) -> zbus::fdo::Result<()> {
+        log::info!("remove_notification({}, {})", app_id, id);
+
+        let removed = match self.shown.lock() {
+            Ok(mut shown) => shown.remove(&(app_id.to_owned(), id.to_owned())),
+            Err(_) => None,
+        };
+
+        // Apps may remove notifications that are long gone, which is nothing to withdraw.
+        if removed.is_none() {
+            return Ok(());
+        }
+
+        let notifier = self.notifier.clone();
+
+        let (app_id, id) = (app_id.to_owned(), id.to_owned());
+
+        show(move || notifier.withdraw(&app_id, &id)).await
+    }
+
+    /// Tells the app the user invoked an action of its notification, with the target of the
+    /// action as the parameter if it has one.
+    #[dbus_interface(signal)]
+    async fn action_invoked(
+        ctxt: &SignalContext<'_>,
+        app_id: &str,
+        id: &str,
+        action: &str,
+        parameter: Vec<zvariant::Value<'_>>,
+    ) -> zbus::Result<()>;
+}
+
+/// Tell apps about the actions the user invokes by the `activations` of their notifications.
+pub async fn invoke_actions(
+    conn: zbus::Connection,
+    mut activations: UnboundedReceiver<Activation>,
+) {
+    let iface = match conn
+        .object_server()
+        .interface::<_, Notification>(PATH)
+        .await
+    {
+        Ok(iface) => iface,
+
+        Err(e) => {
+            log::error!("failed to signal notification actions: {}", e);
+            return;
+        }
+    };
+
+    let ctxt = match SignalContext::new(&conn, PATH) {
+        Ok(ctxt) => ctxt,
+
+        Err(e) => {
+            log::error!("failed to signal notification actions: {}", e);
+            return;
+        }
+    };
+
+    while let Some(activation) = activations.recv().await {
+        log::info!("activated {} of {}", activation.id, activation.app_id);
+
+        // Notifications may be activated after the app withdrew them.
+        let Some(action) = iface.get().await.activate(&activation) else {
+            continue;
+        };
+
+        let parameter = action.target.into_iter().map(Into::into).collect();
+
+        let invoked = Notification::action_invoked(
+            &ctxt,
+            &activation.app_id,
+            &activation.id,
+            &action.name,
+            parameter,
+        )
+        .await;
+
+        if let Err(e) = invoked {
+            log::warn!(
+                "failed to tell {} about an action of its notification: {}",
+                activation.app_id,
+                e
+            );
+        }
+    }
+}
+
+/// Parse the notification vardict of AddNotification.
+///
+/// Buttons without a label or an action are left out.
+fn parse_notification(notification: &StrMap<'_>) -> notify::Notification {
+    let text = |key| match notification.get(key).map(unwrap) {
+        Some(zvariant::Value::Str(text)) => text.to_string(),
+        _ => String::new(),
+    };
+
+    let default_action = match notification.get("default-action").map(unwrap) {
+        Some(zvariant::Value::Str(name)) => Some(Action {
+            name: name.to_string(),
+            target: notification
+                .get("default-action-target")
+                .map(|target| unwrap(target).into()),
+        }),
+
+        _ => None,
+    };
+
+    let buttons = match notification.get("buttons").map(unwrap) {
+        Some(zvariant::Value::Array(buttons)) => buttons.iter().filter_map(parse_button).collect(),
+        _ => Vec::new(),
+    };
+
+    notify::Notification {
+        title: text("title"),
+        body: text("body"),
+        icon: notification
+            .get("icon")
+            .and_then(|icon| parse_icon(unwrap(icon))),
+        priority: match notification.get("priority").map(unwrap) {
+            Some(zvariant::Value::Str(priority)) => parse_priority(priority),
+            _ => Priority::default(),
+        },
+        sound: notification
+            .get("sound")
+            .and_then(|sound| parse_sound(unwrap(sound))),
+        default_action,
+        buttons,
+    }
+}
+
+/// Parse the `icon` of a notification, a serialized GIcon.
+///
+/// GIcons are serialized as `(sv)` of their kind and data, or as a string of an icon name or
+/// a URI.
+fn parse_icon(icon: &zvariant::Value<'_>) -> Option<Icon> {
+    let (kind, data) = match icon {
+        zvariant::Value::Str(name) if name.contains("://") => {
+            return uri::file_path(name).map(Icon::File)
+        }
+
+        zvariant::Value::Str(name) => return Some(Icon::Themed(vec![name.to_string()])),
+
+        zvariant::Value::Structure(icon) => match icon.fields() {
+            [zvariant::Value::Str(kind), data] => (kind.as_str(), unwrap(data)),
+            _ => return None,
+        },
+
+        _ => return None,
+    };
+
+    match (kind, data) {
+        ("themed", zvariant::Value::Array(names)) => {
+            let names = names
+                .iter()
+                .filter_map(|name| match name {
+                    zvariant::Value::Str(name) => Some(name.to_string()),
+                    _ => None,
+                })
+                .collect();
+
+            Some(Icon::Themed(names))
+        }
+
+        ("bytes", data) => Some(Icon::Bytes(data.clone().try_into().ok()?)),
+        ("file", zvariant::Value::Str(uri)) => uri::file_path(uri).map(Icon::File),
+
+        (kind, _) => {
+            log::debug!("ignoring a notification icon of kind {}", kind);
+            None
+        }
+    }
+}
+
+/// Parse the `priority` of a notification, which is normal unless it's one of the others.
+fn parse_priority(priority: &str) -> Priority {
+    match priority {
+        "low" => Priority::Low,
+        "high" => Priority::High,
+        "urgent" => Priority::Urgent,
+        _ => Priority::Normal,
+    }
+}
+
+/// Parse the `sound` of a notification, `default`, `silent` or a serialized sound file.
+fn parse_sound(sound: &zvariant::Value<'_>) -> Option<Sound> {
+    match sound {
+        zvariant::Value::Str(sound) if sound.as_str() == "default" => Some(Sound::Default),
+        zvariant::Value::Str(sound) if sound.as_str() == "silent" => Some(Sound::Silent),
+
+        zvariant::Value::Structure(sound) => match sound.fields() {
+            [zvariant::Value::Str(kind), uri] if kind.as_str() == "file" => match unwrap(uri) {
+                zvariant::Value::Str(uri) => uri::file_path(uri).map(Sound::File),
+                _ => None,
+            },
+
+            _ => None,
+        },
+
+        _ => None,
+    }
+}
+
+/// Parse a button of the `buttons` of a notification, an `a{sv}` of its label, action and
+/// target.
+fn parse_button(button: &zvariant::Value<'_>) -> Option<Button> {
+    let zvariant::Value::Dict(button) = unwrap(button) else {
+        return None;
+    };
+
+    let button: HashMap<String, zvariant::OwnedValue> = button.clone().try_into().ok()?;
+
+    let text = |key| match button.get(key).map(|value| unwrap(value)) {
+        Some(zvariant::Value::Str(text)) => Some(text.to_string()),
+        _ => None,
+    };
+
+    Some(Button {
+        label: text("label")?,
+        action: Action {
+            name: text("action")?,
+            target: button.get("target").map(|target| unwrap(target).into()),
+        },
+    })
+}
+
+/// Get the value inside a variant, which nested vardicts keep their values in.
+fn unwrap<'a>(value: &'a zvariant::Value<'a>) -> &'a zvariant::Value<'a> {
+    match value {
+        zvariant::Value::Value(value) => value,
+        value => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use zbus::zvariant;
+
+    use super::{parse_icon, parse_notification, StrMap};
+    use crate::notify::{
+        Action, Activation, Button, Icon, Notification, Notifier, Priority, Sound,
+    };
+
+    struct Silent;
+
+    impl Notifier for Silent {
+        fn show(&self, _: &str, _: &str, _: &Notification) -> std::io::Result<()> {
+            Ok(())
+        }
+
+        fn withdraw(&self, _: &str, _: &str) {}
+    }
+
+    #[test]
+    fn notifications() {
+        let buttons: Vec<HashMap<&str, zvariant::Value<'_>>> = vec![
+            HashMap::from([
+                ("label", zvariant::Value::from("Reply")),
+                ("action", zvariant::Value::from("reply")),
+                ("target", zvariant::Value::from(7u32)),
+            ]),
+            HashMap::from([("label", zvariant::Value::from("No action"))]),
+        ];
+
+        let mut notification = StrMap::new();
+
+        notification.insert("title", zvariant::Value::from("New message"));
+        notification.insert(
+            "icon",
+            zvariant::Value::from(("themed", zvariant::Value::from(vec!["mail-unread"]))),
+        );
+        notification.insert("priority", zvariant::Value::from("urgent"));
+        notification.insert("sound", zvariant::Value::from("silent"));
+        notification.insert("default-action", zvariant::Value::from("open"));
+        notification.insert("buttons", zvariant::Value::from(buttons));
+
+        // Pass it through a message, so it arrives the way apps send it.
+        let message = zbus::Message::method(
+            None::<&str>,
+            None::<&str>,
+            "/",
+            None::<&str>,
+            "Add",
+            &notification,
+        )
+        .unwrap();
+        let notification: StrMap<'_> = message.body().unwrap();
+
+        assert_eq!(
+            parse_notification(&notification),
+            Notification {
+                title: String::from("New message"),
+                body: String::new(),
+                icon: Some(Icon::Themed(vec![String::from("mail-unread")])),
+                priority: Priority::Urgent,
+                sound: Some(Sound::Silent),
+                default_action: Some(Action {
+                    name: String::from("open"),
+                    target: None,
+                }),
+                buttons: vec![Button {
+                    label: String::from("Reply"),
+                    action: Action {
+                        name: String::from("reply"),
+                        target: Some(zvariant::Value::from(7u32).into()),
+                    },
+                }],
+            }
+        );
+    }
+
+    #[test]
+    fn icons() {
+        let bytes = zvariant::Value::from(("bytes", zvariant::Value::from(vec![1u8, 2, 3])));
+        let file = zvariant::Value::from(("file", zvariant::Value::from("file:///tmp/a%20b.png")));
+
+        assert_eq!(parse_icon(&bytes), Some(Icon::Bytes(vec![1, 2, 3])));
+        assert_eq!(
+            parse_icon(&file),
+            Some(Icon::File(std::path::PathBuf::from("/tmp/a b.png")))
+        );
+        assert_eq!(
+            parse_icon(&zvariant::Value::from("mail-unread")),
+            Some(Icon::Themed(vec![String::from("mail-unread")]))
+        );
+    }
+
+    #[test]
+    fn activations() {
+        let action = |name: &str| Action {
+            name: name.to_owned(),
+            target: None,
+        };
+
+        let service = super::Notification::new(Arc::default(), Arc::new(Silent));
+
+        let notification = Notification {
+            default_action: Some(action("open")),
+            buttons: vec![Button {
+                label: String::from("Reply"),
+                action: action("reply"),
+            }],
+            ..Notification::default()
+        };
+
+        let activation = |id: &str, button| Activation {
+            app_id: String::from("org.example.App"),
+            id: id.to_owned(),
+            button,
+        };
+
+        for id in ["a", "b"] {
+            service.shown.lock().unwrap().insert(
+                (String::from("org.example.App"), id.to_owned()),
+                notification.clone(),
+            );
+        }
+
+        assert_eq!(
+            service.activate(&activation("a", None)),
+            Some(action("open"))
+        );
+        assert_eq!(
+            service.activate(&activation("b", Some(0))),
+            Some(action("reply"))
+        );
+
+        // Activated notifications are gone.
+        assert_eq!(service.activate(&activation("a", None)), None);
+    }
+}
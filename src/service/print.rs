@@ -0,0 +1,465 @@
+use std::{
+    io::Read,
+    os::fd::{AsRawFd, BorrowedFd},
+    sync::Arc,
+};
+
+use zbus::{dbus_interface, zvariant};
+
+use super::{requester, show, StrMap};
+use crate::{
+    audit::{Audit, Outcome},
+    config::Config,
+    dialog::{DialogProvider, Message, PrintRequest},
+    print::{self, Prepared, Printer, Settings},
+    request,
+    schedule::Scheduler,
+    window::ParentWindow,
+};
+
+/// Print implements the org.freedesktop.impl.portal.Print interface.
+pub struct Print {
+    pub config: Arc<Config>,
+    pub dialogs: Arc<dyn DialogProvider>,
+    pub scheduler: Arc<Scheduler>,
+    pub audit: Arc<Audit>,
+
+    /// The settings PreparePrint handed out tokens for.
+    pub prepared: Prepared,
+}
+
+#[dbus_interface(name = "org.freedesktop.impl.portal.Print")]
+impl Print {
+    /// Asks the user how to print a document, starting from the settings and page setup the
+    /// app has, and hands the app a token to print with them.
+    ///
+    /// The settings and page setup are the vardicts of GtkPrintSettings and GtkPageSetup,
+    /// which other toolkits speak too.
+    #[dbus_interface(out_args("response", "results"))]
+    #[allow(clippy::too_many_arguments)]
+    async fn prepare_print(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        parent_window: &str,
+        title: &str,
+        settings: StrMap<'_>,
+        page_setup: StrMap<'_>,
+        _options: StrMap<'_>,
+    ) -> zbus::fdo::Result<(u32, StrMap<'static>)> {
+        log::info!(
+            "prepare_print({}, {}, {}, {})",
+            handle,
+            app_id,
+            parent_window,
+            title
+        );
+
+        if self.config.lockdown.disable_printing {
+            log::warn!("rejecting {}, printing is locked down", handle);
+            return zbus::fdo::Result::Ok((2, StrMap::new()));
+        }
+
+        let printers = match show(print::printers).await? {
+            Ok(printers) if !printers.is_empty() => printers,
+
+            Ok(_) => {
+                log::warn!("rejecting {}, there are no printers", handle);
+                return zbus::fdo::Result::Ok((2, StrMap::new()));
+            }
+
+            Err(e) => {
+                log::error!("rejecting {}, failed to reach CUPS: {}", handle, e);
+                return zbus::fdo::Result::Ok((2, StrMap::new()));
+            }
+        };
+
+        let Some(ticket) = self.scheduler.ticket(app_id) else {
+            log::warn!("rejecting {}, {} already has a dialog open", handle, app_id);
+            return zbus::fdo::Result::Ok((2, StrMap::new()));
+        };
+
+        let title = match title {
+            "" => String::from("Untitled Document"),
+            title => String::from(title),
+        };
+
+        let request = PrintRequest {
+            message: Message {
+                title: String::from("Print"),
+                description: format!("{} wants to print “{}”.", requester(app_id), title),
+                parent: ParentWindow::parse(parent_window),
+                accept_label: Some(String::from("Print")),
+                ..Message::default()
+            },
+            settings: read_settings(&settings, &page_setup, &printers),
+            printers,
+        };
+
+        let dialogs = self.dialogs.clone();
+
+        let dialog = show(move || dialogs.prepare_print(&request));
+
+        let timeout = self.config.dialog.timeout();
+
+        let (response, results) =
+            match request::run(conn, &handle, timeout, ticket.run(dialog)).await? {
+                Some(Some(settings)) => {
+                    let mut results = results(&settings);
+
+                    let token = self.prepared.insert(app_id, settings);
+
+                    results.insert("token", token.into());
+
+                    (0, results)
+                }
+
+                Some(None) => (1, StrMap::new()),
+                None => (2, StrMap::new()),
+            };
+
+        let outcome = match response {
+            0 => Outcome::Chosen,
+            1 => Outcome::Cancelled,
+            _ => Outcome::Failed,
+        };
+
+        self.audit.record(app_id, "PreparePrint", outcome, &[]);
+
+        zbus::fdo::Result::Ok((response, results))
+    }
+
+    /// Prints the document in `fd` through CUPS.
+    ///
+    /// With the `token` of an earlier PreparePrint, the document is printed as the user set
+    /// up there without asking again; otherwise the user is asked to print it on the default
+    /// printer as it's set up.
+    #[dbus_interface(out_args("response", "results"))]
+    #[allow(clippy::too_many_arguments)]
+    async fn print(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        parent_window: &str,
+        title: &str,
+        fd: zvariant::Fd,
+        options: StrMap<'_>,
+    ) -> zbus::fdo::Result<(u32, StrMap<'static>)> {
+        log::info!(
+            "print({}, {}, {}, {})",
+            handle,
+            app_id,
+            parent_window,
+            title
+        );
+
+        if self.config.lockdown.disable_printing {
+            log::warn!("rejecting {}, printing is locked down", handle);
+            return zbus::fdo::Result::Ok((2, StrMap::new()));
+        }
+
+        // SAFETY: the file descriptors of a message stay open while it's handled.
+        let document = unsafe { BorrowedFd::borrow_raw(fd.as_raw_fd()) }
+            .try_clone_to_owned()
+            .map(std::fs::File::from)
+            .map_err(|e| zbus::fdo::Error::Failed(e.to_string()))?;
+
+        let prepared = match options.get("token") {
+            Some(zvariant::Value::U32(token)) => {
+                let prepared = self.prepared.take(app_id, *token);
+
+                if prepared.is_none() {
+                    log::warn!("{} passed the unknown print token {}", app_id, token);
+                }
+
+                prepared
+            }
+
+            _ => None,
+        };
+
+        let confirm = prepared.is_none();
+
+        let settings = match prepared {
+            Some(settings) => settings,
+
+            None => match show(print::default_printer).await? {
+                Ok(Some(printer)) => Settings::new(printer),
+
+                Ok(None) => {
+                    log::warn!("rejecting {}, there's no default printer", handle);
+                    return zbus::fdo::Result::Ok((2, StrMap::new()));
+                }
+
+                Err(e) => {
+                    log::error!("rejecting {}, failed to reach CUPS: {}", handle, e);
+                    return zbus::fdo::Result::Ok((2, StrMap::new()));
+                }
+            },
+        };
+
+        let Some(ticket) = self.scheduler.ticket(app_id) else {
+            log::warn!("rejecting {}, {} already has a dialog open", handle, app_id);
+            return zbus::fdo::Result::Ok((2, StrMap::new()));
+        };
+
+        let title = match title {
+            "" => String::from("Untitled Document"),
+            title => String::from(title),
+        };
+
+        let message = Message {
+            title: String::from("Print"),
+            description: format!(
+                "{} wants to print “{}” on {}.",
+                requester(app_id),
+                title,
+                settings.printer
+            ),
+            parent: ParentWindow::parse(parent_window),
+            accept_label: Some(String::from("Print")),
+            ..Message::default()
+        };
+
+        let dialogs = self.dialogs.clone();
+
+        let dialog = show(move || !confirm || dialogs.confirm(&message));
+
+        let timeout = self.config.dialog.timeout();
+
+        let response = match request::run(conn, &handle, timeout, ticket.run(dialog)).await? {
+            Some(true) => {
+                let submitted = show(move || {
+                    let mut bytes = Vec::new();
+
+                    (&document).read_to_end(&mut bytes)?;
+
+                    print::submit(&settings, &title, &bytes)
+                });
+
+                match submitted.await? {
+                    Ok(job) => {
+                        log::info!("printing {} as job {}", handle, job);
+                        0
+                    }
+
+                    Err(e) => {
+                        log::error!("failed to print {}: {}", handle, e);
+                        2
+                    }
+                }
+            }
+
+            Some(false) => 1,
+            None => 2,
+        };
+
+        let outcome = match response {
+            0 => Outcome::Chosen,
+            1 => Outcome::Cancelled,
+            _ => Outcome::Failed,
+        };
+
+        self.audit.record(app_id, "Print", outcome, &[]);
+
+        zbus::fdo::Result::Ok((response, StrMap::new()))
+    }
+}
+
+/// Read the settings an app starts PreparePrint with, on one of `printers`.
+///
+/// GtkPrintSettings keeps every value as a string, and counts pages from 0.
+fn read_settings(settings: &StrMap<'_>, page_setup: &StrMap<'_>, printers: &[Printer]) -> Settings {
+    let string = |map: &StrMap<'_>, key: &str| match map.get(key) {
+        Some(zvariant::Value::Str(value)) => Some(value.to_string()),
+        _ => None,
+    };
+
+    let printer = string(settings, "printer")
+        .and_then(|name| printers.iter().find(|printer| printer.name == name))
+        .or(printers.first());
+
+    let copies = string(settings, "n-copies")
+        .and_then(|copies| copies.parse().ok())
+        .filter(|copies| *copies > 0)
+        .unwrap_or(1);
+
+    let orientation = string(settings, "orientation").or_else(|| string(page_setup, "Orientation"));
+
+    let ranges = match string(settings, "print-pages").as_deref() {
+        Some("ranges") => string(settings, "page-ranges")
+            .map(|ranges| ranges.replace(' ', ""))
+            .and_then(|ranges| {
+                ranges
+                    .split(',')
+                    .map(|range| {
+                        let (first, last) = range.split_once('-').unwrap_or((range, range));
+                        Some((
+                            first.parse::<u32>().ok()? + 1,
+                            last.parse::<u32>().ok()? + 1,
+                        ))
+                    })
+                    .collect::<Option<Vec<_>>>()
+            })
+            .unwrap_or_default(),
+        _ => Vec::new(),
+    };
+
+    // Toolkits name paper sizes without their dimensions, like `iso_a4` for
+    // `iso_a4_210x297mm`.
+    let paper = string(page_setup, "Name").or_else(|| string(settings, "paper-format"));
+
+    let media = printer.zip(paper).and_then(|(printer, paper)| {
+        printer
+            .media
+            .iter()
+            .find(|media| **media == paper || media.starts_with(&format!("{}_", paper)))
+            .cloned()
+    });
+
+    Settings {
+        copies,
+        media,
+        landscape: orientation.is_some_and(|orientation| orientation.ends_with("landscape")),
+        ranges,
+        ..Settings::new(
+            printer
+                .map(|printer| printer.name.clone())
+                .unwrap_or_default(),
+        )
+    }
+}
+
+/// Describe the settings picked in PreparePrint as its `settings` and `page-setup` results.
+fn results(settings: &Settings) -> StrMap<'static> {
+    let orientation = match settings.landscape {
+        true => "landscape",
+        false => "portrait",
+    };
+
+    let mut print_settings: StrMap<'static> = StrMap::from([
+        ("printer", settings.printer.clone().into()),
+        ("n-copies", settings.copies.to_string().into()),
+        ("orientation", orientation.into()),
+    ]);
+
+    let mut page_setup: StrMap<'static> = StrMap::from([("Orientation", orientation.into())]);
+
+    match settings.ranges.as_slice() {
+        [] => {
+            print_settings.insert("print-pages", "all".into());
+        }
+
+        ranges => {
+            let ranges: Vec<String> = ranges
+                .iter()
+                .map(|(first, last)| format!("{}-{}", first - 1, last - 1))
+                .collect();
+
+            print_settings.insert("print-pages", "ranges".into());
+            print_settings.insert("page-ranges", ranges.join(",").into());
+        }
+    }
+
+    if let Some(media) = &settings.media {
+        let name = media
+            .rsplit_once('_')
+            .map_or(media.as_str(), |(name, _)| name);
+
+        print_settings.insert("paper-format", String::from(name).into());
+
+        page_setup.insert("Name", String::from(name).into());
+        page_setup.insert("DisplayName", print::media_name(media).into());
+
+        if let Some((width, height)) = print::media_size(media) {
+            print_settings.insert("paper-width", width.to_string().into());
+            print_settings.insert("paper-height", height.to_string().into());
+
+            page_setup.insert("Width", width.into());
+            page_setup.insert("Height", height.into());
+        }
+    }
+
+    StrMap::from([
+        ("settings", print_settings.into()),
+        ("page-setup", page_setup.into()),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use zbus::zvariant;
+
+    use super::{read_settings, results, StrMap};
+    use crate::print::{Printer, Settings};
+
+    #[test]
+    fn print_settings() {
+        let printers = [
+            Printer {
+                name: String::from("office"),
+                ..Printer::default()
+            },
+            Printer {
+                name: String::from("photo"),
+                media: vec![String::from("iso_a4_210x297mm")],
+                ..Printer::default()
+            },
+        ];
+
+        let settings = StrMap::from([
+            ("printer", zvariant::Value::from("photo")),
+            ("n-copies", zvariant::Value::from("2")),
+            ("print-pages", zvariant::Value::from("ranges")),
+            ("page-ranges", zvariant::Value::from("0-2,4")),
+        ]);
+
+        let page_setup = StrMap::from([
+            ("Name", zvariant::Value::from("iso_a4")),
+            ("Orientation", zvariant::Value::from("landscape")),
+        ]);
+
+        let read = read_settings(&settings, &page_setup, &printers);
+
+        assert_eq!(
+            read,
+            Settings {
+                copies: 2,
+                media: Some(String::from("iso_a4_210x297mm")),
+                landscape: true,
+                ranges: vec![(1, 3), (5, 5)],
+                ..Settings::new(String::from("photo"))
+            }
+        );
+
+        // What's picked reads back the same.
+        let results = results(&read);
+
+        let dict = |key| -> HashMap<String, zvariant::OwnedValue> {
+            results[key].clone().try_into().unwrap()
+        };
+
+        let (settings, page_setup) = (dict("settings"), dict("page-setup"));
+
+        fn borrow(map: &HashMap<String, zvariant::OwnedValue>) -> StrMap<'_> {
+            map.iter()
+                .map(|(key, value)| (key.as_str(), zvariant::Value::from(value.clone())))
+                .collect()
+        }
+
+        assert_eq!(
+            read_settings(&borrow(&settings), &borrow(&page_setup), &printers),
+            read
+        );
+        assert_eq!(page_setup["Width"], zvariant::OwnedValue::from(210.0));
+
+        // Unknown printers fall back to the first one.
+        let read = read_settings(&StrMap::new(), &StrMap::new(), &printers);
+
+        assert_eq!(read, Settings::new(String::from("office")));
+    }
+}
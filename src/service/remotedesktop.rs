@@ -0,0 +1,680 @@
+use std::sync::Arc;
+
+use tokio::sync::mpsc::UnboundedReceiver;
+use zbus::{dbus_interface, zvariant};
+
+use super::{
+    clipboard::{self, Shared},
+    requester,
+    screencast::{kinds, share, stream_results, CastSession},
+    show, StrMap,
+};
+use crate::{
+    audit::{Audit, Outcome},
+    capture::Capture,
+    cast::{Cast, Stream},
+    clipboard::{Clipboard, Event, Selection},
+    config::Config,
+    dialog::{DialogProvider, Message},
+    input::{Devices, Input, KEYBOARD, POINTER, TOUCHSCREEN},
+    request,
+    schedule::Scheduler,
+    session::Sessions,
+    window::ParentWindow,
+};
+
+/// RemoteDesktop implements the org.freedesktop.impl.portal.RemoteDesktop interface.
+///
+/// Its sessions are kept with those of ScreenCast, since the frontend calls ScreenCast's
+/// SelectSources on them to share the screen along with the input.
+pub struct RemoteDesktop {
+    pub config: Arc<Config>,
+    pub dialogs: Arc<dyn DialogProvider>,
+    pub scheduler: Arc<Scheduler>,
+    pub audit: Arc<Audit>,
+    pub capture: Arc<dyn Capture>,
+    pub cast: Arc<dyn Cast>,
+    pub input: Arc<dyn Input>,
+    pub clipboard: Arc<dyn Clipboard>,
+    pub sessions: Arc<Sessions<CastSession>>,
+}
+
+/// `Remote` is the input side of a remote desktop session.
+#[derive(Default)]
+pub struct Remote {
+    /// The device type bits the app asked to control.
+    types: u32,
+
+    /// The connected devices, once the session was started.
+    devices: Option<Box<dyn Devices>>,
+
+    pub(super) clipboard: Shared,
+}
+
+impl Remote {
+    /// Whether the session was started, after which what it controls can't change.
+    pub(super) fn started(&self) -> bool {
+        self.devices.is_some()
+    }
+}
+
+/// `Started` is what a session was started with, once the user allowed it.
+struct Started {
+    streams: Vec<Stream>,
+    devices: Box<dyn Devices>,
+
+    /// The session's end of the clipboard and what happens to it, if it asked for it and
+    /// the compositor shares it.
+    clipboard: Option<(Box<dyn Selection>, UnboundedReceiver<Event>)>,
+}
+
+#[dbus_interface(name = "org.freedesktop.impl.portal.RemoteDesktop")]
+impl RemoteDesktop {
+    /// The version of the interface this backend implements.
+    #[dbus_interface(property, name = "version")]
+    fn version(&self) -> u32 {
+        1
+    }
+
+    /// The types of devices that can be controlled.
+    #[dbus_interface(property)]
+    fn available_device_types(&self) -> u32 {
+        self.input.device_types()
+    }
+
+    /// Create a remote desktop session.
+    #[dbus_interface(out_args("response", "results"))]
+    async fn create_session(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        handle: zvariant::ObjectPath<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        _options: StrMap<'_>,
+    ) -> zbus::fdo::Result<(u32, StrMap<'_>)> {
+        log::info!("create_session({}, {}, {})", handle, session_handle, app_id);
+
+        let cast = self.cast.clone();
+
+        // The devices disconnect as they're dropped with the session.
+        let on_close = move |session: CastSession| {
+            if let Some(streams) = session.streams {
+                cast.stop(&streams);
+            }
+        };
+
+        // Apps that don't select devices get all of them.
+        let mut session = CastSession::default();
+
+        session.remote = Some(Remote {
+            types: self.input.device_types(),
+            ..Remote::default()
+        });
+
+        self.sessions
+            .create(conn, &session_handle, app_id, session, on_close)
+            .await?;
+
+        zbus::fdo::Result::Ok((0, StrMap::new()))
+    }
+
+    /// Configure what to control in a session.
+    #[dbus_interface(out_args("response", "results"))]
+    async fn select_devices(
+        &self,
+        handle: zvariant::ObjectPath<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        options: StrMap<'_>,
+    ) -> zbus::fdo::Result<(u32, StrMap<'_>)> {
+        log::info!("select_devices({}, {}, {})", handle, session_handle, app_id);
+
+        let available = self.input.device_types();
+
+        let selected = self.sessions.with(&session_handle, app_id, |session| {
+            match &mut session.remote {
+                // Devices can't change once the user allowed them.
+                Some(remote) if !remote.started() => {
+                    remote.types = parse_device_types(&options, available);
+                    true
+                }
+
+                _ => false,
+            }
+        });
+
+        match selected {
+            Some(true) => zbus::fdo::Result::Ok((0, StrMap::new())),
+            _ => zbus::fdo::Result::Ok((2, StrMap::new())),
+        }
+    }
+
+    /// Start a session, once the user allowed the app to control the devices and picked what
+    /// to share, if it asked to.
+    #[dbus_interface(out_args("response", "results"))]
+    async fn start(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        handle: zvariant::ObjectPath<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        parent_window: &str,
+        _options: StrMap<'_>,
+    ) -> zbus::fdo::Result<(u32, StrMap<'_>)> {
+        log::info!(
+            "start({}, {}, {}, {})",
+            handle,
+            session_handle,
+            app_id,
+            parent_window
+        );
+
+        let session = self.sessions.with(&session_handle, app_id, |session| {
+            let remote = session.remote.as_ref()?;
+
+            (!remote.started() && session.streams.is_none()).then(|| {
+                (
+                    remote.types,
+                    remote.clipboard.requested,
+                    session.sources_selected.then_some(session.selection),
+                )
+            })
+        });
+
+        let Some(Some((types, requested, selection))) = session else {
+            log::warn!("{} isn't a session {} can start", session_handle, app_id);
+            return zbus::fdo::Result::Ok((2, StrMap::new()));
+        };
+
+        if types == 0 {
+            log::warn!("rejecting {}, there are no devices to control", handle);
+            return zbus::fdo::Result::Ok((2, StrMap::new()));
+        }
+
+        let Some(ticket) = self.scheduler.ticket(app_id) else {
+            log::warn!("rejecting {}, {} already has a dialog open", handle, app_id);
+            return zbus::fdo::Result::Ok((2, StrMap::new()));
+        };
+
+        let control = match requested {
+            true => format!("control your {} and use your clipboard", devices(types)),
+            false => format!("control your {}", devices(types)),
+        };
+
+        let description = match selection {
+            Some(selection) if selection.multiple => format!(
+                "{} wants to {} and share your screen. Pick the {} to share.",
+                requester(app_id),
+                control,
+                kinds(selection.types)
+            ),
+            Some(selection) => format!(
+                "{} wants to {} and share your screen. Pick one of the {} to share.",
+                requester(app_id),
+                control,
+                kinds(selection.types)
+            ),
+            None => format!("{} wants to {}.", requester(app_id), control),
+        };
+
+        let message = Message {
+            title: String::from("Remote Desktop"),
+            description,
+            parent: ParentWindow::parse(parent_window),
+            accept_label: Some(String::from("Allow")),
+            ..Message::default()
+        };
+
+        let dialogs = self.dialogs.clone();
+
+        let capture = self.capture.clone();
+
+        let cast = self.cast.clone();
+
+        let input = self.input.clone();
+
+        let clipboard = self.clipboard.clone();
+
+        let dialog = show(move || {
+            // Picking what to share allows the input along with it.
+            let streams = match selection {
+                Some(selection) => {
+                    match share(&*dialogs, &capture, &*cast, selection, None, message)? {
+                        Ok((_, streams)) => streams,
+                        Err(e) => return Some(Err(e)),
+                    }
+                }
+
+                None => match dialogs.confirm(&message) {
+                    true => Vec::new(),
+                    false => return None,
+                },
+            };
+
+            let devices = match input.connect(types) {
+                Ok(devices) => devices,
+
+                Err(e) => {
+                    cast.stop(&streams);
+                    return Some(Err(e));
+                }
+            };
+
+            // Sessions still start without the clipboard, which Start tells the app.
+            let clipboard = match requested {
+                true => {
+                    let (events, receiver) = tokio::sync::mpsc::unbounded_channel();
+
+                    match clipboard.connect(events) {
+                        Ok(selection) => Some((selection, receiver)),
+
+                        Err(e) => {
+                            log::warn!("failed to share the clipboard: {}", e);
+                            None
+                        }
+                    }
+                }
+
+                false => None,
+            };
+
+            Some(Ok(Started {
+                streams,
+                devices,
+                clipboard,
+            }))
+        });
+
+        let timeout = self.config.dialog.timeout();
+
+        let result = match request::run(conn, &handle, timeout, ticket.run(dialog)).await? {
+            Some(Some(Ok(started))) => self.keep(conn, &session_handle, app_id, types, started),
+
+            Some(Some(Err(e))) => {
+                log::error!("failed to start the remote desktop session: {}", e);
+                (2, StrMap::new())
+            }
+
+            Some(None) => (1, StrMap::new()),
+            None => (2, StrMap::new()),
+        };
+
+        let outcome = match result.0 {
+            0 => Outcome::Chosen,
+            1 => Outcome::Cancelled,
+            _ => Outcome::Failed,
+        };
+
+        self.audit.record(app_id, "RemoteDesktop", outcome, &[]);
+
+        zbus::fdo::Result::Ok(result)
+    }
+
+    /// Move the pointer by `dx` and `dy`.
+    async fn notify_pointer_motion(
+        &self,
+        session_handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        _options: StrMap<'_>,
+        dx: f64,
+        dy: f64,
+    ) -> zbus::fdo::Result<()> {
+        self.notify(&session_handle, app_id, POINTER, |devices| {
+            devices.pointer_motion(dx, dy)
+        })
+    }
+
+    /// Move the pointer to `x` and `y` in a stream of the session.
+    async fn notify_pointer_motion_absolute(
+        &self,
+        session_handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        _options: StrMap<'_>,
+        stream: u32,
+        x: f64,
+        y: f64,
+    ) -> zbus::fdo::Result<()> {
+        let (x, y) = self.position(&session_handle, app_id, stream, x, y)?;
+
+        self.notify(&session_handle, app_id, POINTER, |devices| {
+            devices.pointer_motion_absolute(x, y)
+        })
+    }
+
+    /// Press or release a pointer button.
+    async fn notify_pointer_button(
+        &self,
+        session_handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        _options: StrMap<'_>,
+        button: i32,
+        state: u32,
+    ) -> zbus::fdo::Result<()> {
+        let button = code(button)?;
+
+        self.notify(&session_handle, app_id, POINTER, |devices| {
+            devices.pointer_button(button, state != 0)
+        })
+    }
+
+    /// Scroll smoothly by `dx` and `dy`.
+    async fn notify_pointer_axis(
+        &self,
+        session_handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        options: StrMap<'_>,
+        dx: f64,
+        dy: f64,
+    ) -> zbus::fdo::Result<()> {
+        let finish = matches!(options.get("finish"), Some(zvariant::Value::Bool(true)));
+
+        self.notify(&session_handle, app_id, POINTER, |devices| {
+            devices.pointer_axis(dx, dy, finish)
+        })
+    }
+
+    /// Scroll by wheel clicks.
+    async fn notify_pointer_axis_discrete(
+        &self,
+        session_handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        _options: StrMap<'_>,
+        axis: u32,
+        steps: i32,
+    ) -> zbus::fdo::Result<()> {
+        self.notify(&session_handle, app_id, POINTER, |devices| {
+            devices.pointer_axis_discrete(axis, steps)
+        })
+    }
+
+    /// Press or release a key, by evdev keycode.
+    async fn notify_keyboard_keycode(
+        &self,
+        session_handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        _options: StrMap<'_>,
+        keycode: i32,
+        state: u32,
+    ) -> zbus::fdo::Result<()> {
+        let keycode = code(keycode)?;
+
+        self.notify(&session_handle, app_id, KEYBOARD, |devices| {
+            devices.keyboard_keycode(keycode, state != 0)
+        })
+    }
+
+    /// Press or release the key typing an X11 keysym with the compositor's keymap.
+    async fn notify_keyboard_keysym(
+        &self,
+        session_handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        _options: StrMap<'_>,
+        keysym: i32,
+        state: u32,
+    ) -> zbus::fdo::Result<()> {
+        // Keysyms fit in 29 bits, so D-Bus's sign doesn't matter to them.
+        let keysym = keysym as u32;
+
+        self.notify(&session_handle, app_id, KEYBOARD, |devices| {
+            devices.keyboard_keysym(keysym, state != 0)
+        })
+    }
+
+    /// Put a finger down at `x` and `y` in a stream of the session.
+    #[allow(clippy::too_many_arguments)]
+    async fn notify_touch_down(
+        &self,
+        session_handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        _options: StrMap<'_>,
+        stream: u32,
+        slot: u32,
+        x: f64,
+        y: f64,
+    ) -> zbus::fdo::Result<()> {
+        let (x, y) = self.position(&session_handle, app_id, stream, x, y)?;
+
+        self.notify(&session_handle, app_id, TOUCHSCREEN, |devices| {
+            devices.touch_down(slot, x, y)
+        })
+    }
+
+    /// Move a finger that's down to `x` and `y` in a stream of the session.
+    #[allow(clippy::too_many_arguments)]
+    async fn notify_touch_motion(
+        &self,
+        session_handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        _options: StrMap<'_>,
+        stream: u32,
+        slot: u32,
+        x: f64,
+        y: f64,
+    ) -> zbus::fdo::Result<()> {
+        let (x, y) = self.position(&session_handle, app_id, stream, x, y)?;
+
+        self.notify(&session_handle, app_id, TOUCHSCREEN, |devices| {
+            devices.touch_motion(slot, x, y)
+        })
+    }
+
+    /// Lift a finger.
+    async fn notify_touch_up(
+        &self,
+        session_handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        _options: StrMap<'_>,
+        slot: u32,
+    ) -> zbus::fdo::Result<()> {
+        self.notify(&session_handle, app_id, TOUCHSCREEN, |devices| {
+            devices.touch_up(slot)
+        })
+    }
+}
+
+impl RemoteDesktop {
+    /// Keep the streams and devices of a started session, returning the results of Start.
+    ///
+    /// The app is told about the clipboard from then on, if the session shares it.
+    fn keep(
+        &self,
+        conn: &zbus::Connection,
+        session_handle: &zvariant::ObjectPath<'_>,
+        app_id: &str,
+        types: u32,
+        started: Started,
+    ) -> (u32, StrMap<'static>) {
+        let Started {
+            streams,
+            devices,
+            clipboard,
+        } = started;
+
+        let (selection, events) = clipboard.unzip();
+
+        let clipboard_enabled = selection.is_some();
+
+        let kept = self.sessions.with(session_handle, app_id, |session| {
+            session.streams = Some(streams.clone());
+
+            if let Some(remote) = &mut session.remote {
+                remote.devices = Some(devices);
+                remote.clipboard.selection = selection;
+            }
+        });
+
+        // The session may have been closed while the user was deciding.
+        if kept.is_none() {
+            self.cast.stop(&streams);
+            return (2, StrMap::new());
+        }
+
+        if let Some(events) = events {
+            tokio::spawn(clipboard::forward(
+                conn.clone(),
+                self.sessions.clone(),
+                session_handle.to_owned().into(),
+                events,
+            ));
+        }
+
+        let mut results = StrMap::new();
+
+        results.insert("devices", types.into());
+        results.insert("clipboard_enabled", clipboard_enabled.into());
+
+        if !streams.is_empty() {
+            results.insert("streams", stream_results(&streams).into());
+        }
+
+        (0, results)
+    }
+
+    /// Turn `x` and `y` in the `stream` of a session into a position in the compositor's layout.
+    fn position(
+        &self,
+        session_handle: &zvariant::ObjectPath<'_>,
+        app_id: &str,
+        stream: u32,
+        x: f64,
+        y: f64,
+    ) -> zbus::fdo::Result<(f64, f64)> {
+        let position = self
+            .sessions
+            .with(session_handle, app_id, |session| {
+                to_layout(session.streams.as_deref()?, stream, x, y)
+            })
+            .flatten();
+
+        position.ok_or_else(|| {
+            zbus::fdo::Error::InvalidArgs(format!(
+                "{} isn't a stream of a monitor in {}",
+                stream, session_handle
+            ))
+        })
+    }
+
+    /// Send input with the devices of a started session, as long as the user allowed the app
+    /// to control the device type `required`.
+    fn notify(
+        &self,
+        session_handle: &zvariant::ObjectPath<'_>,
+        app_id: &str,
+        required: u32,
+        f: impl FnOnce(&dyn Devices) -> std::io::Result<()>,
+    ) -> zbus::fdo::Result<()> {
+        let sent = self.sessions.with(session_handle, app_id, |session| {
+            let remote = session.remote.as_ref()?;
+
+            if remote.types & required == 0 {
+                return None;
+            }
+
+            Some(f(remote.devices.as_deref()?))
+        });
+
+        match sent.flatten() {
+            Some(result) => result.map_err(|e| zbus::fdo::Error::Failed(e.to_string())),
+
+            None => Err(zbus::fdo::Error::AccessDenied(format!(
+                "{} can't send this input to {}",
+                app_id, session_handle
+            ))),
+        }
+    }
+}
+
+/// Parse the `types` option of SelectDevices, keeping only the device types in `available`.
+///
+/// Apps that don't say get all of them.
+fn parse_device_types(options: &StrMap<'_>, available: u32) -> u32 {
+    match options.get("types") {
+        Some(zvariant::Value::U32(types)) => types & available,
+        _ => available,
+    }
+}
+
+/// Name the devices of the given `types` for people, like `keyboard and pointer`.
+pub(super) fn devices(types: u32) -> String {
+    let devices: Vec<&str> = [
+        (KEYBOARD, "keyboard"),
+        (POINTER, "pointer"),
+        (TOUCHSCREEN, "touchscreen"),
+    ]
+    .into_iter()
+    .filter(|(device, _)| types & device != 0)
+    .map(|(_, name)| name)
+    .collect();
+
+    match devices.as_slice() {
+        [] => String::from("input devices"),
+        [device] => String::from(*device),
+        [devices @ .., last] => format!("{} and {}", devices.join(", "), last),
+    }
+}
+
+/// Turn a position in the stream `node_id` into one in the compositor's layout.
+///
+/// Positions outside of the stream are moved to its edge. Windows have no place in the layout,
+/// so there's none in their streams.
+fn to_layout(streams: &[Stream], node_id: u32, x: f64, y: f64) -> Option<(f64, f64)> {
+    let stream = streams.iter().find(|stream| stream.node_id == node_id)?;
+
+    let (left, top) = stream.position?;
+
+    let (width, height) = stream.size;
+
+    Some((
+        f64::from(left) + x.clamp(0.0, f64::from(width)),
+        f64::from(top) + y.clamp(0.0, f64::from(height)),
+    ))
+}
+
+/// Check an evdev button or key code, which D-Bus passes signed.
+fn code(code: i32) -> zbus::fdo::Result<u32> {
+    u32::try_from(code)
+        .map_err(|_| zbus::fdo::Error::InvalidArgs(format!("{} isn't an evdev code", code)))
+}
+
+#[cfg(test)]
+mod tests {
+    use zbus::zvariant;
+
+    use super::{devices, parse_device_types, to_layout, StrMap};
+    use crate::cast::Stream;
+
+    #[test]
+    fn devices_and_positions() {
+        let mut options = StrMap::new();
+
+        // Touchscreens aren't available, so only the keyboard is kept.
+        options.insert("types", zvariant::Value::U32(1 | 4));
+
+        assert_eq!(parse_device_types(&options, 1 | 2), 1);
+        assert_eq!(parse_device_types(&StrMap::new(), 1 | 2), 1 | 2);
+
+        assert_eq!(devices(1), "keyboard");
+        assert_eq!(devices(1 | 2), "keyboard and pointer");
+        assert_eq!(devices(1 | 2 | 4), "keyboard, pointer and touchscreen");
+
+        let streams = [
+            Stream {
+                node_id: 42,
+                source_type: 1,
+                position: Some((1920, 0)),
+                size: (2560, 1440),
+            },
+            Stream {
+                node_id: 43,
+                source_type: 2,
+                position: None,
+                size: (800, 600),
+            },
+        ];
+
+        assert_eq!(to_layout(&streams, 42, 100.0, 50.5), Some((2020.0, 50.5)));
+        assert_eq!(to_layout(&streams, 42, 3000.0, -10.0), Some((4480.0, 0.0)));
+        assert_eq!(to_layout(&streams, 43, 10.0, 10.0), None);
+        assert_eq!(to_layout(&streams, 44, 10.0, 10.0), None);
+    }
+}
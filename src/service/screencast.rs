@@ -0,0 +1,753 @@
+use std::sync::Arc;
+
+use zbus::{dbus_interface, zvariant};
+
+use super::{remotedesktop::Remote, requester, show, StrMap};
+use crate::{
+    audit::{Audit, Outcome},
+    capture::{Capture, Image, Output, Rect},
+    cast::{Cast, Selection, Source, Stream, MONITOR, VIRTUAL, WINDOW},
+    config::Config,
+    dialog::{DialogProvider, Message, SourceRequest},
+    permissions, request,
+    restore::{Grant, Saved, Tokens, DONT_PERSIST, PERSISTENT, TRANSIENT},
+    schedule::Scheduler,
+    session::Sessions,
+    window::ParentWindow,
+};
+
+/// The permission store table holding whether apps may restore sessions without asking,
+/// which the user revokes by resetting it.
+const PERMISSION_TABLE: &str = "screencast-restore";
+
+/// The entry of `PERMISSION_TABLE` apps are allowed on.
+const PERMISSION_ID: &str = "restore";
+
+/// The vendor of the `restore_data` this backend hands out.
+const RESTORE_VENDOR: &str = "xdg-desktop-portal-rs";
+
+/// The version of the `restore_data` format.
+const RESTORE_VERSION: u32 = 1;
+
+/// ScreenCast implements the org.freedesktop.impl.portal.ScreenCast interface.
+pub struct ScreenCast {
+    config: Arc<Config>,
+    dialogs: Arc<dyn DialogProvider>,
+    scheduler: Arc<Scheduler>,
+    audit: Arc<Audit>,
+    capture: Arc<dyn Capture>,
+    cast: Arc<dyn Cast>,
+    sessions: Arc<Sessions<CastSession>>,
+    tokens: Arc<Tokens>,
+}
+
+/// `CastSession` is the state of a screen cast session, or of a remote desktop session,
+/// which streams the screen through ScreenCast's SelectSources too.
+#[derive(Default)]
+pub struct CastSession {
+    pub(super) selection: Selection,
+
+    /// Whether the app called SelectSources, which remote desktop sessions don't have to.
+    pub(super) sources_selected: bool,
+
+    /// How long the app wants to be able to restore the session.
+    persist_mode: u32,
+
+    /// The restore token the app passed to SelectSources, if any.
+    restore: Option<String>,
+
+    /// The streams, once the session was started.
+    pub(super) streams: Option<Vec<Stream>>,
+
+    /// The input side of a remote desktop session.
+    pub(super) remote: Option<Remote>,
+}
+
+#[dbus_interface(name = "org.freedesktop.impl.portal.ScreenCast")]
+impl ScreenCast {
+    /// The version of the interface this backend implements.
+    #[dbus_interface(property, name = "version")]
+    fn version(&self) -> u32 {
+        5
+    }
+
+    /// The types of sources that can be selected.
+    #[dbus_interface(property)]
+    fn available_source_types(&self) -> u32 {
+        self.cast.source_types()
+    }
+
+    /// The ways the pointer can be shown in streams.
+    #[dbus_interface(property)]
+    fn available_cursor_modes(&self) -> u32 {
+        self.cast.cursor_modes()
+    }
+
+    /// Create a screen cast session.
+    #[dbus_interface(out_args("response", "results"))]
+    async fn create_session(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        handle: zvariant::ObjectPath<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        _options: StrMap<'_>,
+    ) -> zbus::fdo::Result<(u32, StrMap<'_>)> {
+        log::info!("create_session({}, {}, {})", handle, session_handle, app_id);
+
+        let cast = self.cast.clone();
+
+        // Closing the session stops its streams; the frontend closes it when the app goes away.
+        let on_close = move |session: CastSession| {
+            if let Some(streams) = session.streams {
+                cast.stop(&streams);
+            }
+        };
+
+        self.sessions
+            .create(
+                conn,
+                &session_handle,
+                app_id,
+                CastSession::default(),
+                on_close,
+            )
+            .await?;
+
+        zbus::fdo::Result::Ok((0, StrMap::new()))
+    }
+
+    /// Configure what to stream in a session.
+    #[dbus_interface(out_args("response", "results"))]
+    async fn select_sources(
+        &self,
+        handle: zvariant::ObjectPath<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        options: StrMap<'_>,
+    ) -> zbus::fdo::Result<(u32, StrMap<'_>)> {
+        log::info!("select_sources({}, {}, {})", handle, session_handle, app_id);
+
+        let selected = self.sessions.with(&session_handle, app_id, |session| {
+            // Sources can't change under a running stream.
+            if session.streams.is_some() {
+                return false;
+            }
+
+            session.selection = parse_selection(&options, self.cast.source_types());
+            session.sources_selected = true;
+            session.persist_mode = parse_persist_mode(&options);
+            session.restore = parse_restore_data(&options);
+
+            true
+        });
+
+        match selected {
+            Some(true) => zbus::fdo::Result::Ok((0, StrMap::new())),
+            _ => zbus::fdo::Result::Ok((2, StrMap::new())),
+        }
+    }
+
+    /// Start the streams of a session, once the user picked what to share.
+    #[dbus_interface(out_args("response", "results"))]
+    async fn start(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        handle: zvariant::ObjectPath<'_>,
+        session_handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        parent_window: &str,
+        _options: StrMap<'_>,
+    ) -> zbus::fdo::Result<(u32, StrMap<'_>)> {
+        log::info!(
+            "start({}, {}, {}, {})",
+            handle,
+            session_handle,
+            app_id,
+            parent_window
+        );
+
+        // Remote desktop sessions are started by RemoteDesktop.
+        let selection = self.sessions.with(&session_handle, app_id, |session| {
+            (session.streams.is_none() && session.remote.is_none()).then(|| {
+                (
+                    session.selection,
+                    session.persist_mode,
+                    session.restore.take(),
+                )
+            })
+        });
+
+        let Some(Some((selection, persist_mode, token))) = selection else {
+            log::warn!("{} isn't a session {} can start", session_handle, app_id);
+            return zbus::fdo::Result::Ok((2, StrMap::new()));
+        };
+
+        let restored = match token {
+            Some(token) => self.restore(conn, &token, app_id).await,
+            None => None,
+        };
+
+        let Some(ticket) = self.scheduler.ticket(app_id) else {
+            log::warn!("rejecting {}, {} already has a dialog open", handle, app_id);
+            return zbus::fdo::Result::Ok((2, StrMap::new()));
+        };
+
+        let title = String::from("Screen Sharing");
+
+        let what = kinds(selection.types);
+
+        let mut description = match selection.multiple {
+            true => format!(
+                "{} wants to share your screen. Pick the {} to share.",
+                requester(app_id),
+                what
+            ),
+            false => format!(
+                "{} wants to share your screen. Pick one of the {} to share.",
+                requester(app_id),
+                what
+            ),
+        };
+
+        match persist_mode {
+            TRANSIENT => {
+                description.push_str(" It can share them again without asking until it quits.")
+            }
+            PERSISTENT => description.push_str(
+                " It can share them again without asking until you reset its permissions.",
+            ),
+            _ => {}
+        }
+
+        let message = Message {
+            title,
+            description,
+            parent: ParentWindow::parse(parent_window),
+            ..Message::default()
+        };
+
+        let dialogs = self.dialogs.clone();
+
+        let capture = self.capture.clone();
+
+        let cast = self.cast.clone();
+
+        let dialog = show(move || share(&*dialogs, &capture, &*cast, selection, restored, message));
+
+        let timeout = self.config.dialog.timeout();
+
+        let result = match request::run(conn, &handle, timeout, ticket.run(dialog)).await? {
+            Some(Some(Ok((sources, streams)))) => {
+                let (response, mut results) = self.keep(&session_handle, app_id, streams);
+
+                if response == 0 && persist_mode != DONT_PERSIST {
+                    if let Some((persist_mode, data)) =
+                        self.persist(conn, app_id, &sources, persist_mode).await
+                    {
+                        results.insert("persist_mode", persist_mode.into());
+                        results.insert("restore_data", data);
+                    }
+                }
+
+                (response, results)
+            }
+
+            Some(Some(Err(e))) => {
+                log::error!("failed to start streaming: {}", e);
+                (2, StrMap::new())
+            }
+
+            Some(None) => (1, StrMap::new()),
+            None => (2, StrMap::new()),
+        };
+
+        let outcome = match result.0 {
+            0 => Outcome::Chosen,
+            1 => Outcome::Cancelled,
+            _ => Outcome::Failed,
+        };
+
+        self.audit.record(app_id, "ScreenCast", outcome, &[]);
+
+        zbus::fdo::Result::Ok(result)
+    }
+}
+
+impl ScreenCast {
+    pub fn new(
+        config: Arc<Config>,
+        dialogs: Arc<dyn DialogProvider>,
+        scheduler: Arc<Scheduler>,
+        audit: Arc<Audit>,
+        capture: Arc<dyn Capture>,
+        cast: Arc<dyn Cast>,
+        sessions: Arc<Sessions<CastSession>>,
+    ) -> Self {
+        Self {
+            config,
+            dialogs,
+            scheduler,
+            audit,
+            capture,
+            cast,
+            sessions,
+            tokens: Arc::new(Tokens::new()),
+        }
+    }
+
+    /// Spend the restore `token` of `app_id`, returning the sources to share again.
+    ///
+    /// Persistent tokens are only honored while the app is still allowed in the permission
+    /// store; once the user reset it, they're all forgotten.
+    async fn restore(
+        &self,
+        conn: &zbus::Connection,
+        token: &str,
+        app_id: &str,
+    ) -> Option<Vec<Saved>> {
+        let (grant, persist_mode) = self.tokens.take(token, app_id)?;
+
+        if persist_mode == PERSISTENT {
+            match permissions::lookup(conn, PERMISSION_TABLE, PERMISSION_ID, app_id).await {
+                Ok(permissions) if permissions.iter().any(|p| p == "yes") => {}
+
+                Ok(_) => {
+                    log::info!("{} may no longer restore sessions", app_id);
+                    self.tokens.revoke(app_id);
+                    return None;
+                }
+
+                Err(e) => {
+                    log::warn!("failed to look up the permission of {}: {}", app_id, e);
+                    return None;
+                }
+            }
+        }
+
+        Some(grant.sources)
+    }
+
+    /// Issue a restore token for the `sources` of a started session.
+    ///
+    /// Returns the persist mode the token was issued with and the `restore_data` holding it.
+    async fn persist(
+        &self,
+        conn: &zbus::Connection,
+        app_id: &str,
+        sources: &[Source],
+        mut persist_mode: u32,
+    ) -> Option<(u32, zvariant::Value<'static>)> {
+        // Without the permission, a persistent token would be revoked as soon as it's used.
+        if persist_mode == PERSISTENT {
+            if let Err(e) =
+                permissions::set(conn, PERMISSION_TABLE, PERMISSION_ID, app_id, &["yes"]).await
+            {
+                log::warn!("failed to store the permission of {}: {}", app_id, e);
+                persist_mode = TRANSIENT;
+            }
+        }
+
+        let grant = Grant {
+            app_id: app_id.to_owned(),
+            sources: sources.iter().map(Saved::of).collect(),
+        };
+
+        match self.tokens.issue(grant, persist_mode) {
+            Ok(token) => Some((persist_mode, restore_data(token))),
+
+            Err(e) => {
+                log::warn!("failed to issue a restore token to {}: {}", app_id, e);
+                None
+            }
+        }
+    }
+
+    /// Keep the streams started for a session, returning the results of Start.
+    fn keep(
+        &self,
+        session_handle: &zvariant::ObjectPath<'_>,
+        app_id: &str,
+        streams: Vec<Stream>,
+    ) -> (u32, StrMap<'static>) {
+        let kept = self.sessions.with(session_handle, app_id, |session| {
+            session.streams = Some(streams.clone());
+        });
+
+        // The session may have been closed while the user was deciding.
+        if kept.is_none() {
+            self.cast.stop(&streams);
+            return (2, StrMap::new());
+        }
+
+        let mut results = StrMap::new();
+
+        results.insert("streams", stream_results(&streams).into());
+
+        (0, results)
+    }
+}
+
+/// Let the user pick what to share, as `message` asks, and start streaming it.
+///
+/// The `restored` sources are shared again without asking, as long as they're all still
+/// there. Returns None if the user cancelled.
+pub(super) fn share(
+    dialogs: &dyn DialogProvider,
+    capture: &Arc<dyn Capture>,
+    cast: &dyn Cast,
+    selection: Selection,
+    restored: Option<Vec<Saved>>,
+    message: Message,
+) -> Option<std::io::Result<(Vec<Source>, Vec<Stream>)>> {
+    let screen = match capture.capture(false) {
+        Ok(screen) => screen,
+        Err(e) => return Some(Err(e)),
+    };
+
+    let available = sources(&**capture, &screen, selection.types);
+
+    if available.is_empty() {
+        return Some(Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "nothing of the requested source types can be streamed",
+        )));
+    }
+
+    let restored = restored
+        .and_then(|saved| {
+            saved
+                .iter()
+                .map(|saved| saved.find(&available))
+                .collect::<Option<Vec<Source>>>()
+        })
+        .filter(|sources| !sources.is_empty())
+        .filter(|sources| selection.multiple || sources.len() == 1);
+
+    let mut sources = match restored {
+        Some(sources) => sources,
+
+        None => {
+            let request = SourceRequest {
+                title: message.title.clone(),
+                description: message.description,
+                parent: message.parent.clone(),
+                sources: available,
+                multiple: selection.multiple,
+                capture: capture.clone(),
+            };
+
+            let picked = dialogs.pick_sources(&request)?;
+
+            let mut sources: Vec<Source> = picked
+                .into_iter()
+                .filter_map(|index| request.sources.get(index).cloned())
+                .collect();
+
+            // Parts of the screen are selected over a still of it once they're picked.
+            for source in &mut sources {
+                if let Source::Region(area) = source {
+                    let message = Message {
+                        title: message.title.clone(),
+                        description: String::from("Select the part of the screen to share."),
+                        parent: message.parent.clone(),
+                        accept_label: Some(String::from("Share")),
+                        ..Message::default()
+                    };
+
+                    let windows = capture.windows(&screen);
+
+                    *area = dialogs.select_region(&message, &screen, &windows)?;
+                }
+            }
+
+            sources
+        }
+    };
+
+    let outputs = capture.outputs(&screen);
+
+    for source in &mut sources {
+        if let Source::Region(area) = source {
+            *area = on_monitor(*area, &screen, &outputs);
+
+            // Selecting nothing is taken as changing one's mind.
+            if area.width == 0 || area.height == 0 {
+                return None;
+            }
+        }
+    }
+
+    Some(
+        cast.start(&selection, &sources)
+            .map(|streams| (sources, streams)),
+    )
+}
+
+/// Parse the options of SelectSources, keeping only the source types in `available`.
+fn parse_selection(options: &StrMap<'_>, available: u32) -> Selection {
+    let defaults = Selection::default();
+
+    let types = match options.get("types") {
+        Some(zvariant::Value::U32(types)) => types & available,
+        _ => defaults.types,
+    };
+
+    let multiple = matches!(options.get("multiple"), Some(zvariant::Value::Bool(true)));
+
+    let cursor_mode = match options.get("cursor_mode") {
+        Some(zvariant::Value::U32(mode)) => *mode,
+        _ => defaults.cursor_mode,
+    };
+
+    Selection {
+        types,
+        multiple,
+        cursor_mode,
+    }
+}
+
+/// Parse the `persist_mode` option of SelectSources, which is `DONT_PERSIST` if it's missing.
+fn parse_persist_mode(options: &StrMap<'_>) -> u32 {
+    match options.get("persist_mode") {
+        Some(zvariant::Value::U32(mode)) => (*mode).min(PERSISTENT),
+        _ => DONT_PERSIST,
+    }
+}
+
+/// Get the restore token out of the `(suv)` `restore_data` option of SelectSources.
+///
+/// Data of other backends, or of other versions of this one, is ignored.
+fn parse_restore_data(options: &StrMap<'_>) -> Option<String> {
+    let Some(zvariant::Value::Structure(data)) = options.get("restore_data") else {
+        return None;
+    };
+
+    match data.fields() {
+        [zvariant::Value::Str(vendor), zvariant::Value::U32(RESTORE_VERSION), zvariant::Value::Value(token)]
+            if vendor.as_str() == RESTORE_VENDOR =>
+        {
+            match &**token {
+                zvariant::Value::Str(token) => Some(token.to_string()),
+                _ => None,
+            }
+        }
+
+        _ => None,
+    }
+}
+
+/// Wrap a restore token as the `(suv)` `restore_data` result of Start.
+fn restore_data(token: String) -> zvariant::Value<'static> {
+    zvariant::StructureBuilder::new()
+        .add_field(RESTORE_VENDOR)
+        .add_field(RESTORE_VERSION)
+        .append_field(zvariant::Value::Value(Box::new(token.into())))
+        .build()
+        .into()
+}
+
+/// Name the kinds of sources of the given `types` for people, like `monitors or windows`.
+pub(super) fn kinds(types: u32) -> String {
+    let kinds: Vec<&str> = [
+        (MONITOR, "monitors"),
+        (WINDOW, "windows"),
+        (VIRTUAL, "virtual displays"),
+    ]
+    .into_iter()
+    .filter(|(kind, _)| types & kind != 0)
+    .map(|(_, name)| name)
+    .collect();
+
+    match kinds.as_slice() {
+        [] => String::from("monitors"),
+        [kind] => String::from(*kind),
+        [kinds @ .., last] => format!("{} or {}", kinds.join(", "), last),
+    }
+}
+
+/// Clip a part of `screen` to the monitor it starts on, so its stream doesn't span the gaps
+/// between monitors.
+fn on_monitor(region: Rect, screen: &Image, outputs: &[Output]) -> Rect {
+    let monitor = outputs
+        .iter()
+        .map(|output| output.area)
+        .find(|area| area.contains(region.x, region.y))
+        .unwrap_or_else(|| screen.bounds());
+
+    monitor.clip(&region)
+}
+
+/// List the sources of the given `types` in a capture of `screen`, monitors and a part of the
+/// screen first and a new virtual display last.
+fn sources(capture: &dyn Capture, screen: &Image, types: u32) -> Vec<Source> {
+    let mut sources = Vec::new();
+
+    if types & MONITOR != 0 {
+        sources.extend(capture.outputs(screen).into_iter().map(Source::Monitor));
+        sources.push(Source::Region(screen.bounds()));
+    }
+
+    if types & WINDOW != 0 {
+        sources.extend(capture.toplevels(screen).into_iter().map(Source::Window));
+    }
+
+    if types & VIRTUAL != 0 {
+        sources.push(Source::Virtual);
+    }
+
+    sources
+}
+
+/// Describe streams as the `a(ua{sv})` `streams` result of Start.
+pub(super) fn stream_results(streams: &[Stream]) -> Vec<(u32, StrMap<'static>)> {
+    streams
+        .iter()
+        .map(|stream| {
+            let mut properties = StrMap::new();
+
+            properties.insert("source_type", stream.source_type.into());
+            properties.insert("size", stream.size.into());
+
+            // Windows have no place in the layout.
+            if let Some(position) = stream.position {
+                properties.insert("position", position.into());
+            }
+
+            (stream.node_id, properties)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use zbus::zvariant;
+
+    use super::{
+        kinds, on_monitor, parse_persist_mode, parse_restore_data, parse_selection, restore_data,
+        stream_results, StrMap,
+    };
+    use crate::{
+        capture::{Image, Output, Rect},
+        cast::{Selection, Stream},
+    };
+
+    #[test]
+    fn selection_and_streams() {
+        let mut options = StrMap::new();
+
+        options.insert("types", zvariant::Value::U32(1 | 2));
+        options.insert("multiple", zvariant::Value::Bool(true));
+        options.insert("cursor_mode", zvariant::Value::U32(2));
+
+        // Window sources aren't available, so only monitors are kept.
+        assert_eq!(
+            parse_selection(&options, 1),
+            Selection {
+                types: 1,
+                multiple: true,
+                cursor_mode: 2
+            }
+        );
+        assert_eq!(parse_selection(&StrMap::new(), 1), Selection::default());
+
+        assert_eq!(kinds(2), "windows");
+        assert_eq!(kinds(1 | 2 | 4), "monitors, windows or virtual displays");
+
+        let streams = stream_results(&[
+            Stream {
+                node_id: 42,
+                source_type: 1,
+                position: Some((1920, 0)),
+                size: (2560, 1440),
+            },
+            Stream {
+                node_id: 43,
+                source_type: 2,
+                position: None,
+                size: (800, 600),
+            },
+        ]);
+
+        assert_eq!(streams.len(), 2);
+        assert_eq!(streams[0].0, 42);
+        assert_eq!(
+            streams[0].1.get("position"),
+            Some(&zvariant::Value::from((1920, 0)))
+        );
+        assert_eq!(streams[1].1.get("position"), None);
+    }
+
+    #[test]
+    fn regions_on_monitors() {
+        let screen = Image {
+            width: 300,
+            height: 100,
+            pixels: vec![0; 300 * 100 * 4],
+        };
+
+        let monitor = |x: u32, width: u32| Output {
+            area: Rect {
+                x,
+                y: 0,
+                width,
+                height: 100,
+            },
+            ..Output::default()
+        };
+
+        let outputs = [monitor(0, 100), monitor(100, 200)];
+
+        let region = Rect {
+            x: 50,
+            y: 50,
+            width: 100,
+            height: 100,
+        };
+
+        // The region is cut where its monitor ends.
+        assert_eq!(
+            on_monitor(region, &screen, &outputs),
+            Rect {
+                x: 50,
+                y: 50,
+                width: 50,
+                height: 50
+            }
+        );
+        assert_eq!(
+            on_monitor(Rect { x: 120, ..region }, &screen, &outputs),
+            Rect {
+                x: 120,
+                y: 50,
+                width: 100,
+                height: 50
+            }
+        );
+    }
+
+    #[test]
+    fn restore_options() {
+        let mut options = StrMap::new();
+
+        options.insert("persist_mode", zvariant::Value::U32(7));
+        options.insert("restore_data", restore_data(String::from("0123")));
+
+        assert_eq!(parse_persist_mode(&options), 2);
+        assert_eq!(parse_restore_data(&options), Some(String::from("0123")));
+
+        // Data another backend handed out isn't ours to restore.
+        options.insert(
+            "restore_data",
+            zvariant::StructureBuilder::new()
+                .add_field("gnome")
+                .add_field(1u32)
+                .append_field(zvariant::Value::Value(Box::new("0123".into())))
+                .build()
+                .into(),
+        );
+
+        assert_eq!(parse_restore_data(&options), None);
+        assert_eq!(parse_persist_mode(&StrMap::new()), 0);
+    }
+}
@@ -0,0 +1,380 @@
+use std::{path::PathBuf, sync::Arc};
+
+use zbus::{dbus_interface, zvariant};
+
+use super::{numbered_path, requester, show, StrMap};
+use crate::{
+    audit::{Audit, Outcome},
+    capture::Capture,
+    config::Config,
+    dialog::{DialogProvider, Message, ScreenshotOptions},
+    permissions, policy, request,
+    schedule::Scheduler,
+    uri,
+    window::ParentWindow,
+};
+
+/// The permission store table holding whether apps may take screenshots without asking,
+/// shared with the other portal backends.
+const PERMISSION_TABLE: &str = "screenshot";
+
+/// The entry of `PERMISSION_TABLE` apps are allowed or denied on.
+const PERMISSION_ID: &str = "screenshot";
+
+/// Screenshot implements the org.freedesktop.impl.portal.Screenshot interface.
+pub struct Screenshot {
+    pub config: Arc<Config>,
+    pub dialogs: Arc<dyn DialogProvider>,
+    pub scheduler: Arc<Scheduler>,
+    pub audit: Arc<Audit>,
+    pub capture: Arc<dyn Capture>,
+}
+
+#[dbus_interface(name = "org.freedesktop.impl.portal.Screenshot")]
+impl Screenshot {
+    /// The version of the interface this backend implements.
+    #[dbus_interface(property, name = "version")]
+    fn version(&self) -> u32 {
+        2
+    }
+
+    /// Takes a screenshot.
+    #[dbus_interface(out_args("response", "results"))]
+    async fn screenshot(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        parent_window: &str,
+        options: StrMap<'_>,
+    ) -> zbus::fdo::Result<(u32, StrMap<'_>)> {
+        let result = self
+            .take(conn, &handle, app_id, parent_window, options)
+            .await;
+
+        let (outcome, uris) = match &result {
+            Ok((0, results)) => match results.get("uri") {
+                Some(zvariant::Value::Str(uri)) => (Outcome::Chosen, vec![uri.to_string()]),
+                _ => (Outcome::Chosen, Vec::new()),
+            },
+            Ok((1, _)) => (Outcome::Cancelled, Vec::new()),
+            _ => (Outcome::Failed, Vec::new()),
+        };
+
+        self.audit.record(app_id, "Screenshot", outcome, &uris);
+
+        result
+    }
+
+    /// Obtains the color of a single pixel.
+    #[dbus_interface(out_args("response", "results"))]
+    async fn pick_color(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        parent_window: &str,
+        _options: StrMap<'_>,
+    ) -> zbus::fdo::Result<(u32, StrMap<'_>)> {
+        log::info!("pick_color({}, {}, {})", handle, app_id, parent_window);
+
+        let Some(ticket) = self.scheduler.ticket(app_id) else {
+            log::warn!("rejecting {}, {} already has a dialog open", handle, app_id);
+            return zbus::fdo::Result::Ok((2, StrMap::new()));
+        };
+
+        let capture = self.capture.clone();
+
+        let dialogs = self.dialogs.clone();
+
+        // The picker shows a still of the screen, so it's captured before the picker opens.
+        let dialog = show(move || {
+            // The pointer would cover the pixels around the one picked.
+            let screen = capture
+                .capture(false)
+                .map_err(|e| log::error!("failed to capture the screen: {}", e))
+                .ok()?;
+
+            let (x, y) = dialogs.pick_pixel(&screen)?;
+
+            screen.pixel(x, y)
+        });
+
+        let timeout = self.config.dialog.timeout();
+
+        let result = match request::run(conn, &handle, timeout, ticket.run(dialog)).await? {
+            Some(Some(pixel)) => {
+                let mut results = StrMap::new();
+
+                results.insert("color", color(pixel).into());
+
+                (0, results)
+            }
+
+            Some(None) => (1, StrMap::new()),
+            None => (2, StrMap::new()),
+        };
+
+        let outcome = match result.0 {
+            0 => Outcome::Chosen,
+            1 => Outcome::Cancelled,
+            _ => Outcome::Failed,
+        };
+
+        self.audit.record(app_id, "PickColor", outcome, &[]);
+
+        zbus::fdo::Result::Ok(result)
+    }
+}
+
+impl Screenshot {
+    /// Capture the screen, letting the user select an area of it if the call is interactive,
+    /// and save it.
+    ///
+    /// Non-interactive screenshots need the app to be allowed to take them without asking.
+    async fn take(
+        &self,
+        conn: &zbus::Connection,
+        handle: &zvariant::ObjectPath<'_>,
+        app_id: &str,
+        parent_window: &str,
+        options: StrMap<'_>,
+    ) -> zbus::fdo::Result<(u32, StrMap<'static>)> {
+        log::info!("screenshot({}, {}, {})", handle, app_id, parent_window);
+
+        let interactive = matches!(
+            options.get("interactive"),
+            Some(zvariant::Value::Bool(true))
+        );
+
+        let capture = self.capture.clone();
+
+        let image = if interactive {
+            let Some(ticket) = self.scheduler.ticket(app_id) else {
+                log::warn!("rejecting {}, {} already has a dialog open", handle, app_id);
+                return zbus::fdo::Result::Ok((2, StrMap::new()));
+            };
+
+            let message = Message {
+                title: String::from("Screenshot"),
+                description: format!("{} wants to take a screenshot.", requester(app_id)),
+                parent: ParentWindow::parse(parent_window),
+                accept_label: Some(String::from("Take Screenshot")),
+                reject_label: Some(String::from("Cancel")),
+                ..Message::default()
+            };
+
+            let dialogs = self.dialogs.clone();
+
+            let defaults = self.defaults();
+
+            // The selection is drawn over a still, so it's captured before the dialog opens.
+            let dialog = show(move || {
+                let options = dialogs.screenshot_options(&message, defaults)?;
+
+                dialogs.countdown(options.delay);
+
+                let screen = match capture.capture(options.cursor) {
+                    Ok(screen) => screen,
+                    Err(e) => return Some(Err(e)),
+                };
+
+                let windows = capture.windows(&screen);
+
+                let region = dialogs.select_region(&message, &screen, &windows)?;
+
+                Some(Ok(screen.crop(&region)))
+            });
+
+            let timeout = self.config.dialog.timeout();
+
+            match request::run(conn, handle, timeout, ticket.run(dialog)).await? {
+                Some(Some(image)) => image,
+                Some(None) => return zbus::fdo::Result::Ok((1, StrMap::new())),
+                None => return zbus::fdo::Result::Ok((2, StrMap::new())),
+            }
+        } else {
+            match self.access(conn, handle, app_id, parent_window).await? {
+                Some(true) => {}
+                Some(false) => return zbus::fdo::Result::Ok((1, StrMap::new())),
+                None => return zbus::fdo::Result::Ok((2, StrMap::new())),
+            }
+
+            let dialogs = self.dialogs.clone();
+
+            let options = self.defaults();
+
+            show(move || {
+                dialogs.countdown(options.delay);
+
+                capture.capture(options.cursor)
+            })
+            .await?
+        };
+
+        let directory = self.directory();
+
+        let saved = show(move || {
+            let path = target(&directory);
+
+            image?.save(&path)?;
+
+            std::io::Result::Ok(path)
+        })
+        .await?;
+
+        let path = match saved {
+            Ok(path) => path,
+
+            Err(e) => {
+                log::error!("failed to take a screenshot: {}", e);
+                return zbus::fdo::Result::Ok((2, StrMap::new()));
+            }
+        };
+
+        let mut results = StrMap::new();
+
+        results.insert("uri", uri::file_uri(&path).into());
+
+        zbus::fdo::Result::Ok((0, results))
+    }
+
+    /// Check whether `app_id` may take screenshots without asking,
+    /// asking the user the first time and keeping the answer in the permission store.
+    ///
+    /// Returns `None` if the question couldn't be asked or wasn't answered in time.
+    async fn access(
+        &self,
+        conn: &zbus::Connection,
+        handle: &zvariant::ObjectPath<'_>,
+        app_id: &str,
+        parent_window: &str,
+    ) -> zbus::fdo::Result<Option<bool>> {
+        // Host apps have no id to remember the answer for, and could capture the screen anyway.
+        if app_id.is_empty() {
+            return Ok(Some(true));
+        }
+
+        match permissions::lookup(conn, PERMISSION_TABLE, PERMISSION_ID, app_id).await {
+            Ok(permissions) if permissions.iter().any(|p| p == "yes") => return Ok(Some(true)),
+            Ok(permissions) if permissions.iter().any(|p| p == "no") => return Ok(Some(false)),
+
+            Ok(_) => {}
+
+            Err(e) => log::warn!("failed to look up the permission of {}: {}", app_id, e),
+        }
+
+        let Some(ticket) = self.scheduler.ticket(app_id) else {
+            log::warn!("rejecting {}, {} already has a dialog open", handle, app_id);
+            return Ok(None);
+        };
+
+        let message = Message {
+            title: String::from("Screenshot"),
+            description: format!(
+                "{} wants to take screenshots without asking. Your answer is remembered.",
+                requester(app_id)
+            ),
+            parent: ParentWindow::parse(parent_window),
+            ..Message::default()
+        };
+
+        let dialogs = self.dialogs.clone();
+
+        let dialog = show(move || ask(&*dialogs, &message));
+
+        let timeout = self.config.dialog.timeout();
+
+        let allowed = match request::run(conn, handle, timeout, ticket.run(dialog)).await? {
+            Some(Some(allowed)) => allowed,
+
+            // Dismissing the prompt denies this screenshot without deciding for the next ones.
+            Some(None) => return Ok(Some(false)),
+            None => return Ok(None),
+        };
+
+        let permissions = [if allowed { "yes" } else { "no" }];
+
+        if let Err(e) =
+            permissions::set(conn, PERMISSION_TABLE, PERMISSION_ID, app_id, &permissions).await
+        {
+            log::warn!("failed to store the permission of {}: {}", app_id, e);
+        }
+
+        Ok(Some(allowed))
+    }
+
+    /// Get the screenshot options set in the config.
+    fn defaults(&self) -> ScreenshotOptions {
+        ScreenshotOptions {
+            delay: self.config.screenshot.delay,
+            cursor: self.config.screenshot.include_cursor,
+        }
+    }
+
+    /// Get the folder to save screenshots in.
+    fn directory(&self) -> PathBuf {
+        self.config
+            .screenshot
+            .directory
+            .as_deref()
+            .map(policy::expand)
+            .or_else(dirs::picture_dir)
+            .filter(|dir| dir.is_dir())
+            .unwrap_or_else(std::env::temp_dir)
+    }
+}
+
+/// Ask whether to allow screenshots with `message`, returning `None` unless the user
+/// explicitly allowed or denied them.
+fn ask(dialogs: &dyn DialogProvider, message: &Message) -> Option<bool> {
+    let options = ["Allow", "Deny"].map(String::from);
+
+    dialogs.choose(message, &options).map(|answer| answer == 0)
+}
+
+/// Convert the 8-bit channels of a pixel to the `(ddd)` color of PickColor results,
+/// with each channel between 0 and 1.
+fn color([r, g, b, _]: [u8; 4]) -> (f64, f64, f64) {
+    let channel = |value: u8| f64::from(value) / f64::from(u8::MAX);
+
+    (channel(r), channel(g), channel(b))
+}
+
+/// Get a path for a new screenshot in `directory`, named after the current time.
+fn target(directory: &std::path::Path) -> PathBuf {
+    // `2024-05-01T12:30:00Z` becomes `2024-05-01 12-30-00`, which is safe in any file system.
+    let time = humantime::format_rfc3339_seconds(std::time::SystemTime::now())
+        .to_string()
+        .trim_end_matches('Z')
+        .replace('T', " ")
+        .replace(':', "-");
+
+    let path = directory.join(format!("Screenshot from {}.png", time));
+
+    if !path.exists() {
+        return path;
+    }
+
+    (1..)
+        .map(|n| numbered_path(&path, n))
+        .find(|path| !path.exists())
+        .unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ask;
+    use crate::dialog::{tests::Buttons, Message};
+
+    #[test]
+    fn explicit_answers() {
+        let message = Message::default();
+
+        assert_eq!(ask(&Buttons::new(&[true]), &message), Some(true));
+        assert_eq!(ask(&Buttons::new(&[false, true]), &message), Some(false));
+
+        // Dismissing the dialogs is no answer to remember.
+        assert_eq!(ask(&Buttons::new(&[]), &message), None);
+    }
+}
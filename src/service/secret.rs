@@ -0,0 +1,234 @@
+use std::{
+    io::Write,
+    os::fd::{AsRawFd, BorrowedFd},
+    sync::Arc,
+};
+
+use zbus::{dbus_interface, zvariant};
+
+use super::{requester, show, StrMap};
+use crate::{
+    audit::{Audit, Outcome},
+    config::{Config, SecretBackend},
+    dialog::{DialogProvider, Message, PasswordRequest},
+    request,
+    schedule::Scheduler,
+    secret::{self, Keyring, SecretService, Vault},
+};
+
+/// Secret implements the org.freedesktop.impl.portal.Secret interface.
+pub struct Secret {
+    pub config: Arc<Config>,
+    pub dialogs: Arc<dyn DialogProvider>,
+    pub scheduler: Arc<Scheduler>,
+    pub audit: Arc<Audit>,
+
+    /// Serializes requests, so the vault is unlocked and a secret is moved only once.
+    pub lock: tokio::sync::Mutex<()>,
+}
+
+#[dbus_interface(name = "org.freedesktop.impl.portal.Secret")]
+impl Secret {
+    /// The version of the interface this backend implements.
+    #[dbus_interface(property, name = "version")]
+    fn version(&self) -> u32 {
+        1
+    }
+
+    /// Writes the master secret of a sandboxed app to `fd`, which libsecret derives the key
+    /// of the app's own keyring file from.
+    ///
+    /// Secrets are kept in the Secret Service, or else in the vault, whose password is asked
+    /// for once per login. Apps are never asked about, like they aren't when they use the
+    /// Secret Service outside sandboxes.
+    #[dbus_interface(out_args("response", "results"))]
+    async fn retrieve_secret(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        fd: zvariant::Fd,
+        options: StrMap<'_>,
+    ) -> zbus::fdo::Result<(u32, StrMap<'static>)> {
+        log::info!("retrieve_secret({}, {})", handle, app_id);
+
+        // Apps outside sandboxes use the Secret Service, and would all share a secret here.
+        if app_id.is_empty() {
+            log::warn!("rejecting {}, it isn't from a sandboxed app", handle);
+            return zbus::fdo::Result::Ok((2, StrMap::new()));
+        }
+
+        let token = match options.get("token") {
+            Some(zvariant::Value::Str(token)) if !token.is_empty() => Some(token.to_string()),
+            _ => None,
+        };
+
+        // SAFETY: the file descriptors of a message stay open while it's handled.
+        let fd = unsafe { BorrowedFd::borrow_raw(fd.as_raw_fd()) }
+            .try_clone_to_owned()
+            .map_err(|e| zbus::fdo::Error::Failed(e.to_string()))?;
+
+        let _guard = self.lock.lock().await;
+
+        let backend = self.config.secret.backend;
+
+        let service = match backend {
+            SecretBackend::File => None,
+            _ => connect(conn).await,
+        };
+
+        let keyring = match (service, backend) {
+            (Some(service), _) => Keyring::SecretService(service),
+
+            (None, SecretBackend::SecretService) => {
+                log::error!(
+                    "failed to hand {} its secret: there's no Secret Service",
+                    app_id
+                );
+                self.audit
+                    .record(app_id, "RetrieveSecret", Outcome::Failed, &[]);
+                return zbus::fdo::Result::Ok((2, StrMap::new()));
+            }
+
+            (None, _) => match self.unlock(conn, &handle, app_id).await? {
+                Some(Ok(vault)) => Keyring::Vault(vault),
+
+                Some(Err(e)) => {
+                    log::warn!("the vault stays locked for {}: {}", app_id, e);
+                    self.audit
+                        .record(app_id, "RetrieveSecret", Outcome::Cancelled, &[]);
+                    return zbus::fdo::Result::Ok((1, StrMap::new()));
+                }
+
+                None => {
+                    self.audit
+                        .record(app_id, "RetrieveSecret", Outcome::Failed, &[]);
+                    return zbus::fdo::Result::Ok((2, StrMap::new()));
+                }
+            },
+        };
+
+        // Only looked at the first time an app asks, when its secret may be kept elsewhere.
+        let others = async {
+            let mut others: Vec<Keyring> = Keyring::plain().into_iter().collect();
+
+            match &keyring {
+                Keyring::Vault(_) if backend == SecretBackend::File => {
+                    others.extend(connect(conn).await.map(Keyring::SecretService));
+                }
+
+                Keyring::SecretService(_) if Vault::exists() => {
+                    match self.unlock(conn, &handle, app_id).await {
+                        Ok(Some(Ok(vault))) => others.push(Keyring::Vault(vault)),
+                        Ok(Some(Err(e))) => {
+                            log::warn!("the vault stays locked for {}: {}", app_id, e)
+                        }
+                        Ok(None) => log::warn!("the vault stays locked for {}", app_id),
+                        Err(e) => log::warn!("failed to unlock the vault: {}", e),
+                    }
+                }
+
+                _ => {}
+            }
+
+            others
+        };
+
+        let retrieved = match secret::retrieve(&keyring, others, app_id, token.as_deref()).await {
+            Ok((secret, token)) => show(move || std::fs::File::from(fd).write_all(&secret))
+                .await?
+                .map(|()| token),
+
+            Err(e) => Err(e),
+        };
+
+        let (response, results) = match retrieved {
+            Ok(token) => (0, StrMap::from([("token", token.into())])),
+
+            Err(e) => {
+                log::error!("failed to hand {} its secret: {}", app_id, e);
+                (2, StrMap::new())
+            }
+        };
+
+        let outcome = match response {
+            0 => Outcome::Chosen,
+            _ => Outcome::Failed,
+        };
+
+        self.audit.record(app_id, "RetrieveSecret", outcome, &[]);
+
+        zbus::fdo::Result::Ok((response, results))
+    }
+}
+
+impl Secret {
+    /// Unlock the vault, asking the user for its password, or to choose one if there's no
+    /// vault yet, on behalf of `app_id` in the request at `handle`.
+    ///
+    /// Returns `None` if `app_id` already has a dialog open, or the request was closed or
+    /// timed out.
+    async fn unlock(
+        &self,
+        conn: &zbus::Connection,
+        handle: &zvariant::ObjectPath<'_>,
+        app_id: &str,
+    ) -> zbus::fdo::Result<Option<std::io::Result<Vault>>> {
+        let Some(ticket) = self.scheduler.ticket(app_id) else {
+            log::warn!("rejecting {}, {} already has a dialog open", handle, app_id);
+            return Ok(None);
+        };
+
+        let dialogs = self.dialogs.clone();
+        let requester = requester(app_id);
+
+        let dialog = show(move || {
+            Vault::unlock(|new| {
+                let message = match new {
+                    true => Message {
+                        title: String::from("Protect Secrets"),
+                        description: format!(
+                            "{} wants to keep secrets. Choose a password to encrypt the secrets \
+                             of applications with, which is asked for once per login.",
+                            requester
+                        ),
+                        accept_label: Some(String::from("Protect")),
+                        ..Message::default()
+                    },
+
+                    false => Message {
+                        title: String::from("Unlock Secrets"),
+                        description: format!(
+                            "{} wants to open its keyring. Enter the password of your secrets \
+                             to unlock them until you log out.",
+                            requester
+                        ),
+                        accept_label: Some(String::from("Unlock")),
+                        ..Message::default()
+                    },
+                };
+
+                dialogs.ask_password(&PasswordRequest {
+                    message,
+                    confirm: new,
+                })
+            })
+        });
+
+        let timeout = self.config.dialog.timeout();
+
+        request::run(conn, handle, timeout, ticket.run(dialog)).await
+    }
+}
+
+/// Connect to the Secret Service, or return `None` if there's none.
+async fn connect(conn: &zbus::Connection) -> Option<SecretService> {
+    match SecretService::connect(conn).await {
+        Ok(service) => service,
+
+        Err(e) => {
+            log::warn!("failed to connect to the Secret Service: {}", e);
+            None
+        }
+    }
+}
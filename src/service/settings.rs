@@ -0,0 +1,232 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use zbus::{dbus_interface, zvariant, DBusError, SignalContext};
+
+use super::PATH;
+use crate::{
+    config::{self, Config},
+    settings::{self, Values},
+};
+
+/// How long the config file is left to settle after it changed, as editors save in steps.
+const SETTLE: Duration = Duration::from_millis(100);
+
+/// `Error` is an error of the Settings portal, named the way apps expect.
+#[derive(Debug, DBusError)]
+#[dbus_error(prefix = "org.freedesktop.portal.Error")]
+pub enum Error {
+    #[dbus_error(zbus_error)]
+    ZBus(zbus::Error),
+
+    /// The setting isn't known.
+    NotFound(String),
+}
+
+/// Settings implements the org.freedesktop.impl.portal.Settings interface.
+///
+/// The settings are those of the config and of the GSettings schemas it names, which are read
+/// again as either changes.
+pub struct Settings {
+    values: Mutex<Values>,
+}
+
+impl Settings {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            values: Mutex::new(settings::load(config)),
+        }
+    }
+}
+
+#[dbus_interface(name = "org.freedesktop.impl.portal.Settings")]
+impl Settings {
+    /// The version of the interface this backend implements.
+    #[dbus_interface(property, name = "version")]
+    fn version(&self) -> u32 {
+        2
+    }
+
+    /// Reads the settings of the namespaces matching one of `namespaces`, or of every
+    /// namespace if it's empty.
+    async fn read_all(
+        &self,
+        namespaces: Vec<&str>,
+    ) -> HashMap<String, HashMap<String, zvariant::OwnedValue>> {
+        log::info!("read_all({:?})", namespaces);
+
+        let Ok(values) = self.values.lock() else {
+            return HashMap::new();
+        };
+
+        values
+            .iter()
+            .filter(|(namespace, _)| settings::matches(&namespaces, namespace))
+            .map(|(namespace, keys)| (namespace.clone(), keys.clone()))
+            .collect()
+    }
+
+    /// Reads a single setting, as a variant in a variant; it's kept for apps written before
+    /// ReadOne.
+    async fn read(&self, namespace: &str, key: &str) -> Result<zvariant::OwnedValue, Error> {
+        log::info!("read({}, {})", namespace, key);
+
+        let value = self.lookup(namespace, key)?;
+
+        Ok(zvariant::Value::Value(Box::new(value.into())).into())
+    }
+
+    /// Reads a single setting.
+    async fn read_one(&self, namespace: &str, key: &str) -> Result<zvariant::OwnedValue, Error> {
+        log::info!("read_one({}, {})", namespace, key);
+
+        self.lookup(namespace, key)
+    }
+
+    /// Tells apps a setting changed.
+    #[dbus_interface(signal)]
+    async fn setting_changed(
+        ctxt: &SignalContext<'_>,
+        namespace: &str,
+        key: &str,
+        value: zvariant::Value<'_>,
+    ) -> zbus::Result<()>;
+}
+
+impl Settings {
+    /// Get the setting `key` of `namespace`.
+    fn lookup(&self, namespace: &str, key: &str) -> Result<zvariant::OwnedValue, Error> {
+        self.values
+            .lock()
+            .ok()
+            .and_then(|values| values.get(namespace)?.get(key).cloned())
+            .ok_or_else(|| Error::NotFound(format!("{}.{} isn't set", namespace, key)))
+    }
+
+    /// Replace the settings with `values`, returning the ones that changed.
+    fn replace(&self, values: Values) -> Vec<(String, String, zvariant::OwnedValue)> {
+        match self.values.lock() {
+            Ok(mut current) => {
+                let changed = settings::changes(&current, &values);
+                *current = values;
+                changed
+            }
+
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+/// Read the settings again whenever the config file or the GSettings `schemas` change,
+/// telling apps which of them changed, so they follow things like the color scheme right away.
+pub async fn watch_settings(conn: zbus::Connection, schemas: Vec<String>) {
+    let Some(dir) = config::path().and_then(|path| Some(path.parent()?.to_path_buf())) else {
+        return;
+    };
+
+    let (changed, mut changes) = tokio::sync::mpsc::unbounded_channel();
+
+    settings::monitor(&schemas, changed.clone());
+
+    if let Err(e) = settings::watch(&dir, changed) {
+        log::warn!("not following changes to the config file: {}", e);
+    }
+
+    let iface = match conn.object_server().interface::<_, Settings>(PATH).await {
+        Ok(iface) => iface,
+
+        Err(e) => {
+            log::error!("failed to signal setting changes: {}", e);
+            return;
+        }
+    };
+
+    let ctxt = match SignalContext::new(&conn, PATH) {
+        Ok(ctxt) => ctxt,
+
+        Err(e) => {
+            log::error!("failed to signal setting changes: {}", e);
+            return;
+        }
+    };
+
+    while changes.recv().await.is_some() {
+        tokio::time::sleep(SETTLE).await;
+
+        while changes.try_recv().is_ok() {}
+
+        let Ok(values) = tokio::task::spawn_blocking(|| settings::load(&Config::load())).await
+        else {
+            continue;
+        };
+
+        for (namespace, key, value) in iface.get().await.replace(values) {
+            log::info!("{}.{} changed", namespace, key);
+
+            let signalled = Settings::setting_changed(&ctxt, &namespace, &key, value.into()).await;
+
+            if let Err(e) = signalled {
+                log::warn!("failed to tell apps {}.{} changed: {}", namespace, key, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use zbus::zvariant;
+
+    use super::Settings;
+    use crate::config::Config;
+
+    #[test]
+    fn lookups() {
+        let settings = Settings::new(&Config::default());
+
+        // The color scheme and contrast are there even if the config doesn't set them.
+        assert_eq!(
+            settings
+                .lookup("org.freedesktop.appearance", "color-scheme")
+                .unwrap(),
+            zvariant::Value::from(0u32).into()
+        );
+        assert_eq!(
+            settings
+                .lookup("org.freedesktop.appearance", "contrast")
+                .unwrap(),
+            zvariant::Value::from(0u32).into()
+        );
+        assert!(settings
+            .lookup("org.freedesktop.appearance", "accent-color")
+            .is_err());
+
+        let config = Config {
+            settings: HashMap::from([(
+                String::from("org.freedesktop.appearance"),
+                HashMap::from([(
+                    String::from("color-scheme"),
+                    toml::Value::String(String::from("prefer-dark")),
+                )]),
+            )]),
+            ..Config::default()
+        };
+
+        let changed = settings.replace(crate::settings::load(&config));
+
+        assert_eq!(
+            changed,
+            [(
+                String::from("org.freedesktop.appearance"),
+                String::from("color-scheme"),
+                zvariant::Value::from(1u32).into()
+            )]
+        );
+        assert_eq!(
+            settings
+                .lookup("org.freedesktop.appearance", "color-scheme")
+                .unwrap(),
+            zvariant::Value::from(1u32).into()
+        );
+    }
+}
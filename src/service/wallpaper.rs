@@ -0,0 +1,167 @@
+use std::sync::Arc;
+
+use zbus::{dbus_interface, zvariant};
+
+use super::{requester, show, StrMap};
+use crate::{
+    audit::{Audit, Outcome},
+    capture::Capture,
+    config::Config,
+    dialog::{DialogProvider, Message},
+    request,
+    schedule::Scheduler,
+    uri,
+    wallpaper::{self, Setter, Target},
+    window::ParentWindow,
+};
+
+/// Wallpaper implements the org.freedesktop.impl.portal.Wallpaper interface.
+pub struct Wallpaper {
+    pub config: Arc<Config>,
+    pub dialogs: Arc<dyn DialogProvider>,
+    pub scheduler: Arc<Scheduler>,
+    pub audit: Arc<Audit>,
+    pub setter: Arc<dyn Setter>,
+
+    /// Finds the outputs previews are drawn on.
+    pub capture: Arc<dyn Capture>,
+}
+
+#[dbus_interface(name = "org.freedesktop.impl.portal.Wallpaper")]
+impl Wallpaper {
+    /// Sets the picture at `uri` as the wallpaper, once the user confirmed it.
+    ///
+    /// The frontend passes pictures apps send as files by their path in the document portal,
+    /// so both arrive here as `file://` URIs. The user is always asked, and shown the picture
+    /// on their outputs if the app asks for a preview.
+    #[dbus_interface(name = "SetWallpaperURI")]
+    async fn set_wallpaper_uri(
+        &self,
+        #[zbus(connection)] conn: &zbus::Connection,
+        handle: zvariant::ObjectPath<'_>,
+        app_id: &str,
+        parent_window: &str,
+        uri: &str,
+        options: StrMap<'_>,
+    ) -> zbus::fdo::Result<u32> {
+        log::info!(
+            "set_wallpaper_uri({}, {}, {}, {})",
+            handle,
+            app_id,
+            parent_window,
+            uri
+        );
+
+        let Some(path) = uri::file_path(uri) else {
+            log::warn!("rejecting {}, {} isn't a local file", handle, uri);
+            return Ok(2);
+        };
+
+        let Some(target) = target(&options) else {
+            log::warn!(
+                "rejecting {}, it sets the wallpaper on nothing known",
+                handle
+            );
+            return Ok(2);
+        };
+
+        let preview = matches!(
+            options.get("show-preview"),
+            Some(zvariant::Value::Bool(true))
+        );
+
+        let Some(ticket) = self.scheduler.ticket(app_id) else {
+            log::warn!("rejecting {}, {} already has a dialog open", handle, app_id);
+            return Ok(2);
+        };
+
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| uri.to_owned());
+
+        let message = Message {
+            title: String::from("Set Wallpaper"),
+            description: format!(
+                "{} wants to set {} as {}.",
+                requester(app_id),
+                name,
+                target.describe()
+            ),
+            parent: ParentWindow::parse(parent_window),
+            accept_label: Some(String::from("Apply")),
+            ..Message::default()
+        };
+
+        let dialogs = self.dialogs.clone();
+
+        let capture = self.capture.clone();
+
+        let picture = path.clone();
+
+        let dialog = show(move || match preview {
+            true => dialogs.confirm_preview(&message, &wallpaper::preview(&picture, &*capture)),
+            false => dialogs.confirm(&message),
+        });
+
+        let timeout = self.config.dialog.timeout();
+
+        let response = match request::run(conn, &handle, timeout, ticket.run(dialog)).await? {
+            Some(true) => {
+                let setter = self.setter.clone();
+
+                let set = show(move || wallpaper::apply(&*setter, &path, target)).await?;
+
+                match set {
+                    Ok(()) => 0,
+
+                    Err(e) => {
+                        log::error!("failed to set the wallpaper: {}", e);
+                        2
+                    }
+                }
+            }
+
+            Some(false) => 1,
+            None => 2,
+        };
+
+        let outcome = match response {
+            0 => Outcome::Chosen,
+            1 => Outcome::Cancelled,
+            _ => Outcome::Failed,
+        };
+
+        self.audit
+            .record(app_id, "SetWallpaperURI", outcome, &[uri.to_owned()]);
+
+        Ok(response)
+    }
+}
+
+/// Get what the `set-on` option of a request sets the picture as, the background if it's
+/// unset, or `None` if it's unknown.
+fn target(options: &StrMap<'_>) -> Option<Target> {
+    match options.get("set-on") {
+        Some(zvariant::Value::Str(on)) => Target::parse(on),
+        _ => Some(Target::Background),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zbus::zvariant;
+
+    use super::{target, StrMap, Target};
+
+    #[test]
+    fn targets() {
+        let on = |target: &'static str| StrMap::from([("set-on", zvariant::Value::from(target))]);
+
+        assert_eq!(target(&StrMap::new()), Some(Target::Background));
+        assert_eq!(target(&on("background")), Some(Target::Background));
+        assert_eq!(target(&on("both")), Some(Target::Both));
+        assert_eq!(target(&on("lockscreen")), Some(Target::LockScreen));
+        assert_eq!(target(&on("desktop")), None);
+    }
+}
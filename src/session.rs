@@ -0,0 +1,209 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use zbus::{dbus_interface, zvariant};
+
+/// Session implements the org.freedesktop.impl.portal.Session interface.
+///
+/// One is exported at the `session_handle` path of each session a portal creates,
+/// until the frontend closes it.
+pub struct Session {
+    path: zvariant::OwnedObjectPath,
+
+    /// Forgets the session's state, which closes whatever it holds open.
+    end: Box<dyn Fn() + Send + Sync>,
+}
+
+#[dbus_interface(name = "org.freedesktop.impl.portal.Session")]
+impl Session {
+    /// The version of the interface this backend implements.
+    #[dbus_interface(property, name = "version")]
+    fn version(&self) -> u32 {
+        1
+    }
+
+    /// Closes the session.
+    async fn close(
+        &self,
+        #[zbus(object_server)] server: &zbus::ObjectServer,
+    ) -> zbus::fdo::Result<()> {
+        log::info!("close({})", self.path);
+
+        (self.end)();
+
+        server.remove::<Session, _>(&self.path).await?;
+
+        Ok(())
+    }
+}
+
+/// `Sessions` keeps the state of the open sessions of a portal, by session handle.
+pub struct Sessions<T> {
+    sessions: Mutex<HashMap<String, Entry<T>>>,
+}
+
+/// `Entry` is an open session.
+struct Entry<T> {
+    app_id: String,
+    state: T,
+
+    /// Releases what the session holds once it's closed.
+    on_close: Box<dyn FnOnce(T) + Send>,
+}
+
+impl<T: Send + 'static> Sessions<T> {
+    /// Create an empty list of sessions.
+    pub fn new() -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Open a session of `app_id` at `path`, exporting a Session there.
+    ///
+    /// `on_close` gets the state back once the session is closed.
+    pub async fn create(
+        self: &Arc<Self>,
+        conn: &zbus::Connection,
+        path: &zvariant::ObjectPath<'_>,
+        app_id: &str,
+        state: T,
+        on_close: impl FnOnce(T) + Send + 'static,
+    ) -> zbus::Result<()> {
+        let path = zvariant::OwnedObjectPath::from(path.to_owned());
+
+        let entry = Entry {
+            app_id: app_id.to_owned(),
+            state,
+            on_close: Box::new(on_close),
+        };
+
+        if let Ok(mut sessions) = self.sessions.lock() {
+            sessions.insert(path.to_string(), entry);
+        }
+
+        let sessions = self.clone();
+
+        let session = Session {
+            path: path.clone(),
+            end: Box::new({
+                let path = path.to_string();
+                move || sessions.end(&path)
+            }),
+        };
+
+        if let Err(e) = conn.object_server().at(&path, session).await {
+            self.end(path.as_str());
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    /// Run `f` on the state of the session at `path`, if it's open and belongs to `app_id`.
+    pub fn with<R>(
+        &self,
+        path: &zvariant::ObjectPath<'_>,
+        app_id: &str,
+        f: impl FnOnce(&mut T) -> R,
+    ) -> Option<R> {
+        let mut sessions = self.sessions.lock().ok()?;
+
+        let entry = sessions.get_mut(path.as_str())?;
+
+        // Session handles are picked by the frontend, but an app must not touch another's.
+        if entry.app_id != app_id {
+            log::warn!("{} doesn't belong to {}", path, app_id);
+            return None;
+        }
+
+        Some(f(&mut entry.state))
+    }
+
+    /// Run `f` on the state of the session at `path`, if it's open, whichever app it
+    /// belongs to.
+    ///
+    /// This is for interfaces whose methods aren't told the app, which leave checking the
+    /// caller owns the session to the frontend.
+    pub fn find<R>(
+        &self,
+        path: &zvariant::ObjectPath<'_>,
+        f: impl FnOnce(&mut T) -> R,
+    ) -> Option<R> {
+        let mut sessions = self.sessions.lock().ok()?;
+
+        Some(f(&mut sessions.get_mut(path.as_str())?.state))
+    }
+
+    /// List the paths of the open sessions.
+    pub fn paths(&self) -> Vec<String> {
+        match self.sessions.lock() {
+            Ok(sessions) => sessions.keys().cloned().collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Forget the session at `path`, handing its state to its `on_close`.
+    fn end(&self, path: &str) {
+        let entry = match self.sessions.lock() {
+            Ok(mut sessions) => sessions.remove(path),
+            Err(_) => None,
+        };
+
+        if let Some(entry) = entry {
+            (entry.on_close)(entry.state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    use zbus::zvariant;
+
+    use super::{Entry, Sessions};
+
+    #[test]
+    fn sessions_by_app() {
+        let sessions = Sessions::new();
+
+        let path =
+            zvariant::ObjectPath::try_from("/org/freedesktop/portal/desktop/session/1").unwrap();
+
+        let closed = Arc::new(AtomicBool::new(false));
+
+        let entry = Entry {
+            app_id: String::from("org.example.App"),
+            state: 1,
+            on_close: Box::new({
+                let closed = closed.clone();
+                move |state| closed.store(state == 2, Ordering::SeqCst)
+            }),
+        };
+
+        sessions
+            .sessions
+            .lock()
+            .unwrap()
+            .insert(path.to_string(), entry);
+
+        assert_eq!(sessions.with(&path, "org.example.Other", |_| ()), None);
+        assert_eq!(
+            sessions.with(&path, "org.example.App", |state| *state += 1),
+            Some(())
+        );
+        assert_eq!(sessions.find(&path, |state| *state), Some(2));
+        assert_eq!(sessions.paths(), [path.as_str()]);
+
+        sessions.end(path.as_str());
+
+        assert!(closed.load(Ordering::SeqCst));
+        assert_eq!(sessions.with(&path, "org.example.App", |_| ()), None);
+    }
+}
@@ -0,0 +1,439 @@
+use std::{collections::HashMap, ffi::CString, os::unix::ffi::OsStrExt, path::Path};
+
+use tokio::sync::mpsc::UnboundedSender;
+use zbus::zvariant;
+
+use crate::config::Config;
+
+mod gsettings;
+
+pub use gsettings::monitor;
+
+/// `Values` are settings as apps read them, by namespace and key.
+pub type Values = HashMap<String, HashMap<String, zvariant::OwnedValue>>;
+
+/// The settings apps get even if the config doesn't set them, with their values then.
+const DEFAULTS: [(&str, &str, u32); 2] = [
+    ("org.freedesktop.appearance", "color-scheme", 0),
+    ("org.freedesktop.appearance", "contrast", 0),
+];
+
+/// The accent colors named the way GNOME names them, with their RGB values.
+const ACCENTS: [(&str, &str); 9] = [
+    ("blue", "#3584e4"),
+    ("teal", "#2190a4"),
+    ("green", "#3a944a"),
+    ("yellow", "#c88800"),
+    ("orange", "#ed5b00"),
+    ("red", "#e62d42"),
+    ("pink", "#d56199"),
+    ("purple", "#9141ac"),
+    ("slate", "#6f8396"),
+];
+
+/// Load the settings apps read: those of the GSettings schemas the config names, with the
+/// settings of the config itself taking precedence.
+///
+/// This runs `gsettings`, so it blocks.
+pub fn load(config: &Config) -> Values {
+    let mut values = gsettings::read(&config.gsettings.schemas);
+
+    for (namespace, keys) in configured(&config.settings) {
+        values.entry(namespace).or_default().extend(keys);
+    }
+
+    defaults(&mut values);
+
+    values
+}
+
+/// Convert the settings of the config to what apps read, leaving out those that can't be.
+fn configured(settings: &HashMap<String, HashMap<String, toml::Value>>) -> Values {
+    settings
+        .iter()
+        .map(|(namespace, keys)| {
+            let keys = keys
+                .iter()
+                .filter_map(|(key, value)| Some((key.clone(), self::value(namespace, key, value)?)))
+                .collect();
+
+            (namespace.clone(), keys)
+        })
+        .collect()
+}
+
+/// Add the settings of `DEFAULTS` that aren't set.
+fn defaults(values: &mut Values) {
+    for (namespace, key, value) in DEFAULTS {
+        values
+            .entry(namespace.to_owned())
+            .or_default()
+            .entry(key.to_owned())
+            .or_insert_with(|| zvariant::Value::from(value).into());
+    }
+}
+
+/// List the settings of `new` that aren't the same in `old`.
+///
+/// Settings that are gone from `new` aren't listed, as there's no value to tell apps.
+pub fn changes(old: &Values, new: &Values) -> Vec<(String, String, zvariant::OwnedValue)> {
+    new.iter()
+        .flat_map(|(namespace, keys)| {
+            keys.iter()
+                .filter(|(key, value)| {
+                    old.get(namespace).and_then(|keys| keys.get(*key)) != Some(*value)
+                })
+                .map(|(key, value)| (namespace.clone(), key.clone(), value.clone()))
+        })
+        .collect()
+}
+
+/// Tell `changed` whenever a file in `dir` changes, using inotify, until it's dropped.
+///
+/// The directory is watched rather than the file, as editors replace files when saving.
+pub fn watch(dir: &Path, changed: UnboundedSender<()>) -> std::io::Result<()> {
+    let path = CString::new(dir.as_os_str().as_bytes())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+
+    // SAFETY: inotify_init1 has no preconditions; the result is checked below.
+    let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    let mask = libc::IN_CREATE | libc::IN_DELETE | libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO;
+
+    // SAFETY: `fd` is an open inotify descriptor and `path` is NUL-terminated.
+    if unsafe { libc::inotify_add_watch(fd, path.as_ptr(), mask) } < 0 {
+        let e = std::io::Error::last_os_error();
+
+        // SAFETY: `fd` is open and owned here.
+        unsafe { libc::close(fd) };
+
+        return Err(e);
+    }
+
+    std::thread::Builder::new()
+        .name(String::from("settings-watch"))
+        .spawn(move || {
+            let mut buffer = [0u8; 4096];
+
+            loop {
+                // SAFETY: `buffer` is valid for writes of its whole length.
+                let read = unsafe { libc::read(fd, buffer.as_mut_ptr().cast(), buffer.len()) };
+
+                if read < 0 {
+                    let e = std::io::Error::last_os_error();
+
+                    if e.kind() == std::io::ErrorKind::Interrupted {
+                        continue;
+                    }
+
+                    log::warn!("stopped watching the settings: {}", e);
+                    break;
+                }
+
+                if changed.send(()).is_err() {
+                    break;
+                }
+            }
+
+            // SAFETY: `fd` is open and owned by this thread.
+            unsafe { libc::close(fd) };
+        })?;
+
+    Ok(())
+}
+
+/// Check whether `namespace` is matched by one of `patterns`, which match everything if
+/// there are none.
+///
+/// Patterns are either a namespace or a namespace ending in `*`, like `org.freedesktop.*`,
+/// which matches the namespaces starting with what's before the `*`.
+pub fn matches(patterns: &[&str], namespace: &str) -> bool {
+    patterns.is_empty()
+        || patterns
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => namespace.starts_with(prefix),
+                None => *pattern == namespace,
+            })
+}
+
+/// Convert the value of `key` in `namespace` to what apps expect to read.
+///
+/// The keys of org.freedesktop.appearance have types of their own, and take names as well
+/// as numbers, like `color-scheme = "prefer-dark"`; see `accent` for the accent color.
+/// Returns `None` for values that have no D-Bus type, like arrays of mixed types.
+pub fn value(namespace: &str, key: &str, value: &toml::Value) -> Option<zvariant::OwnedValue> {
+    match (namespace, key, value) {
+        ("org.freedesktop.appearance", "color-scheme", value) => {
+            let names = ["default", "prefer-dark", "prefer-light"];
+            enumeration(value, &names).map(Into::into)
+        }
+
+        ("org.freedesktop.appearance", "contrast", value) => {
+            let names = ["normal", "high"];
+            enumeration(value, &names).map(Into::into)
+        }
+
+        ("org.freedesktop.appearance", "accent-color", value) => {
+            accent(value).map(|rgb| zvariant::Value::from(rgb).into())
+        }
+
+        (_, _, value) => convert(value).map(Into::into),
+    }
+}
+
+/// Convert a value of the config to its D-Bus counterpart, keeping integers 32 bits wide
+/// where they fit, as most settings are.
+fn convert(value: &toml::Value) -> Option<zvariant::Value<'static>> {
+    match value {
+        toml::Value::String(s) => Some(s.clone().into()),
+        toml::Value::Boolean(b) => Some((*b).into()),
+        toml::Value::Float(f) => Some((*f).into()),
+        toml::Value::Datetime(d) => Some(d.to_string().into()),
+
+        toml::Value::Integer(i) => Some(match i32::try_from(*i) {
+            Ok(i) => i.into(),
+            Err(_) => (*i).into(),
+        }),
+
+        toml::Value::Array(values) => array(values),
+
+        toml::Value::Table(table) => {
+            let dict: HashMap<String, zvariant::Value<'static>> = table
+                .iter()
+                .filter_map(|(key, value)| Some((key.clone(), convert(value)?)))
+                .collect();
+
+            Some(dict.into())
+        }
+    }
+}
+
+/// Get an accent color of the config as its red, green and blue, each from 0 to 1.
+///
+/// It's written as those three numbers, as a hex color like `"#3584e4"` or as the name GNOME
+/// gives it, like `"blue"`. Colors out of range are left unset, as apps would ignore them.
+fn accent(value: &toml::Value) -> Option<(f64, f64, f64)> {
+    let rgb = match value {
+        toml::Value::Array(rgb) => match rgb.iter().map(float).collect::<Option<Vec<_>>>()?[..] {
+            [r, g, b] => (r, g, b),
+            _ => return None,
+        },
+
+        toml::Value::String(color) => {
+            let hex = ACCENTS
+                .iter()
+                .find(|(name, _)| name == color)
+                .map_or(color.as_str(), |(_, hex)| hex);
+
+            let hex = hex.strip_prefix('#')?;
+
+            if hex.len() != 6 || !hex.is_ascii() {
+                return None;
+            }
+
+            let channel = |i: usize| {
+                let channel = u8::from_str_radix(&hex[i..i + 2], 16).ok()?;
+                Some(f64::from(channel) / 255.0)
+            };
+
+            (channel(0)?, channel(2)?, channel(4)?)
+        }
+
+        _ => return None,
+    };
+
+    [rgb.0, rgb.1, rgb.2]
+        .iter()
+        .all(|channel| (0.0..=1.0).contains(channel))
+        .then_some(rgb)
+}
+
+/// Convert an array of the config to an array of its items' type, keeping integers 32 bits
+/// wide if they all fit. Arrays of integers and floats are arrays of floats, and empty arrays
+/// are arrays of strings, as there's no telling their type.
+fn array(values: &[toml::Value]) -> Option<zvariant::Value<'static>> {
+    fn all<T>(values: &[toml::Value], item: impl Fn(&toml::Value) -> Option<T>) -> Option<Vec<T>> {
+        values.iter().map(item).collect()
+    }
+
+    if let Some(strings) = all(values, |v| v.as_str().map(String::from)) {
+        return Some(strings.into());
+    }
+
+    if let Some(bools) = all(values, toml::Value::as_bool) {
+        return Some(bools.into());
+    }
+
+    if let Some(integers) = all(values, toml::Value::as_integer) {
+        return Some(match all(values, |v| i32::try_from(v.as_integer()?).ok()) {
+            Some(integers) => integers.into(),
+            None => integers.into(),
+        });
+    }
+
+    all(values, float).map(Into::into)
+}
+
+/// Get a number of the config as a float, whether it's written as one or not.
+fn float(value: &toml::Value) -> Option<f64> {
+    match value {
+        toml::Value::Float(f) => Some(*f),
+        toml::Value::Integer(i) => Some(*i as f64),
+        _ => None,
+    }
+}
+
+/// Get the number of an enumerated setting, written as its number or as one of its `names`.
+fn enumeration(value: &toml::Value, names: &[&str]) -> Option<u32> {
+    match value {
+        toml::Value::Integer(i) => u32::try_from(*i).ok(),
+
+        toml::Value::String(name) => names
+            .iter()
+            .position(|n| n == name)
+            .and_then(|i| u32::try_from(i).ok()),
+
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zbus::zvariant;
+
+    use std::collections::HashMap;
+
+    use super::{changes, load, matches, value};
+    use crate::config::Config;
+
+    #[test]
+    fn namespaces() {
+        assert!(matches(&[], "org.gnome.desktop.interface"));
+        assert!(matches(
+            &["org.freedesktop.*"],
+            "org.freedesktop.appearance"
+        ));
+        assert!(matches(
+            &["org.freedesktop.appearance"],
+            "org.freedesktop.appearance"
+        ));
+        assert!(!matches(&["org.freedesktop.appearance"], "org.freedesktop"));
+        assert!(!matches(&["org.gnome.*"], "org.freedesktop.appearance"));
+    }
+
+    #[test]
+    fn conversions() {
+        let appearance = "org.freedesktop.appearance";
+
+        let dark = toml::Value::String(String::from("prefer-dark"));
+        assert_eq!(
+            value(appearance, "color-scheme", &dark),
+            Some(zvariant::Value::from(1u32).into())
+        );
+
+        let accent: toml::Value = toml::from_str("a = [1, 0.5, 0.0]").unwrap();
+        assert_eq!(
+            value(appearance, "accent-color", &accent["a"]),
+            Some(zvariant::Value::from((1.0, 0.5, 0.0)).into())
+        );
+
+        let hex = toml::Value::String(String::from("#ff8000"));
+        assert_eq!(
+            value(appearance, "accent-color", &hex),
+            Some(zvariant::Value::from((1.0, 128.0 / 255.0, 0.0)).into())
+        );
+
+        let named = toml::Value::String(String::from("blue"));
+        assert_eq!(
+            value(appearance, "accent-color", &named),
+            Some(zvariant::Value::from((53.0 / 255.0, 132.0 / 255.0, 228.0 / 255.0)).into())
+        );
+
+        let bright: toml::Value = toml::from_str("a = [2, 0, 0]").unwrap();
+        assert_eq!(value(appearance, "accent-color", &bright["a"]), None);
+
+        let high = toml::Value::String(String::from("high"));
+        assert_eq!(
+            value(appearance, "contrast", &high),
+            Some(zvariant::Value::from(1u32).into())
+        );
+
+        let size = toml::Value::Integer(24);
+        assert_eq!(
+            value("org.gnome.desktop.interface", "cursor-size", &size),
+            Some(zvariant::Value::from(24i32).into())
+        );
+
+        let custom: toml::Value =
+            toml::from_str("a = [1, 2]\nb = [true]\nc = [1, 2.5]\nd = [4294967296]").unwrap();
+        assert_eq!(
+            value("org.example", "a", &custom["a"]),
+            Some(zvariant::Value::from(vec![1i32, 2]).into())
+        );
+        assert_eq!(
+            value("org.example", "b", &custom["b"]),
+            Some(zvariant::Value::from(vec![true]).into())
+        );
+        assert_eq!(
+            value("org.example", "c", &custom["c"]),
+            Some(zvariant::Value::from(vec![1.0, 2.5]).into())
+        );
+        assert_eq!(
+            value("org.example", "d", &custom["d"]),
+            Some(zvariant::Value::from(vec![4294967296i64]).into())
+        );
+
+        let mixed: toml::Value = toml::from_str("a = [1, \"two\"]").unwrap();
+        assert_eq!(value("org.example", "mixed", &mixed["a"]), None);
+    }
+
+    #[test]
+    fn defaults_and_changes() {
+        let old = load(&Config::default());
+
+        assert_eq!(
+            old["org.freedesktop.appearance"]["color-scheme"],
+            zvariant::Value::from(0u32).into()
+        );
+        assert_eq!(
+            old["org.freedesktop.appearance"]["contrast"],
+            zvariant::Value::from(0u32).into()
+        );
+
+        let settings: HashMap<String, HashMap<String, toml::Value>> = toml::from_str(
+            "[\"org.freedesktop.appearance\"]\ncolor-scheme = \"prefer-light\"\ncontrast = 1",
+        )
+        .unwrap();
+
+        let new = load(&Config {
+            settings,
+            ..Config::default()
+        });
+
+        let mut changed = changes(&old, &new);
+        changed.sort_by(|a, b| a.1.cmp(&b.1));
+
+        assert_eq!(
+            changed,
+            [
+                (
+                    String::from("org.freedesktop.appearance"),
+                    String::from("color-scheme"),
+                    zvariant::Value::from(2u32).into()
+                ),
+                (
+                    String::from("org.freedesktop.appearance"),
+                    String::from("contrast"),
+                    zvariant::Value::from(1u32).into()
+                ),
+            ]
+        );
+
+        assert!(changes(&new, &new).is_empty());
+    }
+}
@@ -0,0 +1,484 @@
+use std::{
+    collections::HashMap,
+    io::BufRead,
+    process::{Command, Stdio},
+};
+
+use tokio::sync::mpsc::UnboundedSender;
+use zbus::zvariant;
+
+use super::Values;
+
+/// The namespace of the settings every desktop shares.
+const APPEARANCE: &str = "org.freedesktop.appearance";
+
+/// Read the keys of the GSettings `schemas` with the `gsettings` tool, as namespaces named
+/// after the schemas, the way GNOME's portal backend serves them.
+///
+/// Schemas that aren't installed are left out, and so are keys whose values have types apps
+/// aren't served here, like dictionaries. GNOME's color scheme and high contrast are served
+/// as those of org.freedesktop.appearance as well, when their schemas are read.
+pub fn read(schemas: &[String]) -> Values {
+    let mut values: Values = schemas
+        .iter()
+        .filter_map(|schema| Some((schema.clone(), read_schema(schema)?)))
+        .collect();
+
+    let appearance = appearance(&values);
+
+    if !appearance.is_empty() {
+        values
+            .entry(APPEARANCE.to_owned())
+            .or_default()
+            .extend(appearance);
+    }
+
+    values
+}
+
+/// Get the settings of org.freedesktop.appearance that GNOME's own settings in `values` tell.
+fn appearance(values: &Values) -> HashMap<String, zvariant::OwnedValue> {
+    let get = |schema: &str, key: &str| values.get(schema)?.get(key);
+
+    let mut appearance = HashMap::new();
+
+    // GNOME names the color schemes and accent colors the way the config does.
+    for key in ["color-scheme", "accent-color"] {
+        let value = get("org.gnome.desktop.interface", key)
+            .and_then(|name| <&str>::try_from(name).ok())
+            .map(|name| toml::Value::String(name.to_owned()))
+            .and_then(|name| super::value(APPEARANCE, key, &name));
+
+        if let Some(value) = value {
+            appearance.insert(key.to_owned(), value);
+        }
+    }
+
+    let contrast = get("org.gnome.desktop.a11y.interface", "high-contrast")
+        .and_then(|high| bool::try_from(high).ok());
+
+    if let Some(high) = contrast {
+        appearance.insert(
+            String::from("contrast"),
+            zvariant::Value::from(u32::from(high)).into(),
+        );
+    }
+
+    appearance
+}
+
+/// Read the keys of a GSettings schema, or `None` if it can't be read.
+fn read_schema(schema: &str) -> Option<HashMap<String, zvariant::OwnedValue>> {
+    let output = match Command::new("gsettings")
+        .args(["list-recursively", schema])
+        .output()
+    {
+        Ok(output) => output,
+
+        Err(e) => {
+            log::warn!("failed to run gsettings: {}", e);
+            return None;
+        }
+    };
+
+    if !output.status.success() {
+        log::warn!(
+            "failed to read the settings of {}: {}",
+            schema,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        return None;
+    }
+
+    let keys = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            // Lines are the schema, the key and the value, with spaces between.
+            let (_, rest) = line.split_once(' ')?;
+            let (key, value) = rest.split_once(' ')?;
+
+            Some((key.to_owned(), parse(value)?.into()))
+        })
+        .collect();
+
+    Some(keys)
+}
+
+/// Tell `changed` whenever a key of the GSettings `schemas` changes, by following
+/// `gsettings monitor` from a thread of its own for each schema.
+pub fn monitor(schemas: &[String], changed: UnboundedSender<()>) {
+    for schema in schemas {
+        let child = Command::new("gsettings")
+            .args(["monitor", schema])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn();
+
+        let mut child = match child {
+            Ok(child) => child,
+
+            Err(e) => {
+                log::warn!("failed to monitor {}: {}", schema, e);
+                continue;
+            }
+        };
+
+        let Some(stdout) = child.stdout.take() else {
+            continue;
+        };
+
+        let changed = changed.clone();
+
+        let spawned = std::thread::Builder::new()
+            .name(String::from("gsettings-monitor"))
+            .spawn(move || {
+                // Each line is a key that changed, and its value.
+                for _ in std::io::BufReader::new(stdout).lines() {
+                    if changed.send(()).is_err() {
+                        break;
+                    }
+                }
+
+                let _ = child.kill();
+                let _ = child.wait();
+            });
+
+        if let Err(e) = spawned {
+            log::warn!("failed to monitor {}: {}", schema, e);
+        }
+    }
+}
+
+/// `Parsed` is a value in GVariant's text format, like `gsettings` prints them.
+#[derive(Debug, Clone, PartialEq)]
+enum Parsed {
+    Str(String),
+    Bool(bool),
+    Double(f64),
+
+    /// An integer and the type it was written with, `i` if it was written without one.
+    Int(i64, char),
+
+    /// The items of an array and its type, if it was written with one, like `@as []`.
+    Array(Vec<Parsed>, Option<String>),
+
+    Tuple(Vec<Parsed>),
+}
+
+/// Parse a value in GVariant's text format into a D-Bus value, or `None` if it isn't one of
+/// the types served, like strings, numbers, booleans and arrays and tuples of them.
+fn parse(text: &str) -> Option<zvariant::Value<'static>> {
+    let mut parser = Parser { text: text.trim() };
+
+    let parsed = parser.value()?;
+
+    parser.text.is_empty().then_some(())?;
+
+    convert(parsed)
+}
+
+/// `Parser` reads a value in GVariant's text format from the start of `text`.
+struct Parser<'a> {
+    text: &'a str,
+}
+
+impl Parser<'_> {
+    fn value(&mut self) -> Option<Parsed> {
+        self.skip_spaces();
+
+        // Type annotations tell the type of values that can't show it, like empty arrays.
+        if let Some(rest) = self.text.strip_prefix('@') {
+            let end = rest.find(' ')?;
+            let signature = rest[..end].to_owned();
+
+            self.text = &rest[end..];
+
+            return match self.value()? {
+                Parsed::Array(items, _) => Some(Parsed::Array(items, Some(signature))),
+                value => Some(value),
+            };
+        }
+
+        let prefixes = [
+            ("byte ", 'y'),
+            ("int16 ", 'n'),
+            ("uint16 ", 'q'),
+            ("int32 ", 'i'),
+            ("uint32 ", 'u'),
+            ("int64 ", 'x'),
+            ("uint64 ", 't'),
+        ];
+
+        for (prefix, kind) in prefixes {
+            if let Some(rest) = self.text.strip_prefix(prefix) {
+                self.text = rest;
+
+                return match self.number()? {
+                    Parsed::Int(i, _) => Some(Parsed::Int(i, kind)),
+                    _ => None,
+                };
+            }
+        }
+
+        if let Some(rest) = self.text.strip_prefix("double ") {
+            self.text = rest;
+
+            return match self.number()? {
+                Parsed::Int(i, _) => Some(Parsed::Double(i as f64)),
+                double => Some(double),
+            };
+        }
+
+        match self.text.chars().next()? {
+            quote @ ('\'' | '"') => self.string(quote),
+            '[' => self.items('[', ']').map(|items| Parsed::Array(items, None)),
+            '(' => self.items('(', ')').map(Parsed::Tuple),
+
+            _ => {
+                for (word, value) in [("true", true), ("false", false)] {
+                    if let Some(rest) = self.text.strip_prefix(word) {
+                        self.text = rest;
+                        return Some(Parsed::Bool(value));
+                    }
+                }
+
+                self.number()
+            }
+        }
+    }
+
+    /// Read a quoted string, unescaping it.
+    fn string(&mut self, quote: char) -> Option<Parsed> {
+        let mut chars = self.text.char_indices().skip(1);
+
+        let mut string = String::new();
+
+        while let Some((index, c)) = chars.next() {
+            match c {
+                '\\' => {
+                    let (_, escaped) = chars.next()?;
+
+                    string.push(match escaped {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        escaped => escaped,
+                    });
+                }
+
+                c if c == quote => {
+                    self.text = &self.text[index + c.len_utf8()..];
+                    return Some(Parsed::Str(string));
+                }
+
+                c => string.push(c),
+            }
+        }
+
+        None
+    }
+
+    /// Read the items of an array or a tuple, between `open` and `close`.
+    fn items(&mut self, open: char, close: char) -> Option<Vec<Parsed>> {
+        self.text = self.text.strip_prefix(open)?;
+
+        let mut items = Vec::new();
+
+        loop {
+            self.skip_spaces();
+
+            if let Some(rest) = self.text.strip_prefix(close) {
+                self.text = rest;
+                return Some(items);
+            }
+
+            if !items.is_empty() {
+                self.text = self.text.strip_prefix(',')?;
+            }
+
+            self.skip_spaces();
+
+            // Tuples of one item end in a comma, like `('a',)`.
+            if let Some(rest) = self.text.strip_prefix(close) {
+                self.text = rest;
+                return Some(items);
+            }
+
+            items.push(self.value()?);
+        }
+    }
+
+    /// Read an integer, or a double if it has a fraction or an exponent.
+    fn number(&mut self) -> Option<Parsed> {
+        let end = self
+            .text
+            .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '+' | '.')))
+            .unwrap_or(self.text.len());
+
+        let number = &self.text[..end];
+
+        let parsed = if let Some(hex) = number.strip_prefix("0x") {
+            Parsed::Int(i64::from_str_radix(hex, 16).ok()?, 'i')
+        } else if let Ok(i) = number.parse() {
+            Parsed::Int(i, 'i')
+        } else {
+            Parsed::Double(number.parse().ok()?)
+        };
+
+        self.text = &self.text[end..];
+
+        Some(parsed)
+    }
+
+    fn skip_spaces(&mut self) {
+        self.text = self.text.trim_start();
+    }
+}
+
+/// Convert a parsed value to a D-Bus value, if it has a type served here.
+fn convert(parsed: Parsed) -> Option<zvariant::Value<'static>> {
+    match parsed {
+        Parsed::Str(s) => Some(s.into()),
+        Parsed::Bool(b) => Some(b.into()),
+        Parsed::Double(d) => Some(d.into()),
+
+        Parsed::Int(i, kind) => Some(match kind {
+            'y' => u8::try_from(i).ok()?.into(),
+            'n' => i16::try_from(i).ok()?.into(),
+            'q' => u16::try_from(i).ok()?.into(),
+            'u' => u32::try_from(i).ok()?.into(),
+            'x' => i.into(),
+            't' => u64::try_from(i).ok()?.into(),
+            _ => i32::try_from(i).ok()?.into(),
+        }),
+
+        Parsed::Array(items, signature) => array(items, signature.as_deref()),
+
+        Parsed::Tuple(items) => {
+            let fields = items.into_iter().map(convert).collect::<Option<Vec<_>>>()?;
+
+            let structure = fields
+                .into_iter()
+                .fold(zvariant::StructureBuilder::new(), |builder, field| {
+                    builder.append_field(field)
+                });
+
+            Some(structure.build().into())
+        }
+    }
+}
+
+/// Convert the items of an array of strings, booleans or numbers, typed by `signature` if
+/// it's empty.
+fn array(items: Vec<Parsed>, signature: Option<&str>) -> Option<zvariant::Value<'static>> {
+    let Some(first) = items.first() else {
+        return match signature.unwrap_or("as") {
+            "as" => Some(Vec::<String>::new().into()),
+            "ai" => Some(Vec::<i32>::new().into()),
+            "au" => Some(Vec::<u32>::new().into()),
+            "ad" => Some(Vec::<f64>::new().into()),
+            "ab" => Some(Vec::<bool>::new().into()),
+            _ => None,
+        };
+    };
+
+    macro_rules! collect {
+        ($pattern:pat => $item:expr) => {
+            items
+                .iter()
+                .map(|item| match item {
+                    $pattern => Some($item),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>()
+                .map(Into::into)
+        };
+    }
+
+    match first {
+        Parsed::Str(_) => collect!(Parsed::Str(s) => s.clone()),
+        Parsed::Bool(_) => collect!(Parsed::Bool(b) => *b),
+        Parsed::Double(_) => collect!(Parsed::Double(d) => *d),
+        Parsed::Int(_, 'u') => collect!(Parsed::Int(i, 'u') => u32::try_from(*i).ok()?),
+        Parsed::Int(_, 'i') => collect!(Parsed::Int(i, 'i') => i32::try_from(*i).ok()?),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use zbus::zvariant;
+
+    use super::{appearance, parse};
+
+    #[test]
+    fn gvariant_text() {
+        assert_eq!(
+            parse("'prefer-dark'"),
+            Some(zvariant::Value::from("prefer-dark"))
+        );
+        assert_eq!(parse("'it\\'s'"), Some(zvariant::Value::from("it's")));
+        assert_eq!(parse("true"), Some(zvariant::Value::from(true)));
+        assert_eq!(parse("24"), Some(zvariant::Value::from(24i32)));
+        assert_eq!(parse("uint32 5"), Some(zvariant::Value::from(5u32)));
+        assert_eq!(parse("1.25"), Some(zvariant::Value::from(1.25)));
+        assert_eq!(
+            parse("['a', \"b\"]"),
+            Some(zvariant::Value::from(vec!["a", "b"]))
+        );
+        assert_eq!(
+            parse("@as []"),
+            Some(zvariant::Value::from(Vec::<String>::new()))
+        );
+        assert_eq!(
+            parse("(0.5, 1.0, 0.0)"),
+            Some(zvariant::Value::from((0.5, 1.0, 0.0)))
+        );
+
+        // Dictionaries aren't served.
+        assert_eq!(parse("{'a': 1}"), None);
+        assert_eq!(parse("[1, 'a']"), None);
+    }
+
+    #[test]
+    fn gnome_appearance() {
+        let values = HashMap::from([
+            (
+                String::from("org.gnome.desktop.interface"),
+                HashMap::from([
+                    (
+                        String::from("color-scheme"),
+                        parse("'prefer-dark'").unwrap().into(),
+                    ),
+                    (
+                        String::from("accent-color"),
+                        parse("'slate'").unwrap().into(),
+                    ),
+                ]),
+            ),
+            (
+                String::from("org.gnome.desktop.a11y.interface"),
+                HashMap::from([(String::from("high-contrast"), parse("true").unwrap().into())]),
+            ),
+        ]);
+
+        assert_eq!(
+            appearance(&values),
+            HashMap::from([
+                (
+                    String::from("color-scheme"),
+                    zvariant::Value::from(1u32).into()
+                ),
+                (String::from("contrast"), zvariant::Value::from(1u32).into()),
+                (
+                    String::from("accent-color"),
+                    zvariant::Value::from((111.0 / 255.0, 131.0 / 255.0, 150.0 / 255.0)).into()
+                ),
+            ])
+        );
+
+        assert!(appearance(&HashMap::new()).is_empty());
+    }
+}
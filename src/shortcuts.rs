@@ -0,0 +1,532 @@
+mod hyprland;
+mod kde;
+mod sway;
+mod x11;
+
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::config::{GlobalShortcutsConfig, ShortcutsBackend};
+
+/// The modifiers of triggers, in the order they're written.
+const MODIFIERS: [(&str, &str); 5] = [
+    ("CTRL", "Ctrl"),
+    ("ALT", "Alt"),
+    ("SHIFT", "Shift"),
+    ("LOGO", "Super"),
+    ("NUM", "Num Lock"),
+];
+
+/// Serializes read-modify-write cycles of the shortcut file between concurrent requests.
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// `Trigger` is a key combination, as the shortcuts specification of freedesktop.org writes
+/// them: modifiers and an XKB keysym name joined by `+`, like `CTRL+SHIFT+m`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Trigger {
+    /// The names of the modifiers held, in the order of `MODIFIERS`.
+    pub modifiers: Vec<&'static str>,
+
+    /// The keysym name of the key pressed.
+    pub key: String,
+}
+
+impl Trigger {
+    /// Parse a trigger, with modifiers in any order and case, or return `None` if it isn't
+    /// one.
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+
+        let key = parts.pop().filter(|key| !key.is_empty())?;
+
+        let mut modifiers = Vec::new();
+
+        for part in parts {
+            let (name, _) = MODIFIERS
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(part))?;
+
+            modifiers.push(*name);
+        }
+
+        modifiers.sort_by_key(|name| MODIFIERS.iter().position(|(other, _)| other == name));
+        modifiers.dedup();
+
+        Some(Self {
+            modifiers,
+            key: String::from(key),
+        })
+    }
+
+    /// Describe the trigger for people, like `Ctrl+Shift+M`.
+    pub fn describe(&self) -> String {
+        let mut parts: Vec<String> = self
+            .modifiers
+            .iter()
+            .filter_map(|name| MODIFIERS.iter().find(|(other, _)| other == name))
+            .map(|(_, label)| String::from(*label))
+            .collect();
+
+        parts.push(match self.key.len() {
+            1 => self.key.to_uppercase(),
+            _ => self.key.clone(),
+        });
+
+        parts.join("+")
+    }
+}
+
+impl std::fmt::Display for Trigger {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for modifier in &self.modifiers {
+            write!(f, "{}+", modifier)?;
+        }
+
+        write!(f, "{}", self.key)
+    }
+}
+
+impl TryFrom<String> for Trigger {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, String> {
+        Self::parse(&text).ok_or_else(|| format!("{:?} isn't a trigger", text))
+    }
+}
+
+impl From<Trigger> for String {
+    fn from(trigger: Trigger) -> Self {
+        trigger.to_string()
+    }
+}
+
+/// `Shortcut` is a shortcut of an app, bound to a trigger unless none was free.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Shortcut {
+    pub id: String,
+    pub description: String,
+    pub trigger: Option<Trigger>,
+}
+
+/// `Requested` is a shortcut an app asks to bind.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Requested {
+    pub id: String,
+    pub description: String,
+
+    /// The trigger the app would like, if it's free.
+    pub preferred: Option<Trigger>,
+}
+
+/// `Binding` is a trigger to grab, and the shortcut it activates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding {
+    pub app_id: String,
+    pub id: String,
+    pub description: String,
+    pub trigger: Trigger,
+}
+
+/// `Event` is the trigger of a shortcut pressed or released.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub app_id: String,
+    pub id: String,
+    pub pressed: bool,
+}
+
+/// `Grabber` grabs the triggers of bound shortcuts from the compositor, sending an `Event`
+/// as they're pressed and released.
+///
+/// Grabbing only queues the change for the compositor, so it doesn't block.
+pub trait Grabber: Send + Sync {
+    /// Grab the triggers of `bindings`, releasing those grabbed before.
+    fn grab(&self, bindings: &[Binding]);
+}
+
+/// `Unsupported` stands in for compositors triggers can't be grabbed from, so shortcuts are
+/// bound without ever being activated.
+struct Unsupported;
+
+impl Grabber for Unsupported {
+    fn grab(&self, bindings: &[Binding]) {
+        if !bindings.is_empty() {
+            log::warn!("global shortcuts can't be grabbed from this compositor");
+        }
+    }
+}
+
+/// Create the grabber selected in the config, which sends what's pressed to `events`.
+pub fn from_config(
+    config: &GlobalShortcutsConfig,
+    events: UnboundedSender<Event>,
+) -> Arc<dyn Grabber> {
+    let backend = match config.backend {
+        ShortcutsBackend::Auto => match detect() {
+            Some(backend) => backend,
+
+            None => {
+                log::debug!("no way to grab global shortcuts in this session");
+                return Arc::new(Unsupported);
+            }
+        },
+
+        backend => backend,
+    };
+
+    log::debug!("grabbing global shortcuts with {:?}", backend);
+
+    let grabber: std::io::Result<Arc<dyn Grabber>> = match backend {
+        ShortcutsBackend::Hyprland | ShortcutsBackend::Auto => {
+            hyprland::Hyprland::new(events).map(|grabber| Arc::new(grabber) as _)
+        }
+        ShortcutsBackend::Sway => sway::Sway::new(events).map(|grabber| Arc::new(grabber) as _),
+        ShortcutsBackend::Kde => Ok(Arc::new(kde::Kde::new(events))),
+        ShortcutsBackend::X11 => x11::X11::new(events).map(|grabber| Arc::new(grabber) as _),
+    };
+
+    grabber.unwrap_or_else(|e| {
+        log::warn!("failed to grab global shortcuts with {:?}: {}", backend, e);
+        Arc::new(Unsupported)
+    })
+}
+
+/// Pick the grabber that fits the session, if there's one.
+fn detect() -> Option<ShortcutsBackend> {
+    if std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+        return Some(ShortcutsBackend::Hyprland);
+    }
+
+    if std::env::var_os("SWAYSOCK").is_some() {
+        return Some(ShortcutsBackend::Sway);
+    }
+
+    if crate::dialog::is_kde() {
+        return Some(ShortcutsBackend::Kde);
+    }
+
+    // Wayland compositors don't let X clients grab keys of other windows.
+    match std::env::var_os("WAYLAND_DISPLAY") {
+        None if std::env::var_os("DISPLAY").is_some() => Some(ShortcutsBackend::X11),
+        _ => None,
+    }
+}
+
+/// Run `command`, returning what it printed, and failing if it does.
+fn run(command: &mut std::process::Command) -> std::io::Result<String> {
+    let output = command
+        .stdin(std::process::Stdio::null())
+        .stderr(std::process::Stdio::piped())
+        .output()?;
+
+    if !output.status.success() {
+        return Err(std::io::Error::other(format!(
+            "{:?} failed with {}: {}",
+            command.get_program(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Bind the shortcuts `requested` by an app, which bound `bound` before, returning them and
+/// whether any of them is new.
+///
+/// Shortcuts bound before keep their triggers. New ones get their preferred trigger unless
+/// another shortcut has it already, in `taken` or before them in `requested`.
+pub fn assign(
+    requested: &[Requested],
+    bound: &[Shortcut],
+    taken: &[Trigger],
+) -> (Vec<Shortcut>, bool) {
+    let mut shortcuts: Vec<Shortcut> = Vec::new();
+    let mut new = false;
+
+    for request in requested {
+        let trigger = match bound.iter().find(|shortcut| shortcut.id == request.id) {
+            Some(shortcut) => shortcut.trigger.clone(),
+
+            None => {
+                new = true;
+
+                request.preferred.clone().filter(|preferred| {
+                    !taken.contains(preferred)
+                        && !shortcuts
+                            .iter()
+                            .chain(bound)
+                            .any(|shortcut| shortcut.trigger.as_ref() == Some(preferred))
+                })
+            }
+        };
+
+        shortcuts.push(Shortcut {
+            id: request.id.clone(),
+            description: request.description.clone(),
+            trigger,
+        });
+    }
+
+    (shortcuts, new)
+}
+
+/// Find the triggers several of `shortcuts` have, or that one of them has and are `taken`.
+pub fn conflicts<'a>(
+    shortcuts: impl IntoIterator<Item = &'a Shortcut>,
+    taken: &[Trigger],
+) -> Vec<Trigger> {
+    let mut seen: Vec<&Trigger> = Vec::new();
+    let mut conflicts = Vec::new();
+
+    for trigger in shortcuts
+        .into_iter()
+        .filter_map(|shortcut| shortcut.trigger.as_ref())
+    {
+        if (seen.contains(&trigger) || taken.contains(trigger)) && !conflicts.contains(trigger) {
+            conflicts.push(trigger.clone());
+        }
+
+        seen.push(trigger);
+    }
+
+    conflicts
+}
+
+/// `Stored` is the shortcut file, `$XDG_STATE_HOME/xdg-desktop-portal-rs/shortcuts.toml`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Stored {
+    /// The shortcuts of each app, keyed by app id.
+    apps: HashMap<String, Vec<Shortcut>>,
+}
+
+/// Get the shortcuts of `app_id` bound before, and the triggers other apps bound.
+pub fn load(app_id: &str) -> (Vec<Shortcut>, Vec<Trigger>) {
+    match path() {
+        Some(path) => load_in(&path, app_id),
+        None => (Vec::new(), Vec::new()),
+    }
+}
+
+fn load_in(path: &Path, app_id: &str) -> (Vec<Shortcut>, Vec<Trigger>) {
+    let _guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut stored = read(path);
+
+    let bound = stored.apps.remove(app_id).unwrap_or_default();
+
+    let taken = stored
+        .apps
+        .into_values()
+        .flatten()
+        .filter_map(|shortcut| shortcut.trigger)
+        .collect();
+
+    (bound, taken)
+}
+
+/// Get the shortcuts of every app, sorted by app id.
+pub fn load_all() -> Vec<(String, Vec<Shortcut>)> {
+    match path() {
+        Some(path) => load_all_in(&path),
+        None => Vec::new(),
+    }
+}
+
+fn load_all_in(path: &Path) -> Vec<(String, Vec<Shortcut>)> {
+    let _guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut apps: Vec<(String, Vec<Shortcut>)> = read(path).apps.into_iter().collect();
+
+    apps.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    apps
+}
+
+/// Remember the `shortcuts` of `app_id`, replacing those with the same ids.
+pub fn save(app_id: &str, shortcuts: &[Shortcut]) -> std::io::Result<()> {
+    let path = path().ok_or(std::io::ErrorKind::NotFound)?;
+
+    save_in(&path, app_id, shortcuts)
+}
+
+fn save_in(path: &Path, app_id: &str, shortcuts: &[Shortcut]) -> std::io::Result<()> {
+    let _guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut stored = read(path);
+
+    let bound = stored.apps.entry(String::from(app_id)).or_default();
+
+    for shortcut in shortcuts {
+        match bound.iter_mut().find(|other| other.id == shortcut.id) {
+            Some(other) => *other = shortcut.clone(),
+            None => bound.push(shortcut.clone()),
+        }
+    }
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let contents = toml::to_string(&stored)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+    // Write a new file and move it over the old one, so it's never read half written.
+    let temporary = path.with_extension("toml.new");
+
+    std::fs::File::create(&temporary)?.write_all(contents.as_bytes())?;
+
+    std::fs::rename(temporary, path)
+}
+
+/// Read the shortcut file, which is empty if it's missing or unreadable.
+fn read(path: &Path) -> Stored {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => toml::from_str(&contents).unwrap_or_else(|e| {
+            log::warn!("ignoring invalid shortcut file {:?}: {}", path, e);
+            Stored::default()
+        }),
+
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Stored::default(),
+
+        Err(e) => {
+            log::warn!("failed to read shortcut file {:?}: {}", path, e);
+            Stored::default()
+        }
+    }
+}
+
+/// Get the path of the shortcut file.
+fn path() -> Option<PathBuf> {
+    Some(
+        dirs::state_dir()?
+            .join("xdg-desktop-portal-rs")
+            .join("shortcuts.toml"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{assign, conflicts, load_all_in, load_in, save_in, Requested, Shortcut, Trigger};
+
+    fn requested(id: &str, preferred: &str) -> Requested {
+        Requested {
+            id: String::from(id),
+            description: format!("Do {}", id),
+            preferred: Trigger::parse(preferred),
+        }
+    }
+
+    #[test]
+    fn triggers() {
+        let trigger = Trigger::parse("shift+Ctrl+m").unwrap();
+
+        assert_eq!(trigger.modifiers, ["CTRL", "SHIFT"]);
+        assert_eq!(trigger.to_string(), "CTRL+SHIFT+m");
+        assert_eq!(trigger.describe(), "Ctrl+Shift+M");
+
+        assert_eq!(Trigger::parse("LOGO+F1").unwrap().describe(), "Super+F1");
+        assert_eq!(Trigger::parse("HYPER+a"), None);
+        assert_eq!(Trigger::parse("CTRL+"), None);
+    }
+
+    #[test]
+    fn assigning() {
+        let taken = [Trigger::parse("CTRL+a").unwrap()];
+
+        let (shortcuts, new) = assign(
+            &[
+                requested("talk", "CTRL+t"),
+                requested("mute", "CTRL+t"),
+                requested("select", "CTRL+a"),
+            ],
+            &[],
+            &taken,
+        );
+
+        assert!(new);
+
+        // Triggers go to the first shortcut asking for them, if no other app has them.
+        let triggers: Vec<Option<String>> = shortcuts
+            .iter()
+            .map(|shortcut| shortcut.trigger.as_ref().map(Trigger::to_string))
+            .collect();
+
+        assert_eq!(triggers, [Some(String::from("CTRL+t")), None, None]);
+
+        // Shortcuts bound before keep their triggers, even if the app prefers others now.
+        let (again, new) = assign(&[requested("talk", "CTRL+x")], &shortcuts, &taken);
+
+        assert!(!new);
+        assert_eq!(again[0].trigger, Trigger::parse("CTRL+t"));
+    }
+
+    #[test]
+    fn conflicting() {
+        let shortcut = |trigger: &str| Shortcut {
+            id: String::new(),
+            description: String::new(),
+            trigger: Trigger::parse(trigger),
+        };
+
+        let shortcuts = [
+            shortcut("CTRL+t"),
+            shortcut(""),
+            shortcut("ctrl+T"),
+            shortcut("CTRL+t"),
+            shortcut("CTRL+a"),
+            shortcut("CTRL+m"),
+        ];
+
+        let taken = [Trigger::parse("CTRL+a").unwrap()];
+
+        // Keysym names tell cases apart, unlike modifiers.
+        assert_eq!(
+            conflicts(&shortcuts, &taken),
+            [Trigger::parse("CTRL+t").unwrap(), taken[0].clone()]
+        );
+        assert!(conflicts(&shortcuts[4..], &[]).is_empty());
+    }
+
+    #[test]
+    fn stored() {
+        let dir = std::env::temp_dir().join(format!("shortcuts-test-{}", std::process::id()));
+        let path = dir.join("shortcuts.toml");
+
+        let shortcut = |id: &str, trigger: &str| Shortcut {
+            id: String::from(id),
+            description: String::new(),
+            trigger: Trigger::parse(trigger),
+        };
+
+        save_in(&path, "org.example.App", &[shortcut("talk", "CTRL+t")]).unwrap();
+        save_in(&path, "org.example.App", &[shortcut("mute", "CTRL+m")]).unwrap();
+        save_in(&path, "org.example.Other", &[shortcut("play", "LOGO+p")]).unwrap();
+
+        let (bound, taken) = load_in(&path, "org.example.App");
+
+        assert_eq!(
+            bound,
+            [shortcut("talk", "CTRL+t"), shortcut("mute", "CTRL+m")]
+        );
+        assert_eq!(
+            taken,
+            Trigger::parse("LOGO+p").into_iter().collect::<Vec<_>>()
+        );
+
+        let apps: Vec<String> = load_all_in(&path).into_iter().map(|(app, _)| app).collect();
+
+        assert_eq!(apps, ["org.example.App", "org.example.Other"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
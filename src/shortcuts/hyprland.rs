@@ -0,0 +1,324 @@
+use std::{
+    os::fd::{AsRawFd, BorrowedFd},
+    process::Command,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender},
+    time::Duration,
+};
+
+use tokio::sync::mpsc::UnboundedSender;
+use wayland_client::{
+    delegate_noop,
+    globals::{registry_queue_init, GlobalListContents},
+    protocol::wl_registry,
+    Connection, Dispatch, EventQueue, QueueHandle,
+};
+
+use self::protocol::{hyprland_global_shortcut_v1, hyprland_global_shortcuts_manager_v1};
+use super::{run, Binding, Event, Grabber, Trigger};
+
+/// How often the connection is checked for the compositor's events while the bindings stay
+/// the same.
+const INTERVAL: Duration = Duration::from_millis(20);
+
+/// The names Hyprland gives the modifiers of triggers.
+const MODIFIERS: [(&str, &str); 5] = [
+    ("CTRL", "CTRL"),
+    ("ALT", "ALT"),
+    ("SHIFT", "SHIFT"),
+    ("LOGO", "SUPER"),
+    ("NUM", "MOD2"),
+];
+
+/// The client side of Hyprland's global shortcuts protocol, which wayland-protocols lacks.
+mod protocol {
+    #![allow(dead_code, non_camel_case_types, unused_unsafe, unused_variables)]
+    #![allow(non_upper_case_globals, non_snake_case, unused_imports)]
+    #![allow(missing_docs, clippy::all)]
+
+    use wayland_client;
+    use wayland_client::protocol::*;
+
+    pub mod __interfaces {
+        use wayland_client::protocol::__interfaces::*;
+        wayland_scanner::generate_interfaces!("protocols/hyprland-global-shortcuts-v1.xml");
+    }
+    use self::__interfaces::*;
+
+    wayland_scanner::generate_client_code!("protocols/hyprland-global-shortcuts-v1.xml");
+}
+
+/// `Hyprland` grabs triggers with Hyprland's global shortcuts protocol, binding each to its
+/// shortcut through `hyprctl`.
+///
+/// The shortcuts are registered by a thread of their own, which owns the connection.
+pub struct Hyprland {
+    requests: Sender<Vec<Binding>>,
+}
+
+impl Hyprland {
+    /// Connect to Hyprland, failing if it doesn't support global shortcuts.
+    pub fn new(events: UnboundedSender<Event>) -> std::io::Result<Self> {
+        let (requests, receiver) = mpsc::channel();
+
+        let (ready, connected) = mpsc::sync_channel(1);
+
+        std::thread::Builder::new()
+            .name(String::from("hyprland-shortcuts"))
+            .spawn(move || serve(events, receiver, ready))?;
+
+        connected
+            .recv()
+            .map_err(|_| std::io::Error::other("the hyprland-shortcuts thread died"))??;
+
+        Ok(Self { requests })
+    }
+}
+
+impl Grabber for Hyprland {
+    fn grab(&self, bindings: &[Binding]) {
+        if self.requests.send(bindings.to_vec()).is_err() {
+            log::warn!("lost the connection to Hyprland, global shortcuts can't change");
+        }
+    }
+}
+
+/// `Client` is the Wayland side of the global shortcuts.
+struct Client {
+    queue: EventQueue<State>,
+    state: State,
+    manager: hyprland_global_shortcuts_manager_v1::HyprlandGlobalShortcutsManagerV1,
+
+    /// The bindings registered, with their shortcut objects.
+    registered: Vec<(
+        Binding,
+        hyprland_global_shortcut_v1::HyprlandGlobalShortcutV1,
+    )>,
+}
+
+/// `State` forwards the events of the shortcuts.
+struct State {
+    events: UnboundedSender<Event>,
+}
+
+/// Connect to Hyprland, report whether that worked on `ready`, then register the bindings
+/// of each of the `requests` until the grabber is dropped or the connection is lost.
+fn serve(
+    events: UnboundedSender<Event>,
+    requests: Receiver<Vec<Binding>>,
+    ready: SyncSender<std::io::Result<()>>,
+) {
+    let mut client = match Client::connect(events) {
+        Ok(client) => {
+            let _ = ready.send(Ok(()));
+            client
+        }
+
+        Err(e) => {
+            let _ = ready.send(Err(e));
+            return;
+        }
+    };
+
+    loop {
+        match requests.recv_timeout(INTERVAL) {
+            Ok(bindings) => client.grab(bindings),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+
+        if let Err(e) = client.dispatch() {
+            log::warn!("lost the connection to Hyprland: {}", e);
+            return;
+        }
+    }
+}
+
+impl Client {
+    fn connect(events: UnboundedSender<Event>) -> std::io::Result<Self> {
+        let conn = Connection::connect_to_env().map_err(std::io::Error::other)?;
+
+        let (globals, queue) =
+            registry_queue_init::<State>(&conn).map_err(std::io::Error::other)?;
+
+        let manager = globals
+            .bind(&queue.handle(), 1..=1, ())
+            .map_err(|e| std::io::Error::other(format!("no global shortcuts support: {}", e)))?;
+
+        Ok(Self {
+            queue,
+            state: State { events },
+            manager,
+            registered: Vec::new(),
+        })
+    }
+
+    /// Register `bindings`, destroying the shortcuts registered before that aren't among
+    /// them.
+    fn grab(&mut self, bindings: Vec<Binding>) {
+        let (kept, released): (Vec<_>, Vec<_>) = std::mem::take(&mut self.registered)
+            .into_iter()
+            .partition(|(binding, _)| bindings.contains(binding));
+
+        for (binding, shortcut) in released {
+            if let Err(e) = hyprctl(&["keyword", "unbind", &keys(&binding.trigger)]) {
+                log::warn!("failed to unbind {}: {}", binding.trigger, e);
+            }
+
+            shortcut.destroy();
+        }
+
+        self.registered = kept;
+
+        let qh = self.queue.handle();
+
+        for binding in bindings {
+            // Registering a shortcut of an app twice is a protocol error.
+            let registered = self
+                .registered
+                .iter()
+                .any(|(other, _)| other.app_id == binding.app_id && other.id == binding.id);
+
+            if registered {
+                continue;
+            }
+
+            let shortcut = self.manager.register_shortcut(
+                binding.id.clone(),
+                binding.app_id.clone(),
+                binding.description.clone(),
+                binding.trigger.describe(),
+                &qh,
+                (binding.app_id.clone(), binding.id.clone()),
+            );
+
+            if let Err(e) = hyprctl(&["keyword", "bind", &bind(&binding)]) {
+                log::warn!("failed to bind {}: {}", binding.trigger, e);
+            }
+
+            self.registered.push((binding, shortcut));
+        }
+    }
+
+    /// Handle what the compositor sent.
+    fn dispatch(&mut self) -> std::io::Result<()> {
+        self.queue.flush().map_err(std::io::Error::other)?;
+
+        if let Some(guard) = self.queue.prepare_read() {
+            if readable(guard.connection_fd())? {
+                guard.read().map_err(std::io::Error::other)?;
+            }
+        }
+
+        self.queue
+            .dispatch_pending(&mut self.state)
+            .map_err(std::io::Error::other)?;
+
+        self.queue.flush().map_err(std::io::Error::other)
+    }
+}
+
+/// Write the modifiers and key of `trigger` as Hyprland binds take them, like `CTRL SHIFT,m`.
+fn keys(trigger: &Trigger) -> String {
+    let modifiers: Vec<&str> = trigger
+        .modifiers
+        .iter()
+        .filter_map(|name| MODIFIERS.iter().find(|(other, _)| other == name))
+        .map(|(_, modifier)| *modifier)
+        .collect();
+
+    format!("{},{}", modifiers.join(" "), trigger.key)
+}
+
+/// Write the bind handing the trigger of `binding` to its shortcut.
+fn bind(binding: &Binding) -> String {
+    format!(
+        "{},global,{}:{}",
+        keys(&binding.trigger),
+        binding.app_id,
+        binding.id
+    )
+}
+
+/// Run `hyprctl` with `args`, which prints `ok` unless Hyprland refused the command.
+fn hyprctl(args: &[&str]) -> std::io::Result<()> {
+    let reply = run(Command::new("hyprctl").args(args))?;
+
+    match reply.trim() {
+        "ok" => Ok(()),
+        reply => Err(std::io::Error::other(String::from(reply))),
+    }
+}
+
+/// Check whether `fd` is readable, without waiting.
+fn readable(fd: BorrowedFd<'_>) -> std::io::Result<bool> {
+    let mut pollfd = libc::pollfd {
+        fd: fd.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+
+    match unsafe { libc::poll(&mut pollfd, 1, 0) } {
+        count if count >= 0 => Ok(count > 0),
+
+        _ => match std::io::Error::last_os_error() {
+            e if e.kind() == std::io::ErrorKind::Interrupted => Ok(false),
+            e => Err(e),
+        },
+    }
+}
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for State {
+    fn event(
+        _: &mut Self,
+        _: &wl_registry::WlRegistry,
+        _: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+/// Each shortcut knows the app and the id it's registered for.
+impl Dispatch<hyprland_global_shortcut_v1::HyprlandGlobalShortcutV1, (String, String)> for State {
+    fn event(
+        state: &mut Self,
+        _: &hyprland_global_shortcut_v1::HyprlandGlobalShortcutV1,
+        event: hyprland_global_shortcut_v1::Event,
+        (app_id, id): &(String, String),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let pressed = match event {
+            hyprland_global_shortcut_v1::Event::Pressed { .. } => true,
+            hyprland_global_shortcut_v1::Event::Released { .. } => false,
+        };
+
+        let _ = state.events.send(Event {
+            app_id: app_id.clone(),
+            id: id.clone(),
+            pressed,
+        });
+    }
+}
+
+delegate_noop!(State: hyprland_global_shortcuts_manager_v1::HyprlandGlobalShortcutsManagerV1);
+
+#[cfg(test)]
+mod tests {
+    use super::{bind, keys, Binding, Trigger};
+
+    #[test]
+    fn binds() {
+        let binding = Binding {
+            app_id: String::from("org.example.App"),
+            id: String::from("talk"),
+            description: String::from("Push to talk"),
+            trigger: Trigger::parse("LOGO+SHIFT+t").unwrap(),
+        };
+
+        assert_eq!(keys(&binding.trigger), "SHIFT SUPER,t");
+        assert_eq!(bind(&binding), "SHIFT SUPER,t,global,org.example.App:talk");
+        assert_eq!(keys(&Trigger::parse("F1").unwrap()), ",F1");
+    }
+}
@@ -0,0 +1,287 @@
+use futures_util::StreamExt;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use zbus::{dbus_proxy, zvariant};
+
+use super::{Binding, Event, Grabber, Trigger};
+
+/// Tells kglobalaccel a shortcut is the default one of its action.
+const IS_DEFAULT: u32 = 1;
+
+/// Tells kglobalaccel to make a shortcut the present one of its action, unless the user
+/// chose another in Plasma's settings.
+const SET_PRESENT: u32 = 2;
+
+/// The Qt modifiers of the modifiers of triggers, which have none for Num Lock.
+const MODIFIERS: [(&str, i32); 4] = [
+    ("CTRL", 0x0400_0000),
+    ("ALT", 0x0800_0000),
+    ("SHIFT", 0x0200_0000),
+    ("LOGO", 0x1000_0000),
+];
+
+/// The Qt keys of the keysyms that aren't letters, digits or function keys.
+const KEYS: [(&str, i32); 24] = [
+    ("Escape", 0x0100_0000),
+    ("Tab", 0x0100_0001),
+    ("BackSpace", 0x0100_0003),
+    ("Return", 0x0100_0004),
+    ("Insert", 0x0100_0006),
+    ("Delete", 0x0100_0007),
+    ("Pause", 0x0100_0008),
+    ("Print", 0x0100_0009),
+    ("Home", 0x0100_0010),
+    ("End", 0x0100_0011),
+    ("Left", 0x0100_0012),
+    ("Up", 0x0100_0013),
+    ("Right", 0x0100_0014),
+    ("Down", 0x0100_0015),
+    ("Prior", 0x0100_0016),
+    ("Page_Up", 0x0100_0016),
+    ("Next", 0x0100_0017),
+    ("Page_Down", 0x0100_0017),
+    ("space", 0x20),
+    ("comma", 0x2c),
+    ("minus", 0x2d),
+    ("period", 0x2e),
+    ("slash", 0x2f),
+    ("semicolon", 0x3b),
+];
+
+/// KDE's global shortcuts daemon.
+#[dbus_proxy(
+    interface = "org.kde.KGlobalAccel",
+    default_service = "org.kde.kglobalaccel",
+    default_path = "/kglobalaccel"
+)]
+trait KGlobalAccel {
+    /// Register the action `action_id`, which is the component, the action, and their names.
+    #[dbus_proxy(name = "doRegister")]
+    fn do_register(&self, action_id: &[&str]) -> zbus::Result<()>;
+
+    #[dbus_proxy(name = "setShortcut")]
+    fn set_shortcut(&self, action_id: &[&str], keys: &[i32], flags: u32) -> zbus::Result<Vec<i32>>;
+
+    /// Release the shortcut of an action, which keeps it for when the action is registered
+    /// again.
+    #[dbus_proxy(name = "setInactive")]
+    fn set_inactive(&self, action_id: &[&str]) -> zbus::Result<()>;
+
+    #[dbus_proxy(name = "getComponent")]
+    fn get_component(&self, component_unique: &str) -> zbus::Result<zvariant::OwnedObjectPath>;
+}
+
+/// The actions of an app, as kglobalaccel groups them.
+#[dbus_proxy(
+    interface = "org.kde.kglobalaccel.Component",
+    default_service = "org.kde.kglobalaccel"
+)]
+trait Component {
+    #[dbus_proxy(signal, name = "globalShortcutPressed")]
+    fn global_shortcut_pressed(
+        &self,
+        component_unique: &str,
+        shortcut_unique: &str,
+        timestamp: i64,
+    ) -> zbus::Result<()>;
+
+    #[dbus_proxy(signal, name = "globalShortcutReleased")]
+    fn global_shortcut_released(
+        &self,
+        component_unique: &str,
+        shortcut_unique: &str,
+        timestamp: i64,
+    ) -> zbus::Result<()>;
+}
+
+/// `Kde` registers shortcuts with kglobalaccel, as actions of a component named after the
+/// app, so they show up in Plasma's settings where their triggers can be changed.
+pub struct Kde {
+    requests: UnboundedSender<Vec<Binding>>,
+}
+
+impl Kde {
+    /// Create the backend; it must be called from within the tokio runtime.
+    pub fn new(events: UnboundedSender<Event>) -> Self {
+        let (requests, receiver) = tokio::sync::mpsc::unbounded_channel();
+
+        tokio::spawn(serve(events, receiver));
+
+        Self { requests }
+    }
+}
+
+impl Grabber for Kde {
+    fn grab(&self, bindings: &[Binding]) {
+        if self.requests.send(bindings.to_vec()).is_err() {
+            log::warn!("lost the connection to kglobalaccel, global shortcuts can't change");
+        }
+    }
+}
+
+/// `Client` is the end of the shortcuts talking to kglobalaccel.
+struct Client {
+    conn: zbus::Connection,
+    events: UnboundedSender<Event>,
+
+    /// The bindings registered.
+    bound: Vec<Binding>,
+
+    /// The apps whose components are listened to.
+    listened: Vec<String>,
+}
+
+/// Register the bindings of each of the `requests`, until the grabber is dropped.
+async fn serve(events: UnboundedSender<Event>, mut requests: UnboundedReceiver<Vec<Binding>>) {
+    let conn = match zbus::Connection::session().await {
+        Ok(conn) => conn,
+
+        Err(e) => {
+            log::warn!("failed to connect to kglobalaccel: {}", e);
+            return;
+        }
+    };
+
+    let mut client = Client {
+        conn,
+        events,
+        bound: Vec::new(),
+        listened: Vec::new(),
+    };
+
+    while let Some(bindings) = requests.recv().await {
+        if let Err(e) = client.grab(bindings).await {
+            log::warn!("failed to bind global shortcuts in kglobalaccel: {}", e);
+        }
+    }
+}
+
+impl Client {
+    /// Register `bindings`, releasing the shortcuts registered before that aren't among them.
+    async fn grab(&mut self, bindings: Vec<Binding>) -> zbus::Result<()> {
+        let accel = KGlobalAccelProxy::new(&self.conn).await?;
+
+        let bound = std::mem::replace(&mut self.bound, bindings.clone());
+
+        for binding in bound.iter().filter(|binding| !bindings.contains(binding)) {
+            accel.set_inactive(&action(binding)).await?;
+        }
+
+        for binding in bindings.iter().filter(|binding| !bound.contains(binding)) {
+            let Some(key) = qt_key(&binding.trigger) else {
+                log::warn!("{} can't be bound in kglobalaccel", binding.trigger);
+                continue;
+            };
+
+            let action = action(binding);
+
+            accel.do_register(&action).await?;
+            accel.set_shortcut(&action, &[key], IS_DEFAULT).await?;
+            accel.set_shortcut(&action, &[key], SET_PRESENT).await?;
+
+            if !self.listened.contains(&binding.app_id) {
+                self.listen(&accel, &binding.app_id).await?;
+                self.listened.push(binding.app_id.clone());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Send an `Event` for each shortcut of `app_id` pressed or released, once its component
+    /// is registered.
+    async fn listen(&self, accel: &KGlobalAccelProxy<'_>, app_id: &str) -> zbus::Result<()> {
+        let path = accel.get_component(app_id).await?;
+
+        let component = ComponentProxy::builder(&self.conn)
+            .path(path)?
+            .build()
+            .await?;
+
+        let mut pressed = component.receive_global_shortcut_pressed().await?;
+        let mut released = component.receive_global_shortcut_released().await?;
+
+        let events = self.events.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let event = tokio::select! {
+                    Some(signal) = pressed.next() => signal.args().map(|args| Event {
+                        app_id: String::from(*args.component_unique()),
+                        id: String::from(*args.shortcut_unique()),
+                        pressed: true,
+                    }),
+
+                    Some(signal) = released.next() => signal.args().map(|args| Event {
+                        app_id: String::from(*args.component_unique()),
+                        id: String::from(*args.shortcut_unique()),
+                        pressed: false,
+                    }),
+
+                    else => return,
+                };
+
+                if let Ok(event) = event {
+                    if events.send(event).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
+
+/// Get the action id of `binding`: its component, action, and their names.
+fn action(binding: &Binding) -> [&str; 4] {
+    [
+        &binding.app_id,
+        &binding.id,
+        &binding.app_id,
+        &binding.description,
+    ]
+}
+
+/// Get the Qt key combination of `trigger`, or `None` if Qt has no name for it.
+fn qt_key(trigger: &Trigger) -> Option<i32> {
+    let key = match trigger.key.as_bytes() {
+        [key] if key.is_ascii_alphanumeric() => i32::from(key.to_ascii_uppercase()),
+
+        _ => match trigger
+            .key
+            .strip_prefix('F')
+            .and_then(|n| n.parse::<i32>().ok())
+        {
+            Some(n @ 1..=35) => 0x0100_002f + n,
+
+            _ => {
+                KEYS.iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(&trigger.key))?
+                    .1
+            }
+        },
+    };
+
+    trigger.modifiers.iter().try_fold(key, |key, name| {
+        let (_, modifier) = MODIFIERS.iter().find(|(other, _)| other == name)?;
+
+        Some(key | modifier)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{qt_key, Trigger};
+
+    #[test]
+    fn qt_keys() {
+        let key = |trigger: &str| qt_key(&Trigger::parse(trigger).unwrap());
+
+        assert_eq!(key("CTRL+SHIFT+m"), Some(0x0600_004d));
+        assert_eq!(key("LOGO+F1"), Some(0x1100_0030));
+        assert_eq!(key("ALT+Page_Down"), Some(0x0900_0017));
+        assert_eq!(key("7"), Some(0x37));
+        assert_eq!(key("NUM+a"), None);
+        assert_eq!(key("XF86AudioPlay"), None);
+    }
+}
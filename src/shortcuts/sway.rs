@@ -0,0 +1,246 @@
+use std::{
+    io::{BufRead, BufReader},
+    process::{Child, ChildStdout, Command, Stdio},
+    sync::Mutex,
+};
+
+use percent_encoding::{AsciiSet, CONTROLS};
+use tokio::sync::mpsc::UnboundedSender;
+
+use super::{run, Binding, Event, Grabber, Trigger};
+
+/// The command of the bindings made here, which does nothing but tell the shortcut apart.
+const COMMAND: &str = "nop xdg-desktop-portal-rs";
+
+/// The names sway gives the modifiers of triggers.
+const MODIFIERS: [(&str, &str); 5] = [
+    ("CTRL", "Ctrl"),
+    ("ALT", "Mod1"),
+    ("SHIFT", "Shift"),
+    ("LOGO", "Mod4"),
+    ("NUM", "Mod2"),
+];
+
+/// The characters escaped in the app ids and shortcut ids of commands, which sway splits
+/// at spaces and semicolons.
+const FIELD: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b';')
+    .add(b',')
+    .add(b'"')
+    .add(b'\'')
+    .add(b'%');
+
+/// `Sway` binds triggers through `swaymsg`, to commands `swaymsg` reports back as they run.
+///
+/// A trigger bound in sway's config too is taken over while the shortcut is bound.
+pub struct Sway {
+    /// The bindings made.
+    bound: Mutex<Vec<Binding>>,
+}
+
+impl Sway {
+    /// Start listening for sway's bindings to run.
+    pub fn new(events: UnboundedSender<Event>) -> std::io::Result<Self> {
+        let mut monitor = Command::new("swaymsg")
+            .args([
+                "--monitor",
+                "--raw",
+                "--type",
+                "subscribe",
+                r#"["binding"]"#,
+            ])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let stdout = monitor
+            .stdout
+            .take()
+            .ok_or_else(|| std::io::Error::other("swaymsg has no output"))?;
+
+        std::thread::Builder::new()
+            .name(String::from("sway-shortcuts"))
+            .spawn(move || listen(monitor, stdout, events))?;
+
+        Ok(Self {
+            bound: Mutex::default(),
+        })
+    }
+}
+
+impl Grabber for Sway {
+    fn grab(&self, bindings: &[Binding]) {
+        let mut bound = self.bound.lock().unwrap_or_else(|e| e.into_inner());
+
+        // Released triggers are unbound first, in case a new binding takes one of them.
+        let commands: Vec<String> = bound
+            .iter()
+            .filter(|binding| !bindings.contains(binding))
+            .flat_map(|binding| unbind(&binding.trigger))
+            .chain(
+                bindings
+                    .iter()
+                    .filter(|binding| !bound.contains(binding))
+                    .flat_map(bind),
+            )
+            .collect();
+
+        *bound = bindings.to_vec();
+
+        if commands.is_empty() {
+            return;
+        }
+
+        if let Err(e) = run(Command::new("swaymsg").arg(commands.join("; "))) {
+            log::warn!("failed to bind global shortcuts in sway: {}", e);
+        }
+    }
+}
+
+/// Send an `Event` for each binding made here that `monitor` reports run, until it exits.
+fn listen(mut monitor: Child, stdout: ChildStdout, events: UnboundedSender<Event>) {
+    for line in BufReader::new(stdout).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+
+        let Ok(message) = serde_json::from_str::<serde_json::Value>(&line) else {
+            continue;
+        };
+
+        let Some(event) = message["binding"]["command"].as_str().and_then(parse) else {
+            continue;
+        };
+
+        if events.send(event).is_err() {
+            break;
+        }
+    }
+
+    let _ = monitor.kill();
+    let _ = monitor.wait();
+
+    log::warn!("stopped listening for sway's bindings, global shortcuts aren't activated");
+}
+
+/// Write the modifiers and key of `trigger` as sway binds take them, like `Ctrl+Shift+m`.
+fn combination(trigger: &Trigger) -> String {
+    trigger
+        .modifiers
+        .iter()
+        .filter_map(|name| MODIFIERS.iter().find(|(other, _)| other == name))
+        .map(|(_, modifier)| *modifier)
+        .chain([trigger.key.as_str()])
+        .collect::<Vec<&str>>()
+        .join("+")
+}
+
+/// Write the commands binding the trigger of `binding` as it's pressed and released.
+fn bind(binding: &Binding) -> [String; 2] {
+    let combination = combination(&binding.trigger);
+
+    let app_id = percent_encoding::utf8_percent_encode(&binding.app_id, FIELD);
+    let id = percent_encoding::utf8_percent_encode(&binding.id, FIELD);
+
+    [
+        format!(
+            "bindsym --no-repeat {} {} pressed {} {}",
+            combination, COMMAND, app_id, id
+        ),
+        format!(
+            "bindsym --release {} {} released {} {}",
+            combination, COMMAND, app_id, id
+        ),
+    ]
+}
+
+/// Write the commands undoing `bind`.
+fn unbind(trigger: &Trigger) -> [String; 2] {
+    let combination = combination(trigger);
+
+    [
+        format!("unbindsym --no-repeat {}", combination),
+        format!("unbindsym --release {}", combination),
+    ]
+}
+
+/// Parse the command of a binding run, or return `None` if it isn't one made here.
+fn parse(command: &str) -> Option<Event> {
+    let decode = |field: &str| {
+        percent_encoding::percent_decode_str(field)
+            .decode_utf8()
+            .ok()
+            .map(String::from)
+    };
+
+    let fields: Vec<&str> = command.strip_prefix(COMMAND)?.split_whitespace().collect();
+
+    let [state, app_id, id] = fields[..] else {
+        return None;
+    };
+
+    let pressed = match state {
+        "pressed" => true,
+        "released" => false,
+        _ => return None,
+    };
+
+    Some(Event {
+        app_id: decode(app_id)?,
+        id: decode(id)?,
+        pressed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bind, parse, unbind, Binding, Event, Trigger};
+
+    #[test]
+    fn commands() {
+        let binding = Binding {
+            app_id: String::from("org.example.App"),
+            id: String::from("talk; exit"),
+            description: String::from("Push to talk"),
+            trigger: Trigger::parse("LOGO+SHIFT+t").unwrap(),
+        };
+
+        let [pressed, released] = bind(&binding);
+
+        assert_eq!(
+            pressed,
+            "bindsym --no-repeat Shift+Mod4+t nop xdg-desktop-portal-rs pressed \
+             org.example.App talk%3B%20exit"
+        );
+        assert_eq!(
+            unbind(&binding.trigger),
+            [
+                "unbindsym --no-repeat Shift+Mod4+t",
+                "unbindsym --release Shift+Mod4+t"
+            ]
+        );
+
+        // What's bound is parsed back when sway runs it.
+        let command = |bound: &str| String::from(bound.split_once("+t ").unwrap().1);
+
+        assert_eq!(
+            parse(&command(&released)),
+            Some(Event {
+                app_id: binding.app_id.clone(),
+                id: binding.id.clone(),
+                pressed: false,
+            })
+        );
+        assert_eq!(
+            parse(&command(&pressed)).map(|event| event.pressed),
+            Some(true)
+        );
+        assert_eq!(parse("nop something else"), None);
+        assert_eq!(
+            parse("nop xdg-desktop-portal-rs pressed org.example.App"),
+            None
+        );
+    }
+}
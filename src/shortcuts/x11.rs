@@ -0,0 +1,290 @@
+use std::{
+    ffi::CString,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::mpsc::UnboundedSender;
+use x11rb::{
+    connection::Connection,
+    protocol::{
+        xproto::{ConnectionExt as _, GrabMode, Keycode, ModMask, Window},
+        Event as XEvent,
+    },
+    rust_connection::RustConnection,
+};
+use xkbcommon_dl::{xkb_keysym_flags, xkbcommon_option};
+
+use super::{Binding, Event, Grabber, Trigger};
+
+/// The X modifier masks of the modifiers of triggers.
+const MODIFIERS: [(&str, u16); 5] = [
+    ("CTRL", 1 << 2),
+    ("ALT", 1 << 3),
+    ("SHIFT", 1),
+    ("LOGO", 1 << 6),
+    ("NUM", NUM_LOCK),
+];
+
+/// The mask of Caps Lock, which doesn't keep triggers from being pressed.
+const CAPS_LOCK: u16 = 1 << 1;
+
+/// The mask of Num Lock, which doesn't either unless the trigger has it.
+const NUM_LOCK: u16 = 1 << 4;
+
+/// `Grabbed` is a binding whose key is grabbed.
+struct Grabbed {
+    binding: Binding,
+    keycode: Keycode,
+    modifiers: u16,
+}
+
+/// `X11` grabs the keys of triggers on the root window of the X server.
+///
+/// Keys are looked up in the keyboard mapping as it is when the portal starts.
+pub struct X11 {
+    conn: Arc<RustConnection>,
+    root: Window,
+
+    /// The keysyms of each keycode from the first, and how many each has.
+    keysyms: (Keycode, Vec<u32>, usize),
+
+    grabbed: Arc<Mutex<Vec<Grabbed>>>,
+}
+
+impl X11 {
+    /// Connect to the X server and start listening for the keys grabbed.
+    pub fn new(events: UnboundedSender<Event>) -> std::io::Result<Self> {
+        let (conn, screen) = x11rb::connect(None).map_err(std::io::Error::other)?;
+
+        let setup = conn.setup();
+        let root = setup.roots[screen].root;
+        let (first, last) = (setup.min_keycode, setup.max_keycode);
+
+        let mapping = conn
+            .get_keyboard_mapping(first, last - first + 1)
+            .map_err(std::io::Error::other)?
+            .reply()
+            .map_err(std::io::Error::other)?;
+
+        let keysyms = (
+            first,
+            mapping.keysyms,
+            usize::from(mapping.keysyms_per_keycode),
+        );
+
+        let conn = Arc::new(conn);
+        let grabbed = Arc::new(Mutex::new(Vec::new()));
+
+        std::thread::Builder::new()
+            .name(String::from("x11-shortcuts"))
+            .spawn({
+                let conn = conn.clone();
+                let grabbed = grabbed.clone();
+
+                move || listen(&conn, &grabbed, &events)
+            })?;
+
+        Ok(Self {
+            conn,
+            root,
+            keysyms,
+            grabbed,
+        })
+    }
+
+    /// Find the keycode typing the keysym named `name`.
+    fn keycode(&self, name: &str) -> Option<Keycode> {
+        let keysym = keysym(name)?;
+
+        let (first, keysyms, per_keycode) = &self.keysyms;
+
+        let index = keysyms
+            .chunks(*per_keycode)
+            .position(|keysyms| keysyms.contains(&keysym))?;
+
+        first.checked_add(u8::try_from(index).ok()?)
+    }
+
+    /// Run `f` with each modifier mask the key of a trigger with `modifiers` is grabbed with,
+    /// which are those with and without the locks it doesn't have.
+    fn each_mask(modifiers: u16, mut f: impl FnMut(ModMask)) {
+        for locks in [0, CAPS_LOCK, NUM_LOCK, CAPS_LOCK | NUM_LOCK] {
+            if locks & modifiers == 0 {
+                f(ModMask::from(modifiers | locks));
+            }
+        }
+    }
+}
+
+impl Grabber for X11 {
+    fn grab(&self, bindings: &[Binding]) {
+        let mut grabbed = self.grabbed.lock().unwrap_or_else(|e| e.into_inner());
+
+        for released in grabbed.iter().filter(|g| !bindings.contains(&g.binding)) {
+            Self::each_mask(released.modifiers, |mask| {
+                let _ = self.conn.ungrab_key(released.keycode, self.root, mask);
+            });
+        }
+
+        grabbed.retain(|g| bindings.contains(&g.binding));
+
+        for binding in bindings {
+            if grabbed.iter().any(|g| g.binding == *binding) {
+                continue;
+            }
+
+            let Some(keycode) = self.keycode(&binding.trigger.key) else {
+                log::warn!(
+                    "no key types {} to grab for {}",
+                    binding.trigger.key,
+                    binding.id
+                );
+                continue;
+            };
+
+            let modifiers = modifiers(&binding.trigger);
+
+            // Failing grabs, like of keys another client grabbed, are reported as errors.
+            Self::each_mask(modifiers, |mask| {
+                let _ = self.conn.grab_key(
+                    true,
+                    self.root,
+                    mask,
+                    keycode,
+                    GrabMode::ASYNC,
+                    GrabMode::ASYNC,
+                );
+            });
+
+            grabbed.push(Grabbed {
+                binding: binding.clone(),
+                keycode,
+                modifiers,
+            });
+        }
+
+        if let Err(e) = self.conn.flush() {
+            log::warn!("failed to grab global shortcuts from the X server: {}", e);
+        }
+    }
+}
+
+/// Send an `Event` for each of the keys `grabbed` pressed or released, until the connection
+/// is lost.
+fn listen(conn: &RustConnection, grabbed: &Mutex<Vec<Grabbed>>, events: &UnboundedSender<Event>) {
+    let mut next = None;
+
+    loop {
+        let event = match next.take() {
+            Some(event) => event,
+
+            None => match conn.wait_for_event() {
+                Ok(event) => event,
+
+                Err(e) => {
+                    log::warn!("lost the connection to the X server: {}", e);
+                    return;
+                }
+            },
+        };
+
+        let (keycode, state, pressed) = match event {
+            XEvent::KeyPress(key) => (key.detail, u16::from(key.state), true),
+
+            XEvent::KeyRelease(key) => {
+                // Held keys repeat as releases followed by presses at the same time.
+                if let Ok(Some(XEvent::KeyPress(press))) = conn.poll_for_event() {
+                    if press.detail == key.detail && press.time == key.time {
+                        continue;
+                    }
+
+                    next = Some(XEvent::KeyPress(press));
+                }
+
+                (key.detail, u16::from(key.state), false)
+            }
+
+            XEvent::Error(e) => {
+                log::warn!(
+                    "the X server refused to grab a global shortcut: {:?}",
+                    e.error_kind
+                );
+                continue;
+            }
+
+            _ => continue,
+        };
+
+        let grabbed = grabbed.lock().unwrap_or_else(|e| e.into_inner());
+
+        let binding = grabbed.iter().find(|g| {
+            let locks = CAPS_LOCK | (NUM_LOCK & !g.modifiers);
+
+            g.keycode == keycode && state & !locks == g.modifiers
+        });
+
+        if let Some(g) = binding {
+            let event = Event {
+                app_id: g.binding.app_id.clone(),
+                id: g.binding.id.clone(),
+                pressed,
+            };
+
+            if events.send(event).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Get the modifier mask of `trigger`.
+fn modifiers(trigger: &Trigger) -> u16 {
+    trigger
+        .modifiers
+        .iter()
+        .filter_map(|name| MODIFIERS.iter().find(|(other, _)| other == name))
+        .fold(0, |mask, (_, modifier)| mask | modifier)
+}
+
+/// Get the keysym named `name`, ignoring its case only if no keysym has the name as it is.
+fn keysym(name: &str) -> Option<u32> {
+    let xkb = xkbcommon_option()?;
+
+    let name = CString::new(name).ok()?;
+
+    [
+        xkb_keysym_flags::XKB_KEYSYM_NO_FLAGS,
+        xkb_keysym_flags::XKB_KEYSYM_CASE_INSENSITIVE,
+    ]
+    .into_iter()
+    .map(|flags| unsafe { (xkb.xkb_keysym_from_name)(name.as_ptr(), flags) })
+    .find(|keysym| *keysym != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{keysym, modifiers, Trigger};
+
+    #[test]
+    fn masks() {
+        assert_eq!(modifiers(&Trigger::parse("CTRL+SHIFT+m").unwrap()), 0b101);
+        assert_eq!(
+            modifiers(&Trigger::parse("LOGO+NUM+F1").unwrap()),
+            0b101_0000
+        );
+        assert_eq!(modifiers(&Trigger::parse("space").unwrap()), 0);
+    }
+
+    #[test]
+    fn keysyms() {
+        // Machines without libxkbcommon can't look keysyms up at all.
+        if xkbcommon_dl::xkbcommon_option().is_none() {
+            return;
+        }
+
+        assert_eq!(keysym("m"), Some(0x6d));
+        assert_eq!(keysym("M"), Some(0x4d));
+        assert_eq!(keysym("return"), Some(0xff0d));
+        assert_eq!(keysym("NotAKey"), None);
+    }
+}
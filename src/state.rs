@@ -0,0 +1,134 @@
+use std::{collections::HashMap, path::PathBuf, sync::Mutex};
+
+use serde::{Deserialize, Serialize};
+
+/// Serializes read-modify-write cycles of the state file between concurrent requests.
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// `State` is remembered between sessions in `$XDG_STATE_HOME/xdg-desktop-portal-rs/state.toml`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct State {
+    /// Whether the built-in file chooser lists hidden files.
+    pub show_hidden: bool,
+
+    /// The directory each application last chose a file in, keyed by app id.
+    pub last_directory: HashMap<String, PathBuf>,
+
+    /// The application to always use for each content type, keyed by content type.
+    pub always_use: HashMap<String, String>,
+}
+
+impl State {
+    /// Load the state file, falling back to the default state if it's missing or unreadable.
+    pub fn load() -> Self {
+        let Some(path) = path() else {
+            return Self::default();
+        };
+
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents).unwrap_or_else(|e| {
+                log::warn!("ignoring invalid state file {:?}: {}", path, e);
+                Self::default()
+            }),
+
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+
+            Err(e) => {
+                log::warn!("failed to read state file {:?}: {}", path, e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Write the state file, creating its directory if needed.
+    pub fn save(&self) -> std::io::Result<()> {
+        let path = path().ok_or(std::io::ErrorKind::NotFound)?;
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let contents = toml::to_string(self)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+        std::fs::write(path, contents)
+    }
+
+    /// Load the state, apply `f` to it and save it again.
+    pub fn update(f: impl FnOnce(&mut Self)) {
+        let _guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let mut state = Self::load();
+
+        f(&mut state);
+
+        if let Err(e) = state.save() {
+            log::warn!("failed to save state: {}", e);
+        }
+    }
+}
+
+/// Get the directory the given application last chose a file in, if it still exists.
+pub fn last_directory(app_id: &str) -> Option<PathBuf> {
+    State::load()
+        .last_directory
+        .remove(app_id)
+        .filter(|directory| directory.is_dir())
+}
+
+/// Remember the directory the given application chose a file in.
+pub fn set_last_directory(app_id: &str, directory: &std::path::Path) {
+    // TOML strings must be valid UTF-8, so such directories can't be remembered.
+    if directory.to_str().is_none() {
+        log::debug!("not remembering non-UTF-8 directory {:?}", directory);
+        return;
+    }
+
+    State::update(|state| {
+        state
+            .last_directory
+            .insert(app_id.to_string(), directory.to_path_buf());
+    });
+}
+
+/// Get the application the user always wants to use for a content type.
+pub fn always_use(content_type: &str) -> Option<String> {
+    State::load().always_use.remove(content_type)
+}
+
+/// Remember the application to always use for a content type, or forget it.
+pub fn set_always_use(content_type: &str, app: Option<&str>) {
+    State::update(|state| match app {
+        Some(app) => {
+            state
+                .always_use
+                .insert(content_type.to_owned(), app.to_owned());
+        }
+
+        None => {
+            state.always_use.remove(content_type);
+        }
+    });
+}
+
+/// Get whether the built-in file choosers list hidden files.
+#[cfg_attr(not(any(feature = "egui", feature = "tui")), allow(dead_code))]
+pub fn show_hidden() -> bool {
+    State::load().show_hidden
+}
+
+/// Remember whether the built-in file chooser lists hidden files.
+#[cfg_attr(not(feature = "egui"), allow(dead_code))]
+pub fn set_show_hidden(show_hidden: bool) {
+    State::update(|state| state.show_hidden = show_hidden);
+}
+
+/// Get the path of the state file.
+fn path() -> Option<PathBuf> {
+    Some(
+        dirs::state_dir()?
+            .join("xdg-desktop-portal-rs")
+            .join("state.toml"),
+    )
+}
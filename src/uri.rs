@@ -0,0 +1,126 @@
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC};
+
+/// Characters left unescaped in the path of a `file://` URI.
+///
+/// This is the RFC 3986 unreserved set plus the sub-delimiters, `:`, `@` and `/`,
+/// matching what GLib's `g_filename_to_uri` produces.
+const PATH: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~')
+    .remove(b'!')
+    .remove(b'$')
+    .remove(b'&')
+    .remove(b'\'')
+    .remove(b'(')
+    .remove(b')')
+    .remove(b'*')
+    .remove(b'+')
+    .remove(b',')
+    .remove(b';')
+    .remove(b'=')
+    .remove(b':')
+    .remove(b'@')
+    .remove(b'/');
+
+/// Convert an absolute path to a `file://` URI with an empty authority, as described in RFC 8089.
+///
+/// The path is encoded from its raw bytes, so names that aren't valid UTF-8 survive the round trip.
+pub fn file_uri(path: &std::path::Path) -> String {
+    format!("file://{}", encode_path(path))
+}
+
+/// Percent-encode a path for use as the path of a URI.
+pub fn encode_path(path: &std::path::Path) -> String {
+    use std::os::unix::ffi::OsStrExt;
+
+    let bytes = path.as_os_str().as_bytes();
+
+    percent_encoding::percent_encode(bytes, PATH).to_string()
+}
+
+/// Convert a local `file://` URI back to a path, decoding escaped bytes.
+///
+/// Returns `None` for other schemes and for URIs naming a remote host.
+pub fn file_path(uri: &str) -> Option<std::path::PathBuf> {
+    use std::os::unix::ffi::OsStringExt;
+
+    let rest = uri.strip_prefix("file://")?;
+
+    let path = rest.strip_prefix("localhost").unwrap_or(rest);
+
+    if !path.starts_with('/') {
+        return None;
+    }
+
+    let bytes: Vec<u8> = percent_encoding::percent_decode_str(path).collect();
+
+    Some(std::ffi::OsString::from_vec(bytes).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{file_path, file_uri};
+    use std::path::Path;
+
+    #[test]
+    fn plain_path() {
+        assert_eq!(
+            file_uri(Path::new("/home/user/file.txt")),
+            "file:///home/user/file.txt"
+        );
+    }
+
+    #[test]
+    fn reserved_characters() {
+        assert_eq!(
+            file_uri(Path::new("/tmp/my file #1?.txt")),
+            "file:///tmp/my%20file%20%231%3F.txt"
+        );
+
+        assert_eq!(
+            file_uri(Path::new("/tmp/100%.txt")),
+            "file:///tmp/100%25.txt"
+        );
+    }
+
+    #[test]
+    fn allowed_delimiters() {
+        assert_eq!(
+            file_uri(Path::new("/tmp/a+b=c,d;e@f:(g)")),
+            "file:///tmp/a+b=c,d;e@f:(g)"
+        );
+    }
+
+    #[test]
+    fn non_ascii() {
+        assert_eq!(
+            file_uri(Path::new("/tmp/café/日本.png")),
+            "file:///tmp/caf%C3%A9/%E6%97%A5%E6%9C%AC.png"
+        );
+    }
+
+    #[test]
+    fn non_utf8() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        // "café" encoded as Latin-1.
+        let path = Path::new(OsStr::from_bytes(b"/tmp/caf\xe9.txt"));
+
+        assert_eq!(file_uri(path), "file:///tmp/caf%E9.txt");
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = Path::new("/tmp/my file #1?.txt");
+
+        assert_eq!(file_path(&file_uri(path)).as_deref(), Some(path));
+        assert_eq!(
+            file_path("file://localhost/tmp").as_deref(),
+            Some(Path::new("/tmp"))
+        );
+        assert_eq!(file_path("file://example.com/tmp"), None);
+        assert_eq!(file_path("sftp://example.com/tmp"), None);
+    }
+}
@@ -0,0 +1,241 @@
+use std::{
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::Arc,
+};
+
+use crate::{
+    capture::{Capture, Image},
+    config::{WallpaperBackend, WallpaperConfig},
+};
+
+mod feh;
+mod hyprpaper;
+mod preview;
+mod swaybg;
+mod swww;
+
+/// `Setter` sets the wallpaper for the Wallpaper portal.
+///
+/// Setting it blocks until the program doing it is done, so callers run it off the async
+/// executor.
+pub trait Setter: Send + Sync {
+    /// Show the picture at `path` as the wallpaper of every output.
+    fn set(&self, path: &Path) -> std::io::Result<()>;
+}
+
+/// `Target` is what a picture is set as, by the `set-on` option of the portal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Background,
+    LockScreen,
+    Both,
+}
+
+impl Target {
+    /// Get the target named by a `set-on` option, or `None` if it names none.
+    pub fn parse(set_on: &str) -> Option<Self> {
+        match set_on {
+            "background" => Some(Self::Background),
+            "lockscreen" => Some(Self::LockScreen),
+            "both" => Some(Self::Both),
+            _ => None,
+        }
+    }
+
+    /// Describe what the picture is set as for people.
+    pub fn describe(self) -> &'static str {
+        match self {
+            Self::Background => "the wallpaper",
+            Self::LockScreen => "the lock screen picture",
+            Self::Both => "the wallpaper and the lock screen picture",
+        }
+    }
+}
+
+/// Set the picture at `path` as `target`, with `setter` for the background.
+///
+/// Lock screens aren't running to be told, so their picture is copied to the path
+/// `lock_screen` returns, which the configs of lockers like swaylock can point at.
+pub fn apply(setter: &dyn Setter, path: &Path, target: Target) -> std::io::Result<()> {
+    if target != Target::LockScreen {
+        setter.set(&store(path)?)?;
+    }
+
+    if target != Target::Background {
+        let lock_screen = lock_screen()?;
+
+        // The copy is renamed into place, so lockers never read half of it.
+        let partial = lock_screen.with_extension("partial");
+
+        std::fs::copy(path, &partial)?;
+        std::fs::rename(&partial, &lock_screen)?;
+    }
+
+    Ok(())
+}
+
+/// Get the path lock screen pictures are copied to,
+/// `$XDG_DATA_HOME/xdg-desktop-portal-rs/lockscreen`.
+pub fn lock_screen() -> std::io::Result<PathBuf> {
+    let dir = dirs::data_dir()
+        .ok_or_else(|| std::io::Error::other("no data directory"))?
+        .join("xdg-desktop-portal-rs");
+
+    std::fs::create_dir_all(&dir)?;
+
+    Ok(dir.join("lockscreen"))
+}
+
+/// Draw a preview of the picture at `path` on the outputs `capture` finds, for the user to
+/// confirm it with.
+///
+/// Only PNG pictures can be drawn, so the outputs are left blank for others; without a
+/// capture of the screen, a single 1920x1080 output is drawn.
+pub fn preview(path: &Path, capture: &dyn Capture) -> Image {
+    let picture = std::fs::read(path)
+        .ok()
+        .and_then(|bytes| Image::decode(&bytes));
+
+    let outputs = match capture.capture(false) {
+        Ok(screen) => capture.outputs(&screen),
+
+        Err(e) => {
+            log::warn!(
+                "failed to find the outputs to preview the wallpaper on: {}",
+                e
+            );
+            Vec::new()
+        }
+    };
+
+    preview::compose(picture.as_ref(), &outputs)
+}
+
+/// Keep a copy of the picture at `path`, returning the path of the copy, so the wallpaper
+/// stays when the original goes, like files apps only shared for the call.
+///
+/// Copies are named by the hash of the picture, as some setters cache pictures by path, and
+/// earlier copies are removed.
+fn store(path: &Path) -> std::io::Result<PathBuf> {
+    let bytes = std::fs::read(path)?;
+
+    let dir = dirs::data_dir()
+        .ok_or_else(|| std::io::Error::other("no data directory"))?
+        .join("xdg-desktop-portal-rs")
+        .join("wallpaper");
+
+    std::fs::create_dir_all(&dir)?;
+
+    let name = file_name(&bytes, path);
+
+    let copy = dir.join(&name);
+
+    if !copy.is_file() {
+        std::fs::write(&copy, &bytes)?;
+    }
+
+    for entry in std::fs::read_dir(&dir)?.flatten() {
+        if entry.file_name() != name.as_str() {
+            let _ = std::fs::remove_file(entry.path());
+        }
+    }
+
+    Ok(copy)
+}
+
+/// Name the copy of picture `bytes` read from `path`, keeping its extension so setters can
+/// tell its format.
+fn file_name(bytes: &[u8], path: &Path) -> String {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+
+    bytes.hash(&mut hasher);
+
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => format!("{:016x}.{}", hasher.finish(), extension),
+        None => format!("{:016x}", hasher.finish()),
+    }
+}
+
+/// Run a setter's `command`, failing if it does.
+fn run(command: &mut Command) -> std::io::Result<()> {
+    let output = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()?;
+
+    if !output.status.success() {
+        return Err(std::io::Error::other(format!(
+            "{:?} failed with {}: {}",
+            command.get_program(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(())
+}
+
+/// Create the wallpaper setter selected in the config.
+pub fn from_config(config: &WallpaperConfig) -> Arc<dyn Setter> {
+    let backend = match config.backend {
+        WallpaperBackend::Auto => detect(),
+        backend => backend,
+    };
+
+    log::debug!("setting the wallpaper with {:?}", backend);
+
+    match backend {
+        WallpaperBackend::Swaybg | WallpaperBackend::Auto => Arc::new(swaybg::Swaybg::default()),
+        WallpaperBackend::Hyprpaper => Arc::new(hyprpaper::Hyprpaper),
+        WallpaperBackend::Swww => Arc::new(swww::Swww),
+        WallpaperBackend::Feh => Arc::new(feh::Feh),
+    }
+}
+
+/// Pick the setter that fits the session.
+fn detect() -> WallpaperBackend {
+    if std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+        return WallpaperBackend::Hyprpaper;
+    }
+
+    // swww only works with its daemon running, which `swww query` fails without.
+    if run(Command::new("swww").arg("query")).is_ok() {
+        return WallpaperBackend::Swww;
+    }
+
+    match std::env::var_os("WAYLAND_DISPLAY") {
+        Some(_) => WallpaperBackend::Swaybg,
+        None => WallpaperBackend::Feh,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{file_name, Target};
+
+    #[test]
+    fn copy_names() {
+        let jpeg = file_name(b"picture", Path::new("/tmp/beach.jpg"));
+
+        assert!(jpeg.ends_with(".jpg"));
+        assert_eq!(
+            jpeg,
+            file_name(b"picture", Path::new("/doc/1234/beach.jpg"))
+        );
+        assert_ne!(jpeg, file_name(b"other", Path::new("/tmp/beach.jpg")));
+        assert!(!file_name(b"picture", Path::new("/tmp/beach")).contains('.'));
+    }
+
+    #[test]
+    fn targets() {
+        assert_eq!(Target::parse("background"), Some(Target::Background));
+        assert_eq!(Target::parse("lockscreen"), Some(Target::LockScreen));
+        assert_eq!(Target::parse("both"), Some(Target::Both));
+        assert_eq!(Target::parse("desktop"), None);
+    }
+}
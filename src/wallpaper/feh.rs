@@ -0,0 +1,13 @@
+use std::{path::Path, process::Command};
+
+use super::{run, Setter};
+
+/// `Feh` sets the wallpaper of X11 sessions with `feh`, which draws it on the root window.
+pub struct Feh;
+
+impl Setter for Feh {
+    fn set(&self, path: &Path) -> std::io::Result<()> {
+        // feh writes ~/.fehbg too, so the wallpaper is set again with `~/.fehbg` on login.
+        run(Command::new("feh").arg("--bg-fill").arg(path))
+    }
+}
@@ -0,0 +1,25 @@
+use std::{path::Path, process::Command};
+
+use super::{run, Setter};
+
+/// `Hyprpaper` sets the wallpaper through the `hyprpaper` daemon of Hyprland, which has to
+/// be running.
+pub struct Hyprpaper;
+
+impl Setter for Hyprpaper {
+    fn set(&self, path: &Path) -> std::io::Result<()> {
+        let path = path.to_string_lossy();
+
+        run(Command::new("hyprctl").args(["hyprpaper", "preload", &path]))?;
+
+        // No monitor before the comma sets it on every monitor.
+        run(Command::new("hyprctl").args(["hyprpaper", "wallpaper", &format!(",{}", path)]))?;
+
+        // Pictures stay loaded until they're unloaded, so the last one is let go of.
+        if let Err(e) = run(Command::new("hyprctl").args(["hyprpaper", "unload", "unused"])) {
+            log::warn!("failed to unload the last wallpaper: {}", e);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+use crate::capture::{Image, Output};
+
+/// The largest size of previews, in pixels.
+const MAX_SIZE: (f64, f64) = (640.0, 360.0);
+
+/// The layout previewed when the outputs aren't known.
+const FALLBACK_SIZE: (i32, i32) = (1920, 1080);
+
+const BACKDROP: [u8; 4] = [40, 40, 40, 255];
+const BLANK: [u8; 4] = [90, 90, 90, 255];
+const BORDER: [u8; 4] = [200, 200, 200, 255];
+
+/// Draw the `outputs` as the compositor lays them out, each filled with `picture` the way
+/// setters fill them, or blank if there's no picture to show.
+pub fn compose(picture: Option<&Image>, outputs: &[Output]) -> Image {
+    let areas: Vec<((i32, i32), (i32, i32))> = match outputs {
+        [] => vec![((0, 0), FALLBACK_SIZE)],
+        outputs => outputs
+            .iter()
+            .map(|output| (output.position, output.size))
+            .collect(),
+    };
+
+    let left = areas.iter().map(|((x, _), _)| *x).min().unwrap_or(0);
+    let top = areas.iter().map(|((_, y), _)| *y).min().unwrap_or(0);
+    let right = areas
+        .iter()
+        .map(|((x, _), (w, _))| x + w)
+        .max()
+        .unwrap_or(1);
+    let bottom = areas
+        .iter()
+        .map(|((_, y), (_, h))| y + h)
+        .max()
+        .unwrap_or(1);
+
+    let (width, height) = (
+        f64::from((right - left).max(1)),
+        f64::from((bottom - top).max(1)),
+    );
+
+    let scale = (MAX_SIZE.0 / width).min(MAX_SIZE.1 / height);
+
+    let mut preview = Image {
+        width: (width * scale).ceil() as u32,
+        height: (height * scale).ceil() as u32,
+        pixels: Vec::new(),
+    };
+
+    preview.pixels = BACKDROP.repeat((preview.width * preview.height) as usize);
+
+    for ((x, y), (w, h)) in areas {
+        let x0 = (f64::from(x - left) * scale).round() as u32;
+        let y0 = (f64::from(y - top) * scale).round() as u32;
+        let x1 = ((f64::from(x - left + w) * scale).round() as u32).min(preview.width);
+        let y1 = ((f64::from(y - top + h) * scale).round() as u32).min(preview.height);
+
+        for py in y0..y1 {
+            for px in x0..x1 {
+                let border = px == x0 || py == y0 || px + 1 == x1 || py + 1 == y1;
+
+                let pixel = match picture {
+                    _ if border => BORDER,
+                    Some(picture) => fill(picture, (px - x0, py - y0), (x1 - x0, y1 - y0)),
+                    None => BLANK,
+                };
+
+                let i = ((py * preview.width + px) * 4) as usize;
+
+                preview.pixels[i..i + 4].copy_from_slice(&pixel);
+            }
+        }
+    }
+
+    preview
+}
+
+/// Get the pixel of `picture` at `position` of an area of `size` it's scaled to cover,
+/// centered and cropped.
+fn fill(picture: &Image, position: (u32, u32), size: (u32, u32)) -> [u8; 4] {
+    let scale = (f64::from(size.0) / f64::from(picture.width.max(1)))
+        .max(f64::from(size.1) / f64::from(picture.height.max(1)));
+
+    let offset = |area: u32, length: u32| (f64::from(area) - f64::from(length) * scale) / 2.0;
+
+    let x = (f64::from(position.0) - offset(size.0, picture.width)) / scale;
+    let y = (f64::from(position.1) - offset(size.1, picture.height)) / scale;
+
+    picture
+        .pixel(x.max(0.0) as u32, y.max(0.0) as u32)
+        .unwrap_or(BLANK)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compose, BACKDROP, BLANK, BORDER};
+    use crate::capture::{Image, Output};
+
+    #[test]
+    fn previews() {
+        let output = |x: i32, width: i32| Output {
+            position: (x, 0),
+            size: (width, 1080),
+            ..Output::default()
+        };
+
+        // Two monitors side by side are scaled down together.
+        let preview = compose(None, &[output(0, 1920), output(1920, 1920)]);
+
+        assert_eq!((preview.width, preview.height), (640, 180));
+        assert_eq!(preview.pixel(0, 0), Some(BORDER));
+        assert_eq!(preview.pixel(100, 90), Some(BLANK));
+        assert_eq!(preview.pixel(420, 90), Some(BLANK));
+
+        // A smaller monitor leaves the backdrop below it.
+        let small = Output {
+            position: (1920, 0),
+            size: (1280, 720),
+            ..Output::default()
+        };
+
+        let preview = compose(None, &[output(0, 1920), small]);
+
+        assert_eq!(preview.pixel(600, 200), Some(BACKDROP));
+
+        // Pictures cover each output.
+        let red = Image {
+            width: 2,
+            height: 2,
+            pixels: [255, 0, 0, 255].repeat(4),
+        };
+
+        let preview = compose(Some(&red), &[]);
+
+        assert_eq!((preview.width, preview.height), (640, 360));
+        assert_eq!(preview.pixel(320, 180), Some([255, 0, 0, 255]));
+    }
+}
@@ -0,0 +1,67 @@
+use std::{
+    path::Path,
+    process::{Child, Command, Stdio},
+    sync::Mutex,
+};
+
+use super::{run, Setter};
+
+/// `Swaybg` sets the wallpaper with `swaybg`, which works on wlroots compositors.
+///
+/// On sway, sway is told to run it, so the wallpaper replaces the one of its config. Elsewhere
+/// it's run here, replacing the one run before.
+#[derive(Default)]
+pub struct Swaybg {
+    running: Mutex<Option<Child>>,
+}
+
+impl Setter for Swaybg {
+    fn set(&self, path: &Path) -> std::io::Result<()> {
+        if std::env::var_os("SWAYSOCK").is_some() {
+            return run(Command::new("swaymsg").args(["output", "*", "bg", &quote(path), "fill"]));
+        }
+
+        let child = Command::new("swaybg")
+            .arg("--image")
+            .arg(path)
+            .args(["--mode", "fill"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::inherit())
+            .spawn()?;
+
+        // The new one is started first, so the screen doesn't go blank in between.
+        let previous = self
+            .running
+            .lock()
+            .map_err(|_| std::io::Error::other("the last swaybg was lost"))?
+            .replace(child);
+
+        if let Some(mut previous) = previous {
+            let _ = previous.kill();
+            let _ = previous.wait();
+        }
+
+        Ok(())
+    }
+}
+
+/// Quote `path` as an argument of a sway command, which are split at spaces.
+fn quote(path: &Path) -> String {
+    let path = path.to_string_lossy();
+
+    format!("\"{}\"", path.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::quote;
+
+    #[test]
+    fn sway_arguments() {
+        assert_eq!(quote(Path::new("/a b/c.png")), "\"/a b/c.png\"");
+        assert_eq!(quote(Path::new("/a\"b")), "\"/a\\\"b\"");
+    }
+}
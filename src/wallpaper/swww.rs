@@ -0,0 +1,12 @@
+use std::{path::Path, process::Command};
+
+use super::{run, Setter};
+
+/// `Swww` sets the wallpaper through the `swww` daemon, which has to be running.
+pub struct Swww;
+
+impl Setter for Swww {
+    fn set(&self, path: &Path) -> std::io::Result<()> {
+        run(Command::new("swww").arg("img").arg(path))
+    }
+}
@@ -0,0 +1,99 @@
+use raw_window_handle::{HasRawWindowHandle, RawWindowHandle, XlibWindowHandle};
+
+/// `ParentWindow` identifies the application window a dialog should be transient for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParentWindow {
+    /// An X11 window id.
+    X11(u64),
+
+    /// A Wayland surface exported through the xdg-foreign protocol.
+    Wayland(String),
+}
+
+impl ParentWindow {
+    /// Parse a `parent_window` argument such as `x11:1a00004` or `wayland:<handle>`.
+    ///
+    /// Returns `None` for an empty string, which callers send when they have no window.
+    pub fn parse(parent_window: &str) -> Option<Self> {
+        let (kind, id) = parent_window.split_once(':')?;
+
+        match kind {
+            "x11" => u64::from_str_radix(id.trim_start_matches("0x"), 16)
+                .ok()
+                .map(Self::X11),
+
+            "wayland" if !id.is_empty() => Some(Self::Wayland(id.to_string())),
+
+            _ => {
+                log::warn!("unsupported parent window {:?}", parent_window);
+                None
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for ParentWindow {
+    /// Format the window as a `parent_window` argument, the inverse of [`ParentWindow::parse`].
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::X11(id) => write!(f, "x11:{:x}", id),
+            Self::Wayland(handle) => write!(f, "wayland:{}", handle),
+        }
+    }
+}
+
+/// `X11Parent` lets an X11 window id be passed to dialogs expecting a raw window handle.
+pub struct X11Parent(pub u64);
+
+unsafe impl HasRawWindowHandle for X11Parent {
+    fn raw_window_handle(&self) -> RawWindowHandle {
+        let mut handle = XlibWindowHandle::empty();
+
+        handle.window = self.0;
+
+        RawWindowHandle::Xlib(handle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ParentWindow;
+
+    #[test]
+    fn parse_x11() {
+        assert_eq!(
+            ParentWindow::parse("x11:1a00004"),
+            Some(ParentWindow::X11(0x1a00004))
+        );
+
+        assert_eq!(
+            ParentWindow::parse("x11:0x1a00004"),
+            Some(ParentWindow::X11(0x1a00004))
+        );
+    }
+
+    #[test]
+    fn parse_wayland() {
+        assert_eq!(
+            ParentWindow::parse("wayland:3bd5f3e2-6f1f-4c2a"),
+            Some(ParentWindow::Wayland(String::from("3bd5f3e2-6f1f-4c2a")))
+        );
+    }
+
+    #[test]
+    fn format() {
+        for parent_window in ["x11:1a00004", "wayland:3bd5f3e2-6f1f-4c2a"] {
+            let parent = ParentWindow::parse(parent_window).unwrap();
+
+            assert_eq!(parent.to_string(), parent_window);
+        }
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert_eq!(ParentWindow::parse(""), None);
+        assert_eq!(ParentWindow::parse("x11:nothex"), None);
+        assert_eq!(ParentWindow::parse("wayland:"), None);
+        assert_eq!(ParentWindow::parse("win32:1234"), None);
+    }
+}